@@ -0,0 +1,142 @@
+//! Measures how Sira's own overhead grows with the size of a [Plan], to find the point at which it
+//! stops being negligible. Please see 'How small is "small"?' in the README for the results.
+//!
+//! Run with `cargo bench --features test-fixtures`. The fleet-scale run uses a fake network (see
+//! [TestClientFactory]), so it measures only what Sira does on the control node: building,
+//! iterating, and compiling the plan, queueing hosts, and reporting. It doesn't sign actions
+//! unless an action key is installed in `/etc/sira/keys`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use indexmap::IndexMap;
+use sira::core::action::HostAction;
+use sira::core::plan::SharedPlan;
+use sira::core::{Action, Manifest, Plan, Task};
+use sira::run_plan::fixtures::TestClientFactory;
+use sira::run_plan::{embed, RunOptions};
+use std::time::Duration;
+
+/// Fleet sizes to measure, in hosts.
+const HOSTS: [usize; 3] = [100, 1_000, 10_000];
+
+/// Actions in each task.
+const ACTIONS: usize = 20;
+
+/// Returns a [Plan] with `manifests` manifests, each of which runs the same two tasks of [ACTIONS]
+/// actions on its own share of `hosts` hosts. Every action substitutes variables.
+fn plan(hosts: usize, manifests: usize) -> Plan {
+    let task = |name: &str| Task {
+        source: None,
+        name: name.to_string(),
+        actions: (0..ACTIONS)
+            .map(|i| Action::Command(vec![format!("install -d -o $user ${{dir}}/{name}/{i}")]))
+            .collect(),
+        vars: IndexMap::from([("dir".to_string(), "/srv/app".to_string())]),
+        sensitive: Vec::new(),
+        confirm: false,
+        user: None,
+        escalate: false,
+        dpkg_lock_timeout: None,
+        limits: Default::default(),
+        sandbox: None,
+        independent: false,
+        verify: Vec::new(),
+    };
+    let tasks = vec![task("packages"), task("services")];
+
+    let per_manifest = hosts / manifests;
+    let manifests = (0..manifests)
+        .map(|m| Manifest {
+            source: None,
+            name: format!("manifest{m}"),
+            hosts: (m * per_manifest..(m + 1) * per_manifest)
+                .map(|h| format!("host{h:05}"))
+                .collect(),
+            fact: None,
+            host_vars: Default::default(),
+            include: tasks.clone(),
+            vars: IndexMap::from([("user".to_string(), "app".to_string())]),
+            sensitive: vec!["user".to_string()],
+        })
+        .collect();
+    Plan { manifests }
+}
+
+/// Converting a [Plan] into a [SharedPlan] and listing its hosts, as every run does first.
+fn construction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("construction");
+    for hosts in HOSTS {
+        let plan = plan(hosts, 10);
+        group.throughput(Throughput::Elements(hosts as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(hosts), &plan, |b, plan| {
+            b.iter(|| {
+                let hosts = plan.hosts();
+                (hosts, SharedPlan::from(plan.clone()))
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Finding and iterating over every host's actions, without compiling them.
+fn iteration(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iteration");
+    for hosts in HOSTS {
+        let plan = plan(hosts, 10);
+        let names = plan.hosts();
+        let shared = SharedPlan::from(plan);
+        group.throughput(Throughput::Elements(hosts as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(hosts), &names, |b, names| {
+            b.iter(|| {
+                names
+                    .iter()
+                    .map(|host| shared.plan_for(host).unwrap().count())
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Substituting variables into one action and redacting it for reports, as each host does for
+/// each of its actions.
+fn compile(c: &mut Criterion) {
+    let plan = plan(1, 1);
+    let manifest = &plan.manifests[0];
+    let task = &manifest.include[0];
+    let action = HostAction::new(&manifest.hosts[0], manifest, task, &task.actions[0]);
+    c.bench_function("compile", |b| {
+        b.iter(|| {
+            let action = black_box(&action);
+            action.redactor().redact_action(&action.compile())
+        })
+    });
+}
+
+/// Running a whole plan against a fake network, i.e. everything but SSH and `sira-client`.
+fn run(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("run");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(20));
+    for hosts in HOSTS {
+        let plan = plan(hosts, 10);
+        group.throughput(Throughput::Elements(hosts as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(hosts), &plan, |b, plan| {
+            b.to_async(&runtime).iter(|| async {
+                let result = embed::run(
+                    plan.clone(),
+                    TestClientFactory::new(),
+                    (),
+                    (),
+                    RunOptions::default(),
+                )
+                .await;
+                assert!(result.is_success());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, construction, iteration, compile, run);
+criterion_main!(benches);
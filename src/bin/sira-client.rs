@@ -1,36 +1,94 @@
 use anyhow::{anyhow, bail, Context};
 use shlex::Shlex;
 use sira::client;
-use sira::core::action::{line_in_file, script, Action, FILE_TRANSFER_PATH};
+use sira::client::dpkg_lock;
+use sira::client::facts::{Facts, FACTS_COMMAND};
+use sira::client::install::{self, Staged};
+use sira::client::platform;
+use sira::client::policy::{self, Policy};
+use sira::client::query::{Query, QUERY_COMMAND};
+use sira::client::queue;
+use sira::client::selinux;
+use sira::core::action::bundle::APPLY_QUEUED_COMMAND;
+use sira::core::action::{
+    check_script, controller_key, kernel_module_changes, line_in_file, line_in_file_preview,
+    script, swap_changes, unified_diff, Action, ClientError, Contents, Envelope, ErrorCode,
+    KernelModuleChange, KernelModulePaths, Limits, Progress, Status, SwapChange, SwapPaths,
+    WithCode, FILE_TRANSFER_PATH, UPLOAD_CHECKSUM_PREFIX,
+};
 use sira::crypto;
 use std::env;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::Write;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
+use std::process::{self, Command, ExitCode};
+use std::time::Duration;
 
 /// The name of the allowed signers file used to verify actions.
 pub const ALLOWED_SIGNERS_FILE: &str = "action";
 
-fn main() -> anyhow::Result<()> {
-    // Number of actual arguments (excluding the name of the binary).
-    let argc = env::args()
-        .len()
-        .checked_sub(1)
-        .expect("integer underflow in argc-1; please report this");
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Explain the error to humans on stderr, and report its class to the control node on
+            // stdout. Please see sira::core::action::error.
+            eprintln!("Error: {e:?}");
+            println!("{}", ClientError::classify(&e).line());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    // The actual arguments (excluding the name of the binary).
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    // Gathering facts only reads information about the system, so it needs no signature.
+    if args.len() == 1 && args[0] == FACTS_COMMAND {
+        println!("{}", Facts::gather()?.to_json());
+        return Ok(());
+    }
+
+    // So is answering a ping, which only proves that sira-client runs.
+    if args.len() == 1 && args[0] == client::PING_COMMAND {
+        println!("{}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    // So is answering a query, which also changes nothing.
+    if args.first().map(String::as_str) == Some(QUERY_COMMAND) {
+        println!("{}", Query::from_args(&args[1..])?.answer()?.to_json());
+        return Ok(());
+    }
+
+    // Queued actions are each verified when sira-client runs itself on them, just like actions
+    // that arrive over SSH.
+    if args.len() == 1 && args[0] == APPLY_QUEUED_COMMAND {
+        return queue::apply_queued(&env::current_exe()?);
+    }
+
+    // In check mode, report what the action would change without changing anything.
+    let check = args.first().map(String::as_str) == Some(client::CHECK_FLAG);
+    if check {
+        args.remove(0);
+    }
+    let argc = args.len();
 
     let require_signature = crypto::allowed_signers_installed(ALLOWED_SIGNERS_FILE)?;
 
     if argc == 2 && !require_signature {
         // Error: missing allowed signers file.
-        return error_missing_allowed_signers_file();
+        return error_missing_allowed_signers_file().with_code(ErrorCode::Unauthorized);
     } else if argc == 1 && require_signature {
         // Error: missing signature.
         //
         // If the administrator is trying to run an action directly, this error will be misleading,
         // but that's not an intended use case. As of this writing, we don't have a good way to
         // differentiate these cases.
-        return error_missing_signature();
+        return error_missing_signature().with_code(ErrorCode::Unauthorized);
     } else if argc == 0 || argc > 2 {
         // Error: too few or too many arguments.
         //
@@ -38,16 +96,31 @@ fn main() -> anyhow::Result<()> {
         // action on a client manually. This isn't an intended use case, but we'll provide more
         // detailed instructions here than in the other cases in order to help the administrator on
         // their way.
-        return error_wrong_arguments(require_signature);
+        return error_wrong_arguments(require_signature).with_code(ErrorCode::ParseFailure);
     }
 
-    let yaml = env::args().nth(1).expect(
+    let yaml = args.first().cloned().expect(
         "missing required argument <action-as-yaml>, but failed to detect this and display a \
         helpful error message to the user",
     );
 
+    // Load the policy before doing anything else so that a broken policy file fails closed.
+    let policy = Policy::load()?;
+    if policy.is_some() && !require_signature {
+        let message = format!(
+            "{} is installed, but actions are not signed. Please install the action allowed \
+            signers file so that sira-client can tell which key signed each action:\n\n{}\n",
+            policy::policy_path().to_string_lossy(),
+            crypto::allowed_signers_path(ALLOWED_SIGNERS_FILE)?.to_string_lossy(),
+        );
+        return Err(ClientError::new(ErrorCode::Unauthorized, message).into());
+    }
+
+    // The principal whose key signed the action, if signatures are required.
+    let mut signer = None;
+
     if require_signature {
-        let signature = env::args().nth(2).expect(
+        let signature = args.get(1).cloned().expect(
             "missing required argument <action-signature>, but failed to detect this and display a \
             helpful error message to the user",
         );
@@ -59,32 +132,279 @@ fn main() -> anyhow::Result<()> {
             .context("sira-client encountered an error writing action signature to disk")?;
         drop(signature_file);
 
-        crypto::verify(
-            yaml.as_bytes(),
-            &signature_path,
-            ALLOWED_SIGNERS_FILE,
-            "sira",
-        )?;
+        // Verify the signature as the first principal whose key matches it. Keys installed by
+        // sira-install all use the principal "sira".
+        let verified =
+            crypto::find_principals(&signature_path, ALLOWED_SIGNERS_FILE).and_then(|principals| {
+                let principal = principals
+                    .into_iter()
+                    .next()
+                    .ok_or(anyhow!("no principal matched the action signature"))?;
+                crypto::verify(
+                    yaml.as_bytes(),
+                    &signature_path,
+                    ALLOWED_SIGNERS_FILE,
+                    &principal,
+                )?;
+                Ok(principal)
+            });
 
         fs::remove_file(&signature_path)
             .context("sira-client encountered an error removing action signature file")?;
+        signer = Some(verified.with_code(ErrorCode::Unauthorized)?);
+    }
+
+    // Signed actions must arrive in an unexpired envelope so that a leaked signature stops working
+    // once it expires. Unsigned actions may also be bare, e.g. when run by hand.
+    let (action, limits, sandbox, user, escalate, dpkg_lock_timeout, check_only) =
+        match Envelope::from_yaml(&yaml) {
+            Ok(envelope) => {
+                envelope.check_expiry()?;
+                (
+                    envelope.action,
+                    envelope.limits,
+                    envelope.sandbox,
+                    envelope.user,
+                    envelope.escalate,
+                    envelope.dpkg_lock_timeout,
+                    envelope.check,
+                )
+            }
+            Err(e) if require_signature => {
+                return Err(e.context("signed actions must be wrapped in an envelope"));
+            }
+            Err(_) => (
+                serde_yaml::from_str(&yaml)?,
+                Limits::default(),
+                None,
+                None,
+                false,
+                None,
+                false,
+            ),
+        };
+
+    // An action signed for check mode stays in check mode, so that its signature can't be
+    // replayed to make changes.
+    let check = check || check_only;
+
+    // Check the limits and sandbox up front, so that invalid ones fail even in check mode.
+    limits.properties()?;
+    if let Some(sandbox) = &sandbox {
+        sandbox.validate()?;
+    }
+    if !cfg!(target_os = "linux") && (!limits.is_empty() || sandbox.is_some()) {
+        bail!("limits and sandboxes need systemd-run and bwrap, which only Linux has");
+    }
+
+    if let (Some(policy), Some(signer)) = (&policy, &signer) {
+        policy.check(signer, &action)?;
     }
 
-    let action: Action = serde_yaml::from_str(&yaml)?;
+    // On rootless hosts, sira-client runs as the user that Sira logs in as. An action that needs
+    // root goes to another sira-client with root privileges, which checks it all over again.
+    if escalate && client::whoami() != "root" {
+        return run_escalated();
+    }
 
-    match action {
+    // Report whether the action changed anything, so that a run can tell the user when nothing
+    // did. If the action fails, the exit code says so instead.
+    let status = match action {
         Action::Command(commands) => {
             for command_string in commands {
+                if check {
+                    // There's no telling what a command would change without running it.
+                    match &user {
+                        Some(user) => println!("Would run as {user}: {command_string}"),
+                        None => println!("Would run: {command_string}"),
+                    }
+                    continue;
+                }
+                progress(format!("Running {command_string}"));
                 let mut words = Shlex::new(&command_string);
                 let command = words
                     .next()
                     .ok_or(anyhow!("sira-client received a blank command"))?;
-                let args: Vec<_> = words.collect();
-                client::run(command, &args)?;
+
+                // Like a script's user, the task's user goes inside the limits.
+                let (command, args): (String, Vec<_>) = match &user {
+                    Some(user) => (
+                        platform::run_as().to_string(),
+                        ["-u".to_string(), user.clone(), command]
+                            .into_iter()
+                            .chain(words)
+                            .collect(),
+                    ),
+                    None => (command, words.collect()),
+                };
+                let (command, args) = limits.wrap(&command, &args)?;
+                match dpkg_lock_timeout {
+                    Some(timeout) => dpkg_lock::run(command, &args, Duration::from_secs(timeout))?,
+                    None => client::run(command, &args)?,
+                }
+            }
+            Status::Changed
+        }
+        Action::ControllerKey { .. } => {
+            // sira-client runs via sudo (or doas), so the Sira user is the user who invoked it.
+            // Like the installer, assume that the Sira user's home directory is
+            // /home/<sira-user> (or, on macOS, /Users/<sira-user>).
+            let sira_user = env::var("SUDO_USER")
+                .or_else(|_| env::var("DOAS_USER"))
+                .context(
+                    "could not determine the Sira user: neither SUDO_USER nor DOAS_USER is set",
+                )?;
+            let authorized_keys = platform::home_dir(&sira_user).join(".ssh/authorized_keys");
+            let allowed_signers = crypto::allowed_signers_path(ALLOWED_SIGNERS_FILE)?;
+            let read_both = || {
+                (
+                    fs::read(&authorized_keys).unwrap_or_default(),
+                    fs::read(&allowed_signers).unwrap_or_default(),
+                )
+            };
+            if check {
+                println!(
+                    "Would update {} and {}",
+                    authorized_keys.display(),
+                    allowed_signers.display(),
+                );
+                Status::Changed
+            } else {
+                let before = read_both();
+                controller_key(&action, &authorized_keys, &allowed_signers)?;
+                Status::from_changed(read_both() != before)
+            }
+        }
+        Action::Custom { plugin, args } => {
+            if !check {
+                progress(format!("Running plugin {plugin}"));
+            }
+            let response = client::plugin::run(&plugin, &args, check, &limits)?;
+            if !response.output.is_empty() {
+                println!("{}", response.output.trim_end());
             }
+            Status::from_changed(response.changed)
+        }
+        Action::KernelModule { .. } => {
+            if !cfg!(target_os = "linux") {
+                bail!("kernel modules can only be managed on Linux");
+            }
+            let changes = kernel_module_changes(&action, &KernelModulePaths::default())?;
+            for change in &changes {
+                // Report what changed, if anything, so that nobody has to log in to find out.
+                match change {
+                    KernelModuleChange::Write {
+                        path,
+                        before,
+                        after,
+                    } => {
+                        let path = path.to_string_lossy();
+                        if let Some(diff) =
+                            unified_diff(before.as_bytes(), after.as_bytes(), &path, &path)
+                        {
+                            print!("{diff}");
+                        }
+                    }
+                    KernelModuleChange::Remove { path, before } => {
+                        let path = path.to_string_lossy();
+                        if let Some(diff) = unified_diff(before.as_bytes(), b"", &path, "/dev/null")
+                        {
+                            print!("{diff}");
+                        }
+                    }
+                    KernelModuleChange::Load(name) if check => {
+                        println!("Would load kernel module {name}")
+                    }
+                    KernelModuleChange::Load(name) => {
+                        progress(format!("Loading kernel module {name}"))
+                    }
+                    KernelModuleChange::Unload(name) if check => {
+                        println!("Would unload kernel module {name}")
+                    }
+                    KernelModuleChange::Unload(name) => {
+                        progress(format!("Unloading kernel module {name}"))
+                    }
+                }
+                if !check {
+                    change.apply()?;
+                }
+            }
+            Status::from_changed(!changes.is_empty())
+        }
+        Action::LineInFile { ref path, .. } => {
+            // Report what changed, if anything, so that nobody has to log in to find out.
+            let before = fs::read(path).unwrap_or_default();
+            let after = match check {
+                true => line_in_file_preview(&action)?.into_bytes(),
+                false => {
+                    line_in_file(&action)?;
+                    fs::read(path).unwrap_or_default()
+                }
+            };
+            if let Some(diff) = unified_diff(&before, &after, path, path) {
+                print!("{diff}");
+            }
+            Status::from_changed(after != before)
+        }
+        Action::Script { ref name, .. } if check => {
+            check_script(&action)?;
+            println!("Would run script: {name}");
+            Status::Changed
+        }
+        Action::Script { ref name, .. } => {
+            progress(format!("Running script {name}"));
+            script(&action, &limits, sandbox.as_ref())?;
+            Status::Changed
+        }
+        Action::Swap { .. } => {
+            if !cfg!(target_os = "linux") {
+                bail!("swap space can only be managed on Linux");
+            }
+            let changes = swap_changes(&action, &SwapPaths::default())?;
+            let report = |would: &str, doing: &str, path: &Path| match check {
+                true => println!("Would {would} {}", path.display()),
+                false => progress(format!("{doing} {}", path.display())),
+            };
+            for change in &changes {
+                // Report what changed, if anything, so that nobody has to log in to find out.
+                match change {
+                    SwapChange::WriteFstab {
+                        path,
+                        before,
+                        after,
+                    } => {
+                        let path = path.to_string_lossy();
+                        if let Some(diff) =
+                            unified_diff(before.as_bytes(), after.as_bytes(), &path, &path)
+                        {
+                            print!("{diff}");
+                        }
+                    }
+                    SwapChange::Create { path, .. } => {
+                        report("create swap file", "Creating swap file", path)
+                    }
+                    SwapChange::Secure(path) => {
+                        report("secure swap file", "Securing swap file", path)
+                    }
+                    SwapChange::Format(path) => {
+                        report("format swap file", "Formatting swap file", path)
+                    }
+                    SwapChange::Activate(path) => {
+                        report("enable swap on", "Enabling swap on", path)
+                    }
+                    SwapChange::Deactivate(path) => {
+                        report("disable swap on", "Disabling swap on", path)
+                    }
+                    SwapChange::Remove(path) => {
+                        report("remove swap file", "Removing swap file", path)
+                    }
+                }
+                if !check {
+                    change.apply()?;
+                }
+            }
+            Status::from_changed(!changes.is_empty())
         }
-        Action::LineInFile { .. } => line_in_file(&action)?,
-        Action::Script { .. } => script(&action)?,
         Action::Upload {
             from,
             to,
@@ -92,6 +412,14 @@ fn main() -> anyhow::Result<()> {
             group,
             permissions,
             overwrite,
+            encrypt,
+            seuser,
+            serole,
+            setype,
+            acl,
+            transfer_permissions: _,
+            xattrs,
+            capabilities,
         } => {
             // It probably isn't exploitable, but let's try to perform some basic sanity checking
             // before we inject `{user}:{group}` into an argument and pass it to chown as root!
@@ -104,38 +432,15 @@ fn main() -> anyhow::Result<()> {
                 bail!("group should not contain a colon (\":\") character: {group}");
             }
 
-            // chmod the temporary file to its final state.
-            //
-            // We do this before we chown under the theory that it might be slightly more secure.
-            // If the final permissions are more restrictive than the Sira user's default
-            // permissions, then we don't want to change the user or group, thereby granting
-            // additional potential access, before we restrict permissions for said user and group.
-            if let Some(permissions) = permissions {
-                client::run("chmod", &[&permissions[..], FILE_TRANSFER_PATH])?;
-            }
-
-            // chown the temporary file to its final state.
-            client::run(
-                "chown",
-                &[&format!("{user}:{group}")[..], FILE_TRANSFER_PATH],
-            )?;
-
-            // Install the file, i.e. mv the file into place.
-            let mut args: Vec<OsString> = Vec::new();
-            if !overwrite {
-                args.push("-n".into());
-            }
-            args.push(FILE_TRANSFER_PATH.into());
-
             // Handle various edge cases on `to`.
-            match to.trim() {
+            let destination: OsString = match to.trim() {
                 "." => {
                     // We need to use the source file name. Otherwise, we will wind up calling
                     // `mv FILE_TRANSFER_PATH .`, which is wrong.
-                    let path = Path::new(&from)
+                    Path::new(&from)
                         .file_name()
-                        .expect("Action::Upload::from should be a file, not a directory");
-                    args.push(path.into())
+                        .expect("Action::Upload::from should be a file, not a directory")
+                        .into()
                 }
                 to if Path::new(to).is_dir() => {
                     // `to` is a directory, so we need to add the source file name to the
@@ -144,28 +449,223 @@ fn main() -> anyhow::Result<()> {
                     let file_name = Path::new(&from)
                         .file_name()
                         .expect("Action::Upload::from should be a file, not a directory");
-                    let path = Path::new(to).join(file_name);
-                    args.push(path.into())
+                    Path::new(to).join(file_name).into()
                 }
                 // Intentionally unhandled case: "~" - almost certainly not what the user meant,
                 // but the docs warned about this, so we'll trust the user.
-                _ => args.push(to.into()),
+                _ => to.into(),
             };
 
-            if let Err(e) = client::run("mv", &args) {
-                // Try to delete the temporary file for security, but if that fails, silently
-                // ignore the failure. Either way, return the error from `mv`.
-                //
-                // We need to invoke `rm` instead of of using std::fs so we can resolve the path
-                // the same way as `mv` and the other commands.
-                let _ = client::run("rm", &[FILE_TRANSFER_PATH]);
-                return Err(e);
+            // Decrypt an encrypted upload next to its destination, into a file that only root can
+            // read and that's removed however the upload ends, and from here on, work with that
+            // file instead. Remove the ciphertext either way.
+            let staged = match encrypt {
+                true => {
+                    progress("Decrypting");
+                    let staged = Staged::next_to(&destination).and_then(|staged| {
+                        crypto::decrypt_file(
+                            FILE_TRANSFER_PATH,
+                            crypto::upload_identity_path(),
+                            staged.path(),
+                        )?;
+                        Ok(staged)
+                    });
+                    let _ = client::run("rm", &[FILE_TRANSFER_PATH]);
+                    Some(staged?)
+                }
+                false => None,
+            };
+            let transfer_path = staged
+                .as_ref()
+                .map_or(FILE_TRANSFER_PATH, Staged::path)
+                .to_string();
+
+            // Give the file its final owner, permissions, and attributes before it's in place.
+            install::set_attributes(
+                &transfer_path,
+                &install::Attributes {
+                    user: &user,
+                    group: &group,
+                    permissions: permissions.as_deref(),
+                    acl: &acl,
+                    xattrs: &xattrs,
+                    capabilities: capabilities.as_deref(),
+                },
+            )?;
+
+            // Large files are only compared by checksum, so that uploads of any size fit in memory.
+            let before = Contents::read(&destination);
+            let compared = Compared {
+                acl: !acl.is_empty(),
+                xattrs: !xattrs.is_empty() || capabilities.is_some(),
+            };
+            let before_attributes = compared.read(&destination.to_string_lossy());
+            let selinux_enabled = selinux::enabled();
+            let before_context = match selinux_enabled {
+                true => selinux::context(&destination.to_string_lossy()),
+                false => None,
+            };
+
+            if check {
+                check_upload(
+                    &transfer_path,
+                    &destination,
+                    &before,
+                    compared,
+                    before_attributes,
+                    overwrite,
+                    encrypt,
+                )?
+            } else {
+                progress(format!("Installing {}", destination.to_string_lossy()));
+
+                let moved = match install::move_into_place(&transfer_path, &destination, overwrite)
+                {
+                    Ok(moved) => moved,
+                    Err(e) => {
+                        // Try to delete the temporary file for security, but if that fails,
+                        // silently ignore the failure. Either way, return the error from `mv`.
+                        //
+                        // We need to invoke `rm` instead of of using std::fs so we can resolve the
+                        // path the same way as `mv` and the other commands.
+                        let _ = client::run("rm", &[&transfer_path]);
+                        return Err(e);
+                    }
+                };
+
+                // Report the installed file's checksum so that the control node can check it
+                // against the source file. If `mv -n` left an existing file in place, then there
+                // is nothing to report.
+                if !moved {
+                    Status::Ok
+                } else {
+                    // The file still has the context of wherever it was uploaded to, so fix that.
+                    let mut after_context = None;
+                    if selinux_enabled {
+                        let destination = destination.to_string_lossy();
+                        selinux::apply(
+                            &destination,
+                            seuser.as_deref(),
+                            serole.as_deref(),
+                            setype.as_deref(),
+                        )?;
+                        after_context = selinux::context(&destination);
+                    }
+
+                    // Report what changed, too, unless the file is a secret.
+                    let after = Contents::read(&destination);
+                    if !encrypt {
+                        let label = destination.to_string_lossy();
+                        if let Some(diff) = before.diff(&after, &label, &label) {
+                            print!("{diff}");
+                        }
+                    }
+
+                    let checksum = crypto::sha256_file(&destination)?;
+                    println!("{UPLOAD_CHECKSUM_PREFIX}{checksum}");
+                    let after_attributes = compared.read(&destination.to_string_lossy());
+                    let changed = after != before
+                        || after_attributes != before_attributes
+                        || after_context != before_context;
+                    Status::from_changed(changed)
+                }
             }
         }
+    };
+    println!("{}", status.line());
+    Ok(())
+}
+
+// In check mode, compares an uploaded file, which already has its final owner and permissions,
+// with its destination, reports what installing it would change, and then removes it.
+fn check_upload(
+    transfer_path: &str,
+    destination: &OsStr,
+    before: &Contents,
+    compared: Compared,
+    before_attributes: Attributes,
+    overwrite: bool,
+    encrypt: bool,
+) -> anyhow::Result<Status> {
+    let staged = Contents::read(transfer_path);
+    let staged_attributes = compared.read(transfer_path);
+    let checksum = crypto::sha256_file(transfer_path);
+    let _ = client::run("rm", &[transfer_path]);
+    let checksum = checksum?;
+
+    // Without overwrite, `mv -n` would leave an existing file in place.
+    if !overwrite && before_attributes.metadata.is_some() {
+        return Ok(Status::Ok);
+    }
+
+    if !encrypt {
+        let label = destination.to_string_lossy();
+        if let Some(diff) = before.diff(&staged, &label, &label) {
+            print!("{diff}");
+        }
+    }
+
+    // Report the checksum of the file that would have been installed.
+    println!("{UPLOAD_CHECKSUM_PREFIX}{checksum}");
+    let changed = staged != *before || staged_attributes != before_attributes;
+    Ok(Status::from_changed(changed))
+}
+
+// The attributes of a file that an upload only compares if it sets them, since reading them
+// requires tools that might not be installed. Ownership and mode are always compared.
+#[derive(Clone, Copy)]
+struct Compared {
+    acl: bool,
+    xattrs: bool,
+}
+
+// A file's ownership and mode, plus its ACL and extended attributes where compared.
+#[derive(PartialEq)]
+struct Attributes {
+    metadata: Option<(u32, u32, u32)>,
+    acl: Option<String>,
+    xattrs: Option<String>,
+}
+
+impl Compared {
+    fn read(self, path: &str) -> Attributes {
+        Attributes {
+            metadata: ownership_and_mode(path),
+            acl: self.acl.then(|| client::acl::get(path)).flatten(),
+            xattrs: self.xattrs.then(|| client::xattr::get(path)).flatten(),
+        }
+    }
+}
+
+// Runs sira-client again with the same arguments and root privileges, e.g. `sudo -n
+// /opt/sira/bin/sira-client <action-as-yaml> <action-signature>`, on the same stdout and stderr. If
+// it fails, exits with its exit code, since it has already reported why.
+fn run_escalated() -> anyhow::Result<()> {
+    let program = platform::run_as();
+    let status = Command::new(program)
+        .arg("-n")
+        .arg(env::current_exe()?)
+        .args(env::args_os().skip(1))
+        .status()
+        .with_context(|| format!("could not run sira-client with {program}"))?;
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
     }
     Ok(())
 }
 
+// Reports progress on the running action to the control node, which shows it while the action
+// runs.
+fn progress(message: impl Into<String>) {
+    println!("{}", Progress::new(message).line());
+}
+
+// Returns a file's owner, group, and mode, or None if it doesn't exist.
+fn ownership_and_mode(path: impl AsRef<Path>) -> Option<(u32, u32, u32)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.uid(), metadata.gid(), metadata.mode()))
+}
+
 // Run a command locally, and if it fails, return a descriptive Err value.
 //
 // `command_string` should be a precise text-form equivalent of `command`. If `command_string` is
@@ -193,16 +693,38 @@ fn error_wrong_arguments(require_signature: bool) -> anyhow::Result<()> {
         "\
 Please provide the correct arguments:
 
-    sira-client <action-as-yaml> [<action-signature>]
+    sira-client [--check] <action-as-yaml> [<action-signature>]
+
+The first argument is an Action written in YAML format. If the action is signed, it must be \
+wrapped in an envelope with an expiration time:
 
-The first argument is an Action written in YAML format.
+    expires: <RFC 3339 timestamp>
+    action: <action>
 
 The second argument is a cryptographic signature for this action, generated by invoking \
-`ssh-keygen -Y sign` on the action. This is required if the allowed signers file is \
+`ssh-keygen -Y sign` on the envelope. This is required if the allowed signers file is \
 installed:
 
     Location: {}
-    Status: {}\n\
+    Status: {}
+
+With --check, sira-client reports what the action would change without changing anything.
+
+To print facts about this system as JSON instead, e.g. its operating system, memory, disks, and IP \
+addresses:
+
+    sira-client facts
+
+To answer a query about this system's current state as JSON, e.g. whether a file, package, or \
+service is present:
+
+    sira-client query file <path>
+    sira-client query package <name>
+    sira-client query service <name>
+
+To apply the actions that the control node queued for this system, e.g. at boot:
+
+    sira-client apply-queued\n\
         ",
         crypto::allowed_signers_path(ALLOWED_SIGNERS_FILE)?.to_string_lossy(),
         match require_signature {
@@ -4,10 +4,12 @@
 //!
 //! [installation.md]: https://github.com/edev/sira/blob/main/installation.md
 
+use shlex::Quoter;
 use sira::client;
+use sira::client::platform;
 use sira::config;
 use sira::core::action::{self, Action};
-use sira::crypto::{ALLOWED_SIGNERS_DIR, KEY_DIR};
+use sira::crypto::{self, ALLOWED_SIGNERS_DIR, KEY_DIR};
 use std::env;
 use std::ffi::{OsStr, OsString};
 use std::fmt::Display;
@@ -42,6 +44,40 @@ const MANIFEST_KEY: &str = "manifest";
 /// The SSH private key used to sign actions sent to the client binary, known as the "action key".
 const ACTION_KEY: &str = "action";
 
+// SECTION: SSH certificate authorities
+
+/// The name under which the user certificate authority's public key is transferred to managed
+/// nodes, without the ".pub" extension.
+const USER_CA_KEY: &str = "user_ca";
+
+/// Where managed nodes install the user certificate authority's public key for `sshd`.
+const SSHD_USER_CA_PATH: &str = "/etc/ssh/sira_user_ca.pub";
+
+/// The main `sshd` configuration file on managed nodes.
+const SSHD_CONFIG: &str = "/etc/ssh/sshd_config";
+
+/// The `sshd` drop-in configuration directory on managed nodes, if the distribution uses one.
+const SSHD_CONFIG_DIR: &str = "/etc/ssh/sshd_config.d";
+
+/// Options passed to the control node invocation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct ControlNodeOptions {
+    /// Whether to generate new keys on a hardware security key (`ed25519-sk`) rather than on disk.
+    security_key: bool,
+
+    /// The public key of the authority that signs the control node user's login certificate.
+    ///
+    /// If present, managed nodes trust this authority instead of the login public key, so the
+    /// login key never appears in `authorized_keys`.
+    user_ca: Option<PathBuf>,
+
+    /// The public key of the authority that signs managed nodes' host certificates.
+    ///
+    /// If present, the control node trusts this authority in Sira's known hosts file, so managed
+    /// nodes with host certificates need no `known_hosts` entries of their own.
+    host_ca: Option<PathBuf>,
+}
+
 /// Indicates whether a public key is present in one of several locations.
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum PublicKeyState {
@@ -81,19 +117,32 @@ const ACTION_FLAG: &str = ".sira-install-skip-action-key";
 /// Program entry point.
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() == 3 {
-        if args[1] == "--managed-node" {
-            // sira-install --managed-node <sira-user>
-            //
-            // Managed node invocation is intentionally undocumented in the public interface. It is
-            // meant for internal use only.
-            managed_node(&args[2]);
-        } else {
-            // sira-install <sira-user> [<admin-user>@]<managed-node>
-            control_node(&args[1], &args[2]);
+    if args.len() == 3 && args[1] == "--managed-node" {
+        // sira-install --managed-node <sira-user>
+        //
+        // Managed node invocation is intentionally undocumented in the public interface. It is
+        // meant for internal use only.
+        managed_node(&args[2]);
+        return;
+    }
+
+    // sira-install [--security-key] [--user-ca <ca.pub>] [--host-ca <ca.pub>] \
+    //     <sira-user> [<admin-user>@]<managed-node>
+    let mut options = ControlNodeOptions::default();
+    let mut positional = vec![];
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--security-key" => options.security_key = true,
+            "--user-ca" => options.user_ca = args.next().map(PathBuf::from),
+            "--host-ca" => options.host_ca = args.next().map(PathBuf::from),
+            _ => positional.push(arg),
         }
-    } else {
-        println!("Please see the installation guide for instructions.");
+    }
+
+    match positional[..] {
+        [sira_user, destination] => control_node(sira_user, destination, &options),
+        _ => println!("Please see the installation guide for instructions."),
     }
 }
 
@@ -111,7 +160,7 @@ fn allowed_signers_dir() -> &'static Path {
 }
 
 /// Installer logic that should run on the control node as the control node user.
-fn control_node(sira_user: &str, destination: &str) {
+fn control_node(sira_user: &str, destination: &str, options: &ControlNodeOptions) {
     // Compute the Cargo bin directory, typically ~/.cargo/bin
     let cargo_bin_dir = {
         let mut cargo_home = home::cargo_home().expect("could not retrieve Cargo directory");
@@ -165,13 +214,23 @@ fn control_node(sira_user: &str, destination: &str) {
                 password is highly recommended.\n",
                 key_path.display(),
             );
-            ssh_keygen(key_path);
+            ssh_keygen(key_path, options.security_key);
+        }
+
+        // With a user certificate authority, managed nodes trust the authority instead of the
+        // login public key, which we then have no reason to deploy.
+        match &options.user_ca {
+            Some(user_ca) => check_login_certificate(&ssh_dir, user_ca, sira_user),
+            None => file_transfers.push(ssh_dir.join(public_key(LOGIN_KEY))),
         }
-        file_transfers.push(ssh_dir.join(public_key(LOGIN_KEY)));
     }
 
     create_config_dirs();
 
+    if let Some(host_ca) = &options.host_ca {
+        install_host_ca(host_ca);
+    }
+
     // Check for the manifest public key, and prompt to generate a key pair if it's absent.
     //
     // The manifest public key may be present as either a public key file in the user's SSH
@@ -205,7 +264,7 @@ fn control_node(sira_user: &str, destination: &str) {
                 );
             }
             let key_created =
-                prompt_to_generate_signing_key_pair(&ssh_dir, MANIFEST_KEY, MANIFEST_FLAG);
+                prompt_to_generate_signing_key_pair(&ssh_dir, MANIFEST_KEY, MANIFEST_FLAG, options);
             if key_created {
                 public_state = PublicKeyState::PublicKeyFile;
             }
@@ -272,7 +331,7 @@ fn control_node(sira_user: &str, destination: &str) {
         let transfer_key = if private_installed {
             true
         } else if private_exists
-            || prompt_to_generate_signing_key_pair(&ssh_dir, ACTION_KEY, ACTION_FLAG)
+            || prompt_to_generate_signing_key_pair(&ssh_dir, ACTION_KEY, ACTION_FLAG, options)
         {
             install_signing_key_pair(&ssh_dir, ACTION_KEY);
             true
@@ -302,6 +361,14 @@ fn control_node(sira_user: &str, destination: &str) {
         let destination = format!("{destination}:");
         args.push(destination.as_ref());
         client::run("scp", &args).expect("error transferring files");
+
+        // The user certificate authority's public key needs a fixed name on the managed node,
+        // regardless of its name here, so it gets its own transfer.
+        if let Some(user_ca) = &options.user_ca {
+            let destination = format!("{destination}{}", public_key(USER_CA_KEY).display());
+            client::run("scp", &[user_ca.as_os_str(), destination.as_ref()])
+                .expect("error transferring user certificate authority");
+        }
         println!();
     }
 
@@ -309,22 +376,118 @@ fn control_node(sira_user: &str, destination: &str) {
     //
     // ssh -t <destination> sudo ./sira-install --managed-node <sira-user>
     //
+    // OpenBSD ships doas instead of sudo, so where sudo isn't installed, use doas instead.
+    //
     // Be sure to use std::process::Command::new("ssh") rather than the openssh crate, because we
     // specifically WANT stdio to be piped to enable password-protected sudo in this case. The `-t`
     // argument makes it interactive, so sudo can prompt for a password.
     {
-        println!("Running {INSTALLER_BIN} via sudo on {destination}");
-        let command = format!("./{INSTALLER_BIN}");
-        let args = [
-            "-t",
-            destination,
-            "sudo",
-            &command,
-            "--managed-node",
-            sira_user,
-        ];
-        client::run("ssh", &args).expect("error running installer on managed node");
+        println!("Running {INSTALLER_BIN} via sudo (or doas) on {destination}");
+        let installer = Quoter::new()
+            .join([
+                &format!("./{INSTALLER_BIN}")[..],
+                "--managed-node",
+                sira_user,
+            ])
+            .expect("Sira user name should not contain a null byte");
+        let command = format!(
+            "if command -v sudo >/dev/null; then sudo {installer}; else doas {installer}; fi"
+        );
+        client::run("ssh", &["-t", destination, &command])
+            .expect("error running installer on managed node");
+    }
+}
+
+/// Checks that the login key has a certificate alongside it, printing instructions if not.
+///
+/// OpenSSH presents `~/.ssh/sira-cert.pub` automatically when logging in with `~/.ssh/sira`. The
+/// certificate must list the Sira user as a principal, or `sshd` will reject it.
+fn check_login_certificate(ssh_dir: &Path, user_ca: &Path, sira_user: &str) {
+    let certificate = ssh_dir.join(format!("{LOGIN_KEY}-cert.pub"));
+    if path_exists(&certificate, "login certificate") {
+        return;
     }
+
+    println!(
+        // Wrapped to 80 characters.
+        "Could not find a certificate for the login key: {}
+        
+        Managed nodes will trust certificates signed by {} instead of the login
+        public key. To sign the login key, run the following wherever the certificate
+        authority's private key lives, and then copy the certificate to the path above:
+        
+        ssh-keygen -s <user-ca-private-key> -I sira -n {sira_user} {}
+",
+        certificate.display(),
+        user_ca.display(),
+        public_key(LOGIN_KEY).display(),
+    );
+}
+
+/// Installs an `@cert-authority` line for a host certificate authority into Sira's known hosts
+/// file, creating the file if needed. Does nothing if the line is already present.
+fn install_host_ca(host_ca: &Path) {
+    let key = fs::read_to_string(host_ca).expect("could not read host certificate authority");
+    let line = crypto::cert_authority_line(&key, "*")
+        .unwrap_or_else(|e| panic!("{}: {e}", host_ca.display()));
+
+    let known_hosts = crypto::known_hosts_path();
+    let mut contents = if path_exists(&known_hosts, "known hosts file") {
+        fs::read_to_string(&known_hosts).expect("could not read known hosts file")
+    } else {
+        String::new()
+    };
+    if contents.lines().any(|existing| existing == line.trim_end()) {
+        return;
+    }
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(&line);
+
+    // Write the known hosts file to a temp file.
+    let (mut file, temp_file_path) = client::mktemp().expect("could not open temporary file");
+    file.write_all(contents.as_bytes())
+        .expect("error writing temporary file");
+    file.flush().expect("error flushing temporary file");
+    drop(file);
+
+    println!(
+        "Trusting host certificate authority in known hosts file: {}\n\
+        You might be prompted for your password one or more times.",
+        known_hosts.display(),
+    );
+    client::run(
+        "sudo",
+        &[
+            OsStr::new("cp"),
+            OsStr::new(&temp_file_path),
+            known_hosts.as_os_str(),
+        ],
+    )
+    .expect("error copying known hosts file to Sira config directory");
+    fs::remove_file(&temp_file_path).expect("error cleaning up temporary file");
+
+    let owner = root_owner();
+    println!("Setting owner to {owner}");
+    client::run(
+        "sudo",
+        &[
+            OsStr::new("chown"),
+            OsStr::new(&owner),
+            known_hosts.as_ref(),
+        ],
+    )
+    .expect("could not chown known hosts file");
+
+    let mode = "0644";
+    println!("Setting mode to {mode}");
+    client::run(
+        "sudo",
+        &[OsStr::new("chmod"), OsStr::new(&mode), known_hosts.as_ref()],
+    )
+    .expect("could not chmod known hosts file");
+    println!("Known hosts file installed.\n");
 }
 
 /// Checks whether a public key is present either in the public key directory or as an allowed
@@ -363,13 +526,13 @@ fn create_config_dir(dir: impl AsRef<Path>) {
     client::run("sudo", &[OsStr::new("mkdir"), dir.as_ref().as_ref()])
         .expect("could not create config directory");
 
-    let owner = "root:root";
+    let owner = root_owner();
     println!("Setting owner to {owner}");
     client::run(
         "sudo",
         &[
             OsStr::new("chown"),
-            OsStr::new(owner),
+            OsStr::new(&owner),
             dir.as_ref().as_ref(),
         ],
     )
@@ -474,13 +637,13 @@ fn install_allowed_signers_file(dir: impl AsRef<Path>, key_name: impl AsRef<Path
     )
     .expect("error copying allowed signers file to Sira config directory");
 
-    let owner = "root:root";
+    let owner = root_owner();
     println!("Setting owner to {owner}");
     client::run(
         "sudo",
         &[
             OsStr::new("chown"),
-            OsStr::new(owner),
+            OsStr::new(&owner),
             allowed_signers_file.as_ref(),
         ],
     )
@@ -541,13 +704,13 @@ fn install_signing_key_pair(dir: impl AsRef<Path>, key_name: impl AsRef<Path>) {
     )
     .expect("could not chown private key");
 
-    let owner = "root:root";
+    let owner = root_owner();
     println!("Setting owner to {owner}");
     client::run(
         "sudo",
         &[
             OsStr::new("chown"),
-            OsStr::new(owner),
+            OsStr::new(&owner),
             installed_public_key.as_ref(),
         ],
     )
@@ -603,13 +766,14 @@ fn key_exists(dir: impl AsRef<Path>, key_name: impl AsRef<Path>) -> bool {
 /// Installer logic that should run on the managed node as root.
 fn managed_node(sira_user: &str) {
     // Move the login public key to the Sira user's ~/.ssh/authorized_keys, ensuring correct permissions.
-    // Feel free to assume it's at /home/<sira-user>. If someone wants to deploy this in a funky
-    // setup, they can write their own installer or modify this one; this is all well-documented.
+    // Feel free to assume it's at /home/<sira-user> (please see platform::home_dir). If someone
+    // wants to deploy this in a funky setup, they can write their own installer or modify this
+    // one; this is all well-documented.
     //
     // If you're reading this because you want to modify the installer, and you think your changes
     // will be useful to others as well, please feel free to open an issue to discuss them.
-    {
-        let sira_home_dir = Path::new("/home").join(sira_user);
+    'login_key: {
+        let sira_home_dir = platform::home_dir(sira_user);
         let sira_ssh_dir = sira_home_dir.join(".ssh");
 
         // Create the Sira user's ~/.ssh if it doesn't exist. If the Sira user doesn't exist,
@@ -639,6 +803,16 @@ fn managed_node(sira_user: &str) {
             println!();
         }
 
+        // If the control node sent a user certificate authority instead of a login public key,
+        // sshd will authorize the Sira user by certificate, so there is nothing to install here.
+        if !key_exists(".", public_key(LOGIN_KEY)) {
+            assert!(
+                key_exists(".", public_key(USER_CA_KEY)),
+                "found neither a login public key nor a user certificate authority to install",
+            );
+            break 'login_key;
+        }
+
         // For security, deliberately wipe out any existing contents of AUTHORIZED_KEYS.
         println!("Installing Sira user's public key as ~/.ssh/authorized_keys");
         let authorized_keys = sira_ssh_dir.join("authorized_keys");
@@ -657,6 +831,54 @@ fn managed_node(sira_user: &str) {
         println!();
     }
 
+    // If present in the CWD, install the user certificate authority and configure sshd to trust
+    // it, so that the Sira user can log in with any certificate that the authority signs for it.
+    if key_exists(".", public_key(USER_CA_KEY)) {
+        println!("Installing user certificate authority as {SSHD_USER_CA_PATH}");
+        client::run(
+            "mv",
+            &[
+                public_key(USER_CA_KEY).as_ref(),
+                Path::new(SSHD_USER_CA_PATH),
+            ],
+        )
+        .expect("error moving user certificate authority");
+
+        println!("Setting owner.");
+        client::run("chown", &[root_owner().as_str(), SSHD_USER_CA_PATH])
+            .expect("error chowning user certificate authority");
+
+        println!("Setting mode.");
+        client::run("chmod", &["0644", SSHD_USER_CA_PATH])
+            .expect("error chmodding user certificate authority");
+
+        // If the sshd drop-in directory exists, use it. Otherwise, modify sshd_config.
+        let path = if path_exists(SSHD_CONFIG_DIR, "sshd drop-in configuration directory") {
+            let path = format!("{SSHD_CONFIG_DIR}/10_sira.conf");
+            if !path_exists(&path, "Sira sshd configuration file") {
+                println!("Creating file: {path}");
+                let _ = File::create(&path).expect("could not create sshd configuration file");
+            }
+            path
+        } else {
+            SSHD_CONFIG.to_string()
+        };
+
+        println!("Updating {path} to trust the user certificate authority.");
+        action::line_in_file(&Action::LineInFile {
+            path: path.clone(),
+            line: format!("TrustedUserCAKeys {SSHD_USER_CA_PATH}"),
+            pattern: Some("^TrustedUserCAKeys ".to_string()),
+            after: None,
+            indent: false,
+        })
+        .expect("error updating sshd configuration");
+        println!(
+            "Please reload sshd (e.g. `systemctl reload ssh`, `service sshd reload`, or \
+            `rcctl reload sshd`) for this change to take effect.\n"
+        );
+    }
+
     // Ensure existence of the client install directory. Don't mangle the administrator's owner,
     // group, or permissions: by default, this operation should require root, but if the
     // administrator is doing something different, we'll trust them to know what they're doing.
@@ -675,8 +897,8 @@ fn managed_node(sira_user: &str) {
 
         println!("Setting owner.");
         let client_path = Path::new(CLIENT_INSTALL_DIR).join(CLIENT_BIN);
-        let owner = "root:root";
-        client::run("chown", &[OsStr::new(owner), client_path.as_ref()])
+        let owner = root_owner();
+        client::run("chown", &[OsStr::new(&owner), client_path.as_ref()])
             .expect("error chowning client binary");
 
         println!("Setting mode.");
@@ -686,32 +908,72 @@ fn managed_node(sira_user: &str) {
         println!();
     }
 
-    // Install the Sira user in sudoers, idempotently.
-    //
-    // If /etc/sudoers.d exists, use it. Otherwise, modify /etc/sudoers.
-    {
+    // OpenBSD ships doas instead of sudo, so unless sudo is installed there, permit the Sira user
+    // to run sira-client as root with doas instead, idempotently.
+    let sudoers_dir = Path::new(platform::sudoers_dir());
+    let sudo_installed = path_exists(sudoers_dir.join("sudoers"), "sudoers file");
+    if env::consts::OS == "openbsd" && !sudo_installed {
+        let path = "/etc/doas.conf";
+
+        println!("Permitting {sira_user} to run {CLIENT_BIN} with doas.");
+        if !path_exists(path, "doas configuration file") {
+            println!("Creating file: {path}");
+            let _ = File::create(path).expect("could not create doas configuration file");
+
+            println!("Setting mode.");
+            client::run("chmod", &["0600", path]).expect("error chmodding doas configuration file");
+        }
+
+        // sira-client runs scripts with `doas -u <user>`, which root may only do if permitted.
+        println!("Updating {path} with entries for {sira_user} and root");
+        for line in [
+            format!("permit nopass {sira_user} as root cmd /opt/sira/bin/sira-client"),
+            "permit nopass root".to_string(),
+        ] {
+            action::line_in_file(&Action::LineInFile {
+                path: path.to_string(),
+                line,
+                pattern: None,
+                after: None,
+                indent: false,
+            })
+            .expect("error updating doas configuration file");
+        }
+        println!(
+            "Please set `escalation = \"doas\"` for this host in the control node's sira.toml.\n"
+        );
+    } else {
+        // Install the Sira user in sudoers, idempotently.
+        //
+        // If sudoers.d exists, use it. Otherwise, modify sudoers.
+
         // Components of the eventual Action::LineInFile; we will shadow these if needed.
-        let path = "/etc/sudoers";
-        let line = format!("{sira_user}\tALL=(root:root) NOPASSWD:/opt/sira/bin/sira-client");
+        let path = sudoers_dir.join("sudoers");
+        let line = format!(
+            "{sira_user}\tALL=({}) NOPASSWD:/opt/sira/bin/sira-client",
+            root_owner(),
+        );
         let pattern = None;
         let after = None;
         let indent = false;
 
         println!("Installing {sira_user} as a sudoer.");
-        if path_exists("/etc/sudoers.d", "/etc/sudoers.d directory") {
-            let path = "/etc/sudoers.d/10_sira";
+        let drop_in_dir = sudoers_dir.join("sudoers.d");
+        if path_exists(&drop_in_dir, "sudoers.d directory") {
+            let path = drop_in_dir.join("10_sira");
 
-            if !path_exists(path, "Sira sudoers file") {
-                println!("Creating file: {path}");
-                let _ = File::create(path).expect("could not create sudoers file");
+            if !path_exists(&path, "Sira sudoers file") {
+                println!("Creating file: {}", path.display());
+                let _ = File::create(&path).expect("could not create sudoers file");
 
                 println!("Setting mode.");
-                client::run("chmod", &["0640", path]).expect("error chmodding sudoers file");
+                client::run("chmod", &[OsStr::new("0640"), path.as_ref()])
+                    .expect("error chmodding sudoers file");
             }
 
-            println!("Updating {path} with entry for {sira_user}");
+            println!("Updating {} with entry for {sira_user}", path.display());
             action::line_in_file(&Action::LineInFile {
-                path: path.to_string(),
+                path: path.to_string_lossy().into_owned(),
                 line,
                 pattern,
                 after,
@@ -719,9 +981,9 @@ fn managed_node(sira_user: &str) {
             })
             .expect("error updating sudoers file");
         } else {
-            println!("Updating {path} with entry for {sira_user}");
+            println!("Updating {} with entry for {sira_user}", path.display());
             action::line_in_file(&Action::LineInFile {
-                path: path.to_string(),
+                path: path.to_string_lossy().into_owned(),
                 line,
                 pattern,
                 after,
@@ -748,10 +1010,43 @@ fn managed_node(sira_user: &str) {
         }
     }
 
+    // Generate an age identity for decrypting encrypted uploads, if age is installed and the
+    // identity doesn't exist yet. The private half never leaves this node; the administrator copies
+    // the public half to the control node's recipients directory.
+    {
+        let identity = crypto::upload_identity_path();
+        if !path_exists(&identity, "upload identity") {
+            println!("Generating upload identity: {}", identity.display());
+            match client::run("age-keygen", &[OsStr::new("-o"), identity.as_ref()]) {
+                Ok(()) => {
+                    client::run("chmod", &[OsStr::new("0600"), identity.as_ref()])
+                        .expect("error chmodding upload identity");
+                    println!(
+                        // Wrapped to 80 characters.
+                        "To enable encrypted uploads to this node, save the public key above on the\n\
+                        control node as {}\n",
+                        crypto::recipients_path("<managed-node>")
+                            .expect("placeholder host should be a valid file name")
+                            .display(),
+                    );
+                }
+                Err(_) => {
+                    println!("Skipping upload identity; install age to enable encrypted uploads.\n")
+                }
+            }
+        }
+    }
+
     // Remove the installer; we're done with it.
     fs::remove_file(INSTALLER_BIN).expect("error cleaning up installer");
 }
 
+/// Returns the owner of files that only root may change, e.g. `root:root`, or `root:wheel` on
+/// systems that have no `root` group.
+fn root_owner() -> String {
+    format!("root:{}", platform::group("root"))
+}
+
 /// Wraps [Path::try_exists] with a panic in case of failure
 ///
 /// `desc` is a very brief description of the file type.
@@ -781,6 +1076,7 @@ fn prompt_to_generate_signing_key_pair(
     dir: impl AsRef<Path>,
     key_name: impl AsRef<Path>,
     flag_file: impl AsRef<Path>,
+    options: &ControlNodeOptions,
 ) -> bool {
     // Implementation detail: the flag file is in the CWD, not starting_directory.
     //
@@ -816,9 +1112,19 @@ fn prompt_to_generate_signing_key_pair(
             \n\
             Both keys are independent: you may freely set neither, either, or both.\n\
             \n\
-            Protecting these keys with strong and unique passwords is highly recommended.\n",
+            Protecting these keys with strong and unique passwords is highly recommended.\n\
+            {security_key}",
             manifest = key_exists(allowed_signers_dir(), MANIFEST_KEY),
             action = key_exists(key_dir(), ACTION_KEY),
+            security_key = match options.security_key {
+                // Wrapped to 80 characters.
+                true =>
+                    "\n\
+                    Keys will be generated on your security key. Signing with a security key\n\
+                    requires a touch each time, so an action key on a security key requires a\n\
+                    touch for every action on every managed node.\n",
+                false => "",
+            },
         );
     }
 
@@ -837,7 +1143,7 @@ fn prompt_to_generate_signing_key_pair(
         .starts_with('n')
     {
         println!();
-        ssh_keygen(dir.as_ref().join(key_name));
+        ssh_keygen(dir.as_ref().join(key_name), options.security_key);
         true
     } else {
         let _ = File::create(flag_file).expect("could not create flag file");
@@ -856,15 +1162,22 @@ fn public_key(private_key: impl AsRef<Path>) -> PathBuf {
 
 /// Runs `ssh-keygen` to generate a key pair suitable for Sira.
 ///
-/// `key_file` is the path to the private key.
+/// `key_file` is the path to the private key. If `security_key`, the key pair is generated on a
+/// FIDO2 hardware security key, and `key_file` merely refers to it.
 ///
 /// Panics if this process exits with an error.
-fn ssh_keygen(key_file: impl AsRef<OsStr>) {
+fn ssh_keygen(key_file: impl AsRef<OsStr>, security_key: bool) {
+    if security_key {
+        println!("Touch your security key when it blinks to generate the key pair.");
+    }
     client::run(
         "ssh-keygen",
         &[
             OsStr::new("-t"),
-            OsStr::new("ed25519"),
+            OsStr::new(match security_key {
+                true => "ed25519-sk",
+                false => "ed25519",
+            }),
             OsStr::new("-C"),
             OsStr::new("sira"),
             OsStr::new("-f"),
@@ -0,0 +1,75 @@
+//! The `sira adhoc` subcommand.
+
+use crate::Outcome;
+use anyhow::bail;
+use sira::core::{Action, Plan};
+use sira::run_plan::{adhoc, inventory, report, RunOptions};
+
+/// `sira adhoc [--profile <name>] --hosts <pattern>[,<pattern>]... (--command <command> | --upload
+/// <from> <to>) [-v|-vv] [<manifest-file>...]`
+///
+/// Runs a single action on the hosts that the patterns select, without a manifest file. Patterns
+/// with wildcards match hosts named in the manifest files, if any, and in the `hosts` table in
+/// sira.toml; please see [adhoc::select_hosts]. The manifest files' actions are not run.
+///
+/// Unlike a normal run, it prints the output of every action by default, since that is usually
+/// the point. Other settings, e.g. the audit log, come from sira.toml.
+pub async fn adhoc(args: &[String]) -> anyhow::Result<Outcome> {
+    let (plan, options) = prepare(args).map_err(crate::invalid_input)?;
+    crate::run_and_report(plan, options).await
+}
+
+/// Implements the part of [adhoc] that parses `args` and builds the [Plan].
+fn prepare(args: &[String]) -> anyhow::Result<(Plan, RunOptions)> {
+    let (config, args) = crate::load_config(args)?;
+    let mut options = RunOptions::from_config(config);
+    let mut patterns = vec![];
+    let mut action = None;
+    let mut manifest_files = vec![];
+    let mut verbosity = 0;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--command" => match args.next() {
+                Some(command) => set_action(&mut action, Action::Command(vec![command.clone()]))?,
+                None => bail!("{arg} requires a command"),
+            },
+            "--hosts" => match args.next() {
+                Some(list) => patterns.extend(list.split(',').map(str::to_string)),
+                None => bail!("{arg} requires a comma-separated list of hosts or patterns"),
+            },
+            "--upload" => match (args.next(), args.next()) {
+                (Some(from), Some(to)) => set_action(&mut action, adhoc::upload(from, to))?,
+                _ => bail!("{arg} requires a source file and a destination path"),
+            },
+            flag if crate::is_verbose_flag(flag) => verbosity += flag.len() - 1,
+            _ => manifest_files.push(arg),
+        }
+    }
+
+    let Some(action) = action else {
+        bail!(
+            "Usage: sira adhoc [--profile <name>] --hosts <pattern>[,<pattern>]... \
+            (--command <command> | --upload <from> <to>) [-v|-vv] [<manifest-file>...]"
+        );
+    };
+    let mut plan = Plan::from_manifest_files(&manifest_files)?;
+    inventory::apply(&mut plan, &mut options)?;
+    let mut known = plan.hosts();
+    known.extend(options.hosts.keys().cloned());
+    let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+    let hosts = adhoc::select_hosts(&patterns, &known)?;
+
+    options.verbosity = report::Verbosity::from_count(verbosity + 2);
+    Ok((adhoc::plan(&hosts, action), options))
+}
+
+/// Sets `action`, unless it is already set.
+fn set_action(action: &mut Option<Action>, new: Action) -> anyhow::Result<()> {
+    if action.is_some() {
+        bail!("sira adhoc runs a single action; please give either --command or --upload, once");
+    }
+    *action = Some(new);
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! The `sira agent-add` subcommand.
+
+use anyhow::bail;
+use sira::config;
+use sira::crypto::agent::{self, Constraints};
+use sira::crypto::KEY_DIR;
+use sira::run_plan::{rotate_keys, ACTION_SIGNING_KEY};
+use std::path::PathBuf;
+
+/// `sira agent-add [--profile <name>] [--confirm] [--lifetime <time>] [<key-file>...]`
+///
+/// Adds keys to `ssh-agent`, optionally asking it to confirm each use of them or to forget them
+/// after a while. Without key files, adds the login key and the action key, as set in sira.toml or
+/// else where Sira installs them, skipping either if it doesn't exist.
+pub fn add(args: &[String]) -> anyhow::Result<()> {
+    let (config, args) = crate::load_config(args)?;
+    let mut constraints = Constraints::default();
+    let mut key_files = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--confirm" => constraints.confirm = true,
+            "--lifetime" => match args.next() {
+                Some(time) => constraints.lifetime = Some(time.clone()),
+                None => bail!("{arg} requires a time, e.g. 30m or 1h30m"),
+            },
+            _ => key_files.push(PathBuf::from(arg)),
+        }
+    }
+
+    if key_files.is_empty() {
+        let login_key = config.login_key.or_else(|| {
+            home::home_dir().map(|home| home.join(".ssh").join(rotate_keys::LOGIN_KEY))
+        });
+        let action_key = config
+            .action_key
+            .unwrap_or_else(|| config::config_dir().join(KEY_DIR).join(ACTION_SIGNING_KEY));
+        key_files = login_key
+            .into_iter()
+            .chain([action_key])
+            .filter(|key| key.exists())
+            .collect();
+        if key_files.is_empty() {
+            bail!(
+                "found no login or action key to add; please name the key files, \
+                e.g. sira agent-add ~/.ssh/sira"
+            );
+        }
+    }
+
+    for key_file in &key_files {
+        agent::add(key_file, &constraints)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! The `sira audit-verify` subcommand.
+
+use anyhow::bail;
+use sira::crypto;
+use sira::run_plan::report::title;
+use sira::run_plan::{audit, ACTION_SIGNING_KEY};
+use std::path::PathBuf;
+
+/// `sira audit-verify [--allowed-signers <file>] <audit-log>`
+///
+/// Verifies every record in an audit log and prints a summary of each one. By default, checks
+/// signatures against the action allowed signers file.
+pub fn verify(args: &[String]) -> anyhow::Result<()> {
+    let mut allowed_signers = None;
+    let mut logs = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--allowed-signers" => match args.next() {
+                Some(path) => allowed_signers = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to an allowed signers file"),
+            },
+            _ => logs.push(arg),
+        }
+    }
+
+    let [log] = logs[..] else {
+        bail!("Usage: sira audit-verify [--allowed-signers <file>] <audit-log>");
+    };
+
+    let allowed_signers = match allowed_signers {
+        Some(path) => path,
+        None => crypto::allowed_signers_path(ACTION_SIGNING_KEY)?,
+    };
+
+    let records = audit::verify_log(log, &allowed_signers, "sira")?;
+    for record in &records {
+        let outcome = match (record.success, record.exit_code) {
+            (true, _) => "ok".to_string(),
+            (false, Some(code)) => format!("exit code {code}"),
+            (false, None) => "error".to_string(),
+        };
+        println!(
+            "{} {} [{}] {} ({outcome})",
+            record.timestamp,
+            record.user,
+            record.host,
+            title(&record.action),
+        );
+    }
+    println!("Verified {} record(s) in {log}", records.len());
+    Ok(())
+}
@@ -0,0 +1,317 @@
+//! The `sira daemon` subcommand.
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local};
+use sira::config::{Config, Confirmation};
+use sira::core::Plan;
+use sira::run_plan::api::{self, read_token, RunRegistry, RunRequest};
+use sira::run_plan::drift::DriftReport;
+use sira::run_plan::email::EmailConfig;
+use sira::run_plan::report::{self, Reporter, Verbosity};
+use sira::run_plan::schedule::ScheduledRun;
+use sira::run_plan::syslog::{self, Priority};
+use sira::run_plan::webhook::{Notifier, Webhook};
+use sira::run_plan::{inventory, run_plan_with_ui, RunOptions};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex};
+
+/// The longest that the daemon sleeps at a time while waiting for a run, so that it notices when
+/// the clock jumps, e.g. when the control node resumes from suspend.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// `sira daemon [--history <file>] [--webhook [slack:|matrix:]<url>]...`
+///
+/// Starts each run in the `schedule` table of sira.toml whenever it's due, until killed. Please see
+/// [sira::run_plan::schedule]. Runs take turns, so a run that comes due while another is underway
+/// starts once that one finishes, and a run that came due several times in the meantime starts
+/// only once.
+///
+/// With an `api` table in sira.toml, the daemon also serves an HTTP API through which clients can
+/// submit runs, which take turns with the scheduled ones, and follow every run. Please see
+/// [sira::run_plan::api].
+///
+/// Each run records itself in the history database from `--history`, or else from the `history`
+/// setting of its profile, and the daemon refuses to start unless every scheduled run has one.
+/// Each run notifies the webhooks from `--webhook` and the email recipients in email.yaml as usual,
+/// and the webhooks also hear about scheduled runs that can't start. Besides printing each run's
+/// failures, the daemon prints, and with `syslog = true` in sira.toml also logs, when each run
+/// starts and finishes and when each scheduled run is next due.
+///
+/// The daemon reads sira.toml once, at startup, but it loads each run's manifest files afresh for
+/// each run.
+pub async fn daemon(args: &[String]) -> anyhow::Result<()> {
+    const USAGE: &str =
+        "Usage: sira daemon [--history <file>] [--webhook [slack:|matrix:]<url>]...";
+
+    let config = Config::load()?;
+    let mut history = None;
+    let mut webhooks = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--history" => match args.next() {
+                Some(path) => history = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a history database"),
+            },
+            "--webhook" => match args.next() {
+                Some(webhook) => webhooks.push(webhook.parse()?),
+                None => bail!("{arg} requires a webhook URL"),
+            },
+            _ => bail!(USAGE),
+        }
+    }
+    if config.schedule.is_empty() && config.api.is_none() {
+        bail!(
+            "nothing to do; please add a schedule to sira.toml, e.g. [schedule.enforce], or turn \
+            on the API with [api]"
+        );
+    }
+
+    // Catch mistakes now rather than whenever each run comes due.
+    let now = Local::now();
+    let mut due = BTreeMap::new();
+    for (name, run) in &config.schedule {
+        let settings = run
+            .config(&config)
+            .with_context(|| format!("invalid schedule {name}"))?;
+        if history.is_none() && settings.history.is_none() {
+            bail!(
+                "schedule {name} has no history database; please set history in sira.toml or \
+                pass --history <file>"
+            );
+        }
+        due.insert(name.clone(), next_due(name, run, &now)?);
+    }
+
+    let daemon = Arc::new(Daemon {
+        config,
+        history,
+        webhooks,
+        registry: RunRegistry::new(),
+        turn: Mutex::new(()),
+    });
+    let config = &daemon.config;
+    if let Some(api) = &config.api {
+        let token = read_token(&api.token_file)?;
+        let listener = TcpListener::bind(api.listen())
+            .await
+            .with_context(|| format!("API could not listen on {}", api.listen()))?;
+        let (sender, mut submissions) = mpsc::unbounded_channel();
+        tokio::spawn(api::serve(listener, token, daemon.registry.clone(), sender));
+        let api_daemon = daemon.clone();
+        tokio::spawn(async move {
+            while let Some(id) = submissions.recv().await {
+                let daemon = api_daemon.clone();
+                tokio::spawn(async move { daemon.run(id, None).await });
+            }
+        });
+        let message = format!("API listening on {}", api.listen());
+        announce(config, "daemon", Priority::Info, message);
+    }
+
+    announce(
+        config,
+        "daemon",
+        Priority::Info,
+        format!("Started with {} schedules", due.len()),
+    );
+    for (name, next) in &due {
+        announce_next(config, name, next);
+    }
+    loop {
+        let Some((name, next)) = due
+            .iter()
+            .min_by_key(|(_, next)| **next)
+            .map(|(name, next)| (name.clone(), *next))
+        else {
+            // Only the API has anything to do.
+            return std::future::pending().await;
+        };
+        wait_until(next).await;
+
+        let run = &config.schedule[&name];
+        let id = daemon.registry.submit(&name, run.into());
+        daemon.run(id, Some(&name)).await;
+        let next = next_due(&name, run, &Local::now())?;
+        announce_next(config, &name, &next);
+        due.insert(name, next);
+    }
+}
+
+/// What every run that the daemon starts shares.
+struct Daemon {
+    /// The settings from sira.toml.
+    config: Config,
+
+    /// The history database from `--history`, if any.
+    history: Option<PathBuf>,
+
+    /// The webhooks from `--webhook`.
+    webhooks: Vec<Webhook>,
+
+    /// Every run that the daemon remembers, for the API.
+    registry: RunRegistry,
+
+    /// Held by the run that's underway, so that runs take turns.
+    turn: Mutex<()>,
+}
+
+impl Daemon {
+    /// Runs the run with ID `id` in [Daemon::registry] once it gets its turn, and reports how it
+    /// went. `schedule` names the run's schedule, if it's a scheduled run rather than one from the
+    /// API.
+    ///
+    /// A run that can't start is reported rather than returned as an error, so that the daemon
+    /// keeps running, and the next run might succeed, e.g. once a manifest file is fixed.
+    async fn run(&self, id: u64, schedule: Option<&str>) {
+        let _turn = self.turn.lock().await;
+        let name = &schedule.map_or_else(|| format!("api run {id}"), str::to_string);
+        let config = &self.config;
+        let Some(run) = self.registry.get(id) else {
+            return;
+        };
+        announce(config, name, Priority::Info, "Starting run");
+        let (plan, mut options) = match self.prepare(&run.request) {
+            Ok(prepared) => prepared,
+            Err(error) => {
+                let message = format!("Run could not start: {error:#}");
+                announce(config, name, Priority::Err, message);
+                // API clients hear about it from the API instead.
+                if let Some(schedule) = schedule {
+                    Notifier::new(self.webhooks.clone()).not_started(schedule, &error);
+                }
+                self.registry.not_started(id, &error);
+                return;
+            }
+        };
+
+        let (status, events) = self.registry.start(id);
+        options.status = Some(status);
+        let drift = options.check.clone();
+        let ui = (events, Reporter::new(options.verbosity, options.color));
+        let errors = run_plan_with_ui(plan, options, ui)
+            .await
+            .err()
+            .unwrap_or_default();
+        self.registry.finish(id, &errors, drift.as_ref());
+        let mut stderr = io::stderr().lock();
+        for (host, error) in &errors {
+            let _ = report::print_host_message(&mut stderr, host, format!("{error:#}"));
+        }
+        drop(stderr);
+        let drifted = match drift {
+            Some(drift) => {
+                let _ = drift.write(&mut io::stdout().lock());
+                !drift.is_compliant()
+            }
+            None => false,
+        };
+
+        let (priority, message) = match (errors.len(), drifted) {
+            (0, false) => (
+                Priority::Info,
+                "Run finished: every host succeeded".to_string(),
+            ),
+            (0, true) => (
+                Priority::Err,
+                "Run finished: at least one host doesn't meet its manifests".to_string(),
+            ),
+            (failed, _) => (
+                Priority::Err,
+                format!("Run finished: {failed} hosts failed"),
+            ),
+        };
+        announce(config, name, priority, message);
+    }
+
+    /// Returns the [Plan] and [RunOptions] with which to run `request` now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the plan can't be loaded, if the run has no history database, or if the
+    /// run would need somebody to confirm an action, since nobody is around to do so.
+    fn prepare(&self, request: &RunRequest) -> anyhow::Result<(Plan, RunOptions)> {
+        let settings = self.config.clone().select(request.profile.as_deref())?;
+        let mut options = RunOptions {
+            email: EmailConfig::load()?,
+            webhooks: self.webhooks.clone(),
+            verbosity: Verbosity::Quiet,
+            check: request.check.then(DriftReport::new),
+            ..RunOptions::from_config(settings)
+        };
+        if let Some(history) = &self.history {
+            options.history = Some(history.clone());
+        }
+        if options.history.is_none() {
+            bail!("the run has no history database; please set history in sira.toml");
+        }
+        let mut plan = Plan::from_manifest_files(&request.manifests)?;
+        inventory::apply(&mut plan, &mut options)?;
+
+        // Nothing asks for confirmation in check mode.
+        if options.check.is_none() {
+            if options.confirm == Confirmation::All {
+                bail!(
+                    "confirm = \"all\" requires confirmation for every action, so nothing can run"
+                );
+            }
+            let mut tasks = plan.manifests.iter().flat_map(|manifest| &manifest.include);
+            if let Some(task) = tasks.find(|task| task.confirm) {
+                bail!(
+                    "task {:?} requires confirmation, so it can't run unattended",
+                    task.name,
+                );
+            }
+        }
+        Ok((plan, options))
+    }
+}
+
+/// Returns when `run`, named `name`, is next due after `after`.
+///
+/// # Errors
+///
+/// Returns an error if it never is, e.g. because its schedule names February 30.
+fn next_due(
+    name: &str,
+    run: &ScheduledRun,
+    after: &DateTime<Local>,
+) -> anyhow::Result<DateTime<Local>> {
+    match run.cron.next_after(after) {
+        Some(next) => Ok(next),
+        None => bail!("schedule {name} never comes due: {}", run.cron),
+    }
+}
+
+/// Sleeps until `time`, a little at a time.
+async fn wait_until(time: DateTime<Local>) {
+    while let Ok(remaining) = (time - Local::now()).to_std() {
+        if remaining.is_zero() {
+            break;
+        }
+        tokio::time::sleep(remaining.min(MAX_SLEEP)).await;
+    }
+}
+
+/// Prints `message` about the run named `name`, or about the daemon itself, and sends it to the
+/// system log if sira.toml says to.
+fn announce(config: &Config, name: &str, priority: Priority, message: impl AsRef<str>) {
+    let message = format!("[{name}] {}", message.as_ref());
+    println!("{message}");
+    if config.syslog.unwrap_or_default() {
+        if let Err(e) = syslog::log(priority, &message) {
+            eprintln!("Warning: could not write to the system log: {e}");
+        }
+    }
+}
+
+/// Announces when the run named `name` is next due.
+fn announce_next(config: &Config, name: &str, next: &DateTime<Local>) {
+    let message = format!("Next run at {}", next.format("%Y-%m-%d %H:%M %:z"));
+    announce(config, name, Priority::Info, message);
+}
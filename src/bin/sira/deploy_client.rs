@@ -0,0 +1,33 @@
+//! The `sira deploy-client` subcommand.
+
+use anyhow::bail;
+use sira::core::Plan;
+use sira::run_plan::RunOptions;
+use sira::run_plan::{deploy_client, inventory};
+
+/// `sira deploy-client [--profile <name>] <manifest-file>...`
+///
+/// Installs the matching `sira-client` binary on every host named in the manifest files, e.g. to
+/// upgrade it. The manifest files are only used to build the list of hosts; their actions are not
+/// run.
+pub async fn deploy_client(args: &[String]) -> anyhow::Result<()> {
+    let (config, args) = crate::load_config(args)?;
+    if args.is_empty() {
+        bail!("Usage: sira deploy-client [--profile <name>] <manifest-file>...");
+    }
+
+    let mut options = RunOptions::from_config(config);
+    let mut plan = Plan::from_manifest_files(&args)?;
+    inventory::apply(&mut plan, &mut options)?;
+    let hosts = plan.hosts();
+    let Err(errors) = deploy_client::deploy_client(&hosts, options).await else {
+        println!("\nDeployed sira-client to {} hosts.", hosts.len());
+        return Ok(());
+    };
+
+    let mut message = String::from("could not deploy sira-client to these hosts:\n");
+    for (host, error) in errors {
+        message.push_str(&format!("[{host}] {error:?}\n"));
+    }
+    bail!(message)
+}
@@ -0,0 +1,54 @@
+//! The `sira facts` subcommand.
+
+use crate::Outcome;
+use anyhow::bail;
+use sira::core::Plan;
+use sira::run_plan::{facts, inventory, RunOptions};
+
+/// `sira facts [--profile <name>] [--refresh-facts] <manifest-file>...`
+///
+/// Prints the facts of every host named in the manifest files, one line of JSON per host, e.g.
+/// `{"host":"web1","facts":{...}}`. Uses cached facts while they're fresh, unless
+/// `--refresh-facts` is given. Like `sira ping`, it uses the manifest files only to build the list
+/// of hosts. Exits with [Outcome::Unreachable] if any host's facts couldn't be gathered.
+pub async fn facts(args: &[String]) -> anyhow::Result<Outcome> {
+    let (hosts, options) = prepare(args).map_err(crate::invalid_input)?;
+    let mut failed = 0;
+    for (host, facts) in facts::gather(&hosts, &options).await {
+        match facts {
+            Ok(facts) => println!("{}", serde_json::json!({"host": host, "facts": facts})),
+            Err(e) => {
+                eprintln!("Sira could not gather facts from {host}: {e:#}");
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("\n{failed} of {} hosts did not answer.", hosts.len());
+        return Ok(Outcome::Unreachable);
+    }
+    Ok(Outcome::Success)
+}
+
+/// Implements the part of [facts] that parses `args` and loads the hosts.
+fn prepare(args: &[String]) -> anyhow::Result<(Vec<String>, RunOptions)> {
+    const USAGE: &str = "Usage: sira facts [--profile <name>] [--refresh-facts] <manifest-file>...";
+
+    let (config, args) = crate::load_config(args)?;
+    let mut options = RunOptions::from_config(config);
+    let mut manifest_files = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--refresh-facts" => options.refresh_facts = true,
+            flag if flag.starts_with("--") => bail!(USAGE),
+            _ => manifest_files.push(arg),
+        }
+    }
+    if manifest_files.is_empty() {
+        bail!(USAGE);
+    }
+    let mut plan = Plan::from_manifest_files(&manifest_files)?;
+    inventory::apply(&mut plan, &mut options)?;
+    Ok((plan.hosts(), options))
+}
@@ -0,0 +1,72 @@
+//! The `sira fmt` subcommand.
+
+use anyhow::{bail, Context};
+use sira::core::manifest;
+use sira::crypto;
+use std::fs;
+use std::path::PathBuf;
+
+/// `sira fmt [--check] <manifest-file>...`
+///
+/// Rewrites each manifest file and every task file it includes in canonical form. Please see
+/// [manifest::format_manifest]. With `--check`, changes nothing, but lists the files that would
+/// change and exits with an error if there are any, e.g. for CI.
+pub fn fmt(args: &[String]) -> anyhow::Result<()> {
+    let mut check = false;
+    let mut manifest_files = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => manifest_files.push(arg),
+        }
+    }
+
+    if manifest_files.is_empty() {
+        bail!("Usage: sira fmt [--check] <manifest-file>...");
+    }
+
+    let mut seen: Vec<PathBuf> = vec![];
+    let mut unformatted = 0;
+    for manifest_file in manifest_files {
+        // The first file in each tree is the manifest file itself; the rest are task files.
+        for (index, file) in manifest::manifest_tree(manifest_file)?
+            .into_iter()
+            .enumerate()
+        {
+            if seen.contains(&file) {
+                continue;
+            }
+            let source =
+                fs::read(&file).with_context(|| format!("could not read {}", file.display()))?;
+            let formatted = match index {
+                0 => manifest::format_manifest(&source),
+                _ => manifest::format_tasks(&source),
+            }
+            .with_context(|| format!("could not parse {}", file.display()))?;
+
+            if formatted.as_bytes() != source {
+                if check {
+                    println!("Would reformat {}", file.display());
+                    unformatted += 1;
+                } else {
+                    fs::write(&file, formatted)
+                        .with_context(|| format!("could not write {}", file.display()))?;
+                    println!("Formatted {}", file.display());
+                    if crypto::signature_path(&file).try_exists()? {
+                        eprintln!(
+                            "Warning: {} changed, so its signature is no longer valid. Please sign \
+                            it again with `sira sign`.",
+                            file.display(),
+                        );
+                    }
+                }
+            }
+            seen.push(file);
+        }
+    }
+
+    if unformatted > 0 {
+        bail!("{unformatted} file(s) would be reformatted");
+    }
+    Ok(())
+}
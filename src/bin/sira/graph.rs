@@ -0,0 +1,18 @@
+//! The `sira graph` subcommand.
+
+use anyhow::bail;
+use sira::core::Plan;
+use sira::run_plan::list;
+
+/// `sira graph <manifest-file>...`
+///
+/// Prints a Graphviz graph of the manifests, their tasks, and their hosts. Please see
+/// [list::graph].
+pub fn graph(args: &[String]) -> anyhow::Result<()> {
+    if args.is_empty() {
+        bail!("Usage: sira graph <manifest-file>...");
+    }
+    let plan = Plan::from_manifest_files(args)?;
+    print!("{}", list::graph(&plan));
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! The `sira history` subcommand.
+
+use anyhow::{bail, Context};
+use sira::run_plan::history::{self, Filter};
+use std::path::PathBuf;
+
+/// How many runs or actions `sira history` lists unless `--limit` says otherwise.
+const DEFAULT_LIMIT: usize = 20;
+
+/// `sira history [--profile <name>] [--history <file>] [--run <id>] [--host <host>] [--task <name>]
+/// [--limit <n>]`
+///
+/// Lists the most recent runs in the history database, newest first. With `--run`, `--host`, or
+/// `--task`, lists the most recent actions that match all of them instead. The database comes
+/// from `--history`, or else from the `history` setting in sira.toml.
+pub fn history(args: &[String]) -> anyhow::Result<()> {
+    const USAGE: &str = "Usage: sira history [--profile <name>] [--history <file>] [--run <id>] \
+        [--host <host>] [--task <name>] [--limit <n>]";
+
+    let (config, args) = crate::load_config(args)?;
+    let mut path = config.history;
+    let mut filter = Filter::default();
+    let mut limit = DEFAULT_LIMIT;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--history" => match args.next() {
+                Some(value) => path = Some(PathBuf::from(value)),
+                None => bail!("{arg} requires a path to a history database"),
+            },
+            "--run" => match args.next() {
+                Some(value) => {
+                    filter.run = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("{arg} requires a run ID"))?,
+                    );
+                }
+                None => bail!("{arg} requires a run ID"),
+            },
+            "--host" => match args.next() {
+                Some(value) => filter.host = Some(value.clone()),
+                None => bail!("{arg} requires a host name"),
+            },
+            "--task" => match args.next() {
+                Some(value) => filter.task = Some(value.clone()),
+                None => bail!("{arg} requires a task name"),
+            },
+            "--limit" => match args.next() {
+                Some(value) => {
+                    limit = value
+                        .parse()
+                        .with_context(|| format!("{arg} requires a number"))?;
+                }
+                None => bail!("{arg} requires a number"),
+            },
+            _ => bail!(USAGE),
+        }
+    }
+    let Some(path) = path else {
+        bail!("no history database; pass --history <file> or set history in sira.toml");
+    };
+
+    if filter == Filter::default() {
+        print!("{}", history::format_runs(&history::runs(&path, limit)?));
+    } else {
+        print!(
+            "{}",
+            history::format_actions(&history::actions(&path, &filter, limit)?)
+        );
+    }
+    Ok(())
+}
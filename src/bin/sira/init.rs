@@ -0,0 +1,63 @@
+//! The `sira init` subcommand.
+
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The files that `sira init` creates, as (path, contents) pairs. Paths are relative to the
+/// project directory.
+const FILES: &[(&str, &str)] = &[
+    (
+        "README.md",
+        include_str!("../../../resources/init/README.md"),
+    ),
+    (
+        "manifests/example.yaml",
+        include_str!("../../../resources/init/manifests/example.yaml"),
+    ),
+    (
+        "tasks/example.yaml",
+        include_str!("../../../resources/init/tasks/example.yaml"),
+    ),
+    (
+        "files/motd",
+        include_str!("../../../resources/init/files/motd"),
+    ),
+    (
+        "sira.example.toml",
+        include_str!("../../../resources/init/sira.example.toml"),
+    ),
+];
+
+/// `sira init [<directory>]`
+///
+/// Creates a starter project in `directory` (by default, the current directory): a manifest, a
+/// task, a file to upload, and example settings, all with comments. Creates `directory` if it does
+/// not exist, but refuses to overwrite any existing file.
+pub fn init(args: &[String]) -> anyhow::Result<()> {
+    let directory = match args {
+        [] => Path::new("."),
+        [directory] => Path::new(directory),
+        _ => bail!("Usage: sira init [<directory>]"),
+    };
+
+    let paths: Vec<PathBuf> = FILES.iter().map(|(path, _)| directory.join(path)).collect();
+    for path in &paths {
+        if path.try_exists()? {
+            bail!(
+                "{} already exists; not overwriting anything",
+                path.display()
+            );
+        }
+    }
+
+    for (path, (_, contents)) in paths.iter().zip(FILES) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("could not create {}", parent.display()))?;
+        }
+        fs::write(path, contents).with_context(|| format!("could not write {}", path.display()))?;
+        println!("Created {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,493 @@
+use anyhow::{anyhow, bail};
+use sira::config::Config;
+use sira::core::Plan;
+use sira::crypto;
+use sira::run_plan::client::ConnectionError;
+use sira::run_plan::drift::DriftReport;
+use sira::run_plan::email::EmailConfig;
+use sira::run_plan::{inventory, json_log, list, report};
+use sira::run_plan::{run_plan_with, RunOptions, TaskError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+mod adhoc;
+mod agent;
+mod audit;
+mod daemon;
+mod deploy_client;
+mod facts;
+mod fmt;
+mod graph;
+mod history;
+mod init;
+mod ping;
+mod queue;
+mod rotate_keys;
+mod sign;
+
+/// The exit status of `sira`, which tells scripts and CI how a run went without parsing its output.
+///
+/// If several happen, [Outcome::InternalError] takes precedence over [Outcome::ActionsFailed],
+/// which takes precedence over [Outcome::Unreachable].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Everything succeeded on every host.
+    Success = 0,
+
+    /// Sira itself failed, e.g. because it couldn't write a log file, or its task for a host
+    /// panicked.
+    InternalError = 1,
+
+    /// The command line, `sira.toml`, or a manifest or task file was invalid, so nothing ran.
+    InvalidInput = 2,
+
+    /// An action failed on at least one host.
+    ActionsFailed = 3,
+
+    /// No action failed, but Sira couldn't reach at least one host.
+    Unreachable = 4,
+
+    /// In check mode, every host ran its checks, but at least one doesn't meet its manifests.
+    Drifted = 5,
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome as u8)
+    }
+}
+
+/// Wraps an error to mark it as [Outcome::InvalidInput]. Displays just like the wrapped error.
+#[derive(Debug)]
+struct InvalidInput(anyhow::Error);
+
+impl Display for InvalidInput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for InvalidInput {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Marks `error` as [Outcome::InvalidInput], e.g. with `map_err`.
+fn invalid_input(error: anyhow::Error) -> anyhow::Error {
+    anyhow::Error::new(InvalidInput(error))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Subcommands take precedence over manifest files. To run a manifest file whose name collides
+    // with a subcommand, write it as a path, e.g. `./sign`.
+    let success = |result: anyhow::Result<()>| result.map(|()| Outcome::Success);
+    let result = match args.first().map(String::as_str) {
+        Some("sign") => success(sign::sign(&args[1..])),
+        Some("verify") => success(sign::verify(&args[1..])),
+        Some("audit-verify") => success(audit::verify(&args[1..])),
+        Some("adhoc") => adhoc::adhoc(&args[1..]).await,
+        Some("agent-add") => success(agent::add(&args[1..])),
+        Some("daemon") => success(daemon::daemon(&args[1..]).await),
+        Some("deploy-client") => success(deploy_client::deploy_client(&args[1..]).await),
+        Some("facts") => facts::facts(&args[1..]).await,
+        Some("fmt") => success(fmt::fmt(&args[1..])),
+        Some("graph") => success(graph::graph(&args[1..])),
+        Some("history") => success(history::history(&args[1..])),
+        Some("init") => success(init::init(&args[1..])),
+        Some("ping") => ping::ping(&args[1..]).await,
+        Some("queue") => success(queue::queue(&args[1..]).await),
+        Some("rotate-keys") => success(rotate_keys::rotate_keys(&args[1..]).await),
+        _ => run(&args).await,
+    };
+
+    match result {
+        Ok(outcome) => outcome.into(),
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            match error.downcast_ref::<InvalidInput>() {
+                Some(_) => Outcome::InvalidInput.into(),
+                None => Outcome::InternalError.into(),
+            }
+        }
+    }
+}
+
+/// Loads the defaults in sira.toml, using the profile named by `--profile <name>` in `args`, if
+/// any. Returns the defaults and the rest of `args`.
+fn load_config(args: &[String]) -> anyhow::Result<(Config, Vec<String>)> {
+    let mut profile = None;
+    let mut rest = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => match args.next() {
+                Some(name) => profile = Some(name.as_str()),
+                None => bail!("{arg} requires the name of a profile in sira.toml"),
+            },
+            _ => rest.push(arg.clone()),
+        }
+    }
+    Ok((Config::load()?.select(profile)?, rest))
+}
+
+/// Runs a [Plan] built from a list of manifest files and reports the outcome.
+///
+/// `sira [--profile <name>] [--list-hosts|--list-actions <host>|--syntax-check|--check] [--artifacts <dir>] [--audit-log <file>] [--color auto|always|never] [--dashboard] [--history <file>] [--html-report <file>] [--json-log <file>] [--known-hosts <file>]
+/// [--metrics <file>] [--on-failure continue|abort] [--output text|json] [--progress] [--refresh-facts] [--syslog] [--webhook [slack:|matrix:]<url>]... [--quiet|-v|-vv|-vvv]
+/// <manifest-file>...`
+///
+/// With `--json-log -` or `--output json`, JSON replaces the usual output on stdout. Each `v` raises the
+/// [report::Verbosity] by one level; repeated flags add up, so `-v -v` is the same as `-vv`.
+/// `--quiet` (or `-q`) prints only failures and the summary at the end of the run, e.g. for cron.
+/// `--list-hosts` prints the hosts that the manifest files name instead of running anything; with
+/// `-v`, it lists them by manifest. Hosts that a manifest selects by fact aren't known until the run
+/// gathers facts, so they aren't listed. `--refresh-facts` gathers those facts again even if the
+/// cached ones are fresh. `--list-actions` prints the actions that would run on a host,
+/// in order, under their manifests and tasks; with `-v`, it shows them with variables substituted.
+/// `--syntax-check` loads and checks the manifest files, reporting every problem it finds, and exits
+/// with an error if there are any. `--on-failure abort` stops every host as soon as one fails.
+/// `--check` changes nothing: it checks each host against the manifest files, reports which
+/// declared states it doesn't meet, and exits with [Outcome::Drifted] if any host has drifted.
+/// `--dashboard` replaces the usual output with a full-screen dashboard from which to follow the
+/// run, ignore or retry hosts, and abort it; please see [sira::run_plan::dashboard].
+async fn run(args: &[String]) -> anyhow::Result<Outcome> {
+    match prepare_run(args).map_err(invalid_input)? {
+        Some((plan, options)) => run_and_report(plan, options).await,
+        None => Ok(Outcome::Success),
+    }
+}
+
+/// Implements the part of [run] that parses `args` and loads the [Plan]. Returns [None] if there's
+/// nothing to run, e.g. because `args` asked for a list of hosts instead.
+fn prepare_run(args: &[String]) -> anyhow::Result<Option<(Plan, RunOptions)>> {
+    // Flags override the defaults in sira.toml.
+    let (config, args) = load_config(args)?;
+    let mut options = RunOptions {
+        email: EmailConfig::load()?,
+        ..RunOptions::from_config(config)
+    };
+    let mut manifest_files = vec![];
+    let mut verbosity = 0;
+    let mut quiet = false;
+    let mut list_hosts = false;
+    let mut list_actions = None;
+    let mut syntax_check = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--artifacts" => match args.next() {
+                Some(path) => options.artifacts = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a directory"),
+            },
+            "--audit-log" => match args.next() {
+                Some(path) => options.audit_log = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a log file"),
+            },
+            "--check" => options.check = Some(DriftReport::new()),
+            "--color" => match args.next() {
+                Some(choice) => options.color = choice.parse()?,
+                None => bail!("{arg} requires a choice: auto, always, or never"),
+            },
+            "--dashboard" => options.dashboard = true,
+            "--history" => match args.next() {
+                Some(path) => options.history = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a history database"),
+            },
+            "--html-report" => match args.next() {
+                Some(path) => options.html_report = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to an HTML file"),
+            },
+            "--json-log" => match args.next() {
+                Some(path) => options.json_log = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a log file, or - for stdout"),
+            },
+            "--known-hosts" => match args.next() {
+                Some(path) => options.known_hosts = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a known hosts file"),
+            },
+            "--list-actions" => match args.next() {
+                Some(host) => list_actions = Some(host),
+                None => bail!("{arg} requires a host name"),
+            },
+            "--list-hosts" => list_hosts = true,
+            "--metrics" => match args.next() {
+                Some(path) => options.metrics = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a metrics file"),
+            },
+            "--on-failure" => match args.next() {
+                Some(policy) => options.on_failure = policy.parse()?,
+                None => bail!("{arg} requires a policy: continue or abort"),
+            },
+            "--output" => match args.next() {
+                Some(format) => options.output = format.parse()?,
+                None => bail!("{arg} requires a format: text or json"),
+            },
+            "--progress" => options.progress = true,
+            "--refresh-facts" => options.refresh_facts = true,
+            "--quiet" | "-q" => quiet = true,
+            "--syntax-check" => syntax_check = true,
+            "--syslog" => options.syslog = true,
+            "--webhook" => match args.next() {
+                Some(webhook) => options.webhooks.push(webhook.parse()?),
+                None => bail!("{arg} requires a webhook URL"),
+            },
+            flag if is_verbose_flag(flag) => verbosity += flag.len() - 1,
+            _ => manifest_files.push(arg),
+        }
+    }
+    options.verbosity = match (quiet, verbosity, options.progress) {
+        (false, _, _) => report::Verbosity::from_count(verbosity),
+        (true, 0, false) => report::Verbosity::Quiet,
+        (true, _, true) => bail!("--quiet cannot be combined with --progress"),
+        (true, _, false) => bail!("--quiet cannot be combined with -v"),
+    };
+    if options.dashboard && options.progress {
+        bail!("--dashboard cannot be combined with --progress");
+    }
+    if options.dashboard && quiet {
+        bail!("--quiet cannot be combined with --dashboard");
+    }
+
+    if syntax_check {
+        check_syntax(&manifest_files)?;
+        return Ok(None);
+    }
+    let mut plan = Plan::from_manifest_files(&manifest_files)?;
+    inventory::apply(&mut plan, &mut options)?;
+    if list_hosts {
+        print!("{}", list::hosts(&plan, verbosity > 0));
+        return Ok(None);
+    }
+    if let Some(host) = list_actions {
+        print!("{}", list::actions(&plan, host, verbosity > 0)?);
+        return Ok(None);
+    }
+    Ok(Some((plan, options)))
+}
+
+/// Runs `plan` and reports the outcome, e.g. for `sira` and `sira adhoc`.
+async fn run_and_report(plan: Plan, options: RunOptions) -> anyhow::Result<Outcome> {
+    let json_log_on_stdout = options.json_log.as_deref() == Some(Path::new(json_log::STDOUT));
+    let json_on_stdout = json_log_on_stdout || options.output == report::OutputFormat::Json;
+    if json_log_on_stdout && options.output == report::OutputFormat::Json {
+        return Err(invalid_input(anyhow!(
+            "--output json cannot be combined with --json-log -"
+        )));
+    }
+    if options.dashboard && json_on_stdout {
+        return Err(invalid_input(anyhow!(
+            "--dashboard cannot be combined with JSON on stdout"
+        )));
+    }
+    if options.dashboard && !io::stdout().is_terminal() {
+        return Err(invalid_input(anyhow!("--dashboard requires a terminal")));
+    }
+
+    let drift = options.check.clone();
+    let unsorted_errors = match run_plan_with(plan, options).await {
+        Err(errors) => errors,
+        Ok(()) => vec![],
+    };
+    if let Some(drift) = &drift {
+        // Keep stdout clean for JSON records, if that's where they're going.
+        let mut output: Box<dyn Write> = match json_on_stdout {
+            true => Box::new(io::stderr().lock()),
+            false => Box::new(io::stdout().lock()),
+        };
+        writeln!(
+            &mut output,
+            "\n\
+            =============\n\
+            Drift report:\n\
+            =============\n",
+        )?;
+        drift.write(&mut output)?;
+    }
+    if unsorted_errors.is_empty() {
+        return Ok(match drift {
+            Some(drift) if !drift.is_compliant() => Outcome::Drifted,
+            _ => Outcome::Success,
+        });
+    }
+
+    // Error values that resulted from connections problems; these exit with
+    // [Outcome::Unreachable] unless there are other errors, too.
+    //
+    // Stored as a BTreeMap (host -> error) for alphabetical sorting by host.
+    let mut connection_errors: BTreeMap<String, anyhow::Error> = BTreeMap::new();
+
+    // Any other error values; these exit with [Outcome::ActionsFailed], or with
+    // [Outcome::InternalError] if any of them is a bug in Sira.
+    //
+    // Stored as a BTreeMap (host -> error) for alphabetical sorting by host.
+    let mut other_errors: BTreeMap<String, anyhow::Error> = BTreeMap::new();
+    let mut internal_error = false;
+
+    for (host, error) in unsorted_errors {
+        match host_outcome(&error) {
+            Outcome::Unreachable => {
+                safe_insert_connection_error(&mut connection_errors, &host, error)?;
+            }
+            outcome => {
+                internal_error |= outcome == Outcome::InternalError;
+                safe_insert_other_error(&mut other_errors, &host, error)?;
+            }
+        }
+    }
+
+    // Print final reports.
+    let mut outcome = Outcome::Success;
+    if !connection_errors.is_empty() {
+        outcome = Outcome::Unreachable;
+        // Keep stdout clean for JSON records, if that's where they're going.
+        let mut output: Box<dyn Write> = match json_on_stdout {
+            true => Box::new(io::stderr().lock()),
+            false => Box::new(io::stdout().lock()),
+        };
+        writeln!(
+            &mut output,
+            "\n\
+            ==================\n\
+            Connection issues:\n\
+            ==================\n\
+            \n\
+            The following hosts encountered connection issues and could not complete their runs:\n",
+        )?;
+        // Problems with ssh-agent usually affect every host alike, so explain each only once.
+        let mut hints = BTreeSet::new();
+        for (host, error) in connection_errors {
+            let error = format!("{error:#}");
+            hints.extend(crypto::agent::hint(&error));
+            report::print_host_message(&mut output, host, error)?;
+        }
+        for hint in hints {
+            writeln!(&mut output, "\nHint: {hint}")?;
+        }
+    }
+    if !other_errors.is_empty() {
+        let mut stderr_lock = io::stderr().lock();
+        writeln!(
+            &mut stderr_lock,
+            "\n\
+            =======\n\
+            Errors:\n\
+            =======\n\
+            \n\
+            The following hosts encountered errors and had to abort their their runs:\n",
+        )?;
+        for (host, error) in other_errors {
+            report::print_host_message(&mut stderr_lock, host, error)?;
+        }
+        writeln!(
+            &mut stderr_lock,
+            "\nExiting with error due to the errors listed above."
+        )?;
+        outcome = match internal_error {
+            true => Outcome::InternalError,
+            false => Outcome::ActionsFailed,
+        };
+    }
+    Ok(outcome)
+}
+
+/// Returns how a host's `error` would have `sira` exit if it were the only one: with
+/// [Outcome::Unreachable] if Sira couldn't connect to the host, with [Outcome::InternalError] if
+/// Sira's own task for the host failed, e.g. because it panicked, or else with
+/// [Outcome::ActionsFailed].
+fn host_outcome(error: &anyhow::Error) -> Outcome {
+    if error.downcast_ref::<TaskError>().is_some() {
+        Outcome::InternalError
+    } else if error.downcast_ref::<ConnectionError>().is_some() {
+        Outcome::Unreachable
+    } else {
+        Outcome::ActionsFailed
+    }
+}
+
+/// Loads each manifest file on its own and checks it with [Plan::check], printing every problem to
+/// stderr. Returns an error if there were any problems.
+fn check_syntax(manifest_files: &[&String]) -> anyhow::Result<()> {
+    if manifest_files.is_empty() {
+        bail!("--syntax-check requires at least one manifest file");
+    }
+
+    let mut problems = 0;
+    for file in manifest_files {
+        let file_problems = match Plan::from_manifest_files(&[file]) {
+            Ok(plan) => plan.check(),
+            Err(error) => vec![error],
+        };
+        for problem in &file_problems {
+            eprintln!("{file}: {problem:#}");
+        }
+        problems += file_problems.len();
+    }
+    match problems {
+        0 => {
+            println!("Syntax OK: {} manifest file(s)", manifest_files.len());
+            Ok(())
+        }
+        1 => bail!("Found 1 problem."),
+        count => bail!("Found {count} problems."),
+    }
+}
+
+/// Inserts a value into `connection_errors` in `main`.
+fn safe_insert_connection_error<H: Display>(
+    map: &mut BTreeMap<String, anyhow::Error>,
+    host: &H,
+    error: anyhow::Error,
+) -> std::io::Result<()> {
+    _safe_insert(map, "connection_errors", host, error)
+}
+
+/// Inserts a value into `other_errors` in `main`.
+fn safe_insert_other_error<H: Display>(
+    map: &mut BTreeMap<String, anyhow::Error>,
+    host: &H,
+    error: anyhow::Error,
+) -> std::io::Result<()> {
+    _safe_insert(map, "other_errors", host, error)
+}
+
+/// Inserts a (host, error) pair into a BTreeMap, reporting a bug if a host appears twice.
+///
+/// For internal use only.
+fn _safe_insert<E: Display, H: Display>(
+    map: &mut BTreeMap<String, E>,
+    map_name: &'static str,
+    host: &H,
+    error: E,
+) -> std::io::Result<()> {
+    let evicted = map.insert(host.to_string(), error);
+    if let Some(evicted) = evicted {
+        let mut stderr_lock = io::stderr().lock();
+        writeln!(
+            &mut stderr_lock,
+            "BUG: there should be at most one error per host, but found multiple! \
+            See below for details.\n\
+            Evicted an error from {map_name}:"
+        )?;
+        report::print_host_message(&mut stderr_lock, host, evicted)?;
+        writeln!(&mut stderr_lock, "Please report this bug!")?;
+    }
+    Ok(())
+}
+
+/// Returns whether `arg` is `-v`, `-vv`, `-vvv`, etc.
+fn is_verbose_flag(arg: &str) -> bool {
+    arg.len() > 1 && arg.starts_with('-') && arg[1..].chars().all(|c| c == 'v')
+}
+
+#[cfg(test)]
+mod test;
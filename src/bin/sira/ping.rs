@@ -0,0 +1,36 @@
+//! The `sira ping` subcommand.
+
+use crate::Outcome;
+use anyhow::bail;
+use sira::core::Plan;
+use sira::run_plan::{inventory, ping, RunOptions};
+
+/// `sira ping [--profile <name>] <manifest-file>...`
+///
+/// Connects to every host named in the manifest files, runs `sira-client ping` there, and prints a
+/// table of the results. Like `sira deploy-client`, it uses the manifest files only to build the
+/// list of hosts. Exits with [Outcome::Unreachable] if any host doesn't answer.
+pub async fn ping(args: &[String]) -> anyhow::Result<Outcome> {
+    let (hosts, options) = prepare(args).map_err(crate::invalid_input)?;
+    let replies = ping::ping(&hosts, &options).await;
+    print!("{}", ping::table(&replies));
+
+    let silent = replies.iter().filter(|(_, reply)| !reply.is_pong()).count();
+    if silent > 0 {
+        eprintln!("\n{silent} of {} hosts did not answer.", hosts.len());
+        return Ok(Outcome::Unreachable);
+    }
+    Ok(Outcome::Success)
+}
+
+/// Implements the part of [ping] that parses `args` and loads the hosts.
+fn prepare(args: &[String]) -> anyhow::Result<(Vec<String>, RunOptions)> {
+    let (config, args) = crate::load_config(args)?;
+    if args.is_empty() {
+        bail!("Usage: sira ping [--profile <name>] <manifest-file>...");
+    }
+    let mut options = RunOptions::from_config(config);
+    let mut plan = Plan::from_manifest_files(&args)?;
+    inventory::apply(&mut plan, &mut options)?;
+    Ok((plan.hosts(), options))
+}
@@ -0,0 +1,56 @@
+//! The `sira queue` subcommand.
+
+use anyhow::{bail, Context};
+use chrono::TimeDelta;
+use sira::core::Plan;
+use sira::run_plan::queue::{self, DEFAULT_LIFETIME_DAYS};
+use sira::run_plan::RunOptions;
+
+/// `sira queue [--profile <name>] [--expires-in <days>] <manifest-file>...`
+///
+/// Queues the actions in the manifest files on each of their hosts instead of running them, so
+/// that each managed node applies them later with `sira-client apply-queued`. Queued actions
+/// expire after [DEFAULT_LIFETIME_DAYS] days unless `--expires-in` says otherwise.
+pub async fn queue(args: &[String]) -> anyhow::Result<()> {
+    const USAGE: &str =
+        "Usage: sira queue [--profile <name>] [--expires-in <days>] <manifest-file>...";
+
+    let (config, args) = crate::load_config(args)?;
+    let mut days = DEFAULT_LIFETIME_DAYS;
+    let mut manifest_files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--expires-in" => match args.next() {
+                Some(value) => {
+                    days = value
+                        .parse()
+                        .with_context(|| format!("{arg} requires a number of days"))?;
+                }
+                None => bail!("{arg} requires a number of days"),
+            },
+            _ => manifest_files.push(arg),
+        }
+    }
+    if manifest_files.is_empty() {
+        bail!(USAGE);
+    }
+    let lifetime = match TimeDelta::try_days(days) {
+        Some(lifetime) if days > 0 => lifetime,
+        _ => bail!("--expires-in requires a positive number of days"),
+    };
+
+    let plan = Plan::from_manifest_files(&manifest_files)?;
+    let hosts = plan.hosts();
+    let options = RunOptions::from_config(config);
+    let Err(errors) = queue::queue(&plan, lifetime, options).await else {
+        println!("\nQueued actions on {} hosts.", hosts.len());
+        return Ok(());
+    };
+
+    let mut message = String::from("could not queue actions on these hosts:\n");
+    for (host, error) in errors {
+        message.push_str(&format!("[{host}] {error:?}\n"));
+    }
+    bail!(message)
+}
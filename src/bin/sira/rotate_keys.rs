@@ -0,0 +1,21 @@
+//! The `sira rotate-keys` subcommand.
+
+use anyhow::bail;
+use sira::core::Plan;
+use sira::run_plan::rotate_keys;
+
+/// `sira rotate-keys <sira-user> <manifest-file>...`
+///
+/// Rotates the login and action keys on every host named in the manifest files. The manifest
+/// files are only used to build the list of hosts; their actions are not run.
+pub async fn rotate_keys(args: &[String]) -> anyhow::Result<()> {
+    let (sira_user, manifest_files) = match args {
+        [sira_user, manifest_files @ ..] if !manifest_files.is_empty() => {
+            (sira_user, manifest_files)
+        }
+        _ => bail!("Usage: sira rotate-keys <sira-user> <manifest-file>..."),
+    };
+
+    let hosts = Plan::from_manifest_files(manifest_files)?.hosts();
+    rotate_keys::rotate_keys(sira_user, &hosts).await
+}
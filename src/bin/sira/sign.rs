@@ -0,0 +1,91 @@
+//! The `sira sign` and `sira verify` subcommands.
+//!
+//! Both subcommands operate on whole manifest trees: each manifest file given on the command line
+//! plus every task file it includes.
+
+use anyhow::{bail, Context};
+use sira::core::manifest;
+use sira::crypto;
+use std::path::PathBuf;
+
+/// The default manifest private key, relative to the user's home directory.
+///
+/// This matches the location where `sira-install` generates the manifest key.
+const DEFAULT_MANIFEST_KEY: &str = ".ssh/manifest";
+
+/// `sira sign [--key <private-key>] <manifest-file>...`
+///
+/// Signs each manifest file and every task file it includes, writing detached signatures
+/// alongside them.
+pub fn sign(args: &[String]) -> anyhow::Result<()> {
+    let mut key = None;
+    let mut manifest_files = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key" | "-f" => match args.next() {
+                Some(path) => key = Some(PathBuf::from(path)),
+                None => bail!("{arg} requires a path to a private key"),
+            },
+            _ => manifest_files.push(arg),
+        }
+    }
+
+    if manifest_files.is_empty() {
+        bail!("Usage: sira sign [--key <private-key>] <manifest-file>...");
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => home::home_dir()
+            .context("could not retrieve user's home directory")?
+            .join(DEFAULT_MANIFEST_KEY),
+    };
+
+    for file in files_in_trees(&manifest_files)? {
+        let signature = crypto::sign_file(&file, &key)
+            .with_context(|| format!("failed to sign {}", file.display()))?;
+        println!("Signed {} -> {}", file.display(), signature.display());
+    }
+    Ok(())
+}
+
+/// `sira verify <manifest-file>...`
+///
+/// Verifies the signatures on each manifest file and every task file it includes. Reports every
+/// failure rather than stopping at the first one.
+pub fn verify(args: &[String]) -> anyhow::Result<()> {
+    if args.is_empty() {
+        bail!("Usage: sira verify <manifest-file>...");
+    }
+
+    let mut failures = 0;
+    for file in files_in_trees(args)? {
+        match manifest::verify_signature(&file) {
+            Ok(()) => println!("Verified {}", file.display()),
+            Err(err) => {
+                eprintln!("Failed   {}\n{err}", file.display());
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{failures} file(s) failed verification");
+    }
+    Ok(())
+}
+
+/// Returns every file in the manifest trees rooted at `manifest_files`, without duplicates.
+fn files_in_trees(manifest_files: &[impl AsRef<std::path::Path>]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for manifest_file in manifest_files {
+        for file in manifest::manifest_tree(manifest_file)? {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
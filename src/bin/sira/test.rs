@@ -0,0 +1,38 @@
+use super::*;
+
+mod host_outcome {
+    use super::*;
+
+    #[test]
+    fn task_errors_are_internal_errors() {
+        let errors = [
+            TaskError::Panicked(Some("oops".to_string())),
+            TaskError::Panicked(None),
+            TaskError::EmbedderPanicked(None),
+            TaskError::Cancelled,
+        ];
+        for error in errors {
+            let error = anyhow::Error::new(error).context("while running");
+            assert_eq!(Outcome::InternalError, host_outcome(&error));
+        }
+    }
+
+    #[test]
+    fn connection_errors_are_unreachable() {
+        // E.g. a container that isn't running, or SSH that couldn't connect.
+        let errors = [
+            anyhow!("could not run commands in container web-test"),
+            anyhow::Error::new(openssh::Error::Disconnected),
+        ];
+        for error in errors {
+            let error = anyhow::Error::new(ConnectionError(error)).context("Wake-on-LAN failed");
+            assert_eq!(Outcome::Unreachable, host_outcome(&error));
+        }
+    }
+
+    #[test]
+    fn other_errors_are_failed_actions() {
+        let error = anyhow!("Action exited with exit code 1");
+        assert_eq!(Outcome::ActionsFailed, host_outcome(&error));
+    }
+}
@@ -3,14 +3,36 @@
 //! This module is used writing client logic and has no interaction with SSH. The SSH connection to
 //! the managed node runs through [mod@crate::run_plan].
 
+use crate::core::action::{ClientError, ErrorCode};
 use anyhow::{bail, Context};
 use shlex::Quoter;
 use std::ffi::{OsStr, OsString};
 use std::fs::{File, OpenOptions};
 use std::os::unix::ffi::OsStringExt;
-use std::process::{Command, Output};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output};
 use std::sync::OnceLock;
 
+pub mod acl;
+pub mod dpkg_lock;
+pub mod facts;
+pub mod install;
+pub mod platform;
+pub mod plugin;
+pub mod policy;
+pub mod query;
+pub mod queue;
+pub mod selinux;
+pub mod xattr;
+
+/// The argument that tells `sira-client` to print its version and exit without changing anything,
+/// e.g. for `sira ping`.
+pub const PING_COMMAND: &str = "ping";
+
+/// The flag that runs an action in check mode, which reports what the action would change without
+/// changing anything. It goes before the action, e.g. `sira-client --check <action-as-yaml>`.
+pub const CHECK_FLAG: &str = "--check";
+
 /// Invokes the `mktemp` system utility.
 ///
 /// `mktemp` might write a newline after the returned path, so this function trims trailing white
@@ -31,7 +53,25 @@ use std::sync::OnceLock;
 /// Returns an error if `mktemp` cannot be run for any reason, exits with an error, or returns a
 /// path that cannot be parsed as UTF-8.
 pub fn mktemp() -> anyhow::Result<(File, String)> {
-    let output = Command::new("mktemp").output()?;
+    open_mktemp(Command::new("mktemp"))
+}
+
+/// Like [mktemp], but creates the file in `dir` rather than the system's temporary directory, with
+/// a name that starts with `.sira-`, e.g. so that moving it elsewhere in `dir` is a rename.
+///
+/// # Errors
+///
+/// Returns an error for the same reasons as [mktemp], e.g. if `dir` doesn't exist.
+pub fn mktemp_in(dir: impl AsRef<Path>) -> anyhow::Result<(File, String)> {
+    let mut command = Command::new("mktemp");
+    command.arg(dir.as_ref().join(".sira-XXXXXXXXXX"));
+    open_mktemp(command)
+}
+
+/// Does the work of [mktemp] and [mktemp_in], running `command` and opening the file that it
+/// created.
+fn open_mktemp(mut command: Command) -> anyhow::Result<(File, String)> {
+    let output = command.output()?;
     if !output.status.success() {
         bail!(
             "mktemp exited with error:\n{:?}",
@@ -89,47 +129,59 @@ pub fn mktemp() -> anyhow::Result<(File, String)> {
 /// assert!(client::run("cargo", &["doc", "--document-private-items"]).is_ok());
 /// ```
 pub fn run<C: AsRef<OsStr>, A: AsRef<OsStr>>(cmd: C, args: &[A]) -> anyhow::Result<()> {
-    // If needed for error output, join cmd and args with spaces to construct a user-friendly
-    // representation of the command.
-    //
-    // There are at least three likely ways this information might be used:
-    // 1. In separate fields as part of this Rust function and calling code
-    // 2. In a YAML file
-    // 3. In the user's shell
-    //
-    // These all present the information a bit differently, so there is no canonical representation
-    // to apply. We just want to make a best effort to indicate to the user what went wrong.
-    let command = || {
-        // Build a Vec of the command and its arguments as Strings.
-        let mut components = Vec::with_capacity(args.len() + 1);
-        components.push(cmd.as_ref().to_string_lossy().to_string());
-        components.extend(
-            args.iter()
-                .map(|a| a.as_ref().to_string_lossy().to_string()),
-        );
-
-        // Try to use shlex to properly quote the string. If that fails, naively join with spaces.
-        match Quoter::new().join(components.iter().map(|s| &s[..])) {
-            Ok(s) => s,
-            Err(_) => components.join(" "),
-        }
-    };
-
     let status = Command::new(&cmd)
         .args(args)
         .status()
-        .with_context(|| format!("failed to start command: {}", command()))?;
+        .with_context(|| format!("failed to start command: {}", command_line(&cmd, args)))?;
+    check_status(status, &cmd, args)
+}
 
+/// Returns an error describing the command `cmd` with `args` if `status` isn't successful.
+pub(crate) fn check_status<C: AsRef<OsStr>, A: AsRef<OsStr>>(
+    status: ExitStatus,
+    cmd: C,
+    args: &[A],
+) -> anyhow::Result<()> {
     if !status.success() {
         let error = match status.code() {
             Some(i) => format!("exit code {i}"),
             None => "error".to_string(),
         };
-        bail!("command exited with {error}: {}", command());
+        return Err(ClientError::new(
+            ErrorCode::CommandFailed,
+            format!("command exited with {error}: {}", command_line(cmd, args)),
+        )
+        .into());
     }
     Ok(())
 }
 
+/// Joins `cmd` and `args` with spaces to construct a user-friendly representation of the command
+/// for error output.
+pub(crate) fn command_line<C: AsRef<OsStr>, A: AsRef<OsStr>>(cmd: C, args: &[A]) -> String {
+    // There are at least three likely ways this information might be used:
+    // 1. In separate fields as part of this Rust function and calling code
+    // 2. In a YAML file
+    // 3. In the user's shell
+    //
+    // These all present the information a bit differently, so there is no canonical representation
+    // to apply. We just want to make a best effort to indicate to the user what went wrong.
+
+    // Build a Vec of the command and its arguments as Strings.
+    let mut components = Vec::with_capacity(args.len() + 1);
+    components.push(cmd.as_ref().to_string_lossy().to_string());
+    components.extend(
+        args.iter()
+            .map(|a| a.as_ref().to_string_lossy().to_string()),
+    );
+
+    // Try to use shlex to properly quote the string. If that fails, naively join with spaces.
+    match Quoter::new().join(components.iter().map(|s| &s[..])) {
+        Ok(s) => s,
+        Err(_) => components.join(" "),
+    }
+}
+
 /// Invokes the `whoami` system utility.
 ///
 /// Memorizes the identity on first run and returns the cached result on all subsequent calls.
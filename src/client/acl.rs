@@ -0,0 +1,45 @@
+//! Sets POSIX ACL entries on files that `sira-client` installs.
+
+use super::run;
+use anyhow::bail;
+use std::process::Command;
+
+/// Adds `entries` to the ACL of the file at `path` with `setfacl`, e.g. `u:alice:r`.
+///
+/// # Errors
+///
+/// Returns an error if an entry is blank or contains a comma, or if `setfacl` fails.
+pub fn set(path: &str, entries: &[String]) -> anyhow::Result<()> {
+    run("setfacl", &setfacl_args(path, entries)?)
+}
+
+/// Returns the ACL of the file at `path` as listed by `getfacl`, without the header naming the
+/// file, or `None` if it can't be read.
+pub fn get(path: &str) -> Option<String> {
+    let output = Command::new("getfacl")
+        .args(["--omit-header", "--absolute-names", "--", path])
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => None,
+    }
+}
+
+/// Returns the arguments for `setfacl` that add `entries` to the ACL of the file at `path`.
+pub(crate) fn setfacl_args(path: &str, entries: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut args = Vec::with_capacity(entries.len() + 2);
+    for entry in entries {
+        // setfacl separates entries with commas, so one entry must not hold several.
+        if entry.trim().is_empty() || entry.contains(',') {
+            bail!("invalid ACL entry {entry:?}; please list each entry separately");
+        }
+        args.push(format!("--modify={entry}"));
+    }
+    args.push("--".to_string());
+    args.push(path.to_string());
+    Ok(args)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,30 @@
+use super::*;
+
+mod setfacl_args {
+    use super::*;
+
+    #[test]
+    fn modifies_each_entry() {
+        let entries = vec!["u:alice:r".to_string(), "g:web:rw".to_string()];
+        assert_eq!(
+            vec![
+                "--modify=u:alice:r",
+                "--modify=g:web:rw",
+                "--",
+                "/etc/app.conf"
+            ],
+            setfacl_args("/etc/app.conf", &entries).unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_combined_entries() {
+        let entries = vec!["u:alice:r,g:web:rw".to_string()];
+        assert!(setfacl_args("/etc/app.conf", &entries).is_err());
+    }
+
+    #[test]
+    fn rejects_blank_entries() {
+        assert!(setfacl_args("/etc/app.conf", &[" ".to_string()]).is_err());
+    }
+}
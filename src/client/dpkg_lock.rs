@@ -0,0 +1,104 @@
+//! Waits out the package database's lock, e.g. while `unattended-upgrades` holds it, instead of
+//! failing. Please see [Task::dpkg_lock_timeout](crate::core::Task::dpkg_lock_timeout).
+
+use super::{check_status, command_line};
+use anyhow::Context;
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait between attempts to run a command that couldn't get the lock.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What `apt`, `apt-get`, and `dpkg` print when another process holds the lock.
+const LOCK_ERRORS: [&str; 4] = [
+    "Could not get lock",
+    "Unable to acquire the dpkg frontend lock",
+    "Unable to lock the administration directory",
+    "dpkg status database is locked by another process",
+];
+
+/// Returns whether `stderr` shows that a command failed because it couldn't get the package
+/// database's lock.
+pub fn is_lock_error(stderr: &str) -> bool {
+    LOCK_ERRORS.iter().any(|error| stderr.contains(error))
+}
+
+/// Runs the command `cmd` with `args`, like [super::run], and runs it again every [POLL_INTERVAL]
+/// for up to `timeout` for as long as it fails because it couldn't get the package database's
+/// lock. The command's stderr still reaches sira-client's stderr.
+///
+/// # Errors
+///
+/// Returns an error if the command can't be run, fails for any other reason, or still can't get
+/// the lock when `timeout` runs out.
+pub fn run<C: AsRef<OsStr>, A: AsRef<OsStr>>(
+    cmd: C,
+    args: &[A],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    run_polling(cmd, args, timeout, POLL_INTERVAL)
+}
+
+/// Does the work of [run], trying again every `interval`.
+pub(crate) fn run_polling<C: AsRef<OsStr>, A: AsRef<OsStr>>(
+    cmd: C,
+    args: &[A],
+    timeout: Duration,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let (status, stderr) = run_teeing_stderr(&cmd, args)?;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if status.success() || !is_lock_error(&stderr) {
+            return check_status(status, &cmd, args);
+        }
+        if remaining.is_zero() {
+            return check_status(status, &cmd, args).with_context(|| {
+                format!(
+                    "the package database was still locked after {} seconds",
+                    timeout.as_secs()
+                )
+            });
+        }
+        eprintln!(
+            "sira-client: waiting for the package database's lock ({} seconds left)",
+            remaining.as_secs()
+        );
+        thread::sleep(interval.min(remaining));
+    }
+}
+
+/// Runs the command `cmd` with `args`, copying its stderr to sira-client's stderr as it arrives,
+/// and returns its exit status and stderr.
+fn run_teeing_stderr<C: AsRef<OsStr>, A: AsRef<OsStr>>(
+    cmd: C,
+    args: &[A],
+) -> anyhow::Result<(ExitStatus, String)> {
+    let mut child = Command::new(&cmd)
+        .args(args)
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to start command: {}", command_line(&cmd, args)))?;
+    let mut stderr = child.stderr.take().expect("stderr was not piped");
+    let mut captured = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let read = match stderr.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("could not read command's stderr"),
+        };
+        let _ = io::stderr().write_all(&buffer[..read]);
+        captured.extend_from_slice(&buffer[..read]);
+    }
+    let status = child.wait().context("could not wait for command")?;
+    Ok((status, String::from_utf8_lossy(&captured).into_owned()))
+}
+
+#[cfg(test)]
+mod test;
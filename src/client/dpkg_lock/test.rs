@@ -0,0 +1,83 @@
+use super::*;
+use std::fs;
+use std::path::Path;
+
+const LOCKED: &str = "echo 'E: Could not get lock /var/lib/dpkg/lock-frontend. It is held by \
+    process 1234 (unattended-upgr)' >&2; exit 100";
+
+/// Runs `script` with `sh -c` via [run_polling], counting each attempt in `attempts`, with a short
+/// interval.
+fn run_script(attempts: &Path, script: &str, timeout: Duration) -> anyhow::Result<()> {
+    let script = format!("echo >> {}; {script}", attempts.display());
+    run_polling(
+        "sh",
+        &["-c", script.as_str()],
+        timeout,
+        Duration::from_millis(10),
+    )
+}
+
+fn count(attempts: &Path) -> usize {
+    fs::read_to_string(attempts).unwrap().lines().count()
+}
+
+#[test]
+fn recognizes_lock_errors() {
+    assert!(is_lock_error(
+        "E: Could not get lock /var/lib/dpkg/lock-frontend. It is held by process 1234"
+    ));
+    assert!(is_lock_error(
+        "E: Unable to acquire the dpkg frontend lock (/var/lib/dpkg/lock-frontend), is another \
+        process using it?"
+    ));
+    assert!(is_lock_error(
+        "dpkg: error: dpkg frontend lock was locked by another process with pid 1234\n\
+        dpkg: error: dpkg status database is locked by another process"
+    ));
+    assert!(!is_lock_error("E: Unable to locate package nonexistent"));
+}
+
+#[test]
+fn retries_until_lock_clears() {
+    let dir = tempfile::tempdir().unwrap();
+    let attempts = dir.path().join("attempts");
+    let script = format!(
+        "[ $(wc -l < {}) -ge 3 ] || {{ {LOCKED}; }}",
+        attempts.display()
+    );
+    run_script(&attempts, &script, Duration::from_secs(10)).unwrap();
+    assert_eq!(3, count(&attempts));
+}
+
+#[test]
+fn gives_up_when_timeout_runs_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let attempts = dir.path().join("attempts");
+    let error = run_script(&attempts, LOCKED, Duration::from_millis(50)).unwrap_err();
+    let message = format!("{error:#}");
+    assert!(
+        message.contains("still locked after 0 seconds"),
+        "{message}"
+    );
+    assert!(message.contains("exit code 100"), "{message}");
+    assert!(count(&attempts) > 1);
+}
+
+#[test]
+fn doesnt_retry_other_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let attempts = dir.path().join("attempts");
+    let error = run_script(&attempts, "exit 2", Duration::from_secs(10)).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("command exited with exit code 2"));
+    assert_eq!(1, count(&attempts));
+}
+
+#[test]
+fn succeeds_without_retrying() {
+    let dir = tempfile::tempdir().unwrap();
+    let attempts = dir.path().join("attempts");
+    run_script(&attempts, "true", Duration::from_secs(10)).unwrap();
+    assert_eq!(1, count(&attempts));
+}
@@ -0,0 +1,431 @@
+//! Gathers facts about a managed node, e.g. its operating system, memory, disks, and IP addresses.
+//!
+//! `sira-client facts` prints a node's [Facts] as JSON on stdout, e.g. (wrapped here for
+//! readability):
+//!
+//! ```json
+//! {"hostname":"web1","os":{"id":"debian","name":"Debian GNU/Linux","version_id":"12",
+//! "pretty_name":"Debian GNU/Linux 12 (bookworm)"},"kernel":"6.1.0-18-amd64","arch":"x86_64",
+//! "memory":{"total_bytes":4100000000,"available_bytes":3200000000},
+//! "disks":[{"device":"/dev/sda1","mount_point":"/","total_bytes":21000000000,
+//! "available_bytes":11000000000}],
+//! "addresses":[{"interface":"eth0","family":"inet","address":"192.0.2.10","prefix_len":24}]}
+//! ```
+//!
+//! On Linux, facts come from `/etc/os-release`, `/proc`, `df`, and `ip`, so gathering them
+//! requires iproute2. Elsewhere, they come from tools that come with the system:
+//!
+//! - **macOS:** `sw_vers`, `uname`, `sysctl`, `vm_stat`, `df`, and `ifconfig`. [Os::id] is always
+//!   `macos`.
+//! - **FreeBSD and OpenBSD:** `uname`, `sysctl`, `vmstat`, `df`, and `ifconfig`. [Os::id] is
+//!   `freebsd` or `openbsd`, and [Os::version_id] is the release, e.g. `14.0-RELEASE`.
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::env::consts::OS;
+use std::fs;
+use std::process::Command;
+
+/// The argument that tells `sira-client` to print facts instead of running an action.
+pub const FACTS_COMMAND: &str = "facts";
+
+/// Facts about a managed node. Please see the [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Facts {
+    /// The node's host name, as the kernel knows it.
+    pub hostname: String,
+
+    /// The node's operating system, from `/etc/os-release` or, on macOS, `sw_vers`.
+    pub os: Os,
+
+    /// The kernel release, e.g. `6.1.0-18-amd64`.
+    pub kernel: String,
+
+    /// The CPU architecture, e.g. `x86_64` or `aarch64`.
+    pub arch: String,
+
+    /// The node's memory.
+    pub memory: Memory,
+
+    /// The node's disks, i.e. mounted file systems backed by a device under `/dev`.
+    pub disks: Vec<Disk>,
+
+    /// The IP addresses assigned to the node's network interfaces.
+    pub addresses: Vec<Address>,
+}
+
+/// A managed node's operating system. Any of these may be missing from `/etc/os-release`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Os {
+    /// A lower-case identifier for the operating system, e.g. `debian`.
+    pub id: Option<String>,
+
+    /// The operating system's name, e.g. `Debian GNU/Linux`.
+    pub name: Option<String>,
+
+    /// The operating system's version, e.g. `12`.
+    pub version_id: Option<String>,
+
+    /// A name suitable for display, e.g. `Debian GNU/Linux 12 (bookworm)`.
+    pub pretty_name: Option<String>,
+}
+
+/// A managed node's memory.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Memory {
+    /// The total usable memory, in bytes.
+    pub total_bytes: u64,
+
+    /// The memory available for starting new programs without swapping, in bytes.
+    pub available_bytes: u64,
+}
+
+/// A mounted file system.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Disk {
+    /// The device that holds the file system, e.g. `/dev/sda1`.
+    pub device: String,
+
+    /// Where the file system is mounted, e.g. `/`.
+    pub mount_point: String,
+
+    /// The file system's size, in bytes.
+    pub total_bytes: u64,
+
+    /// The space available to unprivileged users, in bytes.
+    pub available_bytes: u64,
+}
+
+/// An IP address assigned to a network interface.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Address {
+    /// The network interface, e.g. `eth0`.
+    pub interface: String,
+
+    /// Either `inet` (IPv4) or `inet6` (IPv6).
+    pub family: String,
+
+    /// The address, without its prefix length.
+    pub address: String,
+
+    /// The length of the network prefix, e.g. 24 for a /24 network.
+    pub prefix_len: u8,
+}
+
+impl Facts {
+    /// Gathers facts about the local system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any fact can't be gathered, e.g. because `/proc` isn't mounted.
+    pub fn gather() -> anyhow::Result<Self> {
+        match OS {
+            "macos" => Self::gather_macos(),
+            "freebsd" | "openbsd" => Self::gather_bsd(),
+            _ => Self::gather_linux(),
+        }
+    }
+
+    /// Gathers facts about the local Linux system.
+    fn gather_linux() -> anyhow::Result<Self> {
+        Ok(Facts {
+            hostname: read_trimmed("/proc/sys/kernel/hostname")?,
+            os: parse_os_release(&read("/etc/os-release")?),
+            kernel: read_trimmed("/proc/sys/kernel/osrelease")?,
+            arch: std::env::consts::ARCH.to_string(),
+            memory: parse_meminfo(&read("/proc/meminfo")?)?,
+            disks: parse_df(&output("df", &["-P", "-k"])?),
+            addresses: parse_ip_addr(&output("ip", &["-o", "addr", "show"])?),
+        })
+    }
+
+    /// Gathers facts about the local macOS system.
+    fn gather_macos() -> anyhow::Result<Self> {
+        Ok(Facts {
+            hostname: output("uname", &["-n"])?.trim().to_string(),
+            os: parse_sw_vers(&output("sw_vers", &[])?),
+            kernel: output("uname", &["-r"])?.trim().to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            memory: Memory {
+                total_bytes: sysctl("hw.memsize")?,
+                available_bytes: parse_vm_stat(&output("vm_stat", &[])?)?,
+            },
+            disks: parse_df(&output("df", &["-P", "-k"])?),
+            addresses: parse_ifconfig(&output("ifconfig", &[])?),
+        })
+    }
+
+    /// Gathers facts about the local FreeBSD or OpenBSD system.
+    fn gather_bsd() -> anyhow::Result<Self> {
+        Ok(Facts {
+            hostname: output("uname", &["-n"])?.trim().to_string(),
+            os: parse_uname(&output("uname", &["-sr"])?),
+            kernel: output("uname", &["-r"])?.trim().to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            memory: Memory {
+                total_bytes: sysctl("hw.physmem")?,
+                available_bytes: parse_vmstat_s(&output("vmstat", &["-s"])?)?,
+            },
+            disks: parse_df(&output("df", &["-P", "-k"])?),
+            addresses: parse_ifconfig(&output("ifconfig", &[])?),
+        })
+    }
+
+    /// Returns these facts as one line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Facts should always serialize to JSON")
+    }
+
+    /// Parses facts from JSON, e.g. as printed by `sira-client facts`.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        serde_json::from_str(json).context("could not parse facts")
+    }
+}
+
+/// Reads a file as text.
+fn read(path: &str) -> anyhow::Result<String> {
+    fs::read_to_string(path).with_context(|| format!("could not read {path}"))
+}
+
+/// Reads a file as text, without surrounding white space.
+fn read_trimmed(path: &str) -> anyhow::Result<String> {
+    Ok(read(path)?.trim().to_string())
+}
+
+/// Runs a command and returns its stdout.
+fn output(command: &str, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new(command)
+        .args(args)
+        .output()
+        .with_context(|| format!("could not run {command}"))?;
+    if !output.status.success() {
+        bail!(
+            "{command} exited with error: {}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads a numeric value with `sysctl`, e.g. `hw.physmem`.
+fn sysctl(name: &str) -> anyhow::Result<u64> {
+    let value = output("sysctl", &["-n", name])?;
+    let value = value.trim();
+    value
+        .parse()
+        .with_context(|| format!("sysctl reported an invalid {name}: {value}"))
+}
+
+/// Parses the contents of `/etc/os-release`.
+pub(crate) fn parse_os_release(contents: &str) -> Os {
+    let mut os = Os::default();
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        // Values use shell quoting, e.g. PRETTY_NAME="Debian GNU/Linux 12 (bookworm)".
+        let value = match shlex::split(value) {
+            Some(words) => words.join(" "),
+            None => continue,
+        };
+        let field = match key.trim() {
+            "ID" => &mut os.id,
+            "NAME" => &mut os.name,
+            "VERSION_ID" => &mut os.version_id,
+            "PRETTY_NAME" => &mut os.pretty_name,
+            _ => continue,
+        };
+        *field = Some(value);
+    }
+    os
+}
+
+/// Parses the contents of `/proc/meminfo`.
+pub(crate) fn parse_meminfo(contents: &str) -> anyhow::Result<Memory> {
+    let field = |name: &str| -> anyhow::Result<u64> {
+        let kibibytes = contents
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.split_whitespace().next())
+            .with_context(|| format!("/proc/meminfo has no {name}"))?;
+        let kibibytes: u64 = kibibytes
+            .parse()
+            .with_context(|| format!("/proc/meminfo has an invalid {name}: {kibibytes}"))?;
+        Ok(kibibytes * 1024)
+    };
+    Ok(Memory {
+        total_bytes: field("MemTotal")?,
+        available_bytes: field("MemAvailable")?,
+    })
+}
+
+/// Parses the output of `df -P -k`, keeping only file systems backed by a device under `/dev`.
+pub(crate) fn parse_df(output: &str) -> Vec<Disk> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            // Filesystem 1024-blocks Used Available Capacity Mounted-on, where the mount point
+            // may contain spaces.
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 || !fields[0].starts_with("/dev/") {
+                return None;
+            }
+            Some(Disk {
+                device: fields[0].to_string(),
+                mount_point: fields[5..].join(" "),
+                total_bytes: fields[1].parse::<u64>().ok()? * 1024,
+                available_bytes: fields[3].parse::<u64>().ok()? * 1024,
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `ip -o addr show`.
+pub(crate) fn parse_ip_addr(output: &str) -> Vec<Address> {
+    output
+        .lines()
+        .filter_map(|line| {
+            // 2: eth0    inet 192.0.2.10/24 brd 192.0.2.255 scope global eth0\ ...
+            let mut fields = line.split_whitespace().skip(1);
+            let interface = fields.next()?;
+            let family = fields.next()?;
+            if family != "inet" && family != "inet6" {
+                return None;
+            }
+            let (address, prefix_len) = fields.next()?.split_once('/')?;
+            Some(Address {
+                interface: interface.to_string(),
+                family: family.to_string(),
+                address: address.to_string(),
+                prefix_len: prefix_len.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `sw_vers` on macOS.
+pub(crate) fn parse_sw_vers(output: &str) -> Os {
+    let field = |name: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(|value| value.trim().to_string())
+    };
+    let name = field("ProductName");
+    let version_id = field("ProductVersion");
+    let pretty_name = match (&name, &version_id) {
+        (Some(name), Some(version_id)) => Some(format!("{name} {version_id}")),
+        (name, _) => name.clone(),
+    };
+    Os {
+        id: Some("macos".to_string()),
+        name,
+        version_id,
+        pretty_name,
+    }
+}
+
+/// Parses the output of `vm_stat` on macOS, returning the bytes available for starting new
+/// programs without swapping, i.e. free, inactive, and speculative pages.
+pub(crate) fn parse_vm_stat(output: &str) -> anyhow::Result<u64> {
+    // Mach Virtual Memory Statistics: (page size of 16384 bytes)
+    let page_size: u64 = output
+        .lines()
+        .next()
+        .and_then(|line| {
+            line.split_once("page size of ")?
+                .1
+                .split_whitespace()
+                .next()
+        })
+        .and_then(|page_size| page_size.parse().ok())
+        .context("vm_stat reported no page size")?;
+    let pages = |name: &str| -> anyhow::Result<u64> {
+        // Pages free:                               12345.
+        let pages = output
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .with_context(|| format!("vm_stat reported no {name}"))?;
+        let pages = pages.trim().trim_end_matches('.');
+        pages
+            .parse()
+            .with_context(|| format!("vm_stat reported an invalid {name}: {pages}"))
+    };
+    let available = pages("Pages free")? + pages("Pages inactive")? + pages("Pages speculative")?;
+    Ok(available * page_size)
+}
+
+/// Parses the output of `uname -sr` on the BSDs, e.g. `FreeBSD 14.0-RELEASE`.
+pub(crate) fn parse_uname(output: &str) -> Os {
+    let mut words = output.split_whitespace();
+    let name = words.next();
+    let version_id = words.next();
+    Os {
+        id: name.map(str::to_lowercase),
+        name: name.map(str::to_string),
+        version_id: version_id.map(str::to_string),
+        pretty_name: name.map(|_| output.trim().to_string()),
+    }
+}
+
+/// Parses the output of `vmstat -s` on the BSDs, returning the bytes available for starting new
+/// programs without swapping, i.e. free and inactive pages.
+pub(crate) fn parse_vmstat_s(output: &str) -> anyhow::Result<u64> {
+    // Each line is a number followed by a description, e.g. `    4096 bytes per page`.
+    let field = |name: &str| -> anyhow::Result<u64> {
+        output
+            .lines()
+            .find_map(|line| {
+                let (value, description) = line.trim().split_once(' ')?;
+                (description.trim() == name).then_some(value)
+            })
+            .with_context(|| format!("vmstat reported no {name}"))?
+            .parse()
+            .with_context(|| format!("vmstat reported an invalid {name}"))
+    };
+    Ok((field("pages free")? + field("pages inactive")?) * field("bytes per page")?)
+}
+
+/// Parses the output of BSD `ifconfig`, e.g. on macOS, FreeBSD, or OpenBSD.
+pub(crate) fn parse_ifconfig(output: &str) -> Vec<Address> {
+    let mut addresses = Vec::new();
+    let mut interface = None;
+    for line in output.lines() {
+        // en0: flags=8863<UP,BROADCAST,SMART,RUNNING,SIMPLEX,MULTICAST> mtu 1500
+        if !line.starts_with(char::is_whitespace) {
+            interface = line.split_once(": ").map(|(name, _)| name.to_string());
+            continue;
+        }
+        let Some(interface) = &interface else {
+            continue;
+        };
+
+        //     inet 192.0.2.10 netmask 0xffffff00 broadcast 192.0.2.255
+        //     inet6 fe80::1%en0 prefixlen 64 secured scopeid 0x4
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let prefix_len = match fields.as_slice() {
+            ["inet", _, "netmask", netmask, ..] => netmask
+                .strip_prefix("0x")
+                .and_then(|netmask| u32::from_str_radix(netmask, 16).ok())
+                .map(|netmask| netmask.count_ones() as u8),
+            ["inet6", _, "prefixlen", prefix_len, ..] => prefix_len.parse().ok(),
+            _ => None,
+        };
+        let Some(prefix_len) = prefix_len else {
+            continue;
+        };
+        let address = fields[1].split('%').next().unwrap_or_default();
+        addresses.push(Address {
+            interface: interface.clone(),
+            family: fields[0].to_string(),
+            address: address.to_string(),
+            prefix_len,
+        });
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod test;
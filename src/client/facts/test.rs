@@ -0,0 +1,309 @@
+use super::*;
+
+mod parse_os_release {
+    use super::*;
+
+    #[test]
+    fn reads_quoted_and_unquoted_values() {
+        let contents = "\
+PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"
+NAME=\"Debian GNU/Linux\"
+VERSION_ID=\"12\"
+VERSION=\"12 (bookworm)\"
+ID=debian
+HOME_URL=\"https://www.debian.org/\"
+";
+        assert_eq!(
+            Os {
+                id: Some("debian".to_string()),
+                name: Some("Debian GNU/Linux".to_string()),
+                version_id: Some("12".to_string()),
+                pretty_name: Some("Debian GNU/Linux 12 (bookworm)".to_string()),
+            },
+            parse_os_release(contents),
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_missing_fields() {
+        let contents = "# Written by hand\n\nID=arch\n";
+        assert_eq!(
+            Os {
+                id: Some("arch".to_string()),
+                ..Default::default()
+            },
+            parse_os_release(contents),
+        );
+    }
+}
+
+mod parse_meminfo {
+    use super::*;
+
+    #[test]
+    fn converts_to_bytes() {
+        let contents = "\
+MemTotal:        4000000 kB
+MemFree:          500000 kB
+MemAvailable:    3000000 kB
+";
+        assert_eq!(
+            Memory {
+                total_bytes: 4_096_000_000,
+                available_bytes: 3_072_000_000,
+            },
+            parse_meminfo(contents).unwrap(),
+        );
+    }
+
+    #[test]
+    fn requires_fields() {
+        assert!(parse_meminfo("MemTotal: 4000000 kB\n").is_err());
+        assert!(parse_meminfo("MemTotal: lots\nMemAvailable: 1 kB\n").is_err());
+    }
+}
+
+mod parse_df {
+    use super::*;
+
+    #[test]
+    fn keeps_devices() {
+        let output = "\
+Filesystem     1024-blocks    Used Available Capacity Mounted on
+udev               2000000       0   2000000       0% /dev
+/dev/sda1         20000000 8000000  11000000      43% /
+/dev/sdb1          1000000       4    999996       1% /mnt/backup disk
+tmpfs               400000    1000    399000       1% /run
+";
+        assert_eq!(
+            vec![
+                Disk {
+                    device: "/dev/sda1".to_string(),
+                    mount_point: "/".to_string(),
+                    total_bytes: 20_480_000_000,
+                    available_bytes: 11_264_000_000,
+                },
+                Disk {
+                    device: "/dev/sdb1".to_string(),
+                    mount_point: "/mnt/backup disk".to_string(),
+                    total_bytes: 1_024_000_000,
+                    available_bytes: 1_023_995_904,
+                },
+            ],
+            parse_df(output),
+        );
+    }
+}
+
+mod parse_ip_addr {
+    use super::*;
+
+    #[test]
+    fn reads_ipv4_and_ipv6() {
+        let output = "\
+1: lo    inet 127.0.0.1/8 scope host lo\\       valid_lft forever preferred_lft forever
+2: eth0    inet 192.0.2.10/24 brd 192.0.2.255 scope global eth0\\       valid_lft forever
+2: eth0    inet6 fe80::1/64 scope link \\       valid_lft forever preferred_lft forever
+";
+        let address = |interface: &str, family: &str, address: &str, prefix_len| Address {
+            interface: interface.to_string(),
+            family: family.to_string(),
+            address: address.to_string(),
+            prefix_len,
+        };
+        assert_eq!(
+            vec![
+                address("lo", "inet", "127.0.0.1", 8),
+                address("eth0", "inet", "192.0.2.10", 24),
+                address("eth0", "inet6", "fe80::1", 64),
+            ],
+            parse_ip_addr(output),
+        );
+    }
+
+    #[test]
+    fn skips_other_lines() {
+        assert!(parse_ip_addr("3: wg0    link/none \n\n").is_empty());
+    }
+}
+
+mod parse_sw_vers {
+    use super::*;
+
+    #[test]
+    fn reads_product_name_and_version() {
+        let output = "\
+ProductName:\t\tmacOS
+ProductVersion:\t\t14.4.1
+BuildVersion:\t\t23E224
+";
+        assert_eq!(
+            Os {
+                id: Some("macos".to_string()),
+                name: Some("macOS".to_string()),
+                version_id: Some("14.4.1".to_string()),
+                pretty_name: Some("macOS 14.4.1".to_string()),
+            },
+            parse_sw_vers(output),
+        );
+    }
+
+    #[test]
+    fn skips_missing_fields() {
+        let os = parse_sw_vers("ProductName:\tMac OS X\n");
+        assert_eq!(Some("Mac OS X".to_string()), os.pretty_name);
+        assert_eq!(None, os.version_id);
+    }
+}
+
+mod parse_vm_stat {
+    use super::*;
+
+    #[test]
+    fn counts_free_inactive_and_speculative_pages() {
+        let output = "\
+Mach Virtual Memory Statistics: (page size of 16384 bytes)
+Pages free:                                3000.
+Pages active:                            200000.
+Pages inactive:                           10000.
+Pages speculative:                         1000.
+Pages wired down:                         90000.
+";
+        assert_eq!(14_000 * 16_384, parse_vm_stat(output).unwrap());
+    }
+
+    #[test]
+    fn requires_page_size_and_fields() {
+        assert!(parse_vm_stat("Pages free: 3000.\n").is_err());
+        assert!(parse_vm_stat(
+            "Mach Virtual Memory Statistics: (page size of 4096 bytes)\nPages free: 3000.\n",
+        )
+        .is_err());
+    }
+}
+
+mod parse_uname {
+    use super::*;
+
+    #[test]
+    fn reads_system_and_release() {
+        assert_eq!(
+            Os {
+                id: Some("freebsd".to_string()),
+                name: Some("FreeBSD".to_string()),
+                version_id: Some("14.0-RELEASE".to_string()),
+                pretty_name: Some("FreeBSD 14.0-RELEASE".to_string()),
+            },
+            parse_uname("FreeBSD 14.0-RELEASE\n"),
+        );
+    }
+
+    #[test]
+    fn reads_nothing_from_nothing() {
+        assert_eq!(Os::default(), parse_uname("\n"));
+    }
+}
+
+mod parse_vmstat_s {
+    use super::*;
+
+    #[test]
+    fn counts_free_and_inactive_pages() {
+        // OpenBSD; FreeBSD's output differs in other lines.
+        let output = "\
+     4096 bytes per page
+  2055913 pages managed
+   123456 pages free
+   300000 pages active
+    20000 pages inactive
+        0 pages being paged out
+";
+        assert_eq!(143_456 * 4096, parse_vmstat_s(output).unwrap());
+    }
+
+    #[test]
+    fn requires_fields() {
+        assert!(parse_vmstat_s("4096 bytes per page\n12 pages free\n").is_err());
+        assert!(parse_vmstat_s("12 pages free\n34 pages inactive\n").is_err());
+    }
+}
+
+mod parse_ifconfig {
+    use super::*;
+
+    #[test]
+    fn reads_ipv4_and_ipv6() {
+        let output = "\
+lo0: flags=8049<UP,LOOPBACK,RUNNING,MULTICAST> mtu 16384
+\toptions=1203<RXCSUM,TXCSUM,TXSTATUS,SW_TIMESTAMP>
+\tinet 127.0.0.1 netmask 0xff000000
+\tinet6 ::1 prefixlen 128
+\tinet6 fe80::1%lo0 prefixlen 64 scopeid 0x1
+en0: flags=8863<UP,BROADCAST,SMART,RUNNING,SIMPLEX,MULTICAST> mtu 1500
+\tether 02:00:00:00:00:01
+\tinet 192.0.2.10 netmask 0xffffff00 broadcast 192.0.2.255
+\tstatus: active
+";
+        let address = |interface: &str, family: &str, address: &str, prefix_len| Address {
+            interface: interface.to_string(),
+            family: family.to_string(),
+            address: address.to_string(),
+            prefix_len,
+        };
+        assert_eq!(
+            vec![
+                address("lo0", "inet", "127.0.0.1", 8),
+                address("lo0", "inet6", "::1", 128),
+                address("lo0", "inet6", "fe80::1", 64),
+                address("en0", "inet", "192.0.2.10", 24),
+            ],
+            parse_ifconfig(output),
+        );
+    }
+
+    #[test]
+    fn reads_freebsd_and_openbsd_output() {
+        let output = "\
+vtnet0: flags=1008843<UP,BROADCAST,RUNNING,SIMPLEX,MULTICAST,LOWER_UP> metric 0 mtu 1500
+\toptions=4c079b<RXCSUM,TXCSUM,VLAN_MTU,VLAN_HWTAGGING,VLAN_HWCSUM,TSO4,TSO6,LRO>
+\tether 02:00:00:00:00:02
+\tinet 198.51.100.7 netmask 0xfffffc00 broadcast 198.51.103.255
+\tinet6 2001:db8::7 prefixlen 64
+\tmedia: Ethernet autoselect (10Gbase-T <full-duplex>)
+";
+        assert_eq!(
+            vec![
+                Address {
+                    interface: "vtnet0".to_string(),
+                    family: "inet".to_string(),
+                    address: "198.51.100.7".to_string(),
+                    prefix_len: 22,
+                },
+                Address {
+                    interface: "vtnet0".to_string(),
+                    family: "inet6".to_string(),
+                    address: "2001:db8::7".to_string(),
+                    prefix_len: 64,
+                },
+            ],
+            parse_ifconfig(output),
+        );
+    }
+
+    #[test]
+    fn skips_interfaces_without_addresses() {
+        let output = "gif0: flags=8010<POINTOPOINT,MULTICAST> mtu 1280\n\tstatus: inactive\n";
+        assert!(parse_ifconfig(output).is_empty());
+    }
+}
+
+#[test]
+fn json_round_trip() {
+    let facts = Facts {
+        hostname: "web1".to_string(),
+        kernel: "6.1.0".to_string(),
+        arch: "x86_64".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(facts, Facts::from_json(&facts.to_json()).unwrap());
+}
@@ -0,0 +1,129 @@
+//! Installs files that `sira-client` receives, e.g. uploads, at their destinations.
+//!
+//! A file is installed in two steps: [set_attributes] gives it its final owner, permissions, and
+//! other attributes where it is, and then [move_into_place] moves it to its destination. Files that
+//! mustn't be left lying around if either step fails, e.g. decrypted uploads, are [Staged] next to
+//! their destinations first.
+
+use super::{acl, mktemp_in, platform, run, xattr};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::Path;
+
+/// A temporary file on its way to being installed, which is removed when this is dropped unless it
+/// has been moved into place by then. This way, neither an error nor an existing destination that
+/// [move_into_place] keeps leaves the file behind.
+#[derive(Debug)]
+pub struct Staged {
+    /// The path to the file.
+    path: String,
+}
+
+impl Staged {
+    /// Creates an empty file that only its owner can read or write in the same directory as
+    /// `destination`, so that its contents never pass through a shared temporary directory and
+    /// installing it is a rename.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be created, e.g. because `destination`'s directory
+    /// doesn't exist.
+    pub fn next_to(destination: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = match destination.as_ref().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let (_file, path) = mktemp_in(dir)?;
+        Ok(Staged { path })
+    }
+
+    /// Returns the path to the file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        // If the file was moved into place, it's already gone.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The owner, permissions, and other attributes that an installed file should have.
+#[derive(Clone, Copy, Debug)]
+pub struct Attributes<'a> {
+    /// The file's owner.
+    pub user: &'a str,
+
+    /// The file's group.
+    pub group: &'a str,
+
+    /// The file's permissions, in any form that `chmod` accepts, if they should change.
+    pub permissions: Option<&'a str>,
+
+    /// Entries to add to the file's ACL. Please see [acl::set].
+    pub acl: &'a [String],
+
+    /// Extended attributes to set on the file. Please see [xattr::set].
+    pub xattrs: &'a BTreeMap<String, String>,
+
+    /// The file's capabilities, if any. Please see [xattr::set_capabilities].
+    pub capabilities: Option<&'a str>,
+}
+
+/// Gives the file at `path` its final `attributes`.
+///
+/// # Errors
+///
+/// Returns an error if any attribute can't be set. Those set before it stay set.
+pub fn set_attributes(path: &str, attributes: &Attributes) -> anyhow::Result<()> {
+    // chmod before chown under the theory that it might be slightly more secure. If the final
+    // permissions are more restrictive than the Sira user's default permissions, then we don't want
+    // to change the user or group, thereby granting additional potential access, before we
+    // restrict permissions for said user and group.
+    if let Some(permissions) = attributes.permissions {
+        run("chmod", &[permissions, path])?;
+    }
+
+    let group = platform::group(attributes.group);
+    run(
+        "chown",
+        &[&format!("{}:{group}", attributes.user)[..], path],
+    )?;
+
+    // Add ACL entries last, since chmod can change the ACL mask.
+    if !attributes.acl.is_empty() {
+        acl::set(path, attributes.acl)?;
+    }
+
+    // Likewise, set capabilities after chown, which clears them.
+    xattr::set(path, attributes.xattrs)?;
+    if let Some(capabilities) = attributes.capabilities {
+        xattr::set_capabilities(path, capabilities)?;
+    }
+    Ok(())
+}
+
+/// Moves the file at `path` to `destination` with `mv`, unless `destination` already exists and
+/// `overwrite` is false. Returns whether it moved the file.
+///
+/// # Errors
+///
+/// Returns an error if `mv` fails. The file at `path` stays where it is.
+pub fn move_into_place(path: &str, destination: &OsStr, overwrite: bool) -> anyhow::Result<bool> {
+    let mut args: Vec<OsString> = Vec::with_capacity(3);
+    if !overwrite {
+        args.push("-n".into());
+    }
+    args.push(path.into());
+    args.push(destination.into());
+    run("mv", &args)?;
+
+    // `mv -n` succeeds without moving anything if the destination exists.
+    Ok(!Path::new(path).exists())
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,113 @@
+use super::*;
+use crate::client::whoami;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+// Returns the name of the current user's primary group.
+fn current_group() -> String {
+    let output = Command::new("id").arg("-gn").output().unwrap();
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+fn attributes<'a>(
+    user: &'a str,
+    group: &'a str,
+    xattrs: &'a BTreeMap<String, String>,
+) -> Attributes<'a> {
+    Attributes {
+        user,
+        group,
+        permissions: Some("0640"),
+        acl: &[],
+        xattrs,
+        capabilities: None,
+    }
+}
+
+mod staged {
+    use super::*;
+
+    #[test]
+    fn creates_private_file_next_to_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = Staged::next_to(dir.path().join("secret")).unwrap();
+        let path = Path::new(staged.path()).to_path_buf();
+        assert_eq!(Some(dir.path()), path.parent());
+        assert_eq!(
+            0o600,
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+
+        drop(staged);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn returns_error_if_destination_dir_is_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Staged::next_to(dir.path().join("missing/secret")).is_err());
+    }
+}
+
+mod set_attributes {
+    use super::*;
+
+    #[test]
+    fn sets_owner_and_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = Staged::next_to(dir.path().join("secret")).unwrap();
+        let group = current_group();
+        let xattrs = BTreeMap::new();
+        set_attributes(staged.path(), &attributes(whoami(), &group, &xattrs)).unwrap();
+        let mode = fs::metadata(staged.path()).unwrap().permissions().mode();
+        assert_eq!(0o640, mode & 0o777);
+    }
+
+    #[test]
+    fn leaves_nothing_behind_if_chown_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = Staged::next_to(dir.path().join("secret")).unwrap();
+        let path = staged.path().to_string();
+        let xattrs = BTreeMap::new();
+        let attributes = attributes("sira-no-such-user", "sira-no-such-group", &xattrs);
+
+        let error = set_attributes(&path, &attributes).unwrap_err();
+        assert!(error.to_string().contains("chown"), "{error}");
+        drop(staged);
+        assert!(!Path::new(&path).exists());
+    }
+}
+
+mod move_into_place {
+    use super::*;
+
+    #[test]
+    fn moves_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("secret");
+        fs::write(&destination, "old").unwrap();
+        let staged = Staged::next_to(&destination).unwrap();
+        fs::write(staged.path(), "new").unwrap();
+
+        assert!(move_into_place(staged.path(), destination.as_os_str(), true).unwrap());
+        assert_eq!("new", fs::read_to_string(&destination).unwrap());
+        drop(staged);
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn keeps_existing_destination_without_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("secret");
+        fs::write(&destination, "old").unwrap();
+        let staged = Staged::next_to(&destination).unwrap();
+        fs::write(staged.path(), "new").unwrap();
+
+        assert!(!move_into_place(staged.path(), destination.as_os_str(), false).unwrap());
+        assert_eq!("old", fs::read_to_string(&destination).unwrap());
+
+        // The staged file, e.g. a decrypted secret, doesn't outlive the upload.
+        drop(staged);
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+}
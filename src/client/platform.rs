@@ -0,0 +1,70 @@
+//! Hides the differences between the operating systems that `sira-client` runs on, i.e. Linux,
+//! macOS, FreeBSD, and OpenBSD.
+//!
+//! `sira-client` mostly calls utilities whose common options work alike on all of them, e.g.
+//! `chmod`, `chown`, `mktemp`, and `mv`. Where the systems differ, e.g. in where home directories
+//! live or in which group owns system files, these functions choose for the local system. They
+//! decide by [OS], which is a constant, so each build keeps only its own system's choices.
+
+use std::env::consts::OS;
+use std::path::PathBuf;
+
+/// Returns whether the local system is one of the BSDs that Sira supports, i.e. FreeBSD or
+/// OpenBSD.
+pub fn is_bsd() -> bool {
+    matches!(OS, "freebsd" | "openbsd")
+}
+
+/// Returns the home directory of `user`, assuming that, like the Sira user created in
+/// `installation.md`, it lives in `/home` (or, on macOS, `/Users`).
+pub fn home_dir(user: &str) -> PathBuf {
+    let root = match OS {
+        "macos" => "/Users",
+        _ => "/home",
+    };
+    PathBuf::from(root).join(user)
+}
+
+/// Returns the local name of the group `group`.
+///
+/// macOS and the BSDs have no `root` group; their group 0 is `wheel`. There, `root` means `wheel`,
+/// so that actions written for Linux, e.g. uploads owned by `root:root`, work unchanged.
+/// Elsewhere, every group keeps its name.
+pub fn group(group: &str) -> &str {
+    match group {
+        "root" if OS == "macos" || is_bsd() => "wheel",
+        group => group,
+    }
+}
+
+/// Returns the program that prints a file's SHA-256 checksum before anything else on its output,
+/// as GNU `sha256sum` does, followed by the arguments it needs to do so.
+pub fn sha256sum() -> (&'static str, &'static [&'static str]) {
+    match OS {
+        "macos" => ("shasum", &["--algorithm", "256"]),
+        "freebsd" | "openbsd" => ("sha256", &["-q"]),
+        _ => ("sha256sum", &[]),
+    }
+}
+
+/// Returns the directory that holds sudo's configuration, i.e. `sudoers` and `sudoers.d`. That's
+/// `/etc`, except on FreeBSD, which installs sudo from ports under `/usr/local`.
+pub fn sudoers_dir() -> &'static str {
+    match OS {
+        "freebsd" => "/usr/local/etc",
+        _ => "/etc",
+    }
+}
+
+/// Returns the program that runs a command as another user, given `-u <user>`, e.g. for
+/// [Action::Script](crate::core::Action::Script). That's `sudo`, except on OpenBSD, which ships
+/// `doas` instead.
+pub fn run_as() -> &'static str {
+    match OS {
+        "openbsd" => "doas",
+        _ => "sudo",
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,49 @@
+use super::*;
+
+mod home_dir {
+    use super::*;
+
+    #[test]
+    fn joins_home_root() {
+        let expected = match OS {
+            "macos" => "/Users/sira",
+            _ => "/home/sira",
+        };
+        assert_eq!(PathBuf::from(expected), home_dir("sira"));
+    }
+}
+
+mod group {
+    use super::*;
+
+    #[test]
+    fn maps_root_to_group_zero() {
+        let expected = match OS {
+            "macos" | "freebsd" | "openbsd" => "wheel",
+            _ => "root",
+        };
+        assert_eq!(expected, group("root"));
+    }
+
+    #[test]
+    fn keeps_other_groups() {
+        assert_eq!("staff", group("staff"));
+        assert_eq!("wheel", group("wheel"));
+    }
+}
+
+mod sha256sum {
+    use super::*;
+
+    #[test]
+    fn names_an_installed_program() {
+        let (program, args) = sha256sum();
+        let status = std::process::Command::new(program)
+            .args(args)
+            .arg("--")
+            .arg("Cargo.toml")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,310 @@
+//! Runs the plugins behind [Action::Custom], so that sites can add their own actions without
+//! forking Sira.
+//!
+//! A plugin is an executable in [plugin_dir], i.e. `/etc/sira/plugins/<plugin>` on the managed
+//! node. Because `sira-client` runs plugins as root, it refuses any plugin that isn't a regular
+//! file owned by root and writable only by root.
+//!
+//! # Contract
+//!
+//! `sira-client` runs the plugin with no arguments and writes a [Request] as JSON to its standard
+//! input, then closes it:
+//!
+//! ```json
+//! {"version": 1, "plugin": "firewall", "args": {"port": 443, "protocol": "tcp"}, "check": false}
+//! ```
+//!
+//! - `version`: the version of this contract, currently [PROTOCOL_VERSION].
+//! - `plugin`: the plugin's name, as in the action.
+//! - `args`: the action's arguments, after variable substitution, or `null` if there are none.
+//! - `check`: whether `sira-client` is in check mode. If so, the plugin must report what it would
+//!   change without changing anything, or fail if it can't.
+//!
+//! On success, the plugin exits with status 0 and writes a [Response] as JSON to its standard
+//! output:
+//!
+//! ```json
+//! {"changed": true, "output": "Opened port 443/tcp"}
+//! ```
+//!
+//! - `changed`: whether the plugin changed anything (or would have, in check mode).
+//! - `output`: optional text to show the user, e.g. what changed.
+//!
+//! On failure, the plugin exits with a non-zero status. Anything it writes to standard output is
+//! then shown as is. Whether it succeeds or fails, anything it writes to standard error is shown,
+//! too.
+//!
+//! Future versions of the contract will only add fields, so plugins should ignore fields they
+//! don't know. A plugin that needs a newer contract than `sira-client` offers should fail.
+//!
+//! # Writing plugins in Rust
+//!
+//! Plugins can be written in any language, but a Rust plugin can depend on the `sira` crate and
+//! let [serve] handle the contract:
+//!
+//! ```no_run
+//! use serde::Deserialize;
+//! use sira::client::plugin::{self, Response};
+//!
+//! #[derive(Deserialize)]
+//! struct Args {
+//!     port: u16,
+//! }
+//!
+//! fn main() -> std::process::ExitCode {
+//!     plugin::serve(|request| {
+//!         let args: Args = request.args_as()?;
+//!         if request.check {
+//!             return Ok(Response::unchanged());
+//!         }
+//!         // Open the port here.
+//!         Ok(Response::changed(format!("Opened port {}", args.port)))
+//!     })
+//! }
+//! ```
+//!
+//! [Action::Custom]: crate::core::Action::Custom
+
+use crate::config;
+use crate::core::action::{ClientError, ErrorCode, Limits};
+use anyhow::{bail, Context};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::process::{Command, ExitCode, Stdio};
+
+/// The name of the plugin directory within Sira's configuration directory.
+pub const PLUGIN_DIR: &str = "plugins";
+
+/// The version of the contract between `sira-client` and plugins.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// What `sira-client` asks a plugin to do. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    /// The version of the contract, i.e. [PROTOCOL_VERSION].
+    pub version: u32,
+
+    /// The plugin's name.
+    pub plugin: String,
+
+    /// The action's arguments.
+    pub args: serde_json::Value,
+
+    /// Whether to report what would change without changing anything.
+    pub check: bool,
+}
+
+impl Request {
+    /// Returns the action's arguments as a `T`, e.g. a struct that derives [Deserialize].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the arguments don't fit `T`.
+    pub fn args_as<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        T::deserialize(&self.args)
+            .with_context(|| format!("invalid arguments for plugin {}", self.plugin))
+    }
+}
+
+/// What a plugin reports back to `sira-client`. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response {
+    /// Whether the plugin changed anything.
+    pub changed: bool,
+
+    /// Text to show the user.
+    #[serde(default)]
+    pub output: String,
+}
+
+impl Response {
+    /// Returns a [Response] reporting that the plugin changed something, with `output` to show the
+    /// user.
+    pub fn changed(output: impl Into<String>) -> Self {
+        Response {
+            changed: true,
+            output: output.into(),
+        }
+    }
+
+    /// Returns a [Response] reporting that the plugin changed nothing.
+    pub fn unchanged() -> Self {
+        Response {
+            changed: false,
+            output: String::new(),
+        }
+    }
+}
+
+/// Implements the plugin side of the contract: reads a [Request] from standard input, passes it to
+/// `handler`, and writes the [Response] to standard output. Returns the exit status for the plugin
+/// to exit with. Please see the [module documentation](self#writing-plugins-in-rust).
+///
+/// If the request can't be read, asks for a newer contract than [PROTOCOL_VERSION], or `handler`
+/// returns an error, prints the error to standard error and returns a failure.
+pub fn serve<F>(handler: F) -> ExitCode
+where
+    F: FnOnce(&Request) -> anyhow::Result<Response>,
+{
+    match _serve(io::stdin().lock(), io::stdout().lock(), handler) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// A testable version of [serve] that reads from `input` and writes to `output`.
+pub(crate) fn _serve<R, W, F>(mut input: R, mut output: W, handler: F) -> anyhow::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnOnce(&Request) -> anyhow::Result<Response>,
+{
+    let mut buf = Vec::new();
+    input
+        .read_to_end(&mut buf)
+        .context("could not read request")?;
+    let request: Request = serde_json::from_slice(&buf).context("could not parse request")?;
+    if request.version > PROTOCOL_VERSION {
+        bail!(
+            "request uses version {} of the plugin contract, but this plugin only knows version \
+            {PROTOCOL_VERSION}",
+            request.version,
+        );
+    }
+    let response = handler(&request)?;
+    serde_json::to_writer(&mut output, &response)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Returns the plugin directory, i.e. `/etc/sira/plugins`.
+pub fn plugin_dir() -> PathBuf {
+    config::config_dir().join(PLUGIN_DIR)
+}
+
+/// Returns the path to the plugin named `name`.
+///
+/// # Errors
+///
+/// Returns an error if `name` isn't a plain file name, or if the plugin isn't installed, isn't a
+/// regular file, or could be changed by anyone but root.
+pub fn plugin_path(name: &str) -> anyhow::Result<PathBuf> {
+    check_name(name)?;
+    let path = plugin_dir().join(name);
+    let metadata = fs::metadata(&path)
+        .with_context(|| format!("plugin {name} is not installed at {}", path.display()))?;
+    if !metadata.is_file() {
+        bail!("plugin {name} is not a regular file: {}", path.display());
+    }
+    check_ownership(metadata.uid(), metadata.mode())
+        .with_context(|| format!("refusing to run plugin {name}: {}", path.display()))?;
+    Ok(path)
+}
+
+/// Runs the plugin named `name` with `args` under `limits` and returns its response.
+///
+/// # Errors
+///
+/// Returns an error if the plugin can't be found or run, if it fails, or if its response isn't
+/// valid.
+pub fn run(
+    name: &str,
+    args: &serde_json::Value,
+    check: bool,
+    limits: &Limits,
+) -> anyhow::Result<Response> {
+    let path = plugin_path(name)?;
+    let path = path.to_string_lossy();
+    let (command, command_args) = limits.wrap(&path, &[])?;
+    let request = Request {
+        version: PROTOCOL_VERSION,
+        plugin: name.to_string(),
+        args: args.clone(),
+        check,
+    };
+
+    let mut child = Command::new(&command)
+        .args(&command_args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("could not run plugin {name}"))?;
+
+    // A plugin that doesn't read its request may close its standard input early, which is fine.
+    let mut stdin = child.stdin.take().expect("plugin's stdin should be piped");
+    match stdin.write_all(serde_json::to_string(&request)?.as_bytes()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            return Err(e).with_context(|| format!("could not send request to plugin {name}"))
+        }
+        _ => drop(stdin),
+    }
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("could not run plugin {name}"))?;
+    if !output.status.success() {
+        io::stdout().write_all(&output.stdout)?;
+        let error = match output.status.code() {
+            Some(i) => format!("exit code {i}"),
+            None => "error".to_string(),
+        };
+        return Err(ClientError::new(
+            ErrorCode::CommandFailed,
+            format!("plugin {name} exited with {error}"),
+        )
+        .into());
+    }
+    parse_response(&output.stdout)
+        .with_context(|| format!("plugin {name} did not return a valid response"))
+}
+
+/// Parses a plugin's response from its standard output.
+pub(crate) fn parse_response(stdout: &[u8]) -> anyhow::Result<Response> {
+    Ok(serde_json::from_slice(stdout)?)
+}
+
+/// Returns an error unless `name` is a plain file name, so that it can't name a file outside
+/// the plugin directory.
+pub(crate) fn check_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        bail!(
+            "invalid plugin name {name:?}; please use only letters, digits, '-', '_', and '.', \
+            and don't start with '.'"
+        );
+    }
+    Ok(())
+}
+
+/// Returns an error unless a file with owner `uid` and permissions `mode` can only be changed by
+/// root.
+pub(crate) fn check_ownership(uid: u32, mode: u32) -> anyhow::Result<()> {
+    if uid != 0 {
+        return Err(
+            ClientError::new(ErrorCode::PermissionDenied, "plugins must be owned by root").into(),
+        );
+    }
+    if mode & 0o022 != 0 {
+        return Err(ClientError::new(
+            ErrorCode::PermissionDenied,
+            "plugins must not be writable by group or others",
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test;
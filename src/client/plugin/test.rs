@@ -0,0 +1,183 @@
+use super::*;
+
+mod check_name {
+    use super::*;
+
+    #[test]
+    fn accepts_plain_names() {
+        check_name("firewall").unwrap();
+        check_name("site_backup-2.sh").unwrap();
+    }
+
+    #[test]
+    fn rejects_paths() {
+        assert!(check_name("../bin/sh").is_err());
+        assert!(check_name("/bin/sh").is_err());
+        assert!(check_name("sub/plugin").is_err());
+    }
+
+    #[test]
+    fn rejects_hidden_and_empty_names() {
+        assert!(check_name(".hidden").is_err());
+        assert!(check_name("").is_err());
+    }
+}
+
+mod check_ownership {
+    use super::*;
+
+    #[test]
+    fn accepts_root_owned() {
+        check_ownership(0, 0o100755).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_owners() {
+        assert!(check_ownership(1000, 0o100755).is_err());
+    }
+
+    #[test]
+    fn rejects_group_or_world_writable() {
+        assert!(check_ownership(0, 0o100775).is_err());
+        assert!(check_ownership(0, 0o100757).is_err());
+    }
+}
+
+mod parse_response {
+    use super::*;
+
+    #[test]
+    fn output_is_optional() {
+        assert_eq!(
+            Response {
+                changed: false,
+                output: String::new(),
+            },
+            parse_response(br#"{"changed": false}"#).unwrap(),
+        );
+    }
+
+    #[test]
+    fn requires_changed() {
+        assert!(parse_response(br#"{"output": "done"}"#).is_err());
+    }
+
+    #[test]
+    fn rejects_non_json() {
+        assert!(parse_response(b"done").is_err());
+    }
+}
+
+mod serve {
+    use super::*;
+
+    fn request(version: u32, args: serde_json::Value) -> Vec<u8> {
+        let request = Request {
+            version,
+            plugin: "firewall".to_string(),
+            args,
+            check: true,
+        };
+        serde_json::to_vec(&request).unwrap()
+    }
+
+    #[test]
+    fn passes_request_to_handler_and_writes_response() {
+        let input = request(PROTOCOL_VERSION, serde_json::json!({"port": 443}));
+        let mut output = Vec::new();
+        _serve(input.as_slice(), &mut output, |request| {
+            assert!(request.check);
+            let port: u16 = request.args["port"].as_u64().unwrap() as u16;
+            Ok(Response::changed(format!("Opened port {port}")))
+        })
+        .unwrap();
+        assert_eq!(
+            Response::changed("Opened port 443"),
+            parse_response(&output).unwrap(),
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_fields() {
+        let input = br#"{"version":1,"plugin":"firewall","args":null,"check":false,"new":1}"#;
+        let mut output = Vec::new();
+        _serve(&input[..], &mut output, |_| Ok(Response::unchanged())).unwrap();
+        assert_eq!(Response::unchanged(), parse_response(&output).unwrap());
+    }
+
+    #[test]
+    fn rejects_newer_contract() {
+        let input = request(PROTOCOL_VERSION + 1, serde_json::Value::Null);
+        let result = _serve(
+            input.as_slice(),
+            Vec::new(),
+            |_| -> anyhow::Result<Response> {
+                panic!("handler should not run");
+            },
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn returns_handler_error() {
+        let input = request(PROTOCOL_VERSION, serde_json::Value::Null);
+        let mut output = Vec::new();
+        let result = _serve(input.as_slice(), &mut output, |_| {
+            anyhow::bail!("no firewall")
+        });
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+}
+
+mod args_as {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Args {
+        port: u16,
+    }
+
+    fn request(args: serde_json::Value) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            plugin: "firewall".to_string(),
+            args,
+            check: false,
+        }
+    }
+
+    #[test]
+    fn deserializes_args() {
+        let args: Args = request(serde_json::json!({"port": 443})).args_as().unwrap();
+        assert_eq!(Args { port: 443 }, args);
+    }
+
+    #[test]
+    fn names_plugin_on_mismatch() {
+        let error = request(serde_json::json!({"port": "https"}))
+            .args_as::<Args>()
+            .unwrap_err();
+        assert!(error.to_string().contains("firewall"));
+    }
+}
+
+#[test]
+fn request_matches_contract() {
+    let request = Request {
+        version: PROTOCOL_VERSION,
+        plugin: "firewall".to_string(),
+        args: serde_json::json!({"port": 443}),
+        check: false,
+    };
+    assert_eq!(
+        r#"{"version":1,"plugin":"firewall","args":{"port":443},"check":false}"#,
+        serde_json::to_string(&request).unwrap(),
+    );
+}
+
+#[test]
+fn refuses_missing_plugin() {
+    let error = plugin_path("sira-missing-plugin").unwrap_err();
+    assert!(error.to_string().contains("is not installed"));
+}
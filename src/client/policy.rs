@@ -0,0 +1,205 @@
+//! Per-host policies that restrict which actions each controller key may run.
+//!
+//! A managed node may install a policy file at `/etc/sira/policy.yaml`. If present, `sira-client`
+//! looks up the principal that signed each action (i.e. the first field of the matching line in
+//! the action allowed signers file) and refuses to run any action that the principal's rules do
+//! not permit. Principals that the policy does not mention may not run any actions at all.
+//!
+//! If the policy file is absent, every key in the action allowed signers file may run any action.
+//!
+//! # Format
+//!
+//! The policy file maps each principal to a list of rules. An action is permitted if any of its
+//! principal's rules permits it:
+//!
+//! ```yaml
+//! # Trusted administrators may run anything.
+//! sira:
+//!   - any
+//!
+//! # The deployment key may only upload to /srv/app and restart app.service.
+//! deploy:
+//!   - upload: /srv/app/*
+//!   - command: systemctl restart app.service
+//! ```
+//!
+//! Each rule matches one type of [Action] against a pattern. A pattern matches a value exactly,
+//! unless it ends with `*`, in which case it matches any value that starts with the rest of the
+//! pattern. The rules are:
+//!
+//! - `any`: permits every action. This is the only rule that permits an [Action::ControllerKey],
+//!   since that action changes which control nodes the managed node trusts.
+//! - `command: <pattern>`: permits an [Action::Command] if the pattern matches each of its commands.
+//! - `custom: <pattern>`: permits an [Action::Custom] if the pattern matches its plugin's name.
+//! - `kernel_module: <pattern>`: permits an [Action::KernelModule] if the pattern matches the
+//!   module's name.
+//! - `line_in_file: <pattern>`: permits an [Action::LineInFile] if the pattern matches its path.
+//! - `script: <pattern>`: permits an [Action::Script] if the pattern matches the user it runs as.
+//! - `swap: <pattern>`: permits an [Action::Swap] if the pattern matches the swap space's path.
+//! - `upload: <pattern>`: permits an [Action::Upload] if the pattern matches its destination.
+//!
+//! Paths that contain `..` never match, so `/srv/app/*` cannot be used to escape `/srv/app`.
+
+use crate::config;
+use crate::core::action::{ClientError, ErrorCode};
+use crate::core::Action;
+use anyhow::Context;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The name of the policy file within Sira's configuration directory.
+pub const POLICY_FILE: &str = "policy.yaml";
+
+/// A per-host policy: a list of [Rule]s for each principal.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// Maps each principal to the rules that govern it.
+    pub principals: IndexMap<String, Vec<Rule>>,
+}
+
+/// A rule that permits a class of [Action]s. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// Permits every action.
+    Any,
+
+    /// Permits [Action::Command] if the pattern matches each of its commands.
+    Command(String),
+
+    /// Permits [Action::Custom] if the pattern matches its plugin's name.
+    Custom(String),
+
+    /// Permits [Action::KernelModule] if the pattern matches the module's name.
+    KernelModule(String),
+
+    /// Permits [Action::LineInFile] if the pattern matches its path.
+    LineInFile(String),
+
+    /// Permits [Action::Script] if the pattern matches its user.
+    Script(String),
+
+    /// Permits [Action::Swap] if the pattern matches its path.
+    Swap(String),
+
+    /// Permits [Action::Upload] if the pattern matches its destination.
+    Upload(String),
+}
+
+impl Policy {
+    /// Parses a policy from YAML.
+    pub fn from_yaml(yaml: &str) -> anyhow::Result<Self> {
+        let principals = serde_yaml::with::singleton_map_recursive::deserialize(
+            serde_yaml::Deserializer::from_str(yaml),
+        )?;
+        Ok(Policy { principals })
+    }
+
+    /// Loads the installed policy file, if any.
+    ///
+    /// Returns [None] if the policy file is not installed.
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        let path = policy_path();
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+
+        let yaml = fs::read_to_string(&path)
+            .with_context(|| format!("could not read policy file: {}", path.display()))?;
+        let policy = Policy::from_yaml(&yaml)
+            .with_context(|| format!("could not parse policy file: {}", path.display()))?;
+        Ok(Some(policy))
+    }
+
+    /// Returns an error unless one of `principal`'s rules permits `action`.
+    pub fn check(&self, principal: &str, action: &Action) -> anyhow::Result<()> {
+        let Some(rules) = self.principals.get(principal) else {
+            return Err(ClientError::new(
+                ErrorCode::Unauthorized,
+                format!("policy does not permit principal \"{principal}\" to run any actions"),
+            )
+            .into());
+        };
+
+        let permitted = match action {
+            // Every command must be permitted, though not necessarily by the same rule.
+            Action::Command(commands) => commands
+                .iter()
+                .all(|command| rules.iter().any(|rule| rule.permits_command(command))),
+            action => rules.iter().any(|rule| rule.permits(action)),
+        };
+
+        if !permitted {
+            return Err(ClientError::new(
+                ErrorCode::Unauthorized,
+                format!(
+                    "policy does not permit principal \"{principal}\" to run this action:\n{}",
+                    serde_yaml::to_string(action)?,
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    /// Returns whether this rule permits `action`.
+    pub fn permits(&self, action: &Action) -> bool {
+        match (self, action) {
+            (Rule::Any, _) => true,
+            (Rule::Command(_), Action::Command(commands)) => {
+                commands.iter().all(|command| self.permits_command(command))
+            }
+            (Rule::Custom(pattern), Action::Custom { plugin, .. }) => matches(pattern, plugin),
+            (Rule::KernelModule(pattern), Action::KernelModule { name, .. }) => {
+                matches(pattern, name)
+            }
+            (Rule::LineInFile(pattern), Action::LineInFile { path, .. }) => {
+                matches_path(pattern, path)
+            }
+            (Rule::Script(pattern), Action::Script { user, .. }) => matches(
+                pattern,
+                user.as_deref().unwrap_or(Action::DEFAULT_USER_AND_GROUP),
+            ),
+            (Rule::Swap(pattern), Action::Swap { path, .. }) => matches_path(pattern, path),
+            (Rule::Upload(pattern), Action::Upload { to, .. }) => matches_path(pattern, to),
+            _ => false,
+        }
+    }
+
+    /// Returns whether this rule permits a single command from an [Action::Command].
+    fn permits_command(&self, command: &str) -> bool {
+        match self {
+            Rule::Any => true,
+            Rule::Command(pattern) => matches(pattern, command),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the path to the policy file.
+pub fn policy_path() -> PathBuf {
+    config::config_dir().join(POLICY_FILE)
+}
+
+/// Matches `value` against `pattern`, which may end with a `*` wildcard.
+fn matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => value == pattern,
+    }
+}
+
+/// Same as [matches], but never matches a path that contains a `..` component.
+fn matches_path(pattern: &str, path: &str) -> bool {
+    let escapes = Path::new(path)
+        .components()
+        .any(|component| component == Component::ParentDir);
+    !escapes && matches(pattern, path)
+}
+
+#[cfg(test)]
+mod test;
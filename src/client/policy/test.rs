@@ -0,0 +1,244 @@
+use super::*;
+
+const POLICY: &str = "\
+sira:
+  - any
+deploy:
+  - upload: /srv/app/*
+  - command: systemctl restart app.service
+  - command: systemctl status *
+  - line_in_file: /etc/app.conf
+  - script: app
+  - custom: firewall
+  - kernel_module: nf_*
+  - swap: /swap*
+";
+
+fn policy() -> Policy {
+    Policy::from_yaml(POLICY).unwrap()
+}
+
+fn command(commands: &[&str]) -> Action {
+    Action::Command(commands.iter().map(|c| c.to_string()).collect())
+}
+
+fn upload(to: &str) -> Action {
+    Action::Upload {
+        from: "app.tar".to_string(),
+        to: to.to_string(),
+        user: "app".to_string(),
+        group: "app".to_string(),
+        permissions: None,
+        overwrite: true,
+        encrypt: false,
+        seuser: None,
+        serole: None,
+        setype: None,
+        acl: vec![],
+        transfer_permissions: None,
+        xattrs: Default::default(),
+        capabilities: None,
+    }
+}
+
+mod from_yaml {
+    use super::*;
+
+    #[test]
+    fn works() {
+        let policy = policy();
+        assert_eq!(vec![Rule::Any], policy.principals["sira"]);
+        assert_eq!(
+            vec![
+                Rule::Upload("/srv/app/*".to_string()),
+                Rule::Command("systemctl restart app.service".to_string()),
+                Rule::Command("systemctl status *".to_string()),
+                Rule::LineInFile("/etc/app.conf".to_string()),
+                Rule::Script("app".to_string()),
+                Rule::Custom("firewall".to_string()),
+                Rule::KernelModule("nf_*".to_string()),
+                Rule::Swap("/swap*".to_string()),
+            ],
+            policy.principals["deploy"],
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_rule() {
+        assert!(Policy::from_yaml("sira:\n  - reboot: now\n").is_err());
+    }
+}
+
+mod load {
+    use super::*;
+
+    #[test]
+    fn returns_none_if_not_installed() {
+        assert!(!policy_path().exists());
+        assert_eq!(None, Policy::load().unwrap());
+    }
+}
+
+mod check {
+    use super::*;
+
+    #[test]
+    fn any_permits_everything() {
+        let policy = policy();
+        policy.check("sira", &command(&["rm -rf /"])).unwrap();
+        policy.check("sira", &upload("/etc/shadow")).unwrap();
+    }
+
+    #[test]
+    fn only_any_permits_controller_key() {
+        let policy = policy();
+        let action = Action::ControllerKey {
+            login_key: Some("ssh-ed25519 AAAA deploy".to_string()),
+            action_key: None,
+            remove: false,
+        };
+        policy.check("sira", &action).unwrap();
+        assert!(policy.check("deploy", &action).is_err());
+    }
+
+    #[test]
+    fn matches_custom_plugin() {
+        let policy = policy();
+        let custom = |plugin: &str| Action::Custom {
+            plugin: plugin.to_string(),
+            args: serde_json::Value::Null,
+        };
+        policy.check("deploy", &custom("firewall")).unwrap();
+        assert!(policy.check("deploy", &custom("users")).is_err());
+    }
+
+    #[test]
+    fn matches_kernel_module_name() {
+        let policy = policy();
+        let kernel_module = |name: &str| Action::KernelModule {
+            name: name.to_string(),
+            options: vec![],
+            persist: true,
+            remove: false,
+        };
+        policy
+            .check("deploy", &kernel_module("nf_conntrack"))
+            .unwrap();
+        assert!(policy.check("deploy", &kernel_module("dummy")).is_err());
+    }
+
+    #[test]
+    fn matches_swap_path() {
+        let policy = policy();
+        let swap = |path: &str| Action::Swap {
+            path: path.to_string(),
+            size: Some("2G".to_string()),
+            persist: true,
+            remove: false,
+        };
+        policy.check("deploy", &swap("/swapfile")).unwrap();
+        assert!(policy
+            .check("deploy", &swap("/swap/../etc/passwd"))
+            .is_err());
+        assert!(policy.check("deploy", &swap("/dev/sda2")).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_principal() {
+        assert!(policy().check("intruder", &command(&["true"])).is_err());
+    }
+
+    #[test]
+    fn matches_exact_command() {
+        let policy = policy();
+        policy
+            .check("deploy", &command(&["systemctl restart app.service"]))
+            .unwrap();
+        assert!(policy
+            .check("deploy", &command(&["systemctl restart sshd.service"]))
+            .is_err());
+        assert!(policy
+            .check("deploy", &command(&["systemctl restart app.service --now"]))
+            .is_err());
+    }
+
+    #[test]
+    fn matches_command_prefix() {
+        policy()
+            .check("deploy", &command(&["systemctl status sshd.service"]))
+            .unwrap();
+    }
+
+    #[test]
+    fn requires_every_command_to_be_permitted() {
+        let policy = policy();
+        policy
+            .check(
+                "deploy",
+                &command(&["systemctl restart app.service", "systemctl status app"]),
+            )
+            .unwrap();
+        assert!(policy
+            .check(
+                "deploy",
+                &command(&["systemctl restart app.service", "reboot"]),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn matches_upload_destination() {
+        let policy = policy();
+        policy.check("deploy", &upload("/srv/app/app.tar")).unwrap();
+        assert!(policy
+            .check("deploy", &upload("/srv/other/app.tar"))
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_parent_directory_escape() {
+        assert!(policy()
+            .check("deploy", &upload("/srv/app/../../etc/shadow"))
+            .is_err());
+    }
+
+    #[test]
+    fn matches_line_in_file_path() {
+        let policy = policy();
+        let mut action = Action::LineInFile {
+            path: "/etc/app.conf".to_string(),
+            line: "debug = false".to_string(),
+            pattern: None,
+            after: None,
+            indent: false,
+        };
+        policy.check("deploy", &action).unwrap();
+
+        if let Action::LineInFile { path, .. } = &mut action {
+            *path = "/etc/sudoers".to_string();
+        }
+        assert!(policy.check("deploy", &action).is_err());
+    }
+
+    #[test]
+    fn matches_script_user() {
+        let policy = policy();
+        let mut action = Action::Script {
+            name: "Migrate".to_string(),
+            user: Some("app".to_string()),
+            contents: "#!/bin/sh\n".to_string(),
+        };
+        policy.check("deploy", &action).unwrap();
+
+        if let Action::Script { user, .. } = &mut action {
+            *user = Some("root".to_string());
+        }
+        assert!(policy.check("deploy", &action).is_err());
+
+        // A script without a user runs as root.
+        if let Action::Script { user, .. } = &mut action {
+            *user = None;
+        }
+        assert!(policy.check("deploy", &action).is_err());
+    }
+}
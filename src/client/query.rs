@@ -0,0 +1,395 @@
+//! Answers cheap questions about a managed node's current state, e.g. whether a package is
+//! installed, so that the control node can decide whether heavier actions are needed.
+//!
+//! `sira-client query` answers one [Query] and prints the [Answer] as JSON on stdout, e.g.:
+//!
+//! ```text
+//! $ sira-client query file /etc/hosts
+//! {"query":"file","path":"/etc/hosts","exists":true,"kind":"file","size":186,"mode":420,"uid":0,
+//! "gid":0,"sha256":"..."}
+//! $ sira-client query package git
+//! {"query":"package","name":"git","installed":true,"version":"1:2.39.2-1.1"}
+//! $ sira-client query service ssh
+//! {"query":"service","name":"ssh","active":true,"state":"active"}
+//! ```
+//!
+//! Packages and services are looked up with the local system's own tools:
+//!
+//! | System  | Packages     | Services                |
+//! |---------|--------------|-------------------------|
+//! | Linux   | `dpkg-query` | `systemctl is-active`   |
+//! | macOS   | unsupported  | `launchctl print`       |
+//! | FreeBSD | `pkg query`  | `service <name> status` |
+//! | OpenBSD | `pkg_info`   | `rcctl check`           |
+//!
+//! On macOS, services are launchd jobs in the system domain, named by label, e.g.
+//! `sira-client query service com.openssh.sshd`.
+//!
+//! Queries change nothing, so, like [facts](super::facts), they need no signature. To keep them
+//! from revealing the contents of secret files, a file's checksum is only reported if the Sira
+//! user could read the file itself.
+
+use crate::crypto;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::env::consts::OS;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::process::Command;
+
+/// The argument that tells `sira-client` to answer a query instead of running an action.
+pub const QUERY_COMMAND: &str = "query";
+
+/// A question about a managed node's current state. Please see the
+/// [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    /// Does a file exist, and if so, what are its type, size, owner, mode, and checksum?
+    File { path: String },
+
+    /// Is a package installed, according to the package manager, and if so, which version?
+    Package { name: String },
+
+    /// Is a service active, according to the service manager?
+    Service { name: String },
+}
+
+/// The answer to a [Query].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "query", rename_all = "lowercase")]
+pub enum Answer {
+    File(FileState),
+    Package(PackageState),
+    Service(ServiceState),
+}
+
+/// The state of a file, without following symbolic links.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    /// The path that was queried.
+    pub path: String,
+
+    /// Whether anything exists at the path. If not, every other field is empty.
+    pub exists: bool,
+
+    /// One of `file`, `directory`, `symlink`, or `other`.
+    pub kind: Option<String>,
+
+    /// The size in bytes.
+    pub size: Option<u64>,
+
+    /// The permission bits, e.g. 420 (i.e. 0o644).
+    pub mode: Option<u32>,
+
+    /// The owner's user ID.
+    pub uid: Option<u32>,
+
+    /// The owner's group ID.
+    pub gid: Option<u32>,
+
+    /// The SHA-256 checksum of a regular file's contents, if the Sira user could read it.
+    pub sha256: Option<String>,
+}
+
+/// Whether a package is installed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageState {
+    /// The package that was queried.
+    pub name: String,
+
+    /// Whether the package is fully installed.
+    pub installed: bool,
+
+    /// The installed version, if any.
+    pub version: Option<String>,
+}
+
+/// Whether a service is active.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceState {
+    /// The service that was queried.
+    pub name: String,
+
+    /// Whether the service is active, i.e. running.
+    pub active: bool,
+
+    /// The service's state as reported by `systemctl is-active`, e.g. `active` or `failed`. On
+    /// macOS, this is launchd's state for the job, e.g. `running` or `not running`, or `unloaded`
+    /// if launchd has no such job. On the BSDs, which only say whether a service is running, this
+    /// is either `running` or `stopped`.
+    pub state: String,
+}
+
+impl Query {
+    /// Parses a query from the arguments that follow [QUERY_COMMAND], e.g. `["package", "git"]`.
+    pub fn from_args(args: &[String]) -> anyhow::Result<Self> {
+        let [kind, subject] = args else {
+            bail!("expected a query kind (file, package, or service) and one argument");
+        };
+        if subject.is_empty() || subject.starts_with('-') {
+            bail!("invalid query argument: {subject:?}");
+        }
+        let subject = subject.to_string();
+        match kind.as_str() {
+            "file" => Ok(Query::File { path: subject }),
+            "package" => Ok(Query::Package { name: subject }),
+            "service" => Ok(Query::Service { name: subject }),
+            _ => bail!("unknown query kind {kind:?}; expected file, package, or service"),
+        }
+    }
+
+    /// Returns the arguments that ask `sira-client` this query, starting with [QUERY_COMMAND].
+    pub fn args(&self) -> [&str; 3] {
+        match self {
+            Query::File { path } => [QUERY_COMMAND, "file", path],
+            Query::Package { name } => [QUERY_COMMAND, "package", name],
+            Query::Service { name } => [QUERY_COMMAND, "service", name],
+        }
+    }
+
+    /// Answers this query about the local system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the answer can't be determined, e.g. because `dpkg-query` isn't
+    /// installed or because packages can't be queried on macOS. A missing file, package, or
+    /// service is not an error.
+    pub fn answer(&self) -> anyhow::Result<Answer> {
+        match self {
+            Query::File { path } => file_state(path).map(Answer::File),
+            Query::Package { name } => package_state(name).map(Answer::Package),
+            Query::Service { name } => service_state(name).map(Answer::Service),
+        }
+    }
+}
+
+impl Answer {
+    /// Returns this answer as one line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Answer should always serialize to JSON")
+    }
+
+    /// Parses an answer from JSON, e.g. as printed by `sira-client query`.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        serde_json::from_str(json).context("could not parse query answer")
+    }
+}
+
+/// Returns the state of the file at `path`.
+fn file_state(path: &str) -> anyhow::Result<FileState> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Ok(FileState {
+                path: path.to_string(),
+                ..Default::default()
+            })
+        }
+        Err(e) => return Err(e).with_context(|| format!("could not stat {path}")),
+    };
+
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_file() {
+        "file"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "symlink"
+    } else {
+        "other"
+    };
+
+    // sudo sets SUDO_UID to the Sira user's ID.
+    let sira_uid = env::var("SUDO_UID").ok().and_then(|uid| uid.parse().ok());
+    let readable = readable_by(metadata.mode(), metadata.uid(), sira_uid);
+    let sha256 = match file_type.is_file() && readable {
+        true => Some(crypto::sha256_file(path)?),
+        false => None,
+    };
+
+    Ok(FileState {
+        path: path.to_string(),
+        exists: true,
+        kind: Some(kind.to_string()),
+        size: Some(metadata.len()),
+        mode: Some(metadata.mode() & 0o7777),
+        uid: Some(metadata.uid()),
+        gid: Some(metadata.gid()),
+        sha256,
+    })
+}
+
+/// Returns whether a file with the given `mode` and owner `file_uid` is certainly readable by the
+/// user `uid`, i.e. because anyone can read it or because `uid` owns it and can read it.
+///
+/// Group permissions are ignored, so this may return false for a file that `uid` could read.
+pub(crate) fn readable_by(mode: u32, file_uid: u32, uid: Option<u32>) -> bool {
+    mode & 0o004 != 0 || (uid == Some(file_uid) && mode & 0o400 != 0)
+}
+
+/// Returns whether the package `name` is installed, according to the local package manager.
+fn package_state(name: &str) -> anyhow::Result<PackageState> {
+    let (program, args) = match OS {
+        "macos" => bail!("package queries aren't supported on macOS"),
+        "freebsd" => (
+            "pkg",
+            vec!["query".to_string(), "%v".to_string(), name.to_string()],
+        ),
+        "openbsd" => ("pkg_info", vec!["-e".to_string(), format!("{name}-*")]),
+        _ => return dpkg_package_state(name),
+    };
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("could not run {program}"))?;
+    let success = output.status.success();
+    let output = String::from_utf8_lossy(&output.stdout);
+    Ok(match program {
+        "pkg" => parse_pkg_query(name, success, &output),
+        _ => parse_pkg_info(name, success, &output),
+    })
+}
+
+/// Returns whether the package `name` is installed, according to `dpkg-query`.
+fn dpkg_package_state(name: &str) -> anyhow::Result<PackageState> {
+    let output = Command::new("dpkg-query")
+        .args([
+            "--show",
+            "--showformat=${db:Status-Status}\\t${Version}",
+            name,
+        ])
+        .output()
+        .context("could not run dpkg-query")?;
+    Ok(parse_dpkg_query(
+        name,
+        output.status.success(),
+        &String::from_utf8_lossy(&output.stdout),
+    ))
+}
+
+/// Parses the output of `dpkg-query --show --showformat='${db:Status-Status}\t${Version}'`, which
+/// exits with an error if the package is unknown.
+pub(crate) fn parse_dpkg_query(name: &str, success: bool, output: &str) -> PackageState {
+    let installed = |(status, version): (&str, &str)| {
+        (status == "installed").then(|| version.trim().to_string())
+    };
+    let version = match success {
+        true => output.split_once('\t').and_then(installed),
+        false => None,
+    };
+    PackageState {
+        name: name.to_string(),
+        installed: version.is_some(),
+        version,
+    }
+}
+
+/// Returns whether the service `name` is active, according to the local service manager.
+fn service_state(name: &str) -> anyhow::Result<ServiceState> {
+    let (program, args) = match OS {
+        "macos" => (
+            "launchctl",
+            vec!["print".to_string(), format!("system/{name}")],
+        ),
+        "freebsd" => ("service", vec![name.to_string(), "status".to_string()]),
+        "openbsd" => ("rcctl", vec!["check".to_string(), name.to_string()]),
+        _ => ("systemctl", vec!["is-active".to_string(), name.to_string()]),
+    };
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("could not run {program}"))?;
+    let success = output.status.success();
+    let output = String::from_utf8_lossy(&output.stdout);
+    match program {
+        "launchctl" => parse_launchctl_print(name, success, &output),
+        "systemctl" => parse_is_active(name, &output),
+        _ => Ok(rc_state(name, success)),
+    }
+}
+
+/// Parses the output of `systemctl is-active`.
+pub(crate) fn parse_is_active(name: &str, output: &str) -> anyhow::Result<ServiceState> {
+    let state = output.trim();
+    if state.is_empty() {
+        bail!("systemctl reported no state for {name}");
+    }
+    Ok(ServiceState {
+        name: name.to_string(),
+        active: state == "active",
+        state: state.to_string(),
+    })
+}
+
+/// Parses the output of `launchctl print system/<name>`, which exits with an error if launchd has
+/// no such job.
+pub(crate) fn parse_launchctl_print(
+    name: &str,
+    success: bool,
+    output: &str,
+) -> anyhow::Result<ServiceState> {
+    let state = match success {
+        // The job's own state comes first, before those of its endpoints and subsystems, e.g.
+        //     state = running
+        true => output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("state = "))
+            .with_context(|| format!("launchctl reported no state for {name}"))?,
+        false => "unloaded",
+    };
+    Ok(ServiceState {
+        name: name.to_string(),
+        active: state == "running",
+        state: state.to_string(),
+    })
+}
+
+/// Parses the output of `pkg query %v <name>` on FreeBSD, which exits with an error if the package
+/// isn't installed.
+pub(crate) fn parse_pkg_query(name: &str, success: bool, output: &str) -> PackageState {
+    let version = output.trim();
+    let version = (success && !version.is_empty()).then(|| version.to_string());
+    PackageState {
+        name: name.to_string(),
+        installed: version.is_some(),
+        version,
+    }
+}
+
+/// Parses the output of `pkg_info -e '<name>-*'` on OpenBSD, e.g. `inst:git-2.42.0`, which exits
+/// with an error if the package isn't installed.
+pub(crate) fn parse_pkg_info(name: &str, success: bool, output: &str) -> PackageState {
+    // Versions start with a digit, which tells git-2.42.0 apart from git-lfs-3.4.0.
+    let version = output.lines().find_map(|line| {
+        let version = line.trim().strip_prefix("inst:")?.strip_prefix(name)?;
+        let version = version.strip_prefix('-')?;
+        version
+            .starts_with(|c: char| c.is_ascii_digit())
+            .then(|| version.to_string())
+    });
+    let version = version.filter(|_| success);
+    PackageState {
+        name: name.to_string(),
+        installed: version.is_some(),
+        version,
+    }
+}
+
+/// Returns the state of the service `name` on the BSDs, whose `service <name> status` and `rcctl
+/// check` only say, by exiting successfully or not, whether it's running.
+pub(crate) fn rc_state(name: &str, running: bool) -> ServiceState {
+    let state = match running {
+        true => "running",
+        false => "stopped",
+    };
+    ServiceState {
+        name: name.to_string(),
+        active: running,
+        state: state.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test;
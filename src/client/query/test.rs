@@ -0,0 +1,327 @@
+use super::*;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+mod from_args {
+    use super::*;
+
+    #[test]
+    fn parses_each_kind() {
+        assert_eq!(
+            Query::File {
+                path: "/etc/hosts".to_string()
+            },
+            Query::from_args(&args(&["file", "/etc/hosts"])).unwrap(),
+        );
+        assert_eq!(
+            Query::Package {
+                name: "git".to_string()
+            },
+            Query::from_args(&args(&["package", "git"])).unwrap(),
+        );
+        assert_eq!(
+            Query::Service {
+                name: "ssh".to_string()
+            },
+            Query::from_args(&args(&["service", "ssh"])).unwrap(),
+        );
+    }
+
+    #[test]
+    fn round_trips_args() {
+        let query = Query::Package {
+            name: "git".to_string(),
+        };
+        let [command, rest @ ..] = query.args();
+        assert_eq!(QUERY_COMMAND, command);
+        assert_eq!(query, Query::from_args(&args(&rest)).unwrap());
+    }
+
+    #[test]
+    fn rejects_unknown_kind() {
+        assert!(Query::from_args(&args(&["user", "root"])).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_number_of_arguments() {
+        assert!(Query::from_args(&args(&["file"])).is_err());
+        assert!(Query::from_args(&args(&["file", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn rejects_options() {
+        assert!(Query::from_args(&args(&["package", "--help"])).is_err());
+        assert!(Query::from_args(&args(&["service", ""])).is_err());
+    }
+}
+
+mod answer {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn reports_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").to_string_lossy().to_string();
+        let query = Query::File { path: path.clone() };
+        assert_eq!(
+            Answer::File(FileState {
+                path,
+                ..Default::default()
+            }),
+            query.answer().unwrap(),
+        );
+    }
+
+    #[test]
+    fn reports_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        let Answer::File(state) = Query::File { path }.answer().unwrap() else {
+            panic!("expected a file answer");
+        };
+        assert!(state.exists);
+        assert_eq!(Some("directory".to_string()), state.kind);
+        assert_eq!(None, state.sha256);
+    }
+
+    #[test]
+    fn reports_readable_file_with_checksum() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello\n").unwrap();
+        fs::set_permissions(
+            file.path(),
+            std::os::unix::fs::PermissionsExt::from_mode(0o644),
+        )
+        .unwrap();
+        let path = file.path().to_string_lossy().to_string();
+        let Answer::File(state) = Query::File { path }.answer().unwrap() else {
+            panic!("expected a file answer");
+        };
+        assert!(state.exists);
+        assert_eq!(Some("file".to_string()), state.kind);
+        assert_eq!(Some(6), state.size);
+        assert_eq!(Some(0o644), state.mode);
+        assert_eq!(
+            Some("5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03".to_string()),
+            state.sha256,
+        );
+    }
+}
+
+mod readable_by {
+    use super::*;
+
+    #[test]
+    fn allows_world_readable_files() {
+        assert!(readable_by(0o644, 0, None));
+    }
+
+    #[test]
+    fn allows_owner_readable_files_for_owner() {
+        assert!(readable_by(0o600, 1000, Some(1000)));
+        assert!(!readable_by(0o200, 1000, Some(1000)));
+    }
+
+    #[test]
+    fn denies_private_files_of_others() {
+        assert!(!readable_by(0o640, 0, Some(1000)));
+        assert!(!readable_by(0o600, 0, None));
+    }
+}
+
+mod parse_dpkg_query {
+    use super::*;
+
+    #[test]
+    fn reports_installed_package() {
+        assert_eq!(
+            PackageState {
+                name: "git".to_string(),
+                installed: true,
+                version: Some("1:2.39.2-1.1".to_string()),
+            },
+            parse_dpkg_query("git", true, "installed\t1:2.39.2-1.1"),
+        );
+    }
+
+    #[test]
+    fn reports_removed_package_as_not_installed() {
+        let state = parse_dpkg_query("git", true, "config-files\t1:2.39.2-1.1");
+        assert!(!state.installed);
+        assert_eq!(None, state.version);
+    }
+
+    #[test]
+    fn reports_unknown_package_as_not_installed() {
+        let state = parse_dpkg_query("nonesuch", false, "");
+        assert!(!state.installed);
+        assert_eq!(None, state.version);
+    }
+}
+
+mod parse_is_active {
+    use super::*;
+
+    #[test]
+    fn reports_active_service() {
+        assert_eq!(
+            ServiceState {
+                name: "ssh".to_string(),
+                active: true,
+                state: "active".to_string(),
+            },
+            parse_is_active("ssh", "active\n").unwrap(),
+        );
+    }
+
+    #[test]
+    fn reports_other_states_as_inactive() {
+        let state = parse_is_active("ssh", "failed\n").unwrap();
+        assert!(!state.active);
+        assert_eq!("failed", state.state);
+    }
+
+    #[test]
+    fn requires_a_state() {
+        assert!(parse_is_active("ssh", "").is_err());
+    }
+}
+
+mod parse_pkg_query {
+    use super::*;
+
+    #[test]
+    fn reports_installed_version() {
+        assert_eq!(
+            PackageState {
+                name: "git".to_string(),
+                installed: true,
+                version: Some("2.44.0_1".to_string()),
+            },
+            parse_pkg_query("git", true, "2.44.0_1\n"),
+        );
+    }
+
+    #[test]
+    fn reports_missing_package() {
+        let state = parse_pkg_query("git", false, "");
+        assert!(!state.installed);
+        assert_eq!(None, state.version);
+    }
+}
+
+mod parse_pkg_info {
+    use super::*;
+
+    #[test]
+    fn reports_installed_version() {
+        assert_eq!(
+            PackageState {
+                name: "git".to_string(),
+                installed: true,
+                version: Some("2.42.0".to_string()),
+            },
+            parse_pkg_info("git", true, "inst:git-2.42.0\n"),
+        );
+    }
+
+    #[test]
+    fn ignores_packages_whose_names_only_start_alike() {
+        assert!(!parse_pkg_info("git", true, "inst:gitea-1.21.1\n").installed);
+        assert!(!parse_pkg_info("git", true, "inst:git-lfs-3.4.0\n").installed);
+    }
+
+    #[test]
+    fn reports_missing_package() {
+        assert!(!parse_pkg_info("git", false, "").installed);
+    }
+}
+
+mod rc_state {
+    use super::*;
+
+    #[test]
+    fn reports_running_and_stopped_services() {
+        assert_eq!(
+            ServiceState {
+                name: "sshd".to_string(),
+                active: true,
+                state: "running".to_string(),
+            },
+            rc_state("sshd", true),
+        );
+        let state = rc_state("sshd", false);
+        assert!(!state.active);
+        assert_eq!("stopped", state.state);
+    }
+}
+
+mod parse_launchctl_print {
+    use super::*;
+
+    #[test]
+    fn reports_running_job() {
+        let output = "\
+system/com.openssh.sshd = {
+\tactive count = 1
+\tpath = /System/Library/LaunchDaemons/ssh.plist
+\ttype = LaunchDaemon
+\tstate = running
+
+\tprogram = /usr/libexec/sshd-keygen-wrapper
+\tendpoints = {
+\t\t\"com.openssh.sshd\" = {
+\t\t\tstate = active
+\t\t}
+\t}
+}
+";
+        assert_eq!(
+            ServiceState {
+                name: "com.openssh.sshd".to_string(),
+                active: true,
+                state: "running".to_string(),
+            },
+            parse_launchctl_print("com.openssh.sshd", true, output).unwrap(),
+        );
+    }
+
+    #[test]
+    fn reports_other_states_as_inactive() {
+        let output = "system/com.example.app = {\n\tstate = not running\n}\n";
+        let state = parse_launchctl_print("com.example.app", true, output).unwrap();
+        assert!(!state.active);
+        assert_eq!("not running", state.state);
+    }
+
+    #[test]
+    fn reports_unknown_job_as_unloaded() {
+        let state = parse_launchctl_print("com.example.missing", false, "").unwrap();
+        assert!(!state.active);
+        assert_eq!("unloaded", state.state);
+    }
+
+    #[test]
+    fn requires_a_state() {
+        assert!(parse_launchctl_print("com.example.app", true, "").is_err());
+    }
+}
+
+mod to_json {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_tag() {
+        let answer = Answer::Service(ServiceState {
+            name: "ssh".to_string(),
+            active: true,
+            state: "active".to_string(),
+        });
+        let json = answer.to_json();
+        assert!(json.starts_with(r#"{"query":"service","#));
+        assert_eq!(answer, Answer::from_json(&json).unwrap());
+    }
+}
@@ -0,0 +1,116 @@
+//! Applies the [Bundle]s that the control node queued on this managed node.
+//!
+//! Please see [crate::core::action::bundle] for an overview.
+
+use crate::core::action::bundle::QUEUE_DIR;
+use crate::core::action::{Bundle, FILE_TRANSFER_PATH};
+use anyhow::{bail, Context};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The suffix added to a bundle that failed to apply, so that it isn't retried.
+pub const FAILED_SUFFIX: &str = ".failed";
+
+/// Applies each bundle in [QUEUE_DIR], oldest first, by running `client` (i.e. `sira-client`
+/// itself) on each of its actions in turn, exactly as the control node would over SSH.
+///
+/// A bundle that applies completely is removed. If an action fails, the rest of its bundle is
+/// skipped, and the bundle is renamed with [FAILED_SUFFIX] so that it isn't retried. Later bundles
+/// still apply.
+///
+/// # Errors
+///
+/// Returns an error if [QUEUE_DIR] can't be read or if any bundle fails to apply.
+pub fn apply_queued(client: &Path) -> anyhow::Result<()> {
+    apply_queued_in(Path::new(QUEUE_DIR), client)
+}
+
+/// Same as [apply_queued], but for the bundles in `queue_dir`.
+pub(crate) fn apply_queued_in(queue_dir: &Path, client: &Path) -> anyhow::Result<()> {
+    let mut bundles = match fs::read_dir(queue_dir) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("could not read {}", queue_dir.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("could not read {}", queue_dir.display())),
+    };
+    bundles.retain(|path| path.extension() == Some(OsStr::new("yaml")));
+    bundles.sort();
+
+    let mut failed = Vec::new();
+    for path in bundles {
+        println!("Applying {}", path.display());
+        match apply_bundle(&path, client) {
+            Ok(()) => fs::remove_file(&path)
+                .with_context(|| format!("could not remove {}", path.display()))?,
+            Err(e) => {
+                eprintln!("Could not apply {}: {e:#}", path.display());
+                let mut renamed = path.clone().into_os_string();
+                renamed.push(FAILED_SUFFIX);
+                fs::rename(&path, &renamed)
+                    .with_context(|| format!("could not rename {}", path.display()))?;
+                failed.push(path);
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("{} queued bundles failed to apply", failed.len());
+    }
+    Ok(())
+}
+
+/// Applies the bundle at `path`, stopping at the first action that fails.
+fn apply_bundle(path: &Path, client: &Path) -> anyhow::Result<()> {
+    let yaml = fs::read_to_string(path).context("could not read bundle")?;
+    let bundle = Bundle::from_yaml(&yaml).context("could not parse bundle")?;
+
+    // Like the Sira user's home directory over SSH, this is where uploaded files arrive.
+    let work_dir = mktemp_dir()?;
+    let result = apply_actions(&bundle, &work_dir, client);
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+/// Runs `client` on each action in `bundle` from `work_dir`.
+fn apply_actions(bundle: &Bundle, work_dir: &Path, client: &Path) -> anyhow::Result<()> {
+    let count = bundle.actions.len();
+    for (i, queued) in bundle.actions.iter().enumerate() {
+        if let Some(contents) = queued.file_contents()? {
+            fs::write(work_dir.join(FILE_TRANSFER_PATH), contents)
+                .context("could not stage file to upload")?;
+        }
+
+        let mut command = Command::new(client);
+        command.arg(&queued.envelope).current_dir(work_dir);
+        if let Some(signature) = &queued.signature {
+            command.arg(signature);
+        }
+        let status = command
+            .status()
+            .with_context(|| format!("could not run {}", client.display()))?;
+        if !status.success() {
+            bail!("action {} of {count} failed", i + 1);
+        }
+    }
+    Ok(())
+}
+
+/// Creates a temporary directory that only the current user can access and returns its path.
+fn mktemp_dir() -> anyhow::Result<PathBuf> {
+    let output = Command::new("mktemp").arg("-d").output()?;
+    if !output.status.success() {
+        bail!(
+            "mktemp exited with error: {}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    let path = String::from_utf8(output.stdout).context("mktemp returned a non-UTF-8 path")?;
+    Ok(PathBuf::from(path.trim_end()))
+}
+
+#[cfg(test)]
+mod test;
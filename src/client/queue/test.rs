@@ -0,0 +1,71 @@
+use super::*;
+use crate::core::action::bundle::{encode_hex, QueuedAction};
+use std::fs;
+
+fn queued(envelope: &str) -> QueuedAction {
+    QueuedAction {
+        envelope: envelope.to_string(),
+        signature: None,
+        file: None,
+    }
+}
+
+fn write_bundle(dir: &Path, name: &str, actions: Vec<QueuedAction>) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, Bundle { actions }.to_yaml()).unwrap();
+    path
+}
+
+#[test]
+fn removes_applied_bundles() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_bundle(dir.path(), "1.yaml", vec![queued("a"), queued("b")]);
+    apply_queued_in(dir.path(), Path::new("true")).unwrap();
+    assert!(!path.exists());
+}
+
+#[test]
+fn keeps_failed_bundles_and_applies_the_rest() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // `test <envelope>` succeeds unless the envelope is empty.
+    let first = write_bundle(dir.path(), "1.yaml", vec![queued("")]);
+    let second = write_bundle(dir.path(), "2.yaml", vec![queued("b")]);
+    assert!(apply_queued_in(dir.path(), Path::new("test")).is_err());
+    assert!(!first.exists());
+    assert!(dir.path().join("1.yaml.failed").exists());
+    assert!(!second.exists());
+}
+
+#[test]
+fn ignores_other_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("1.yaml.failed");
+    fs::write(&path, "not a bundle").unwrap();
+    apply_queued_in(dir.path(), Path::new("false")).unwrap();
+    assert!(path.exists());
+}
+
+#[test]
+fn succeeds_without_queue_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    apply_queued_in(&dir.path().join("missing"), Path::new("false")).unwrap();
+}
+
+#[test]
+fn stages_uploaded_files() {
+    // `test -s <file>` succeeds only if the staged file exists and isn't empty.
+    let bundle = Bundle {
+        actions: vec![QueuedAction {
+            envelope: "-s".to_string(),
+            signature: Some(FILE_TRANSFER_PATH.to_string()),
+            file: Some(encode_hex(b"contents")),
+        }],
+    };
+    let work_dir = tempfile::tempdir().unwrap();
+    apply_actions(&bundle, work_dir.path(), Path::new("test")).unwrap();
+    assert_eq!(
+        b"contents".to_vec(),
+        fs::read(work_dir.path().join(FILE_TRANSFER_PATH)).unwrap(),
+    );
+}
@@ -0,0 +1,75 @@
+//! Sets the SELinux contexts of files that `sira-client` installs.
+//!
+//! A file that `sira-client` moves into place keeps the SELinux context of the temporary location
+//! it was uploaded to, e.g. `user_home_t`, which is usually wrong for its destination. On managed
+//! nodes with SELinux enabled, [apply] resets the context to the policy's default for the file's
+//! path and then applies any overrides that the action asked for.
+
+use super::run;
+use std::process::Command;
+
+/// Returns whether SELinux is enabled on this system, according to `selinuxenabled`.
+///
+/// If `selinuxenabled` isn't installed, SELinux is assumed to be disabled.
+pub fn enabled() -> bool {
+    Command::new("selinuxenabled")
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Returns the SELinux context of the file at `path`, e.g.
+/// `system_u:object_r:httpd_sys_content_t:s0`, or `None` if it can't be read.
+pub fn context(path: &str) -> Option<String> {
+    let output = Command::new("stat")
+        .args(["--format=%C", "--", path])
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => None,
+    }
+}
+
+/// Resets the SELinux context of the file at `path` to the policy's default with `restorecon`,
+/// and then overrides its user, role, and type with `chcon` where given.
+///
+/// # Errors
+///
+/// Returns an error if `restorecon` or `chcon` fails.
+pub fn apply(
+    path: &str,
+    seuser: Option<&str>,
+    serole: Option<&str>,
+    setype: Option<&str>,
+) -> anyhow::Result<()> {
+    run("restorecon", &["--", path])?;
+    if let Some(args) = chcon_args(path, seuser, serole, setype) {
+        run("chcon", &args)?;
+    }
+    Ok(())
+}
+
+/// Returns the arguments for `chcon` that override the given parts of the context of the file at
+/// `path`, or `None` if there is nothing to override.
+pub(crate) fn chcon_args(
+    path: &str,
+    seuser: Option<&str>,
+    serole: Option<&str>,
+    setype: Option<&str>,
+) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    for (flag, value) in [("--user", seuser), ("--role", serole), ("--type", setype)] {
+        if let Some(value) = value {
+            args.push(format!("{flag}={value}"));
+        }
+    }
+    if args.is_empty() {
+        return None;
+    }
+    args.push("--".to_string());
+    args.push(path.to_string());
+    Some(args)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,28 @@
+use super::*;
+
+mod chcon_args {
+    use super::*;
+
+    #[test]
+    fn overrides_given_parts() {
+        assert_eq!(
+            Some(vec![
+                "--user=system_u".to_string(),
+                "--type=httpd_sys_content_t".to_string(),
+                "--".to_string(),
+                "/srv/www/index.html".to_string(),
+            ]),
+            chcon_args(
+                "/srv/www/index.html",
+                Some("system_u"),
+                None,
+                Some("httpd_sys_content_t"),
+            ),
+        );
+    }
+
+    #[test]
+    fn returns_none_without_overrides() {
+        assert_eq!(None, chcon_args("/srv/www/index.html", None, None, None));
+    }
+}
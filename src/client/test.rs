@@ -21,6 +21,30 @@ mod mktemp {
     }
 }
 
+mod mktemp_in {
+    use super::*;
+
+    #[test]
+    fn creates_file_in_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_file, path) = mktemp_in(dir.path()).unwrap();
+        let path = Path::new(&path);
+        assert_eq!(Some(dir.path()), path.parent());
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with(".sira-"));
+        assert!(path.is_file());
+    }
+
+    #[test]
+    fn returns_error_for_missing_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(mktemp_in(dir.path().join("missing")).is_err());
+    }
+}
+
 mod run {
     use super::*;
 
@@ -82,15 +106,16 @@ mod run {
 mod whoami {
     use super::*;
 
+    // `users` would only list logged-in users, and CI runners and containers often have none.
     #[test]
-    fn users_contains_whoami() {
-        let users = {
-            let output = Command::new("users").output().unwrap();
+    fn matches_id() {
+        let user = {
+            let output = Command::new("id").arg("-un").output().unwrap();
             assert!(output.status.success());
             assert!(output.stderr.is_empty());
             String::from_utf8(output.stdout).unwrap()
         };
-        assert!(users.contains(whoami()));
+        assert_eq!(user.trim(), whoami());
     }
 
     #[test]
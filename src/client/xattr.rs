@@ -0,0 +1,81 @@
+//! Sets extended attributes and file capabilities on files that `sira-client` installs.
+
+use super::run;
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::process::Command;
+
+/// Sets each of `xattrs` on the file at `path` with `setfattr`, e.g. `user.origin: sira`.
+///
+/// # Errors
+///
+/// Returns an error if a name is invalid or if `setfattr` fails.
+pub fn set(path: &str, xattrs: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    for (name, value) in xattrs {
+        run("setfattr", &setfattr_args(path, name, value)?)?;
+    }
+    Ok(())
+}
+
+/// Gives the file at `path` the capabilities in `capabilities` with `setcap`, e.g.
+/// `cap_net_bind_service=+ep`.
+///
+/// # Errors
+///
+/// Returns an error if `capabilities` looks like an option or if `setcap` fails.
+pub fn set_capabilities(path: &str, capabilities: &str) -> anyhow::Result<()> {
+    // setcap doesn't accept `--`, so make sure that it can't mistake the capabilities for an option.
+    if capabilities.trim().is_empty() || capabilities.starts_with('-') {
+        bail!("invalid capabilities {capabilities:?}");
+    }
+    run("setcap", &[capabilities, path])
+}
+
+/// Returns the extended attributes of the file at `path`, including its capabilities, as dumped
+/// by `getfattr`, or `None` if they can't be read.
+///
+/// The header naming the file is left out, so that the attributes of different files can be
+/// compared. So is the SELinux context, since [super::selinux] manages it separately.
+pub fn get(path: &str) -> Option<String> {
+    let output = Command::new("getfattr")
+        .args(["--dump", "--match=-", "--absolute-names", "--", path])
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => Some(attributes_only(&String::from_utf8_lossy(&output.stdout))),
+        false => None,
+    }
+}
+
+// Removes the file name and SELinux context from a `getfattr --dump` listing.
+fn attributes_only(dump: &str) -> String {
+    dump.lines()
+        .filter(|line| !line.starts_with("# file: ") && !line.starts_with("security.selinux="))
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// Returns the arguments for `setfattr` that set the extended attribute `name` of the file at
+/// `path` to `value`.
+///
+/// `setfattr` decodes values that start with `0x` or `0s` and unquotes values in double quotes, so
+/// the value is always passed hex-encoded to set it exactly as written.
+pub(crate) fn setfattr_args(path: &str, name: &str, value: &str) -> anyhow::Result<Vec<String>> {
+    // Every extended attribute name belongs to a namespace, e.g. `user.` or `security.`.
+    if name.starts_with('-') || !name.contains('.') {
+        bail!("invalid extended attribute name {name:?}; please include its namespace, e.g. user.");
+    }
+    let mut args = vec![format!("--name={name}")];
+
+    // Without --value, setfattr sets an empty value.
+    if !value.is_empty() {
+        let hex: String = value.bytes().map(|byte| format!("{byte:02x}")).collect();
+        args.push(format!("--value=0x{hex}"));
+    }
+    args.push("--".to_string());
+    args.push(path.to_string());
+    Ok(args)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,63 @@
+use super::*;
+
+mod setfattr_args {
+    use super::*;
+
+    #[test]
+    fn hex_encodes_value() {
+        assert_eq!(
+            vec![
+                "--name=user.origin",
+                "--value=0x73697261",
+                "--",
+                "/usr/bin/app"
+            ],
+            setfattr_args("/usr/bin/app", "user.origin", "sira").unwrap(),
+        );
+    }
+
+    #[test]
+    fn omits_empty_value() {
+        assert_eq!(
+            vec!["--name=user.flag", "--", "/usr/bin/app"],
+            setfattr_args("/usr/bin/app", "user.flag", "").unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_names_without_namespace() {
+        assert!(setfattr_args("/usr/bin/app", "origin", "sira").is_err());
+    }
+
+    #[test]
+    fn rejects_names_that_look_like_options() {
+        assert!(setfattr_args("/usr/bin/app", "-x.origin", "sira").is_err());
+    }
+}
+
+mod set_capabilities {
+    use super::*;
+
+    #[test]
+    fn rejects_options() {
+        assert!(set_capabilities("/usr/bin/app", "-r").is_err());
+    }
+
+    #[test]
+    fn rejects_blank_capabilities() {
+        assert!(set_capabilities("/usr/bin/app", " ").is_err());
+    }
+}
+
+#[test]
+fn attributes_only_removes_file_name_and_context() {
+    let dump = "# file: /usr/bin/app\n\
+        security.capability=0sAQAAAgAEAAAAAAAAAAAAAAAAAAA=\n\
+        security.selinux=\"system_u:object_r:bin_t:s0\"\n\
+        user.origin=\"sira\"\n";
+    assert_eq!(
+        "security.capability=0sAQAAAgAEAAAAAAAAAAAAAAAAAAA=\n\
+        user.origin=\"sira\"\n",
+        attributes_only(dump),
+    );
+}
@@ -1,6 +1,75 @@
 //! Provides access to Sira's configuration files, e.g. `/etc/sira`.
+//!
+//! Defaults for `sira` on the control node live in [CONFIG_FILE], e.g. `/etc/sira/sira.toml`, and
+//! in the same file in the user's configuration directory, e.g. `~/.config/sira/sira.toml`, which
+//! takes precedence. Every setting is optional, e.g.:
+//!
+//! ```toml
+//! user = "sira"
+//! client_path = "/opt/sira/bin/sira-client"
+//! concurrency = 10
+//! pipeline = 4
+//! known_hosts = "/etc/sira/known_hosts"
+//! host_key_checking = "strict"
+//! login_key = "/home/alice/.ssh/sira"
+//! action_key = "/etc/sira/keys/action"
+//! json_log = "/var/log/sira/actions.jsonl"
+//! syslog = true
+//! facts_ttl = 3600
+//! inventory = ["./inventory/cmdb.sh"]
+//! pre_run = ["make -C files"]
+//! post_run = ["./scripts/collect-results.sh"]
+//! ```
+//!
+//! Connection settings can be overridden for individual hosts in a `hosts` table, keyed by the
+//! host's name as it appears in manifests, e.g.:
+//!
+//! ```toml
+//! [hosts.legacy1]
+//! port = 2222
+//! user = "admin"
+//! escalation = "doas"
+//! connect_timeout = 30
+//!
+//! [hosts.legacy1.ssh_options]
+//! ProxyJump = "bastion.example.com"
+//!
+//! [hosts.control]
+//! transport = "local"
+//! ```
+//!
+//! To keep environments apart, e.g. staging and production, bundle their settings into named
+//! profiles, and select one with `sira --profile <name>`:
+//!
+//! ```toml
+//! [profile.prod]
+//! login_key = "/home/alice/.ssh/sira-prod"
+//! action_key = "/etc/sira/keys/action-prod"
+//! concurrency = 2
+//! confirm = "all"
+//! ```
+//!
+//! A selected profile replaces the settings outside of profiles entirely, rather than adding to
+//! them, so that a setting meant for one environment never leaks into another. Please see
+//! [Config::select].
+//!
+//! Command-line flags take precedence over both files. Please see [Config] and [HostConfig] for
+//! every setting.
 
+use crate::run_plan::api::ApiConfig;
+use crate::run_plan::inventory::dns::DnsSource;
+use crate::run_plan::schedule::ScheduledRun;
+use crate::run_plan::wake;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The name of the file, in [config_dir] or [user_config_dir], that holds defaults for `sira`.
+pub const CONFIG_FILE: &str = "sira.toml";
 
 /// Returns a [PathBuf] to the directory where Sira's configuration should live.
 ///
@@ -22,6 +91,542 @@ pub fn config_dir() -> PathBuf {
     path
 }
 
+/// Returns the directory where the current user's configuration for Sira lives, i.e.
+/// `$XDG_CONFIG_HOME/sira`, or `~/.config/sira` if `XDG_CONFIG_HOME` is unset, or [None] if the
+/// home directory can't be determined.
+pub fn user_config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => home::home_dir()?.join(".config"),
+    };
+    Some(base.join("sira"))
+}
+
+/// Returns the directory where Sira caches data for the current user, i.e. `$XDG_CACHE_HOME/sira`,
+/// or `~/.cache/sira` if `XDG_CACHE_HOME` is unset, or [None] if the home directory can't be
+/// determined.
+pub fn user_cache_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => home::home_dir()?.join(".cache"),
+    };
+    Some(base.join("sira"))
+}
+
+/// How `sira` checks managed nodes' host keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyChecking {
+    /// Reject hosts whose keys aren't already known.
+    Strict,
+
+    /// Trust and remember the keys of hosts that aren't known yet, but reject changed keys.
+    AcceptNew,
+}
+
+/// Which actions require confirmation before they run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Confirmation {
+    /// Only the actions of tasks that ask for confirmation.
+    #[default]
+    Tasks,
+
+    /// Every action.
+    All,
+}
+
+/// What happens to the rest of a run when one host fails, e.g. because an action failed there or
+/// Sira couldn't reach it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnFailure {
+    /// The failed host stops, and every other host runs to completion.
+    #[default]
+    Continue,
+
+    /// Every host stops, e.g. for changes that must apply everywhere or nowhere. Hosts start no
+    /// further actions, and hosts still waiting for their turn never connect.
+    Abort,
+}
+
+impl FromStr for OnFailure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "continue" => Ok(OnFailure::Continue),
+            "abort" => Ok(OnFailure::Abort),
+            _ => bail!("unknown failure policy {s:?}; expected \"continue\" or \"abort\""),
+        }
+    }
+}
+
+/// How `sira-client` gains root privileges on a managed node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Escalation {
+    /// Run `sira-client` with `sudo`.
+    #[default]
+    Sudo,
+
+    /// Run `sira-client` with `doas`.
+    Doas,
+
+    /// Run `sira-client` directly, e.g. when logging in as root.
+    None,
+}
+
+impl Escalation {
+    /// Returns the program that runs `sira-client` with root privileges, if any.
+    pub fn program(self) -> Option<&'static str> {
+        match self {
+            Escalation::Sudo => Some("sudo"),
+            Escalation::Doas => Some("doas"),
+            Escalation::None => None,
+        }
+    }
+}
+
+/// How Sira reaches a managed node to run `sira-client` on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    /// Log in over SSH with OpenSSH.
+    #[default]
+    Ssh,
+
+    /// Run `sira-client` on the control node itself, without logging in, e.g. to manage the
+    /// control node along with the rest of the fleet. SSH settings have no effect.
+    Local,
+
+    /// Run `sira-client` in a running container on the control node with `docker exec`, e.g. to
+    /// test manifests against throwaway containers. The host's name is the container's name or ID,
+    /// and `user` picks the user to run as in the container. Other SSH settings have no effect,
+    /// and `escalation` defaults to [Escalation::None].
+    Docker,
+}
+
+/// How to connect to a managed node and run `sira-client` on it.
+///
+/// Each setting left unset falls back to the global setting in [Config], if any, and then to
+/// Sira's built-in default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    /// How to reach the host. Defaults to [Transport::Ssh].
+    pub transport: Option<Transport>,
+
+    /// The SSH port.
+    pub port: Option<u16>,
+
+    /// The user to log in as, unless the host names one, e.g. `deploy@web1`.
+    pub user: Option<String>,
+
+    /// Where `sira-client` is installed.
+    pub client_path: Option<String>,
+
+    /// How `sira-client` gains root privileges. Defaults to [Escalation::Sudo].
+    pub escalation: Option<Escalation>,
+
+    /// Whether `sira-client` runs with the privileges of the user that Sira logs in as, rather
+    /// than as root, e.g. on a host where Sira may only be trusted with one account. Defaults to
+    /// `false`.
+    ///
+    /// If set, `escalation` has no effect: only the actions of tasks that set
+    /// [Task::escalate](crate::core::Task::escalate) gain root privileges, and `sira-client`
+    /// gains them itself.
+    pub rootless: Option<bool>,
+
+    /// How long to wait for a connection, in seconds.
+    pub connect_timeout: Option<u64>,
+
+    /// How often to check that an idle connection is alive, in seconds.
+    pub server_alive_interval: Option<u64>,
+
+    /// Whether to check, before running any actions, that the installed `sira-client` matches
+    /// the one that `sira deploy-client` would install. Please see
+    /// [verify_client](crate::run_plan::deploy_client::verify_client).
+    pub verify_client: Option<bool>,
+
+    /// The host's MAC address, e.g. `00:11:22:33:44:55`, with which to wake it. An inventory can
+    /// set it, too. Please see [wake](crate::run_plan::wake).
+    pub mac_address: Option<String>,
+
+    /// Whether to wake the host with Wake-on-LAN and try again if Sira can't connect to it.
+    /// Defaults to `false`. Has no effect without [HostConfig::mac_address].
+    pub wake_on_lan: Option<bool>,
+
+    /// How long to wait for the host to boot after waking it, in seconds. Defaults to
+    /// [DEFAULT_WAKE_DELAY](crate::run_plan::wake::DEFAULT_WAKE_DELAY).
+    pub wake_delay: Option<u64>,
+
+    /// Extra OpenSSH options, e.g. `IdentityFile` or `ProxyJump`, as in `ssh_config(5)`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ssh_options: BTreeMap<String, String>,
+}
+
+impl HostConfig {
+    /// Returns an error if any of [HostConfig::ssh_options] can't be written to an OpenSSH
+    /// configuration file as is, or if [HostConfig::mac_address] isn't a MAC address.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, value) in &self.ssh_options {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+                bail!("invalid SSH option name {name:?}; expected e.g. \"ProxyJump\"");
+            }
+            if value.trim().is_empty() || value.contains(['\n', '\r', '\0']) {
+                bail!("invalid value for SSH option {name}: {value:?}");
+            }
+        }
+        if let Some(mac_address) = &self.mac_address {
+            wake::parse_mac_address(mac_address)?;
+        }
+        Ok(())
+    }
+
+    /// Returns these settings with each setting that `other` sets replaced by `other`'s. SSH
+    /// options are merged option by option, too.
+    pub fn merge(self, other: HostConfig) -> HostConfig {
+        let mut ssh_options = self.ssh_options;
+        ssh_options.extend(other.ssh_options);
+        HostConfig {
+            ssh_options,
+            transport: other.transport.or(self.transport),
+            port: other.port.or(self.port),
+            user: other.user.or(self.user),
+            client_path: other.client_path.or(self.client_path),
+            escalation: other.escalation.or(self.escalation),
+            rootless: other.rootless.or(self.rootless),
+            connect_timeout: other.connect_timeout.or(self.connect_timeout),
+            server_alive_interval: other.server_alive_interval.or(self.server_alive_interval),
+            verify_client: other.verify_client.or(self.verify_client),
+            mac_address: other.mac_address.or(self.mac_address),
+            wake_on_lan: other.wake_on_lan.or(self.wake_on_lan),
+            wake_delay: other.wake_delay.or(self.wake_delay),
+        }
+    }
+}
+
+/// Defaults for `sira` on the control node. Please see the [module documentation](self).
+///
+/// Each setting left unset keeps Sira's built-in default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// How to reach every managed node. Defaults to [Transport::Ssh].
+    pub transport: Option<Transport>,
+
+    /// The SSH port of every managed node.
+    pub port: Option<u16>,
+
+    /// The user to log into managed nodes as, unless a host names one, e.g. `deploy@web1`.
+    pub user: Option<String>,
+
+    /// Where `sira-client` is installed on managed nodes.
+    pub client_path: Option<String>,
+
+    /// How `sira-client` gains root privileges on managed nodes.
+    pub escalation: Option<Escalation>,
+
+    /// Whether `sira-client` runs on managed nodes with the privileges of the user that Sira logs
+    /// in as, rather than as root. Please see [HostConfig::rootless].
+    pub rootless: Option<bool>,
+
+    /// How long to wait for a connection to a managed node, in seconds.
+    pub connect_timeout: Option<u64>,
+
+    /// How often to check that an idle connection to a managed node is alive, in seconds.
+    pub server_alive_interval: Option<u64>,
+
+    /// Whether to check that every managed node's `sira-client` matches the one that
+    /// `sira deploy-client` would install before running any actions on it.
+    pub verify_client: Option<bool>,
+
+    /// Whether to wake managed nodes with Wake-on-LAN and try again if Sira can't connect to them.
+    /// Please see [HostConfig::wake_on_lan].
+    pub wake_on_lan: Option<bool>,
+
+    /// How long to wait for a managed node to boot after waking it, in seconds.
+    pub wake_delay: Option<u64>,
+
+    /// Extra OpenSSH options for every managed node, e.g. `IdentityFile` or `ProxyJump`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ssh_options: BTreeMap<String, String>,
+
+    /// The most managed nodes to run a plan on at once.
+    pub concurrency: Option<usize>,
+
+    /// The most actions from an independent task to keep in flight on each managed node at once.
+    pub pipeline: Option<usize>,
+
+    /// The known hosts file against which to check managed nodes' host keys.
+    pub known_hosts: Option<PathBuf>,
+
+    /// How to check managed nodes' host keys.
+    pub host_key_checking: Option<HostKeyChecking>,
+
+    /// The private key used to log into managed nodes.
+    pub login_key: Option<PathBuf>,
+
+    /// The private key used to sign actions.
+    pub action_key: Option<PathBuf>,
+
+    /// A file to which to append a signed record of every action that runs.
+    pub audit_log: Option<PathBuf>,
+
+    /// A file to which to append a JSON record of every action that runs.
+    pub json_log: Option<PathBuf>,
+
+    /// A file to which to write an HTML report of each run.
+    pub html_report: Option<PathBuf>,
+
+    /// A file to which to write Prometheus metrics at the end of each run.
+    pub metrics: Option<PathBuf>,
+
+    /// A directory under which to save each action's captured output.
+    pub artifacts: Option<PathBuf>,
+
+    /// A SQLite database in which to record every run.
+    pub history: Option<PathBuf>,
+
+    /// A directory in which to cache the facts gathered from each managed node. Please see
+    /// [facts](crate::run_plan::facts).
+    pub facts_cache: Option<PathBuf>,
+
+    /// How long cached facts stay fresh, in seconds. Please see
+    /// [facts](crate::run_plan::facts).
+    pub facts_ttl: Option<u64>,
+
+    /// Shell commands on the control node that print inventories of hosts, groups, and variables
+    /// as JSON. Please see [inventory](crate::run_plan::inventory).
+    pub inventory: Option<Vec<String>>,
+
+    /// Which AWS EC2 instances to list as an inventory. Requires the `ec2` feature. Please see
+    /// [ec2](crate::run_plan::inventory::ec2).
+    #[cfg(feature = "ec2")]
+    pub ec2: Option<crate::run_plan::inventory::ec2::Ec2Config>,
+
+    /// Groups of hosts to look up in DNS, by group name. Please see
+    /// [dns](crate::run_plan::inventory::dns).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dns: BTreeMap<String, DnsSource>,
+
+    /// Whether to also send reports to the control node's system log.
+    pub syslog: Option<bool>,
+
+    /// Which actions require confirmation. Defaults to [Confirmation::Tasks].
+    pub confirm: Option<Confirmation>,
+
+    /// What happens to the rest of a run when one host fails. Defaults to [OnFailure::Continue].
+    pub on_failure: Option<OnFailure>,
+
+    /// Shell commands to run on the control node before each run. Please see
+    /// [hooks](crate::run_plan::hooks).
+    pub pre_run: Option<Vec<String>>,
+
+    /// Shell commands to run on the control node after each run. Please see
+    /// [hooks](crate::run_plan::hooks).
+    pub post_run: Option<Vec<String>>,
+
+    /// Connection settings for individual hosts, which take precedence over the settings above.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, HostConfig>,
+
+    /// Runs for `sira daemon` to start on a schedule, by name. Please see
+    /// [schedule](crate::run_plan::schedule).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub schedule: BTreeMap<String, ScheduledRun>,
+
+    /// Settings for the HTTP API of `sira daemon`, which is off unless these are set. Please see
+    /// [api](crate::run_plan::api).
+    pub api: Option<ApiConfig>,
+
+    /// Named bundles of settings, one of which may replace the settings above. Please see
+    /// [Config::select].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile: BTreeMap<String, Config>,
+}
+
+impl Config {
+    /// Loads [CONFIG_FILE] from [config_dir] and then from [user_config_dir], with settings in the
+    /// latter taking precedence. Missing files are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either file exists but can't be read or parsed.
+    pub fn load() -> anyhow::Result<Config> {
+        let mut paths = vec![config_dir().join(CONFIG_FILE)];
+        if let Some(dir) = user_config_dir() {
+            paths.push(dir.join(CONFIG_FILE));
+        }
+        Config::load_from(&paths)
+    }
+
+    /// Loads each of `paths` that exists in turn, with settings in later files taking precedence.
+    pub(crate) fn load_from(paths: &[PathBuf]) -> anyhow::Result<Config> {
+        let mut config = Config::default();
+        for path in paths {
+            if !path.try_exists()? {
+                continue;
+            }
+            let toml = fs::read_to_string(path)
+                .with_context(|| format!("could not read config file: {}", path.display()))?;
+            let file = Config::from_toml(&toml)
+                .with_context(|| format!("could not parse config file: {}", path.display()))?;
+            config = config.merge(file);
+        }
+        Ok(config)
+    }
+
+    /// Parses a [Config] from the contents of a [CONFIG_FILE].
+    pub fn from_toml(toml: &str) -> anyhow::Result<Config> {
+        let config: Config = toml::from_str(toml)?;
+        config.validate()?;
+        for (name, profile) in &config.profile {
+            if !profile.profile.is_empty() {
+                bail!("profile {name} must not contain other profiles");
+            }
+            if !profile.schedule.is_empty() {
+                bail!("profile {name} must not contain schedules");
+            }
+            if profile.api.is_some() {
+                bail!("profile {name} must not contain API settings");
+            }
+            profile
+                .validate()
+                .with_context(|| format!("invalid settings in profile {name}"))?;
+        }
+        Ok(config)
+    }
+
+    /// Returns an error if any setting is invalid.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.concurrency == Some(0) {
+            bail!("concurrency must be at least 1");
+        }
+        if self.pipeline == Some(0) {
+            bail!("pipeline must be at least 1");
+        }
+        self.connection().validate()?;
+        for (host, host_config) in &self.hosts {
+            host_config
+                .validate()
+                .with_context(|| format!("invalid settings for host {host}"))?;
+        }
+        for (group, source) in &self.dns {
+            source
+                .validate()
+                .with_context(|| format!("invalid DNS settings for group {group}"))?;
+        }
+        for (name, run) in &self.schedule {
+            run.validate()
+                .with_context(|| format!("invalid settings for schedule {name}"))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the settings of the profile named `profile`, if given, or else the settings outside
+    /// of profiles.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no profile named `profile`.
+    pub fn select(mut self, profile: Option<&str>) -> anyhow::Result<Config> {
+        let Some(name) = profile else {
+            return Ok(self);
+        };
+        match self.profile.remove(name) {
+            Some(selected) => Ok(selected),
+            None if self.profile.is_empty() => bail!("no profile named {name}; none are defined"),
+            None => bail!(
+                "no profile named {name}; please choose one of: {}",
+                self.profile.into_keys().collect::<Vec<_>>().join(", "),
+            ),
+        }
+    }
+
+    /// Returns the connection settings for every host, i.e. this [Config]'s global settings.
+    pub fn connection(&self) -> HostConfig {
+        HostConfig {
+            transport: self.transport,
+            port: self.port,
+            user: self.user.clone(),
+            client_path: self.client_path.clone(),
+            escalation: self.escalation,
+            rootless: self.rootless,
+            connect_timeout: self.connect_timeout,
+            server_alive_interval: self.server_alive_interval,
+            verify_client: self.verify_client,
+            mac_address: None,
+            wake_on_lan: self.wake_on_lan,
+            wake_delay: self.wake_delay,
+            ssh_options: self.ssh_options.clone(),
+        }
+    }
+
+    /// Returns this [Config] with each setting that `other` sets replaced by `other`'s. Per-host
+    /// settings are merged setting by setting, too.
+    pub fn merge(self, other: Config) -> Config {
+        let mut hosts = self.hosts;
+        for (host, config) in other.hosts {
+            let merged = hosts.remove(&host).unwrap_or_default().merge(config);
+            hosts.insert(host, merged);
+        }
+        let mut profile = self.profile;
+        for (name, config) in other.profile {
+            let merged = profile.remove(&name).unwrap_or_default().merge(config);
+            profile.insert(name, merged);
+        }
+        let mut dns = self.dns;
+        dns.extend(other.dns);
+        let mut schedule = self.schedule;
+        schedule.extend(other.schedule);
+        let mut ssh_options = self.ssh_options;
+        ssh_options.extend(other.ssh_options);
+        Config {
+            ssh_options,
+            transport: other.transport.or(self.transport),
+            port: other.port.or(self.port),
+            user: other.user.or(self.user),
+            client_path: other.client_path.or(self.client_path),
+            escalation: other.escalation.or(self.escalation),
+            rootless: other.rootless.or(self.rootless),
+            connect_timeout: other.connect_timeout.or(self.connect_timeout),
+            server_alive_interval: other.server_alive_interval.or(self.server_alive_interval),
+            verify_client: other.verify_client.or(self.verify_client),
+            wake_on_lan: other.wake_on_lan.or(self.wake_on_lan),
+            wake_delay: other.wake_delay.or(self.wake_delay),
+            concurrency: other.concurrency.or(self.concurrency),
+            pipeline: other.pipeline.or(self.pipeline),
+            known_hosts: other.known_hosts.or(self.known_hosts),
+            host_key_checking: other.host_key_checking.or(self.host_key_checking),
+            login_key: other.login_key.or(self.login_key),
+            action_key: other.action_key.or(self.action_key),
+            audit_log: other.audit_log.or(self.audit_log),
+            json_log: other.json_log.or(self.json_log),
+            html_report: other.html_report.or(self.html_report),
+            metrics: other.metrics.or(self.metrics),
+            artifacts: other.artifacts.or(self.artifacts),
+            history: other.history.or(self.history),
+            facts_cache: other.facts_cache.or(self.facts_cache),
+            facts_ttl: other.facts_ttl.or(self.facts_ttl),
+            inventory: other.inventory.or(self.inventory),
+            #[cfg(feature = "ec2")]
+            ec2: other.ec2.or(self.ec2),
+            dns,
+            syslog: other.syslog.or(self.syslog),
+            confirm: other.confirm.or(self.confirm),
+            on_failure: other.on_failure.or(self.on_failure),
+            pre_run: other.pre_run.or(self.pre_run),
+            post_run: other.post_run.or(self.post_run),
+            hosts,
+            schedule,
+            api: other.api.or(self.api),
+            profile,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,4 +643,281 @@ mod tests {
         let config_dir_exists = expected.try_exists();
         assert!(config_dir_exists.expect("could not confirm or deny whether config dir exists"));
     }
+
+    #[test]
+    fn no_config_file_is_installed_for_tests() {
+        assert!(!config_dir().join(CONFIG_FILE).exists());
+    }
+
+    mod config {
+        use super::*;
+
+        #[test]
+        fn parses_every_setting() {
+            let config = Config::from_toml(
+                r#"
+                user = "deploy"
+                client_path = "/usr/local/bin/sira-client"
+                concurrency = 4
+                pipeline = 8
+                host_key_checking = "accept-new"
+                json_log = "/var/log/sira.jsonl"
+                syslog = true
+                "#,
+            )
+            .unwrap();
+            assert_eq!(Some("deploy"), config.user.as_deref());
+            assert_eq!(
+                Some("/usr/local/bin/sira-client"),
+                config.client_path.as_deref(),
+            );
+            assert_eq!(Some(4), config.concurrency);
+            assert_eq!(Some(8), config.pipeline);
+            assert_eq!(Some(HostKeyChecking::AcceptNew), config.host_key_checking);
+            assert_eq!(Some(PathBuf::from("/var/log/sira.jsonl")), config.json_log,);
+            assert_eq!(Some(true), config.syslog);
+            assert_eq!(None, config.known_hosts);
+        }
+
+        #[test]
+        fn parses_hooks() {
+            let config = Config::from_toml(
+                r#"
+                pre_run = ["make -C files", "./check.sh"]
+                post_run = []
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                Some(vec!["make -C files".to_string(), "./check.sh".to_string()]),
+                config.pre_run,
+            );
+            assert_eq!(Some(vec![]), config.post_run);
+        }
+
+        #[test]
+        fn parses_facts_cache() {
+            let config = Config::from_toml(
+                r#"
+                facts_cache = "/var/cache/sira/facts"
+                facts_ttl = 600
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                Some(PathBuf::from("/var/cache/sira/facts")),
+                config.facts_cache,
+            );
+            assert_eq!(Some(600), config.facts_ttl);
+        }
+
+        #[test]
+        fn parses_inventory() {
+            let config = Config::from_toml(r#"inventory = ["./cmdb.sh --all"]"#).unwrap();
+            assert_eq!(Some(vec!["./cmdb.sh --all".to_string()]), config.inventory);
+        }
+
+        #[test]
+        fn rejects_unknown_settings() {
+            assert!(Config::from_toml("usr = \"deploy\"").is_err());
+        }
+
+        #[test]
+        fn rejects_zero_concurrency() {
+            assert!(Config::from_toml("concurrency = 0").is_err());
+        }
+
+        #[test]
+        fn rejects_zero_pipeline() {
+            assert!(Config::from_toml("pipeline = 0").is_err());
+        }
+
+        #[test]
+        fn later_files_take_precedence() {
+            let dir = tempfile::tempdir().unwrap();
+            let system = dir.path().join("system.toml");
+            let user = dir.path().join("user.toml");
+            fs::write(&system, "user = \"sira\"\nconcurrency = 8\n").unwrap();
+            fs::write(&user, "user = \"alice\"\n").unwrap();
+
+            let missing = dir.path().join("missing.toml");
+            let config = Config::load_from(&[system, missing, user]).unwrap();
+            assert_eq!(Some("alice"), config.user.as_deref());
+            assert_eq!(Some(8), config.concurrency);
+        }
+
+        #[test]
+        fn parses_host_overrides() {
+            let config = Config::from_toml(
+                r#"
+                user = "sira"
+                connect_timeout = 10
+                verify_client = true
+
+                [hosts.legacy1]
+                port = 2222
+                escalation = "doas"
+                rootless = true
+                "#,
+            )
+            .unwrap();
+            let legacy1 = config.connection().merge(config.hosts["legacy1"].clone());
+            assert_eq!(Some(2222), legacy1.port);
+            assert_eq!(Some("sira"), legacy1.user.as_deref());
+            assert_eq!(Some(Escalation::Doas), legacy1.escalation);
+            assert_eq!(Some(10), legacy1.connect_timeout);
+            assert_eq!(Some(true), legacy1.verify_client);
+            assert_eq!(Some(true), legacy1.rootless);
+        }
+
+        #[test]
+        fn parses_transport() {
+            let config = Config::from_toml("[hosts.control]\ntransport = \"local\"").unwrap();
+            assert_eq!(None, config.connection().transport);
+            assert_eq!(Some(Transport::Local), config.hosts["control"].transport);
+            let config = Config::from_toml("[hosts.web-test]\ntransport = \"docker\"").unwrap();
+            assert_eq!(Some(Transport::Docker), config.hosts["web-test"].transport);
+            assert!(Config::from_toml("transport = \"carrier-pigeon\"").is_err());
+        }
+
+        #[test]
+        fn parses_on_failure() {
+            let config = Config::from_toml("on_failure = \"abort\"").unwrap();
+            assert_eq!(Some(OnFailure::Abort), config.on_failure);
+            assert_eq!(OnFailure::Continue, "continue".parse().unwrap());
+            assert!("stop".parse::<OnFailure>().is_err());
+        }
+
+        #[test]
+        fn rejects_unknown_host_settings() {
+            assert!(Config::from_toml("[hosts.web1]\nprot = 2222").is_err());
+        }
+
+        #[test]
+        fn merges_host_overrides_from_each_file() {
+            let dir = tempfile::tempdir().unwrap();
+            let system = dir.path().join("system.toml");
+            let user = dir.path().join("user.toml");
+            fs::write(&system, "[hosts.web1]\nport = 2222\nuser = \"sira\"\n").unwrap();
+            fs::write(&user, "[hosts.web1]\nuser = \"alice\"\n").unwrap();
+
+            let config = Config::load_from(&[system, user]).unwrap();
+            assert_eq!(Some(2222), config.hosts["web1"].port);
+            assert_eq!(Some("alice"), config.hosts["web1"].user.as_deref());
+        }
+
+        #[test]
+        fn merges_ssh_options_option_by_option() {
+            let config = Config::from_toml(
+                r#"
+                ssh_options = { ConnectionAttempts = "3", ProxyJump = "bastion" }
+
+                [hosts.web1.ssh_options]
+                ProxyJump = "bastion2"
+                "#,
+            )
+            .unwrap();
+            let web1 = config.connection().merge(config.hosts["web1"].clone());
+            assert_eq!(
+                BTreeMap::from([
+                    ("ConnectionAttempts".to_string(), "3".to_string()),
+                    ("ProxyJump".to_string(), "bastion2".to_string()),
+                ]),
+                web1.ssh_options,
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_ssh_options() {
+            assert!(Config::from_toml("ssh_options = { \"Proxy Jump\" = \"x\" }").is_err());
+            assert!(Config::from_toml("ssh_options = { ProxyJump = \"x\\nUser root\" }").is_err());
+            assert!(Config::from_toml("[hosts.web1.ssh_options]\nProxyJump = \"\"").is_err());
+        }
+
+        #[test]
+        fn parses_wake_on_lan() {
+            let config = Config::from_toml(
+                r#"
+                wake_on_lan = true
+
+                [hosts.laptop1]
+                mac_address = "00:11:22:33:44:55"
+                wake_delay = 90
+                "#,
+            )
+            .unwrap();
+            let laptop1 = config.connection().merge(config.hosts["laptop1"].clone());
+            assert_eq!(Some(true), laptop1.wake_on_lan);
+            assert_eq!(Some("00:11:22:33:44:55"), laptop1.mac_address.as_deref());
+            assert_eq!(Some(90), laptop1.wake_delay);
+            assert!(Config::from_toml("[hosts.laptop1]\nmac_address = \"laptop1\"").is_err());
+            assert!(Config::from_toml("mac_address = \"00:11:22:33:44:55\"").is_err());
+        }
+
+        #[test]
+        fn selected_profile_replaces_other_settings() {
+            let config = Config::from_toml(
+                r#"
+                login_key = "/home/alice/.ssh/sira-staging"
+                concurrency = 10
+
+                [profile.prod]
+                action_key = "/etc/sira/keys/action-prod"
+                confirm = "all"
+                "#,
+            )
+            .unwrap();
+
+            let prod = config.clone().select(Some("prod")).unwrap();
+            assert_eq!(Some(Confirmation::All), prod.confirm);
+            assert_eq!(
+                Some(PathBuf::from("/etc/sira/keys/action-prod")),
+                prod.action_key,
+            );
+            assert_eq!(None, prod.login_key);
+            assert_eq!(None, prod.concurrency);
+
+            assert_eq!(config.clone(), config.select(None).unwrap());
+        }
+
+        #[test]
+        fn rejects_unknown_profile() {
+            let config = Config::from_toml("[profile.prod]\n[profile.staging]\n").unwrap();
+            let error = config.select(Some("production")).unwrap_err();
+            assert!(error.to_string().contains("prod, staging"), "{error}");
+            assert!(Config::default().select(Some("prod")).is_err());
+        }
+
+        #[test]
+        fn rejects_nested_and_invalid_profiles() {
+            assert!(Config::from_toml("[profile.a.profile.b]\n").is_err());
+            assert!(Config::from_toml("[profile.a]\nconcurrency = 0\n").is_err());
+        }
+
+        #[test]
+        fn merges_profiles_from_each_file() {
+            let dir = tempfile::tempdir().unwrap();
+            let system = dir.path().join("system.toml");
+            let user = dir.path().join("user.toml");
+            fs::write(&system, "[profile.prod]\nconcurrency = 2\n").unwrap();
+            fs::write(&user, "[profile.prod]\nconfirm = \"all\"\n").unwrap();
+
+            let prod = Config::load_from(&[system, user])
+                .unwrap()
+                .select(Some("prod"))
+                .unwrap();
+            assert_eq!(Some(2), prod.concurrency);
+            assert_eq!(Some(Confirmation::All), prod.confirm);
+        }
+
+        #[test]
+        fn names_file_that_fails_to_parse() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("sira.toml");
+            fs::write(&path, "concurrency = \"many\"").unwrap();
+
+            let error = Config::load_from(std::slice::from_ref(&path)).unwrap_err();
+            assert!(error.to_string().contains(&path.display().to_string()));
+        }
+    }
 }
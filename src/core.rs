@@ -1,6 +1,7 @@
 //! Provides types that represent the user's instructions, e.g. manifest and task files.
 
 pub mod action;
+pub mod expr;
 pub mod manifest;
 pub mod plan;
 pub mod task;
@@ -17,7 +18,9 @@ pub use plan::Plan;
 #[doc(inline)]
 pub use task::Task;
 
-#[cfg(test)]
+/// Fixtures for testing code that uses Sira. Outside of Sira, these are only available with the
+/// `test-fixtures` feature. Please see [run_plan::fixtures](crate::run_plan::fixtures).
+#[cfg(any(test, feature = "test-fixtures"))]
 pub mod fixtures {
     use super::*;
     use indexmap::IndexMap;
@@ -32,14 +35,26 @@ pub mod fixtures {
             name: "API test".into(),
             actions: vec![action.clone()],
             vars: IndexMap::new(),
+            sensitive: Vec::new(),
+            confirm: false,
+            user: None,
+            escalate: false,
+            dpkg_lock_timeout: None,
+            limits: Default::default(),
+            sandbox: None,
+            independent: false,
+            verify: Vec::new(),
         };
 
         let manifest = Manifest {
             source: None,
             name: "API test".into(),
             hosts: vec!["archie-desktop".into()],
+            fact: None,
+            host_vars: Default::default(),
             include: vec![task.clone()],
             vars: IndexMap::new(),
+            sensitive: Vec::new(),
         };
 
         let plan = Plan {
@@ -3,20 +3,73 @@
 #[cfg(doc)]
 use crate::core::plan::Plan;
 use crate::core::{manifest::Manifest, task::Task};
-use regex::{NoExpand, Regex};
+use indexmap::IndexMap;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-#[cfg(doc)]
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
 
 /// The relative path to the temporary file that the `sira` and `sira-client` both use when
 /// uploading files.
 pub const FILE_TRANSFER_PATH: &str = ".sira-transfer";
 
+/// The prefix of the line of output in which `sira-client` reports the SHA-256 checksum of a file
+/// that it installed for an [Action::Upload].
+pub const UPLOAD_CHECKSUM_PREFIX: &str = "sha256: ";
+
+/// The prefix of the line of output in which `sira-client` reports the [Status] of an [Action].
+pub const STATUS_PREFIX: &str = "sira-status: ";
+
+/// The prefix of each line of output in which `sira-client` reports [Progress] while an [Action]
+/// runs.
+pub const PROGRESS_PREFIX: &str = "sira-progress: ";
+
+/// The prefix of the line of output in which `sira-client` reports a [ClientError] if an [Action]
+/// fails.
+pub const ERROR_PREFIX: &str = "sira-error: ";
+
+pub mod bundle;
+pub use bundle::Bundle;
+
+pub mod controller_key;
+pub use controller_key::controller_key;
+
+pub mod diff;
+pub use diff::{unified_diff, Contents};
+
+pub mod envelope;
+pub use envelope::Envelope;
+
+pub mod error;
+pub use error::{ClientError, ErrorCode, WithCode};
+
+pub mod kernel_module;
+pub use kernel_module::{kernel_module_changes, KernelModuleChange, KernelModulePaths};
+
+pub mod limits;
+pub use limits::Limits;
+
 pub mod line_in_file;
-pub use line_in_file::line_in_file;
+pub use line_in_file::{line_in_file, line_in_file_preview};
+
+pub mod progress;
+pub use progress::Progress;
+
+pub mod redact;
+pub use redact::Redactor;
+
+pub mod sandbox;
+pub use sandbox::Sandbox;
 
 pub mod script;
-pub use script::script;
+pub use script::{check_script, script};
+
+pub mod status;
+pub use status::Status;
+
+pub mod swap;
+pub use swap::{swap_changes, SwapChange, SwapPaths};
 
 /// The types of actions that Sira can perform on a client.
 // In order to allow Action to (de)serialize using singleton map notation rather than externally
@@ -151,6 +204,159 @@ pub enum Action {
     /// With these files in place, you can simply run `./run` from `~/sira` on your control node.
     Command(Vec<String>),
 
+    /// Adds or removes a control node's public keys on managed nodes.
+    ///
+    /// Use this [Action] to onboard another control node, or to revoke one, with a normal Sira
+    /// run. For example:
+    ///
+    /// ```text
+    /// ---
+    /// name: Trust the backup control node
+    /// actions:
+    ///   - controller_key:
+    ///       login_key: ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI... sira
+    ///       action_key: ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI... backup
+    /// ```
+    ///
+    /// Each key is matched by its key type and key data, so comments and options don't matter
+    /// when checking whether a key is present. Adding a key that is already present does nothing.
+    ///
+    /// # Security considerations
+    ///
+    /// This [Action] controls who can run actions on managed nodes, so a policy (see
+    /// [crate::client::policy]) only permits it through the `any` rule.
+    ///
+    /// `sira-client` refuses to remove the last key from either file, since that would leave the
+    /// managed node unreachable or unable to verify any actions. To replace the only key, add the
+    /// new key and then remove the old one, signing the second run with the new key.
+    ControllerKey {
+        /// A control node's login public key, e.g. the contents of `~/.ssh/sira.pub`, to add to or
+        /// remove from the Sira user's `authorized_keys` file.
+        ///
+        /// `sira-client` assumes that the Sira user's home directory is `/home/<sira-user>`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        login_key: Option<String>,
+
+        /// A control node's action public key, e.g. the contents of `/etc/sira/keys/action.pub`,
+        /// to add to or remove from the action allowed signers file.
+        ///
+        /// As with `sira-install`, the key's comment becomes its principal. If the managed node
+        /// has no action allowed signers file, adding a key creates one, and from then on,
+        /// `sira-client` will only run signed actions.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        action_key: Option<String>,
+
+        /// Whether to remove the keys rather than add them. Defaults to `false`.
+        #[serde(skip_serializing_if = "is_false")]
+        #[serde(default)]
+        remove: bool,
+    },
+
+    /// Runs a site-specific plugin on managed nodes (as root), e.g. to manage something that
+    /// Sira's built-in actions don't cover, without forking Sira.
+    ///
+    /// A plugin is an executable installed on the managed node in the plugin directory, i.e.
+    /// `/etc/sira/plugins/<plugin>`, owned by root and writable only by root. For example:
+    ///
+    /// ```text
+    /// ---
+    /// name: Open the web server's port
+    /// actions:
+    ///   - custom:
+    ///       plugin: firewall
+    ///       args:
+    ///         port: 443
+    ///         protocol: tcp
+    /// ```
+    ///
+    /// `sira-client` runs the plugin with no arguments and writes a JSON request to its standard
+    /// input, then reads a JSON response from its standard output. Please see
+    /// [crate::client::plugin] for the details of this contract.
+    ///
+    /// A policy (see [crate::client::policy]) permits this [Action] through the `custom` rule,
+    /// which matches the plugin's name.
+    Custom {
+        /// The name of the plugin, i.e. its file name in the plugin directory.
+        plugin: String,
+
+        /// The arguments to pass to the plugin, in any form that JSON can represent. Variables are
+        /// substituted in every string within.
+        #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+        #[serde(default)]
+        args: serde_json::Value,
+    },
+
+    /// Loads or unloads a Linux kernel module and keeps it that way across reboots.
+    ///
+    /// # Behavior
+    ///
+    /// To load a module, `sira-client`:
+    ///
+    /// 1. Writes [Action::KernelModule::options], if any, to `/etc/modprobe.d/<name>.conf`, or
+    ///    removes that file if there are none.
+    ///
+    /// 1. If [Action::KernelModule::persist] is true, writes the module's name to
+    ///    `/etc/modules-load.d/<name>.conf` so that `systemd-modules-load` loads it at boot.
+    ///    Otherwise, removes that file.
+    ///
+    /// 1. Runs `modprobe <name>` if the module isn't already loaded.
+    ///
+    /// If [Action::KernelModule::remove] is true, `sira-client` instead runs `modprobe -r <name>`
+    /// if the module is loaded, then removes both files.
+    ///
+    /// Options only take effect when the module loads, so changing the options of a module that
+    /// is already loaded updates `/etc/modprobe.d` but not the running kernel. To apply them now,
+    /// remove the module and then load it again.
+    ///
+    /// Sira owns both files and replaces anything else in them. `sira-client` changes only what
+    /// differs from the desired state and reports each change. This action is only available on
+    /// Linux managed nodes.
+    ///
+    /// # Example
+    ///
+    /// ```text
+    /// ---
+    /// name: Prepare a Kubernetes node
+    /// actions:
+    ///   - kernel_module:
+    ///       name: br_netfilter
+    ///   - kernel_module:
+    ///       name: nf_conntrack
+    ///       options:
+    ///         - hashsize=262144
+    ///   - kernel_module:
+    ///       name: floppy
+    ///       remove: true
+    /// ```
+    ///
+    /// A policy (see [crate::client::policy]) permits this [Action] through the `kernel_module`
+    /// rule, which matches the module's name.
+    KernelModule {
+        /// The module's name, e.g. `br_netfilter`. Names may contain only ASCII letters, digits,
+        /// `_`, and `-`; as the kernel does, Sira treats `-` and `_` alike.
+        name: String,
+
+        /// The module's parameters, each in the form `<parameter>=<value>`, e.g.
+        /// `hashsize=262144`. Ignored if [Action::KernelModule::remove] is true.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        #[serde(default)]
+        options: Vec<String>,
+
+        /// Whether to load the module at boot. Defaults to `true`. Ignored if
+        /// [Action::KernelModule::remove] is true.
+        #[serde(skip_serializing_if = "is_true")]
+        #[serde(default = "Action::default_persist")]
+        persist: bool,
+
+        /// Whether to unload the module and remove its configuration instead. Defaults to
+        /// `false`.
+        #[serde(skip_serializing_if = "is_false")]
+        #[serde(default)]
+        remove: bool,
+    },
+
     /// Replaces a line in a file or inserts a new line.
     ///
     /// # Behavior
@@ -195,6 +401,8 @@ pub enum Action {
     /// regular expressions, please open an issue so that we may discuss it and perhaps design an
     /// appropriate new [Action] to support it, e.g. `Action::RegexInFile`.
     ///
+    /// If the file changes, `sira-client` reports a unified diff of the changes.
+    ///
     /// # Special cases
     ///
     /// If the file is empty or contains only [Unicode whitespace], and [line] contains characters
@@ -331,14 +539,14 @@ pub enum Action {
         /// A user-friendly name or description for the script.
         name: String,
 
-        /// The user on the managed node that will run the script. Defaults to root.
+        /// The user on the managed node that will run the script. Defaults to [Task::user], if
+        /// set, or else to root.
         ///
         /// Please note that the script will start in the Sira users's SSH starting directory
         /// (typically their home directory). If you intend to run a script as a different user,
         /// you might want to begin the script with `cd` to switch to that user's home directory.
-        #[serde(skip_serializing_if = "Action::user_or_group_is_default")]
-        #[serde(default = "Action::default_user_and_group")]
-        user: String,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        user: Option<String>,
 
         /// The contents of the script. This should start with a shebang. You may want to use YAML
         /// block scalar syntax (as in the example) to prevent the shebang from being parsed as a
@@ -346,14 +554,92 @@ pub enum Action {
         contents: String,
     },
 
+    /// Creates and enables a swap file, or enables a swap partition, and keeps it enabled across
+    /// reboots.
+    ///
+    /// # Behavior
+    ///
+    /// To enable swap space, `sira-client`:
+    ///
+    /// 1. If nothing exists at [Action::Swap::path], creates a swap file there that is
+    ///    [Action::Swap::size] bytes long, readable only by root, and formats it with `mkswap`. If a
+    ///    swap file of a different size exists there, disables and replaces it. If a swap file of
+    ///    the right size exists, makes it owned and readable only by root, and formats it if it
+    ///    isn't already formatted as swap space.
+    ///
+    /// 1. Runs `swapon <path>` if the swap space isn't already enabled.
+    ///
+    /// 1. If [Action::Swap::persist] is true, adds `<path> none swap sw 0 0` to `/etc/fstab` so that
+    ///    the system enables the swap space at boot, replacing any other entries for the same
+    ///    path. Otherwise, removes any such entries.
+    ///
+    /// If [Action::Swap::remove] is true, `sira-client` instead runs `swapoff <path>` if the swap
+    /// space is enabled, removes the swap file, and removes its entries from `/etc/fstab`.
+    ///
+    /// If [Action::Swap::path] is a block device, i.e. a swap partition, Sira never formats or
+    /// removes it: it must already be formatted with `mkswap`, and [Action::Swap::size] doesn't
+    /// apply. `sira-client` changes only what differs from the desired state and reports each
+    /// change. This action is only available on Linux managed nodes.
+    ///
+    /// # Example
+    ///
+    /// ```text
+    /// ---
+    /// name: Add swap space
+    /// actions:
+    ///   - swap:
+    ///       path: /swapfile
+    ///       size: 2G
+    ///   - swap:
+    ///       path: /dev/sdb2
+    ///   - swap:
+    ///       path: /old.swap
+    ///       remove: true
+    /// ```
+    ///
+    /// A policy (see [crate::client::policy]) permits this [Action] through the `swap` rule, which
+    /// matches the swap space's path.
+    Swap {
+        /// The absolute path to the swap file or partition, e.g. `/swapfile`. Paths may not contain
+        /// white space.
+        path: String,
+
+        /// The size of the swap file, in bytes or with a `K`, `M`, `G`, or `T` suffix for powers of
+        /// 1024, e.g. `2G`. Must be at least `1M`. Required to create a swap file; if unset, Sira
+        /// enables the existing swap file or partition as it is. Ignored if [Action::Swap::remove]
+        /// is true.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        size: Option<String>,
+
+        /// Whether to enable the swap space at boot. Defaults to `true`. Ignored if
+        /// [Action::Swap::remove] is true.
+        #[serde(skip_serializing_if = "is_true")]
+        #[serde(default = "Action::default_persist")]
+        persist: bool,
+
+        /// Whether to disable the swap space, remove the swap file, and remove its entries from
+        /// `/etc/fstab` instead. Defaults to `false`.
+        #[serde(skip_serializing_if = "is_false")]
+        #[serde(default)]
+        remove: bool,
+    },
+
     /// Transfers a file from the control node to managed nodes.
     ///
     /// The transfer takes place in two stages:
     /// 1. Sira transfers the file on the control node to a temporary file owned by the Sira user
-    ///    in the Sira user's home directory (or wherever OpenSSH `scp` sessions start).
+    ///    in the Sira user's home directory (or wherever OpenSSH sessions start).
     /// 1. Then, Sira invokes `sira-client` on the managed node to change the file's owner
     ///    (i.e. user), group, and permissions and move it into place.
     ///
+    /// Afterward, `sira-client` reports the installed file's SHA-256 checksum, and Sira fails the
+    /// action if it doesn't match the file on the control node. (If [Action::Upload::overwrite]
+    /// is false and the destination already exists, there is no installed file to check.) Unless
+    /// [Action::Upload::encrypt] is true, `sira-client` first reports a unified diff of any
+    /// changes to the destination file, or only that it changed if either version is larger than
+    /// [DIFF_LIMIT](diff::DIFF_LIMIT).
+    ///
     /// # Security considerations
     ///
     /// When the file is initially transferred to the managed node, it will be in the Sira user's
@@ -361,8 +647,11 @@ pub enum Action {
     /// start). You may wish to restrict permissions on this directory, e.g. to
     /// `700` (`u=rwx,g=,o=`) or `770` (`u=rwx,g=u,o=`).
     ///
-    /// More advanced security procedures, e.g. encrypting Sira configuration data or files at
-    /// rest, are possible but outside of the scope of this documentation. If you find that Sira
+    /// For secrets, set [Action::Upload::encrypt] so that the file is only ever decrypted by
+    /// `sira-client`, as root, just before it moves into place.
+    ///
+    /// More advanced security procedures, e.g. encrypting Sira configuration data, are possible
+    /// but outside of the scope of this documentation. If you find that Sira
     /// either interferes with or lacks features to support an advanced security procedure you wish
     /// to apply, please feel free to open an issue to discuss it.
     Upload {
@@ -425,6 +714,89 @@ pub enum Action {
         #[serde(skip_serializing_if = "is_true")]
         #[serde(default = "Action::default_overwrite")]
         overwrite: bool,
+
+        /// Whether to encrypt the file on the control node and decrypt it on the managed node.
+        /// Defaults to `false`.
+        ///
+        /// If `true`, Sira encrypts the file with [age](https://age-encryption.org) to the
+        /// recipients listed for the managed node in the control node's recipients directory (see
+        /// [crypto::recipients_path]) before transferring it. `sira-client` then decrypts it with
+        /// the managed node's upload identity (see [crypto::upload_identity_path]) into a
+        /// temporary file readable only by root next to the destination, just before moving it
+        /// into place, and removes that file if the upload fails or leaves an existing file in
+        /// place. Thus, the file's contents never transit the network or rest in the Sira user's
+        /// home directory or a shared temporary directory unencrypted.
+        ///
+        /// Both nodes need the `age` command installed.
+        ///
+        /// [crypto::recipients_path]: crate::crypto::recipients_path
+        /// [crypto::upload_identity_path]: crate::crypto::upload_identity_path
+        #[serde(skip_serializing_if = "is_false")]
+        #[serde(default)]
+        encrypt: bool,
+
+        /// The SELinux user to give the file on the managed node, e.g. `system_u`.
+        ///
+        /// On managed nodes with SELinux enabled, `sira-client` always resets the installed
+        /// file's context to the policy's default for its path with `restorecon`, since the file
+        /// would otherwise keep the context of the temporary location it was uploaded to. If
+        /// [Action::Upload::seuser], [Action::Upload::serole], or [Action::Upload::setype] is
+        /// set, `sira-client` then overrides that part of the context with `chcon`. On managed
+        /// nodes without SELinux, these fields are ignored.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        seuser: Option<String>,
+
+        /// The SELinux role to give the file on the managed node, e.g. `object_r`. Please see
+        /// [Action::Upload::seuser].
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        serole: Option<String>,
+
+        /// The SELinux type to give the file on the managed node, e.g. `httpd_sys_content_t`.
+        /// Please see [Action::Upload::seuser].
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        setype: Option<String>,
+
+        /// POSIX ACL entries to add to the file on the managed node, in any form that `setfacl -m`
+        /// will accept, e.g. `u:alice:r` or `g:web:rw`.
+        ///
+        /// Like [Action::Upload::permissions], ACL entries are applied before the file is moved
+        /// into place. If the file already exists and [Action::Upload::overwrite] is `false`, they
+        /// are not applied.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        #[serde(default)]
+        acl: Vec<String>,
+
+        /// The permissions of the file while it is in transit, i.e. from when it starts uploading
+        /// into the Sira user's home directory until `sira-client` gives it its final
+        /// permissions, in any form that `install -m` will accept, e.g. `600`.
+        ///
+        /// If this value is unspecified, the file has the Sira user's default permissions in
+        /// transit, which may let other users on the managed node read it. For sensitive files,
+        /// set this to `600`, or use [Action::Upload::encrypt].
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        transfer_permissions: Option<String>,
+
+        /// Extended attributes to set on the file on the managed node, by name, e.g.
+        /// `user.origin: sira`. Values are set as text with `setfattr`.
+        ///
+        /// Like [Action::Upload::acl], extended attributes are set before the file is moved into
+        /// place.
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        #[serde(default)]
+        xattrs: BTreeMap<String, String>,
+
+        /// File capabilities to give the file on the managed node, in any form that `setcap` will
+        /// accept, e.g. `cap_net_bind_service=+ep`.
+        ///
+        /// `chown` clears a file's capabilities, so `sira-client` sets them after it has given the
+        /// file its final owner and permissions.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        capabilities: Option<String>,
     },
 }
 
@@ -463,6 +835,96 @@ impl<'de> Deserialize<'de> for Action {
 }
 
 impl Action {
+    /// Applies `f` to every [String] field of the [Action], e.g. to substitute variables.
+    pub fn map_strings(&mut self, mut f: impl FnMut(&mut String)) {
+        use Action::*;
+        match self {
+            Command(commands) => {
+                commands.iter_mut().for_each(f);
+            }
+            ControllerKey {
+                login_key,
+                action_key,
+                remove: _,
+            } => {
+                login_key.as_mut().map(&mut f);
+                action_key.as_mut().map(&mut f);
+            }
+            Custom { plugin, args } => {
+                f(plugin);
+                map_json_strings(args, &mut f);
+            }
+            KernelModule {
+                name,
+                options,
+                persist: _,
+                remove: _,
+            } => {
+                f(name);
+                options.iter_mut().for_each(f);
+            }
+            LineInFile {
+                path,
+                line,
+                pattern,
+                after,
+                indent: _,
+            } => {
+                f(path);
+                f(line);
+                pattern.as_mut().map(&mut f);
+                after.as_mut().map(&mut f);
+            }
+            Script {
+                name,
+                user,
+                contents,
+            } => {
+                f(name);
+                user.as_mut().map(&mut f);
+                f(contents);
+            }
+            Swap {
+                path,
+                size,
+                persist: _,
+                remove: _,
+            } => {
+                f(path);
+                size.as_mut().map(&mut f);
+            }
+            Upload {
+                from,
+                to,
+                user,
+                group,
+                permissions,
+                overwrite: _,
+                encrypt: _,
+                seuser,
+                serole,
+                setype,
+                acl,
+                transfer_permissions,
+                xattrs,
+                capabilities,
+            } => {
+                f(from);
+                f(to);
+                f(user);
+                f(group);
+                permissions.as_mut().map(&mut f);
+                seuser.as_mut().map(&mut f);
+                serole.as_mut().map(&mut f);
+                setype.as_mut().map(&mut f);
+                acl.iter_mut().for_each(&mut f);
+                transfer_permissions.as_mut().map(&mut f);
+                xattrs.values_mut().for_each(&mut f);
+                capabilities.as_mut().map(&mut f);
+            }
+        }
+    }
+
     /// Splits a list of [Action]s into as many individual [Action]s as possible.
     ///
     /// For example, an [Action::Command] can contain many commands. To provide the most
@@ -481,6 +943,14 @@ impl Action {
     ///         group: "root".to_owned(),
     ///         permissions: None,
     ///         overwrite: false,
+    ///         encrypt: false,
+    ///         seuser: None,
+    ///         serole: None,
+    ///         setype: None,
+    ///         acl: vec![],
+    ///         transfer_permissions: None,
+    ///         xattrs: Default::default(),
+    ///         capabilities: None,
     ///     },
     /// ];
     ///
@@ -496,6 +966,14 @@ impl Action {
     ///         group: "root".to_owned(),
     ///         permissions: None,
     ///         overwrite: false,
+    ///         encrypt: false,
+    ///         seuser: None,
+    ///         serole: None,
+    ///         setype: None,
+    ///         acl: vec![],
+    ///         transfer_permissions: None,
+    ///         xattrs: Default::default(),
+    ///         capabilities: None,
     ///     },
     /// ];
     /// assert_eq!(expected, actions);
@@ -511,9 +989,13 @@ impl Action {
                         .iter()
                         .map(|command| Command(vec![command.to_owned()])),
                 ),
-                action @ LineInFile { .. } | action @ Upload { .. } | action @ Script { .. } => {
-                    output.push(action.to_owned())
-                }
+                action @ ControllerKey { .. }
+                | action @ Custom { .. }
+                | action @ KernelModule { .. }
+                | action @ LineInFile { .. }
+                | action @ Upload { .. }
+                | action @ Script { .. }
+                | action @ Swap { .. } => output.push(action.to_owned()),
             }
         }
         *list = output;
@@ -524,7 +1006,8 @@ impl Action {
         true
     }
 
-    const DEFAULT_USER_AND_GROUP: &'static str = "root";
+    /// The user and group that own uploads, and the user that runs scripts, unless set otherwise.
+    pub const DEFAULT_USER_AND_GROUP: &'static str = "root";
 
     /// Provides the default user and group when deserializing.
     fn default_user_and_group() -> String {
@@ -540,6 +1023,12 @@ impl Action {
     fn default_overwrite() -> bool {
         true
     }
+
+    /// Provides the default value for [Action::KernelModule::persist] and [Action::Swap::persist]
+    /// when deserializing.
+    fn default_persist() -> bool {
+        true
+    }
 }
 
 /// An [Action] in the context of a single [Manifest], [Task], and host.
@@ -548,30 +1037,25 @@ impl Action {
 /// information needed to run an [Action] on a given host as well as information about where the
 /// [Action] was specified, for informational, logging, and debugging purposes.
 ///
-/// # Optimization
-///
-/// Note that this type has a lot of room for optimization. Speed shouldn't be a factor at the
-/// scale Sira is designed for, but it's worth noting briefly that this type makes an awful lot of
-/// copies. [HostAction] values get passed throughout the program and across threads, so references
-/// are not a great fit.
+/// # Sharing
 ///
-/// Optimizing this type is a good candidate for future work. An easy approach would be to pare
-/// down unneeded fields before making copies. For instance, with the [Manifest], you could strip
-/// away the [Task]s, since this type shouldn't need them. There are more aggressive options, too.
+/// [HostAction] values get passed throughout the program and across threads, so they own their
+/// data rather than borrowing it from the [Plan]. To keep that cheap on large plans, the [Manifest]
+/// and [Task] are behind [Arc]s: a [Plan]'s iterators copy each [Manifest] and [Task] at most once
+/// per host, or just once for every host with a [SharedPlan], and every [HostAction] from the same
+/// [Task] shares those copies. Only the [Action] itself is copied for each [HostAction].
 ///
-/// A safer option might be to move aggressively into using [Arc]s for storing [Manifest]s,
-/// [Task]s, and [Action]s both here and in their normal [Plan]-[Manifest]-[Task]-[Action]
-/// hierarchy.
+/// [SharedPlan]: crate::core::plan::SharedPlan
 #[derive(Clone, Debug, PartialEq)]
 pub struct HostAction {
     /// The host on which this [Action] should run.
     host: String,
 
     /// The [Manifest] that listed the [Task] containing this [Action].
-    manifest: Manifest,
+    manifest: Arc<Manifest>,
 
     /// The [Task] that listed this [Action].
-    task: Task,
+    task: Arc<Task>,
 
     /// The [Action] to be executed on the host.
     action: Action,
@@ -583,7 +1067,8 @@ impl HostAction {
     /// # Panics
     ///
     /// Panics if the values provided are not sane. For instance, `manifest` must specify that
-    /// `task` run on `host`, and `task` must specify `action`. Violating these sanity checks would
+    /// `task` run on `host`, and `task` must specify `action`, either in [Task::actions] or in
+    /// [Task::verify]. Violating these sanity checks would
     /// result in unwanted (though well-defined) behavior and is clearly a bug in the calling code.
     pub fn new<'plan>(
         host: &'plan str,
@@ -612,7 +1097,7 @@ impl HostAction {
             task,
         );
         assert!(
-            task.actions.iter().any(|act| act == action),
+            task.actions.iter().chain(&task.verify).any(|act| act == action),
             "Cannot create HostAction for manifest \"{}\" and task \"{}\" because the task does not \
             include this action:\n\
             {:?}\n\
@@ -623,11 +1108,30 @@ impl HostAction {
             action,
         );
 
+        HostAction::shared(
+            host,
+            Arc::new(manifest.clone()),
+            Arc::new(task.clone()),
+            action.clone(),
+        )
+    }
+
+    /// Creates a new [HostAction] that shares `manifest` and `task` with other [HostAction]s.
+    ///
+    /// Unlike [HostAction::new], doesn't check that `manifest` includes `task` on `host`, or that
+    /// `task` includes `action`, since those checks compare whole [Task]s. Callers must only pass
+    /// values that they took from the same [Manifest], e.g. while iterating over it.
+    pub(in crate::core) fn shared(
+        host: &str,
+        manifest: Arc<Manifest>,
+        task: Arc<Task>,
+        action: Action,
+    ) -> Self {
         HostAction {
             host: host.to_string(),
-            manifest: manifest.clone(),
-            task: task.clone(),
-            action: action.clone(),
+            manifest,
+            task,
+            action,
         }
     }
 
@@ -657,7 +1161,8 @@ impl HostAction {
     ///
     /// [Task] variables take precedence over [Manifest] variables. For example, if in
     /// [Manifest::vars] you set the variable `breakfast` to be `cake` and in [Task::vars] you set
-    /// `breakfast` to be `pie`, the final value of `breakfast` will be `pie`.
+    /// `breakfast` to be `pie`, the final value of `breakfast` will be `pie`. Both take precedence
+    /// over the host's own variables in [Manifest::host_vars], e.g. from an inventory.
     ///
     /// # Variable substitution
     ///
@@ -679,81 +1184,115 @@ impl HostAction {
     /// remote host. As long as they do not match the above substitution rules, they will pass
     /// through to the remote host's shell unchanged.
     ///
+    /// # Escaping
+    ///
+    /// To pass `$var` or `${var}` through unchanged even though `var` is defined, e.g. in a shell
+    /// script that uses a shell variable of the same name, double the dollar sign: `$$var` and
+    /// `$${var}` compile to `$var` and `${var}`. A doubled dollar sign that isn't followed by a
+    /// defined variable's name, e.g. the shell's `$$` (its process ID), remains unchanged.
+    ///
     /// # Substitution order
     ///
     /// Variables are substituted in the order in which they are defined, and variables defined in
-    /// [Manifest::vars] are substituted before variables defined in [Task::vars]. By relying on
+    /// [Manifest::vars] are substituted before variables defined in [Task::vars]. The host's own
+    /// variables come first of all. By relying on
     /// this ordering, it is possible to use cascading variable substitutions to a limited degree,
     /// though this generally is not recommended.
+    ///
+    /// # Default user
+    ///
+    /// An [Action::Script] that doesn't set its own user gets [Task::user], if set. Like the rest
+    /// of the [Task]'s settings, [Task::user] is used as is, without variable substitution.
     pub fn compile(&self) -> Action {
         let mut action = self.action.clone();
 
         // To implement variable substitution rules with precedence, we merge variables, in order,
         // and then substitute, again in order.
-        let mut vars = self.manifest.vars.clone();
-        for (var, value) in &self.task.vars {
-            let _ = vars.insert(var.clone(), value.clone());
-        }
+        let vars = self.vars();
 
         // Substitute variables. In order to prevent accidentally recursively substituting
         // variables in some strange corner and edge cases, we use a single regular expression
         // rather than two naive string substitution passes.
         for (var, value) in vars {
-            // Form a regular expression that matches $<var> (as a whole word) and ${<var>} where
-            // <var> is the name of the variable.
-            let pattern = format!(r"\${var}\b|\$\{{{var}}}");
-            let regex = Regex::new(&pattern).unwrap();
-
-            // Build an ergonomic regex replacer so we can write DRY code below.
-            let replace = |s: &mut String| {
-                let _ = std::mem::replace(s, regex.replace_all(s, NoExpand(&value)).into_owned());
-            };
+            let regex = substitution_regex(&var);
 
             // Run the replacement across all fields of the Action.
-            use Action::*;
-            match &mut action {
-                Command(commands) => {
-                    commands.iter_mut().for_each(replace);
-                }
-                LineInFile {
-                    path,
-                    line,
-                    pattern,
-                    after,
-                    indent: _,
-                } => {
-                    replace(path);
-                    replace(line);
-                    pattern.as_mut().map(replace);
-                    after.as_mut().map(replace);
-                }
-                Script {
-                    name,
-                    user,
-                    contents,
-                } => {
-                    replace(name);
-                    replace(user);
-                    replace(contents);
-                }
-                Upload {
-                    from,
-                    to,
-                    user,
-                    group,
-                    permissions,
-                    overwrite: _,
-                } => {
-                    replace(from);
-                    replace(to);
-                    replace(user);
-                    replace(group);
-                    permissions.as_mut().map(replace);
+            // Most strings don't mention most variables, so only replace strings that changed.
+            // An escaped reference loses its extra dollar sign instead.
+            action.map_strings(|s: &mut String| {
+                let replaced = regex.replace_all(s, |captures: &Captures| match &captures[1] {
+                    "" => value.clone(),
+                    _ => captures[0][1..].to_string(),
+                });
+                if let Cow::Owned(replaced) = replaced {
+                    *s = replaced;
                 }
-            }
+            });
+        }
+
+        // A script that doesn't name a user runs as the task's user, if any.
+        if let Action::Script {
+            user: user @ None, ..
+        } = &mut action
+        {
+            user.clone_from(&self.task.user);
         }
         action
     }
+
+    /// Returns a [Redactor] that masks the values of this action's sensitive variables.
+    ///
+    /// A variable is sensitive if either [Manifest::sensitive] or [Task::sensitive] lists its
+    /// name. The value masked is the variable's final value, i.e. after applying the precedence
+    /// rules described in [HostAction::compile].
+    pub fn redactor(&self) -> Redactor {
+        let sensitive = |name: &String| {
+            self.manifest.sensitive.contains(name) || self.task.sensitive.contains(name)
+        };
+        Redactor::new(
+            self.vars()
+                .into_iter()
+                .filter(|(name, _)| sensitive(name))
+                .map(|(_, value)| value),
+        )
+    }
+
+    /// Merges the host's [Manifest::host_vars], [Manifest::vars], and [Task::vars], giving
+    /// precedence to the latter.
+    fn vars(&self) -> IndexMap<String, String> {
+        let mut vars = self
+            .manifest
+            .host_vars
+            .get(&self.host)
+            .cloned()
+            .unwrap_or_default();
+        for (var, value) in self.manifest.vars.iter().chain(&self.task.vars) {
+            let _ = vars.insert(var.clone(), value.clone());
+        }
+        vars
+    }
+}
+
+/// Returns a regular expression that matches `$<var>` (as a whole word) and `${<var>}`, where
+/// `<var>` is `var`, along with an optional extra dollar sign in front that escapes them. The
+/// first group captures that extra dollar sign, if any.
+///
+/// Every action on every host substitutes the same few variables, so the expressions are compiled
+/// once per variable name and cached for the life of the process.
+fn substitution_regex(var: &str) -> Arc<Regex> {
+    // Share each Regex rather than cloning it: a clone shares the compiled expression but starts
+    // with empty search caches, which made rebuilding them the slowest part of compiling an action.
+    static CACHE: OnceLock<Mutex<HashMap<String, Arc<Regex>>>> = OnceLock::new();
+    let mut cache = CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(regex) = cache.get(var) {
+        return Arc::clone(regex);
+    }
+    let regex = Arc::new(Regex::new(&format!(r"(\$?)\$(?:{var}\b|\{{{var}}})")).unwrap());
+    let _ = cache.insert(var.to_string(), Arc::clone(&regex));
+    regex
 }
 
 /// Trivial function for use with `skip_serializing_if`.
@@ -761,6 +1300,27 @@ fn is_true(var: &bool) -> bool {
     *var
 }
 
+/// Used with `#[serde(skip_serializing_if)]`.
+fn is_false(var: &bool) -> bool {
+    !*var
+}
+
+/// Applies `f` to every string within `value`, including in nested arrays and objects, but not to
+/// object keys.
+fn map_json_strings(value: &mut serde_json::Value, f: &mut impl FnMut(&mut String)) {
+    use serde_json::Value;
+    match value {
+        Value::String(string) => f(string),
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| map_json_strings(value, f)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|value| map_json_strings(value, f)),
+        Value::Null | Value::Bool(_) | Value::Number(_) => (),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::fixtures::plan;
@@ -811,6 +1371,104 @@ mod tests {
                 }
             }
 
+            mod controller_key {
+                use super::*;
+
+                #[test]
+                fn works() {
+                    let yaml = "\
+controller_key:
+  login_key: a
+  action_key: b
+  remove: true\n";
+                    let action = Action::ControllerKey {
+                        login_key: Some("a".to_string()),
+                        action_key: Some("b".to_string()),
+                        remove: true,
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn defaults_to_adding_no_keys() {
+                    let yaml = "controller_key: {}\n";
+                    let action = Action::ControllerKey {
+                        login_key: None,
+                        action_key: None,
+                        remove: false,
+                    };
+                    check(yaml, action);
+                }
+            }
+
+            mod custom {
+                use super::*;
+
+                #[test]
+                fn works() {
+                    let yaml = "\
+custom:
+  plugin: firewall
+  args:
+    port: 443
+    protocols:
+    - tcp\n";
+                    let action = Action::Custom {
+                        plugin: "firewall".to_string(),
+                        args: serde_json::json!({"port": 443, "protocols": ["tcp"]}),
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn args_are_optional() {
+                    let yaml = "\
+custom:
+  plugin: firewall\n";
+                    let action = Action::Custom {
+                        plugin: "firewall".to_string(),
+                        args: serde_json::Value::Null,
+                    };
+                    check(yaml, action);
+                }
+            }
+
+            mod kernel_module {
+                use super::*;
+
+                #[test]
+                fn works() {
+                    let yaml = "\
+kernel_module:
+  name: nf_conntrack
+  options:
+  - hashsize=262144
+  persist: false
+  remove: true\n";
+                    let action = Action::KernelModule {
+                        name: "nf_conntrack".to_string(),
+                        options: vec!["hashsize=262144".to_string()],
+                        persist: false,
+                        remove: true,
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn defaults_to_loading_and_persisting() {
+                    let yaml = "\
+kernel_module:
+  name: br_netfilter\n";
+                    let action = Action::KernelModule {
+                        name: "br_netfilter".to_string(),
+                        options: vec![],
+                        persist: true,
+                        remove: false,
+                    };
+                    check(yaml, action);
+                }
+            }
+
             mod line_in_file {
                 use super::*;
 
@@ -900,27 +1558,62 @@ script:
   contents: c\n";
                     let action = Action::Script {
                         name: "a".to_string(),
-                        user: "b".to_string(),
+                        user: Some("b".to_string()),
                         contents: "c".to_string(),
                     };
                     check(yaml, action);
                 }
 
                 #[test]
-                fn user_defaults_to_root() {
+                fn user_is_optional() {
                     let yaml = "\
 script:
   name: a
   contents: c\n";
                     let action = Action::Script {
                         name: "a".to_string(),
-                        user: "root".to_string(),
+                        user: None,
                         contents: "c".to_string(),
                     };
                     check(yaml, action);
                 }
             }
 
+            mod swap {
+                use super::*;
+
+                #[test]
+                fn works() {
+                    let yaml = "\
+swap:
+  path: /swapfile
+  size: 2G
+  persist: false
+  remove: true\n";
+                    let action = Action::Swap {
+                        path: "/swapfile".to_string(),
+                        size: Some("2G".to_string()),
+                        persist: false,
+                        remove: true,
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn defaults_to_enabling_and_persisting() {
+                    let yaml = "\
+swap:
+  path: /dev/sdb2\n";
+                    let action = Action::Swap {
+                        path: "/dev/sdb2".to_string(),
+                        size: None,
+                        persist: true,
+                        remove: false,
+                    };
+                    check(yaml, action);
+                }
+            }
+
             mod upload {
                 use super::*;
 
@@ -941,6 +1634,14 @@ upload:
                         group: "d".to_string(),
                         permissions: Some("e".to_string()),
                         overwrite: false,
+                        encrypt: false,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
                     };
                     check(yaml, action);
                 }
@@ -961,6 +1662,14 @@ upload:
                         group: "d".to_string(),
                         permissions: Some("e".to_string()),
                         overwrite: false,
+                        encrypt: false,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
                     };
                     check(yaml, action);
                 }
@@ -981,6 +1690,14 @@ upload:
                         group: "root".to_string(),
                         permissions: Some("e".to_string()),
                         overwrite: false,
+                        encrypt: false,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
                     };
                     check(yaml, action);
                 }
@@ -1001,6 +1718,125 @@ upload:
                         group: "d".to_string(),
                         permissions: None,
                         overwrite: false,
+                        encrypt: false,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn encrypt_works() {
+                    let yaml = "\
+upload:
+  from: a
+  to: b
+  encrypt: true\n";
+                    let action = Action::Upload {
+                        from: "a".to_string(),
+                        to: "b".to_string(),
+                        user: "root".to_string(),
+                        group: "root".to_string(),
+                        permissions: None,
+                        overwrite: true,
+                        encrypt: true,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn selinux_context_works() {
+                    let yaml = "\
+upload:
+  from: a
+  to: b
+  seuser: system_u
+  serole: object_r
+  setype: httpd_sys_content_t\n";
+                    let action = Action::Upload {
+                        from: "a".to_string(),
+                        to: "b".to_string(),
+                        user: "root".to_string(),
+                        group: "root".to_string(),
+                        permissions: None,
+                        overwrite: true,
+                        encrypt: false,
+                        seuser: Some("system_u".to_string()),
+                        serole: Some("object_r".to_string()),
+                        setype: Some("httpd_sys_content_t".to_string()),
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn acl_and_transfer_permissions_work() {
+                    let yaml = "\
+upload:
+  from: a
+  to: b
+  acl:
+  - u:deploy:r
+  - g:web:rw
+  transfer_permissions: '600'\n";
+                    let action = Action::Upload {
+                        from: "a".to_string(),
+                        to: "b".to_string(),
+                        user: "root".to_string(),
+                        group: "root".to_string(),
+                        permissions: None,
+                        overwrite: true,
+                        encrypt: false,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec!["u:deploy:r".to_string(), "g:web:rw".to_string()],
+                        transfer_permissions: Some("600".to_string()),
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
+                    };
+                    check(yaml, action);
+                }
+
+                #[test]
+                fn xattrs_and_capabilities_work() {
+                    let yaml = "\
+upload:
+  from: a
+  to: b
+  xattrs:
+    user.origin: sira
+  capabilities: cap_net_bind_service=+ep\n";
+                    let action = Action::Upload {
+                        from: "a".to_string(),
+                        to: "b".to_string(),
+                        user: "root".to_string(),
+                        group: "root".to_string(),
+                        permissions: None,
+                        overwrite: true,
+                        encrypt: false,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::from([("user.origin".to_string(), "sira".to_string())]),
+                        capabilities: Some("cap_net_bind_service=+ep".to_string()),
                     };
                     check(yaml, action);
                 }
@@ -1021,6 +1857,14 @@ upload:
                         group: "d".to_string(),
                         permissions: Some("e".to_string()),
                         overwrite: true,
+                        encrypt: false,
+                        seuser: None,
+                        serole: None,
+                        setype: None,
+                        acl: vec![],
+                        transfer_permissions: None,
+                        xattrs: BTreeMap::new(),
+                        capabilities: None,
                     };
                     check(yaml, action);
                 }
@@ -1048,7 +1892,7 @@ upload:
                 },
                 Script {
                     name: "e".to_string(),
-                    user: "g".to_string(),
+                    user: Some("g".to_string()),
                     contents: "h".to_string(),
                 },
                 Upload {
@@ -1058,6 +1902,14 @@ upload:
                     group: "k".to_string(),
                     permissions: Some("l".to_string()),
                     overwrite: true,
+                    encrypt: false,
+                    seuser: None,
+                    serole: None,
+                    setype: None,
+                    acl: vec![],
+                    transfer_permissions: None,
+                    xattrs: BTreeMap::new(),
+                    capabilities: None,
                 },
             ];
 
@@ -1073,7 +1925,7 @@ upload:
                 },
                 Script {
                     name: "e".to_string(),
-                    user: "g".to_string(),
+                    user: Some("g".to_string()),
                     contents: "h".to_string(),
                 },
                 Upload {
@@ -1083,6 +1935,14 @@ upload:
                     group: "k".to_string(),
                     permissions: Some("l".to_string()),
                     overwrite: true,
+                    encrypt: false,
+                    seuser: None,
+                    serole: None,
+                    setype: None,
+                    acl: vec![],
+                    transfer_permissions: None,
+                    xattrs: BTreeMap::new(),
+                    capabilities: None,
                 },
             ];
 
@@ -1103,8 +1963,8 @@ upload:
                 let (_, manifest, task, action) = plan();
                 let host_action = HostAction::new(&manifest.hosts[0], &manifest, &task, &action);
                 assert_eq!(manifest.hosts[0], host_action.host);
-                assert_eq!(manifest, host_action.manifest);
-                assert_eq!(task, host_action.task);
+                assert_eq!(manifest, *host_action.manifest);
+                assert_eq!(task, *host_action.task);
                 assert_eq!(action, host_action.action);
             }
 
@@ -1125,6 +1985,15 @@ upload:
                     name: "task-not-included".into(),
                     actions: vec![],
                     vars: IndexMap::new(),
+                    sensitive: Vec::new(),
+                    confirm: false,
+                    user: None,
+                    escalate: false,
+                    dpkg_lock_timeout: None,
+                    limits: Default::default(),
+                    sandbox: None,
+                    independent: false,
+                    verify: Vec::new(),
                 };
                 HostAction::new(&manifest.hosts[0], &manifest, &task, &action);
             }
@@ -1202,20 +2071,32 @@ upload:
                     source: Some(PathBuf::from(base.clone())),
                     name: base.clone(),
                     hosts: vec![base.clone()],
+                    fact: None,
+                    host_vars: Default::default(),
                     include: vec![Task {
                         source: Some(PathBuf::from(base.clone())),
                         name: base.clone(),
                         actions: vec![Action::Command(vec![action_string.into()])],
                         vars: task_vars,
+                        sensitive: Vec::new(),
+                        confirm: false,
+                        user: None,
+                        escalate: false,
+                        dpkg_lock_timeout: None,
+                        limits: Default::default(),
+                        sandbox: None,
+                        independent: false,
+                        verify: Vec::new(),
                     }],
                     vars: manifest_vars,
+                    sensitive: Vec::new(),
                 };
                 let task = manifest.include[0].clone();
                 let action = task.actions[0].clone();
                 let host_action = HostAction {
                     host: "compile-test".to_owned(),
-                    manifest,
-                    task,
+                    manifest: Arc::new(manifest),
+                    task: Arc::new(task),
                     action,
                 };
 
@@ -1237,11 +2118,32 @@ upload:
                     source: Some(PathBuf::from(base.clone())),
                     name: base.clone(),
                     hosts: vec![base.clone()],
+                    fact: None,
+                    host_vars: Default::default(),
                     include: vec![Task {
                         source: Some(PathBuf::from(base.clone())),
                         name: base.clone(),
                         actions: vec![
                             Command(vec![action_string.clone()]),
+                            ControllerKey {
+                                login_key: Some(action_string.clone()),
+                                action_key: Some(action_string.clone()),
+                                remove: false,
+                            },
+                            Custom {
+                                plugin: action_string.clone(),
+                                args: serde_json::json!({
+                                    "name": action_string.clone(),
+                                    "list": [action_string.clone()],
+                                    "port": 443,
+                                }),
+                            },
+                            KernelModule {
+                                name: action_string.clone(),
+                                options: vec![action_string.clone()],
+                                persist: true,
+                                remove: false,
+                            },
                             LineInFile {
                                 path: action_string.clone(),
                                 line: action_string.clone(),
@@ -1251,9 +2153,15 @@ upload:
                             },
                             Script {
                                 name: action_string.clone(),
-                                user: action_string.clone(),
+                                user: Some(action_string.clone()),
                                 contents: action_string.clone(),
                             },
+                            Swap {
+                                path: action_string.clone(),
+                                size: Some(action_string.clone()),
+                                persist: true,
+                                remove: false,
+                            },
                             Upload {
                                 from: action_string.clone(),
                                 to: action_string.clone(),
@@ -1261,18 +2169,36 @@ upload:
                                 group: action_string.clone(),
                                 permissions: Some(action_string.clone()),
                                 overwrite: true,
+                                encrypt: false,
+                                seuser: None,
+                                serole: None,
+                                setype: None,
+                                acl: vec![],
+                                transfer_permissions: None,
+                                xattrs: BTreeMap::new(),
+                                capabilities: None,
                             },
                         ],
                         vars: IndexMap::new(),
+                        sensitive: Vec::new(),
+                        confirm: false,
+                        user: None,
+                        escalate: false,
+                        dpkg_lock_timeout: None,
+                        limits: Default::default(),
+                        sandbox: None,
+                        independent: false,
+                        verify: Vec::new(),
                     }],
                     vars: manifest_vars,
+                    sensitive: Vec::new(),
                 };
                 let task = manifest.include[0].clone();
 
                 let mut host_action = HostAction {
                     host: base,
-                    manifest,
-                    task: task.clone(),
+                    manifest: Arc::new(manifest),
+                    task: Arc::new(task.clone()),
                     // Placeholder Action; we'll populate this below.
                     action: Command(vec![]),
                 };
@@ -1282,6 +2208,25 @@ upload:
                 for action in task.actions {
                     let expected = match action {
                         Command(_) => Command(vec![expected_string.clone()]),
+                        ControllerKey { .. } => ControllerKey {
+                            login_key: Some(expected_string.clone()),
+                            action_key: Some(expected_string.clone()),
+                            remove: false,
+                        },
+                        Custom { .. } => Custom {
+                            plugin: expected_string.clone(),
+                            args: serde_json::json!({
+                                "name": expected_string.clone(),
+                                "list": [expected_string.clone()],
+                                "port": 443,
+                            }),
+                        },
+                        KernelModule { .. } => KernelModule {
+                            name: expected_string.clone(),
+                            options: vec![expected_string.clone()],
+                            persist: true,
+                            remove: false,
+                        },
                         LineInFile { .. } => LineInFile {
                             path: expected_string.clone(),
                             line: expected_string.clone(),
@@ -1291,9 +2236,15 @@ upload:
                         },
                         Script { .. } => Script {
                             name: expected_string.clone(),
-                            user: expected_string.clone(),
+                            user: Some(expected_string.clone()),
                             contents: expected_string.clone(),
                         },
+                        Swap { .. } => Swap {
+                            path: expected_string.clone(),
+                            size: Some(expected_string.clone()),
+                            persist: true,
+                            remove: false,
+                        },
                         Upload { .. } => Upload {
                             from: expected_string.clone(),
                             to: expected_string.clone(),
@@ -1301,6 +2252,14 @@ upload:
                             group: expected_string.clone(),
                             permissions: Some(expected_string.clone()),
                             overwrite: true,
+                            encrypt: false,
+                            seuser: None,
+                            serole: None,
+                            setype: None,
+                            acl: vec![],
+                            transfer_permissions: None,
+                            xattrs: BTreeMap::new(),
+                            capabilities: None,
                         },
                     };
 
@@ -1373,6 +2332,135 @@ upload:
                     )
                 );
             }
+
+            #[test]
+            fn doubled_dollar_signs_escape_variables() {
+                assert_eq!(
+                    "for x in $x ${x}; do echo $x and $$; done",
+                    compile(
+                        &[("x", "FAIL")],
+                        &[],
+                        "for x in $$x $${x}; do echo $$x and $$; done",
+                    ),
+                );
+                assert_eq!(
+                    "kill $$ $$y $$xy 1",
+                    compile(&[("x", "1")], &[], "kill $$ $$y $$xy $x"),
+                );
+            }
+
+            #[test]
+            fn scripts_default_to_task_user() {
+                let (_, manifest, mut task, _) = plan();
+                task.user = Some("app".to_string());
+                let script = |user: Option<&str>| Action::Script {
+                    name: "Build".to_string(),
+                    user: user.map(str::to_string),
+                    contents: "#!/bin/sh\n".to_string(),
+                };
+                let compile = |action| {
+                    HostAction {
+                        host: manifest.hosts[0].clone(),
+                        manifest: Arc::new(manifest.clone()),
+                        task: Arc::new(task.clone()),
+                        action,
+                    }
+                    .compile()
+                };
+                assert_eq!(script(Some("app")), compile(script(None)));
+                assert_eq!(script(Some("root")), compile(script(Some("root"))));
+            }
+
+            #[test]
+            fn host_vars_have_lowest_precedence() {
+                let (_, mut manifest, mut task, _) = plan();
+                let host = manifest.hosts[0].clone();
+                let host_vars = IndexMap::from([
+                    ("role".to_string(), "web".to_string()),
+                    ("port".to_string(), "8080".to_string()),
+                    ("user".to_string(), "alice".to_string()),
+                ]);
+                let _ = manifest.host_vars.insert(host.clone(), host_vars);
+                let _ = manifest
+                    .host_vars
+                    .insert("other".to_string(), IndexMap::new());
+                let _ = manifest.vars.insert("port".into(), "80".into());
+                let _ = task.vars.insert("user".into(), "bob".into());
+                let host_action = HostAction {
+                    host,
+                    manifest: Arc::new(manifest),
+                    task: Arc::new(task),
+                    action: Action::Command(vec!["echo $role $port $user".into()]),
+                };
+                assert_eq!(
+                    Action::Command(vec!["echo web 80 bob".into()]),
+                    host_action.compile(),
+                );
+            }
+        }
+
+        mod redactor {
+            use super::*;
+
+            #[test]
+            fn masks_values_of_sensitive_variables() {
+                let (_, mut manifest, mut task, action) = plan();
+                let _ = manifest.vars.insert("a".into(), "secret-a".into());
+                let _ = manifest.vars.insert("b".into(), "public".into());
+                let _ = task.vars.insert("c".into(), "secret-c".into());
+                manifest.sensitive = vec!["a".into()];
+                task.sensitive = vec!["c".into()];
+                let host_action = HostAction {
+                    host: manifest.hosts[0].clone(),
+                    manifest: Arc::new(manifest),
+                    task: Arc::new(task),
+                    action,
+                };
+                assert_eq!(
+                    "******** public ********",
+                    host_action
+                        .redactor()
+                        .redact_str("secret-a public secret-c"),
+                );
+            }
+
+            #[test]
+            fn masks_task_override_of_sensitive_variable() {
+                let (_, mut manifest, mut task, action) = plan();
+                let _ = manifest.vars.insert("a".into(), "old".into());
+                let _ = task.vars.insert("a".into(), "new".into());
+                manifest.sensitive = vec!["a".into()];
+                let host_action = HostAction {
+                    host: manifest.hosts[0].clone(),
+                    manifest: Arc::new(manifest),
+                    task: Arc::new(task),
+                    action,
+                };
+                assert_eq!("old ********", host_action.redactor().redact_str("old new"));
+            }
+        }
+    }
+
+    mod substitution_regex {
+        use super::*;
+
+        #[test]
+        fn matches_simple_and_braced_forms() {
+            let regex = substitution_regex("foo");
+            assert_eq!(
+                "X X $foobar X.baz",
+                regex.replace_all("$foo ${foo} $foobar $foo.baz", "X"),
+            );
+        }
+
+        #[test]
+        fn captures_escaping_dollar_sign() {
+            let regex = substitution_regex("foo");
+            let escapes: Vec<_> = regex
+                .captures_iter("$foo $$foo $${foo} $$ $$foobar")
+                .map(|captures| captures[1].to_string())
+                .collect();
+            assert_eq!(vec!["", "$", "$"], escapes);
         }
     }
 }
@@ -0,0 +1,90 @@
+//! Signed actions that a managed node applies on its own schedule, e.g. at its next boot.
+//!
+//! Laptops and edge devices are often offline when Sira runs. For these, `sira queue` compiles
+//! and signs each host's actions ahead of time and drops them onto the managed node as a [Bundle]
+//! in [QUEUE_DIR]. Later, `sira-client apply-queued`, e.g. run by a systemd timer or at boot,
+//! applies each queued bundle in turn.
+//!
+//! Each action in a bundle is still checked like any other: `sira-client` verifies its signature
+//! and expiration time and applies any action policy before running it. Because a bundle may wait
+//! days to be applied, its envelopes expire much later than usual (please see
+//! [Envelope::with_lifetime](super::Envelope::with_lifetime)).
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+/// The directory on managed nodes in which bundles wait to be applied.
+pub const QUEUE_DIR: &str = "/var/lib/sira/queue";
+
+/// The `sira-client` subcommand that applies queued bundles.
+pub const APPLY_QUEUED_COMMAND: &str = "apply-queued";
+
+/// A host's compiled, signed actions, in the order in which to apply them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bundle {
+    /// The actions to apply.
+    pub actions: Vec<QueuedAction>,
+}
+
+/// One action in a [Bundle], exactly as the control node would otherwise send it over SSH.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueuedAction {
+    /// The signed [Envelope](super::Envelope), in YAML.
+    pub envelope: String,
+
+    /// The signature of [QueuedAction::envelope], if actions are signed.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub signature: Option<String>,
+
+    /// For an [Action::Upload](super::Action::Upload), the contents of the file to upload, in
+    /// lowercase hexadecimal. Encrypted uploads carry the encrypted file.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub file: Option<String>,
+}
+
+impl Bundle {
+    /// Parses a bundle from YAML.
+    pub fn from_yaml(yaml: &str) -> anyhow::Result<Self> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    /// Serializes the bundle as YAML.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("could not serialize Bundle")
+    }
+}
+
+impl QueuedAction {
+    /// Returns the contents of the file to upload, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [QueuedAction::file] isn't valid hexadecimal.
+    pub fn file_contents(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        self.file.as_deref().map(decode_hex).transpose()
+    }
+}
+
+/// Encodes `bytes` in lowercase hexadecimal, e.g. for [QueuedAction::file].
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decodes hexadecimal, e.g. from [QueuedAction::file].
+pub fn decode_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        bail!("hexadecimal data has an odd number of digits");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            let digits = hex.get(i..i + 2).context("invalid hexadecimal data")?;
+            u8::from_str_radix(digits, 16).context("invalid hexadecimal data")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test;
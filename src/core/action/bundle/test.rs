@@ -0,0 +1,60 @@
+use super::*;
+
+mod hex {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let bytes = b"\x00\x7fsira\xff";
+        assert_eq!("007f73697261ff", encode_hex(bytes));
+        assert_eq!(bytes.to_vec(), decode_hex("007f73697261ff").unwrap());
+    }
+
+    #[test]
+    fn accepts_uppercase() {
+        assert_eq!(vec![0xab], decode_hex("AB").unwrap());
+    }
+
+    #[test]
+    fn rejects_odd_length() {
+        assert!(decode_hex("abc").is_err());
+    }
+
+    #[test]
+    fn rejects_non_hex() {
+        assert!(decode_hex("zz").is_err());
+        assert!(decode_hex("aé1").is_err());
+    }
+}
+
+mod bundle {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_yaml() {
+        let bundle = Bundle {
+            actions: vec![
+                QueuedAction {
+                    envelope: "expires: x\naction:\n  command:\n  - true\n".to_string(),
+                    signature: Some("-----BEGIN SSH SIGNATURE-----".to_string()),
+                    file: None,
+                },
+                QueuedAction {
+                    envelope: "expires: x\naction:\n  upload: {}\n".to_string(),
+                    signature: None,
+                    file: Some(encode_hex(b"contents")),
+                },
+            ],
+        };
+        assert_eq!(bundle, Bundle::from_yaml(&bundle.to_yaml()).unwrap());
+        assert_eq!(
+            Some(b"contents".to_vec()),
+            bundle.actions[1].file_contents().unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(Bundle::from_yaml("actions: []\nhosts: []\n").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Client-side logic for [Action::ControllerKey].
+
+use super::Action;
+use crate::crypto;
+use anyhow::{anyhow, bail, Context};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Implements client-side logic for [Action::ControllerKey].
+///
+/// `authorized_keys` is the Sira user's `authorized_keys` file, which must already exist.
+/// `allowed_signers` is the action allowed signers file, which is created if needed.
+///
+/// # Returns
+///
+/// Returns `Ok(())` on success, regardless of whether either file was modified.
+///
+/// # Errors
+///
+/// Returns an error if a key isn't an SSH public key, if a file can't be read or written, or if
+/// removing a key would leave a file with no keys at all.
+///
+/// # Panics
+///
+/// Panics if `action` is not of type [Action::ControllerKey].
+pub fn controller_key(
+    action: &Action,
+    authorized_keys: impl AsRef<Path>,
+    allowed_signers: impl AsRef<Path>,
+) -> anyhow::Result<()> {
+    let (login_key, action_key, &remove) = match action {
+        Action::ControllerKey {
+            login_key,
+            action_key,
+            remove,
+        } => (login_key, action_key, remove),
+        _ => {
+            panic!("called controller_key with an Action that was not a ControllerKey: {action:?}")
+        }
+    };
+
+    if let Some(login_key) = login_key {
+        let line = login_key.trim();
+        update_key_file(authorized_keys.as_ref(), line, remove, false)?;
+    }
+
+    if let Some(action_key) = action_key {
+        let line = crypto::allowed_signers_line(action_key)?;
+        update_key_file(allowed_signers.as_ref(), line.trim(), remove, true)?;
+    }
+    Ok(())
+}
+
+/// Adds `line` to, or removes its key from, the key file at `path`.
+///
+/// If `create` is true and the file does not exist, adding a key creates the file.
+fn update_key_file(path: &Path, line: &str, remove: bool, create: bool) -> anyhow::Result<()> {
+    let key = key_data(line).ok_or_else(|| anyhow!("not an SSH public key: {line}"))?;
+
+    let file = match fs::read_to_string(path) {
+        Ok(file) => file,
+        Err(e) if create && !remove && e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let updated = match remove {
+        false => add_key(&file, line, key),
+        true => {
+            let updated = remove_key(&file, key);
+            if matches!(&updated, Some(updated) if !file_has_keys(updated)) {
+                bail!(
+                    "refusing to remove the last key from {}: the managed node would lose its \
+                    only trusted control node",
+                    path.display(),
+                );
+            }
+            updated
+        }
+    };
+
+    if let Some(updated) = updated {
+        fs::write(path, updated).with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Returns `file` with `line` appended, or [None] if `file` already contains `key`.
+fn add_key(file: &str, line: &str, key: (&str, &str)) -> Option<String> {
+    if file.lines().any(|l| key_data(l) == Some(key)) {
+        return None;
+    }
+
+    let mut file = file.to_string();
+    if !file.is_empty() && !file.ends_with('\n') {
+        file.push('\n');
+    }
+    file.push_str(line);
+    file.push('\n');
+    Some(file)
+}
+
+/// Returns `file` without any lines that contain `key`, or [None] if `file` does not contain it.
+fn remove_key(file: &str, key: (&str, &str)) -> Option<String> {
+    if !file.lines().any(|l| key_data(l) == Some(key)) {
+        return None;
+    }
+
+    let mut updated = String::with_capacity(file.len());
+    for line in file.lines().filter(|l| key_data(l) != Some(key)) {
+        updated.push_str(line);
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+/// Returns whether any line in `file` contains a key.
+fn file_has_keys(file: &str) -> bool {
+    file.lines().any(|l| key_data(l).is_some())
+}
+
+/// Returns the key type and Base64-encoded key data from a line of an SSH key file, if any.
+///
+/// Works for public key files, `authorized_keys` files, and `allowed_signers` files, since the key
+/// type and data always appear as adjacent fields after any principals and options.
+fn key_data(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    fields.windows(2).find_map(|pair| {
+        ["ssh-", "ecdsa-", "sk-"]
+            .iter()
+            .any(|prefix| pair[0].starts_with(prefix))
+            .then_some((pair[0], pair[1]))
+    })
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,138 @@
+use super::*;
+use tempfile::TempDir;
+
+const ALICE: &str = "ssh-ed25519 AAAAalice alice";
+const BOB: &str = "ssh-ed25519 AAAAbob bob";
+
+// Runs an Action::ControllerKey against key files in a temporary directory, creating each file
+// with the given contents if provided. Returns the directory, which holds the results.
+fn run(
+    authorized_keys: Option<&str>,
+    allowed_signers: Option<&str>,
+    login_key: Option<&str>,
+    action_key: Option<&str>,
+    remove: bool,
+) -> (TempDir, anyhow::Result<()>) {
+    let dir = tempfile::tempdir().unwrap();
+    if let Some(contents) = authorized_keys {
+        fs::write(dir.path().join("authorized_keys"), contents).unwrap();
+    }
+    if let Some(contents) = allowed_signers {
+        fs::write(dir.path().join("allowed_signers"), contents).unwrap();
+    }
+
+    let action = Action::ControllerKey {
+        login_key: login_key.map(str::to_string),
+        action_key: action_key.map(str::to_string),
+        remove,
+    };
+    let result = controller_key(
+        &action,
+        dir.path().join("authorized_keys"),
+        dir.path().join("allowed_signers"),
+    );
+    (dir, result)
+}
+
+fn read(dir: &TempDir, name: &str) -> String {
+    fs::read_to_string(dir.path().join(name)).unwrap()
+}
+
+#[test]
+#[should_panic(expected = "not a ControllerKey")]
+fn if_action_is_wrong_type_panics() {
+    controller_key(&Action::Command(vec![]), "a", "b").unwrap();
+}
+
+mod add {
+    use super::*;
+
+    #[test]
+    fn appends_login_key() {
+        let (dir, result) = run(Some(&format!("{ALICE}\n")), None, Some(BOB), None, false);
+        result.unwrap();
+        assert_eq!(format!("{ALICE}\n{BOB}\n"), read(&dir, "authorized_keys"));
+    }
+
+    #[test]
+    fn appends_action_key_as_allowed_signer() {
+        let (dir, result) = run(
+            None,
+            Some("alice ssh-ed25519 AAAAalice\n"),
+            None,
+            Some(BOB),
+            false,
+        );
+        result.unwrap();
+        assert_eq!(
+            "alice ssh-ed25519 AAAAalice\nbob ssh-ed25519 AAAAbob\n",
+            read(&dir, "allowed_signers"),
+        );
+    }
+
+    #[test]
+    fn creates_allowed_signers_file() {
+        let (dir, result) = run(None, None, None, Some(BOB), false);
+        result.unwrap();
+        assert_eq!("bob ssh-ed25519 AAAAbob\n", read(&dir, "allowed_signers"));
+    }
+
+    #[test]
+    fn requires_authorized_keys_file() {
+        let (_dir, result) = run(None, None, Some(BOB), None, false);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn ignores_present_key_with_different_comment_and_options() {
+        let contents = format!("no-pty ssh-ed25519 AAAAbob bob@laptop\n{ALICE}");
+        let (dir, result) = run(Some(&contents), None, Some(BOB), None, false);
+        result.unwrap();
+        assert_eq!(contents, read(&dir, "authorized_keys"));
+    }
+
+    #[test]
+    fn rejects_non_key() {
+        let (_dir, result) = run(Some(""), None, Some("hello world"), None, false);
+        assert!(result.is_err());
+    }
+}
+
+mod remove {
+    use super::*;
+
+    #[test]
+    fn removes_login_key() {
+        let contents = format!("# Keys\n{ALICE}\nno-pty ssh-ed25519 AAAAbob bob@laptop\n");
+        let (dir, result) = run(Some(&contents), None, Some(BOB), None, true);
+        result.unwrap();
+        assert_eq!(format!("# Keys\n{ALICE}\n"), read(&dir, "authorized_keys"));
+    }
+
+    #[test]
+    fn removes_action_key() {
+        let contents = "alice ssh-ed25519 AAAAalice\nbob ssh-ed25519 AAAAbob\n";
+        let (dir, result) = run(None, Some(contents), None, Some(BOB), true);
+        result.unwrap();
+        assert_eq!(
+            "alice ssh-ed25519 AAAAalice\n",
+            read(&dir, "allowed_signers")
+        );
+    }
+
+    #[test]
+    fn ignores_absent_key() {
+        let contents = format!("{ALICE}\n");
+        let (dir, result) = run(Some(&contents), None, Some(BOB), None, true);
+        result.unwrap();
+        assert_eq!(contents, read(&dir, "authorized_keys"));
+    }
+
+    #[test]
+    fn refuses_to_remove_last_key() {
+        let contents = format!("# Keys\n{BOB}\n");
+        let (dir, result) = run(Some(&contents), None, Some(BOB), None, true);
+        assert!(result.unwrap_err().to_string().contains("last key"));
+        assert_eq!(contents, read(&dir, "authorized_keys"));
+    }
+}
@@ -0,0 +1,229 @@
+//! Unified diffs of the changes that file-modifying actions make.
+
+use crate::crypto;
+use std::fmt::Write as _;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// The number of unchanged lines to show around each change.
+pub const CONTEXT_LINES: usize = 3;
+
+/// The largest number of line comparisons to spend on finding a minimal diff.
+///
+/// Beyond this, the changed region is shown as entirely removed and re-added, which is still a
+/// correct diff, just not a minimal one.
+const MAX_COMPARISONS: usize = 4_000_000;
+
+/// The largest file, in bytes, that [Contents::read] reads into memory for a diff. Larger files
+/// are only compared by checksum, e.g. so that uploading a multi-gigabyte file doesn't need that
+/// much memory on the managed node.
+pub const DIFF_LIMIT: u64 = 1 << 20;
+
+/// What a diff needs to know about a file's contents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Contents {
+    /// The contents of a file no larger than [DIFF_LIMIT].
+    Small(Vec<u8>),
+
+    /// The SHA-256 checksum of a larger file.
+    Large(String),
+}
+
+impl Contents {
+    /// Reads the contents of the file at `path`, or just its checksum if it's larger than
+    /// [DIFF_LIMIT]. A file that doesn't exist or can't be read is empty.
+    pub fn read(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() > DIFF_LIMIT => match crypto::sha256_file(path) {
+                Ok(checksum) => Contents::Large(checksum),
+                Err(_) => Contents::Small(Vec::new()),
+            },
+            _ => Contents::Small(fs::read(path).unwrap_or_default()),
+        }
+    }
+
+    /// Returns a unified diff from `self` to `new`, or [None] if they're identical.
+    ///
+    /// Works like [unified_diff], except that if either version is [Contents::Large], the diff
+    /// only says that the files differ.
+    pub fn diff(&self, new: &Contents, old_label: &str, new_label: &str) -> Option<String> {
+        match (self, new) {
+            (Contents::Small(old), Contents::Small(new)) => {
+                unified_diff(old, new, old_label, new_label)
+            }
+            _ if self == new => None,
+            _ => Some(format!(
+                "--- {old_label}\n+++ {new_label}\n@@ large files differ @@\n"
+            )),
+        }
+    }
+}
+
+/// One step in turning the old lines into the new lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    /// Old line `.0` is unchanged, and is new line `.1`.
+    Equal(usize, usize),
+
+    /// Old line `.0` was removed.
+    Delete(usize),
+
+    /// New line `.0` was added.
+    Insert(usize),
+}
+
+/// Returns a unified diff from `old` to `new`, or [None] if they're identical.
+///
+/// `old_label` and `new_label` name the two versions in the diff's header. If either version
+/// isn't valid UTF-8 or contains a NUL byte, the diff only says that the files differ.
+pub fn unified_diff(old: &[u8], new: &[u8], old_label: &str, new_label: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+
+    let mut diff = format!("--- {old_label}\n+++ {new_label}\n");
+    let (old, new) = match (text(old), text(new)) {
+        (Some(old), Some(new)) => (old, new),
+        _ => {
+            diff.push_str("@@ binary files differ @@\n");
+            return Some(diff);
+        }
+    };
+
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = diff_lines(&old, &new);
+
+    // The number of old and new lines that come before each op.
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_position, mut new_position) = (0, 0);
+    for op in &ops {
+        positions.push((old_position, new_position));
+        match op {
+            Op::Equal(..) => (old_position, new_position) = (old_position + 1, new_position + 1),
+            Op::Delete(_) => old_position += 1,
+            Op::Insert(_) => new_position += 1,
+        }
+    }
+
+    for hunk in hunks(&ops) {
+        let (old_start, new_start) = positions[hunk.start];
+        let ops = &ops[hunk];
+        let old_count = ops.iter().filter(|op| !matches!(op, Op::Insert(_))).count();
+        let new_count = ops.iter().filter(|op| !matches!(op, Op::Delete(_))).count();
+        let _ = writeln!(
+            diff,
+            "@@ -{} +{} @@",
+            range(old_start, old_count),
+            range(new_start, new_count),
+        );
+
+        for op in ops {
+            let (sign, line) = match *op {
+                Op::Equal(i, _) => (' ', old[i]),
+                Op::Delete(i) => ('-', old[i]),
+                Op::Insert(j) => ('+', new[j]),
+            };
+            diff.push(sign);
+            diff.push_str(line);
+            if !line.ends_with('\n') {
+                diff.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    Some(diff)
+}
+
+/// Returns `bytes` as text, or [None] if they look binary.
+fn text(bytes: &[u8]) -> Option<&str> {
+    match bytes.contains(&0) {
+        true => None,
+        false => std::str::from_utf8(bytes).ok(),
+    }
+}
+
+/// Formats a hunk's line range, given the number of lines before it and the number in it.
+fn range(before: usize, count: usize) -> String {
+    match count {
+        // An empty range names the line after which the change happens.
+        0 => format!("{before},0"),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{count}", before + 1),
+    }
+}
+
+/// Returns the steps that turn `old` into `new`, keeping as many lines unchanged as possible.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Op> {
+    // Most changes touch a small part of a file, so set aside the unchanged start and end.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<Op> = (0..prefix).map(|i| Op::Equal(i, i)).collect();
+
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_COMPARISONS {
+        ops.extend((0..old_middle.len()).map(|i| Op::Delete(prefix + i)));
+        ops.extend((0..new_middle.len()).map(|j| Op::Insert(prefix + j)));
+    } else {
+        // lcs[i][j] is the length of the longest common subsequence of old_middle[i..] and
+        // new_middle[j..].
+        let (n, m) = (old_middle.len(), new_middle.len());
+        let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = match old_middle[i] == new_middle[j] {
+                    true => lcs[i + 1][j + 1] + 1,
+                    false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                ops.push(Op::Equal(prefix + i, prefix + j));
+                (i, j) = (i + 1, j + 1);
+            } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                ops.push(Op::Delete(prefix + i));
+                i += 1;
+            } else {
+                ops.push(Op::Insert(prefix + j));
+                j += 1;
+            }
+        }
+    }
+
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    ops.extend((0..suffix).map(|k| Op::Equal(old_end + k, new_end + k)));
+    ops
+}
+
+/// Groups `ops` into hunks: runs of changes with up to [CONTEXT_LINES] unchanged lines around
+/// them. Changes separated by few enough unchanged lines share a hunk.
+fn hunks(ops: &[Op]) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    for (k, op) in ops.iter().enumerate() {
+        if matches!(op, Op::Equal(..)) {
+            continue;
+        }
+        let start = k.saturating_sub(CONTEXT_LINES);
+        let end = (k + 1 + CONTEXT_LINES).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,176 @@
+use super::*;
+
+fn diff(old: &str, new: &str) -> Option<String> {
+    unified_diff(old.as_bytes(), new.as_bytes(), "a/file", "b/file")
+}
+
+mod unified_diff {
+    use super::*;
+
+    #[test]
+    fn returns_none_if_identical() {
+        assert_eq!(None, diff("a\nb\n", "a\nb\n"));
+    }
+
+    #[test]
+    fn shows_replaced_line_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+        assert_eq!(
+            "--- a/file\n\
+            +++ b/file\n\
+            @@ -2,7 +2,7 @@\n \
+            2\n \
+            3\n \
+            4\n\
+            -5\n\
+            +five\n \
+            6\n \
+            7\n \
+            8\n",
+            diff(old, new).unwrap(),
+        );
+    }
+
+    #[test]
+    fn shows_appended_line() {
+        assert_eq!(
+            "--- a/file\n+++ b/file\n@@ -1,2 +1,3 @@\n a\n b\n+c\n",
+            diff("a\nb\n", "a\nb\nc\n").unwrap(),
+        );
+    }
+
+    #[test]
+    fn shows_new_file() {
+        assert_eq!(
+            "--- a/file\n+++ b/file\n@@ -0,0 +1,2 @@\n+a\n+b\n",
+            diff("", "a\nb\n").unwrap(),
+        );
+    }
+
+    #[test]
+    fn splits_distant_changes_into_hunks() {
+        let old: String = (1..=20).map(|i| format!("{i}\n")).collect();
+        let new: String = (1..=20)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                19 => "nineteen\n".to_string(),
+                i => format!("{i}\n"),
+            })
+            .collect();
+        let diff = diff(&old, &new).unwrap();
+        assert_eq!(
+            2,
+            diff.lines().filter(|l| l.starts_with("@@")).count(),
+            "{diff}"
+        );
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n"), "{diff}");
+        assert!(diff.contains("@@ -16,5 +16,5 @@\n"), "{diff}");
+    }
+
+    #[test]
+    fn marks_missing_newline() {
+        assert_eq!(
+            "--- a/file\n+++ b/file\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+a\n",
+            diff("a", "a\n").unwrap(),
+        );
+    }
+
+    #[test]
+    fn does_not_show_binary_content() {
+        assert_eq!(
+            "--- a/file\n+++ b/file\n@@ binary files differ @@\n",
+            unified_diff(b"\0\x01", b"\0\x02", "a/file", "b/file").unwrap(),
+        );
+    }
+}
+
+mod diff_lines {
+    use super::*;
+
+    #[test]
+    fn keeps_common_lines() {
+        use Op::*;
+        assert_eq!(
+            vec![Equal(0, 0), Delete(1), Equal(2, 1), Insert(2)],
+            diff_lines(&["a", "b", "c"], &["a", "c", "d"]),
+        );
+    }
+
+    #[test]
+    fn gives_up_on_huge_changes() {
+        let old: Vec<String> = (0..3000).map(|i| format!("old {i}")).collect();
+        let new: Vec<String> = (0..3000).map(|i| format!("new {i}")).collect();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+        let ops = diff_lines(&old, &new);
+        assert_eq!(6000, ops.len());
+        assert_eq!(Op::Delete(0), ops[0]);
+        assert_eq!(Op::Insert(0), ops[3000]);
+    }
+}
+
+mod contents {
+    use super::*;
+    use std::fs;
+
+    fn large(byte: u8) -> Vec<u8> {
+        vec![byte; DIFF_LIMIT as usize + 1]
+    }
+
+    #[test]
+    fn reads_small_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("small");
+        fs::write(&path, "hello\n").unwrap();
+        assert_eq!(Contents::Small(b"hello\n".to_vec()), Contents::read(&path));
+    }
+
+    #[test]
+    fn reads_missing_file_as_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            Contents::Small(vec![]),
+            Contents::read(dir.path().join("missing")),
+        );
+    }
+
+    #[test]
+    fn reads_only_checksum_of_large_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large");
+        fs::write(&path, large(b'a')).unwrap();
+        assert_eq!(
+            Contents::Large(crypto::sha256_file(&path).unwrap()),
+            Contents::read(&path),
+        );
+    }
+
+    #[test]
+    fn diffs_small_files_line_by_line() {
+        let old = Contents::Small(b"a\n".to_vec());
+        let new = Contents::Small(b"b\n".to_vec());
+        assert_eq!(
+            unified_diff(b"a\n", b"b\n", "a/file", "b/file"),
+            old.diff(&new, "a/file", "b/file"),
+        );
+    }
+
+    #[test]
+    fn only_notes_that_large_files_differ() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&a, large(b'a')).unwrap();
+        fs::write(&b, large(b'b')).unwrap();
+        let (a, b) = (Contents::read(a), Contents::read(b));
+
+        assert_eq!(None, a.diff(&a.clone(), "a/file", "b/file"));
+        assert_eq!(
+            Some("--- a/file\n+++ b/file\n@@ large files differ @@\n".to_string()),
+            a.diff(&b, "a/file", "b/file"),
+        );
+        assert!(Contents::Small(vec![])
+            .diff(&b, "a/file", "b/file")
+            .is_some());
+    }
+}
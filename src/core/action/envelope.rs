@@ -0,0 +1,294 @@
+//! The message that carries an [Action] from the control node to `sira-client`.
+//!
+//! The control node signs the whole envelope, not just the action. Because the envelope includes
+//! an expiration time, a signed action that leaks (e.g. through a hijacked SSH session or the
+//! process list on a managed node) stops working after [LIFETIME_MINUTES]. Until then, it can be
+//! replayed any number of times: `sira-client` doesn't keep track of the envelopes it has run.
+
+use super::{Action, ClientError, ErrorCode, Limits, Sandbox};
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How long, in minutes, a signed envelope remains valid after the control node creates it.
+///
+/// This also bounds how far the managed node's clock may run ahead of the control node's.
+pub const LIFETIME_MINUTES: i64 = 10;
+
+/// An [Action] plus the metadata that `sira-client` needs to decide whether to run it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    /// When the envelope stops being valid, in RFC 3339 format.
+    pub expires: String,
+
+    /// The action to run.
+    pub action: Action,
+
+    /// The resource limits to run the action under, if any.
+    #[serde(skip_serializing_if = "Limits::is_empty", default)]
+    pub limits: Limits,
+
+    /// The sandbox to run a script in, if any.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sandbox: Option<Sandbox>,
+
+    /// The user to run commands as, if not root. Please see [Task::user].
+    ///
+    /// Scripts don't need this: [HostAction::compile] already sets their user.
+    ///
+    /// [HostAction::compile]: super::HostAction::compile
+    /// [Task::user]: crate::core::Task::user
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub user: Option<String>,
+
+    /// Whether `sira-client` must run the action with root privileges, even if it was started
+    /// without them. Please see [Task::escalate].
+    ///
+    /// [Task::escalate]: crate::core::Task::escalate
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub escalate: bool,
+
+    /// How many seconds commands may wait for the package database's lock, if at all. Please see
+    /// [Task::dpkg_lock_timeout].
+    ///
+    /// [Task::dpkg_lock_timeout]: crate::core::Task::dpkg_lock_timeout
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dpkg_lock_timeout: Option<u64>,
+
+    /// Whether `sira-client` must only report what the action would change, as in check mode,
+    /// even if it wasn't started with [CHECK_FLAG].
+    ///
+    /// The control node sets this for every action in a check-mode run, so that a leaked signature
+    /// can't be replayed to make changes. It passes [CHECK_FLAG], too, so that a `sira-client`
+    /// too old to know this field refuses the action instead of running it.
+    ///
+    /// [CHECK_FLAG]: crate::client::CHECK_FLAG
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub check: bool,
+}
+
+impl Envelope {
+    /// Wraps an [Action] in an envelope that expires [LIFETIME_MINUTES] from now.
+    pub fn new(action: Action) -> Self {
+        let expires = Utc::now() + TimeDelta::minutes(LIFETIME_MINUTES);
+        Envelope {
+            expires: expires.to_rfc3339(),
+            action,
+            limits: Limits::default(),
+            sandbox: None,
+            user: None,
+            escalate: false,
+            dpkg_lock_timeout: None,
+            check: false,
+        }
+    }
+
+    /// Sets the envelope to expire `lifetime` from now instead, e.g. for a [Bundle] that a managed
+    /// node may not apply for days.
+    ///
+    /// [Bundle]: super::Bundle
+    pub fn with_lifetime(mut self, lifetime: TimeDelta) -> Self {
+        self.expires = (Utc::now() + lifetime).to_rfc3339();
+        self
+    }
+
+    /// Sets the resource limits to run the action under.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the sandbox to run a script in.
+    pub fn with_sandbox(mut self, sandbox: Option<Sandbox>) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Sets the user to run commands as.
+    pub fn with_user(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
+    }
+
+    /// Sets whether `sira-client` must run the action with root privileges.
+    pub fn with_escalate(mut self, escalate: bool) -> Self {
+        self.escalate = escalate;
+        self
+    }
+
+    /// Sets how many seconds commands may wait for the package database's lock.
+    pub fn with_dpkg_lock_timeout(mut self, timeout: Option<u64>) -> Self {
+        self.dpkg_lock_timeout = timeout;
+        self
+    }
+
+    /// Sets whether `sira-client` must only report what the action would change.
+    pub fn with_check(mut self, check: bool) -> Self {
+        self.check = check;
+        self
+    }
+
+    /// Parses an envelope from YAML.
+    pub fn from_yaml(yaml: &str) -> anyhow::Result<Self> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    /// Serializes the envelope as YAML, i.e. the form that the control node signs and sends.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("could not serialize Envelope")
+    }
+
+    /// Returns an error if the envelope has expired or its expiration time is malformed.
+    pub fn check_expiry(&self) -> anyhow::Result<()> {
+        let expires = DateTime::parse_from_rfc3339(&self.expires)
+            .with_context(|| format!("malformed expiration time: {}", self.expires))?;
+        if expires < Utc::now() {
+            return Err(ClientError::new(
+                ErrorCode::Expired,
+                format!(
+                    "action expired at {}; if this is unexpected, check that the control node's \
+                    and managed node's clocks agree",
+                    self.expires,
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action() -> Action {
+        Action::Command(vec!["true".to_string()])
+    }
+
+    #[test]
+    fn new_is_not_expired() {
+        Envelope::new(action()).check_expiry().unwrap();
+    }
+
+    #[test]
+    fn with_lifetime_extends_expiry() {
+        let envelope = Envelope::new(action()).with_lifetime(TimeDelta::days(7));
+        let expires = DateTime::parse_from_rfc3339(&envelope.expires).unwrap();
+        assert!(expires > Utc::now() + TimeDelta::days(6));
+        envelope.check_expiry().unwrap();
+    }
+
+    #[test]
+    fn round_trips_through_yaml() {
+        let envelope = Envelope::new(action());
+        assert_eq!(envelope, Envelope::from_yaml(&envelope.to_yaml()).unwrap());
+    }
+
+    #[test]
+    fn round_trips_limits() {
+        let limits = Limits {
+            cpu: Some("50%".to_string()),
+            ..Default::default()
+        };
+        let envelope = Envelope::new(action()).with_limits(limits.clone());
+        assert!(envelope.to_yaml().contains("cpu: 50%"));
+        assert_eq!(
+            limits,
+            Envelope::from_yaml(&envelope.to_yaml()).unwrap().limits
+        );
+    }
+
+    #[test]
+    fn omits_empty_limits() {
+        assert!(!Envelope::new(action()).to_yaml().contains("limits"));
+    }
+
+    #[test]
+    fn round_trips_sandbox() {
+        let sandbox = Some(Sandbox::default());
+        let envelope = Envelope::new(action()).with_sandbox(sandbox.clone());
+        assert!(envelope.to_yaml().contains("sandbox: {}"));
+        assert_eq!(
+            sandbox,
+            Envelope::from_yaml(&envelope.to_yaml()).unwrap().sandbox
+        );
+    }
+
+    #[test]
+    fn round_trips_user() {
+        let envelope = Envelope::new(action()).with_user(Some("app".to_string()));
+        assert!(envelope.to_yaml().contains("user: app"));
+        assert_eq!(
+            Some("app".to_string()),
+            Envelope::from_yaml(&envelope.to_yaml()).unwrap().user
+        );
+        assert!(!Envelope::new(action()).to_yaml().contains("user"));
+    }
+
+    #[test]
+    fn round_trips_escalate() {
+        let envelope = Envelope::new(action()).with_escalate(true);
+        assert!(envelope.to_yaml().contains("escalate: true"));
+        assert!(Envelope::from_yaml(&envelope.to_yaml()).unwrap().escalate);
+        assert!(!Envelope::new(action()).to_yaml().contains("escalate"));
+    }
+
+    #[test]
+    fn round_trips_dpkg_lock_timeout() {
+        let envelope = Envelope::new(action()).with_dpkg_lock_timeout(Some(300));
+        assert!(envelope.to_yaml().contains("dpkg_lock_timeout: 300"));
+        assert_eq!(
+            Some(300),
+            Envelope::from_yaml(&envelope.to_yaml())
+                .unwrap()
+                .dpkg_lock_timeout
+        );
+        assert!(!Envelope::new(action())
+            .to_yaml()
+            .contains("dpkg_lock_timeout"));
+    }
+
+    #[test]
+    fn round_trips_check() {
+        let envelope = Envelope::new(action()).with_check(true);
+        assert!(envelope.to_yaml().contains("check: true"));
+        assert!(Envelope::from_yaml(&envelope.to_yaml()).unwrap().check);
+        assert!(!Envelope::new(action()).to_yaml().contains("check"));
+    }
+
+    #[test]
+    fn rejects_expired() {
+        let envelope = Envelope {
+            expires: (Utc::now() - TimeDelta::seconds(1)).to_rfc3339(),
+            action: action(),
+            limits: Limits::default(),
+            sandbox: None,
+            user: None,
+            escalate: false,
+            dpkg_lock_timeout: None,
+            check: false,
+        };
+        assert!(envelope.check_expiry().is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_expiration() {
+        let envelope = Envelope {
+            expires: "tomorrow".to_string(),
+            action: action(),
+            limits: Limits::default(),
+            sandbox: None,
+            user: None,
+            escalate: false,
+            dpkg_lock_timeout: None,
+            check: false,
+        };
+        assert!(envelope.check_expiry().is_err());
+    }
+
+    #[test]
+    fn rejects_bare_action() {
+        let yaml = serde_yaml::to_string(&action()).unwrap();
+        assert!(Envelope::from_yaml(&yaml).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Typed errors that `sira-client` reports to the control node when an [Action] fails.
+//!
+//! When `sira-client` fails, it still prints a human-readable error to stderr, but it also prints
+//! one line on stdout: [ERROR_PREFIX] followed by a [ClientError] as JSON, e.g.:
+//!
+//! ```text
+//! sira-error: {"code":"permission_denied","message":"could not open /etc/app.conf"}
+//! ```
+//!
+//! `sira` reads that line back out of the action's output with [ClientError::from_output] and
+//! attaches the [ClientError] to the error it returns for the host, so that callers can react to
+//! the [ErrorCode], e.g. by retrying only [transient](ErrorCode::is_transient) failures:
+//!
+//! ```
+//! use sira::core::action::{ClientError, ErrorCode};
+//!
+//! fn should_retry(error: &anyhow::Error) -> bool {
+//!     error
+//!         .downcast_ref::<ClientError>()
+//!         .is_some_and(|error| error.code.is_transient())
+//! }
+//!
+//! let error = anyhow::Error::new(ClientError::new(ErrorCode::Busy, "dpkg is locked"))
+//!     .context("Action exited with exit code 1");
+//! assert!(should_retry(&error));
+//! ```
+
+#[cfg(doc)]
+use super::Action;
+use super::ERROR_PREFIX;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::process::Output;
+
+/// The class of a [ClientError].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The managed node denied access to a file or other resource.
+    PermissionDenied,
+
+    /// A file, directory, command, or plugin doesn't exist.
+    NotFound,
+
+    /// `sira-client` couldn't parse its input, e.g. the action's YAML, or a plugin's output.
+    ParseFailure,
+
+    /// The action or one of its fields isn't supported by this version of `sira-client`.
+    UnsupportedAction,
+
+    /// The action's signature is missing or invalid, or a policy doesn't permit the action.
+    Unauthorized,
+
+    /// The action's envelope has expired.
+    Expired,
+
+    /// A command, script, or plugin that the action ran exited with an error.
+    CommandFailed,
+
+    /// A resource was temporarily unavailable, e.g. locked or timed out. Trying again later may
+    /// succeed.
+    Busy,
+
+    /// Any other error.
+    Other,
+}
+
+impl ErrorCode {
+    /// Returns whether an error of this class might go away on its own, so that it may be worth
+    /// retrying the action.
+    pub fn is_transient(self) -> bool {
+        matches!(self, ErrorCode::Busy)
+    }
+
+    /// Returns the class of an I/O error.
+    pub fn from_io(error: &io::Error) -> Self {
+        use io::ErrorKind::*;
+        match error.kind() {
+            PermissionDenied => ErrorCode::PermissionDenied,
+            NotFound => ErrorCode::NotFound,
+            WouldBlock | TimedOut | Interrupted | ResourceBusy => ErrorCode::Busy,
+            InvalidData => ErrorCode::ParseFailure,
+            _ => ErrorCode::Other,
+        }
+    }
+}
+
+/// An error from `sira-client`. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientError {
+    /// The class of the error.
+    pub code: ErrorCode,
+
+    /// A human-readable description of the error.
+    pub message: String,
+}
+
+impl ClientError {
+    /// Creates a [ClientError].
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ClientError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Classifies `error`, using the first [ClientError] in its chain of causes, if any, or else
+    /// the first cause whose type reveals its class, e.g. an [io::Error].
+    pub fn classify(error: &anyhow::Error) -> Self {
+        let message = format!("{error:#}");
+        let code = error.chain().find_map(code_of).unwrap_or(ErrorCode::Other);
+        ClientError { code, message }
+    }
+
+    /// Returns the line that `sira-client` prints to report this error, without a newline.
+    pub fn line(&self) -> String {
+        let json =
+            serde_json::to_string(self).expect("ClientError should always serialize to JSON");
+        format!("{ERROR_PREFIX}{json}")
+    }
+
+    /// Returns the error that `sira-client` reported in an action's output, if the action failed
+    /// and `sira-client` reported one.
+    pub fn from_output(output: &Output) -> Option<Self> {
+        if output.status.success() {
+            return None;
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .rev()
+            .find_map(|line| line.strip_prefix(ERROR_PREFIX))
+            .and_then(|json| serde_json::from_str(json.trim()).ok())
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ClientError {}
+
+/// Attaches an [ErrorCode] to an error, so that [ClientError::classify] reports it.
+pub trait WithCode<T> {
+    /// Replaces the error, if any, with a [ClientError] of class `code` and the same message.
+    fn with_code(self, code: ErrorCode) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> WithCode<T> for Result<T, E> {
+    fn with_code(self, code: ErrorCode) -> anyhow::Result<T> {
+        self.map_err(|error| {
+            let message = format!("{:#}", error.into());
+            ClientError::new(code, message).into()
+        })
+    }
+}
+
+/// Returns the class of a single error in a chain of causes, if its type reveals it.
+fn code_of(error: &(dyn Error + 'static)) -> Option<ErrorCode> {
+    if let Some(error) = error.downcast_ref::<ClientError>() {
+        return Some(error.code);
+    }
+    if let Some(error) = error.downcast_ref::<io::Error>() {
+        return Some(ErrorCode::from_io(error));
+    }
+    if let Some(error) = error.downcast_ref::<serde_yaml::Error>() {
+        // serde_yaml doesn't say what went wrong except in its message. An unknown variant is
+        // most likely an action that this sira-client predates.
+        return Some(match error.to_string().contains("unknown variant") {
+            true => ErrorCode::UnsupportedAction,
+            false => ErrorCode::ParseFailure,
+        });
+    }
+    if error.is::<serde_json::Error>() {
+        return Some(ErrorCode::ParseFailure);
+    }
+    None
+}
+
+#[cfg(test)]
+mod test;
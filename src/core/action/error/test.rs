@@ -0,0 +1,111 @@
+use super::*;
+use crate::core::Action;
+use anyhow::Context;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn output(code: i32, stdout: &str) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: vec![],
+    }
+}
+
+mod classify {
+    use super::*;
+
+    #[test]
+    fn prefers_client_error() {
+        let error = anyhow::Error::new(ClientError::new(ErrorCode::Expired, "expired"))
+            .context("could not run action");
+        let classified = ClientError::classify(&error);
+        assert_eq!(ErrorCode::Expired, classified.code);
+        assert_eq!("could not run action: expired", classified.message);
+    }
+
+    #[test]
+    fn classifies_io_errors() {
+        let error = anyhow::Error::new(io::Error::from(io::ErrorKind::PermissionDenied))
+            .context("could not open /etc/shadow");
+        assert_eq!(
+            ErrorCode::PermissionDenied,
+            ClientError::classify(&error).code
+        );
+
+        let error = anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(ErrorCode::NotFound, ClientError::classify(&error).code);
+    }
+
+    #[test]
+    fn classifies_parse_failures() {
+        let error = serde_yaml::from_str::<Action>("command: [")
+            .context("could not parse action")
+            .unwrap_err();
+        assert_eq!(ErrorCode::ParseFailure, ClientError::classify(&error).code);
+    }
+
+    #[test]
+    fn classifies_unknown_actions_as_unsupported() {
+        let error = anyhow::Error::new(serde_yaml::from_str::<Action>("reboot: now").unwrap_err());
+        assert_eq!(
+            ErrorCode::UnsupportedAction,
+            ClientError::classify(&error).code
+        );
+    }
+
+    #[test]
+    fn defaults_to_other() {
+        let error = anyhow::anyhow!("something went wrong");
+        assert_eq!(ErrorCode::Other, ClientError::classify(&error).code);
+    }
+}
+
+mod with_code {
+    use super::*;
+
+    #[test]
+    fn keeps_message() {
+        let result: anyhow::Result<()> = Err(anyhow::anyhow!("no principal matched"));
+        let error = result.with_code(ErrorCode::Unauthorized).unwrap_err();
+        assert_eq!("no principal matched", error.to_string());
+        assert_eq!(ErrorCode::Unauthorized, ClientError::classify(&error).code);
+    }
+}
+
+mod from_output {
+    use super::*;
+
+    #[test]
+    fn round_trips_line() {
+        let error = ClientError::new(ErrorCode::CommandFailed, "command exited with exit code 1");
+        let stdout = format!("some output\n{}\n", error.line());
+        assert_eq!(Some(error), ClientError::from_output(&output(1, &stdout)));
+    }
+
+    #[test]
+    fn ignores_successful_actions() {
+        let error = ClientError::new(ErrorCode::Other, "oops");
+        assert_eq!(None, ClientError::from_output(&output(0, &error.line())));
+    }
+
+    #[test]
+    fn returns_none_without_error_line() {
+        assert_eq!(None, ClientError::from_output(&output(1, "failed\n")));
+    }
+}
+
+#[test]
+fn line_matches_wire_format() {
+    let error = ClientError::new(ErrorCode::PermissionDenied, "denied");
+    assert_eq!(
+        r#"sira-error: {"code":"permission_denied","message":"denied"}"#,
+        error.line(),
+    );
+}
+
+#[test]
+fn only_busy_is_transient() {
+    assert!(ErrorCode::Busy.is_transient());
+    assert!(!ErrorCode::CommandFailed.is_transient());
+}
@@ -0,0 +1,203 @@
+//! Client-side logic for [Action::KernelModule].
+
+use super::Action;
+use crate::client;
+use anyhow::{bail, Context};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where [kernel_module_changes] looks for kernel modules and their configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KernelModulePaths {
+    /// The directory of module options files, i.e. `/etc/modprobe.d`.
+    pub modprobe_d: PathBuf,
+
+    /// The directory of lists of modules to load at boot, i.e. `/etc/modules-load.d`.
+    pub modules_load_d: PathBuf,
+
+    /// The directory in which the kernel lists its modules, i.e. `/sys/module`.
+    pub sys_module: PathBuf,
+}
+
+impl Default for KernelModulePaths {
+    fn default() -> Self {
+        KernelModulePaths {
+            modprobe_d: PathBuf::from("/etc/modprobe.d"),
+            modules_load_d: PathBuf::from("/etc/modules-load.d"),
+            sys_module: PathBuf::from("/sys/module"),
+        }
+    }
+}
+
+/// One change that an [Action::KernelModule] makes to a managed node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KernelModuleChange {
+    /// Writes `after` to the file at `path`, which contains `before` (or nothing, if it doesn't
+    /// exist).
+    Write {
+        path: PathBuf,
+        before: String,
+        after: String,
+    },
+
+    /// Removes the file at `path`, which contains `before`.
+    Remove { path: PathBuf, before: String },
+
+    /// Loads the named module with `modprobe`.
+    Load(String),
+
+    /// Unloads the named module with `modprobe -r`.
+    Unload(String),
+}
+
+impl KernelModuleChange {
+    /// Makes the change.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file can't be written or removed, or if `modprobe` fails.
+    pub fn apply(&self) -> anyhow::Result<()> {
+        match self {
+            KernelModuleChange::Write { path, after, .. } => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("failed to create {}", parent.display()))?;
+                }
+                fs::write(path, after)
+                    .with_context(|| format!("failed to write {}", path.display()))
+            }
+            KernelModuleChange::Remove { path, .. } => fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display())),
+            KernelModuleChange::Load(name) => client::run("modprobe", &[name]),
+            KernelModuleChange::Unload(name) => client::run("modprobe", &["-r", name]),
+        }
+    }
+}
+
+/// Implements client-side logic for [Action::KernelModule] by listing the changes that would
+/// bring the managed node into the state it describes, in the order in which to make them. Apply
+/// each with [KernelModuleChange::apply], or report them in check mode.
+///
+/// `paths` is usually [KernelModulePaths::default].
+///
+/// # Returns
+///
+/// Returns an empty list if the module and its configuration are already as described.
+///
+/// # Errors
+///
+/// Returns an error if the module's name or options are invalid, if a configuration file can't be
+/// read, or if the action would unload a module that is built into the kernel.
+///
+/// # Panics
+///
+/// Panics if `action` is not of type [Action::KernelModule].
+pub fn kernel_module_changes(
+    action: &Action,
+    paths: &KernelModulePaths,
+) -> anyhow::Result<Vec<KernelModuleChange>> {
+    let (name, options, &persist, &remove) = match action {
+        Action::KernelModule {
+            name,
+            options,
+            persist,
+            remove,
+        } => (name, options, persist, remove),
+        _ => {
+            panic!("called kernel_module_changes with an Action that was not a KernelModule: {action:?}")
+        }
+    };
+    validate(name, options)?;
+
+    let options_path = paths.modprobe_d.join(format!("{name}.conf"));
+    let load_path = paths.modules_load_d.join(format!("{name}.conf"));
+
+    // The kernel lists each loaded module, and each built-in module with parameters, under the
+    // name it was built with, which always uses '_'. Only loaded modules have an initstate.
+    let sys_module = paths.sys_module.join(name.replace('-', "_"));
+    let loaded = sys_module.exists();
+    let built_in = loaded && !sys_module.join("initstate").exists();
+
+    let mut changes = vec![];
+    if remove {
+        if built_in {
+            bail!("cannot unload kernel module {name}: it is built into the kernel");
+        } else if loaded {
+            changes.push(KernelModuleChange::Unload(name.clone()));
+        }
+        changes.extend(file_change(&load_path, None)?);
+        changes.extend(file_change(&options_path, None)?);
+    } else {
+        // Write the options first so that they apply when the module loads.
+        let options =
+            (!options.is_empty()).then(|| format!("options {name} {}\n", options.join(" ")));
+        changes.extend(file_change(&options_path, options)?);
+        changes.extend(file_change(
+            &load_path,
+            persist.then(|| format!("{name}\n")),
+        )?);
+        if !loaded {
+            changes.push(KernelModuleChange::Load(name.clone()));
+        }
+    }
+    Ok(changes)
+}
+
+/// Checks that a module's name and options are safe to write into configuration files and pass to
+/// `modprobe`.
+fn validate(name: &str, options: &[String]) -> anyhow::Result<()> {
+    let is_name = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with('-')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    };
+    if !is_name(name) {
+        bail!(
+            "invalid kernel module name {name:?}: names may contain only ASCII letters, digits, \
+            '_', and '-', and may not start with '-'"
+        );
+    }
+    for option in options {
+        match option.split_once('=') {
+            Some((parameter, value))
+                if is_name(parameter)
+                    && !value.is_empty()
+                    && !value.chars().any(|c| c.is_whitespace() || c.is_control()) => {}
+            _ => bail!(
+                "invalid option {option:?} for kernel module {name}: options must have the form \
+                <parameter>=<value>, with no whitespace"
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the change that makes the file at `path` contain `contents`, or removes it if
+/// `contents` is [None]. Returns [None] if the file is already as it should be.
+fn file_change(
+    path: &Path,
+    contents: Option<String>,
+) -> anyhow::Result<Option<KernelModuleChange>> {
+    let before = match fs::read_to_string(path) {
+        Ok(before) => Some(before),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let path = path.to_path_buf();
+    Ok(match (before, contents) {
+        (before, Some(after)) if before.as_ref() != Some(&after) => {
+            Some(KernelModuleChange::Write {
+                path,
+                before: before.unwrap_or_default(),
+                after,
+            })
+        }
+        (Some(before), None) => Some(KernelModuleChange::Remove { path, before }),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,235 @@
+use super::*;
+use tempfile::TempDir;
+
+// Returns paths in a temporary directory, with the module directories that exist for loaded and
+// built-in modules.
+fn paths(loaded: &[&str], built_in: &[&str]) -> (TempDir, KernelModulePaths) {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = KernelModulePaths {
+        modprobe_d: dir.path().join("modprobe.d"),
+        modules_load_d: dir.path().join("modules-load.d"),
+        sys_module: dir.path().join("module"),
+    };
+    for name in loaded {
+        let module = paths.sys_module.join(name);
+        fs::create_dir_all(&module).unwrap();
+        fs::write(module.join("initstate"), "live\n").unwrap();
+    }
+    for name in built_in {
+        fs::create_dir_all(paths.sys_module.join(name).join("parameters")).unwrap();
+    }
+    (dir, paths)
+}
+
+fn action(name: &str, options: &[&str], persist: bool, remove: bool) -> Action {
+    Action::KernelModule {
+        name: name.to_string(),
+        options: options.iter().map(|o| o.to_string()).collect(),
+        persist,
+        remove,
+    }
+}
+
+fn write(path: PathBuf, after: &str) -> KernelModuleChange {
+    KernelModuleChange::Write {
+        path,
+        before: String::new(),
+        after: after.to_string(),
+    }
+}
+
+mod kernel_module_changes {
+    use super::*;
+
+    #[test]
+    fn loads_and_persists_module() {
+        let (_dir, paths) = paths(&[], &[]);
+        let changes = kernel_module_changes(&action("br_netfilter", &[], true, false), &paths);
+        assert_eq!(
+            vec![
+                write(
+                    paths.modules_load_d.join("br_netfilter.conf"),
+                    "br_netfilter\n",
+                ),
+                KernelModuleChange::Load("br_netfilter".to_string()),
+            ],
+            changes.unwrap(),
+        );
+    }
+
+    #[test]
+    fn writes_options_before_loading() {
+        let (_dir, paths) = paths(&[], &[]);
+        let action = action(
+            "nf_conntrack",
+            &["hashsize=262144", "expect_hashsize=64"],
+            false,
+            false,
+        );
+        assert_eq!(
+            vec![
+                write(
+                    paths.modprobe_d.join("nf_conntrack.conf"),
+                    "options nf_conntrack hashsize=262144 expect_hashsize=64\n",
+                ),
+                KernelModuleChange::Load("nf_conntrack".to_string()),
+            ],
+            kernel_module_changes(&action, &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn changes_nothing_when_in_place() {
+        let (_dir, paths) = paths(&["br_netfilter"], &[]);
+        let action = action("br-netfilter", &["x=1"], true, false);
+        for change in kernel_module_changes(&action, &paths).unwrap() {
+            change.apply().unwrap();
+        }
+        assert_eq!(
+            Vec::<KernelModuleChange>::new(),
+            kernel_module_changes(&action, &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn updates_options_of_loaded_module() {
+        let (_dir, paths) = paths(&["nf_conntrack"], &[]);
+        let path = paths.modprobe_d.join("nf_conntrack.conf");
+        fs::create_dir_all(&paths.modprobe_d).unwrap();
+        fs::write(&path, "options nf_conntrack hashsize=1024\n").unwrap();
+        assert_eq!(
+            vec![KernelModuleChange::Write {
+                path,
+                before: "options nf_conntrack hashsize=1024\n".to_string(),
+                after: "options nf_conntrack hashsize=262144\n".to_string(),
+            }],
+            kernel_module_changes(
+                &action("nf_conntrack", &["hashsize=262144"], false, false),
+                &paths
+            )
+            .unwrap(),
+        );
+    }
+
+    #[test]
+    fn removes_stale_files() {
+        let (_dir, paths) = paths(&["dummy"], &[]);
+        fs::create_dir_all(&paths.modprobe_d).unwrap();
+        fs::create_dir_all(&paths.modules_load_d).unwrap();
+        fs::write(
+            paths.modprobe_d.join("dummy.conf"),
+            "options dummy numdummies=2\n",
+        )
+        .unwrap();
+        fs::write(paths.modules_load_d.join("dummy.conf"), "dummy\n").unwrap();
+        assert_eq!(
+            vec![
+                KernelModuleChange::Remove {
+                    path: paths.modprobe_d.join("dummy.conf"),
+                    before: "options dummy numdummies=2\n".to_string(),
+                },
+                KernelModuleChange::Remove {
+                    path: paths.modules_load_d.join("dummy.conf"),
+                    before: "dummy\n".to_string(),
+                },
+            ],
+            kernel_module_changes(&action("dummy", &[], false, false), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn unloads_before_removing_files() {
+        let (_dir, paths) = paths(&["floppy"], &[]);
+        fs::create_dir_all(&paths.modules_load_d).unwrap();
+        fs::write(paths.modules_load_d.join("floppy.conf"), "floppy\n").unwrap();
+        assert_eq!(
+            vec![
+                KernelModuleChange::Unload("floppy".to_string()),
+                KernelModuleChange::Remove {
+                    path: paths.modules_load_d.join("floppy.conf"),
+                    before: "floppy\n".to_string(),
+                },
+            ],
+            kernel_module_changes(&action("floppy", &["x=1"], true, true), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn remove_changes_nothing_when_absent() {
+        let (_dir, paths) = paths(&[], &[]);
+        assert_eq!(
+            Vec::<KernelModuleChange>::new(),
+            kernel_module_changes(&action("floppy", &[], true, true), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn treats_built_in_module_as_loaded() {
+        let (_dir, paths) = paths(&[], &["nf_conntrack"]);
+        let action = action("nf_conntrack", &[], false, false);
+        assert_eq!(
+            Vec::<KernelModuleChange>::new(),
+            kernel_module_changes(&action, &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn refuses_to_unload_built_in_module() {
+        let (_dir, paths) = paths(&[], &["nf_conntrack"]);
+        let action = action("nf_conntrack", &[], false, true);
+        assert!(kernel_module_changes(&action, &paths).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let (_dir, paths) = paths(&[], &[]);
+        for name in ["", "-r", "../evil", "a b", "a\nb"] {
+            let action = action(name, &[], true, false);
+            assert!(kernel_module_changes(&action, &paths).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let (_dir, paths) = paths(&[], &[]);
+        for option in ["x", "=1", "x=", "x=1 y=2", "x=1\ninstall dummy /bin/sh"] {
+            let action = action("dummy", &[option], true, false);
+            assert!(
+                kernel_module_changes(&action, &paths).is_err(),
+                "{option:?}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not a KernelModule")]
+    fn panics_on_other_actions() {
+        let (_dir, paths) = paths(&[], &[]);
+        let _ = kernel_module_changes(&Action::Command(vec![]), &paths);
+    }
+}
+
+mod apply {
+    use super::*;
+
+    #[test]
+    fn writes_and_removes_files() {
+        let (dir, _) = paths(&[], &[]);
+        let path = dir.path().join("modprobe.d/dummy.conf");
+        write(path.clone(), "options dummy numdummies=2\n")
+            .apply()
+            .unwrap();
+        assert_eq!(
+            "options dummy numdummies=2\n",
+            fs::read_to_string(&path).unwrap(),
+        );
+
+        KernelModuleChange::Remove {
+            path: path.clone(),
+            before: String::new(),
+        }
+        .apply()
+        .unwrap();
+        assert!(!path.exists());
+    }
+}
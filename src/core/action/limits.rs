@@ -0,0 +1,126 @@
+//! Resource limits for the commands and scripts that `sira-client` runs.
+//!
+//! A [Task](crate::core::Task) may set [Limits] on its actions, e.g.:
+//!
+//! ```text
+//! ---
+//! name: Rebuild search index
+//! limits:
+//!   cpu: 50%
+//!   memory: 1G
+//!   io_weight: 10
+//! actions:
+//!   - command:
+//!       - /usr/local/bin/reindex
+//! ```
+//!
+//! The control node sends the limits to `sira-client` in the signed [Envelope](super::Envelope)
+//! alongside each action. `sira-client` then runs each [Action::Command] and [Action::Script]
+//! through `systemd-run --scope`, which places it in a transient cgroup with the given limits, so
+//! that a runaway process can't take down the managed node. Limits don't affect other actions.
+
+#[cfg(doc)]
+use super::Action;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+/// Resource limits for an action. Please see the [module documentation](self).
+///
+/// Each limit is optional. Without any limits, actions run as usual, without `systemd-run`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// The share of one CPU that the action may use, as a percentage, e.g. `50%`. Values above
+    /// `100%` allow more than one CPU. Sets systemd's `CPUQuota`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub cpu: Option<String>,
+
+    /// The most memory that the action may use, in bytes with an optional `K`, `M`, `G`, or `T`
+    /// suffix, e.g. `512M`, or as a percentage of physical memory, e.g. `25%`. Sets systemd's
+    /// `MemoryMax`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub memory: Option<String>,
+
+    /// The action's share of disk bandwidth relative to other processes, from 1 to 10000. The
+    /// default for other processes is 100. Sets systemd's `IOWeight`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub io_weight: Option<u16>,
+}
+
+impl Limits {
+    /// Returns whether no limits are set.
+    pub fn is_empty(&self) -> bool {
+        self == &Limits::default()
+    }
+
+    /// Returns the systemd properties that apply these limits, e.g. `CPUQuota=50%`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any limit is invalid.
+    pub fn properties(&self) -> anyhow::Result<Vec<String>> {
+        let mut properties = Vec::new();
+        if let Some(cpu) = &self.cpu {
+            if !is_percentage(cpu) {
+                bail!("invalid CPU limit {cpu:?}; expected a percentage, e.g. 50%");
+            }
+            properties.push(format!("CPUQuota={cpu}"));
+        }
+        if let Some(memory) = &self.memory {
+            if !is_percentage(memory) && !is_size(memory) {
+                bail!(
+                    "invalid memory limit {memory:?}; expected a size, e.g. 512M, or a \
+                    percentage, e.g. 25%"
+                );
+            }
+            properties.push(format!("MemoryMax={memory}"));
+        }
+        if let Some(io_weight) = self.io_weight {
+            if !(1..=10000).contains(&io_weight) {
+                bail!("invalid I/O weight {io_weight}; expected a number from 1 to 10000");
+            }
+            properties.push(format!("IOWeight={io_weight}"));
+        }
+        Ok(properties)
+    }
+
+    /// Returns the command and arguments that run `command` with `args` under these limits.
+    ///
+    /// If no limits are set, returns `command` and `args` unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any limit is invalid.
+    pub fn wrap(&self, command: &str, args: &[String]) -> anyhow::Result<(String, Vec<String>)> {
+        if self.is_empty() {
+            return Ok((command.to_string(), args.to_vec()));
+        }
+        let mut wrapped = vec!["--scope".to_string(), "--quiet".to_string()];
+        for property in self.properties()? {
+            wrapped.push("--property".to_string());
+            wrapped.push(property);
+        }
+        wrapped.push("--".to_string());
+        wrapped.push(command.to_string());
+        wrapped.extend_from_slice(args);
+        Ok(("systemd-run".to_string(), wrapped))
+    }
+}
+
+/// Returns whether `s` is a whole-number percentage, e.g. `50%`.
+fn is_percentage(s: &str) -> bool {
+    s.strip_suffix('%').is_some_and(is_number)
+}
+
+/// Returns whether `s` is a whole number of bytes with an optional suffix, e.g. `512M`.
+fn is_size(s: &str) -> bool {
+    is_number(s.strip_suffix(['K', 'M', 'G', 'T']).unwrap_or(s))
+}
+
+/// Returns whether `s` is a non-empty string of ASCII digits.
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,101 @@
+use super::*;
+
+fn limits(cpu: Option<&str>, memory: Option<&str>, io_weight: Option<u16>) -> Limits {
+    Limits {
+        cpu: cpu.map(str::to_string),
+        memory: memory.map(str::to_string),
+        io_weight,
+    }
+}
+
+mod properties {
+    use super::*;
+
+    #[test]
+    fn returns_each_limit() {
+        assert_eq!(
+            vec!["CPUQuota=50%", "MemoryMax=512M", "IOWeight=10"],
+            limits(Some("50%"), Some("512M"), Some(10))
+                .properties()
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn accepts_memory_in_bytes_or_percent() {
+        assert!(limits(None, Some("1073741824"), None).properties().is_ok());
+        assert!(limits(None, Some("25%"), None).properties().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_cpu() {
+        assert!(limits(Some("50"), None, None).properties().is_err());
+        assert!(limits(Some("%"), None, None).properties().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_memory() {
+        assert!(limits(None, Some("lots"), None).properties().is_err());
+        assert!(limits(None, Some("512MB"), None).properties().is_err());
+        assert!(limits(None, Some("1G\nCPUQuota=1%"), None)
+            .properties()
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_io_weight() {
+        assert!(limits(None, None, Some(0)).properties().is_err());
+        assert!(limits(None, None, Some(10001)).properties().is_err());
+    }
+}
+
+mod wrap {
+    use super::*;
+
+    #[test]
+    fn leaves_command_alone_without_limits() {
+        let args = vec!["-c".to_string(), "true".to_string()];
+        assert_eq!(
+            ("bash".to_string(), args.clone()),
+            Limits::default().wrap("bash", &args).unwrap(),
+        );
+    }
+
+    #[test]
+    fn runs_command_in_scope() {
+        let args = vec!["-c".to_string(), "true".to_string()];
+        let (command, wrapped) = limits(Some("50%"), None, None).wrap("bash", &args).unwrap();
+        assert_eq!("systemd-run", command);
+        assert_eq!(
+            vec![
+                "--scope",
+                "--quiet",
+                "--property",
+                "CPUQuota=50%",
+                "--",
+                "bash",
+                "-c",
+                "true",
+            ],
+            wrapped,
+        );
+    }
+}
+
+mod deserialize {
+    use super::*;
+
+    #[test]
+    fn reads_yaml() {
+        let yaml = "cpu: 50%\nmemory: 1G\nio_weight: 10\n";
+        assert_eq!(
+            limits(Some("50%"), Some("1G"), Some(10)),
+            serde_yaml::from_str(yaml).unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_limits() {
+        assert!(serde_yaml::from_str::<Limits>("disk: 1G\n").is_err());
+    }
+}
@@ -16,6 +16,32 @@ use std::io;
 ///
 /// Panics if `action` is not of type [Action::LineInFile].
 pub fn line_in_file(action: &Action) -> io::Result<()> {
+    let (path, before, after) = edit(action)?;
+    if after != before {
+        fs::write(path, &after)?;
+    }
+    Ok(())
+}
+
+/// Returns the contents that [line_in_file] would leave in the file, without changing the file.
+///
+/// If the line is already in place, this is the file's current contents.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read.
+///
+/// # Panics
+///
+/// Panics if `action` is not of type [Action::LineInFile].
+pub fn line_in_file_preview(action: &Action) -> io::Result<String> {
+    let (_, _, after) = edit(action)?;
+    Ok(after)
+}
+
+/// Reads the file for an [Action::LineInFile] and returns its path, its current contents, and its
+/// contents after the edit.
+fn edit(action: &Action) -> io::Result<(&str, String, String)> {
     let (path, line, pattern, after, &indent) = match action {
         Action::LineInFile {
             path,
@@ -27,29 +53,27 @@ pub fn line_in_file(action: &Action) -> io::Result<()> {
         _ => panic!("called line_in_file with an Action that was not a LineInFile: {action:?}"),
     };
 
-    let mut file = fs::read_to_string(path)?;
+    let before = fs::read_to_string(path)?;
+    let mut file = before.clone();
 
     if line_is_present(&file, line, indent) {
-        return Ok(());
+        return Ok((path, before, file));
     }
 
     if let Some(pattern) = pattern {
         if replace_pattern(&mut file, line, pattern, indent) {
-            fs::write(path, &file)?;
-            return Ok(());
+            return Ok((path, before, file));
         }
     }
 
     if let Some(after) = after {
         if insert_after(&mut file, line, after) {
-            fs::write(path, &file)?;
-            return Ok(());
+            return Ok((path, before, file));
         }
     }
 
     append_line(&mut file, line);
-    fs::write(path, &file)?;
-    Ok(())
+    Ok((path, before, file))
 }
 
 /// Returns whether `line` is present in `file`.
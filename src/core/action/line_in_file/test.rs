@@ -826,3 +826,35 @@ fn by_default_appends_line() {
         expected: "something\nexists\nactually  \t\n",
     });
 }
+
+mod line_in_file_preview {
+    use super::*;
+
+    fn preview(contents: &str, line: &str) -> (String, String) {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(contents.as_bytes()).unwrap();
+        let action = Action::LineInFile {
+            path: temp_file.path().to_string_lossy().to_string(),
+            line: line.to_string(),
+            pattern: None,
+            after: None,
+            indent: false,
+        };
+        let preview = line_in_file_preview(&action).unwrap();
+        (preview, fs::read_to_string(temp_file.path()).unwrap())
+    }
+
+    #[test]
+    fn returns_edited_contents_without_writing() {
+        let (preview, file) = preview("one\n", "two");
+        assert_eq!("one\ntwo\n", preview);
+        assert_eq!("one\n", file);
+    }
+
+    #[test]
+    fn returns_current_contents_if_line_is_present() {
+        let (preview, file) = preview("one\ntwo\n", "two");
+        assert_eq!("one\ntwo\n", preview);
+        assert_eq!("one\ntwo\n", file);
+    }
+}
@@ -0,0 +1,47 @@
+//! Progress that `sira-client` reports while an [Action] runs, e.g. which of several commands is
+//! running.
+//!
+//! Each report is one line on stdout: [PROGRESS_PREFIX] followed by a [Progress] as JSON, e.g.:
+//!
+//! ```text
+//! sira-progress: {"message":"Running apt-get update"}
+//! ```
+//!
+//! `sira` reads stdout as it arrives, so it can report progress as soon as `sira-client` prints it.
+//! Progress lines are not part of the action's output.
+
+#[cfg(doc)]
+use super::Action;
+use super::PROGRESS_PREFIX;
+use serde::{Deserialize, Serialize};
+
+/// A progress report from `sira-client`. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    /// What `sira-client` is doing, e.g. `Running apt-get update`.
+    pub message: String,
+}
+
+impl Progress {
+    /// Creates a [Progress] with the given message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Progress {
+            message: message.into(),
+        }
+    }
+
+    /// Returns the line that `sira-client` prints to report this progress, without a newline.
+    pub fn line(&self) -> String {
+        let json = serde_json::to_string(self).expect("Progress should always serialize to JSON");
+        format!("{PROGRESS_PREFIX}{json}")
+    }
+
+    /// Parses a line of `sira-client`'s stdout, returning [None] if it isn't a progress report.
+    pub fn from_line(line: &[u8]) -> Option<Self> {
+        let json = line.strip_prefix(PROGRESS_PREFIX.as_bytes())?;
+        serde_json::from_slice(json.trim_ascii_end()).ok()
+    }
+}
+
+#[cfg(test)]
+mod test;
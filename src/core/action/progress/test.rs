@@ -0,0 +1,16 @@
+use super::*;
+
+#[test]
+fn round_trips_through_line() {
+    let progress = Progress::new("Running \"apt-get update\"\non two lines");
+    let line = format!("{}\n", progress.line());
+    assert_eq!(1, line.lines().count());
+    assert_eq!(Some(progress), Progress::from_line(line.as_bytes()));
+}
+
+#[test]
+fn ignores_other_lines() {
+    assert_eq!(None, Progress::from_line(b"Running apt-get update\n"));
+    assert_eq!(None, Progress::from_line(b"sira-progress: not json\n"));
+    assert_eq!(None, Progress::from_line(b"sira-status: ok\n"));
+}
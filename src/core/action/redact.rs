@@ -0,0 +1,123 @@
+//! Masks the values of sensitive variables before Sira reports them.
+
+use super::Action;
+use std::cmp::Reverse;
+
+/// The text that replaces each occurrence of a sensitive value.
+pub const MASK: &str = "********";
+
+/// Replaces sensitive values with [MASK] in [Action]s, text, and captured output.
+///
+/// Typically obtained from [HostAction::redactor].
+///
+/// [HostAction::redactor]: super::HostAction::redactor
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Redactor {
+    /// The values to mask, longest first, so that a value containing another is masked whole.
+    values: Vec<String>,
+}
+
+impl Redactor {
+    /// Creates a [Redactor] that masks each of `values`. Empty values are ignored.
+    pub fn new(values: impl IntoIterator<Item = String>) -> Self {
+        let mut values: Vec<String> = values.into_iter().filter(|v| !v.is_empty()).collect();
+        values.sort_by_key(|v| (Reverse(v.len()), v.clone()));
+        values.dedup();
+        Redactor { values }
+    }
+
+    /// Returns whether this [Redactor] has no values to mask.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Masks sensitive values in a string.
+    pub fn redact_str(&self, s: &str) -> String {
+        let mut s = s.to_string();
+        for value in &self.values {
+            if s.contains(value.as_str()) {
+                s = s.replace(value.as_str(), MASK);
+            }
+        }
+        s
+    }
+
+    /// Masks sensitive values in every field of an [Action].
+    pub fn redact_action(&self, action: &Action) -> Action {
+        let mut action = action.clone();
+        if !self.is_empty() {
+            action.map_strings(|s| *s = self.redact_str(s));
+        }
+        action
+    }
+
+    /// Masks sensitive values in raw bytes, e.g. captured stdout, without assuming UTF-8.
+    pub fn redact_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        for value in &self.values {
+            bytes = replace_bytes(&bytes, value.as_bytes(), MASK.as_bytes());
+        }
+        bytes
+    }
+}
+
+/// Replaces every non-overlapping occurrence of `from` in `haystack` with `to`.
+fn replace_bytes(haystack: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(haystack.len());
+    let mut i = 0;
+    while i < haystack.len() {
+        if haystack[i..].starts_with(from) {
+            result.extend_from_slice(to);
+            i += from.len();
+        } else {
+            result.push(haystack[i]);
+            i += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor() -> Redactor {
+        Redactor::new([
+            "hunter2".to_string(),
+            "hunter2hunter2".to_string(),
+            String::new(),
+        ])
+    }
+
+    #[test]
+    fn redacts_str() {
+        assert_eq!(
+            "password=******** again=********",
+            redactor().redact_str("password=hunter2hunter2 again=hunter2"),
+        );
+    }
+
+    #[test]
+    fn ignores_empty_values() {
+        assert_eq!("unchanged", redactor().redact_str("unchanged"));
+        assert!(Redactor::new([String::new()]).is_empty());
+    }
+
+    #[test]
+    fn redacts_action() {
+        let action = Action::Command(vec!["mysql -phunter2".to_string()]);
+        assert_eq!(
+            Action::Command(vec!["mysql -p********".to_string()]),
+            redactor().redact_action(&action),
+        );
+    }
+
+    #[test]
+    fn redacts_non_utf8_bytes() {
+        let mut bytes = vec![0xff];
+        bytes.extend_from_slice(b"hunter2\n");
+        let mut expected = vec![0xff];
+        expected.extend_from_slice(b"********\n");
+        assert_eq!(expected, redactor().redact_bytes(&bytes));
+    }
+}
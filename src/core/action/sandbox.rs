@@ -0,0 +1,100 @@
+//! Restricts what an [Action::Script] can reach on a managed node, e.g. for third-party scripts.
+//!
+//! A [Task](crate::core::Task) may declare a [Sandbox] for its scripts, e.g.:
+//!
+//! ```text
+//! ---
+//! name: Run vendor cleanup script
+//! sandbox:
+//!   writable:
+//!     - /var/cache/vendor
+//! actions:
+//!   - script:
+//!       name: cleanup.sh
+//!       contents: |
+//!         #!/bin/sh
+//!         rm -rf /var/cache/vendor/tmp
+//! ```
+//!
+//! The control node sends the sandbox to `sira-client` in the signed [Envelope](super::Envelope)
+//! alongside each action. `sira-client` then runs each script with [bubblewrap], which gives it a
+//! read-only view of the file system, except for its own empty `/tmp` and the paths listed in
+//! [Sandbox::writable], and, unless [Sandbox::network] is set, no network access. The sandbox
+//! doesn't affect other actions.
+//!
+//! [bubblewrap]: https://github.com/containers/bubblewrap
+
+#[cfg(doc)]
+use super::Action;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The restrictions on a sandboxed script. Please see the [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sandbox {
+    /// Whether the script may use the network. Defaults to `false`.
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub network: bool,
+
+    /// Absolute paths that the script may write to. Everything else is read-only.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub writable: Vec<String>,
+}
+
+impl Sandbox {
+    /// Returns an error if any of [Sandbox::writable] is not an absolute path.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for path in &self.writable {
+            if !Path::new(path).is_absolute() {
+                bail!("sandbox paths must be absolute, but this is not: {path}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the command and arguments that run `command` with `args` inside this sandbox.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of [Sandbox::writable] is not an absolute path.
+    pub fn wrap(&self, command: &str, args: &[String]) -> anyhow::Result<(String, Vec<String>)> {
+        self.validate()?;
+        let mut wrapped: Vec<String> = [
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+            "--unshare-all",
+            "--die-with-parent",
+            "--new-session",
+        ]
+        .map(str::to_string)
+        .into();
+        if self.network {
+            wrapped.push("--share-net".to_string());
+        }
+        for path in &self.writable {
+            wrapped.extend(["--bind".to_string(), path.clone(), path.clone()]);
+        }
+
+        // The command itself may live in /tmp, which the sandbox replaces with an empty directory.
+        if Path::new(command).is_absolute() {
+            wrapped.extend(["--ro-bind", command, command].map(str::to_string));
+        }
+
+        wrapped.push("--".to_string());
+        wrapped.push(command.to_string());
+        wrapped.extend_from_slice(args);
+        Ok(("bwrap".to_string(), wrapped))
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,86 @@
+use super::*;
+
+mod wrap {
+    use super::*;
+
+    // The arguments that every sandbox starts with.
+    const BASE: [&str; 12] = [
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+        "--unshare-all",
+        "--die-with-parent",
+        "--new-session",
+    ];
+
+    #[test]
+    fn isolates_by_default() {
+        let (command, args) = Sandbox::default()
+            .wrap("/tmp/tmp.abc", &["-x".to_string()])
+            .unwrap();
+        assert_eq!("bwrap", command);
+
+        let mut expected = BASE.to_vec();
+        expected.extend([
+            "--ro-bind",
+            "/tmp/tmp.abc",
+            "/tmp/tmp.abc",
+            "--",
+            "/tmp/tmp.abc",
+            "-x",
+        ]);
+        assert_eq!(expected, args);
+    }
+
+    #[test]
+    fn shares_network_and_writable_paths() {
+        let sandbox = Sandbox {
+            network: true,
+            writable: vec!["/var/cache/vendor".to_string()],
+        };
+        let (_, args) = sandbox.wrap("script.sh", &[]).unwrap();
+
+        let mut expected = BASE.to_vec();
+        expected.extend([
+            "--share-net",
+            "--bind",
+            "/var/cache/vendor",
+            "/var/cache/vendor",
+            "--",
+            "script.sh",
+        ]);
+        assert_eq!(expected, args);
+    }
+
+    #[test]
+    fn rejects_relative_paths() {
+        let sandbox = Sandbox {
+            network: false,
+            writable: vec!["cache".to_string()],
+        };
+        assert!(sandbox.wrap("/tmp/tmp.abc", &[]).is_err());
+    }
+}
+
+mod deserialize {
+    use super::*;
+
+    #[test]
+    fn defaults_to_full_isolation() {
+        assert_eq!(
+            Sandbox::default(),
+            serde_yaml::from_str::<Sandbox>("{}").unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(serde_yaml::from_str::<Sandbox>("readable: [/]").is_err());
+    }
+}
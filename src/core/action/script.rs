@@ -1,6 +1,8 @@
 //! Client-side logic for [Action::Script].
 
 use crate::client;
+use crate::client::platform;
+use crate::core::action::{Limits, Sandbox};
 use crate::core::Action;
 use anyhow::Context;
 use std::fs;
@@ -8,14 +10,56 @@ use std::io::Write;
 
 /// Implements client-side logic for [Action::Script].
 ///
-/// Writes the script to a temporary file, runs it as the specified user, and then deletes it.
-pub fn script(action: &Action) -> anyhow::Result<()> {
+/// Writes the script to a temporary file, runs it as the specified user under `limits` and in
+/// `sandbox`, if any, and then deletes it.
+pub fn script(action: &Action, limits: &Limits, sandbox: Option<&Sandbox>) -> anyhow::Result<()> {
+    let (user, script_path) = stage(action)?;
+    let result = command(user, &script_path, limits, sandbox)
+        .and_then(|(command, args)| client::run(command, &args));
+    let _ = fs::remove_file(&script_path);
+    result
+}
+
+/// Returns the command and arguments that run the script at `script_path` as `user`.
+///
+/// The sandbox goes inside `sudo` (please see [platform::run_as]), so that the script runs
+/// unprivileged within it, and the limits go outside, so that they cover everything.
+fn command(
+    user: &str,
+    script_path: &str,
+    limits: &Limits,
+    sandbox: Option<&Sandbox>,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let (command, args) = match sandbox {
+        Some(sandbox) => sandbox.wrap(script_path, &[])?,
+        None => (script_path.to_string(), Vec::new()),
+    };
+    let mut run_as_args = vec!["-u".to_string(), user.to_string(), command];
+    run_as_args.extend(args);
+    limits.wrap(platform::run_as(), &run_as_args)
+}
+
+/// Stages a script exactly as [script] would, but deletes it without running it.
+///
+/// This checks that the script can be written and handed over to its user, e.g. that the user
+/// exists, without changing anything else.
+pub fn check_script(action: &Action) -> anyhow::Result<()> {
+    let (_, script_path) = stage(action)?;
+    fs::remove_file(&script_path).context("failed to remove staged script")
+}
+
+/// Writes the script for an [Action::Script] to a temporary file that only its user can access.
+/// Returns the user and the path to the file.
+fn stage(action: &Action) -> anyhow::Result<(&str, String)> {
     let (user, contents) = match action {
         Action::Script {
             name: _,
             user,
             contents,
-        } => (user, contents),
+        } => (
+            user.as_deref().unwrap_or(Action::DEFAULT_USER_AND_GROUP),
+            contents,
+        ),
         _ => panic!("called script with an Action tht was not a Script: {action:?}"),
     };
 
@@ -37,9 +81,8 @@ pub fn script(action: &Action) -> anyhow::Result<()> {
     drop(script_file);
 
     client::run("chown", &[user, &script_path])?;
-
-    let result = client::run("sudo", &["-u", user, &script_path]);
-
-    let _ = fs::remove_file(&script_path);
-    result
+    Ok((user, script_path))
 }
+
+#[cfg(test)]
+mod test;
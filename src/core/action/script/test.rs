@@ -0,0 +1,35 @@
+use super::*;
+
+mod command {
+    use super::*;
+
+    #[test]
+    fn runs_script_as_user() {
+        assert_eq!(
+            (
+                platform::run_as().to_string(),
+                vec![
+                    "-u".to_string(),
+                    "bob".to_string(),
+                    "/tmp/tmp.abc".to_string()
+                ],
+            ),
+            command("bob", "/tmp/tmp.abc", &Limits::default(), None).unwrap(),
+        );
+    }
+
+    #[test]
+    fn sandboxes_inside_sudo_and_limits_outside() {
+        let limits = Limits {
+            cpu: Some("50%".to_string()),
+            ..Default::default()
+        };
+        let (command, args) =
+            command("bob", "/tmp/tmp.abc", &limits, Some(&Sandbox::default())).unwrap();
+        assert_eq!("systemd-run", command);
+
+        let sudo = args.iter().position(|arg| arg == "sudo").unwrap();
+        assert_eq!(["sudo", "-u", "bob", "bwrap"], args[sudo..sudo + 4]);
+        assert_eq!(Some(&"/tmp/tmp.abc".to_string()), args.last());
+    }
+}
@@ -0,0 +1,93 @@
+//! Whether an [Action] changed anything on a managed node.
+//!
+//! After running an action, `sira-client` prints a line starting with [STATUS_PREFIX] to say
+//! whether the action changed anything, e.g. `sira-status: changed`. `sira` reads that line back
+//! out of the action's output with [Status::from_output].
+
+#[cfg(doc)]
+use super::Action;
+use super::STATUS_PREFIX;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::process::Output;
+use std::str::FromStr;
+
+/// The outcome of an [Action].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The action succeeded without changing anything, e.g. because a line was already in a file.
+    Ok,
+
+    /// The action succeeded and changed something, or might have.
+    ///
+    /// `sira-client` can't tell whether a [Action::Command] or [Action::Script] changed anything,
+    /// so it always reports them as changed.
+    Changed,
+
+    /// The action failed.
+    Failed,
+}
+
+impl Status {
+    /// Returns [Status::Changed] if `changed` is true, or else [Status::Ok].
+    pub fn from_changed(changed: bool) -> Self {
+        match changed {
+            true => Status::Changed,
+            false => Status::Ok,
+        }
+    }
+
+    /// Returns the status of an action, given its output.
+    ///
+    /// An action that exited with an error has failed. Otherwise, its status is whatever the last
+    /// status line in its stdout says. If there is no status line, e.g. from an older
+    /// `sira-client`, the action is assumed to have changed something.
+    pub fn from_output(output: &Output) -> Self {
+        if !output.status.success() {
+            return Status::Failed;
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .rev()
+            .find_map(|line| line.strip_prefix(STATUS_PREFIX))
+            .and_then(|status| status.trim().parse().ok())
+            .unwrap_or(Status::Changed)
+    }
+
+    /// Returns the line that `sira-client` prints to report this status.
+    pub fn line(self) -> String {
+        format!("{STATUS_PREFIX}{self}")
+    }
+
+    /// Returns this status's name, e.g. `changed`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Changed => "changed",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Status {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "ok" => Ok(Status::Ok),
+            "changed" => Ok(Status::Changed),
+            "failed" => Ok(Status::Failed),
+            _ => anyhow::bail!("unknown status {s:?}; expected \"ok\", \"changed\", or \"failed\""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
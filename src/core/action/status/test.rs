@@ -0,0 +1,51 @@
+use super::*;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn output(code: i32, stdout: &str) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: vec![],
+    }
+}
+
+mod from_output {
+    use super::*;
+
+    #[test]
+    fn reads_status_line() {
+        let stdout = format!("some output\n{}\n", Status::Ok.line());
+        assert_eq!(Status::Ok, Status::from_output(&output(0, &stdout)));
+
+        let stdout = format!("{}\n", Status::Changed.line());
+        assert_eq!(Status::Changed, Status::from_output(&output(0, &stdout)));
+    }
+
+    #[test]
+    fn uses_last_status_line() {
+        let stdout = format!("{}\n{}\n", Status::Changed.line(), Status::Ok.line());
+        assert_eq!(Status::Ok, Status::from_output(&output(0, &stdout)));
+    }
+
+    #[test]
+    fn assumes_changed_without_status_line() {
+        assert_eq!(Status::Changed, Status::from_output(&output(0, "")));
+        let stdout = format!("{STATUS_PREFIX}bogus\n");
+        assert_eq!(Status::Changed, Status::from_output(&output(0, &stdout)));
+    }
+
+    #[test]
+    fn failure_overrides_status_line() {
+        let stdout = format!("{}\n", Status::Ok.line());
+        assert_eq!(Status::Failed, Status::from_output(&output(1, &stdout)));
+    }
+}
+
+#[test]
+fn round_trips_through_str() {
+    for status in [Status::Ok, Status::Changed, Status::Failed] {
+        assert_eq!(status, status.to_string().parse().unwrap());
+    }
+    assert!("maybe".parse::<Status>().is_err());
+}
@@ -0,0 +1,314 @@
+//! Client-side logic for [Action::Swap].
+
+use super::Action;
+use crate::client;
+use anyhow::{bail, Context};
+use std::fs::{self, File, Metadata, OpenOptions, Permissions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// The smallest swap file that Sira creates, in bytes. `mkswap` needs at least ten pages.
+pub const MIN_SWAP_SIZE: u64 = 1 << 20;
+
+/// The signature that `mkswap` writes at the end of the first page of swap space.
+const SWAP_SIGNATURE: &[u8] = b"SWAPSPACE2";
+
+/// The page sizes at whose ends [swap_changes] looks for [SWAP_SIGNATURE].
+const PAGE_SIZES: [usize; 4] = [4096, 8192, 16384, 65536];
+
+/// Where [swap_changes] looks for the system's swap configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapPaths {
+    /// The file system table, i.e. `/etc/fstab`.
+    pub fstab: PathBuf,
+
+    /// The file in which the kernel lists active swap space, i.e. `/proc/swaps`.
+    pub proc_swaps: PathBuf,
+}
+
+impl Default for SwapPaths {
+    fn default() -> Self {
+        SwapPaths {
+            fstab: PathBuf::from("/etc/fstab"),
+            proc_swaps: PathBuf::from("/proc/swaps"),
+        }
+    }
+}
+
+/// One change that an [Action::Swap] makes to a managed node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SwapChange {
+    /// Creates a swap file at `path`, `size` bytes long, filled with zeros and readable only by
+    /// root.
+    Create { path: PathBuf, size: u64 },
+
+    /// Makes the swap file at `path` owned by root and readable only by root.
+    Secure(PathBuf),
+
+    /// Formats the swap file at `path` with `mkswap`.
+    Format(PathBuf),
+
+    /// Starts swapping to `path` with `swapon`.
+    Activate(PathBuf),
+
+    /// Stops swapping to `path` with `swapoff`.
+    Deactivate(PathBuf),
+
+    /// Removes the swap file at `path`.
+    Remove(PathBuf),
+
+    /// Writes `after` to the file system table at `path`, which contains `before`.
+    WriteFstab {
+        path: PathBuf,
+        before: String,
+        after: String,
+    },
+}
+
+impl SwapChange {
+    /// Makes the change.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file can't be created, changed, or removed, or if `mkswap`, `swapon`,
+    /// or `swapoff` fails.
+    pub fn apply(&self) -> anyhow::Result<()> {
+        match self {
+            SwapChange::Create { path, size } => create(path, *size)
+                .with_context(|| format!("failed to create swap file {}", path.display())),
+            SwapChange::Secure(path) => std::os::unix::fs::chown(path, Some(0), Some(0))
+                .and_then(|()| fs::set_permissions(path, Permissions::from_mode(0o600)))
+                .with_context(|| format!("failed to secure swap file {}", path.display())),
+            SwapChange::Format(path) => client::run("mkswap", &[path]),
+            SwapChange::Activate(path) => client::run("swapon", &[path]),
+            SwapChange::Deactivate(path) => client::run("swapoff", &[path]),
+            SwapChange::Remove(path) => fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display())),
+            SwapChange::WriteFstab { path, after, .. } => fs::write(path, after)
+                .with_context(|| format!("failed to write {}", path.display())),
+        }
+    }
+}
+
+/// Implements client-side logic for [Action::Swap] by listing the changes that would bring the
+/// managed node into the state it describes, in the order in which to make them. Apply each with
+/// [SwapChange::apply], or report them in check mode.
+///
+/// `paths` is usually [SwapPaths::default].
+///
+/// # Returns
+///
+/// Returns an empty list if the swap space and its entry in the file system table are already as
+/// described.
+///
+/// # Errors
+///
+/// Returns an error if the path or size is invalid, if a file can't be read, if a swap file would
+/// need a size to be created, or if a block device isn't formatted as swap space, since Sira never
+/// formats block devices.
+///
+/// # Panics
+///
+/// Panics if `action` is not of type [Action::Swap].
+pub fn swap_changes(action: &Action, paths: &SwapPaths) -> anyhow::Result<Vec<SwapChange>> {
+    let (path, size, &persist, &remove) = match action {
+        Action::Swap {
+            path,
+            size,
+            persist,
+            remove,
+        } => (path, size, persist, remove),
+        _ => panic!("called swap_changes with an Action that was not a Swap: {action:?}"),
+    };
+    if !path.starts_with('/') || path.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        bail!("invalid swap path {path:?}: paths must be absolute, with no whitespace");
+    }
+    let size = size.as_deref().map(parse_size).transpose()?;
+
+    let swaps = read(&paths.proc_swaps)?.unwrap_or_default();
+    let active = swaps.lines().skip(1).any(|line| is_entry(line, path));
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("failed to read {path}")),
+    };
+    let is_device = metadata
+        .as_ref()
+        .is_some_and(|metadata| metadata.file_type().is_block_device());
+    if let Some(metadata) = &metadata {
+        if !is_device && !metadata.is_file() {
+            bail!("{path} is neither a swap file nor a block device");
+        }
+    }
+
+    let file = PathBuf::from(path);
+    let mut changes = vec![];
+    if remove {
+        if active {
+            changes.push(SwapChange::Deactivate(file.clone()));
+        }
+        if metadata.is_some() && !is_device {
+            changes.push(SwapChange::Remove(file));
+        }
+    } else {
+        let mut create = false;
+        match (&metadata, size) {
+            (None, Some(_)) => create = true,
+            (None, None) => bail!("{path} doesn't exist; set size to create a swap file"),
+            (Some(_), Some(_)) if is_device => {
+                bail!("{path} is a block device, so size doesn't apply")
+            }
+            (Some(_), None) if is_device => {
+                if !active && !has_signature(&file)? {
+                    bail!(
+                        "{path} isn't formatted as swap space; run mkswap on it first, since Sira \
+                        never formats block devices"
+                    );
+                }
+            }
+            (Some(metadata), Some(size)) if metadata.len() != size => {
+                // Resize the swap file by replacing it.
+                if active {
+                    changes.push(SwapChange::Deactivate(file.clone()));
+                }
+                changes.push(SwapChange::Remove(file.clone()));
+                create = true;
+            }
+            (Some(metadata), _) => {
+                if !is_secure(metadata) {
+                    changes.push(SwapChange::Secure(file.clone()));
+                }
+                if !active && !has_signature(&file)? {
+                    changes.push(SwapChange::Format(file.clone()));
+                }
+            }
+        }
+        if let (true, Some(size)) = (create, size) {
+            let path = file.clone();
+            changes.push(SwapChange::Create { path, size });
+            changes.push(SwapChange::Format(file.clone()));
+        }
+        if create || !active {
+            changes.push(SwapChange::Activate(file));
+        }
+    }
+
+    let before = read(&paths.fstab)?.unwrap_or_default();
+    let entry = (persist && !remove).then(|| format!("{path} none swap sw 0 0"));
+    if let Some(after) = fstab_with(&before, path, entry.as_deref()) {
+        let path = paths.fstab.clone();
+        changes.push(SwapChange::WriteFstab {
+            path,
+            before,
+            after,
+        });
+    }
+    Ok(changes)
+}
+
+/// Parses a size in bytes, optionally followed by `K`, `M`, `G`, or `T` for powers of 1024, e.g.
+/// `2G`.
+///
+/// # Errors
+///
+/// Returns an error if `size` isn't a size, or is smaller than [MIN_SWAP_SIZE].
+pub fn parse_size(size: &str) -> anyhow::Result<u64> {
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: u64 = match size[digits.len()..].to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => bail!("invalid swap size {size:?}: expected e.g. \"512M\" or \"2G\""),
+    };
+    let bytes = digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(multiplier))
+        .with_context(|| format!("invalid swap size {size:?}: expected e.g. \"512M\" or \"2G\""))?;
+    if bytes < MIN_SWAP_SIZE {
+        bail!("swap size {size:?} is too small: swap space must be at least 1M");
+    }
+    Ok(bytes)
+}
+
+/// Returns the contents of the file at `path`, or [None] if it doesn't exist.
+fn read(path: &Path) -> anyhow::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Returns whether `line`, from the file system table or `/proc/swaps`, is about `path`.
+fn is_entry(line: &str, path: &str) -> bool {
+    line.split_whitespace().next() == Some(path)
+}
+
+/// Returns whether a swap file is owned by root and readable only by root.
+fn is_secure(metadata: &Metadata) -> bool {
+    metadata.uid() == 0 && metadata.gid() == 0 && metadata.mode() & 0o7777 == 0o600
+}
+
+/// Returns whether `path` starts with swap space that `mkswap` formatted.
+fn has_signature(path: &Path) -> anyhow::Result<bool> {
+    let mut start = vec![];
+    File::open(path)
+        .and_then(|file| file.take(PAGE_SIZES[3] as u64).read_to_end(&mut start))
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(PAGE_SIZES.iter().any(|&page_size| {
+        start.get(page_size - SWAP_SIGNATURE.len()..page_size) == Some(SWAP_SIGNATURE)
+    }))
+}
+
+/// Returns `fstab` with its entries for `path` replaced by `entry`, or removed if `entry` is
+/// [None], or [None] if `fstab` is already that way. Entries match regardless of the white space
+/// between their fields.
+fn fstab_with(fstab: &str, path: &str, entry: Option<&str>) -> Option<String> {
+    let entries: Vec<&str> = fstab.lines().filter(|line| is_entry(line, path)).collect();
+    let up_to_date = match entry {
+        Some(entry) => {
+            entries.len() == 1 && entries[0].split_whitespace().eq(entry.split_whitespace())
+        }
+        None => entries.is_empty(),
+    };
+    if up_to_date {
+        return None;
+    }
+
+    // Keep the first entry's place, if any.
+    let mut entry = entry;
+    let mut after = String::new();
+    for line in fstab.lines() {
+        match is_entry(line, path) {
+            true => after.extend(entry.take().map(|entry| format!("{entry}\n"))),
+            false => after.push_str(&format!("{line}\n")),
+        }
+    }
+    after.extend(entry.map(|entry| format!("{entry}\n")));
+    Some(after)
+}
+
+/// Creates a swap file at `path` that's `size` bytes long. Swap files can't have holes, so this
+/// writes every byte rather than just setting the file's length.
+fn create(path: &Path, size: u64) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    let zeros = vec![0; 1 << 20];
+    let mut remaining = size;
+    while remaining > 0 {
+        let chunk = remaining.min(zeros.len() as u64);
+        file.write_all(&zeros[..chunk as usize])?;
+        remaining -= chunk;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod test;
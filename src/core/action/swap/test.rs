@@ -0,0 +1,287 @@
+use super::*;
+use tempfile::TempDir;
+
+const MIB: u64 = 1 << 20;
+
+// Returns paths in a temporary directory and the path of a swap file there, which is active if
+// `active` is true.
+fn paths(active: bool) -> (TempDir, SwapPaths, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = SwapPaths {
+        fstab: dir.path().join("fstab"),
+        proc_swaps: dir.path().join("swaps"),
+    };
+    let file = dir.path().join("swapfile");
+    let mut swaps = "Filename\tType\tSize\tUsed\tPriority\n".to_string();
+    if active {
+        swaps.push_str(&format!("{}\tfile\t1020\t0\t-2\n", file.display()));
+    }
+    fs::write(&paths.proc_swaps, swaps).unwrap();
+    (dir, paths, file)
+}
+
+fn action(path: &Path, size: Option<&str>, persist: bool, remove: bool) -> Action {
+    Action::Swap {
+        path: path.to_str().unwrap().to_string(),
+        size: size.map(str::to_string),
+        persist,
+        remove,
+    }
+}
+
+// Writes a swap file of `size` bytes to `path`, formatted as if by mkswap on a system with 4 KiB
+// pages and readable only by its owner.
+fn formatted(path: &Path, size: u64) {
+    let mut contents = vec![0; size as usize];
+    contents[4096 - SWAP_SIGNATURE.len()..4096].copy_from_slice(SWAP_SIGNATURE);
+    fs::write(path, contents).unwrap();
+    fs::set_permissions(path, Permissions::from_mode(0o600)).unwrap();
+}
+
+// Returns the change that secures `path`, if it needs one: tests may not run as root.
+fn secure(path: &Path) -> Option<SwapChange> {
+    let metadata = fs::metadata(path).unwrap();
+    (!is_secure(&metadata)).then(|| SwapChange::Secure(path.to_path_buf()))
+}
+
+fn write_fstab(paths: &SwapPaths, before: &str, after: &str) -> SwapChange {
+    SwapChange::WriteFstab {
+        path: paths.fstab.clone(),
+        before: before.to_string(),
+        after: after.to_string(),
+    }
+}
+
+mod swap_changes {
+    use super::*;
+
+    #[test]
+    fn creates_enables_and_persists_swap_file() {
+        let (_dir, paths, file) = paths(false);
+        let entry = format!("{} none swap sw 0 0\n", file.display());
+        assert_eq!(
+            vec![
+                SwapChange::Create {
+                    path: file.clone(),
+                    size: 2 * MIB,
+                },
+                SwapChange::Format(file.clone()),
+                SwapChange::Activate(file.clone()),
+                write_fstab(&paths, "", &entry),
+            ],
+            swap_changes(&action(&file, Some("2M"), true, false), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn changes_nothing_when_in_place() {
+        let (_dir, paths, file) = paths(true);
+        formatted(&file, MIB);
+        fs::write(
+            &paths.fstab,
+            format!(
+                "/dev/sda1 / ext4 defaults 0 1\n{}  none  swap  sw  0  0\n",
+                file.display()
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            Vec::from_iter(secure(&file)),
+            swap_changes(&action(&file, Some("1M"), true, false), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn enables_existing_swap_file_without_size() {
+        let (_dir, paths, file) = paths(false);
+        formatted(&file, MIB);
+        let mut expected = Vec::from_iter(secure(&file));
+        expected.push(SwapChange::Activate(file.clone()));
+        assert_eq!(
+            expected,
+            swap_changes(&action(&file, None, false, false), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn formats_unformatted_swap_file() {
+        let (_dir, paths, file) = paths(false);
+        fs::write(&file, vec![0; MIB as usize]).unwrap();
+        fs::set_permissions(&file, Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(
+            vec![
+                SwapChange::Secure(file.clone()),
+                SwapChange::Format(file.clone()),
+                SwapChange::Activate(file.clone()),
+            ],
+            swap_changes(&action(&file, Some("1M"), false, false), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn replaces_swap_file_of_other_size() {
+        let (_dir, paths, file) = paths(true);
+        formatted(&file, MIB);
+        assert_eq!(
+            vec![
+                SwapChange::Deactivate(file.clone()),
+                SwapChange::Remove(file.clone()),
+                SwapChange::Create {
+                    path: file.clone(),
+                    size: 2 * MIB,
+                },
+                SwapChange::Format(file.clone()),
+                SwapChange::Activate(file.clone()),
+            ],
+            swap_changes(&action(&file, Some("2m"), false, false), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn replaces_duplicate_and_outdated_entries() {
+        let (_dir, paths, file) = paths(true);
+        formatted(&file, MIB);
+        let before = format!(
+            "{0} none swap defaults 0 0\n/dev/sda1 / ext4 defaults 0 1\n{0} none swap sw 0 0\n",
+            file.display(),
+        );
+        fs::write(&paths.fstab, &before).unwrap();
+        let after = format!(
+            "{} none swap sw 0 0\n/dev/sda1 / ext4 defaults 0 1\n",
+            file.display(),
+        );
+        let mut expected = Vec::from_iter(secure(&file));
+        expected.push(write_fstab(&paths, &before, &after));
+        assert_eq!(
+            expected,
+            swap_changes(&action(&file, Some("1M"), true, false), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn removes_swap_file_and_entry() {
+        let (_dir, paths, file) = paths(true);
+        formatted(&file, MIB);
+        let before = format!(
+            "/dev/sda1 / ext4 defaults 0 1\n{} none swap sw 0 0\n",
+            file.display(),
+        );
+        fs::write(&paths.fstab, &before).unwrap();
+        assert_eq!(
+            vec![
+                SwapChange::Deactivate(file.clone()),
+                SwapChange::Remove(file.clone()),
+                write_fstab(&paths, &before, "/dev/sda1 / ext4 defaults 0 1\n"),
+            ],
+            swap_changes(&action(&file, None, true, true), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn changes_nothing_when_already_removed() {
+        let (_dir, paths, file) = paths(false);
+        fs::write(&paths.fstab, "/dev/sda1 / ext4 defaults 0 1\n").unwrap();
+        assert_eq!(
+            Vec::<SwapChange>::new(),
+            swap_changes(&action(&file, None, true, true), &paths).unwrap(),
+        );
+    }
+
+    #[test]
+    fn needs_size_to_create_swap_file() {
+        let (_dir, paths, file) = paths(false);
+        assert!(swap_changes(&action(&file, None, true, false), &paths).is_err());
+    }
+
+    #[test]
+    fn rejects_directories() {
+        let (dir, paths, _file) = paths(false);
+        assert!(swap_changes(&action(dir.path(), None, true, false), &paths).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        let (_dir, paths, _file) = paths(false);
+        for path in ["swapfile", "/swap file", "/swap\tfile", ""] {
+            let action = action(Path::new(path), Some("1G"), true, false);
+            assert!(
+                swap_changes(&action, &paths).is_err(),
+                "{path:?} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "called swap_changes with an Action that was not a Swap")]
+    fn panics_on_other_actions() {
+        let (_dir, paths, _file) = paths(false);
+        let action = Action::Command(vec![]);
+        let _ = swap_changes(&action, &paths);
+    }
+}
+
+mod parse_size {
+    use super::*;
+
+    #[test]
+    fn accepts_bytes_and_suffixes() {
+        assert_eq!(MIB, parse_size("1048576").unwrap());
+        assert_eq!(MIB, parse_size("1024K").unwrap());
+        assert_eq!(512 * MIB, parse_size("512M").unwrap());
+        assert_eq!(2 << 30, parse_size("2G").unwrap());
+        assert_eq!(1 << 40, parse_size("1t").unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for size in [
+            "",
+            "G",
+            "2GB",
+            "1.5G",
+            "-1G",
+            "2 G",
+            "99999999999T",
+            "1023K",
+        ] {
+            assert!(parse_size(size).is_err(), "{size:?} should be invalid");
+        }
+    }
+}
+
+mod apply {
+    use super::*;
+
+    #[test]
+    fn creates_swap_file() {
+        let (_dir, _paths, file) = paths(false);
+        let change = SwapChange::Create {
+            path: file.clone(),
+            size: 3 * MIB / 2,
+        };
+        change.apply().unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        assert_eq!(3 * MIB / 2, metadata.len());
+        assert_eq!(0o600, metadata.mode() & 0o777);
+        assert!(fs::read(&file).unwrap().iter().all(|&byte| byte == 0));
+
+        // Never overwrite an existing file.
+        assert!(change.apply().is_err());
+    }
+
+    #[test]
+    fn writes_and_removes_files() {
+        let (_dir, paths, file) = paths(false);
+        write_fstab(&paths, "", "/swapfile none swap sw 0 0\n")
+            .apply()
+            .unwrap();
+        assert_eq!(
+            "/swapfile none swap sw 0 0\n",
+            fs::read_to_string(&paths.fstab).unwrap(),
+        );
+
+        fs::write(&file, "").unwrap();
+        SwapChange::Remove(file.clone()).apply().unwrap();
+        assert!(!file.exists());
+    }
+}
@@ -0,0 +1,332 @@
+//! Fact expressions, which select hosts by their [Facts], e.g. `hosts: {fact: "os.id == 'debian'"}`
+//! in a manifest file.
+//!
+//! An expression compares facts with each other or with literals and combines the comparisons:
+//!
+//! - A fact is a dotted path into the [Facts] that `sira-client facts` prints, e.g. `hostname`,
+//!   `os.id`, or `memory.total_bytes`. Only facts with a single value can be compared, so e.g.
+//!   `disks` can't be.
+//! - A literal is a string in single or double quotes, e.g. `'debian'`, or a whole number, e.g.
+//!   `4000000000`.
+//! - The comparisons are `==`, `!=`, `<`, `<=`, `>`, and `>=`. The last four hold only between
+//!   numbers; a string is never less or greater than anything.
+//! - Comparisons combine with `not`, `and`, and `or`, which bind in that order, and parentheses.
+//!
+//! A fact that's missing, e.g. [Os::id] on a node without `/etc/os-release`, equals nothing, so
+//! `os.id != 'debian'` holds for it.
+//!
+//! [Os::id]: crate::client::facts::Os::id
+
+use crate::client::facts::Facts;
+use anyhow::{anyhow, bail};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A parsed fact expression. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FactExpr {
+    /// The expression's source, for error messages.
+    source: String,
+
+    /// The parsed expression.
+    expr: Expr,
+}
+
+impl FactExpr {
+    /// Parses `source` as a fact expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `source` isn't a valid expression or names a fact that doesn't exist.
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let parse = || {
+            let mut parser = Parser {
+                tokens: tokenize(source)?,
+                next: 0,
+            };
+            let expr = parser.or()?;
+            if let Some(token) = parser.tokens.get(parser.next) {
+                bail!("unexpected {token}");
+            }
+            Ok(expr)
+        };
+        match parse() {
+            Ok(expr) => Ok(FactExpr {
+                source: source.to_string(),
+                expr,
+            }),
+            Err(e) => Err(e.context(format!("invalid fact expression {source:?}"))),
+        }
+    }
+
+    /// Returns whether `facts` match this expression.
+    pub fn matches(&self, facts: &Facts) -> bool {
+        let facts = serde_json::to_value(facts).expect("Facts should always serialize to JSON");
+        self.expr.eval(&facts)
+    }
+}
+
+impl fmt::Display for FactExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A node in a parsed [FactExpr].
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Comparison, Operand),
+}
+
+impl Expr {
+    /// Returns whether `facts`, as JSON, match this expression.
+    fn eval(&self, facts: &Value) -> bool {
+        match self {
+            Expr::Or(left, right) => left.eval(facts) || right.eval(facts),
+            Expr::And(left, right) => left.eval(facts) && right.eval(facts),
+            Expr::Not(expr) => !expr.eval(facts),
+            Expr::Compare(left, comparison, right) => {
+                let (left, right) = (left.value(facts), right.value(facts));
+                match comparison {
+                    Comparison::Eq => equal(left, right),
+                    Comparison::Ne => !equal(left, right),
+                    Comparison::Lt => order(left, right) == Some(Ordering::Less),
+                    Comparison::Le => order(left, right).is_some_and(Ordering::is_le),
+                    Comparison::Gt => order(left, right) == Some(Ordering::Greater),
+                    Comparison::Ge => order(left, right).is_some_and(Ordering::is_ge),
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether two values are equal. A missing value equals nothing.
+fn equal(left: Option<&Value>, right: Option<&Value>) -> bool {
+    match (left, right) {
+        (Some(Value::Null), _) | (_, Some(Value::Null)) | (None, _) | (_, None) => false,
+        (Some(left), Some(right)) => left == right,
+    }
+}
+
+/// Returns how two values compare, or [None] unless both are numbers.
+fn order(left: Option<&Value>, right: Option<&Value>) -> Option<Ordering> {
+    Some(left?.as_u64()?.cmp(&right?.as_u64()?))
+}
+
+/// One side of a comparison.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    /// A dotted path to a fact.
+    Fact(Vec<String>),
+
+    /// A literal string or number.
+    Literal(Value),
+}
+
+impl Operand {
+    /// Returns this operand's value, or [None] if it's a fact that `facts` lack.
+    fn value<'a>(&'a self, facts: &'a Value) -> Option<&'a Value> {
+        match self {
+            Operand::Fact(path) => path.iter().try_fold(facts, |value, key| value.get(key)),
+            Operand::Literal(value) => Some(value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A token in a fact expression's source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    /// A fact's dotted path, or a keyword.
+    Word(String),
+    String(String),
+    Number(u64),
+    Comparison(Comparison),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{word:?}"),
+            Token::String(s) => write!(f, "string {s:?}"),
+            Token::Number(n) => write!(f, "number {n}"),
+            Token::Comparison(_) => f.write_str("comparison"),
+            Token::Open => f.write_str("\"(\""),
+            Token::Close => f.write_str("\")\""),
+        }
+    }
+}
+
+/// Splits `source` into [Token]s.
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '\'' | '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(next) if next == c => break,
+                        Some(next) => s.push(next),
+                        None => bail!("unterminated string"),
+                    }
+                }
+                Token::String(s)
+            }
+            '=' | '!' | '<' | '>' => {
+                let equals = chars.next_if_eq(&'=').is_some();
+                Token::Comparison(match (c, equals) {
+                    ('=', true) => Comparison::Eq,
+                    ('!', true) => Comparison::Ne,
+                    ('<', false) => Comparison::Lt,
+                    ('<', true) => Comparison::Le,
+                    ('>', false) => Comparison::Gt,
+                    ('>', true) => Comparison::Ge,
+                    _ => bail!("unexpected {c:?}; expected == or !="),
+                })
+            }
+            '0'..='9' => {
+                let mut digits = c.to_string();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                Token::Number(
+                    digits
+                        .parse()
+                        .map_err(|_| anyhow!("number {digits} is too large"))?,
+                )
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(next) =
+                    chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+                {
+                    word.push(next);
+                }
+                Token::Word(word)
+            }
+            c => bail!("unexpected {c:?}"),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// A recursive-descent parser over [Token]s.
+struct Parser {
+    tokens: Vec<Token>,
+
+    /// The index of the next token to parse.
+    next: usize,
+}
+
+impl Parser {
+    /// Consumes the next token if it's the keyword `keyword`.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.next) {
+            Some(Token::Word(word)) if word == keyword => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses `and` expressions separated by `or`.
+    fn or(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parses `not` expressions separated by `and`.
+    fn and(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parses a comparison or parenthesized expression, optionally preceded by `not`.
+    fn not(&mut self) -> anyhow::Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.tokens.get(self.next) == Some(&Token::Open) {
+            self.next += 1;
+            let expr = self.or()?;
+            match self.tokens.get(self.next) {
+                Some(Token::Close) => self.next += 1,
+                Some(token) => bail!("unexpected {token}; expected \")\""),
+                None => bail!("missing \")\""),
+            }
+            return Ok(expr);
+        }
+
+        let left = self.operand()?;
+        let comparison = match self.tokens.get(self.next) {
+            Some(Token::Comparison(comparison)) => *comparison,
+            Some(token) => bail!("unexpected {token}; expected a comparison"),
+            None => bail!("missing comparison"),
+        };
+        self.next += 1;
+        let right = self.operand()?;
+        Ok(Expr::Compare(left, comparison, right))
+    }
+
+    /// Parses a fact or a literal.
+    fn operand(&mut self) -> anyhow::Result<Operand> {
+        let Some(token) = self.tokens.get(self.next) else {
+            bail!("missing fact or value");
+        };
+        let operand = match token {
+            Token::Word(word) if ["and", "or", "not"].contains(&word.as_str()) => {
+                bail!("unexpected {token}; expected a fact or value")
+            }
+            Token::Word(path) => Operand::Fact(fact_path(path)?),
+            Token::String(s) => Operand::Literal(Value::from(s.as_str())),
+            Token::Number(n) => Operand::Literal(Value::from(*n)),
+            token => bail!("unexpected {token}; expected a fact or value"),
+        };
+        self.next += 1;
+        Ok(operand)
+    }
+}
+
+/// Splits a fact's dotted path, checking that it names a single value in [Facts].
+fn fact_path(path: &str) -> anyhow::Result<Vec<String>> {
+    let keys: Vec<String> = path.split('.').map(str::to_string).collect();
+    let facts = serde_json::to_value(Facts::default()).expect("Facts should always serialize");
+    match keys.iter().try_fold(&facts, |value, key| value.get(key)) {
+        Some(Value::Object(_) | Value::Array(_)) => {
+            bail!("fact {path:?} has more than one value")
+        }
+        Some(_) => Ok(keys),
+        None => bail!("unknown fact {path:?}"),
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,128 @@
+use super::*;
+use crate::client::facts::{Memory, Os};
+
+fn facts() -> Facts {
+    Facts {
+        hostname: "web1".to_string(),
+        os: Os {
+            id: Some("debian".to_string()),
+            version_id: Some("12".to_string()),
+            ..Default::default()
+        },
+        arch: "x86_64".to_string(),
+        memory: Memory {
+            total_bytes: 4_000_000_000,
+            available_bytes: 1_000_000_000,
+        },
+        ..Default::default()
+    }
+}
+
+fn matches(source: &str) -> bool {
+    FactExpr::parse(source).unwrap().matches(&facts())
+}
+
+mod parse {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_expressions() {
+        for source in [
+            "os.id == 'debian'",
+            "os.id==\"debian\"",
+            "not (arch != 'x86_64' or memory.total_bytes < 1000) and hostname == hostname",
+            "'debian' == os.id",
+        ] {
+            assert!(FactExpr::parse(source).is_ok(), "{source}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for source in [
+            "",
+            "os.id",
+            "os.id = 'debian'",
+            "os.id == 'debian",
+            "os.id == 'debian' and",
+            "(os.id == 'debian'",
+            "os.id == 'debian')",
+            "os.id == 'debian' os.id == 'arch'",
+            "os.id == and",
+            "os.id == 99999999999999999999",
+            "os.id == $x",
+        ] {
+            assert!(FactExpr::parse(source).is_err(), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_facts() {
+        let err = FactExpr::parse("os_family == 'debian'").unwrap_err();
+        assert!(format!("{err:#}").contains("unknown fact \"os_family\""));
+    }
+
+    #[test]
+    fn rejects_facts_with_many_values() {
+        for source in ["os == 'debian'", "disks == 'sda'"] {
+            let err = FactExpr::parse(source).unwrap_err();
+            assert!(
+                format!("{err:#}").contains("more than one value"),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn names_source_in_errors() {
+        let err = FactExpr::parse("os.id ==").unwrap_err();
+        assert!(format!("{err:#}").starts_with("invalid fact expression \"os.id ==\""));
+    }
+}
+
+mod matches {
+    use super::*;
+
+    #[test]
+    fn compares_strings() {
+        assert!(matches("os.id == 'debian'"));
+        assert!(!matches("os.id == 'arch'"));
+        assert!(matches("os.id != 'arch'"));
+        assert!(!matches("os.version_id == 12"));
+    }
+
+    #[test]
+    fn compares_numbers() {
+        assert!(matches("memory.total_bytes >= 4000000000"));
+        assert!(!matches("memory.total_bytes > 4000000000"));
+        assert!(matches("memory.available_bytes < memory.total_bytes"));
+        assert!(matches("memory.available_bytes <= 1000000000"));
+    }
+
+    #[test]
+    fn does_not_order_strings() {
+        assert!(!matches("os.version_id > '1'"));
+        assert!(!matches("os.version_id <= '99'"));
+    }
+
+    #[test]
+    fn missing_facts_equal_nothing() {
+        assert!(!matches("os.name == os.name"));
+        assert!(matches("os.name != 'Debian GNU/Linux'"));
+    }
+
+    #[test]
+    fn combines_comparisons() {
+        assert!(matches("os.id == 'arch' or os.id == 'debian'"));
+        assert!(!matches("os.id == 'debian' and arch == 'aarch64'"));
+        assert!(matches("not arch == 'aarch64'"));
+
+        // `and` binds more tightly than `or`.
+        assert!(matches(
+            "os.id == 'debian' or os.id == 'arch' and arch == 'aarch64'"
+        ));
+        assert!(!matches(
+            "(os.id == 'debian' or os.id == 'arch') and arch == 'aarch64'"
+        ));
+    }
+}
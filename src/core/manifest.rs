@@ -1,13 +1,16 @@
 //! Types for representing manifest files.
 use crate::core::action::{Action, HostAction};
+use crate::core::expr::FactExpr;
 #[cfg(doc)]
 use crate::core::plan::Plan;
 use crate::core::task::Task;
 use crate::crypto;
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context};
 use indexmap::IndexMap;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_yaml::Deserializer;
+use serde_yaml::{Deserializer, Mapping, Value};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Once};
@@ -73,7 +76,6 @@ fn verify(source: impl AsRef<Path>, source_file: &[u8], file_type: &str) -> anyh
 ///
 /// Verifies the signatures on `source` and any task files that `source` includes.
 pub fn load_manifests(source: impl AsRef<Path>) -> anyhow::Result<Vec<Manifest>> {
-    let mut manifests = vec![];
     let source_file = match fs::read(&source) {
         Ok(read) => read,
         Err(err) => bail!(
@@ -91,16 +93,94 @@ pub fn load_manifests(source: impl AsRef<Path>) -> anyhow::Result<Vec<Manifest>>
         source.as_ref(),
     ))?;
 
-    for document in Deserializer::from_slice(&source_file) {
-        let manifest_file = ManifestFile::deserialize(document)?;
-        let include = load_includes(base_path, manifest_file.include)?;
+    parse_manifest_file(source.as_ref(), &source_file, |includes| {
+        load_includes(base_path, includes)
+    })
+}
+
+/// Parses [Manifest] values from the contents of a manifest file in memory, e.g. one that a
+/// program templated or fetched at run time.
+///
+/// `source` labels the manifests (please see [Manifest::source]); it need not be a real path.
+/// Each task file that the manifest includes is looked up by its name, exactly as the manifest
+/// writes it, in `task_files`, which maps names to the task files' contents. Each [Task]'s
+/// [Task::source] is its task file's name.
+///
+/// Unlike [load_manifests], this function touches no files and checks no signatures, so the
+/// caller vouches for the contents, just as when it builds a [Manifest] directly.
+///
+/// # Errors
+///
+/// Returns an error if the manifest or a task file can't be parsed, or if the manifest includes
+/// a task file that `task_files` lacks.
+///
+/// # Example
+///
+/// ```
+/// use sira::core::manifest;
+/// use std::collections::BTreeMap;
+///
+/// let manifest_file = "\
+/// name: Web servers
+/// hosts: [web1]
+/// include: [nginx.yaml]
+/// ";
+/// let task_files = BTreeMap::from([(
+///     "nginx.yaml".to_string(),
+///     "name: Install nginx\nactions:\n  - command: [apt-get install -y nginx]\n".to_string(),
+/// )]);
+/// let manifests = manifest::parse_manifests("generated", manifest_file, &task_files).unwrap();
+/// assert_eq!("Install nginx", manifests[0].include[0].name);
+/// ```
+pub fn parse_manifests(
+    source: impl AsRef<Path>,
+    manifest_file: &str,
+    task_files: &BTreeMap<String, String>,
+) -> anyhow::Result<Vec<Manifest>> {
+    parse_manifest_file(source.as_ref(), manifest_file.as_bytes(), |includes| {
+        let mut tasks = vec![];
+        for task_file in includes {
+            let Some(contents) = task_files.get(&task_file) else {
+                bail!("Error loading task file: {task_file}\nno such task file in memory");
+            };
+            tasks.extend(load_tasks(task_file, contents.as_bytes())?);
+        }
+        Ok(tasks)
+    })
+}
+
+/// Parses [Task] values from the contents of a task file in memory. `source` labels the tasks
+/// (please see [Task::source]); it need not be a real path.
+///
+/// Like [parse_manifests], this checks no signatures.
+pub fn parse_tasks(source: impl AsRef<Path>, task_file: &str) -> anyhow::Result<Vec<Task>> {
+    load_tasks(source, task_file.as_bytes())
+}
+
+/// Parses each manifest in `source_file`, the contents of the manifest file at `source`, and
+/// loads the task files that each one includes with `load_includes`, which receives their names
+/// as the manifest writes them.
+///
+/// This is a private method meant for use by [load_manifests] and [parse_manifests].
+fn parse_manifest_file(
+    source: &Path,
+    source_file: &[u8],
+    mut load_includes: impl FnMut(Vec<String>) -> anyhow::Result<Vec<Task>>,
+) -> anyhow::Result<Vec<Manifest>> {
+    let mut manifests = vec![];
+    for manifest_file in deserialize_documents::<ManifestFile>(source_file)? {
+        let include = load_includes(manifest_file.include)?;
 
+        let (hosts, fact) = manifest_file.hosts.split()?;
         let manifest = Manifest {
-            source: Some(source.as_ref().to_path_buf()),
+            source: Some(source.to_path_buf()),
             name: manifest_file.name,
-            hosts: manifest_file.hosts,
+            hosts,
+            fact,
+            host_vars: BTreeMap::new(),
             include,
             vars: manifest_file.vars,
+            sensitive: manifest_file.sensitive,
         };
         manifests.push(manifest);
     }
@@ -126,11 +206,11 @@ fn load_includes(base_path: &Path, includes: Vec<String>) -> anyhow::Result<Vec<
 
 /// Loads [Task]s from a single file.
 ///
-/// This is a private method meant for use by [load_manifests].
+/// This is a private method meant for use by [load_manifests], [parse_manifests], and
+/// [parse_tasks].
 fn load_tasks(source: impl AsRef<Path>, source_file: &[u8]) -> anyhow::Result<Vec<Task>> {
     let mut tasks = vec![];
-    for document in Deserializer::from_slice(source_file) {
-        let mut task = Task::deserialize(document)?;
+    for mut task in deserialize_documents::<Task>(source_file)? {
         task.source = Some(source.as_ref().to_path_buf());
 
         // Deserializing produces actions in their most direct representations from the source, but
@@ -143,6 +223,201 @@ fn load_tasks(source: impl AsRef<Path>, source_file: &[u8]) -> anyhow::Result<Ve
     Ok(tasks)
 }
 
+/// Lists the files that comprise a manifest file and everything it includes.
+///
+/// Returns `source` followed by each task file that `source` includes, in order and without
+/// duplicates. Task file paths are resolved relative to `source`, just as in [load_manifests].
+///
+/// Unlike [load_manifests], this function does not verify signatures or parse task files. It is
+/// meant for tooling that operates on the files themselves, e.g. signing a manifest tree.
+pub fn manifest_tree(source: impl AsRef<Path>) -> anyhow::Result<Vec<PathBuf>> {
+    let source_file = match fs::read(&source) {
+        Ok(read) => read,
+        Err(err) => bail!(
+            "Error loading manifest file: {}\n{err}",
+            source.as_ref().to_string_lossy(),
+        ),
+    };
+
+    let base_path = source.as_ref().parent().ok_or(anyhow!(
+        "could not compute parent directory for path: {:?}",
+        source.as_ref(),
+    ))?;
+
+    let mut tree = vec![source.as_ref().to_path_buf()];
+    for manifest_file in deserialize_documents::<ManifestFile>(&source_file)? {
+        for task_file in manifest_file.include {
+            let path = base_path.join(task_file);
+            if !tree.contains(&path) {
+                tree.push(path);
+            }
+        }
+    }
+    Ok(tree)
+}
+
+/// Rewrites the contents of a manifest file in Sira's canonical form, e.g. for `sira fmt`.
+///
+/// Parses each YAML document in `source_file` and serializes it again, so that every file uses the
+/// same indentation, lists fields in the order in which [ManifestFile] declares them, and leaves
+/// out fields that are set to their default values. Each document starts with `---`. Variables
+/// keep their order, since it affects substitution.
+///
+/// YAML comments do not survive the round trip.
+pub fn format_manifest(source_file: &[u8]) -> anyhow::Result<String> {
+    format_documents::<ManifestFile>(source_file)
+}
+
+/// Same as [format_manifest], but for task files.
+///
+/// Unlike [load_manifests], this does not split actions, so e.g. a `command` action that lists
+/// several commands keeps them together.
+pub fn format_tasks(source_file: &[u8]) -> anyhow::Result<String> {
+    format_documents::<Task>(source_file)
+}
+
+/// Implements [format_manifest] and [format_tasks].
+fn format_documents<T: DeserializeOwned + Serialize>(source_file: &[u8]) -> anyhow::Result<String> {
+    let mut formatted = String::new();
+    for value in deserialize_documents::<T>(source_file)? {
+        formatted.push_str("---\n");
+        formatted.push_str(&serde_yaml::to_string(&value)?);
+    }
+    Ok(formatted)
+}
+
+/// Deserializes each YAML document in `source_file`, the contents of a manifest or task file.
+///
+/// Besides anchors and aliases, which serde_yaml resolves on its own, this resolves merge keys,
+/// e.g. `<<: *defaults`, so that a mapping can reuse another's entries. Please see
+/// [resolve_merge_keys].
+///
+/// Documents without merge keys are deserialized directly, so that errors in them give their line
+/// and column. Errors in documents with merge keys give the document's number, since their lines
+/// and columns refer to the resolved document.
+fn deserialize_documents<T: DeserializeOwned>(source_file: &[u8]) -> anyhow::Result<Vec<T>> {
+    let values = Deserializer::from_slice(source_file);
+    let documents = Deserializer::from_slice(source_file);
+    let mut deserialized = vec![];
+    for (index, (value, document)) in values.zip(documents).enumerate() {
+        let mut value = Value::deserialize(value).map_err(explain_anchor_error)?;
+        let merged = resolve_merge_keys(&mut value)
+            .with_context(|| format!("invalid document {}", index + 1))?;
+        if !merged {
+            deserialized.push(T::deserialize(document)?);
+            continue;
+        }
+        // Values no longer tell e.g. `80` from `'80'`, so unquoted scalars would no longer pass for
+        // strings. Parse the resolved document again instead.
+        let resolved = serde_yaml::to_string(&value)?;
+        let document = T::deserialize(Deserializer::from_str(&resolved)).with_context(|| {
+            format!("invalid document {} after resolving merge keys", index + 1)
+        })?;
+        deserialized.push(document);
+    }
+    Ok(deserialized)
+}
+
+/// Adds a hint to serde_yaml's error for an alias whose anchor it can't find, since the usual
+/// cause is that the anchor is in another YAML document, i.e. before a `---` line.
+fn explain_anchor_error(error: serde_yaml::Error) -> anyhow::Error {
+    match error.to_string().starts_with("unknown anchor") {
+        true => anyhow!(
+            "{error}: an alias must follow its anchor in the same YAML document, since anchors \
+            don't carry over past `---`"
+        ),
+        false => error.into(),
+    }
+}
+
+/// Replaces each merge key (`<<`) in `value`, and in everything nested in it, with the entries
+/// of the mapping or list of mappings that it names, and returns whether there were any.
+///
+/// As YAML specifies, the mapping's own entries take precedence over merged ones, and among
+/// merged mappings, earlier ones take precedence over later ones. Merged entries take the merge
+/// key's place, which matters for variables, since they're substituted in order. Merged mappings
+/// may themselves use merge keys.
+///
+/// # Errors
+///
+/// Returns an error if a merge key names anything but a mapping or a list of mappings.
+fn resolve_merge_keys(value: &mut Value) -> anyhow::Result<bool> {
+    let mut resolved = false;
+    match value {
+        Value::Mapping(mapping) => {
+            for nested in mapping.values_mut() {
+                resolved |= resolve_merge_keys(nested)?;
+            }
+            if mapping.contains_key("<<") {
+                *mapping = merge_mapping(std::mem::take(mapping))?;
+                resolved = true;
+            }
+        }
+        Value::Sequence(sequence) => {
+            for nested in sequence {
+                resolved |= resolve_merge_keys(nested)?;
+            }
+        }
+        Value::Tagged(tagged) => resolved = resolve_merge_keys(&mut tagged.value)?,
+        _ => (),
+    }
+    Ok(resolved)
+}
+
+/// Does the work of [resolve_merge_keys] for a single mapping whose nested values have already
+/// been resolved.
+fn merge_mapping(mapping: Mapping) -> anyhow::Result<Mapping> {
+    let mut merged = Mapping::with_capacity(mapping.len());
+    for (key, value) in mapping {
+        if key != "<<" {
+            merged.insert(key, value);
+            continue;
+        }
+        let sources = match value {
+            Value::Mapping(source) => vec![source],
+            Value::Sequence(sources) => sources
+                .into_iter()
+                .map(|source| match source {
+                    Value::Mapping(source) => Ok(source),
+                    _ => {
+                        bail!("invalid merge key: expected a list of mappings, e.g. `<<: [*a, *b]`")
+                    }
+                })
+                .collect::<anyhow::Result<_>>()?,
+            _ => {
+                bail!("invalid merge key: expected a mapping or a list of mappings, e.g. `<<: *a`")
+            }
+        };
+        for (key, value) in sources.into_iter().flatten() {
+            if !merged.contains_key(&key) {
+                merged.insert(key, value);
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// Verifies the signature on a single manifest or task file.
+///
+/// Unlike the verification that takes place when loading manifests, this function always requires
+/// a valid signature: if the manifest allowed signers file is not installed or the file is
+/// unsigned, verification fails.
+pub fn verify_signature(source: impl AsRef<Path>) -> anyhow::Result<()> {
+    let source_file = match fs::read(&source) {
+        Ok(read) => read,
+        Err(err) => bail!(
+            "Error loading file: {}\n{err}",
+            source.as_ref().to_string_lossy(),
+        ),
+    };
+    crypto::verify(
+        &source_file,
+        crypto::signature_path(&source),
+        ALLOWED_SIGNERS_FILE,
+        "sira",
+    )
+}
+
 /// Represents a manifest file; typically used in the context of a [Plan].
 ///
 /// This type is typically parsed from a manifest file, but it can be constructed programmatically
@@ -166,8 +441,29 @@ pub struct Manifest {
     /// The list of hosts on which this manifest will run.
     ///
     /// Order is perserved from the source file but is typically unimportant.
+    ///
+    /// If [Manifest::fact] is set, this starts out empty, and Sira fills it in with the hosts
+    /// whose facts match before it runs the manifest.
     pub hosts: Vec<String>,
 
+    /// A fact expression that selects this manifest's hosts, e.g. `os.id == 'debian'`, from a
+    /// manifest file's `hosts: {fact: ...}`. Please see [FactExpr] for the syntax, and
+    /// [run_plan::facts::select_hosts] for which hosts are candidates.
+    ///
+    /// [run_plan::facts::select_hosts]: crate::run_plan::facts::select_hosts
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub fact: Option<String>,
+
+    /// Variables for individual hosts, by host name, e.g. from an inventory (please see
+    /// [run_plan::inventory]). They have the lowest precedence: [Manifest::vars] and [Task::vars]
+    /// override them. Please see [HostAction::compile] for details.
+    ///
+    /// Manifest files can't set these.
+    ///
+    /// [run_plan::inventory]: crate::run_plan::inventory
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub host_vars: BTreeMap<String, IndexMap<String, String>>,
+
     /// [Task]s (typically loaded from task files) that comprise this manifest.
     ///
     /// Order is preserved from the source file. Tasks are executed in order.
@@ -183,6 +479,14 @@ pub struct Manifest {
     /// Order is preserved from the source file but is typically unimportant.
     #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
     pub vars: IndexMap<String, String>,
+
+    /// The names of variables whose values are sensitive, e.g. passwords.
+    ///
+    /// Sira masks the values of these variables wherever it reports actions, e.g. in terminal
+    /// output and audit logs. A name listed here applies to the variable's final value, even if a
+    /// [Task] overrides it. Please see [HostAction::redactor] for details.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sensitive: Vec<String>,
 }
 
 impl Manifest {
@@ -195,30 +499,86 @@ impl Manifest {
 
         Some(TaskIter {
             host,
-            manifest: self,
+            manifest: Arc::new(self.clone()),
             task: None,
             task_iter: self.include.iter(),
             action_iter: None,
         })
     }
+}
+
+/// A [Manifest] and its [Task]s behind [Arc]s, so that the [TaskIntoIter]s for any number of hosts
+/// can share one copy of them.
+#[derive(Debug)]
+pub(in crate::core) struct SharedManifest {
+    /// The [Manifest], shared with every [HostAction] from it.
+    manifest: Arc<Manifest>,
+
+    /// The [Manifest]'s [Task]s, in order, each shared with every [HostAction] from it.
+    tasks: Vec<Arc<Task>>,
 
-    /// Owned version of [Self::tasks_for].
-    pub(in crate::core) fn into_tasks_for(self, host: impl Into<String>) -> Option<TaskIntoIter> {
-        let host = host.into();
+    /// The [Manifest]'s hosts, so that each host can find its manifests without searching every
+    /// manifest's list of hosts.
+    hosts: HashSet<String>,
+}
+
+impl From<Manifest> for SharedManifest {
+    fn from(manifest: Manifest) -> Self {
+        let tasks = manifest.include.iter().cloned().map(Arc::new).collect();
+        let hosts = manifest.hosts.iter().cloned().collect();
+        SharedManifest {
+            manifest: Arc::new(manifest),
+            tasks,
+            hosts,
+        }
+    }
+}
 
-        if self.hosts.iter().all(|h| &host != h) {
+impl SharedManifest {
+    /// Returns whether the [Manifest] runs on `host`.
+    pub(in crate::core) fn runs_on(&self, host: &str) -> bool {
+        self.hosts.contains(host)
+    }
+
+    /// Owned version of [Manifest::tasks_for].
+    ///
+    /// Returns a [TaskIntoIter] over tasks in `manifest`, or [None] if `host` doesn't match.
+    pub(in crate::core) fn tasks_for(manifest: &Arc<Self>, host: &str) -> Option<TaskIntoIter> {
+        if !manifest.runs_on(host) {
             return None;
         }
 
-        let task_iter = self.include.clone().into_iter();
         Some(TaskIntoIter {
-            host,
-            manifest: self,
-            task: None,
-            task_iter,
-            action_iter: None,
+            host: host.to_string(),
+            manifest: Arc::clone(manifest),
+            task: 0,
+            action: 0,
         })
     }
+
+    /// Returns the checks from [Task::verify] in each of `manifest`'s [Task]s, in order, or
+    /// nothing if `manifest` doesn't run on `host`.
+    pub(in crate::core) fn verifications_for(
+        manifest: &Arc<Self>,
+        host: &str,
+    ) -> Vec<Arc<HostAction>> {
+        if !manifest.runs_on(host) {
+            return Vec::new();
+        }
+
+        let mut verifications = Vec::new();
+        for task in &manifest.tasks {
+            for action in &task.verify {
+                verifications.push(Arc::new(HostAction::shared(
+                    host,
+                    Arc::clone(&manifest.manifest),
+                    Arc::clone(task),
+                    action.clone(),
+                )));
+            }
+        }
+        verifications
+    }
 }
 
 /// Iterates over [Task]s in a [Manifest].
@@ -232,15 +592,15 @@ pub(in crate::core) struct TaskIter<'p> {
     /// Passed through to [HostAction].
     host: &'p str,
 
-    /// The [Manifest] that included this [Task].
+    /// A copy of the [Manifest] that included this [Task].
     ///
-    /// Passed through to [HostAction].
-    manifest: &'p Manifest,
+    /// Shared with every [HostAction] that this iterator yields.
+    manifest: Arc<Manifest>,
 
-    /// The [Task] from which [Action]s are currently being read.
+    /// A copy of the [Task] from which [Action]s are currently being read.
     ///
-    /// Passed through to [HostAction].
-    task: Option<&'p Task>,
+    /// Shared with every [HostAction] that this iterator yields from the [Task].
+    task: Option<Arc<Task>>,
 
     /// The iterator that yields the [Task]s that TaskIter walks.
     task_iter: std::slice::Iter<'p, Task>,
@@ -260,11 +620,11 @@ impl<'p> Iterator for TaskIter<'p> {
         // If we have an `Action` iterator, and it has an `Action` for us, then we're done.
         if let Some(ref mut iter) = self.action_iter {
             if let Some(action) = iter.next() {
-                return Some(Arc::new(HostAction::new(
+                return Some(Arc::new(HostAction::shared(
                     self.host,
-                    self.manifest,
-                    self.task.unwrap(),
-                    action,
+                    Arc::clone(&self.manifest),
+                    Arc::clone(self.task.as_ref().unwrap()),
+                    action.clone(),
                 )));
             }
         }
@@ -272,7 +632,7 @@ impl<'p> Iterator for TaskIter<'p> {
         // If we have another `Task`, then save an iterator over its `Action`s and retry.
         if let Some(task) = self.task_iter.next() {
             self.action_iter = Some(task.actions.iter());
-            self.task = Some(task);
+            self.task = Some(Arc::new(task.clone()));
             return self.next();
         }
 
@@ -283,6 +643,9 @@ impl<'p> Iterator for TaskIter<'p> {
 }
 
 /// Owned version of [TaskIter].
+///
+/// Rather than copying the [Manifest], walks a [SharedManifest] by index, so that iterators for
+/// many hosts can share it.
 #[derive(Debug)]
 pub(in crate::core) struct TaskIntoIter {
     /// The host on which these tasks will run.
@@ -290,59 +653,34 @@ pub(in crate::core) struct TaskIntoIter {
     /// Passed through to [HostAction].
     host: String,
 
-    /// The [Manifest] that included this [Task].
-    ///
-    /// Passed through to [HostAction].
-    manifest: Manifest,
+    /// The [Manifest] whose [Task]s this iterator walks.
+    manifest: Arc<SharedManifest>,
 
-    /// The [Task] from which [Action]s are currently being read.
-    ///
-    /// Passed through to [HostAction].
-    task: Option<Task>,
-
-    /// The iterator that yields the [Task]s that TaskIter walks.
-    task_iter: std::vec::IntoIter<Task>,
+    /// The index of the [Task] from which [Action]s are currently being read.
+    task: usize,
 
-    /// The iterator that yields [Action]s for the current [Task]. We use these values in
-    /// combination with values saved in the [TaskIntoIter] to build [HostAction]s.
-    ///
-    /// If there are no tasks in the manifest, then there can be no action iterator. Thus,
-    /// this must be an optional type.
-    ///
-    /// Unlike [TaskIter::action_iter], this field owns its [Action]s and moves them out as it
-    /// yields them. It does not reference [Self::task], though that field holds copies of the same
-    /// [Action]s.
-    action_iter: Option<std::vec::IntoIter<Action>>,
+    /// The index of the next [Action] to read from the current [Task].
+    action: usize,
 }
 
 impl Iterator for TaskIntoIter {
     type Item = Arc<HostAction>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Modeled after [TaskIter].
-
-        if let Some(ref mut iter) = self.action_iter {
-            if let Some(action) = iter.next() {
-                // We own all of the values we need to return, but we need them more than once, and
-                // the return value needs to own its memory. Therefore, we use the same initializer
-                // as [TaskIter], which takes references and clones the values.
-                return Some(Arc::new(HostAction::new(
+        // Skip past any tasks that have no actions left, including empty tasks.
+        while let Some(task) = self.manifest.tasks.get(self.task) {
+            if let Some(action) = task.actions.get(self.action) {
+                self.action += 1;
+                return Some(Arc::new(HostAction::shared(
                     &self.host,
-                    &self.manifest,
-                    self.task.as_ref().unwrap(),
-                    &action,
+                    Arc::clone(&self.manifest.manifest),
+                    Arc::clone(task),
+                    action.clone(),
                 )));
             }
+            self.task += 1;
+            self.action = 0;
         }
-
-        if let Some(task) = self.task_iter.next() {
-            // We need to clone so we don't partially move out of `task`. We do, unfortunately,
-            // need multiple copies of that memory.
-            self.action_iter = Some(task.actions.clone().into_iter());
-            self.task = Some(task);
-            return self.next();
-        }
-
         None
     }
 }
@@ -364,8 +702,9 @@ pub struct ManifestFile {
     /// Same as [Manifest::name].
     pub name: String,
 
-    /// Same as [Manifest::hosts].
-    pub hosts: Vec<String>,
+    /// Either a list of hosts, as in [Manifest::hosts], or a fact expression that selects them,
+    /// as in [Manifest::fact].
+    pub hosts: Hosts,
 
     /// A list of files from which to load [Task]s. Once you have loaded them, you can construct a
     /// full and complete [Manifest].
@@ -389,6 +728,50 @@ pub struct ManifestFile {
     /// Same as [Manifest::vars].
     #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
     pub vars: IndexMap<String, String>,
+
+    /// Same as [Manifest::sensitive].
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sensitive: Vec<String>,
+}
+
+/// The hosts that a [ManifestFile] names: either a list, e.g. `hosts: [web1, web2]`, or a fact
+/// expression, e.g. `hosts: {fact: "os.id == 'debian'"}`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(
+    untagged,
+    expecting = "invalid hosts: expected a sequence of host names or a map with a fact expression"
+)]
+pub enum Hosts {
+    /// Same as [Manifest::hosts].
+    List(Vec<String>),
+
+    /// Same as [Manifest::fact].
+    Fact(FactSelector),
+}
+
+impl Hosts {
+    /// Splits these hosts into a [Manifest::hosts] list and a [Manifest::fact] expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fact expression is invalid. Please see [FactExpr::parse].
+    pub fn split(self) -> anyhow::Result<(Vec<String>, Option<String>)> {
+        match self {
+            Hosts::List(hosts) => Ok((hosts, None)),
+            Hosts::Fact(FactSelector { fact }) => {
+                let _ = FactExpr::parse(&fact)?;
+                Ok((vec![], Some(fact)))
+            }
+        }
+    }
+}
+
+/// The `{fact: ...}` form of [Hosts].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FactSelector {
+    /// The fact expression. Please see [FactExpr].
+    pub fact: String,
 }
 
 #[cfg(test)]
@@ -396,6 +779,11 @@ mod tests {
     use super::super::fixtures::plan;
     use super::*;
 
+    // Owned version of Manifest::tasks_for, for comparison with it.
+    fn into_tasks_for(manifest: Manifest, host: &str) -> Option<TaskIntoIter> {
+        SharedManifest::tasks_for(&Arc::new(manifest.into()), host)
+    }
+
     // load_manifests surfaces any errors it encounters, and much of the complex work it does is
     // through code that's already under test elsewhere, so we only have to test the happy path and
     // signature verification.
@@ -416,6 +804,8 @@ mod tests {
                     ),
                     name: "desktops".to_owned(),
                     hosts: vec!["t470".to_owned(), "zen3".to_owned()],
+                    fact: None,
+                    host_vars: Default::default(),
                     include: vec![
                         Task {
                             source: Some(
@@ -432,6 +822,15 @@ mod tests {
                                 "aptitude build-essential exa".to_owned(),
                             )]
                             .into(),
+                            sensitive: Vec::new(),
+                            confirm: false,
+                            user: None,
+                            escalate: false,
+                            dpkg_lock_timeout: None,
+                            limits: Default::default(),
+                            sandbox: None,
+                            independent: false,
+                            verify: Vec::new(),
                         },
                         Task {
                             source: Some(
@@ -441,6 +840,15 @@ mod tests {
                             name: "snap install".to_owned(),
                             actions: vec![Action::Command(vec!["snap install $snaps".to_owned()])],
                             vars: [("snaps".to_owned(), "discord".to_owned())].into(),
+                            sensitive: Vec::new(),
+                            confirm: false,
+                            user: None,
+                            escalate: false,
+                            dpkg_lock_timeout: None,
+                            limits: Default::default(),
+                            sandbox: None,
+                            independent: false,
+                            verify: Vec::new(),
                         },
                     ],
                     vars: [
@@ -448,6 +856,7 @@ mod tests {
                         ("beta".to_owned(), "b".to_owned()),
                     ]
                     .into(),
+                    sensitive: Vec::new(),
                 },
                 Manifest {
                     source: Some(
@@ -456,6 +865,8 @@ mod tests {
                     ),
                     name: "t470".to_owned(),
                     hosts: vec!["t470".to_owned()],
+                    fact: None,
+                    host_vars: Default::default(),
                     include: vec![Task {
                         source: Some(
                             Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -466,8 +877,18 @@ mod tests {
                             vec!["hostnamectl hostname t470".to_owned()],
                         )],
                         vars: IndexMap::new(),
+                        sensitive: Vec::new(),
+                        confirm: false,
+                        user: None,
+                        escalate: false,
+                        dpkg_lock_timeout: None,
+                        limits: Default::default(),
+                        sandbox: None,
+                        independent: false,
+                        verify: Vec::new(),
                     }],
                     vars: IndexMap::new(),
+                    sensitive: Vec::new(),
                 },
                 Manifest {
                     source: Some(
@@ -476,6 +897,8 @@ mod tests {
                     ),
                     name: "zen3".to_owned(),
                     hosts: vec!["zen3".to_owned()],
+                    fact: None,
+                    host_vars: Default::default(),
                     include: vec![Task {
                         source: Some(
                             Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -486,8 +909,18 @@ mod tests {
                             vec!["hostnamectl hostname zen3".to_owned()],
                         )],
                         vars: IndexMap::new(),
+                        sensitive: Vec::new(),
+                        confirm: false,
+                        user: None,
+                        escalate: false,
+                        dpkg_lock_timeout: None,
+                        limits: Default::default(),
+                        sandbox: None,
+                        independent: false,
+                        verify: Vec::new(),
                     }],
                     vars: IndexMap::new(),
+                    sensitive: Vec::new(),
                 },
             ];
 
@@ -572,6 +1005,423 @@ mod tests {
         }
     }
 
+    mod parse_manifests {
+        use super::*;
+
+        /// Returns the contents of the task files that `manifest1.yaml` includes, by name.
+        fn task_files(dir: &Path) -> BTreeMap<String, String> {
+            ["task1.yaml", "task2.yaml", "t470.yaml", "zen3.yaml"]
+                .into_iter()
+                .map(|name| {
+                    let contents = fs::read_to_string(dir.join(name)).unwrap();
+                    (name.to_string(), contents)
+                })
+                .collect()
+        }
+
+        #[test]
+        fn matches_load_manifests() {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/load_manifests");
+            let manifest_file = fs::read_to_string(dir.join("manifest1.yaml")).unwrap();
+            let parsed = parse_manifests("generated", &manifest_file, &task_files(&dir)).unwrap();
+
+            // Only the sources differ: labels and task file names instead of paths.
+            let mut expected = load_manifests(dir.join("manifest1.yaml")).unwrap();
+            for manifest in &mut expected {
+                manifest.source = Some(PathBuf::from("generated"));
+                for task in &mut manifest.include {
+                    let name = task.source.as_ref().unwrap().file_name().unwrap();
+                    task.source = Some(PathBuf::from(name));
+                }
+            }
+            assert_eq!(expected, parsed);
+        }
+
+        #[test]
+        fn missing_task_file() {
+            let err = parse_manifests(
+                "generated",
+                "name: a\nhosts: [b]\ninclude: [c.yaml]\n",
+                &BTreeMap::new(),
+            )
+            .unwrap_err()
+            .to_string();
+            assert!(err.contains("Error loading task file: c.yaml"), "{err}");
+        }
+
+        #[test]
+        fn parse_tasks_splits_actions() {
+            let tasks = parse_tasks("inline", "name: a\nactions:\n  - command: [b, c]\n").unwrap();
+            assert_eq!(Some(PathBuf::from("inline")), tasks[0].source);
+            assert_eq!(
+                vec![
+                    Action::Command(vec!["b".to_string()]),
+                    Action::Command(vec!["c".to_string()]),
+                ],
+                tasks[0].actions,
+            );
+        }
+    }
+
+    mod manifest_tree {
+        use super::*;
+
+        #[test]
+        fn works() {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/load_manifests");
+            let tree = manifest_tree(dir.join("manifest1.yaml")).unwrap();
+            let expected: Vec<_> = ["manifest1.yaml", "task1.yaml", "task2.yaml", "t470.yaml"]
+                .into_iter()
+                .chain(["zen3.yaml"])
+                .map(|f| dir.join(f))
+                .collect();
+            assert_eq!(expected, tree);
+        }
+
+        #[test]
+        fn deduplicates_task_files() {
+            let dir = tempfile::tempdir().unwrap();
+            let manifest = dir.path().join("manifest.yaml");
+            fs::write(
+                &manifest,
+                "---\n\
+                name: a\n\
+                hosts: []\n\
+                include:\n  - task.yaml\n\
+                ---\n\
+                name: b\n\
+                hosts: []\n\
+                include:\n  - task.yaml\n",
+            )
+            .unwrap();
+
+            let tree = manifest_tree(&manifest).unwrap();
+            assert_eq!(vec![manifest, dir.path().join("task.yaml")], tree);
+        }
+
+        #[test]
+        fn missing_manifest_file() {
+            let err = manifest_tree("/doesnotexist").unwrap_err().to_string();
+            assert!(err.contains("Error loading manifest file: /doesnotexist"));
+        }
+    }
+
+    mod deserialize_documents {
+        use super::*;
+
+        /// Returns the `from`, `to`, `user`, and `permissions` of an upload.
+        fn upload(action: &Action) -> (&str, &str, &str, Option<&str>) {
+            let Action::Upload {
+                from,
+                to,
+                user,
+                permissions,
+                ..
+            } = action
+            else {
+                panic!("not an upload: {action:?}");
+            };
+            (from, to, user, permissions.as_deref())
+        }
+
+        #[test]
+        fn resolves_aliases() {
+            let tasks = parse_tasks(
+                "inline",
+                "name: a\nactions: &actions\n  - command: [b]\nverify: *actions\n",
+            )
+            .unwrap();
+            assert_eq!(tasks[0].actions, tasks[0].verify);
+        }
+
+        #[test]
+        fn resolves_merge_keys() {
+            let tasks = parse_tasks(
+                "inline",
+                "\
+name: Configs
+actions:
+  - upload: &config
+      from: a
+      to: /etc/a
+      user: www-data
+      permissions: '600'
+  - upload:
+      <<: *config
+      from: b
+      to: /etc/b
+",
+            )
+            .unwrap();
+            assert_eq!(
+                ("a", "/etc/a", "www-data", Some("600")),
+                upload(&tasks[0].actions[0]),
+            );
+            assert_eq!(
+                ("b", "/etc/b", "www-data", Some("600")),
+                upload(&tasks[0].actions[1]),
+            );
+        }
+
+        #[test]
+        fn gives_precedence_to_own_then_earlier_entries() {
+            let tasks = parse_tasks(
+                "inline",
+                "\
+name: Vars
+vars:
+  a: 1
+  <<: [{a: 2, b: 2, c: 2}, {b: 3, d: 3}]
+  c: 4
+actions: []
+",
+            )
+            .unwrap();
+            let vars: Vec<_> = tasks[0]
+                .vars
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            assert_eq!(vec![("a", "1"), ("b", "2"), ("c", "4"), ("d", "3")], vars);
+        }
+
+        #[test]
+        fn resolves_chained_merge_keys() {
+            let tasks = parse_tasks(
+                "inline",
+                "\
+name: Configs
+actions:
+  - upload: &base
+      from: a
+      to: /etc/a
+      user: www-data
+  - upload: &private
+      <<: *base
+      permissions: '600'
+  - upload:
+      <<: *private
+      from: b
+",
+            )
+            .unwrap();
+            assert_eq!(
+                ("b", "/etc/a", "www-data", Some("600")),
+                upload(&tasks[0].actions[2]),
+            );
+        }
+
+        #[test]
+        fn resolves_merge_keys_in_manifests() {
+            let manifest_file = "name: a\nhosts: [b]\ninclude: []\nvars:\n  <<: {c: d}\n";
+            let manifests = parse_manifests("generated", manifest_file, &BTreeMap::new()).unwrap();
+            assert_eq!("d", manifests[0].vars["c"]);
+            assert_eq!(
+                "---\nname: a\nhosts:\n- b\ninclude: []\nvars:\n  c: d\n",
+                format_manifest(manifest_file.as_bytes()).unwrap(),
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_merge_keys() {
+            for merge in ["x", "[x]", "[[{a: b}]]"] {
+                let task_file = format!("name: a\nvars:\n  <<: {merge}\nactions: []\n");
+                let err = format!("{:#}", parse_tasks("inline", &task_file).unwrap_err());
+                assert!(err.contains("invalid document 1"), "{err}");
+                assert!(err.contains("invalid merge key"), "{err}");
+            }
+        }
+
+        #[test]
+        fn numbers_documents_with_merge_keys_in_errors() {
+            let err = parse_tasks(
+                "inline",
+                "name: a\nactions: []\n---\nname: b\nactions: []\nvars:\n  <<: {c: [d]}\n",
+            )
+            .unwrap_err();
+            let err = format!("{err:#}");
+            assert!(
+                err.contains("invalid document 2 after resolving merge keys"),
+                "{err}"
+            );
+        }
+
+        #[test]
+        fn keeps_locations_without_merge_keys() {
+            let err = parse_tasks("inline", "name: a\nactions: []\nbogus: 1\n").unwrap_err();
+            assert!(err.to_string().contains("line 3"), "{err}");
+        }
+
+        #[test]
+        fn explains_aliases_across_documents() {
+            let err = parse_tasks(
+                "inline",
+                "name: a\nactions: &actions []\n---\nname: b\nactions: *actions\n",
+            )
+            .unwrap_err()
+            .to_string();
+            assert!(err.contains("unknown anchor"), "{err}");
+            assert!(err.contains("same YAML document"), "{err}");
+        }
+    }
+
+    mod format {
+        use super::*;
+
+        #[test]
+        fn normalizes_manifest() {
+            let source = b"\
+vars:
+    b: '1'
+    a: \"2\"
+include: [task.yaml]
+sensitive: []
+hosts:
+        - web1
+name: Web servers
+";
+            assert_eq!(
+                "---\n\
+                name: Web servers\n\
+                hosts:\n\
+                - web1\n\
+                include:\n\
+                - task.yaml\n\
+                vars:\n  \
+                  b: '1'\n  \
+                  a: '2'\n",
+                format_manifest(source).unwrap(),
+            );
+        }
+
+        #[test]
+        fn keeps_actions_together() {
+            let source = b"\
+name: Greet
+actions:
+  - command: [echo hi, echo bye]
+";
+            assert_eq!(
+                "---\n\
+                name: Greet\n\
+                actions:\n\
+                - command:\n  \
+                  - echo hi\n  \
+                  - echo bye\n",
+                format_tasks(source).unwrap(),
+            );
+        }
+
+        #[test]
+        fn formats_each_document() {
+            let formatted = format_tasks(b"name: a\nactions: []\n---\nname: b\nactions: []\n");
+            assert_eq!(
+                "---\nname: a\nactions: []\n---\nname: b\nactions: []\n",
+                formatted.unwrap(),
+            );
+        }
+
+        #[test]
+        fn is_idempotent() {
+            type Format = fn(&[u8]) -> anyhow::Result<String>;
+            let formatters: [(&str, Format); 2] = [
+                ("sample.manifest", format_manifest),
+                ("sample.task", format_tasks),
+            ];
+            for (file, format) in formatters {
+                let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join(file);
+                let formatted = format(&fs::read(path).unwrap()).unwrap();
+                assert_eq!(formatted, format(formatted.as_bytes()).unwrap());
+            }
+        }
+
+        #[test]
+        fn accepts_init_example() {
+            // `sira init` copies these files into new projects, so they had better parse.
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/init");
+            format_manifest(&fs::read(dir.join("manifests/example.yaml")).unwrap()).unwrap();
+            format_tasks(&fs::read(dir.join("tasks/example.yaml")).unwrap()).unwrap();
+        }
+
+        #[test]
+        fn keeps_fact_expression() {
+            let source = b"name: Debian\nhosts: {fact: \"os.id == 'debian'\"}\ninclude: []\n";
+            assert_eq!(
+                "---\n\
+                name: Debian\n\
+                hosts:\n  \
+                  fact: os.id == 'debian'\n\
+                include: []\n",
+                format_manifest(source).unwrap(),
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_files() {
+            assert!(format_manifest(b"name: a\n").is_err());
+            assert!(format_manifest(b"name: a\nhosts: {fact: x, y: z}\ninclude: []\n").is_err());
+            assert!(format_tasks(b"name: a\nactions: []\nbogus: 1\n").is_err());
+        }
+    }
+
+    mod hosts {
+        use super::*;
+
+        #[test]
+        fn splits_list() {
+            let hosts = Hosts::List(vec!["web1".to_string()]);
+            assert_eq!((vec!["web1".to_string()], None), hosts.split().unwrap());
+        }
+
+        #[test]
+        fn splits_fact_expression() {
+            let hosts = Hosts::Fact(FactSelector {
+                fact: "os.id == 'debian'".to_string(),
+            });
+            assert_eq!(
+                (vec![], Some("os.id == 'debian'".to_string())),
+                hosts.split().unwrap(),
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_fact_expression() {
+            let hosts = Hosts::Fact(FactSelector {
+                fact: "os_family == 'debian'".to_string(),
+            });
+            assert!(hosts.split().is_err());
+        }
+    }
+
+    mod verify_signature {
+        use super::*;
+
+        #[test]
+        fn works() {
+            let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("resources/test/load_manifests/manifest1.yaml");
+            verify_signature(source).unwrap();
+        }
+
+        #[test]
+        #[should_panic(expected = "missing signature file")]
+        fn missing_signature() {
+            let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("resources/test/load_manifests/unsigned.manifest");
+            verify_signature(source).unwrap();
+        }
+
+        #[test]
+        #[should_panic(expected = "incorrect signature")]
+        fn bad_signature() {
+            let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("resources/test/load_manifests/bad-signature.manifest");
+            verify_signature(source).unwrap();
+        }
+    }
+
     mod manifest {
         use super::*;
 
@@ -592,7 +1442,7 @@ mod tests {
 
                 assert_eq!(&manifest.hosts[0], &task_iter.host);
 
-                assert_eq!(&manifest, task_iter.manifest);
+                assert_eq!(manifest, *task_iter.manifest);
 
                 assert!(task_iter.task.is_none());
 
@@ -622,7 +1472,7 @@ mod tests {
 
                 assert_eq!(&"gardenia", &task_iter.host);
 
-                assert_eq!(&manifest, task_iter.manifest);
+                assert_eq!(manifest, *task_iter.manifest);
 
                 assert!(task_iter.task.is_none());
 
@@ -644,7 +1494,7 @@ mod tests {
         }
 
         // These tests are parallel to those for tasks_for.
-        mod into_tasks_for {
+        mod shared_tasks_for {
             use super::*;
 
             #[test]
@@ -652,7 +1502,7 @@ mod tests {
                 let (_, manifest, _, _) = plan();
 
                 let maybe_task_into_iter: Option<TaskIntoIter> =
-                    manifest.clone().into_tasks_for(&manifest.hosts[0]);
+                    into_tasks_for(manifest.clone(), &manifest.hosts[0]);
                 assert!(
                     maybe_task_into_iter.is_some(),
                     "Manifest::tasks_for should return a Some value when given a valid host"
@@ -661,20 +1511,21 @@ mod tests {
 
                 assert_eq!(&manifest.hosts[0], &task_into_iter.host);
 
-                assert_eq!(&manifest, &task_into_iter.manifest);
+                assert_eq!(manifest, *task_into_iter.manifest.manifest);
 
-                assert!(task_into_iter.task.is_none());
+                assert_eq!(0, task_into_iter.task);
 
                 assert_eq!(
                     manifest.include.iter().collect::<Vec<&Task>>(),
                     task_into_iter
                         .manifest
-                        .include
+                        .tasks
                         .iter()
+                        .map(|task| task.as_ref())
                         .collect::<Vec<&Task>>(),
                 );
 
-                assert!(task_into_iter.action_iter.is_none());
+                assert_eq!(0, task_into_iter.action);
             }
 
             #[test]
@@ -687,7 +1538,7 @@ mod tests {
                 manifest.hosts.push("chrysanthemum".into());
 
                 let maybe_task_into_iter: Option<TaskIntoIter> =
-                    manifest.clone().into_tasks_for("gardenia");
+                    into_tasks_for(manifest.clone(), "gardenia");
                 assert!(
                     maybe_task_into_iter.is_some(),
                     "Manifest::tasks_for should return a Some value when given a valid host"
@@ -696,20 +1547,21 @@ mod tests {
 
                 assert_eq!(&"gardenia", &task_into_iter.host);
 
-                assert_eq!(&manifest, &task_into_iter.manifest);
+                assert_eq!(manifest, *task_into_iter.manifest.manifest);
 
-                assert!(task_into_iter.task.is_none());
+                assert_eq!(0, task_into_iter.task);
 
                 assert_eq!(
                     manifest.include.iter().collect::<Vec<&Task>>(),
                     task_into_iter
                         .manifest
-                        .include
+                        .tasks
                         .iter()
+                        .map(|task| task.as_ref())
                         .collect::<Vec<&Task>>(),
                 );
 
-                assert!(task_into_iter.action_iter.is_none());
+                assert_eq!(0, task_into_iter.action);
             }
 
             #[test]
@@ -717,7 +1569,7 @@ mod tests {
                 let (_, manifest, _, _) = plan();
 
                 let maybe_task_into_iter: Option<TaskIntoIter> =
-                    manifest.into_tasks_for("gardenia");
+                    into_tasks_for(manifest, "gardenia");
 
                 assert!(maybe_task_into_iter.is_none());
             }
@@ -755,6 +1607,14 @@ mod tests {
                     group: "group".into(),
                     permissions: Some("777".into()),
                     overwrite: true,
+                    encrypt: false,
+                    seuser: None,
+                    serole: None,
+                    setype: None,
+                    acl: vec![],
+                    transfer_permissions: None,
+                    xattrs: Default::default(),
+                    capabilities: None,
                 },
             ];
 
@@ -770,6 +1630,15 @@ mod tests {
                     name: "Task 1".into(),
                     actions: task_1_actions.clone(),
                     vars: IndexMap::new(),
+                    sensitive: Vec::new(),
+                    confirm: false,
+                    user: None,
+                    escalate: false,
+                    dpkg_lock_timeout: None,
+                    limits: Default::default(),
+                    sandbox: None,
+                    independent: false,
+                    verify: Vec::new(),
                 },
                 // A corner case: a task that's empty.
                 Task {
@@ -777,6 +1646,15 @@ mod tests {
                     name: "Task 2".into(),
                     actions: vec![],
                     vars: IndexMap::new(),
+                    sensitive: Vec::new(),
+                    confirm: false,
+                    user: None,
+                    escalate: false,
+                    dpkg_lock_timeout: None,
+                    limits: Default::default(),
+                    sandbox: None,
+                    independent: false,
+                    verify: Vec::new(),
                 },
                 // Another routine task afterward.
                 Task {
@@ -784,6 +1662,15 @@ mod tests {
                     name: "Task 3".into(),
                     actions: task_3_actions.clone(),
                     vars: IndexMap::new(),
+                    sensitive: Vec::new(),
+                    confirm: false,
+                    user: None,
+                    escalate: false,
+                    dpkg_lock_timeout: None,
+                    limits: Default::default(),
+                    sandbox: None,
+                    independent: false,
+                    verify: Vec::new(),
                 },
             ];
 
@@ -791,8 +1678,11 @@ mod tests {
                 source: None,
                 name: "API test".into(),
                 hosts: vec!["api_test".into()],
+                fact: None,
+                host_vars: Default::default(),
                 include: tasks,
                 vars: IndexMap::new(),
+                sensitive: Vec::new(),
             };
 
             let task_1_host_actions = task_1_actions.into_iter().map(|action| {
@@ -826,8 +1716,7 @@ mod tests {
 
             assert_eq!(
                 expected_host_actions,
-                manifest
-                    .into_tasks_for("api_test")
+                into_tasks_for(manifest, "api_test")
                     .unwrap()
                     .collect::<Vec<Arc<HostAction>>>(),
             );
@@ -839,14 +1728,17 @@ mod tests {
                 source: None,
                 name: "API test".into(),
                 hosts: vec!["api_test".into()],
+                fact: None,
+                host_vars: Default::default(),
                 include: vec![],
                 vars: IndexMap::new(),
+                sensitive: Vec::new(),
             };
 
             let mut task_iter = manifest.tasks_for("api_test").unwrap();
             assert!(task_iter.next().is_none());
 
-            let mut task_into_iter = manifest.into_tasks_for("api_test").unwrap();
+            let mut task_into_iter = into_tasks_for(manifest, "api_test").unwrap();
             assert!(task_into_iter.next().is_none());
         }
 
@@ -857,20 +1749,32 @@ mod tests {
                 name: "API test".into(),
                 actions: vec![],
                 vars: IndexMap::new(),
+                sensitive: Vec::new(),
+                confirm: false,
+                user: None,
+                escalate: false,
+                dpkg_lock_timeout: None,
+                limits: Default::default(),
+                sandbox: None,
+                independent: false,
+                verify: Vec::new(),
             };
 
             let manifest = Manifest {
                 source: None,
                 name: "API test".into(),
                 hosts: vec!["api_test".into()],
+                fact: None,
+                host_vars: Default::default(),
                 include: vec![task],
                 vars: IndexMap::new(),
+                sensitive: Vec::new(),
             };
 
             let mut task_iter = manifest.tasks_for("api_test").unwrap();
             assert!(task_iter.next().is_none());
 
-            let mut task_into_iter = manifest.into_tasks_for("api_test").unwrap();
+            let mut task_into_iter = into_tasks_for(manifest, "api_test").unwrap();
             assert!(task_into_iter.next().is_none());
         }
     }
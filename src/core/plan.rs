@@ -1,12 +1,11 @@
 //! Types for representing an ordered list of manifests to run.
 
-#[cfg(doc)]
-use crate::core::action::Action;
-use crate::core::action::HostAction;
-use crate::core::manifest::{self, Manifest, TaskIntoIter, TaskIter};
+use crate::core::action::{Action, HostAction};
+use crate::core::manifest::{self, Manifest, SharedManifest, TaskIntoIter, TaskIter};
 #[cfg(doc)]
 use crate::core::task::Task;
-use std::collections::BTreeSet;
+use anyhow::anyhow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -40,6 +39,62 @@ impl Plan {
         Ok(Plan { manifests })
     }
 
+    /// Loads a [Plan] from the contents of a manifest file in memory, e.g. one that a program
+    /// templated or fetched at run time, and the task files that it includes, by name.
+    ///
+    /// `source` labels the manifests; it need not be a real path. Signatures are not checked.
+    /// Please see [manifest::parse_manifests] for details.
+    pub fn from_manifest_str(
+        source: impl AsRef<Path>,
+        manifest_file: &str,
+        task_files: &BTreeMap<String, String>,
+    ) -> anyhow::Result<Self> {
+        let manifests = manifest::parse_manifests(source, manifest_file, task_files)?;
+        Ok(Plan { manifests })
+    }
+
+    /// Checks for problems that loading the plan can't detect, e.g. for `sira --syntax-check`.
+    ///
+    /// Currently, this checks that the source file of every [Action::Upload] exists on the control
+    /// node, after substituting variables. Returns one error per problem, in the order in which the
+    /// actions appear, or an empty list if there are none.
+    pub fn check(&self) -> Vec<anyhow::Error> {
+        let mut problems = vec![];
+        let mut seen = BTreeSet::new();
+        for manifest in &self.manifests {
+            // Variables don't vary by host, so any host will do. A manifest without hosts never
+            // runs, so there is nothing to check.
+            let Some(host) = manifest.hosts.first() else {
+                continue;
+            };
+            for task in &manifest.include {
+                for action in task.actions.iter().chain(&task.verify) {
+                    let host_action = HostAction::new(host, manifest, task, action);
+                    let Action::Upload { from, .. } = host_action.compile() else {
+                        continue;
+                    };
+                    if Path::new(&from).is_file() {
+                        continue;
+                    }
+
+                    let from = host_action.redactor().redact_str(&from);
+                    let task_file = match &task.source {
+                        Some(path) => format!("{}: ", path.display()),
+                        None => String::new(),
+                    };
+                    let problem = format!(
+                        "{task_file}task {:?}: upload source is not a file: {from}",
+                        task.name,
+                    );
+                    if seen.insert(problem.clone()) {
+                        problems.push(anyhow!(problem));
+                    }
+                }
+            }
+        }
+        problems
+    }
+
     /// Returns a list of hosts involved in this `Plan` in alphabetical order.
     pub fn hosts(&self) -> Vec<String> {
         let mut set = BTreeSet::new();
@@ -53,11 +108,22 @@ impl Plan {
         set.into_iter().collect()
     }
 
+    /// Removes every host for which `keep` returns false from every manifest, e.g. to run the plan
+    /// again on only the hosts that failed.
+    ///
+    /// Manifests that select hosts by fact still select them when the plan runs, from the hosts
+    /// that remain and any others in [RunOptions::hosts](crate::run_plan::RunOptions::hosts).
+    pub fn retain_hosts(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        for manifest in &mut self.manifests {
+            manifest.hosts.retain(|host| keep(host));
+        }
+    }
+
     /// Returns an execution plan for the specified host.
     ///
     /// Returns [None] if `host` was not in the plan's list of hosts.
     #[allow(unused_variables)]
-    pub fn plan_for(&self, host: &str) -> Option<HostPlan> {
+    pub fn plan_for(&self, host: &str) -> Option<HostPlan<'_>> {
         for manifest in &self.manifests {
             // We're intentionally picking the first matching host reference from the plan itself
             // so we can return an internal reference instead of the host value we were passed.
@@ -156,14 +222,58 @@ impl<'p> IntoIterator for &HostPlan<'p> {
     }
 }
 
+/// A [Plan] whose [Manifest]s and [Task]s are behind [Arc]s, so that the [HostPlanIntoIter]s for
+/// any number of hosts can share one copy of them.
+///
+/// [HostPlan::into_iter] copies the [Plan]'s [Manifest]s for each host. To run a large [Plan] on
+/// many hosts, convert it into a [SharedPlan] once, and then call [SharedPlan::plan_for] for each
+/// host instead. Cloning a [SharedPlan] is cheap.
+#[derive(Clone, Debug)]
+pub struct SharedPlan {
+    /// The [Plan]'s manifests, in order.
+    manifests: Arc<[Arc<SharedManifest>]>,
+}
+
+impl From<Plan> for SharedPlan {
+    fn from(plan: Plan) -> Self {
+        SharedPlan {
+            manifests: plan
+                .manifests
+                .into_iter()
+                .map(|manifest| Arc::new(manifest.into()))
+                .collect(),
+        }
+    }
+}
+
+impl SharedPlan {
+    /// Returns an iterator over [Action]s on `host`, which shares this [SharedPlan]'s data.
+    ///
+    /// Returns [None] if `host` was not in the plan's list of hosts, as [Plan::plan_for] does.
+    pub fn plan_for(&self, host: &str) -> Option<HostPlanIntoIter> {
+        if !self.manifests.iter().any(|manifest| manifest.runs_on(host)) {
+            return None;
+        }
+        Some(HostPlanIntoIter {
+            host: host.to_string(),
+            manifests: Arc::clone(&self.manifests),
+            next_manifest: 0,
+            current_iter: None,
+        })
+    }
+}
+
 /// Owned version of [HostPlanIter].
 #[derive(Debug)]
 pub struct HostPlanIntoIter {
     /// The host on which the plan is intended to run.
     host: String,
 
-    /// An iterator over the manifests in this plan.
-    manifests: std::vec::IntoIter<Manifest>,
+    /// The manifests in this plan, which may be shared with other hosts' iterators.
+    manifests: Arc<[Arc<SharedManifest>]>,
+
+    /// The index in [Self::manifests] of the next manifest to try.
+    next_manifest: usize,
 
     /// The current task iterator, which yields [HostAction] values.
     ///
@@ -172,6 +282,20 @@ pub struct HostPlanIntoIter {
     current_iter: Option<TaskIntoIter>,
 }
 
+impl HostPlanIntoIter {
+    /// Returns the checks from [Task::verify] in every [Task] that runs on this host, in the order
+    /// in which the [Task]s run, to run once every [Action] from this iterator has run.
+    ///
+    /// A [Task] that runs more than once on the host, e.g. because two manifests include it,
+    /// contributes its checks each time, since each [Manifest] may give them different variables.
+    pub fn verifications(&self) -> Vec<Arc<HostAction>> {
+        self.manifests
+            .iter()
+            .flat_map(|manifest| SharedManifest::verifications_for(manifest, &self.host))
+            .collect()
+    }
+}
+
 impl Iterator for HostPlanIntoIter {
     type Item = Arc<HostAction>;
 
@@ -184,8 +308,9 @@ impl Iterator for HostPlanIntoIter {
             }
         }
 
-        if let Some(next_manifest) = self.manifests.next() {
-            self.current_iter = next_manifest.into_tasks_for(&self.host);
+        if let Some(next_manifest) = self.manifests.get(self.next_manifest) {
+            self.next_manifest += 1;
+            self.current_iter = SharedManifest::tasks_for(next_manifest, &self.host);
             return self.next();
         }
 
@@ -198,10 +323,18 @@ impl<'p> IntoIterator for HostPlan<'p> {
     type IntoIter = HostPlanIntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        // Modeled after [HostPlan::iter].
+        // Only copy the manifests that run on this host; the rest would be skipped anyway.
+        let manifests: Vec<Manifest> = self
+            .plan
+            .manifests
+            .iter()
+            .filter(|manifest| manifest.hosts.iter().any(|h| h == self.host))
+            .cloned()
+            .collect();
         HostPlanIntoIter {
             host: self.host.to_string(),
-            manifests: self.plan.manifests.clone().into_iter(),
+            manifests: SharedPlan::from(Plan { manifests }).manifests,
+            next_manifest: 0,
             current_iter: None,
         }
     }
@@ -241,6 +374,8 @@ mod tests {
                             ),
                             name: "desktops".to_owned(),
                             hosts: vec!["t470".to_owned(), "zen3".to_owned()],
+                            fact: None,
+                            host_vars: Default::default(),
                             include: vec![
                                 Task {
                                     source: Some(
@@ -259,6 +394,15 @@ mod tests {
                                         "aptitude build-essential exa".to_owned(),
                                     )]
                                     .into(),
+                                    sensitive: Vec::new(),
+                                    confirm: false,
+                                    user: None,
+                                    escalate: false,
+                                    dpkg_lock_timeout: None,
+                                    limits: Default::default(),
+                                    sandbox: None,
+                                    independent: false,
+                                    verify: Vec::new(),
                                 },
                                 Task {
                                     source: Some(
@@ -270,6 +414,15 @@ mod tests {
                                         "snap install $snaps".to_owned()
                                     ])],
                                     vars: [("snaps".to_owned(), "discord".to_owned())].into(),
+                                    sensitive: Vec::new(),
+                                    confirm: false,
+                                    user: None,
+                                    escalate: false,
+                                    dpkg_lock_timeout: None,
+                                    limits: Default::default(),
+                                    sandbox: None,
+                                    independent: false,
+                                    verify: Vec::new(),
                                 },
                             ],
                             vars: [
@@ -277,6 +430,7 @@ mod tests {
                                 ("beta".to_owned(), "b".to_owned()),
                             ]
                             .into(),
+                            sensitive: Vec::new(),
                         },
                         Manifest {
                             source: Some(
@@ -285,6 +439,8 @@ mod tests {
                             ),
                             name: "t470".to_owned(),
                             hosts: vec!["t470".to_owned()],
+                            fact: None,
+                            host_vars: Default::default(),
                             include: vec![Task {
                                 source: Some(
                                     Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -295,8 +451,18 @@ mod tests {
                                     "hostnamectl hostname t470".to_owned()
                                 ])],
                                 vars: IndexMap::new(),
+                                sensitive: Vec::new(),
+                                confirm: false,
+                                user: None,
+                                escalate: false,
+                                dpkg_lock_timeout: None,
+                                limits: Default::default(),
+                                sandbox: None,
+                                independent: false,
+                                verify: Vec::new(),
                             }],
                             vars: IndexMap::new(),
+                            sensitive: Vec::new(),
                         },
                         Manifest {
                             source: Some(
@@ -305,6 +471,8 @@ mod tests {
                             ),
                             name: "zen3".to_owned(),
                             hosts: vec!["zen3".to_owned()],
+                            fact: None,
+                            host_vars: Default::default(),
                             include: vec![Task {
                                 source: Some(
                                     Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -315,8 +483,18 @@ mod tests {
                                     "hostnamectl hostname zen3".to_owned()
                                 ])],
                                 vars: IndexMap::new(),
+                                sensitive: Vec::new(),
+                                confirm: false,
+                                user: None,
+                                escalate: false,
+                                dpkg_lock_timeout: None,
+                                limits: Default::default(),
+                                sandbox: None,
+                                independent: false,
+                                verify: Vec::new(),
                             }],
                             vars: IndexMap::new(),
+                            sensitive: Vec::new(),
                         },
                         Manifest {
                             source: Some(
@@ -325,6 +503,8 @@ mod tests {
                             ),
                             name: "t470".to_owned(),
                             hosts: vec!["t470".to_owned()],
+                            fact: None,
+                            host_vars: Default::default(),
                             include: vec![Task {
                                 source: Some(
                                     Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -335,8 +515,18 @@ mod tests {
                                     "hostnamectl hostname t470".to_owned()
                                 ])],
                                 vars: IndexMap::new(),
+                                sensitive: Vec::new(),
+                                confirm: false,
+                                user: None,
+                                escalate: false,
+                                dpkg_lock_timeout: None,
+                                limits: Default::default(),
+                                sandbox: None,
+                                independent: false,
+                                verify: Vec::new(),
                             }],
                             vars: IndexMap::new(),
+                            sensitive: Vec::new(),
                         },
                     ],
                 };
@@ -345,6 +535,69 @@ mod tests {
             }
         }
 
+        mod check {
+            use super::*;
+
+            fn with_upload(from: &str) -> Plan {
+                let (mut plan, _, _, _) = plan();
+                let yaml = format!("upload:\n  from: {from}\n  to: /tmp\n");
+                let action: Action = serde_yaml::from_str(&yaml).unwrap();
+                plan.manifests[0].include[0].actions.push(action);
+                plan
+            }
+
+            #[test]
+            fn accepts_existing_upload_source() {
+                let source = tempfile::NamedTempFile::new().unwrap();
+                let plan = with_upload(&source.path().to_string_lossy());
+                assert!(plan.check().is_empty());
+            }
+
+            #[test]
+            fn reports_missing_upload_source() {
+                let dir = tempfile::tempdir().unwrap();
+                let missing = dir.path().join("missing");
+                let plan = with_upload(&missing.to_string_lossy());
+                let problems = plan.check();
+                assert_eq!(1, problems.len());
+                assert!(problems[0]
+                    .to_string()
+                    .contains(&*missing.to_string_lossy()));
+            }
+
+            #[test]
+            fn substitutes_variables() {
+                let dir = tempfile::tempdir().unwrap();
+                let mut plan = with_upload("$dir/source");
+                let dir = dir.path().to_string_lossy().to_string();
+                let _ = plan.manifests[0].vars.insert("dir".into(), dir.clone());
+                assert_eq!(1, plan.check().len());
+
+                std::fs::write(format!("{dir}/source"), "").unwrap();
+                assert!(plan.check().is_empty());
+            }
+
+            #[test]
+            fn reports_each_problem_once() {
+                let mut plan = with_upload("/nonexistent/source");
+                plan.manifests.push(plan.manifests[0].clone());
+                assert_eq!(1, plan.check().len());
+            }
+
+            #[test]
+            fn checks_verifications() {
+                let mut plan = with_upload("/nonexistent/source");
+                let task = &mut plan.manifests[0].include[0];
+                task.verify = vec![task.actions.pop().unwrap()];
+                assert_eq!(1, plan.check().len());
+            }
+
+            #[test]
+            fn finds_no_problems_in_empty_plan() {
+                assert!(Plan::new().check().is_empty());
+            }
+        }
+
         mod hosts {
             use super::*;
 
@@ -411,6 +664,51 @@ mod tests {
             }
         }
 
+        mod retain_hosts {
+            use super::*;
+
+            #[test]
+            fn removes_other_hosts_from_every_manifest() {
+                let (mut plan, _, _, _) = plan();
+                plan.manifests[0].hosts = vec!["web1".to_string(), "web2".to_string()];
+                plan.manifests.push(plan.manifests[0].clone());
+                plan.manifests[1].hosts.push("db1".to_string());
+
+                plan.retain_hosts(|host| host != "web1");
+                assert_eq!(vec!["web2".to_string()], plan.manifests[0].hosts);
+                assert_eq!(
+                    vec!["web2".to_string(), "db1".to_string()],
+                    plan.manifests[1].hosts,
+                );
+            }
+        }
+
+        mod from_manifest_str {
+            use super::*;
+
+            #[test]
+            fn works() {
+                let task_files = BTreeMap::from([(
+                    "task.yaml".to_string(),
+                    "name: Greet\nactions:\n  - command: [echo hi]\n".to_string(),
+                )]);
+                let plan = Plan::from_manifest_str(
+                    "generated",
+                    "name: Hello\nhosts: [web1, web2]\ninclude: [task.yaml]\n",
+                    &task_files,
+                )
+                .unwrap();
+                assert_eq!(vec!["web1".to_string(), "web2".to_string()], plan.hosts());
+                let actions: Vec<_> = plan
+                    .plan_for("web2")
+                    .unwrap()
+                    .iter()
+                    .map(|host_action| host_action.compile())
+                    .collect();
+                assert_eq!(vec![Action::Command(vec!["echo hi".to_string()])], actions);
+            }
+        }
+
         mod plan_for {
             use super::*;
 
@@ -574,6 +872,25 @@ mod tests {
             assert_eq!(expected_host_actions, by_value);
         }
 
+        #[test]
+        fn shares_manifest_and_task_between_actions() {
+            let (mut plan, _, _, action) = plan();
+            plan.manifests[0].include[0].actions.push(action);
+
+            let host_plan = HostPlan {
+                host: &plan.manifests[0].hosts[0],
+                plan: &plan,
+            };
+
+            let by_reference: Vec<_> = host_plan.iter().collect();
+            let by_value: Vec<_> = host_plan.into_iter().collect();
+            for actions in [by_reference, by_value] {
+                assert_eq!(2, actions.len());
+                assert!(std::ptr::eq(actions[0].manifest(), actions[1].manifest()));
+                assert!(std::ptr::eq(actions[0].task(), actions[1].task()));
+            }
+        }
+
         #[test]
         fn implements_into_iterator() {
             // Just a quick test to verify that we actually implement [IntoIterator] for both
@@ -592,4 +909,68 @@ mod tests {
             assert_eq!(by_reference, by_value);
         }
     }
+
+    mod shared_plan {
+        use super::*;
+
+        fn two_hosts() -> Plan {
+            let (mut plan, mut manifest, _, _) = plan();
+            manifest.name = "Other".into();
+            manifest.hosts = vec!["web1".into()];
+            plan.manifests.push(manifest);
+            plan.manifests[0].hosts.push("web1".into());
+            plan
+        }
+
+        #[test]
+        fn yields_same_actions_as_host_plan() {
+            let plan = two_hosts();
+            let shared = SharedPlan::from(plan.clone());
+            for host in plan.hosts() {
+                let expected: Vec<_> = plan.plan_for(&host).unwrap().into_iter().collect();
+                let actual: Vec<_> = shared.plan_for(&host).unwrap().collect();
+                assert_eq!(expected, actual);
+            }
+        }
+
+        #[test]
+        fn returns_none_for_unknown_host() {
+            assert!(SharedPlan::from(two_hosts()).plan_for("web2").is_none());
+        }
+
+        #[test]
+        fn shares_manifests_and_tasks_between_hosts() {
+            let plan = two_hosts();
+            let shared = SharedPlan::from(plan.clone());
+            let first = shared.plan_for(&plan.hosts()[0]).unwrap().next().unwrap();
+            let second = shared.plan_for(&plan.hosts()[1]).unwrap().next().unwrap();
+            assert_ne!(first.host(), second.host());
+            assert!(std::ptr::eq(first.manifest(), second.manifest()));
+            assert!(std::ptr::eq(first.task(), second.task()));
+        }
+
+        #[test]
+        fn lists_verifications_of_each_task_on_host() {
+            let mut plan = two_hosts();
+            let check = Action::Command(vec!["check".into()]);
+            for manifest in &mut plan.manifests {
+                manifest.include[0].verify = vec![check.clone()];
+            }
+            let shared = SharedPlan::from(plan);
+
+            let web1 = shared.plan_for("web1").unwrap().verifications();
+            assert_eq!(2, web1.len());
+            assert_eq!(&check, web1[0].action());
+            assert_eq!("Other", web1[1].manifest().name);
+
+            let others: Vec<_> = shared
+                .plan_for("archie-desktop")
+                .unwrap()
+                .verifications()
+                .into_iter()
+                .map(|check| check.manifest().name.clone())
+                .collect();
+            assert_eq!(vec!["API test".to_string()], others);
+        }
+    }
 }
@@ -1,8 +1,8 @@
 //! Types for representing task files.
 
-use crate::core::action::Action;
+use crate::core::action::{Action, Limits, Sandbox};
 #[cfg(doc)]
-use crate::core::manifest::Manifest;
+use crate::core::{manifest::Manifest, plan::Plan};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -40,11 +40,90 @@ pub struct Task {
     /// Order is preserved from the source file but is typically unimportant.
     #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
     pub vars: IndexMap<String, String>,
+
+    /// The names of variables whose values are sensitive. Same as [Manifest::sensitive].
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sensitive: Vec<String>,
+
+    /// Whether to pause before each of this [Task]'s [Action]s on each host and ask the user to
+    /// confirm it. Defaults to `false`.
+    ///
+    /// Sira shows the host and the compiled action, and it runs the action only if the user
+    /// answers yes. Otherwise, that host stops, just as if the action had failed. This provides a
+    /// human checkpoint for destructive actions, e.g. wiping disks or restarting databases. To
+    /// confirm a single action, place it in a [Task] of its own.
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub confirm: bool,
+
+    /// The user on the managed node that runs this [Task]'s commands and scripts, including those
+    /// in [Task::verify]. A script may override this with its own [Action::Script::user].
+    /// Defaults to root.
+    ///
+    /// This lets a whole [Task] run unprivileged. Other [Action]s, e.g. uploads, still run as
+    /// root, since they set their own owners and permissions.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub user: Option<String>,
+
+    /// Whether this [Task]'s [Action]s, including those in [Task::verify], need root privileges on
+    /// hosts that run `sira-client` without them. Defaults to `false`.
+    ///
+    /// On such rootless hosts (please see
+    /// [HostConfig::rootless](crate::config::HostConfig::rootless)), `sira-client` runs these
+    /// actions with `sudo` (or, on OpenBSD, `doas`), and every other action with the privileges of
+    /// the user that Sira logs in as. Elsewhere, every action already runs as root, so this has no
+    /// effect.
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub escalate: bool,
+
+    /// How many seconds each of this [Task]'s commands may wait for the package database's lock,
+    /// e.g. while `unattended-upgrades` holds it. Defaults to not waiting.
+    ///
+    /// If set, a command that fails because `apt`, `apt-get`, or `dpkg` couldn't get the lock
+    /// runs again every few seconds until it gets past the lock or the time runs out. Scripts
+    /// don't retry, since they may have changed things before they failed; have them pass e.g.
+    /// `-o DPkg::Lock::Timeout=300` to `apt-get` instead.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dpkg_lock_timeout: Option<u64>,
+
+    /// Resource limits, e.g. on CPU and memory, for this [Task]'s commands and scripts. Please see
+    /// [Limits]. Defaults to no limits.
+    #[serde(skip_serializing_if = "Limits::is_empty", default)]
+    pub limits: Limits,
+
+    /// If set, runs this [Task]'s scripts in a [Sandbox] with restricted file system and network
+    /// access. Defaults to no sandbox.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sandbox: Option<Sandbox>,
+
+    /// Whether this [Task]'s [Action]s are independent of each other, i.e. whether none of them
+    /// relies on another having finished first. Defaults to `false`.
+    ///
+    /// If so, and if [RunOptions::pipeline] allows, Sira starts each of these actions on a host
+    /// without waiting for the one before it to finish, which hides the round trip to a distant
+    /// host. Uploads still run one at a time, and the next [Task] still waits for all of these
+    /// actions to finish.
+    ///
+    /// [RunOptions::pipeline]: crate::run_plan::RunOptions::pipeline
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub independent: bool,
+
+    /// Checks that confirm this [Task] did its job, e.g. commands that fail unless a service is
+    /// healthy. Defaults to none.
+    ///
+    /// Each host runs its checks only after every [Action] in the [Plan] has run there, as a phase
+    /// of its own, and Sira reports them apart from the actions that made changes. Checks run in
+    /// the order of their [Task]s, one at a time, with this [Task]'s variables, limits, and
+    /// sandbox. A host whose actions failed doesn't run its checks, and a host fails if any of its
+    /// checks fails.
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub verify: Vec<Action>,
 }
 
 impl Task {
-    /// Wrapper. Calls [Action::split] on [Self::actions].
+    /// Wrapper. Calls [Action::split] on [Self::actions] and [Self::verify].
     pub fn split_actions(&mut self) {
         Action::split(&mut self.actions);
+        Action::split(&mut self.verify);
     }
 }
@@ -1,11 +1,16 @@
 //! Sign and verify files with SSH keys.
 
+use crate::client::platform;
 use crate::config;
 use anyhow::{anyhow, bail, Context};
 use std::ffi::OsString;
+use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::{Mutex, PoisonError};
+
+pub mod agent;
 
 /// The subdirectory within Sira's configuration directory that holds SSH keys.
 pub const KEY_DIR: &str = "keys";
@@ -13,6 +18,19 @@ pub const KEY_DIR: &str = "keys";
 /// The subdirectory within Sira's configuration directory that holds SSH allowed signers files.
 pub const ALLOWED_SIGNERS_DIR: &str = "allowed_signers";
 
+/// The file within Sira's configuration directory that lists the host keys and host certificate
+/// authorities that the control node trusts. Please see [known_hosts_path].
+pub const KNOWN_HOSTS_FILE: &str = "known_hosts";
+
+/// The subdirectory within Sira's configuration directory on the control node that holds the
+/// [age](https://age-encryption.org) recipients for encrypted uploads. Please see
+/// [recipients_path].
+pub const RECIPIENTS_DIR: &str = "recipients";
+
+/// The name of the [age](https://age-encryption.org) identity, within [KEY_DIR] on a managed node,
+/// that decrypts encrypted uploads. Please see [upload_identity_path].
+pub const UPLOAD_IDENTITY: &str = "upload";
+
 /// Returns the path to the signature for a given file.
 ///
 /// Does not check whether the file or its signature exist.
@@ -50,6 +68,236 @@ pub fn allowed_signers_path(name: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
     Ok(path)
 }
 
+/// Converts the contents of an SSH public key file into a line for an `allowed_signers` file.
+///
+/// Public key files have the format `[options] keytype base64-key comment`. Sira uses the comment
+/// as the principal, which `allowed_signers` files expect at the start of the line.
+///
+/// Returns an error if `public_key` has more than one line or too few components to be a key.
+pub fn allowed_signers_line(public_key: &str) -> anyhow::Result<String> {
+    let lines = public_key.lines().count();
+    if lines != 1 {
+        bail!("public key had {lines} lines but should only have 1");
+    }
+
+    let mut components: Vec<&str> = public_key.trim().split(' ').collect();
+    if components.len() < 3 {
+        bail!(
+            "public key had {} components but should have at least 3",
+            components.len(),
+        );
+    }
+
+    let principal = components.pop().unwrap();
+    components.insert(0, principal);
+    let mut line = components.join(" ");
+    line.push('\n');
+    Ok(line)
+}
+
+/// Returns whether the contents of an SSH public key file (or a line from an `authorized_keys` or
+/// allowed signers file) describe a hardware-backed FIDO2 key, e.g. `sk-ssh-ed25519@openssh.com`.
+///
+/// Using such a key requires the administrator to touch the device, unless the key was generated
+/// with `-O no-touch-required`.
+pub fn is_security_key(public_key: &str) -> bool {
+    public_key
+        .split_whitespace()
+        .any(|component| component.starts_with("sk-") && component.ends_with("@openssh.com"))
+}
+
+/// Returns whether the SSH private key at `key_file` is a hardware-backed FIDO2 key.
+///
+/// Checks the public key alongside it, i.e. `<key_file>.pub`. Returns `false` if the public key
+/// cannot be read.
+pub fn key_file_is_security_key(key_file: impl AsRef<Path>) -> bool {
+    let mut public_key: OsString = key_file.as_ref().to_owned().into();
+    public_key.push(".pub");
+    fs::read_to_string(public_key).is_ok_and(|key| is_security_key(&key))
+}
+
+/// Returns the path to Sira's known hosts file on the control node.
+///
+/// If this file exists, Sira checks managed nodes' host keys against it, and only against it,
+/// rather than against the user's `~/.ssh/known_hosts`. Typically, it holds one or more
+/// `@cert-authority` lines (see [cert_authority_line]), so that every managed node with a host
+/// certificate from a trusted authority is accepted without distributing its host key.
+///
+/// Does not check whether this file exists.
+pub fn known_hosts_path() -> PathBuf {
+    config::config_dir().join(KNOWN_HOSTS_FILE)
+}
+
+/// Converts the contents of a certificate authority's SSH public key file into a `known_hosts`
+/// line that trusts host certificates signed by that authority for hosts matching
+/// `host_patterns`, e.g. `*.example.com` or `*`.
+///
+/// Returns an error if `public_key` has more than one line or too few components to be a key.
+pub fn cert_authority_line(public_key: &str, host_patterns: &str) -> anyhow::Result<String> {
+    let lines = public_key.lines().count();
+    if lines != 1 {
+        bail!("public key had {lines} lines but should only have 1");
+    }
+
+    // Format: keytype base64-key [comment]
+    let components = public_key.split_whitespace().count();
+    if components < 2 {
+        bail!("public key had {components} components but should have at least 2");
+    }
+
+    if host_patterns.is_empty() || host_patterns.contains(char::is_whitespace) {
+        bail!("host patterns should be non-empty and contain no whitespace: {host_patterns:?}");
+    }
+
+    Ok(format!(
+        "@cert-authority {host_patterns} {}\n",
+        public_key.trim(),
+    ))
+}
+
+/// Returns the path to the file that lists the [age](https://age-encryption.org) recipients for a
+/// managed node, one per line, e.g. the public key of the node's upload identity.
+///
+/// The file is named after the host exactly as it appears in manifests, e.g.
+/// `/etc/sira/recipients/web1.example.com`.
+///
+/// Does not check whether this file exists. Returns an error if `host` could not safely be used
+/// as a file name.
+pub fn recipients_path(host: &str) -> anyhow::Result<PathBuf> {
+    if host.is_empty() || host == "." || host == ".." || host.contains('/') {
+        bail!("host cannot be used as a recipients file name: {host:?}");
+    }
+    Ok(resource_dir(RECIPIENTS_DIR).join(host))
+}
+
+/// Returns the path to the [age](https://age-encryption.org) identity that decrypts encrypted
+/// uploads on a managed node.
+///
+/// Does not check whether this file exists.
+pub fn upload_identity_path() -> PathBuf {
+    resource_dir(KEY_DIR).join(UPLOAD_IDENTITY)
+}
+
+/// Encrypts the file at `from` with [age](https://age-encryption.org) to the recipients listed in
+/// `recipients_file`, writing the result to `to`.
+pub fn encrypt_file(
+    from: impl AsRef<Path>,
+    recipients_file: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+) -> anyhow::Result<()> {
+    if !recipients_file.as_ref().try_exists()? {
+        bail!(
+            "could not find recipients file for encrypted upload: {}",
+            recipients_file.as_ref().display(),
+        );
+    }
+
+    // age --encrypt -R <recipients-file> -o <to> <from>
+    let mut command = Command::new("age");
+    command
+        .arg("--encrypt")
+        .arg("-R")
+        .arg(recipients_file.as_ref())
+        .arg("-o")
+        .arg(to.as_ref())
+        .arg(from.as_ref());
+    run_age(command, "encrypting", from)
+}
+
+/// Decrypts the file at `from` with the [age](https://age-encryption.org) identity in
+/// `identity_file`, writing the result to `to`.
+pub fn decrypt_file(
+    from: impl AsRef<Path>,
+    identity_file: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+) -> anyhow::Result<()> {
+    // age --decrypt -i <identity-file> -o <to> <from>
+    let mut command = Command::new("age");
+    command
+        .arg("--decrypt")
+        .arg("-i")
+        .arg(identity_file.as_ref())
+        .arg("-o")
+        .arg(to.as_ref())
+        .arg(from.as_ref());
+    run_age(command, "decrypting", from)
+}
+
+/// Runs an `age` command, returning its stderr as an error if it fails.
+fn run_age(mut command: Command, verb: &str, file: impl AsRef<Path>) -> anyhow::Result<()> {
+    let output = command.output().context("failed to run age")?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "Error {verb} file {}:\n{}",
+            file.as_ref().display(),
+            String::from_utf8_lossy(&output.stderr),
+        )),
+    }
+}
+
+/// Returns the SHA-256 checksum of the file at `path` in lowercase hexadecimal, as computed by
+/// `sha256sum` or the local equivalent (please see [platform::sha256sum]).
+pub fn sha256_file(path: impl AsRef<Path>) -> anyhow::Result<String> {
+    let (program, args) = platform::sha256sum();
+    let output = Command::new(program)
+        .args(args)
+        .arg("--")
+        .arg(path.as_ref())
+        .output()
+        .with_context(|| format!("failed to run {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "Error computing checksum of file {}:\n{}",
+            path.as_ref().display(),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+
+    // sha256sum and shasum prefix the line with a backslash if they had to escape the file name.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.trim_start_matches('\\').split_whitespace().next() {
+        Some(checksum) => Ok(checksum.to_string()),
+        None => bail!(
+            "{program} returned no checksum for file {}",
+            path.as_ref().display(),
+        ),
+    }
+}
+
+/// Returns the SHA-256 checksum of `data` in lowercase hexadecimal, as computed by `sha256sum` or
+/// the local equivalent (please see [platform::sha256sum]).
+pub fn sha256(data: &[u8]) -> anyhow::Result<String> {
+    let (program, args) = platform::sha256sum();
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {program}"))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin should be piped")
+        .write_all(data)
+        .with_context(|| format!("failed to write to {program}"))?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "Error computing checksum:\n{}",
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+    match String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+    {
+        Some(checksum) => Ok(checksum.to_string()),
+        None => bail!("{program} returned no checksum"),
+    }
+}
+
 /// Returns the path to the directory for a resource type.
 ///
 /// `name` should be one of the constants defined in this file, e.g. [KEY_DIR].
@@ -146,10 +394,61 @@ pub fn sign(file: &[u8], key: impl AsRef<Path>) -> anyhow::Result<SigningOutcome
         return Ok(SigningOutcome::KeyNotFound);
     }
 
+    Ok(SigningOutcome::Signed(sign_with_key_file(file, key_file)?))
+}
+
+/// Cryptographically signs a file on disk and writes a detached signature alongside it.
+///
+/// Unlike [sign], `key_file` may be any path to an SSH private key, e.g. the manifest key in the
+/// administrator's `~/.ssh` directory. The signature is written to [signature_path], replacing
+/// any existing signature.
+///
+/// # Returns
+///
+/// Returns the path to the signature file on success.
+///
+/// # Errors
+///
+/// Returns an error if `file` cannot be read, if `ssh-keygen` fails for any reason (including a
+/// missing key file), or if the signature cannot be written.
+pub fn sign_file(file: impl AsRef<Path>, key_file: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
+    let contents = fs::read(&file)
+        .with_context(|| format!("failed to read file to sign: {}", file.as_ref().display()))?;
+
+    // We sign via stdin rather than passing the file name to ssh-keygen, because ssh-keygen
+    // prompts interactively before replacing an existing signature file.
+    let signature = sign_with_key_file(&contents, key_file)?;
+
+    let signature_file = signature_path(&file);
+    fs::write(&signature_file, signature).with_context(|| {
+        format!(
+            "failed to write signature file: {}",
+            signature_file.display(),
+        )
+    })?;
+    Ok(signature_file)
+}
+
+/// Cryptographically signs the contents of a buffer with an SSH private key file.
+///
+/// Unlike [sign], `key_file` may be any path, and a missing key file is an error rather than a
+/// [SigningOutcome::KeyNotFound]. This is useful when the caller explicitly chose a key, e.g.
+/// while rotating keys.
+///
+/// If `key_file` is a hardware-backed key (see [key_file_is_security_key]), signing blocks until
+/// the administrator touches the device. Only one such signature is requested at a time, since
+/// most devices can't handle concurrent requests.
+///
+/// Returns the signature on success.
+pub fn sign_with_key_file(file: &[u8], key_file: impl AsRef<Path>) -> anyhow::Result<Vec<u8>> {
+    static SECURITY_KEY: Mutex<()> = Mutex::new(());
+    let _guard = key_file_is_security_key(&key_file)
+        .then(|| SECURITY_KEY.lock().unwrap_or_else(PoisonError::into_inner));
+
     // ssh-keygen -Y sign -f <key-file> -n sira
     let mut child = Command::new("ssh-keygen")
         .args(["-Y", "sign", "-f"])
-        .arg(key_file)
+        .arg(key_file.as_ref())
         .args(["-n", "sira"])
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
@@ -174,8 +473,14 @@ pub fn sign(file: &[u8], key: impl AsRef<Path>) -> anyhow::Result<SigningOutcome
         .wait_with_output()
         .context("failed to wait_with_output for ssh-keygen")?;
     match output.status.success() {
-        true => Ok(SigningOutcome::Signed(output.stdout)),
-        false => Err(anyhow!("{}", String::from_utf8_lossy(&output.stderr))),
+        true => Ok(output.stdout),
+        false => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match agent::hint(&stderr) {
+                Some(hint) => Err(anyhow!("{}\n{hint}", stderr.trim_end())),
+                None => Err(anyhow!("{stderr}")),
+            }
+        }
     }
 }
 
@@ -225,7 +530,22 @@ pub fn verify(
             "please install the required allowed_signers file: {}",
             allowed_signers_file.to_string_lossy(),
         );
-    } else if let Ok(false) = signature.as_ref().try_exists() {
+    }
+    verify_with_allowed_signers_file(file, signature, allowed_signers_file, identity)
+}
+
+/// Same as [verify], but takes the path to any `allowed_signers` file rather than the name of one
+/// installed in Sira's configuration directory.
+///
+/// This is useful for checking signatures away from the node that normally checks them, e.g.
+/// verifying an audit log on an administrator's workstation.
+pub fn verify_with_allowed_signers_file(
+    file: &[u8],
+    signature: impl AsRef<Path>,
+    allowed_signers_file: impl AsRef<Path>,
+    identity: impl AsRef<str>,
+) -> anyhow::Result<()> {
+    if let Ok(false) = signature.as_ref().try_exists() {
         bail!(
             "missing signature file: {}",
             signature.as_ref().to_string_lossy(),
@@ -241,7 +561,7 @@ pub fn verify(
     //   < <file-to-verify>
     let mut child = Command::new("ssh-keygen")
         .args(["-Y", "verify", "-f"])
-        .arg(allowed_signers_file)
+        .arg(allowed_signers_file.as_ref())
         .arg("-I")
         .arg(identity.as_ref())
         .args(["-n", "sira"])
@@ -279,5 +599,45 @@ pub fn verify(
     }
 }
 
+/// Lists the principals in an `allowed_signers` file whose keys match a signature.
+///
+/// This only matches the signature's public key against the `allowed_signers` file; it does
+/// **not** verify the signature. Callers must still pass one of the returned principals to
+/// [verify] as the identity.
+///
+/// `allowed_signers` follows the same restrictions as in [verify].
+///
+/// # Returns
+///
+/// Returns the matching principals in the order that `ssh-keygen` reports them, or an error if
+/// no principal matches or `ssh-keygen` fails for any other reason.
+pub fn find_principals(
+    signature: impl AsRef<Path>,
+    allowed_signers: impl AsRef<Path>,
+) -> anyhow::Result<Vec<String>> {
+    let allowed_signers_file = allowed_signers_path(&allowed_signers)?;
+
+    // ssh-keygen -Y find-principals -f <allowed-signers-file> -s <signature-file>
+    let output = Command::new("ssh-keygen")
+        .args(["-Y", "find-principals", "-f"])
+        .arg(allowed_signers_file)
+        .arg("-s")
+        .arg(signature.as_ref())
+        .output()
+        .context("failed to run ssh-keygen")?;
+
+    if !output.status.success() {
+        bail!(
+            "Error finding principals for signature {}:\n{}",
+            signature.as_ref().to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+
+    let principals = String::from_utf8(output.stdout)
+        .context("ssh-keygen returned principals that were not UTF-8")?;
+    Ok(principals.lines().map(str::to_string).collect())
+}
+
 #[cfg(test)]
 mod test;
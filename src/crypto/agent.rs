@@ -0,0 +1,126 @@
+//! Loads Sira's keys into `ssh-agent` with constraints, and explains failures that come from the
+//! agent.
+//!
+//! Keys in an agent never touch the disk unencrypted, but any process that can reach the agent
+//! can use them. Two constraints limit the damage: with [Constraints::confirm], the agent asks
+//! the administrator (via `ssh-askpass`) before each use, and with [Constraints::lifetime], it
+//! forgets the key after a while. Both are enforced by the agent itself. Please see `ssh-add(1)`.
+
+use anyhow::{bail, Context};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Limits on how `ssh-agent` may use a key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Constraints {
+    /// Whether the agent asks for confirmation each time the key is used.
+    ///
+    /// The agent asks with `ssh-askpass`, so one must be installed, and a display available.
+    pub confirm: bool,
+
+    /// How long the agent keeps the key, in the time format of `sshd_config(5)`, e.g. `3600`,
+    /// `30m`, or `1h30m`. If [None], the agent keeps the key until it exits, unless it has a
+    /// default lifetime of its own.
+    pub lifetime: Option<String>,
+}
+
+impl Constraints {
+    /// Returns the arguments that ask `ssh-add` for these constraints.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [Constraints::lifetime] isn't a valid time.
+    pub fn args(&self) -> anyhow::Result<Vec<String>> {
+        let mut args = vec![];
+        if self.confirm {
+            args.push("-c".to_string());
+        }
+        if let Some(lifetime) = &self.lifetime {
+            if !is_time(lifetime) {
+                bail!("invalid lifetime {lifetime:?}; expected e.g. 3600, 30m, or 1h30m");
+            }
+            args.push("-t".to_string());
+            args.push(lifetime.clone());
+        }
+        Ok(args)
+    }
+}
+
+/// Returns whether `s` is a time in the format of `sshd_config(5)`: one or more numbers, each
+/// followed by an optional unit (`s`, `m`, `h`, `d`, or `w`, in either case), e.g. `1h30m`.
+fn is_time(s: &str) -> bool {
+    let mut digits = 0;
+    for c in s.chars() {
+        match c {
+            '0'..='9' => digits += 1,
+            's' | 'S' | 'm' | 'M' | 'h' | 'H' | 'd' | 'D' | 'w' | 'W' if digits > 0 => digits = 0,
+            _ => return false,
+        }
+    }
+    !s.is_empty()
+}
+
+/// Adds the private key in `key_file` to the running `ssh-agent` with `constraints`.
+///
+/// `ssh-add` prompts for the key's passphrase on the terminal, if it has one.
+///
+/// # Errors
+///
+/// Returns an error if no agent is running, if `constraints` are invalid, or if `ssh-add` fails,
+/// e.g. because the key file is missing or the agent doesn't support a constraint.
+pub fn add(key_file: impl AsRef<Path>, constraints: &Constraints) -> anyhow::Result<()> {
+    if env::var_os("SSH_AUTH_SOCK").is_none_or(|socket| socket.is_empty()) {
+        bail!("{}", NO_AGENT);
+    }
+    let status = Command::new("ssh-add")
+        .args(constraints.args()?)
+        .arg("--")
+        .arg(key_file.as_ref())
+        .status()
+        .context("could not run ssh-add")?;
+
+    // ssh-add exits with 2 if it can't reach the agent, and with 1 for any other failure.
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(2) => bail!("{}", NO_AGENT),
+        _ => bail!(
+            "ssh-add could not add {} to ssh-agent",
+            key_file.as_ref().display(),
+        ),
+    }
+}
+
+/// Explains why Sira couldn't reach `ssh-agent`.
+const NO_AGENT: &str = "could not reach ssh-agent; please check that it's running and that \
+    SSH_AUTH_SOCK is set, e.g. with eval \"$(ssh-agent)\"";
+
+/// Returns advice for an error from OpenSSH, e.g. `ssh` or `ssh-keygen`, that `ssh-agent` may have
+/// caused, or [None] if the error doesn't look like one.
+pub fn hint(error: &str) -> Option<&'static str> {
+    if error.contains("agent refused operation") {
+        Some(
+            "ssh-agent refused to use the key. If the key was added with confirmation, please \
+            approve the prompt, and check that ssh-askpass is installed. If it was added with a \
+            lifetime, it may have expired; please add it again, e.g. with sira agent-add.",
+        )
+    } else if error.contains("Could not open a connection to your authentication agent")
+        || error.contains("Error connecting to agent")
+        || error.contains("communication with agent failed")
+    {
+        Some(
+            "OpenSSH could not reach ssh-agent. Please check that it's running and that \
+            SSH_AUTH_SOCK is set, e.g. with eval \"$(ssh-agent)\".",
+        )
+    } else if error.contains("Permission denied (publickey") {
+        Some(
+            "If the login key is in ssh-agent, please check that it's still there with \
+            ssh-add -l; keys added with a lifetime expire.",
+        )
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test;
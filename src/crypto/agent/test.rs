@@ -0,0 +1,69 @@
+use super::*;
+
+mod constraints {
+    use super::*;
+
+    #[test]
+    fn asks_for_nothing_by_default() {
+        assert!(Constraints::default().args().unwrap().is_empty());
+    }
+
+    #[test]
+    fn asks_for_confirmation_and_lifetime() {
+        let constraints = Constraints {
+            confirm: true,
+            lifetime: Some("1h30m".to_string()),
+        };
+        assert_eq!(vec!["-c", "-t", "1h30m"], constraints.args().unwrap());
+    }
+
+    #[test]
+    fn accepts_valid_lifetimes() {
+        for lifetime in ["3600", "30m", "1h30m", "2D", "1w2d", "1h30"] {
+            assert!(is_time(lifetime), "{lifetime}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_lifetimes() {
+        for lifetime in ["", "h", "1x", "-1", "1 h", "1hh", "-t"] {
+            let constraints = Constraints {
+                confirm: false,
+                lifetime: Some(lifetime.to_string()),
+            };
+            assert!(constraints.args().is_err(), "{lifetime:?}");
+        }
+    }
+}
+
+mod hint {
+    use super::*;
+
+    #[test]
+    fn explains_refused_operation() {
+        let hint = hint("sign_and_send_pubkey: signing failed: agent refused operation").unwrap();
+        assert!(hint.contains("confirmation"));
+        assert!(hint.contains("lifetime"));
+    }
+
+    #[test]
+    fn explains_missing_agent() {
+        for error in [
+            "Could not open a connection to your authentication agent.",
+            "Error connecting to agent: No such file or directory",
+        ] {
+            assert!(hint(error).unwrap().contains("SSH_AUTH_SOCK"), "{error}");
+        }
+    }
+
+    #[test]
+    fn mentions_agent_when_login_is_denied() {
+        let hint = hint("sira@web1: Permission denied (publickey).").unwrap();
+        assert!(hint.contains("ssh-add -l"));
+    }
+
+    #[test]
+    fn ignores_other_errors() {
+        assert_eq!(None, hint("Connection refused"));
+    }
+}
@@ -46,6 +46,212 @@ fn verify_file(
     verify(&file_buffer, signature, allowed_signers, identity)
 }
 
+mod allowed_signers_line {
+    use super::*;
+
+    #[test]
+    fn matches_installed_allowed_signers_file() {
+        let public_key = fs::read_to_string(resource_dir(KEY_DIR).join("manifest.pub")).unwrap();
+        let expected =
+            fs::read_to_string(resource_dir(ALLOWED_SIGNERS_DIR).join("manifest")).unwrap();
+        assert_eq!(expected, allowed_signers_line(&public_key).unwrap());
+    }
+
+    #[test]
+    fn keeps_options() {
+        assert_eq!(
+            "sira no-touch-required ssh-ed25519 AAAA\n",
+            allowed_signers_line("no-touch-required ssh-ed25519 AAAA sira\n").unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_multiple_lines() {
+        assert!(allowed_signers_line("ssh-ed25519 AAAA sira\nssh-ed25519 BBBB sira\n").is_err());
+    }
+
+    #[test]
+    fn rejects_too_few_components() {
+        assert!(allowed_signers_line("ssh-ed25519 AAAA\n").is_err());
+    }
+}
+
+mod cert_authority_line {
+    use super::*;
+
+    #[test]
+    fn works() {
+        assert_eq!(
+            "@cert-authority *.example.com ssh-ed25519 AAAA host-ca\n",
+            cert_authority_line("ssh-ed25519 AAAA host-ca\n", "*.example.com").unwrap(),
+        );
+    }
+
+    #[test]
+    fn accepts_key_without_comment() {
+        assert_eq!(
+            "@cert-authority * ssh-ed25519 AAAA\n",
+            cert_authority_line("ssh-ed25519 AAAA", "*").unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_multiple_lines() {
+        assert!(cert_authority_line("ssh-ed25519 AAAA a\nssh-ed25519 BBBB b\n", "*").is_err());
+    }
+
+    #[test]
+    fn rejects_too_few_components() {
+        assert!(cert_authority_line("ssh-ed25519\n", "*").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_host_patterns() {
+        assert!(cert_authority_line("ssh-ed25519 AAAA", "").is_err());
+        assert!(cert_authority_line("ssh-ed25519 AAAA", "a b").is_err());
+    }
+}
+
+mod is_security_key {
+    use super::*;
+
+    #[test]
+    fn detects_security_keys() {
+        assert!(is_security_key("sk-ssh-ed25519@openssh.com AAAA sira\n"));
+        assert!(is_security_key(
+            "sira no-touch-required sk-ecdsa-sha2-nistp256@openssh.com AAAA\n"
+        ));
+    }
+
+    #[test]
+    fn ignores_other_keys() {
+        assert!(!is_security_key("ssh-ed25519 AAAA sira\n"));
+        assert!(!is_security_key("ssh-ed25519 AAAA sk-laptop\n"));
+    }
+
+    #[test]
+    fn key_file_is_security_key_reads_public_key() {
+        assert!(!key_file_is_security_key(
+            resource_dir(KEY_DIR).join("action")
+        ));
+        assert!(!key_file_is_security_key("/nonexistent/key"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("sk");
+        fs::write(
+            dir.path().join("sk.pub"),
+            "sk-ssh-ed25519@openssh.com AAAA sira\n",
+        )
+        .unwrap();
+        assert!(key_file_is_security_key(key_file));
+    }
+}
+
+mod recipients_path {
+    use super::*;
+
+    #[test]
+    fn works() {
+        assert_eq!(
+            resource_dir(RECIPIENTS_DIR).join("sira@web1"),
+            recipients_path("sira@web1").unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_directory_traversal() {
+        for host in ["", ".", "..", "../keys/action", "a/b"] {
+            assert!(recipients_path(host).is_err(), "{host:?}");
+        }
+    }
+}
+
+mod encrypt_file {
+    use super::*;
+
+    #[test]
+    fn requires_recipients_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = encrypt_file(
+            resource_path("sample.manifest"),
+            dir.path().join("missing"),
+            dir.path().join("out"),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("recipients file"));
+    }
+}
+
+mod sha256_file {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn returns_checksum() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello\n").unwrap();
+        assert_eq!(
+            "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03",
+            sha256_file(file.path()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn returns_error_for_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(sha256_file(dir.path().join("missing")).is_err());
+    }
+}
+
+mod sha256 {
+    use super::*;
+
+    #[test]
+    fn returns_checksum() {
+        assert_eq!(
+            "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03",
+            sha256(b"hello\n").unwrap(),
+        );
+    }
+}
+
+mod find_principals {
+    use super::*;
+
+    #[test]
+    fn works() {
+        let file_path = resource_path("sample.manifest");
+        let key = "manifest";
+        let signature_path = match sign_file(&file_path, key) {
+            Ok((SigningOutcome::Signed(_), Some(signature_path))) => signature_path,
+            x => panic!("expected Ok((SigningOutcome::Signed(_), Some(path)) but received:\n{x:?}"),
+        };
+
+        let result = find_principals(&signature_path, key);
+        fs::remove_file(&signature_path).unwrap();
+        assert_eq!(vec!["sira".to_string()], result.unwrap());
+    }
+
+    #[test]
+    fn returns_error_if_no_principal_matches() {
+        let file_path = resource_path("sample.manifest");
+        let signature_path = match sign_file(&file_path, "manifest") {
+            Ok((SigningOutcome::Signed(_), Some(signature_path))) => signature_path,
+            x => panic!("expected Ok((SigningOutcome::Signed(_), Some(path)) but received:\n{x:?}"),
+        };
+
+        // The action allowed signers file holds a different key.
+        let result = find_principals(&signature_path, "action");
+        fs::remove_file(&signature_path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn protects_from_directory_traversal_on_allowed_signers() {
+        assert!(find_principals("/dev/null", "..").is_err());
+    }
+}
+
 mod sign {
     use super::*;
 
@@ -194,6 +400,40 @@ mod sign {
     }
 }
 
+mod sign_file {
+    use super::*;
+
+    // The test helper of the same name in this file shadows the function under test.
+    use crate::crypto::sign_file;
+
+    #[test]
+    fn writes_verifiable_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("sample.manifest");
+        fs::copy(resource_path("sample.manifest"), &file).unwrap();
+
+        // Write a stale signature to make sure sign_file replaces it rather than failing.
+        fs::write(signature_path(&file), "stale").unwrap();
+
+        let key_file = resource_dir(KEY_DIR).join("manifest");
+        let signature = sign_file(&file, key_file).unwrap();
+        assert_eq!(signature_path(&file), signature);
+
+        verify_file(&file, &signature, "manifest", "sira").unwrap();
+    }
+
+    #[test]
+    fn returns_error_if_key_does_not_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("sample.manifest");
+        fs::copy(resource_path("sample.manifest"), &file).unwrap();
+
+        let key_file = resource_dir(KEY_DIR).join("does_not_exist");
+        assert!(sign_file(&file, key_file).is_err());
+        assert!(!signature_path(&file).exists());
+    }
+}
+
 mod verify {
     use super::*;
 
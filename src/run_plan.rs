@@ -1,22 +1,371 @@
 //! Provides a [tokio]-based [Plan] runner that runs on each host in parallel.
 
-use crate::core::plan::HostPlanIntoIter;
+use crate::config::{
+    self, Config, Confirmation, HostConfig, HostKeyChecking, OnFailure, Transport,
+};
+use crate::core::action::{
+    ClientError, Envelope, HostAction, Progress, Redactor, UPLOAD_CHECKSUM_PREFIX,
+};
+use crate::core::plan::{HostPlanIntoIter, SharedPlan};
 use crate::core::Action;
 use crate::core::Plan;
-use crate::crypto::{self, SigningOutcome};
-use anyhow::bail;
-use std::panic;
-use tokio::task::JoinSet;
+use crate::crypto::{self, SigningOutcome, KEY_DIR};
+use anyhow::{bail, Context};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::future::{self, Future};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::Output;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::Semaphore;
+use tokio::task::{self, JoinSet};
 
 pub mod client;
 use client::*;
 
+pub mod adhoc;
+
+pub mod api;
+
+pub mod artifacts;
+use artifacts::Artifacts;
+
+pub mod audit;
+use audit::AuditLog;
+
+pub mod cancel;
+use cancel::{Aborted, CancelToken, Cancelled};
+
+pub mod dashboard;
+
+pub mod deploy_client;
+use deploy_client::ExpectedClients;
+
+pub mod drift;
+use drift::DriftReport;
+
+pub mod embed;
+use embed::{Embedded, EmbedderPanic};
+
+pub mod email;
+use email::{EmailConfig, EmailNotifier};
+
+pub mod event_stream;
+use event_stream::EventStream;
+
+pub mod facts;
+
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod fixtures;
+
+pub mod history;
+use history::History;
+
+pub mod hooks;
+
+pub mod html_report;
+use html_report::HtmlReport;
+
+pub mod inventory;
+
+pub mod json_log;
+use json_log::JsonLog;
+
+pub mod list;
+
+pub mod metrics;
+use metrics::Metrics;
+
+pub mod ping;
+
+pub mod progress;
+use progress::ProgressReporter;
+
+pub mod queue;
+
 pub mod report;
 use report::*;
 
+pub mod rotate_keys;
+
+pub mod schedule;
+
+pub mod sink;
+use sink::{LogSinks, SinkLogger};
+
+pub mod ssh_config;
+
+pub mod status;
+use status::StatusHandle;
+
+pub mod syslog;
+use syslog::Syslog;
+
+pub mod wake;
+
+pub mod webhook;
+use webhook::{Notifier, Webhook};
+
 /// The name of the key used for signing actions before they're sent from `sira` to `sira-client`.
 pub const ACTION_SIGNING_KEY: &str = "action";
 
+/// The ID of the next run to start in this process. Please see [Report::plan_started].
+static NEXT_PLAN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Options that customize how [run_plan_with] runs a [Plan].
+///
+/// The [Default] value reproduces the behavior of [run_plan].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    /// The private key used to log into managed nodes.
+    ///
+    /// If [None], OpenSSH chooses the key as usual, e.g. from `ssh-agent`.
+    pub login_key: Option<PathBuf>,
+
+    /// The private key used to sign actions before sending them to `sira-client`.
+    ///
+    /// If [None], actions are signed with the action key installed in Sira's key directory (see
+    /// [ACTION_SIGNING_KEY]), or left unsigned if that key is not installed.
+    pub action_key: Option<PathBuf>,
+
+    /// The known hosts file against which to check managed nodes' host keys.
+    ///
+    /// If [None], Sira uses its own known hosts file if installed (see
+    /// [crypto::known_hosts_path]), or else the user's known hosts files as usual. Please see
+    /// [ConnectionManager::new] for details.
+    pub known_hosts: Option<PathBuf>,
+
+    /// How to check managed nodes' host keys.
+    ///
+    /// If [None], Sira rejects unknown hosts if it has a known hosts file to check them against,
+    /// and otherwise trusts and remembers them. Please see [ConnectionManager::new] for details.
+    pub host_key_checking: Option<HostKeyChecking>,
+
+    /// How to connect to every managed node and run `sira-client` on it, e.g. as which user.
+    ///
+    /// Settings left unset keep their defaults: OpenSSH chooses the port and user as usual, e.g.
+    /// from the user's SSH configuration, and `sira-client` runs from [CLIENT_PATH] with `sudo`.
+    pub connection: HostConfig,
+
+    /// Overrides of [RunOptions::connection] for individual hosts. Please see
+    /// [RunOptions::connection_for].
+    pub hosts: BTreeMap<String, HostConfig>,
+
+    /// The most hosts to run the [Plan] on at once. If [None], every host runs at once.
+    pub concurrency: Option<usize>,
+
+    /// The most actions to keep in flight on each host at once, for tasks marked
+    /// [independent](crate::core::Task::independent). If [None], each host runs one action at a
+    /// time.
+    ///
+    /// Sira opens another channel on each host's connection for each extra action in flight, so
+    /// this also limits how many channels each connection opens.
+    pub pipeline: Option<usize>,
+
+    /// Which actions require confirmation before they run.
+    pub confirm: Confirmation,
+
+    /// What happens to the other hosts when one fails. By default, they run to completion.
+    ///
+    /// With [OnFailure::Abort], the first host to fail stops the run on every host, much as
+    /// [RunOptions::cancel] would, and each host that hadn't finished fails with [Aborted].
+    pub on_failure: OnFailure,
+
+    /// A file to which to append a signed record of every action that runs.
+    ///
+    /// Records are signed with the same key as actions, so an action key is required. Please see
+    /// [AuditLog] for details.
+    pub audit_log: Option<PathBuf>,
+
+    /// A file to which to append a JSON record of every action that runs, or [json_log::STDOUT]
+    /// to write these records to stdout instead of the usual human-readable output.
+    ///
+    /// Please see [JsonLog] for details.
+    pub json_log: Option<PathBuf>,
+
+    /// Whether to also send reports to the control node's system log, e.g. syslog or the systemd
+    /// journal.
+    ///
+    /// Please see [Syslog] for details.
+    pub syslog: bool,
+
+    /// Whether to show a progress bar for each host instead of a report for each action.
+    ///
+    /// Please see [ProgressReporter] for details.
+    pub progress: bool,
+
+    /// Whether to show a full-screen dashboard instead of the usual output, from which the user
+    /// can follow each host, answer confirmations, ignore or retry hosts, and abort the run.
+    ///
+    /// Please see [dashboard] for details.
+    pub dashboard: bool,
+
+    /// How much detail to print to the terminal. Has no effect with [RunOptions::progress],
+    /// [RunOptions::dashboard], or when writing a JSON log to stdout.
+    pub verbosity: Verbosity,
+
+    /// A file to which to write a standalone HTML report of the run, overwriting any existing file.
+    ///
+    /// Please see [HtmlReport] for details.
+    pub html_report: Option<PathBuf>,
+
+    /// Webhooks to notify when a host fails and when the run finishes.
+    ///
+    /// Please see [Notifier] for details.
+    pub webhooks: Vec<Webhook>,
+
+    /// A file to which to write Prometheus metrics at the end of the run, e.g. for the node
+    /// exporter's textfile collector.
+    ///
+    /// Please see [Metrics] for details.
+    pub metrics: Option<PathBuf>,
+
+    /// The format in which to report progress on stdout.
+    ///
+    /// With [OutputFormat::Json], [EventStream] replaces the usual output, so
+    /// [RunOptions::verbosity] and [RunOptions::progress] have no effect.
+    pub output: OutputFormat,
+
+    /// Whether to use color in the usual human-readable output.
+    pub color: ColorChoice,
+
+    /// A directory under which to save each action's captured output to its own file.
+    ///
+    /// Please see [Artifacts] for details.
+    pub artifacts: Option<PathBuf>,
+
+    /// A SQLite database in which to record the run, its hosts' results, and every action.
+    ///
+    /// Please see [History] for details.
+    pub history: Option<PathBuf>,
+
+    /// A directory in which to cache the facts gathered from each managed node. If [None], Sira
+    /// uses `facts` in the user's cache directory, e.g. `~/.cache/sira/facts`.
+    ///
+    /// Please see [facts] for details.
+    pub facts_cache: Option<PathBuf>,
+
+    /// How long cached facts stay fresh, in seconds. If [None], uses [facts::DEFAULT_TTL]. If
+    /// zero, Sira gathers facts every time and caches nothing.
+    ///
+    /// Please see [facts] for details.
+    pub facts_ttl: Option<u64>,
+
+    /// Whether to gather facts again even if the cached ones are fresh.
+    ///
+    /// Please see [facts] for details.
+    pub refresh_facts: bool,
+
+    /// Shell commands on the control node that print inventories of hosts, groups, and variables
+    /// as JSON. Names of groups in manifests' hosts stand for the groups' hosts.
+    ///
+    /// Please see [inventory] for details.
+    pub inventory: Vec<String>,
+
+    /// Which AWS EC2 instances to list as an inventory, if any. Requires the `ec2` feature.
+    ///
+    /// Please see [inventory::ec2] for details.
+    #[cfg(feature = "ec2")]
+    pub ec2: Option<inventory::ec2::Ec2Config>,
+
+    /// Groups of hosts to look up in DNS, by group name.
+    ///
+    /// Please see [inventory::dns] for details.
+    pub dns: BTreeMap<String, inventory::dns::DnsSource>,
+
+    /// Where and how to send an email summarizing the run if any host fails.
+    ///
+    /// Usually loaded with [EmailConfig::load]. Please see [EmailNotifier] for details.
+    pub email: Option<EmailConfig>,
+
+    /// Shell commands to run on the control node before the run starts. If one fails, nothing
+    /// runs.
+    ///
+    /// Please see [hooks] for details.
+    pub pre_run: Vec<String>,
+
+    /// Shell commands to run on the control node after the run finishes, each of which receives
+    /// a summary of the run on stdin.
+    ///
+    /// Please see [hooks] for details.
+    pub post_run: Vec<String>,
+
+    /// Additional destinations for a record of every action, e.g. a database.
+    ///
+    /// Please see [sink] for details.
+    pub log_sinks: LogSinks,
+
+    /// A token with which to stop the run early. If [None], the run can't be cancelled.
+    ///
+    /// Please see [cancel] for details.
+    pub cancel: Option<CancelToken>,
+
+    /// A handle with which to query the run's status while it's underway, e.g. for an interactive
+    /// frontend. If [None], nobody can.
+    ///
+    /// Please see [status] for details.
+    pub status: Option<StatusHandle>,
+
+    /// A report in which to record which declared states each host doesn't meet. If [Some], the
+    /// run checks every host against its manifests instead of changing anything.
+    ///
+    /// Please see [drift] for details.
+    pub check: Option<DriftReport>,
+}
+
+impl RunOptions {
+    /// Returns the connection settings for `host`: its overrides, if any, merged over
+    /// [RunOptions::connection].
+    pub fn connection_for(&self, host: &str) -> HostConfig {
+        match self.hosts.get(host) {
+            Some(overrides) => self.connection.clone().merge(overrides.clone()),
+            None => self.connection.clone(),
+        }
+    }
+
+    /// Returns the [RunOptions] that a [Config] asks for, e.g. as loaded by [Config::load], with
+    /// every other option at its default.
+    pub fn from_config(config: Config) -> Self {
+        let connection = config.connection();
+        RunOptions {
+            login_key: config.login_key,
+            action_key: config.action_key,
+            known_hosts: config.known_hosts,
+            host_key_checking: config.host_key_checking,
+            connection,
+            hosts: config.hosts,
+            concurrency: config.concurrency,
+            pipeline: config.pipeline,
+            confirm: config.confirm.unwrap_or_default(),
+            on_failure: config.on_failure.unwrap_or_default(),
+            audit_log: config.audit_log,
+            json_log: config.json_log,
+            syslog: config.syslog.unwrap_or_default(),
+            html_report: config.html_report,
+            metrics: config.metrics,
+            artifacts: config.artifacts,
+            history: config.history,
+            facts_cache: config.facts_cache,
+            facts_ttl: config.facts_ttl,
+            inventory: config.inventory.unwrap_or_default(),
+            #[cfg(feature = "ec2")]
+            ec2: config.ec2,
+            dns: config.dns,
+            pre_run: config.pre_run.unwrap_or_default(),
+            post_run: config.post_run.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
 /// Runs a [Plan] on each of the [Plan]'s hosts in parallel.
 ///
 /// If a host is unreachable, it will simply be skipped; the [Plan] will still run to completion on
@@ -24,7 +373,8 @@ pub const ACTION_SIGNING_KEY: &str = "action";
 ///
 /// Similarly, if a host encounters an error, either due to a connection issue or an [Action] that
 /// fails (e.g. an [Action::Command] that returns a non-zero exit code), that host will execute no
-/// further [Action]s, but other hosts will run to completion.
+/// further [Action]s, but other hosts will run to completion, unless [RunOptions::on_failure]
+/// says to stop them, too.
 ///
 /// # Returns
 ///
@@ -32,10 +382,363 @@ pub const ACTION_SIGNING_KEY: &str = "action";
 /// `(host, error)`. Hosts that do not appear in this error return have successfully run their
 /// portion of the [Plan].
 ///
+/// If Sira itself fails while running a host's portion of the [Plan], e.g. because of a bug that
+/// panics, that host's error is a [TaskError], and the other hosts still run to completion. If the
+/// run is cancelled with [RunOptions::cancel], each host that hadn't finished fails with
+/// [Cancelled].
+///
 /// [Action]: crate::core::Action
 /// [Action::Command]: crate::core::Action::Command
 pub async fn run_plan(plan: Plan) -> Result<(), Vec<(String, anyhow::Error)>> {
-    _run_plan(plan, ConnectionManager, Reporter).await
+    run_plan_with(plan, RunOptions::default()).await
+}
+
+/// Same as [run_plan], but customized by [RunOptions].
+///
+/// Options that block, e.g. hooks, history, logs, and terminal output, require a multi-threaded
+/// Tokio runtime. Without them, any runtime works.
+pub async fn run_plan_with(
+    plan: Plan,
+    options: RunOptions,
+) -> Result<(), Vec<(String, anyhow::Error)>> {
+    if options.dashboard {
+        return dashboard::run(plan, options).await;
+    }
+    _run_plan_with(plan, options, None::<()>).await
+}
+
+/// Same as [run_plan_with], but reports to `ui` instead of the terminal, e.g. for a graphical or
+/// web frontend.
+///
+/// `ui` replaces [RunOptions::output], [RunOptions::progress], [RunOptions::dashboard], and
+/// [RunOptions::verbosity], and it answers when an action requires confirmation. Every other
+/// option applies as usual, e.g. logs and webhooks. Please see [Report] for how to write a
+/// frontend.
+///
+/// If `ui` panics, the host that it was handling fails with [TaskError::EmbedderPanicked].
+pub async fn run_plan_with_ui<U: Report + Clone + Send + 'static>(
+    plan: Plan,
+    options: RunOptions,
+    ui: U,
+) -> Result<(), Vec<(String, anyhow::Error)>> {
+    _run_plan_with(plan, options, Some(Embedded(ui))).await
+}
+
+/// Same as [run_plan_with], but reports progress as a [Stream] of [Event]s rather than on stdout,
+/// for async programs that drive Sira themselves.
+///
+/// Returns a future that runs `plan`, which does nothing until polled, and the [Events] that it
+/// reports. Both must be polled, e.g. by spawning the future and then reading the stream, but
+/// since events are buffered, it's also fine to await the future before reading them. The stream
+/// ends once the future completes.
+///
+/// [Events] replace [RunOptions::output], [RunOptions::progress], [RunOptions::dashboard], and
+/// [RunOptions::verbosity], so nothing is printed, except by a JSON log on stdout. Actions that
+/// require confirmation are declined, with an
+/// [EventKind::ConfirmationDeclined](event_stream::EventKind) event. Every other option applies as
+/// usual, e.g. logs and webhooks.
+///
+/// [Event]: event_stream::Event
+/// [Events]: event_stream::Events
+/// [Stream]: futures_core::Stream
+pub fn run_plan_async(
+    plan: Plan,
+    options: RunOptions,
+) -> (
+    impl Future<Output = Result<(), Vec<(String, anyhow::Error)>>> + Send,
+    event_stream::Events,
+) {
+    let (events, receiver) = EventStream::channel();
+    (_run_plan_with(plan, options, Some(events)), receiver)
+}
+
+/// Does the work of [run_plan_with] and [run_plan_with_ui]. If `ui` is given, reports to it instead
+/// of the terminal.
+async fn _run_plan_with<U: Report + Clone + Send + 'static>(
+    plan: Plan,
+    options: RunOptions,
+    ui: Option<U>,
+) -> Result<(), Vec<(String, anyhow::Error)>> {
+    let mut run = Run::new(&options);
+    let result = run_attempt(&mut run, plan, options, ui).await;
+    run.finish(result.as_ref().err().map(Vec::as_slice).unwrap_or_default());
+    result
+}
+
+/// What happens once per run, however many attempts the run takes, e.g. when the user retries hosts
+/// from the [dashboard]: the hooks, the history entry, and the notifications that the run finished.
+/// Each attempt is a [run_attempt].
+struct Run {
+    /// The commands that run before the first attempt.
+    pre_run: Vec<String>,
+
+    /// The commands that run after the last attempt.
+    post_run: Vec<String>,
+
+    /// Notifies [RunOptions::webhooks] of each attempt's actions and of how the run finished.
+    notifier: Option<Notifier>,
+
+    /// Counts each attempt's actions and writes [RunOptions::metrics] once the run finishes.
+    metrics: Option<Metrics>,
+
+    /// Collects each attempt's results and emails them once the run finishes.
+    email: Option<EmailNotifier>,
+
+    /// Records the run in [RunOptions::history].
+    history: Option<History>,
+
+    /// Every host in the run, once the run has started.
+    hosts: Option<Vec<String>>,
+}
+
+impl Run {
+    /// Creates a run that hasn't started yet.
+    fn new(options: &RunOptions) -> Self {
+        Run {
+            pre_run: options.pre_run.clone(),
+            post_run: options.post_run.clone(),
+            notifier: (!options.webhooks.is_empty())
+                .then(|| Notifier::new(options.webhooks.clone())),
+            metrics: options.metrics.as_ref().map(Metrics::new),
+            email: options.email.clone().map(EmailNotifier::new),
+            history: options.history.as_ref().map(History::new),
+            hosts: None,
+        }
+    }
+
+    /// Runs the pre-run hooks and records the start of the run of `plan` on `hosts` in the
+    /// history, unless the run has already started.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a hook fails or the history can't be written. The run hasn't started
+    /// then, so the next attempt tries again.
+    fn start(&mut self, plan: &Plan, hosts: &[String]) -> anyhow::Result<()> {
+        if self.hosts.is_some() {
+            return Ok(());
+        }
+        // Hooks and history block, and a single-threaded runtime, e.g. an embedder's, can't block
+        // in place, so only block if there's something to do.
+        if !self.pre_run.is_empty() || self.history.is_some() {
+            task::block_in_place(|| {
+                hooks::pre_run(&self.pre_run)?;
+                match &self.history {
+                    Some(history) => history
+                        .start(plan, hosts)
+                        .context("could not record run in history"),
+                    None => Ok(()),
+                }
+            })?;
+        }
+        self.hosts = Some(hosts.to_vec());
+        Ok(())
+    }
+
+    /// Sends the notifications that the run finished with `errors`, records its end in the history,
+    /// and runs the post-run hooks, if the run started.
+    fn finish(self, errors: &[(String, anyhow::Error)]) {
+        let Some(hosts) = self.hosts else {
+            return;
+        };
+        if let Some(metrics) = self.metrics {
+            // The run is over, so there's nowhere left to report this but the terminal.
+            if let Err(e) = task::block_in_place(|| metrics.finished(&hosts, errors)) {
+                eprintln!("Warning: {e:#}");
+            }
+        }
+        if let Some(notifier) = self.notifier {
+            task::block_in_place(|| notifier.finished(&hosts, errors));
+        }
+        if let Some(email) = self.email {
+            if let Err(e) = task::block_in_place(|| email.finished(&hosts, errors)) {
+                eprintln!("Warning: could not send email: {e:#}");
+            }
+        }
+        if let Some(history) = self.history {
+            if let Err(e) = task::block_in_place(|| history.finish(errors)) {
+                eprintln!("Warning: could not record end of run in history: {e:#}");
+            }
+        }
+        if !self.post_run.is_empty() {
+            task::block_in_place(|| hooks::post_run(&self.post_run, &hosts, errors));
+        }
+    }
+}
+
+/// Runs `plan` once as part of `run`, starting `run` if this is its first attempt. Please see
+/// [_run_plan_with].
+async fn run_attempt<U: Report + Clone + Send + 'static>(
+    run: &mut Run,
+    mut plan: Plan,
+    options: RunOptions,
+    ui: Option<U>,
+) -> Result<(), Vec<(String, anyhow::Error)>> {
+    // Until manifests that select hosts by fact have their hosts, nothing knows where the plan
+    // runs. Hosts whose facts couldn't be gathered fail, unless the plan names them anyway, in which
+    // case running them reports the problem.
+    let unselected = match facts::select_hosts(&mut plan, &options).await {
+        Ok(unselected) => unselected,
+        Err(e) => {
+            let message = format!("{e:#}");
+            return Err(plan
+                .hosts()
+                .into_iter()
+                .map(|host| (host, anyhow::anyhow!("{message}")))
+                .collect());
+        }
+    };
+    let selected = plan.hosts();
+    let unselected: Vec<_> = unselected
+        .into_iter()
+        .filter(|(host, _)| selected.binary_search(host).is_err())
+        .collect();
+
+    let connection_manager = connection_manager(&options);
+    let audit_log = options
+        .audit_log
+        .as_ref()
+        .map(|path| AuditLog::new(path, options.action_key.clone()));
+    let json_log = options.json_log.as_ref().map(JsonLog::new);
+    let syslog = options.syslog.then_some(Syslog);
+    let html_report = options.html_report.as_ref().map(HtmlReport::new);
+    let artifacts = options.artifacts.as_ref().map(Artifacts::new);
+    let mut log_sinks = options.log_sinks.clone();
+    if let Some(history) = &run.history {
+        log_sinks.push(history.clone());
+    }
+    let sinks = (!log_sinks.is_empty()).then(|| SinkLogger::new(log_sinks));
+
+    // JSON on stdout replaces the usual output, which would garble it.
+    let json_on_stdout = json_log.as_ref().is_some_and(JsonLog::is_stdout);
+    let (terminal, progress, events, ui) =
+        match (ui, options.output, json_on_stdout, options.progress) {
+            (Some(ui), _, _, _) => (None, None, None, Some(ui)),
+            (None, OutputFormat::Json, _, _) => (None, None, Some(EventStream::default()), None),
+            (None, OutputFormat::Text, true, _) => (None, None, None, None),
+            (None, OutputFormat::Text, false, true) => {
+                (None, Some(ProgressReporter::new(&plan)), None, None)
+            }
+            (None, OutputFormat::Text, false, false) => (
+                Some(Reporter::new(options.verbosity, options.color)),
+                None,
+                None,
+                None,
+            ),
+        };
+    let logs = (audit_log, (json_log, (syslog, (artifacts, sinks))));
+    let summaries = (
+        html_report,
+        (
+            run.notifier.clone(),
+            (
+                run.metrics.clone(),
+                (run.email.clone(), options.check.clone()),
+            ),
+        ),
+    );
+    let reporter = ((terminal, (progress, (events, ui))), (logs, summaries));
+
+    let mut hosts = plan.hosts();
+    hosts.extend(unselected.iter().map(|(host, _)| host.clone()));
+    hosts.sort();
+    if let Err(e) = run.start(&plan, &hosts) {
+        // Nothing ran, so every host failed the same way.
+        let message = format!("{e:#}");
+        return Err(hosts
+            .into_iter()
+            .map(|host| (host, anyhow::anyhow!("{message}")))
+            .collect());
+    }
+    let mut result = _run_plan(plan, connection_manager, reporter, options).await;
+    if !unselected.is_empty() {
+        let mut errors = result.err().unwrap_or_default();
+        errors.extend(unselected);
+        result = Err(errors);
+    }
+    result
+}
+
+/// An error that stopped Sira's own task for a host, rather than an error from the host.
+///
+/// Sira runs each host in its own task. If that task fails, e.g. because of a bug in Sira, in a
+/// [Report], or in a [ManageClient] that panics, Sira reports a [TaskError] for the host instead of
+/// crashing, and other hosts run to completion. Embedding programs can find it with
+/// [anyhow::Error::downcast_ref].
+#[derive(Debug)]
+pub enum TaskError {
+    /// The task panicked, with the given message, if the panic had one.
+    Panicked(Option<String>),
+
+    /// A [Report] or [ManageClient] that the program running Sira passed in, e.g. to
+    /// [run_plan_with_ui] or [embed::run], panicked, with the given message, if the panic had one.
+    EmbedderPanicked(Option<String>),
+
+    /// The task was cancelled before it finished.
+    Cancelled,
+}
+
+impl From<task::JoinError> for TaskError {
+    fn from(error: task::JoinError) -> Self {
+        if !error.is_panic() {
+            return TaskError::Cancelled;
+        }
+        match error.into_panic().downcast::<EmbedderPanic>() {
+            Ok(panic) => TaskError::EmbedderPanicked(panic.0),
+            Err(payload) => TaskError::Panicked(panic_message(payload)),
+        }
+    }
+}
+
+/// Only a panic in Sira itself is a bug in Sira.
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Panicked(Some(message)) => write!(
+                f,
+                "Sira's task for this host panicked: {message}. Please report this bug!"
+            ),
+            TaskError::Panicked(None) => {
+                write!(
+                    f,
+                    "Sira's task for this host panicked. Please report this bug!"
+                )
+            }
+            TaskError::EmbedderPanicked(Some(message)) => {
+                write!(
+                    f,
+                    "The program running Sira panicked on this host: {message}"
+                )
+            }
+            TaskError::EmbedderPanicked(None) => {
+                write!(f, "The program running Sira panicked on this host")
+            }
+            TaskError::Cancelled => write!(f, "Sira's task for this host was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for TaskError {}
+
+/// Returns the message of a panic with the given payload, if it had one.
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> Option<String> {
+    match payload.downcast::<String>() {
+        Ok(message) => Some(*message),
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string()),
+    }
+}
+
+/// Returns a [ConnectionManager] that connects to managed nodes as `options` say.
+fn connection_manager(options: &RunOptions) -> ConnectionManager {
+    let known_hosts = options.known_hosts.clone().or_else(|| {
+        let installed = crypto::known_hosts_path();
+        installed.is_file().then_some(installed)
+    });
+    ConnectionManager::new(options.login_key.clone(), known_hosts)
+        .with_host_key_checking(options.host_key_checking)
+        .with_connections(options.connection.clone(), options.hosts.clone())
+        .with_check(options.check.is_some())
 }
 
 /// Provides dependency injection for unit-testing [run_plan] without SSH, stdout, or stderr.
@@ -47,33 +750,118 @@ async fn _run_plan<
     plan: Plan,
     connection_manager: CM,
     reporter: R,
+    options: RunOptions,
 ) -> Result<(), Vec<(String, anyhow::Error)>> {
     let mut host_plans = JoinSet::new();
 
-    for host in plan.hosts() {
-        let host_plan = plan.plan_for(&host).unwrap().into_iter();
+    // The status counts finished actions from the same reports as everything else.
+    let run_status = options.status.clone();
+    let mut reporter = (reporter, run_status.clone());
+
+    // The first host to fail stops the rest if the options ask for all or nothing.
+    let abort = (options.on_failure == OnFailure::Abort).then(CancelToken::new);
+
+    // Hosts beyond the concurrency limit wait for a permit before connecting.
+    let limit = options.concurrency.map(|n| Arc::new(Semaphore::new(n)));
+
+    // Remembers which task runs which host, in case a task fails before returning its host.
+    let mut tasks = HashMap::new();
+
+    // Hosts that verify their sira-client share one checksum of each binary.
+    let expected_clients = ExpectedClients::default();
+
+    // Every host's plan shares one copy of the manifests and tasks.
+    let hosts = plan.hosts();
+    let plan = SharedPlan::from(plan);
+    if let Some(run_status) = &run_status {
+        run_status.start(hosts.iter().map(|host| {
+            let actions = plan
+                .plan_for(host)
+                .map_or(0, |host_plan| match options.check {
+                    Some(_) => host_plan.count(),
+                    None => host_plan.verifications().len() + host_plan.count(),
+                });
+            (host.clone(), actions)
+        }));
+    }
+
+    // A frontend that can't hear about the plan as a whole still hears about its hosts, so this
+    // doesn't stop the run.
+    let plan_id = NEXT_PLAN_ID.fetch_add(1, Ordering::Relaxed);
+    let host_count = hosts.len();
+    if let Err(e) = reporter.plan_started(plan_id, host_count).await {
+        eprintln!("Warning: could not report start of plan: {e}");
+    }
+
+    for host in hosts {
+        let Some(host_plan) = plan.plan_for(&host) else {
+            continue;
+        };
         let cm = connection_manager.clone();
         let rep = reporter.clone();
-        let _ = host_plans.spawn(async move {
-            let status = run_host_plan(host.clone(), host_plan, cm, rep).await;
-            (host, status)
+        let opts = options.clone();
+        let expected = expected_clients.clone();
+        let limit = limit.clone();
+        let task_host = host.clone();
+        let abort = abort.clone();
+        let handle = host_plans.spawn(async move {
+            let cancel = opts.cancel.clone();
+            let run_status = opts.status.clone();
+            let run = async {
+                // The semaphore is never closed, so acquiring a permit can't fail.
+                let _permit = match limit {
+                    Some(limit) => limit.acquire_owned().await.ok(),
+                    None => None,
+                };
+                if let Some(run_status) = &opts.status {
+                    run_status.activate(&task_host);
+                }
+                run_host_plan(task_host.clone(), host_plan, cm, rep, opts, expected).await
+            };
+
+            // Dropping the host's run on cancellation also drops, and so kills, whatever it was
+            // waiting on, e.g. the SSH command for an action.
+            let status = tokio::select! {
+                biased;
+                () = until_cancelled(cancel.as_ref()) => Err(Cancelled.into()),
+                () = until_cancelled(abort.as_ref()) => Err(Aborted.into()),
+                status = run => status,
+            };
+            if let (Err(_), Some(abort)) = (&status, &abort) {
+                abort.cancel();
+            }
+            if let Some(run_status) = run_status {
+                run_status.finish(&task_host, status.is_ok());
+            }
+            (task_host, status)
         });
+        let _ = tasks.insert(handle.id(), host);
     }
 
     let mut errors = Vec::new();
-    while let Some(join_result) = host_plans.join_next().await {
-        if let Err(err) = join_result {
-            if err.is_panic() {
-                panic::resume_unwind(err.into_panic());
-            } else {
-                panic!("Tokio task failed to execute to completion; this should be impossible");
+    while let Some(join_result) = host_plans.join_next_with_id().await {
+        match join_result {
+            Ok((_, (_, Ok(())))) => (),
+            Ok((_, (host, Err(err)))) => errors.push((host, err)),
+            Err(err) => {
+                if let Some(abort) = &abort {
+                    abort.cancel();
+                }
+                let host = tasks.remove(&err.id()).unwrap_or_default();
+                if let Some(run_status) = &run_status {
+                    run_status.finish(&host, false);
+                }
+                errors.push((host, TaskError::from(err).into()));
             }
         }
+    }
 
-        let (host, status) = join_result.unwrap();
-        if let Err(err) = status {
-            errors.push((host, err));
-        }
+    // The hosts are done, so there's nowhere left to report this but the terminal.
+    if let Err(e) = reporter.plan_finished(plan_id, host_count).await {
+        eprintln!("Warning: could not report end of plan: {e}");
+    }
+    if let Err(e) = reporter.finished().await {
+        eprintln!("Warning: could not finish reporting: {e}");
     }
 
     match errors.len() {
@@ -82,38 +870,394 @@ async fn _run_plan<
     }
 }
 
+/// Waits until `token` is cancelled, or forever if there's no token.
+async fn until_cancelled(token: Option<&CancelToken>) {
+    match token {
+        Some(token) => token.cancelled().await,
+        None => future::pending().await,
+    }
+}
+
 /// Runs a [Plan] on a single host via [HostPlanIntoIter].
-async fn run_host_plan<C: ClientInterface, CM: ManageClient<C>, R: Report + Clone>(
+async fn run_host_plan<C: ClientInterface + Send, CM: ManageClient<C>, R: Report + Clone>(
     host: String,
     plan: HostPlanIntoIter,
     mut connection_manager: CM,
     mut reporter: R,
+    options: RunOptions,
+    expected_clients: ExpectedClients,
 ) -> anyhow::Result<()> {
-    let mut client = connection_manager.connect(&host).await?;
+    // Hardware security keys need a touch for every login and signature, and most devices can
+    // only handle one request at a time, so hosts take turns prompting and waiting for a touch.
+    static SECURITY_KEY: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let action_key = options
+        .action_key
+        .clone()
+        .unwrap_or_else(|| config::config_dir().join(KEY_DIR).join(ACTION_SIGNING_KEY));
+    let sign_needs_touch = crypto::key_file_is_security_key(action_key);
 
-    for action in plan {
-        let action = action.compile();
-        let yaml = serde_yaml::to_string(&action).unwrap();
+    // Only SSH logs in, so only SSH needs a touch to log in.
+    let settings = options.connection_for(&host);
+    let login_needs_touch = settings.transport.unwrap_or_default() == Transport::Ssh
+        && login_key_is_security_key(&options);
 
-        // Lazily sign yaml only if needed.
-        fn sign(yaml: &str) -> anyhow::Result<Option<Vec<u8>>> {
-            match crypto::sign(yaml.as_bytes(), ACTION_SIGNING_KEY)? {
-                SigningOutcome::Signed(sig) => Ok(Some(sig)),
-                SigningOutcome::KeyNotFound => Ok(None),
+    // A host that's switched off gets one chance to wake up. Please see [wake].
+    let mut wake_up = match settings.wake_on_lan.unwrap_or_default() {
+        true => settings.mac_address.as_deref(),
+        false => None,
+    };
+    let mut client = loop {
+        reporter.network(&host, "Connecting").await?;
+        let connecting = Instant::now();
+        let connected = match login_needs_touch {
+            true => {
+                let _guard = SECURITY_KEY.lock().await;
+                reporter.touch_required(&host, "log in").await?;
+                connection_manager.connect(&host).await
             }
+            false => connection_manager.connect(&host).await,
+        };
+        match (connected, wake_up.take()) {
+            (Ok(client), _) => {
+                reporter.network(&host, "Connected").await?;
+                reporter.connected(&host, connecting.elapsed()).await?;
+                break client;
+            }
+            (Err(e), None) => return Err(e),
+            (Err(e), Some(mac_address)) => {
+                let message = format!("Unreachable; waking {mac_address} with Wake-on-LAN");
+                reporter.network(&host, &message).await?;
+                // Keep the connection error, so that the host still counts as unreachable.
+                if let Err(wake_error) = wake::wake(mac_address).await {
+                    return Err(e.context(format!("Wake-on-LAN failed: {wake_error:#}")));
+                }
+                let delay = settings.wake_delay.unwrap_or(wake::DEFAULT_WAKE_DELAY);
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+            }
+        }
+    };
+
+    // Refuse to send anything to a sira-client that isn't the one we'd deploy.
+    if settings.verify_client.unwrap_or_default() {
+        reporter.network(&host, "Verifying sira-client").await?;
+        let installed = client.installed_client().await?;
+        let expected = expected_clients.get(&installed.platform).await?;
+        deploy_client::verify_client(&installed, &expected)?;
+    }
+    let progress = client.take_progress();
+
+    // Independent actions may start before the ones before them finish, each on a lane of its own.
+    let mut lanes = Lanes::new(client, progress, options.pipeline.unwrap_or(1));
+    let mut previous: Option<Arc<HostAction>> = None;
+
+    // Once every action has run, the checks that tasks list under `verify` run as a phase of their
+    // own, one at a time. A check-mode run changes nothing, so there's nothing to verify.
+    let verifications = match options.check {
+        Some(_) => vec![],
+        None => plan.verifications(),
+    };
+    let mut verifying = false;
+    let actions = plan
+        .map(|host_action| (host_action, false))
+        .chain(verifications.into_iter().map(|check| (check, true)));
+
+    for (host_action, check) in actions {
+        if check && !verifying {
+            lanes.finish_all(&host, &mut reporter).await?;
+            reporter.verifying(&host).await?;
+            verifying = true;
         }
 
-        reporter.starting(&host, &action).await?;
+        // Every upload goes through the same transfer file on the host, so uploads never overlap.
+        let independent = !check
+            && lanes.limit > 1
+            && host_action.task().independent
+            && !matches!(host_action.action(), Action::Upload { .. });
 
-        use Action::*;
-        let output = match &action {
-            Command(_) => client.command(&yaml, sign(&yaml)?).await?,
-            LineInFile { .. } => client.line_in_file(&yaml, sign(&yaml)?).await?,
-            Script { .. } => client.script(&yaml, sign(&yaml)?).await?,
-            Upload { from, .. } => client.upload(from, &yaml, sign(&yaml)?).await?,
+        // An action joins those in flight only if they're all from the same independent task.
+        let joins = independent
+            && previous
+                .as_ref()
+                .is_some_and(|previous| ptr::eq(previous.task(), host_action.task()));
+        if !joins {
+            lanes.finish_all(&host, &mut reporter).await?;
+        }
+        if independent {
+            lanes.fork_if_busy();
+        }
+        if lanes.idle.is_empty() {
+            lanes.finish_next(&host, &mut reporter).await?;
+        }
+
+        let prepared = async {
+            // The client needs the real values of sensitive variables, but nothing else should
+            // see them, so everything we report uses the redacted action and output instead.
+            let redactor = host_action.redactor();
+            // Checks change nothing, so only --confirm all asks about them, and in check mode,
+            // nothing changes at all.
+            let needs_confirmation = options.check.is_none()
+                && ((host_action.task().confirm && !check) || options.confirm == Confirmation::All);
+            let limits = host_action.task().limits.clone();
+            let sandbox = host_action.task().sandbox.clone();
+            let user = host_action.task().user.clone();
+            let escalate = host_action.task().escalate;
+            let dpkg_lock_timeout = host_action.task().dpkg_lock_timeout;
+            reporter
+                .action_source(
+                    &host,
+                    &host_action.manifest().name,
+                    &host_action.task().name,
+                )
+                .await?;
+            let action = host_action.compile();
+            let redacted_action = redactor.redact_action(&action);
+
+            if needs_confirmation && !reporter.confirm(&host, &redacted_action).await? {
+                bail!("Action declined: {}", title(&redacted_action));
+            }
+
+            let yaml = Envelope::new(action.clone())
+                .with_limits(limits)
+                .with_sandbox(sandbox)
+                .with_user(user)
+                .with_escalate(escalate)
+                .with_dpkg_lock_timeout(dpkg_lock_timeout)
+                .with_check(options.check.is_some())
+                .to_yaml();
+
+            reporter.starting(&host, &redacted_action).await?;
+
+            let signature = match sign_needs_touch {
+                true => {
+                    let _guard = SECURITY_KEY.lock().await;
+                    reporter.touch_required(&host, "sign this action").await?;
+                    sign_as_controller(yaml.as_bytes(), options.action_key.as_deref())?
+                }
+                false => sign_as_controller(yaml.as_bytes(), options.action_key.as_deref())?,
+            };
+
+            if let Action::Upload { from, .. } = &redacted_action {
+                reporter
+                    .network(&host, &format!("Uploading {from}"))
+                    .await?;
+            }
+            Ok((action, redacted_action, redactor, yaml, signature))
+        }
+        .await;
+        let (action, redacted_action, redactor, yaml, signature) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                lanes.finish_rest(&host, &mut reporter).await;
+                return Err(e);
+            }
         };
 
-        reporter.report(&host, &action, &output).await?;
+        let (client, progress) = lanes.idle.pop().expect("a lane should be idle");
+        lanes.in_flight.push_back(InFlight {
+            dispatch: Some(Box::pin(dispatch(
+                client,
+                host.clone(),
+                action.clone(),
+                yaml,
+                signature,
+            ))),
+            action,
+            redacted_action,
+            redactor,
+            // Start timing after signing, which may have waited for a touch.
+            started: Instant::now(),
+            progress,
+            output: None,
+        });
+        if !independent {
+            lanes
+                .finish_next(&host, &mut reporter)
+                .await
+                .map_err(|e| match check {
+                    true => {
+                        let message = format!("Verification failed: {e}");
+                        e.context(message)
+                    }
+                    false => e,
+                })?;
+        }
+        previous = Some(host_action);
+    }
+    lanes.finish_all(&host, &mut reporter).await
+}
+
+/// The clients on which a host runs its actions, and the actions in flight on them.
+///
+/// Each lane is a client and the receiver for its progress. A host starts with one lane and forks
+/// more from it, up to [RunOptions::pipeline], so that independent actions can run side by side.
+/// However many actions are in flight, each finishes, and is reported, in the order it started.
+struct Lanes<C, F: Future> {
+    /// The lanes that aren't running an action.
+    idle: Vec<(C, Option<UnboundedReceiver<Progress>>)>,
+
+    /// The actions in flight, oldest first.
+    in_flight: VecDeque<InFlight<F>>,
+
+    /// How many lanes there are, whether idle or running an action.
+    count: usize,
+
+    /// The most lanes there may be.
+    limit: usize,
+}
+
+/// An action in flight on one of a host's [Lanes].
+struct InFlight<F: Future> {
+    /// Sends the action and hands back its lane's client once it finishes, until it finishes.
+    dispatch: Option<Pin<Box<F>>>,
+
+    /// The action as sent, with the real values of sensitive variables.
+    action: Action,
+
+    /// The action as reported, with sensitive values masked.
+    redacted_action: Action,
+
+    /// Masks sensitive values in the action's progress and output.
+    redactor: Redactor,
+
+    /// When the action started.
+    started: Instant,
+
+    /// The receiver for progress from the action's lane.
+    progress: Option<UnboundedReceiver<Progress>>,
+
+    /// What [InFlight::dispatch] returned and how long the action took, once it has finished.
+    output: Option<(F::Output, Duration)>,
+}
+
+impl<C, F> Lanes<C, F>
+where
+    C: ClientInterface,
+    F: Future<Output = (C, anyhow::Result<(Output, Option<u64>)>)>,
+{
+    /// Starts with one idle lane, which may grow into as many as `limit` lanes.
+    fn new(client: C, progress: Option<UnboundedReceiver<Progress>>, limit: usize) -> Self {
+        Lanes {
+            idle: vec![(client, progress)],
+            in_flight: VecDeque::new(),
+            count: 1,
+            limit: limit.max(1),
+        }
+    }
+
+    /// If only one lane is idle, forks another from it, unless there are already as many lanes as
+    /// allowed or the client can't fork.
+    fn fork_if_busy(&mut self) {
+        if self.idle.len() != 1 || self.count >= self.limit {
+            return;
+        }
+        if let Some(mut client) = self.idle[0].0.fork() {
+            let progress = client.take_progress();
+            self.idle.push((client, progress));
+            self.count += 1;
+        }
+    }
+
+    /// Waits for every action in flight to finish, in order. If any fails, waits for the rest
+    /// anyway and then returns the first error.
+    async fn finish_all<R: Report>(&mut self, host: &str, reporter: &mut R) -> anyhow::Result<()> {
+        while !self.in_flight.is_empty() {
+            self.finish_next(host, reporter).await?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the oldest action in flight to finish and reports it. If it failed, waits for the
+    /// rest, too, and then returns its error.
+    async fn finish_next<R: Report>(&mut self, host: &str, reporter: &mut R) -> anyhow::Result<()> {
+        let result = self.finish_oldest(host, reporter).await;
+        if result.is_err() {
+            self.finish_rest(host, reporter).await;
+        }
+        result
+    }
+
+    /// Waits for every action in flight to finish after something has already failed, so that
+    /// they're reported rather than cut off. Their errors don't matter: the host has failed.
+    async fn finish_rest<R: Report>(&mut self, host: &str, reporter: &mut R) {
+        while !self.in_flight.is_empty() {
+            let _ = self.finish_oldest(host, reporter).await;
+        }
+    }
+
+    /// Waits for the oldest action in flight to finish, reporting progress from every action in
+    /// flight meanwhile, and then reports how it went. Returns an error if the action failed.
+    async fn finish_oldest<R: Report>(
+        &mut self,
+        host: &str,
+        reporter: &mut R,
+    ) -> anyhow::Result<()> {
+        if self.in_flight.is_empty() {
+            return Ok(());
+        }
+
+        loop {
+            let event = future::poll_fn(|cx| {
+                for (index, action) in self.in_flight.iter_mut().enumerate() {
+                    if let Some(receiver) = &mut action.progress {
+                        if let Poll::Ready(Some(event)) = receiver.poll_recv(cx) {
+                            return Poll::Ready(Some((index, event)));
+                        }
+                    }
+                }
+                for action in self.in_flight.iter_mut() {
+                    if let Some(dispatch) = &mut action.dispatch {
+                        if let Poll::Ready(output) = dispatch.as_mut().poll(cx) {
+                            action.dispatch = None;
+                            action.output = Some((output, action.started.elapsed()));
+                        }
+                    }
+                }
+                match self.in_flight[0].output {
+                    Some(_) => Poll::Ready(None),
+                    None => Poll::Pending,
+                }
+            })
+            .await;
+            let Some((index, event)) = event else {
+                break;
+            };
+            let action = &self.in_flight[index];
+            let message = action.redactor.redact_str(&event.message);
+            reporter
+                .progress(host, &action.redacted_action, &message)
+                .await?;
+        }
+
+        let mut finished = self
+            .in_flight
+            .pop_front()
+            .expect("an action should be in flight");
+        while let Some(event) = finished.progress.as_mut().and_then(|p| p.try_recv().ok()) {
+            let message = finished.redactor.redact_str(&event.message);
+            reporter
+                .progress(host, &finished.redacted_action, &message)
+                .await?;
+        }
+        let ((client, output), elapsed) = finished.output.expect("the action should have finished");
+        self.idle.push((client, finished.progress));
+        let InFlight {
+            action,
+            redacted_action,
+            redactor,
+            ..
+        } = finished;
+        let (output, transferred) = output?;
+
+        let output = Output {
+            status: output.status,
+            stdout: redactor.redact_bytes(&output.stdout),
+            stderr: redactor.redact_bytes(&output.stderr),
+        };
+        if let (true, Some(bytes)) = (output.status.success(), transferred) {
+            reporter.transferred(host, &redacted_action, bytes).await?;
+        }
+        reporter.timing(host, &redacted_action, elapsed).await?;
+        reporter.report(host, &redacted_action, &output).await?;
 
         if !output.status.success() {
             // Adapted from crate::run_plan::report::_report()
@@ -121,11 +1265,153 @@ async fn run_host_plan<C: ClientInterface, CM: ManageClient<C>, R: Report + Clon
                 Some(i) => format!("exit code {i}"),
                 None => "error".to_string(),
             };
-            let action = title(&action);
-            bail!("Action exited with {exit_code_message}: {action}");
+            let action = title(&redacted_action);
+            let message = format!("Action exited with {exit_code_message}: {action}");
+
+            // Attach sira-client's typed error, if it reported one, so that callers can tell what
+            // kind of failure this was.
+            return match ClientError::from_output(&output) {
+                Some(error) => Err(anyhow::Error::new(error).context(message)),
+                None => Err(anyhow::Error::msg(message)),
+            };
+        }
+
+        if let Action::Upload {
+            from, overwrite, ..
+        } = &action
+        {
+            check_upload_checksum(from, *overwrite, &output.stdout)
+                .with_context(|| format!("Upload failed: {}", title(&redacted_action)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends `action` to `host` on `client`, and then hands `client` back for the next action.
+///
+/// Alongside the action's output, returns how many bytes it transferred to the managed node, if it
+/// transfers files, i.e. the size of the file that an upload sent: the encrypted file, if the
+/// upload was encrypted, or else the source file, or 0 if it's gone since.
+async fn dispatch<C: ClientInterface + Send>(
+    mut client: C,
+    host: String,
+    action: Action,
+    yaml: String,
+    signature: Option<Vec<u8>>,
+) -> (C, anyhow::Result<(Output, Option<u64>)>) {
+    use Action::*;
+    let output = async {
+        Ok(match &action {
+            Command(_) => (client.command(&yaml, signature).await?, None),
+            ControllerKey { .. } => (client.controller_key(&yaml, signature).await?, None),
+            Custom { .. } => (client.custom(&yaml, signature).await?, None),
+            KernelModule { .. } => (client.kernel_module(&yaml, signature).await?, None),
+            LineInFile { .. } => (client.line_in_file(&yaml, signature).await?, None),
+            Script { .. } => (client.script(&yaml, signature).await?, None),
+            Swap { .. } => (client.swap(&yaml, signature).await?, None),
+            Upload {
+                from,
+                encrypt: true,
+                transfer_permissions,
+                ..
+            } => {
+                // Encrypt to a temporary file and upload that instead, cleaning up either way.
+                let (file, encrypted) = crate::client::mktemp()?;
+                drop(file);
+                let output = match crypto::recipients_path(&host)
+                    .and_then(|recipients| crypto::encrypt_file(from, recipients, &encrypted))
+                {
+                    Ok(()) => {
+                        client
+                            .upload(
+                                &encrypted,
+                                transfer_permissions.as_deref(),
+                                &yaml,
+                                signature,
+                            )
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                let transferred = file_size(&encrypted);
+                let _ = fs::remove_file(&encrypted);
+                (output?, Some(transferred))
+            }
+            Upload {
+                from,
+                transfer_permissions,
+                ..
+            } => {
+                let output = client
+                    .upload(from, transfer_permissions.as_deref(), &yaml, signature)
+                    .await?;
+                (output, Some(file_size(from)))
+            }
+        })
+    }
+    .await;
+    // If the connection dropped, the host is unreachable now, not failed.
+    (client, output.map_err(ConnectionError::from_ssh))
+}
+
+/// Checks the checksum that `sira-client` reported for an uploaded file against the source file.
+///
+/// `sira-client` reports no checksum if `overwrite` is false and the destination already existed.
+fn check_upload_checksum(from: &str, overwrite: bool, stdout: &[u8]) -> anyhow::Result<()> {
+    let stdout = String::from_utf8_lossy(stdout);
+    let reported = stdout
+        .lines()
+        .rev()
+        .find_map(|line| line.strip_prefix(UPLOAD_CHECKSUM_PREFIX));
+
+    match reported {
+        None if !overwrite => Ok(()),
+        None => bail!("sira-client did not report a checksum for the uploaded file"),
+        Some(reported) => {
+            let expected = crypto::sha256_file(from)?;
+            if reported.trim() != expected {
+                bail!(
+                    "checksum mismatch: {from} has SHA-256 {expected}, but the managed node \
+                    reported {}",
+                    reported.trim(),
+                );
+            }
+            Ok(())
         }
     }
-    Ok(())
+}
+
+/// Returns the size of the file at `path`, or 0 if it's gone.
+fn file_size(path: impl AsRef<Path>) -> u64 {
+    fs::metadata(path).map_or(0, |m| m.len())
+}
+
+/// Returns whether Sira will log in with a hardware security key.
+///
+/// Checks [RunOptions::login_key], if provided, or else Sira's conventional login key,
+/// `~/.ssh/sira`. If OpenSSH picks some other key (e.g. via `~/.ssh/config`), Sira can't tell.
+fn login_key_is_security_key(options: &RunOptions) -> bool {
+    let login_key = options
+        .login_key
+        .clone()
+        .or_else(|| home::home_dir().map(|home| home.join(".ssh").join(rotate_keys::LOGIN_KEY)));
+    login_key.is_some_and(crypto::key_file_is_security_key)
+}
+
+/// Signs `buf` with `key_file`, if provided, or else with the installed action key, if any.
+///
+/// Returns [None] if `key_file` is [None] and the action key is not installed.
+pub(crate) fn sign_as_controller(
+    buf: &[u8],
+    key_file: Option<&Path>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    if let Some(key_file) = key_file {
+        return Ok(Some(crypto::sign_with_key_file(buf, key_file)?));
+    }
+    match crypto::sign(buf, ACTION_SIGNING_KEY)? {
+        SigningOutcome::Signed(sig) => Ok(Some(sig)),
+        SigningOutcome::KeyNotFound => Ok(None),
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,99 @@
+//! Builds one-off [Plan]s for `sira adhoc`, which runs a single action on a set of hosts without a
+//! manifest file.
+//!
+//! The resulting [Plan] runs through [run_plan_with](super::run_plan_with) like any other, so the
+//! action is signed, logged, and reported as usual.
+
+use super::rotate_keys::built_in_plan;
+use crate::core::{Action, Plan};
+use anyhow::bail;
+use regex::Regex;
+
+/// The name of the manifest and task in an ad-hoc [Plan].
+pub const ADHOC: &str = "Ad hoc";
+
+/// Returns a [Plan] that runs `action` on each of `hosts`.
+pub fn plan(hosts: &[String], action: Action) -> Plan {
+    built_in_plan(ADHOC, hosts, vec![action])
+}
+
+/// Returns an [Action::Upload] that installs `from` on the control node at `to` on managed nodes,
+/// with the same defaults as in a task file, i.e. owned by root and replacing any existing file.
+pub fn upload(from: &str, to: &str) -> Action {
+    Action::Upload {
+        from: from.to_string(),
+        to: to.to_string(),
+        user: "root".to_string(),
+        group: "root".to_string(),
+        permissions: None,
+        overwrite: true,
+        encrypt: false,
+        seuser: None,
+        serole: None,
+        setype: None,
+        acl: vec![],
+        transfer_permissions: None,
+        xattrs: Default::default(),
+        capabilities: None,
+    }
+}
+
+/// Returns the hosts that `patterns` select, in order and without duplicates.
+///
+/// A pattern without wildcards names a host directly, whether or not it appears in `known`. A
+/// pattern with wildcards, i.e. `*` for any run of characters and `?` for any single character,
+/// selects every host in `known` that it matches, in the order of `known`.
+///
+/// # Errors
+///
+/// Returns an error if a pattern with wildcards matches none of `known`, since that's most likely
+/// a mistake, or if `patterns` selects no hosts at all.
+pub fn select_hosts(patterns: &[&str], known: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut hosts: Vec<String> = vec![];
+    for pattern in patterns {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            continue;
+        }
+        if !pattern.contains(['*', '?']) {
+            if !hosts.iter().any(|host| host == pattern) {
+                hosts.push(pattern.to_string());
+            }
+            continue;
+        }
+
+        let regex = wildcard_regex(pattern);
+        let mut matched = false;
+        for host in known.iter().filter(|host| regex.is_match(host)) {
+            matched = true;
+            if !hosts.contains(host) {
+                hosts.push(host.clone());
+            }
+        }
+        if !matched {
+            bail!("{pattern:?} matches none of the known hosts");
+        }
+    }
+
+    if hosts.is_empty() {
+        bail!("no hosts selected");
+    }
+    Ok(hosts)
+}
+
+/// Converts a pattern with `*` and `?` wildcards into an equivalent [Regex].
+fn wildcard_regex(pattern: &str) -> Regex {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).expect("escaped wildcard pattern should be a valid regex")
+}
+
+#[cfg(test)]
+mod test;
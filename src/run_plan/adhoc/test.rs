@@ -0,0 +1,70 @@
+use super::*;
+
+fn known() -> Vec<String> {
+    ["web-1", "db-1", "web-2", "web-10"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+mod select_hosts {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards_in_known_order() {
+        assert_eq!(
+            vec!["web-1", "web-2", "web-10"],
+            select_hosts(&["web-*"], &known()).unwrap(),
+        );
+        assert_eq!(
+            vec!["web-1", "web-2"],
+            select_hosts(&["web-?"], &known()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn accepts_unknown_hosts_by_name() {
+        assert_eq!(
+            vec!["mail", "db-1"],
+            select_hosts(&["mail", "d?-1"], &known()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn removes_duplicates() {
+        assert_eq!(
+            vec!["web-2", "web-1", "web-10"],
+            select_hosts(&["web-2", "web-*", "web-1"], &known()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn treats_other_characters_literally() {
+        assert!(select_hosts(&["web.*"], &known()).is_err());
+    }
+
+    #[test]
+    fn rejects_wildcards_that_match_nothing() {
+        assert!(select_hosts(&["mail-*"], &known()).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_selection() {
+        assert!(select_hosts(&[], &known()).is_err());
+        assert!(select_hosts(&[" "], &known()).is_err());
+    }
+}
+
+mod plan {
+    use super::*;
+
+    #[test]
+    fn runs_action_on_hosts() {
+        let hosts = vec!["web-1".to_string(), "web-2".to_string()];
+        let action = Action::Command(vec!["uptime".to_string()]);
+        let plan = plan(&hosts, action.clone());
+        assert_eq!(hosts, plan.hosts());
+        assert_eq!(ADHOC, plan.manifests[0].name);
+        assert_eq!(vec![action], plan.manifests[0].include[0].actions);
+    }
+}
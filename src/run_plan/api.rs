@@ -0,0 +1,670 @@
+//! Lets deployment pipelines and chat bots drive `sira daemon` over HTTP, without shelling into the
+//! control node: submit a run, ask how it's going, and follow its reports as they happen.
+//!
+//! The API is off unless the `api` table of `sira.toml` turns it on. It needs a file that holds a
+//! secret token, which every request must present, and it listens on [DEFAULT_LISTEN] unless
+//! `listen` says otherwise, e.g.:
+//!
+//! ```toml
+//! [api]
+//! listen = "127.0.0.1:8470"
+//! token_file = "/etc/sira/api-token"
+//! ```
+//!
+//! Every request carries the token as `Authorization: Bearer <token>`, and every response is JSON,
+//! except for the stream of events:
+//!
+//! - `POST /runs` submits a [RunRequest], e.g. `{"manifests": ["site.yaml"], "profile": "prod"}`,
+//!   and answers `202 Accepted` with the new run's ID, e.g. `{"id": 3}`. Runs take turns with each
+//!   other and with scheduled runs, so the run may wait in the [RunState::Queued] state at first.
+//! - `GET /runs` lists the most recent runs, newest first, as [RunInfo]s, including scheduled
+//!   runs.
+//! - `GET /runs/<id>` returns the [RunInfo] of one run, including the status of each of its hosts
+//!   while it's underway. Please see [status](super::status).
+//! - `GET /runs/<id>/events` streams the run's reports, from the start, as [JSON Lines] of the
+//!   same [Event]s that `--output json` prints, and ends once the run does.
+//!
+//! The daemon remembers the last [MAX_RUNS] runs. The API speaks plain HTTP, so to reach it from
+//! other machines, put it behind a reverse proxy that adds TLS. The token lets anybody who holds
+//! it run any manifest file that the daemon can read, so guard it as closely as the action key.
+//!
+//! [JSON Lines]: https://jsonlines.org
+
+use super::drift::DriftReport;
+use super::event_stream::{Event, EventStream};
+use super::schedule::ScheduledRun;
+use super::status::{RunStatus, StatusHandle};
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+use tokio::time;
+
+/// Where the API listens unless [ApiConfig::listen] says otherwise.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8470";
+
+/// How many runs the daemon remembers. Once there are more, it forgets the oldest finished ones.
+pub const MAX_RUNS: usize = 100;
+
+/// The most bytes that a request's line and headers may take up.
+const MAX_HEAD: usize = 16 * 1024;
+
+/// The most bytes that a request's body may take up.
+const MAX_BODY: usize = 64 * 1024;
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings for the API, in the `api` table of `sira.toml`. Please see the
+/// [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiConfig {
+    /// The address and port on which to listen. Defaults to [DEFAULT_LISTEN].
+    pub listen: Option<SocketAddr>,
+
+    /// A file that holds the token that every request must present.
+    pub token_file: PathBuf,
+}
+
+impl ApiConfig {
+    /// Returns the address and port on which to listen.
+    pub fn listen(&self) -> SocketAddr {
+        self.listen
+            .unwrap_or_else(|| DEFAULT_LISTEN.parse().expect("default should be valid"))
+    }
+}
+
+/// Reads the token from `path`, ignoring white space around it.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read or holds no token.
+pub fn read_token(path: &Path) -> anyhow::Result<String> {
+    let token = fs::read_to_string(path)
+        .with_context(|| format!("could not read API token file: {}", path.display()))?;
+    match token.trim() {
+        "" => bail!("API token file is empty: {}", path.display()),
+        token => Ok(token.to_string()),
+    }
+}
+
+/// A run that a client asks for with `POST /runs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunRequest {
+    /// The manifest files to run, as paths on the control node.
+    pub manifests: Vec<String>,
+
+    /// The profile in `sira.toml` whose settings the run uses, if any.
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    /// Whether to check each host for drift instead of changing anything. Please see
+    /// [drift](super::drift).
+    #[serde(default)]
+    pub check: bool,
+}
+
+impl RunRequest {
+    /// Returns an error if the run can't ever start, e.g. because it has no manifest files.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.manifests.is_empty() {
+            bail!("manifests must list at least one manifest file");
+        }
+        Ok(())
+    }
+}
+
+impl From<&ScheduledRun> for RunRequest {
+    fn from(run: &ScheduledRun) -> Self {
+        RunRequest {
+            manifests: run.manifests.clone(),
+            profile: run.profile.clone(),
+            check: run.check.unwrap_or_default(),
+        }
+    }
+}
+
+/// Where a run is in its life.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    /// Waiting for its turn.
+    Queued,
+
+    /// Underway.
+    Running,
+
+    /// Over, whether or not every host succeeded.
+    Finished,
+
+    /// Never started, e.g. because a manifest file was invalid.
+    NotStarted,
+}
+
+/// What the API tells clients about a run.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunInfo {
+    /// The run's ID, which counts up from 1 for as long as the daemon runs.
+    pub id: u64,
+
+    /// Who asked for the run: `api`, or the name of a schedule.
+    pub source: String,
+
+    /// What was asked for.
+    pub request: RunRequest,
+
+    /// Where the run is in its life.
+    pub state: RunState,
+
+    /// When the run was submitted, in RFC 3339 format.
+    pub submitted: String,
+
+    /// When the run started, if it has.
+    pub started: Option<String>,
+
+    /// When the run finished, if it has.
+    pub finished: Option<String>,
+
+    /// The status of each host, once the run has started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<RunStatus>,
+
+    /// Maps each host that failed to its error, once the run has finished.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub failed: BTreeMap<String, String>,
+
+    /// In check mode, the hosts that don't meet their manifests, once the run has finished.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drifted: Vec<String>,
+
+    /// Why the run never started, if it didn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Every run that the daemon remembers, shared between the daemon, which starts and finishes
+/// them, and the API, which reports on them. Clones share the same runs.
+#[derive(Clone, Debug)]
+pub struct RunRegistry {
+    /// The runs, by ID.
+    runs: Arc<Mutex<Runs>>,
+
+    /// Bumped whenever a run changes or reports an event, so that streams can wait for news.
+    changed: Arc<watch::Sender<()>>,
+}
+
+/// The contents of a [RunRegistry].
+#[derive(Debug, Default)]
+struct Runs {
+    /// The ID of the most recent run.
+    last_id: u64,
+
+    /// Each run that the daemon remembers, by ID.
+    entries: BTreeMap<u64, Entry>,
+}
+
+/// A run in a [RunRegistry].
+#[derive(Debug)]
+struct Entry {
+    /// What the API reports, except for the status.
+    info: RunInfo,
+
+    /// The status of the run's hosts, once it has started.
+    status: Option<StatusHandle>,
+
+    /// Every event that the run has reported so far.
+    events: Vec<Event>,
+
+    /// Whether the run will report no further events.
+    events_done: bool,
+}
+
+impl Default for RunRegistry {
+    fn default() -> Self {
+        RunRegistry {
+            runs: Arc::default(),
+            changed: Arc::new(watch::channel(()).0),
+        }
+    }
+}
+
+impl RunRegistry {
+    /// Creates a [RunRegistry] with no runs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a new run of `request`, asked for by `source`, that's waiting for its turn. Returns
+    /// its ID.
+    pub fn submit(&self, source: &str, request: RunRequest) -> u64 {
+        let mut runs = self.runs.lock().unwrap();
+        runs.last_id += 1;
+        let id = runs.last_id;
+        let info = RunInfo {
+            id,
+            source: source.to_string(),
+            request,
+            state: RunState::Queued,
+            submitted: now(),
+            started: None,
+            finished: None,
+            status: None,
+            failed: BTreeMap::new(),
+            drifted: vec![],
+            error: None,
+        };
+        let entry = Entry {
+            info,
+            status: None,
+            events: vec![],
+            events_done: false,
+        };
+        runs.entries.insert(id, entry);
+
+        // Forget the oldest runs that are over, but never one that's still to come.
+        let excess = runs.entries.len().saturating_sub(MAX_RUNS);
+        let forgotten: Vec<u64> = runs
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_over())
+            .map(|(id, _)| *id)
+            .take(excess)
+            .collect();
+        for id in forgotten {
+            runs.entries.remove(&id);
+        }
+        drop(runs);
+        self.changed.send_replace(());
+        id
+    }
+
+    /// Records that the run with ID `id` is starting. Returns the [StatusHandle] and
+    /// [EventStream] through which the run should report, e.g. in
+    /// [RunOptions::status](super::RunOptions::status) and to
+    /// [run_plan_with_ui](super::run_plan_with_ui).
+    pub fn start(&self, id: u64) -> (StatusHandle, EventStream) {
+        let status = StatusHandle::new();
+        let (stream, mut events) = EventStream::channel();
+        self.update(id, |entry| {
+            entry.info.state = RunState::Running;
+            entry.info.started = Some(now());
+            entry.status = Some(status.clone());
+        });
+
+        let registry = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                registry.update(id, |entry| entry.events.push(event));
+            }
+            registry.update(id, |entry| entry.events_done = true);
+        });
+        (status, stream)
+    }
+
+    /// Records that the run with ID `id` is over, with `errors` for the hosts that failed and, in
+    /// check mode, its `drift`.
+    pub fn finish(&self, id: u64, errors: &[(String, anyhow::Error)], drift: Option<&DriftReport>) {
+        let failed = errors
+            .iter()
+            .map(|(host, error)| (host.clone(), format!("{error:#}")))
+            .collect();
+        let drifted = drift.map_or(vec![], |drift| {
+            drift
+                .query()
+                .into_iter()
+                .filter(|(_, host)| !host.unmet.is_empty())
+                .map(|(host, _)| host)
+                .collect()
+        });
+        self.update(id, |entry| {
+            entry.info.state = RunState::Finished;
+            entry.info.finished = Some(now());
+            entry.info.failed = failed;
+            entry.info.drifted = drifted;
+        });
+    }
+
+    /// Records that the run with ID `id` never started because of `error`.
+    pub fn not_started(&self, id: u64, error: &anyhow::Error) {
+        self.update(id, |entry| {
+            entry.info.state = RunState::NotStarted;
+            entry.info.finished = Some(now());
+            entry.info.error = Some(format!("{error:#}"));
+            entry.events_done = true;
+        });
+    }
+
+    /// Returns what the API tells clients about the run with ID `id`, if the daemon remembers it.
+    pub fn get(&self, id: u64) -> Option<RunInfo> {
+        self.runs.lock().unwrap().entries.get(&id).map(Entry::info)
+    }
+
+    /// Returns what the API tells clients about every run that the daemon remembers, newest
+    /// first.
+    pub fn list(&self) -> Vec<RunInfo> {
+        let runs = self.runs.lock().unwrap();
+        runs.entries.values().rev().map(Entry::info).collect()
+    }
+
+    /// Applies `update` to the run with ID `id`, if the daemon remembers it, and tells streams.
+    fn update(&self, id: u64, update: impl FnOnce(&mut Entry)) {
+        if let Some(entry) = self.runs.lock().unwrap().entries.get_mut(&id) {
+            update(entry);
+        }
+        self.changed.send_replace(());
+    }
+
+    /// Writes the events of the run with ID `id` to `stream` as JSON Lines, from event `from` on,
+    /// as they happen, until the run reports no further events or is forgotten.
+    async fn stream_events(&self, id: u64, mut from: usize, stream: &mut TcpStream) {
+        let mut changed = self.changed.subscribe();
+        loop {
+            changed.borrow_and_update();
+            let (events, done) = {
+                let runs = self.runs.lock().unwrap();
+                let Some(entry) = runs.entries.get(&id) else {
+                    return;
+                };
+                let events = entry.events.get(from..).unwrap_or_default().to_vec();
+                (events, entry.events_done)
+            };
+            from += events.len();
+            let mut lines = String::new();
+            for event in events {
+                if let Ok(json) = serde_json::to_string(&event) {
+                    lines.push_str(&json);
+                    lines.push('\n');
+                }
+            }
+            if stream.write_all(lines.as_bytes()).await.is_err() {
+                return;
+            }
+            if done || changed.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Entry {
+    /// Returns what the API tells clients about this run.
+    fn info(&self) -> RunInfo {
+        RunInfo {
+            status: self.status.as_ref().map(StatusHandle::query),
+            ..self.info.clone()
+        }
+    }
+
+    /// Returns whether the run is over and has reported every event.
+    fn is_over(&self) -> bool {
+        matches!(self.info.state, RunState::Finished | RunState::NotStarted) && self.events_done
+    }
+}
+
+/// Answers requests on `listener` until the program exits, authenticating each with `token`.
+///
+/// Each run that a client submits is recorded in `registry` and its ID sent to `submissions`, so
+/// that the daemon starts it in turn.
+pub async fn serve(
+    listener: TcpListener,
+    token: String,
+    registry: RunRegistry,
+    submissions: UnboundedSender<u64>,
+) {
+    let token = Arc::new(token);
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("Warning: API could not accept a connection: {e}");
+                continue;
+            }
+        };
+        let (token, registry, submissions) = (token.clone(), registry.clone(), submissions.clone());
+        tokio::spawn(async move {
+            let response = match time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+                Ok(Ok(request)) => route(&request, &token, &registry, &submissions),
+                Ok(Err(e)) => error(400, e),
+                Err(_) => error(408, anyhow!("timed out waiting for the request")),
+            };
+            let _ = respond(&mut stream, response, &registry).await;
+        });
+    }
+}
+
+/// A request, as far as the API cares.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Request {
+    /// The method, e.g. `GET`.
+    method: String,
+
+    /// The path, without any query string, e.g. `/runs/3`.
+    path: String,
+
+    /// The value of the `Authorization` header, if any.
+    authorization: Option<String>,
+
+    /// The body, if any.
+    body: Vec<u8>,
+}
+
+/// What to answer a request with.
+#[derive(Clone, Debug, PartialEq)]
+enum Response {
+    /// A status code and a JSON body.
+    Json(u16, serde_json::Value),
+
+    /// The events of the run with this ID, as they happen.
+    Events(u64),
+}
+
+/// Reads a request from `stream`.
+async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Request> {
+    let mut buffer = Vec::new();
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        if buffer.len() > MAX_HEAD {
+            bail!("request headers are too large");
+        }
+        let mut chunk = [0; 4096];
+        match stream.read(&mut chunk).await? {
+            0 => bail!("connection closed before the end of the request headers"),
+            read => buffer.extend_from_slice(&chunk[..read]),
+        }
+    };
+    let (mut request, length) = parse_head(&buffer[..head_end])?;
+    if length > MAX_BODY {
+        bail!("request body is too large");
+    }
+    let mut body = buffer.split_off(head_end + 4);
+    body.truncate(length);
+    let received = body.len();
+    body.resize(length, 0);
+    stream.read_exact(&mut body[received..]).await?;
+    request.body = body;
+    Ok(request)
+}
+
+/// Parses the line and headers of a request, without the blank line that ends them. Returns the
+/// request, without its body, and the length of its body.
+fn parse_head(head: &[u8]) -> anyhow::Result<(Request, usize)> {
+    let head = std::str::from_utf8(head).context("request headers are not valid UTF-8")?;
+    let mut lines = head.split("\r\n");
+    let line = lines.next().unwrap_or_default();
+    let parts: Vec<&str> = line.split(' ').collect();
+    let [method, target, version] = parts[..] else {
+        bail!("invalid request line: {line:?}");
+    };
+    if !version.starts_with("HTTP/1.") {
+        bail!("unsupported HTTP version: {version}");
+    }
+    let path = target.split('?').next().unwrap_or_default();
+
+    let mut authorization = None;
+    let mut length = 0;
+    for header in lines {
+        let Some((name, value)) = header.split_once(':') else {
+            bail!("invalid header: {header:?}");
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "authorization" => authorization = Some(value.to_string()),
+            "content-length" => {
+                length = value
+                    .parse()
+                    .with_context(|| format!("invalid Content-Length: {value:?}"))?;
+            }
+            "transfer-encoding" => bail!("chunked request bodies are not supported"),
+            _ => (),
+        }
+    }
+    let request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        authorization,
+        body: vec![],
+    };
+    Ok((request, length))
+}
+
+/// Decides how to answer `request`, submitting a run if it asks for one.
+fn route(
+    request: &Request,
+    token: &str,
+    registry: &RunRegistry,
+    submissions: &UnboundedSender<u64>,
+) -> Response {
+    if !authorized(request, token) {
+        return error(401, anyhow!("missing or invalid token"));
+    }
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let id = |id: &str| {
+        id.parse::<u64>()
+            .ok()
+            .filter(|id| registry.get(*id).is_some())
+    };
+    match (request.method.as_str(), &segments[..]) {
+        ("POST", ["runs"]) => {
+            let run = serde_json::from_slice::<RunRequest>(&request.body)
+                .context("invalid run request")
+                .and_then(|run| run.validate().map(|()| run));
+            match run {
+                Ok(run) => {
+                    let id = registry.submit("api", run);
+                    if submissions.send(id).is_err() {
+                        let error = anyhow!("the daemon is shutting down");
+                        registry.not_started(id, &error);
+                        return self::error(503, error);
+                    }
+                    Response::Json(202, json!({ "id": id }))
+                }
+                Err(e) => error(400, e),
+            }
+        }
+        ("GET", ["runs"]) => Response::Json(200, json!(registry.list())),
+        ("GET", ["runs", run]) => match id(run).and_then(|id| registry.get(id)) {
+            Some(info) => Response::Json(200, json!(info)),
+            None => error(404, anyhow!("no run with ID {run}")),
+        },
+        ("GET", ["runs", run, "events"]) => match id(run) {
+            Some(id) => Response::Events(id),
+            None => error(404, anyhow!("no run with ID {run}")),
+        },
+        (_, ["runs"] | ["runs", _] | ["runs", _, "events"]) => {
+            error(405, anyhow!("method not allowed"))
+        }
+        _ => error(404, anyhow!("not found")),
+    }
+}
+
+/// Returns whether `request` presents `token`.
+fn authorized(request: &Request, token: &str) -> bool {
+    let presented = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .trim();
+
+    // Compare every byte, so that the time taken doesn't tell how much of the token was right.
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Returns a JSON response with status `code` that explains `error`.
+fn error(code: u16, error: anyhow::Error) -> Response {
+    Response::Json(code, json!({ "error": format!("{error:#}") }))
+}
+
+/// Writes `response` to `stream`, streaming events from `registry` if it asks for them.
+async fn respond(
+    stream: &mut TcpStream,
+    response: Response,
+    registry: &RunRegistry,
+) -> std::io::Result<()> {
+    match response {
+        Response::Json(code, body) => {
+            let body = body.to_string();
+            let mut head = format!(
+                "HTTP/1.1 {code} {}\r\nContent-Type: application/json\r\n\
+                Content-Length: {}\r\nConnection: close\r\n",
+                reason(code),
+                body.len() + 1,
+            );
+            if code == 401 {
+                head.push_str("WWW-Authenticate: Bearer\r\n");
+            }
+            stream
+                .write_all(format!("{head}\r\n{body}\n").as_bytes())
+                .await?;
+        }
+        Response::Events(id) => {
+            // Without a length, the stream ends when the connection closes.
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\n\
+                Cache-Control: no-cache\r\nConnection: close\r\n\r\n";
+            stream.write_all(head.as_bytes()).await?;
+            registry.stream_events(id, 0, stream).await;
+        }
+    }
+    stream.shutdown().await
+}
+
+/// Returns the reason phrase for an HTTP status `code` that the API uses.
+fn reason(code: u16) -> &'static str {
+    match code {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Returns the current time in RFC 3339 format.
+fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+#[cfg(test)]
+mod test;
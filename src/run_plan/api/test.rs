@@ -0,0 +1,289 @@
+use super::*;
+use crate::run_plan::report::Report;
+use tokio::sync::mpsc;
+
+const TOKEN: &str = "s3cret";
+
+fn run_request() -> RunRequest {
+    RunRequest {
+        manifests: vec!["site.yaml".to_string()],
+        profile: None,
+        check: false,
+    }
+}
+
+fn request(method: &str, path: &str, authorization: Option<&str>, body: &str) -> Request {
+    Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        authorization: authorization.map(str::to_string),
+        body: body.as_bytes().to_vec(),
+    }
+}
+
+/// Routes `method` `path` with the right token and `body`, and returns the response along with the
+/// IDs of any runs that it submitted.
+fn send(registry: &RunRegistry, method: &str, path: &str, body: &str) -> (Response, Vec<u64>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let bearer = format!("Bearer {TOKEN}");
+    let response = route(
+        &request(method, path, Some(&bearer), body),
+        TOKEN,
+        registry,
+        &sender,
+    );
+    let mut submitted = vec![];
+    while let Ok(id) = receiver.try_recv() {
+        submitted.push(id);
+    }
+    (response, submitted)
+}
+
+/// Returns the status code of `response`.
+fn code(response: &Response) -> u16 {
+    match response {
+        Response::Json(code, _) => *code,
+        Response::Events(_) => 200,
+    }
+}
+
+mod parse_head {
+    use super::*;
+
+    #[test]
+    fn reads_method_path_and_headers() {
+        let head = b"POST /runs?wait=no HTTP/1.1\r\nHost: sira\r\n\
+            authorization: Bearer abc\r\nContent-Length: 12";
+        let (parsed, length) = parse_head(head).unwrap();
+        assert_eq!(request("POST", "/runs", Some("Bearer abc"), ""), parsed);
+        assert_eq!(12, length);
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        for head in [
+            &b"GET /runs"[..],
+            b"GET /runs HTTP/2",
+            b"GET /runs HTTP/1.1\r\nno colon",
+            b"GET /runs HTTP/1.1\r\nContent-Length: lots",
+            b"POST /runs HTTP/1.1\r\nTransfer-Encoding: chunked",
+        ] {
+            assert!(
+                parse_head(head).is_err(),
+                "{:?} should be invalid",
+                String::from_utf8_lossy(head),
+            );
+        }
+    }
+}
+
+mod route {
+    use super::*;
+
+    #[test]
+    fn requires_the_token() {
+        let registry = RunRegistry::new();
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        for authorization in [
+            None,
+            Some("Bearer wrong"),
+            Some("Bearer s3cre"),
+            Some(TOKEN),
+        ] {
+            let response = route(
+                &request("GET", "/runs", authorization, ""),
+                TOKEN,
+                &registry,
+                &sender,
+            );
+            assert_eq!(401, code(&response), "{authorization:?}");
+        }
+    }
+
+    #[test]
+    fn submits_runs() {
+        let registry = RunRegistry::new();
+        let body = r#"{"manifests": ["site.yaml"], "profile": "prod", "check": true}"#;
+        let (response, submitted) = send(&registry, "POST", "/runs", body);
+        assert_eq!(Response::Json(202, json!({ "id": 1 })), response);
+        assert_eq!(vec![1], submitted);
+
+        let run = registry.get(1).unwrap();
+        assert_eq!("api", run.source);
+        assert_eq!(RunState::Queued, run.state);
+        assert_eq!(Some("prod"), run.request.profile.as_deref());
+        assert!(run.request.check);
+    }
+
+    #[test]
+    fn rejects_invalid_runs() {
+        let registry = RunRegistry::new();
+        for body in [
+            "",
+            "{}",
+            r#"{"manifests": []}"#,
+            r#"{"manifests": ["site.yaml"], "hosts": ["web1"]}"#,
+        ] {
+            let (response, submitted) = send(&registry, "POST", "/runs", body);
+            assert_eq!(400, code(&response), "{body:?}");
+            assert!(submitted.is_empty());
+        }
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn finds_runs() {
+        let registry = RunRegistry::new();
+        registry.submit("nightly", run_request());
+        let (response, _) = send(&registry, "GET", "/runs/1", "");
+        let Response::Json(200, run) = response else {
+            panic!("unexpected response: {response:?}");
+        };
+        assert_eq!("nightly", run["source"]);
+        assert_eq!("queued", run["state"]);
+
+        let (response, _) = send(&registry, "GET", "/runs/1/events", "");
+        assert_eq!(Response::Events(1), response);
+    }
+
+    #[test]
+    fn reports_unknown_runs_and_routes() {
+        let registry = RunRegistry::new();
+        for path in ["/runs/1", "/runs/x", "/runs/1/events", "/hosts"] {
+            assert_eq!(404, code(&send(&registry, "GET", path, "").0), "{path}");
+        }
+        assert_eq!(405, code(&send(&registry, "DELETE", "/runs", "").0));
+    }
+}
+
+mod run_registry {
+    use super::*;
+
+    #[test]
+    fn lists_newest_first() {
+        let registry = RunRegistry::new();
+        registry.submit("api", run_request());
+        registry.submit("nightly", run_request());
+        let ids: Vec<u64> = registry.list().iter().map(|run| run.id).collect();
+        assert_eq!(vec![2, 1], ids);
+    }
+
+    #[test]
+    fn records_failures() {
+        let registry = RunRegistry::new();
+        let id = registry.submit("api", run_request());
+        let errors = vec![("web1".to_string(), anyhow!("connection refused"))];
+        registry.finish(id, &errors, None);
+
+        let run = registry.get(id).unwrap();
+        assert_eq!(RunState::Finished, run.state);
+        assert!(run.finished.is_some());
+        assert_eq!("connection refused", run.failed["web1"]);
+    }
+
+    #[test]
+    fn records_runs_that_never_started() {
+        let registry = RunRegistry::new();
+        let id = registry.submit("api", run_request());
+        registry.not_started(id, &anyhow!("no such file"));
+
+        let run = registry.get(id).unwrap();
+        assert_eq!(RunState::NotStarted, run.state);
+        assert_eq!(Some("no such file"), run.error.as_deref());
+    }
+
+    #[test]
+    fn forgets_the_oldest_finished_runs() {
+        let registry = RunRegistry::new();
+        let first = registry.submit("api", run_request());
+        for _ in 1..MAX_RUNS {
+            let id = registry.submit("api", run_request());
+            registry.not_started(id, &anyhow!("no such file"));
+        }
+        let last = registry.submit("api", run_request());
+
+        // The first run is still to come, so the next one goes instead.
+        assert_eq!(MAX_RUNS, registry.list().len());
+        assert!(registry.get(first).is_some());
+        assert!(registry.get(first + 1).is_none());
+        assert!(registry.get(last).is_some());
+    }
+}
+
+mod serve {
+    use super::*;
+
+    /// Sends `request` to `address` and returns the whole response.
+    async fn fetch(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn submits_and_streams_runs() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let registry = RunRegistry::new();
+        let (sender, mut submissions) = mpsc::unbounded_channel();
+        tokio::spawn(serve(listener, TOKEN.to_string(), registry.clone(), sender));
+
+        let body = r#"{"manifests": ["site.yaml"]}"#;
+        let response = fetch(
+            address,
+            &format!(
+                "POST /runs HTTP/1.1\r\nAuthorization: Bearer {TOKEN}\r\n\
+                Content-Length: {}\r\n\r\n{body}",
+                body.len(),
+            ),
+        )
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 202 Accepted\r\n"),
+            "{response}"
+        );
+        assert!(response.ends_with("\r\n\r\n{\"id\":1}\n"), "{response}");
+        let id = submissions.recv().await.unwrap();
+
+        // Follow the run while it reports an event and finishes.
+        let request =
+            format!("GET /runs/{id}/events HTTP/1.1\r\nAuthorization: Bearer {TOKEN}\r\n\r\n");
+        let events = tokio::spawn(async move { fetch(address, &request).await });
+        let (_status, mut stream) = registry.start(id);
+        stream.plan_started(7, 1).await.unwrap();
+        drop(stream);
+        registry.finish(id, &[], None);
+
+        let events = events.await.unwrap();
+        assert!(events.starts_with("HTTP/1.1 200 OK\r\n"), "{events}");
+        let (_, body) = events.split_once("\r\n\r\n").unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(1, lines.len(), "{body}");
+        assert!(lines[0].contains("plan_started"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn challenges_requests_without_the_token() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, _submissions) = mpsc::unbounded_channel();
+        tokio::spawn(serve(
+            listener,
+            TOKEN.to_string(),
+            RunRegistry::new(),
+            sender,
+        ));
+
+        let response = fetch(address, "GET /runs HTTP/1.1\r\n\r\n").await;
+        assert!(
+            response.starts_with("HTTP/1.1 401 Unauthorized\r\n"),
+            "{response}"
+        );
+        assert!(
+            response.contains("WWW-Authenticate: Bearer\r\n"),
+            "{response}"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Saves each action's captured output to its own file, so that nothing is lost when long output
+//! scrolls past.
+//!
+//! Each run gets its own directory, named for the time at which it started, with a subdirectory
+//! for each host. Each action's output goes to a file named for its position in the host's plan
+//! and the task that it came from, e.g.:
+//!
+//! ```text
+//! runs/2024-01-01T12-00-00/web1/001-Install-packages.log
+//! runs/2024-01-01T12-00-00/web1/002-Restart-app.log
+//! ```
+//!
+//! Each file begins with a short header describing the action and its outcome, followed by the
+//! action's stdout and stderr. Like everything else Sira reports, the action and output are
+//! redacted.
+
+use super::report::{title, Report, RunningAction, RunningActions};
+use crate::core::Action;
+use anyhow::Context;
+use async_trait::async_trait;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use tokio::task;
+
+/// The format of each run's directory name. Please see [chrono::format::strftime].
+pub const RUN_DIR_FORMAT: &str = "%Y-%m-%dT%H-%M-%S";
+
+/// Saves each action's captured output under a directory. Please see the
+/// [module documentation](self).
+///
+/// Clones share the same run directory.
+#[derive(Clone, Debug)]
+pub struct Artifacts {
+    /// The directory for this run, i.e. `<base>/<timestamp>`.
+    run_dir: PathBuf,
+
+    /// The source and number of each action in flight on each host.
+    running: Arc<Mutex<RunningActions>>,
+}
+
+impl Artifacts {
+    /// Creates an [Artifacts] that saves output for a run starting now under `base`.
+    ///
+    /// Does not touch the file system until the first action finishes.
+    pub fn new(base: impl AsRef<Path>) -> Self {
+        let timestamp = chrono::Local::now().format(RUN_DIR_FORMAT).to_string();
+        Artifacts {
+            run_dir: base.as_ref().join(timestamp),
+            running: Arc::default(),
+        }
+    }
+
+    /// Returns the directory for this run.
+    pub fn run_dir(&self) -> &Path {
+        &self.run_dir
+    }
+
+    /// Notes the manifest and task that the next action on `host` comes from.
+    pub(crate) fn set_source(&self, host: &str, manifest: &str, task: &str) {
+        self.running.lock().unwrap().source(host, manifest, task);
+    }
+
+    /// Saves the output of the oldest action in flight on `host`, returning the path of the new
+    /// file.
+    pub(crate) fn save(
+        &self,
+        host: &str,
+        action: &Action,
+        output: &Output,
+    ) -> anyhow::Result<PathBuf> {
+        let running = self.running.lock().unwrap().finish(host);
+        let (manifest, task, number) = match running {
+            Some(RunningAction {
+                manifest,
+                task,
+                number,
+                ..
+            }) => (manifest, task, number),
+            None => Default::default(),
+        };
+
+        let dir = self.run_dir.join(file_name_safe(host));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("could not create artifacts directory: {}", dir.display()))?;
+        let path = dir.join(format!("{number:03}-{}.log", file_name_safe(&task),));
+
+        fs::write(&path, contents(host, &manifest, &task, action, output))
+            .with_context(|| format!("could not write artifact: {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl Report for Artifacts {
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        self.set_source(host, manifest, task);
+        Ok(())
+    }
+
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        task::block_in_place(|| self.save(host, action, output))
+            .map(|_| ())
+            .map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+}
+
+/// Returns the contents of an action's artifact file.
+fn contents(host: &str, manifest: &str, task: &str, action: &Action, output: &Output) -> String {
+    let result = match (output.status.success(), output.status.code()) {
+        (true, _) => "success".to_string(),
+        (false, Some(code)) => format!("failure (exit code {code})"),
+        (false, None) => "failure".to_string(),
+    };
+
+    let mut contents = String::new();
+    // Writing to a String can't fail, so the results of writeln! are safe to ignore.
+    let _ = writeln!(contents, "Host: {host}");
+    let _ = writeln!(contents, "Manifest: {manifest}");
+    let _ = writeln!(contents, "Task: {task}");
+    let _ = writeln!(contents, "Action: {}", title(action));
+    let _ = writeln!(contents, "Result: {result}");
+    let _ = writeln!(contents, "\n--- stdout ---");
+    contents.push_str(&String::from_utf8_lossy(&output.stdout));
+    let _ = writeln!(contents, "\n--- stderr ---");
+    contents.push_str(&String::from_utf8_lossy(&output.stderr));
+    contents
+}
+
+/// Returns `name` with every character other than ASCII letters, digits, `.`, `_`, and `-`
+/// replaced with `-`, so that it's safe to use as a file name.
+fn file_name_safe(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "._-".contains(c) {
+            true => c,
+            false => '-',
+        })
+        .collect();
+    match safe.trim_start_matches('.') {
+        "" => "-".to_string(),
+        _ => safe,
+    }
+}
+
+#[cfg(test)]
+mod test;
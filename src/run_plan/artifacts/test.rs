@@ -0,0 +1,98 @@
+use super::*;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn output(code: i32, stdout: &str, stderr: &str) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: stderr.as_bytes().to_vec(),
+    }
+}
+
+fn action(command: &str) -> Action {
+    Action::Command(vec![command.to_string()])
+}
+
+mod save {
+    use super::*;
+
+    #[test]
+    fn numbers_files_per_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifacts = Artifacts::new(dir.path());
+        assert_eq!(dir.path(), artifacts.run_dir().parent().unwrap());
+
+        artifacts.set_source("web1", "Web servers", "Install packages");
+        let first = artifacts
+            .save(
+                "web1",
+                &action("apt-get install -y nginx"),
+                &output(0, "", ""),
+            )
+            .unwrap();
+        artifacts.set_source("web1", "Web servers", "Restart app");
+        let second = artifacts
+            .save("web1", &action("systemctl restart app"), &output(0, "", ""))
+            .unwrap();
+        artifacts.set_source("web2", "Web servers", "Install packages");
+        let other = artifacts
+            .save(
+                "web2",
+                &action("apt-get install -y nginx"),
+                &output(0, "", ""),
+            )
+            .unwrap();
+
+        let run_dir = artifacts.run_dir();
+        assert_eq!(run_dir.join("web1/001-Install-packages.log"), first);
+        assert_eq!(run_dir.join("web1/002-Restart-app.log"), second);
+        assert_eq!(run_dir.join("web2/001-Install-packages.log"), other);
+    }
+
+    #[test]
+    fn writes_header_and_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifacts = Artifacts::new(dir.path());
+        artifacts.set_source("web1", "Web servers", "Check app");
+        let path = artifacts
+            .save("web1", &action("false"), &output(1, "out\n", "err\n"))
+            .unwrap();
+
+        assert_eq!(
+            "Host: web1\n\
+            Manifest: Web servers\n\
+            Task: Check app\n\
+            Action: command: false\n\
+            Result: failure (exit code 1)\n\
+            \n\
+            --- stdout ---\n\
+            out\n\
+            \n\
+            --- stderr ---\n\
+            err\n",
+            fs::read_to_string(path).unwrap(),
+        );
+    }
+}
+
+mod file_name_safe {
+    use super::*;
+
+    #[test]
+    fn replaces_unsafe_characters() {
+        assert_eq!(
+            "Set-up-Alice-s-account",
+            file_name_safe("Set up Alice's account")
+        );
+        assert_eq!("a-b-c", file_name_safe("a/b\\c"));
+        assert_eq!("web1.example.com", file_name_safe("web1.example.com"));
+    }
+
+    #[test]
+    fn does_not_allow_relative_paths() {
+        assert_eq!("-", file_name_safe(".."));
+        assert_eq!("-", file_name_safe(""));
+        assert_eq!("..-..-etc", file_name_safe("../../etc"));
+    }
+}
@@ -0,0 +1,256 @@
+//! A signed, append-only audit log of every action that runs.
+//!
+//! The audit log is a YAML file with one document per action. Each document holds a [Record],
+//! serialized as a string exactly as it was signed, and a signature over that string made with
+//! the controller's action key:
+//!
+//! ```yaml
+//! ---
+//! record: |
+//!   timestamp: 2024-01-01T12:00:00.000000000-08:00
+//!   user: alice
+//!   host: web1
+//!   action:
+//!     command:
+//!     - systemctl restart app.service
+//!   exit_code: 0
+//!   success: true
+//!   previous: |
+//!     -----BEGIN SSH SIGNATURE-----
+//!     ...
+//! signature: |
+//!   -----BEGIN SSH SIGNATURE-----
+//!   ...
+//! ```
+//!
+//! Each record includes the signature of the record before it, forming a chain. Since every
+//! signature covers the previous signature, removing, reordering, or editing any record breaks
+//! the chain from that point on, which [verify_log] detects.
+
+use super::{sign_as_controller, ACTION_SIGNING_KEY};
+use crate::client;
+use crate::core::Action;
+use crate::crypto;
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use tokio::task;
+
+use super::report::Report;
+
+/// The information recorded about each action, as signed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// When the action finished, in RFC 3339 format.
+    pub timestamp: String,
+
+    /// The user on the control node who ran the action.
+    pub user: String,
+
+    /// The managed node on which the action ran.
+    pub host: String,
+
+    /// The action, after variable substitution.
+    pub action: Action,
+
+    /// The action's exit code, if any.
+    pub exit_code: Option<i32>,
+
+    /// Whether the action succeeded.
+    pub success: bool,
+
+    /// The signature of the previous record in the log, or [None] if this is the first record.
+    pub previous: Option<String>,
+}
+
+/// One document in the audit log: a serialized [Record] and its signature.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The [Record], serialized as YAML exactly as it was signed.
+    pub record: String,
+
+    /// The signature over [Entry::record].
+    pub signature: String,
+}
+
+/// Appends a signed [Entry] to an audit log for every action that runs.
+///
+/// Because [AuditLog] implements [Report], it runs alongside the usual reporting. The log file is
+/// opened when the first action is about to start, so a missing signing key or an unwritable log
+/// file stops each host before it runs anything. Clones share the same file and chain.
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    /// The path to the log file.
+    path: PathBuf,
+
+    /// The key used to sign records. If [None], records are signed with the installed action key.
+    key_file: Option<PathBuf>,
+
+    /// The open log file and the most recent signature, once opened.
+    state: Arc<Mutex<Option<State>>>,
+}
+
+#[derive(Debug)]
+struct State {
+    file: File,
+    previous: Option<String>,
+}
+
+impl AuditLog {
+    /// Creates an [AuditLog] that appends to `path` and signs with `key_file`, if provided, or else
+    /// the installed action key.
+    ///
+    /// Does not touch the file system; see [AuditLog].
+    pub fn new(path: impl AsRef<Path>, key_file: Option<PathBuf>) -> Self {
+        AuditLog {
+            path: path.as_ref().to_owned(),
+            key_file,
+            state: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Opens the log file, if it isn't open already, and picks up the chain where it left off.
+    pub(crate) fn open(&self) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.is_some() {
+            return Ok(());
+        }
+
+        if self.key_file.is_none()
+            && !crate::config::config_dir()
+                .join(crypto::KEY_DIR)
+                .join(ACTION_SIGNING_KEY)
+                .try_exists()?
+        {
+            bail!("the audit log requires an action key to sign its records");
+        }
+
+        let previous = match fs::read_to_string(&self.path) {
+            Ok(log) => parse_log(&log)?.pop().map(|entry| entry.signature),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("could not open audit log: {}", self.path.display()))?;
+
+        *state = Some(State { file, previous });
+        Ok(())
+    }
+
+    /// Signs and appends a record of an action's outcome.
+    pub(crate) fn append(
+        &self,
+        host: &str,
+        action: &Action,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        self.open()?;
+        let mut state = self.state.lock().unwrap();
+        let state = state.as_mut().unwrap();
+
+        let record = Record {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            user: client::whoami().to_string(),
+            host: host.to_string(),
+            action: action.clone(),
+            exit_code: output.status.code(),
+            success: output.status.success(),
+            previous: state.previous.clone(),
+        };
+        let record = serde_yaml::to_string(&record)?;
+        let signature = sign_as_controller(record.as_bytes(), self.key_file.as_deref())?
+            .ok_or_else(|| anyhow!("the audit log requires an action key to sign its records"))?;
+        let signature = String::from_utf8(signature).context("signature was not UTF-8")?;
+
+        let entry = serde_yaml::to_string(&Entry {
+            record,
+            signature: signature.clone(),
+        })?;
+        state.file.write_all(format!("---\n{entry}").as_bytes())?;
+        state.file.flush()?;
+        state.previous = Some(signature);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Report for AuditLog {
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        task::block_in_place(|| self.open()).map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        task::block_in_place(|| self.append(host, action, output))
+            .map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+}
+
+/// Parses the entries in an audit log without verifying them.
+pub fn parse_log(log: &str) -> anyhow::Result<Vec<Entry>> {
+    // An empty string still counts as one (empty) YAML document.
+    if log.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    serde_yaml::Deserializer::from_str(log)
+        .enumerate()
+        .map(|(i, document)| {
+            Entry::deserialize(document).with_context(|| format!("could not parse entry {}", i + 1))
+        })
+        .collect()
+}
+
+/// Verifies every signature in an audit log and checks that the records form an unbroken chain.
+///
+/// Signatures are checked against `allowed_signers_file` for `identity`, typically the action
+/// allowed signers file and `sira`, respectively.
+///
+/// Returns the verified records on success, or an error identifying the first bad entry.
+pub fn verify_log(
+    path: impl AsRef<Path>,
+    allowed_signers_file: impl AsRef<Path>,
+    identity: &str,
+) -> anyhow::Result<Vec<Record>> {
+    let path = path.as_ref();
+    let log = fs::read_to_string(path)
+        .with_context(|| format!("could not read audit log: {}", path.display()))?;
+
+    let mut records = vec![];
+    let mut previous = None;
+    for (i, entry) in parse_log(&log)?.into_iter().enumerate() {
+        let number = i + 1;
+
+        let (mut signature_file, signature_path) = client::mktemp()?;
+        signature_file.write_all(entry.signature.as_bytes())?;
+        drop(signature_file);
+        let verified = crypto::verify_with_allowed_signers_file(
+            entry.record.as_bytes(),
+            &signature_path,
+            &allowed_signers_file,
+            identity,
+        );
+        fs::remove_file(&signature_path)?;
+        verified.with_context(|| format!("bad signature on entry {number}"))?;
+
+        let record: Record = serde_yaml::from_str(&entry.record)
+            .with_context(|| format!("could not parse record in entry {number}"))?;
+        if record.previous != previous {
+            bail!("entry {number} does not follow the entry before it; the chain is broken");
+        }
+
+        previous = Some(entry.signature);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test;
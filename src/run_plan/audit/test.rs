@@ -0,0 +1,129 @@
+use super::*;
+use crate::config;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use tempfile::TempDir;
+
+fn allowed_signers_file() -> PathBuf {
+    config::config_dir()
+        .join(crypto::ALLOWED_SIGNERS_DIR)
+        .join("action")
+}
+
+fn output(code: i32) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: vec![],
+        stderr: vec![],
+    }
+}
+
+fn action(command: &str) -> Action {
+    Action::Command(vec![command.to_string()])
+}
+
+// Writes a log with three entries and returns the directory holding it and the log's path.
+fn write_log() -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.log");
+    let log = AuditLog::new(&path, None);
+    log.append("alpha", &action("true"), &output(0)).unwrap();
+    log.append("bravo", &action("false"), &output(1)).unwrap();
+    log.append("alpha", &action("pwd"), &output(0)).unwrap();
+    (dir, path)
+}
+
+mod append {
+    use super::*;
+
+    #[test]
+    fn records_actions_in_order() {
+        let (_dir, path) = write_log();
+        let records = verify_log(&path, allowed_signers_file(), "sira").unwrap();
+
+        assert_eq!(3, records.len());
+        assert_eq!("alpha", records[0].host);
+        assert_eq!(action("true"), records[0].action);
+        assert_eq!(Some(0), records[0].exit_code);
+        assert!(records[0].success);
+        assert_eq!(None, records[0].previous);
+
+        assert_eq!("bravo", records[1].host);
+        assert_eq!(Some(1), records[1].exit_code);
+        assert!(!records[1].success);
+    }
+
+    #[test]
+    fn continues_chain_across_runs() {
+        let (_dir, path) = write_log();
+        AuditLog::new(&path, None)
+            .append("charlie", &action("true"), &output(0))
+            .unwrap();
+
+        let records = verify_log(&path, allowed_signers_file(), "sira").unwrap();
+        assert_eq!(4, records.len());
+        assert_eq!("charlie", records[3].host);
+    }
+
+    #[test]
+    fn signs_with_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let key_file = config::config_dir().join(crypto::KEY_DIR).join("manifest");
+        AuditLog::new(&path, Some(key_file))
+            .append("alpha", &action("true"), &output(0))
+            .unwrap();
+
+        let manifest_signers = config::config_dir()
+            .join(crypto::ALLOWED_SIGNERS_DIR)
+            .join("manifest");
+        verify_log(&path, manifest_signers, "sira").unwrap();
+        assert!(verify_log(&path, allowed_signers_file(), "sira").is_err());
+    }
+
+    #[test]
+    fn returns_error_if_log_is_unwritable() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("missing/audit.log"), None);
+        assert!(log.open().is_err());
+    }
+}
+
+mod verify_log {
+    use super::*;
+
+    #[test]
+    fn detects_edited_record() {
+        let (_dir, path) = write_log();
+        let log = fs::read_to_string(&path).unwrap();
+        fs::write(&path, log.replacen("pwd", "rm -rf /", 1)).unwrap();
+
+        let error = verify_log(&path, allowed_signers_file(), "sira").unwrap_err();
+        assert!(format!("{error:#}").contains("entry 3"));
+    }
+
+    #[test]
+    fn detects_removed_record() {
+        let (_dir, path) = write_log();
+        let mut entries = parse_log(&fs::read_to_string(&path).unwrap()).unwrap();
+        entries.remove(1);
+        let log: String = entries
+            .iter()
+            .map(|entry| format!("---\n{}", serde_yaml::to_string(entry).unwrap()))
+            .collect();
+        fs::write(&path, log).unwrap();
+
+        let error = verify_log(&path, allowed_signers_file(), "sira").unwrap_err();
+        assert!(format!("{error:#}").contains("entry 2 does not follow"));
+    }
+
+    #[test]
+    fn accepts_empty_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        fs::write(&path, "").unwrap();
+        assert!(verify_log(&path, allowed_signers_file(), "sira")
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+//! Lets a program that runs a [Plan] stop it early, e.g. when its user clicks "Stop" or when the
+//! program is shutting down.
+//!
+//! Pass a [CancelToken] in [RunOptions::cancel], keep a clone of it, start the run, and call
+//! [CancelToken::cancel] at any time:
+//!
+//! ```no_run
+//! use sira::core::Plan;
+//! use sira::run_plan::cancel::{CancelToken, Cancelled};
+//! use sira::run_plan::{run_plan_with, RunOptions};
+//!
+//! # async fn example(plan: Plan) {
+//! let cancel = CancelToken::new();
+//! let options = RunOptions {
+//!     cancel: Some(cancel.clone()),
+//!     ..Default::default()
+//! };
+//! let run = tokio::spawn(run_plan_with(plan, options));
+//!
+//! // Later, e.g. from a signal handler or another task:
+//! cancel.cancel();
+//!
+//! if let Err(errors) = run.await.unwrap() {
+//!     for (host, error) in errors {
+//!         if error.is::<Cancelled>() {
+//!             println!("{host}: stopped early");
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! Once cancelled, hosts start no further actions, and hosts still waiting for their turn (see
+//! [RunOptions::concurrency]) never connect. Sira stops waiting for actions already underway and
+//! kills its end of their connections, e.g. `ssh`, `scp`, or a local `sira-client`. Each host that
+//! hadn't finished fails with [Cancelled]. The run then returns as usual, once every host has
+//! stopped and every report is finished, so nothing keeps running in the background.
+//!
+//! Killing the connection interrupts a command on a managed node much as a dropped SSH connection
+//! would, so an action may already have taken effect, in part or in full, by the time it stops.
+//!
+//! [Plan]: crate::core::Plan
+//! [RunOptions::cancel]: super::RunOptions::cancel
+//! [RunOptions::concurrency]: super::RunOptions::concurrency
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// A handle with which to cancel a run. Please see the [module documentation](self).
+///
+/// Clones share the same state, so cancelling any clone cancels them all. Once cancelled, a token
+/// stays cancelled, so use a new token, e.g. from [CancelToken::new], for each run that should be
+/// cancelled separately.
+#[derive(Clone, Debug)]
+pub struct CancelToken {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl CancelToken {
+    /// Creates a [CancelToken] that isn't cancelled.
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        CancelToken {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Cancels every run that uses this token or any of its clones.
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Returns whether this token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Waits until this token is cancelled. Returns immediately if it already has been.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as self, so this can't fail.
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tokens are equal if they're clones of each other, so that cancelling one cancels the other.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.sender, &other.sender)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// The error with which a host stops if its run is cancelled. Please see the
+/// [module documentation](self).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Run cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// The error with which a host stops if another host fails first and [RunOptions::on_failure] is
+/// [OnFailure::Abort]. The host stops just as if the run had been cancelled.
+///
+/// [OnFailure::Abort]: crate::config::OnFailure::Abort
+/// [RunOptions::on_failure]: super::RunOptions::on_failure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Run aborted because another host failed")
+    }
+}
+
+impl Error for Aborted {}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,45 @@
+use super::*;
+
+mod cancel_token {
+    use super::*;
+
+    #[test]
+    fn starts_uncancelled() {
+        assert!(!CancelToken::new().is_cancelled());
+    }
+
+    #[test]
+    fn cancels_clones() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(clone.is_cancelled());
+    }
+
+    #[test]
+    fn equals_only_clones() {
+        let token = CancelToken::new();
+        assert_eq!(token, token.clone());
+        assert_ne!(token, CancelToken::new());
+    }
+
+    #[tokio::test]
+    async fn cancelled_returns_once_cancelled() {
+        let token = CancelToken::new();
+        let waiting = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        token.cancel();
+        waiting.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancelled_returns_if_already_cancelled() {
+        let token = CancelToken::new();
+        token.cancel();
+        token.cancel();
+        token.cancelled().await;
+    }
+}
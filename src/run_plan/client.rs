@@ -1,21 +1,76 @@
-//! Provides an interface to execute [Action]s over SSH.
+//! Provides an interface to execute [Action]s on managed nodes, over SSH, locally, or in Docker
+//! containers.
+//!
+//! Each host is reached by the [Transport] that its settings select, so one run can mix hosts
+//! reached over SSH with the control node itself and containers on it. Please see
+//! [ConnectionManager].
 //!
 //! [Action]: crate::core::Action
 
-use crate::core::action::FILE_TRANSFER_PATH;
+use super::deploy_client::platform;
+use super::ssh_config::{self, SshConfig};
+use crate::client::facts::{Facts, FACTS_COMMAND};
+use crate::client::query::{Answer, Query};
+use crate::client::{CHECK_FLAG, PING_COMMAND};
+use crate::config::{Escalation, HostConfig, HostKeyChecking, Transport};
+use crate::core::action::{Progress, FILE_TRANSFER_PATH};
+use anyhow::Context;
 use async_trait::async_trait;
-use openssh::{KnownHosts, Session};
+use openssh::{KnownHosts, OwningCommand, Session, SessionBuilder, Stdio};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
 use std::io;
-use std::process::{Command, Output};
-use tokio::task;
+use std::path::PathBuf;
+use std::process::{Output, Stdio as StdStdio};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Where `sira-client` is installed on managed nodes.
+pub const CLIENT_PATH: &str = "/opt/sira/bin/sira-client";
 
 /// Connects to clients and returns values representing those connections.
 #[async_trait]
 pub trait ManageClient<CI: ClientInterface> {
     /// Connect to `host` and, on success, return an interface to the host.
+    ///
+    /// If `host` can't be reached, the error should be a [ConnectionError], so that the host
+    /// counts as unreachable rather than failed.
     async fn connect(&mut self, host: &str) -> anyhow::Result<CI>;
 }
 
+/// The error with which a host fails if Sira can't reach it, whichever [Transport] reaches it,
+/// e.g. because SSH couldn't connect, the container isn't running, or the connection dropped
+/// partway through the run. Displays just like the transport's error, which it wraps.
+#[derive(Debug)]
+pub struct ConnectionError(pub anyhow::Error);
+
+impl ConnectionError {
+    /// Wraps `error` in a [ConnectionError] if it's an [openssh::Error] that means the connection
+    /// failed or dropped, and otherwise returns it as it is.
+    pub fn from_ssh(error: anyhow::Error) -> anyhow::Error {
+        use openssh::Error::*;
+        match error.downcast_ref::<openssh::Error>() {
+            Some(Master(_) | Connect(_) | Disconnected) => ConnectionError(error).into(),
+            _ => error,
+        }
+    }
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
 /// The interface that Sira uses to talk to clients. Maps directly to [Action].
 ///
 /// [Action]: crate::core::Action
@@ -28,6 +83,32 @@ pub trait ClientInterface {
         signature: Option<Vec<u8>>,
     ) -> Result<Output, openssh::Error>;
 
+    /// Add or remove a control node's public keys on the client.
+    async fn controller_key(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error>;
+
+    /// Run a plugin on the client.
+    async fn custom(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error>;
+
+    /// Load or unload a kernel module on the client.
+    ///
+    /// Sends the action like [ClientInterface::command] by default, since `sira-client` tells
+    /// actions apart by their YAML.
+    async fn kernel_module(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.command(yaml, signature).await
+    }
+
     /// Modify a file on the client.
     async fn line_in_file(
         &mut self,
@@ -41,33 +122,495 @@ pub trait ClientInterface {
         signature: Option<Vec<u8>>,
     ) -> Result<Output, openssh::Error>;
 
+    /// Create, enable, or remove swap space on the client.
+    ///
+    /// Sends the action like [ClientInterface::command] by default, since `sira-client` tells
+    /// actions apart by their YAML.
+    async fn swap(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.command(yaml, signature).await
+    }
+
     /// Upload a file from the Sira control node to the client over SSH.
+    ///
+    /// If `transfer_permissions` is given, the file is created with those permissions before any
+    /// of its contents are uploaded. Please see `transfer_permissions` in
+    /// [Action::Upload](crate::core::Action::Upload).
     async fn upload(
         &mut self,
         from: &str,
+        transfer_permissions: Option<&str>,
         yaml: &str,
         signature: Option<Vec<u8>>,
     ) -> anyhow::Result<Output>;
+
+    /// Runs `sira-client ping`, which changes nothing, and returns the version of `sira-client`
+    /// that answered.
+    async fn ping(&mut self) -> anyhow::Result<String>;
+
+    /// Runs `sira-client facts`, which changes nothing, and returns the managed node's [Facts].
+    async fn facts(&mut self) -> anyhow::Result<Facts>;
+
+    /// Returns the managed node's platform and the checksum of its installed `sira-client`,
+    /// without running it. Please see
+    /// [verify_client](super::deploy_client::verify_client).
+    async fn installed_client(&mut self) -> anyhow::Result<InstalledClient>;
+
+    /// Takes the receiving end of the channel on which this client sends the [Progress] that
+    /// `sira-client` reports while an action runs.
+    ///
+    /// Returns [None] if this client doesn't report progress or if the receiver was already taken.
+    /// Returns [None] by default.
+    fn take_progress(&mut self) -> Option<UnboundedReceiver<Progress>> {
+        None
+    }
+
+    /// Returns another interface to the same host that can run an action while this one runs
+    /// another, e.g. over another channel on the same SSH connection. The new interface reports
+    /// progress through its own channel; please see [ClientInterface::take_progress].
+    ///
+    /// Sira calls this to run independent actions side by side; please see
+    /// [RunOptions::pipeline](super::RunOptions::pipeline). Returns [None] if this client can only
+    /// run one action at a time. Returns [None] by default.
+    fn fork(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
-/// Production implementation of [ManageClient].
-#[derive(Clone)]
-pub struct ConnectionManager;
+/// The `sira-client` installed on a managed node, as returned by
+/// [ClientInterface::installed_client].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstalledClient {
+    /// The managed node's platform, named as by [platform()], e.g. `x86_64`.
+    pub platform: String,
+
+    /// The SHA-256 checksum of the installed `sira-client`, in lowercase hexadecimal.
+    pub sha256: String,
+}
+
+impl InstalledClient {
+    /// Returns the [InstalledClient] that `output`, from running [INSTALLED_CLIENT_SCRIPT] on the
+    /// `sira-client` at `client_path`, describes.
+    fn from_output(output: Output, client_path: &str) -> anyhow::Result<Self> {
+        if !output.status.success() {
+            anyhow::bail!(
+                "could not compute checksum of {client_path}: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        InstalledClient::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Parses the output of [INSTALLED_CLIENT_SCRIPT]: the output of `uname -sm` on one line,
+    /// followed by the output of `sha256sum` or its equivalent.
+    fn parse(output: &str) -> anyhow::Result<Self> {
+        let mut lines = output.lines();
+        let uname = lines.next().unwrap_or_default();
+        let checksum = lines
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches('\\')
+            .split_whitespace()
+            .next()
+            .unwrap_or_default();
+        if uname.trim().is_empty() {
+            anyhow::bail!("could not determine platform from output: {output:?}");
+        }
+        if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!("could not find sira-client's checksum in output: {output:?}");
+        }
+        Ok(InstalledClient {
+            platform: platform(uname),
+            sha256: checksum.to_ascii_lowercase(),
+        })
+    }
+}
+
+/// The shell script that prints what [InstalledClient::parse] reads about the `sira-client` at
+/// `$1`. Managed nodes may have `sha256sum` (Linux), `shasum` (macOS), or `sha256` (the BSDs).
+const INSTALLED_CLIENT_SCRIPT: &str =
+    r#"uname -sm && { sha256sum "$1" || shasum -a 256 "$1" || sha256 -q "$1"; }"#;
+
+/// Production implementation of [ManageClient], which reaches each host by the [Transport] that
+/// its settings select.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionManager {
+    /// The private key used to log into managed nodes, if not left up to OpenSSH.
+    keyfile: Option<PathBuf>,
+
+    /// The known hosts file against which to check managed nodes' host keys, if not the user's.
+    known_hosts: Option<PathBuf>,
+
+    /// How to check host keys, if not as [ConnectionManager::new] describes.
+    host_key_checking: Option<HostKeyChecking>,
+
+    /// How to connect to every host and run `sira-client` on it.
+    connection: HostConfig,
+
+    /// Overrides of [ConnectionManager::connection] for individual hosts.
+    hosts: BTreeMap<String, HostConfig>,
+
+    /// Whether `sira-client` runs every action in check mode. Please see
+    /// [ConnectionManager::with_check].
+    check: bool,
+}
+
+impl ConnectionManager {
+    /// Creates a [ConnectionManager] that logs in with `keyfile`, if provided, and checks host
+    /// keys against `known_hosts`, if provided.
+    ///
+    /// If `keyfile` is [None], OpenSSH chooses the key as usual, e.g. from `ssh-agent` or the
+    /// user's SSH configuration. Either way, if a certificate for the key exists alongside it
+    /// (e.g. `~/.ssh/sira-cert.pub` for `~/.ssh/sira`), OpenSSH presents the certificate, so
+    /// managed nodes that trust a user certificate authority need no `authorized_keys` entry.
+    ///
+    /// If `known_hosts` is [None], OpenSSH checks the user's known hosts files and adds new hosts
+    /// on first connection. Otherwise, OpenSSH checks only `known_hosts` and rejects unknown hosts,
+    /// which suits a file that trusts a host certificate authority.
+    pub fn new(keyfile: Option<PathBuf>, known_hosts: Option<PathBuf>) -> Self {
+        ConnectionManager {
+            keyfile,
+            known_hosts,
+            ..Default::default()
+        }
+    }
+
+    /// Checks host keys as `checking` says, if provided, instead of as [ConnectionManager::new]
+    /// describes.
+    pub fn with_host_key_checking(mut self, checking: Option<HostKeyChecking>) -> Self {
+        self.host_key_checking = checking;
+        self
+    }
+
+    /// Connects to every host as `connection` says, except where `hosts` overrides it for a
+    /// particular host. Settings left unset keep their defaults, as for
+    /// [RunOptions::connection](super::RunOptions::connection).
+    ///
+    /// A user set here only applies to hosts that don't name a user themselves, e.g. `web1` but
+    /// not `deploy@web1`.
+    pub fn with_connections(
+        mut self,
+        connection: HostConfig,
+        hosts: BTreeMap<String, HostConfig>,
+    ) -> Self {
+        self.connection = connection;
+        self.hosts = hosts;
+        self
+    }
+
+    /// Runs every action in check mode if `check` is true, so that `sira-client` only reports what
+    /// each would change. Please see [CHECK_FLAG].
+    pub fn with_check(mut self, check: bool) -> Self {
+        self.check = check;
+        self
+    }
+}
 
 #[async_trait]
-impl ManageClient<Client> for ConnectionManager {
-    async fn connect(&mut self, host: &str) -> anyhow::Result<Client> {
+impl ManageClient<Connection> for ConnectionManager {
+    async fn connect(&mut self, host: &str) -> anyhow::Result<Connection> {
+        let settings = self.settings_for(host);
+        let connection = match settings.transport.unwrap_or_default() {
+            Transport::Ssh => Connection::Ssh(self.connect_ssh(host, settings).await?),
+            Transport::Local => {
+                Connection::Local(LocalClient::new(settings).map_err(ConnectionError)?)
+            }
+            Transport::Docker => Connection::Docker(
+                DockerClient::connect(host, settings)
+                    .await
+                    .map_err(ConnectionError)?,
+            ),
+        };
+        Ok(connection.with_check(self.check))
+    }
+}
+
+impl ConnectionManager {
+    /// Returns the settings for `host`: its overrides, if any, merged over the settings for every
+    /// host.
+    fn settings_for(&self, host: &str) -> HostConfig {
+        let mut settings = match self.hosts.get(host) {
+            Some(overrides) => self.connection.clone().merge(overrides.clone()),
+            None => self.connection.clone(),
+        };
+
+        // On a rootless host, sira-client escalates for the actions that need it by itself.
+        if settings.rootless.unwrap_or_default() {
+            settings.escalation = Some(Escalation::None);
+        }
+        settings
+    }
+
+    /// Logs into `host` over SSH as `settings` say.
+    async fn connect_ssh(&self, host: &str, settings: HostConfig) -> anyhow::Result<Client> {
+        let mut builder = SessionBuilder::default();
+        if let Some(known_hosts) = &self.known_hosts {
+            builder.user_known_hosts_file(known_hosts);
+        }
+        let strict = match self.host_key_checking {
+            Some(checking) => checking == HostKeyChecking::Strict,
+            None => self.known_hosts.is_some(),
+        };
+        builder.known_hosts_check(
+            match ssh_config::known_hosts_check(&settings.ssh_options)? {
+                Some(check) => check,
+                None if strict => KnownHosts::Strict,
+                None => KnownHosts::Add,
+            },
+        );
+        if let Some(keyfile) = &self.keyfile {
+            builder.keyfile(keyfile);
+        }
+
+        if let Some(port) = settings.port {
+            builder.port(port);
+        }
+        if let Some(user) = settings.user.filter(|_| !host.contains('@')) {
+            builder.user(user);
+        }
+        if let Some(seconds) = settings.connect_timeout {
+            builder.connect_timeout(Duration::from_secs(seconds));
+        }
+        if let Some(seconds) = settings.server_alive_interval {
+            builder.server_alive_interval(Duration::from_secs(seconds));
+        }
+
+        // OpenSSH only reads its configuration while connecting, so the file can go right after.
+        let ssh_config = SshConfig::write(&settings.ssh_options)?;
+        if let Some(ssh_config) = &ssh_config {
+            builder.config_file(ssh_config.path());
+        }
+        let session = builder.connect_mux(host).await;
+        drop(ssh_config);
+
+        let (progress, progress_receiver) = mpsc::unbounded_channel();
         Ok(Client {
-            session: openssh::Session::connect_mux(host, KnownHosts::Add).await?,
-            host: host.to_owned(),
+            session: Arc::new(session.map_err(|e| ConnectionError(e.into()))?),
+            client_path: settings
+                .client_path
+                .unwrap_or_else(|| CLIENT_PATH.to_string()),
+            escalation: settings.escalation.unwrap_or_default(),
+            check: false,
+            progress,
+            progress_receiver: Some(progress_receiver),
         })
     }
 }
 
-/// Production implementation of [ClientInterface].
+/// A connection to a managed node by whichever [Transport] reaches it.
+pub enum Connection {
+    /// A connection over SSH.
+    Ssh(Client),
+
+    /// The control node itself.
+    Local(LocalClient),
+
+    /// A container on the control node.
+    Docker(DockerClient),
+}
+
+impl Connection {
+    /// Sets whether the client runs every action in check mode. Please see
+    /// [ConnectionManager::with_check].
+    fn with_check(mut self, check: bool) -> Self {
+        match &mut self {
+            Connection::Ssh(client) => client.check = check,
+            Connection::Local(client) => client.check = check,
+            Connection::Docker(client) => client.check = check,
+        }
+        self
+    }
+
+    /// Returns the managed node's platform, e.g. `x86_64` or `macos-aarch64`. Please see
+    /// [platform].
+    pub async fn platform(&self) -> anyhow::Result<String> {
+        match self {
+            Connection::Ssh(client) => client.platform().await,
+            Connection::Local(client) => client.platform().await,
+            Connection::Docker(client) => client.platform().await,
+        }
+    }
+}
+
+#[async_trait]
+impl ClientInterface for Connection {
+    async fn command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        match self {
+            Connection::Ssh(client) => client.command(yaml, signature).await,
+            Connection::Local(client) => client.command(yaml, signature).await,
+            Connection::Docker(client) => client.command(yaml, signature).await,
+        }
+    }
+
+    async fn controller_key(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        match self {
+            Connection::Ssh(client) => client.controller_key(yaml, signature).await,
+            Connection::Local(client) => client.controller_key(yaml, signature).await,
+            Connection::Docker(client) => client.controller_key(yaml, signature).await,
+        }
+    }
+
+    async fn custom(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        match self {
+            Connection::Ssh(client) => client.custom(yaml, signature).await,
+            Connection::Local(client) => client.custom(yaml, signature).await,
+            Connection::Docker(client) => client.custom(yaml, signature).await,
+        }
+    }
+
+    async fn kernel_module(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        match self {
+            Connection::Ssh(client) => client.kernel_module(yaml, signature).await,
+            Connection::Local(client) => client.kernel_module(yaml, signature).await,
+            Connection::Docker(client) => client.kernel_module(yaml, signature).await,
+        }
+    }
+
+    async fn line_in_file(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        match self {
+            Connection::Ssh(client) => client.line_in_file(yaml, signature).await,
+            Connection::Local(client) => client.line_in_file(yaml, signature).await,
+            Connection::Docker(client) => client.line_in_file(yaml, signature).await,
+        }
+    }
+
+    async fn script(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        match self {
+            Connection::Ssh(client) => client.script(yaml, signature).await,
+            Connection::Local(client) => client.script(yaml, signature).await,
+            Connection::Docker(client) => client.script(yaml, signature).await,
+        }
+    }
+
+    async fn swap(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        match self {
+            Connection::Ssh(client) => client.swap(yaml, signature).await,
+            Connection::Local(client) => client.swap(yaml, signature).await,
+            Connection::Docker(client) => client.swap(yaml, signature).await,
+        }
+    }
+
+    async fn upload(
+        &mut self,
+        from: &str,
+        transfer_permissions: Option<&str>,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> anyhow::Result<Output> {
+        match self {
+            Connection::Ssh(client) => {
+                client
+                    .upload(from, transfer_permissions, yaml, signature)
+                    .await
+            }
+            Connection::Local(client) => {
+                client
+                    .upload(from, transfer_permissions, yaml, signature)
+                    .await
+            }
+            Connection::Docker(client) => {
+                client
+                    .upload(from, transfer_permissions, yaml, signature)
+                    .await
+            }
+        }
+    }
+
+    async fn ping(&mut self) -> anyhow::Result<String> {
+        match self {
+            Connection::Ssh(client) => client.ping().await,
+            Connection::Local(client) => client.ping().await,
+            Connection::Docker(client) => client.ping().await,
+        }
+    }
+
+    async fn facts(&mut self) -> anyhow::Result<Facts> {
+        match self {
+            Connection::Ssh(client) => client.facts().await,
+            Connection::Local(client) => client.facts().await,
+            Connection::Docker(client) => client.facts().await,
+        }
+    }
+
+    async fn installed_client(&mut self) -> anyhow::Result<InstalledClient> {
+        match self {
+            Connection::Ssh(client) => client.installed_client().await,
+            Connection::Local(client) => client.installed_client().await,
+            Connection::Docker(client) => client.installed_client().await,
+        }
+    }
+
+    fn take_progress(&mut self) -> Option<UnboundedReceiver<Progress>> {
+        match self {
+            Connection::Ssh(client) => client.take_progress(),
+            Connection::Local(client) => client.take_progress(),
+            Connection::Docker(client) => client.take_progress(),
+        }
+    }
+
+    fn fork(&self) -> Option<Self> {
+        match self {
+            Connection::Ssh(client) => client.fork().map(Connection::Ssh),
+            Connection::Local(client) => client.fork().map(Connection::Local),
+            Connection::Docker(client) => client.fork().map(Connection::Docker),
+        }
+    }
+}
+
+/// Production implementation of [ClientInterface] over SSH.
 pub struct Client {
-    session: Session,
-    host: String,
+    /// The SSH connection, shared with every [Client] forked from this one, each of which runs
+    /// its commands over channels of its own.
+    session: Arc<Session>,
+
+    /// Where `sira-client` is installed on the managed node.
+    client_path: String,
+
+    /// How `sira-client` gains root privileges on the managed node.
+    escalation: Escalation,
+
+    /// Whether `sira-client` runs actions in check mode.
+    check: bool,
+
+    /// Where to send progress that `sira-client` reports.
+    progress: UnboundedSender<Progress>,
+
+    /// The receiving end of [Client::progress], until taken by [ClientInterface::take_progress].
+    progress_receiver: Option<UnboundedReceiver<Progress>>,
 }
 
 #[async_trait]
@@ -80,6 +623,30 @@ impl ClientInterface for Client {
         self.client_command(yaml, signature).await
     }
 
+    async fn controller_key(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn custom(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn kernel_module(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
     async fn line_in_file(
         &mut self,
         yaml: &str,
@@ -96,22 +663,29 @@ impl ClientInterface for Client {
         self.client_command(yaml, signature).await
     }
 
+    async fn swap(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
     async fn upload(
         &mut self,
         from: &str,
+        transfer_permissions: Option<&str>,
         yaml: &str,
         signature: Option<Vec<u8>>,
     ) -> anyhow::Result<Output> {
-        let to = format!("{}:{}", self.host, FILE_TRANSFER_PATH);
-
-        // TL;DR It's very important to `rm -rf` the FILE_TRANSFER_PATH right before we call `scp`
-        // to upload a file.
+        // TL;DR It's very important to `rm -rf` the FILE_TRANSFER_PATH right before we upload a
+        // file.
         //
         // There shouldn't be anything at FILE_TRANSFER_PATH on the managed node. However, if there
-        // is, it might actually be a directory. In that case, scp will auto-transfer the file into
-        // that directory rather than overwriting or failing. From there, subsequent logic will
-        // misbehave badly. Thus, it's important to forcibly remove anything at the destination
-        // just before we run scp. See below for further discussion of specific security concerns.
+        // is, it might be a directory, which would make the upload fail, or a symbolic link, which
+        // would redirect it. From there, subsequent logic will misbehave badly. Thus, it's
+        // important to forcibly remove anything at the destination just before we upload. See
+        // below for further discussion of specific security concerns.
         //
         // `rm -rf` DOES NOT traverse symbolic links: if the path is a symbolic link, `rm` removes
         // it. Thus, doing this actually helps protect against symbolic link squatting.
@@ -137,37 +711,858 @@ impl ClientInterface for Client {
             .status()
             .await;
 
-        let scp_output = self.scp(from, &to).await?;
-        if !scp_output.status.success() {
-            return Ok(scp_output);
+        // Writing to an existing file keeps its permissions, so create the file with the requested
+        // permissions first. Otherwise, it's created with the Sira user's default permissions.
+        if let Some(permissions) = transfer_permissions {
+            let install_output = self
+                .session
+                .command("install")
+                .arg("-m")
+                .arg(permissions)
+                .arg("/dev/null")
+                .arg(FILE_TRANSFER_PATH)
+                .output()
+                .await?;
+            if !install_output.status.success() {
+                return Ok(install_output);
+            }
+        }
+
+        // Stream the file through the session, rather than reading it into memory, so that large
+        // files can be uploaded, too. Going through `sh` keeps the redirection the same whatever
+        // the Sira user's login shell is.
+        let mut child = self
+            .session
+            .shell(format!("cat > {FILE_TRANSFER_PATH}"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .await?;
+        let stdin = child
+            .stdin()
+            .take()
+            .expect("the upload's stdin should be piped");
+        let sent = send_file(from, stdin, &self.progress).await;
+        let cat_output = child.wait_with_output().await?;
+        if !cat_output.status.success() {
+            return Ok(cat_output);
         }
+        sent.with_context(|| format!("could not upload {from}"))?;
         Ok(self.client_command(yaml, signature).await?)
     }
+
+    async fn ping(&mut self) -> anyhow::Result<String> {
+        let output = self.sira_client().arg(PING_COMMAND).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sira-client ping exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    async fn facts(&mut self) -> anyhow::Result<Facts> {
+        let output = self.sira_client().arg(FACTS_COMMAND).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sira-client facts exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        serde_json::from_slice(&output.stdout).context("could not parse sira-client facts")
+    }
+
+    async fn installed_client(&mut self) -> anyhow::Result<InstalledClient> {
+        let output = self
+            .session
+            .command("sh")
+            .args([
+                "-c",
+                INSTALLED_CLIENT_SCRIPT,
+                "sh",
+                self.client_path.as_str(),
+            ])
+            .output()
+            .await?;
+        InstalledClient::from_output(output, &self.client_path)
+    }
+
+    fn take_progress(&mut self) -> Option<UnboundedReceiver<Progress>> {
+        self.progress_receiver.take()
+    }
+
+    fn fork(&self) -> Option<Self> {
+        let (progress, progress_receiver) = mpsc::unbounded_channel();
+        Some(Client {
+            session: Arc::clone(&self.session),
+            client_path: self.client_path.clone(),
+            escalation: self.escalation,
+            check: self.check,
+            progress,
+            progress_receiver: Some(progress_receiver),
+        })
+    }
 }
 
 impl Client {
-    /// Invoke `sudo /opt/sira/bin/sira-client <yaml> <signature>` on the remote host.
+    /// Returns the managed node's platform, e.g. `x86_64` or `macos-aarch64`. Please see
+    /// [platform].
+    pub async fn platform(&self) -> anyhow::Result<String> {
+        let output = self.session.command("uname").arg("-sm").output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "uname exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Ok(platform(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Asks `sira-client` on the managed node a [Query] about its current state, e.g. before
+    /// deciding whether to run an action.
+    pub async fn query(&self, query: &Query) -> anyhow::Result<Answer> {
+        let output = self.sira_client().args(query.args()).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sira-client query exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Answer::from_json(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Returns a command that runs `sira-client` with root privileges on the remote host, e.g.
+    /// `sudo /opt/sira/bin/sira-client`.
+    fn sira_client(&self) -> OwningCommand<&Session> {
+        match self.escalation.program() {
+            Some(program) => {
+                let mut command = self.session.command(program);
+                command.arg(&self.client_path);
+                command
+            }
+            None => self.session.command(&self.client_path),
+        }
+    }
+
+    /// Invoke `sudo /opt/sira/bin/sira-client <yaml> <signature>` on the remote host, or
+    /// whichever escalation and path the host is configured with.
     async fn client_command(
         &mut self,
         yaml: &str,
         signature: Option<Vec<u8>>,
     ) -> Result<Output, openssh::Error> {
-        let mut command = self.session.command("sudo");
-        command.arg("/opt/sira/bin/sira-client");
+        let mut command = self.sira_client();
+        if self.check {
+            command.arg(CHECK_FLAG);
+        }
         command.arg(yaml);
         if let Some(sig) = signature {
             let sig = String::from_utf8(sig)
                 .expect("expected signature to be Base64-encoded, but it was not valid UTF-8");
             command.arg(&sig);
         }
-        command.output().await
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = command.spawn().await?;
+        let stdout = child
+            .stdout()
+            .take()
+            .expect("sira-client's stdout should be piped");
+        let stderr = child
+            .stderr()
+            .take()
+            .expect("sira-client's stderr should be piped");
+
+        // Like openssh's Child::wait_with_output, finish reading before waiting, since waiting
+        // cuts off any output that's still on its way.
+        let (stdout, stderr) = read_output(stdout, stderr, &self.progress)
+            .await
+            .map_err(openssh::Error::ChildIo)?;
+        Ok(Output {
+            status: child.wait().await?,
+            stdout,
+            stderr,
+        })
     }
+}
+
+/// Production implementation of [ClientInterface] for [Transport::Local], which runs `sira-client`
+/// on the control node itself.
+///
+/// `sira-client` runs in the user's home directory, just as it would in an SSH session, and files
+/// are uploaded there by copying them directly rather than over SSH.
+pub struct LocalClient {
+    /// Where `sira-client` is installed on the control node.
+    client_path: String,
+
+    /// How `sira-client` gains root privileges.
+    escalation: Escalation,
+
+    /// Whether `sira-client` runs actions in check mode.
+    check: bool,
 
-    /// Invoke `scp` on the Sira control node.
+    /// The directory in which `sira-client` runs, i.e. the user's home directory.
+    work_dir: PathBuf,
+
+    /// Where to send progress that `sira-client` reports.
+    progress: UnboundedSender<Progress>,
+
+    /// The receiving end of [LocalClient::progress], until taken by
+    /// [ClientInterface::take_progress].
+    progress_receiver: Option<UnboundedReceiver<Progress>>,
+}
+
+impl LocalClient {
+    /// Creates a [LocalClient] that runs `sira-client` as `settings` say. Settings that only apply
+    /// to SSH are ignored.
     ///
-    /// `from` and `to` need to be formatted correctly for use in an `scp` invocation. The command
-    /// `scp <from> <to>` will be invoked directly, with no further modifications.
-    async fn scp(&self, from: &str, to: &str) -> io::Result<Output> {
-        task::block_in_place(move || Command::new("scp").arg(from).arg(to).output())
+    /// # Errors
+    ///
+    /// Returns an error if the user's home directory can't be determined.
+    pub fn new(settings: HostConfig) -> anyhow::Result<Self> {
+        let work_dir =
+            home::home_dir().ok_or_else(|| anyhow::anyhow!("could not find the home directory"))?;
+        let (progress, progress_receiver) = mpsc::unbounded_channel();
+        Ok(LocalClient {
+            client_path: settings
+                .client_path
+                .unwrap_or_else(|| CLIENT_PATH.to_string()),
+            escalation: settings.escalation.unwrap_or_default(),
+            check: false,
+            work_dir,
+            progress,
+            progress_receiver: Some(progress_receiver),
+        })
+    }
+
+    /// Returns the control node's platform, e.g. `x86_64` or `macos-aarch64`. Please see
+    /// [platform].
+    pub async fn platform(&self) -> anyhow::Result<String> {
+        let output = tokio::process::Command::new("uname")
+            .arg("-sm")
+            .output()
+            .await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "uname exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Ok(platform(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Returns a command that runs `sira-client` with root privileges in [LocalClient::work_dir],
+    /// e.g. `sudo /opt/sira/bin/sira-client`.
+    fn sira_client(&self) -> tokio::process::Command {
+        let mut command = match self.escalation.program() {
+            Some(program) => {
+                let mut command = tokio::process::Command::new(program);
+                command.arg(&self.client_path);
+                command
+            }
+            None => tokio::process::Command::new(&self.client_path),
+        };
+        command
+            .current_dir(&self.work_dir)
+            .stdin(StdStdio::null())
+            .kill_on_drop(true);
+        command
+    }
+
+    /// Invoke `sudo /opt/sira/bin/sira-client <yaml> <signature>` on the control node, or
+    /// whichever escalation and path it's configured with.
+    async fn client_command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self._client_command(yaml, signature)
+            .await
+            .map_err(openssh::Error::ChildIo)
+    }
+
+    /// Does the work of [LocalClient::client_command].
+    async fn _client_command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> io::Result<Output> {
+        let mut command = self.sira_client();
+        if self.check {
+            command.arg(CHECK_FLAG);
+        }
+        command.arg(yaml);
+        if let Some(sig) = signature {
+            let sig = String::from_utf8(sig)
+                .expect("expected signature to be Base64-encoded, but it was not valid UTF-8");
+            command.arg(&sig);
+        }
+        command.stdout(StdStdio::piped()).stderr(StdStdio::piped());
+        let mut child = command.spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .expect("sira-client's stdout should be piped");
+        let stderr = child
+            .stderr
+            .take()
+            .expect("sira-client's stderr should be piped");
+        let (stdout, stderr) = read_output(stdout, stderr, &self.progress).await?;
+        Ok(Output {
+            status: child.wait().await?,
+            stdout,
+            stderr,
+        })
     }
 }
+
+#[async_trait]
+impl ClientInterface for LocalClient {
+    async fn command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn controller_key(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn custom(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn kernel_module(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn line_in_file(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn script(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn swap(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn upload(
+        &mut self,
+        from: &str,
+        transfer_permissions: Option<&str>,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> anyhow::Result<Output> {
+        // As with Client::upload, clear the way first, so that a directory or symbolic link at
+        // FILE_TRANSFER_PATH can't redirect the upload.
+        let to = self.work_dir.join(FILE_TRANSFER_PATH);
+        let _ = tokio::process::Command::new("rm")
+            .arg("-rf")
+            .arg(&to)
+            .status()
+            .await;
+
+        // As with Client::upload, writing to an existing file keeps its permissions.
+        if let Some(permissions) = transfer_permissions {
+            let install_output = tokio::process::Command::new("install")
+                .arg("-m")
+                .arg(permissions)
+                .arg("/dev/null")
+                .arg(&to)
+                .output()
+                .await?;
+            if !install_output.status.success() {
+                return Ok(install_output);
+            }
+        }
+
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&to)
+            .await
+            .with_context(|| format!("could not create {}", to.display()))?;
+        send_file(from, file, &self.progress)
+            .await
+            .with_context(|| format!("could not upload {from}"))?;
+        Ok(self.client_command(yaml, signature).await?)
+    }
+
+    async fn ping(&mut self) -> anyhow::Result<String> {
+        let output = self.sira_client().arg(PING_COMMAND).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sira-client ping exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    async fn facts(&mut self) -> anyhow::Result<Facts> {
+        let output = self.sira_client().arg(FACTS_COMMAND).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sira-client facts exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        serde_json::from_slice(&output.stdout).context("could not parse sira-client facts")
+    }
+
+    async fn installed_client(&mut self) -> anyhow::Result<InstalledClient> {
+        let output = tokio::process::Command::new("sh")
+            .args([
+                "-c",
+                INSTALLED_CLIENT_SCRIPT,
+                "sh",
+                self.client_path.as_str(),
+            ])
+            .output()
+            .await?;
+        InstalledClient::from_output(output, &self.client_path)
+    }
+
+    fn take_progress(&mut self) -> Option<UnboundedReceiver<Progress>> {
+        self.progress_receiver.take()
+    }
+
+    fn fork(&self) -> Option<Self> {
+        let (progress, progress_receiver) = mpsc::unbounded_channel();
+        Some(LocalClient {
+            client_path: self.client_path.clone(),
+            escalation: self.escalation,
+            check: self.check,
+            work_dir: self.work_dir.clone(),
+            progress,
+            progress_receiver: Some(progress_receiver),
+        })
+    }
+}
+
+/// Production implementation of [ClientInterface] for [Transport::Docker], which runs `sira-client`
+/// in a running container on the control node with `docker exec`, e.g. to test manifests against
+/// throwaway containers.
+///
+/// The host's name is the container's name or ID. `sira-client` runs in the container's working
+/// directory, as the host's `user`, if set, or else as the container's user, and files are
+/// uploaded there through `docker exec`'s stdin.
+pub struct DockerClient {
+    /// The name or ID of the container.
+    container: String,
+
+    /// The user to run as in the container, if not the container's user.
+    user: Option<String>,
+
+    /// Where `sira-client` is installed in the container.
+    client_path: String,
+
+    /// How `sira-client` gains root privileges in the container.
+    escalation: Escalation,
+
+    /// Whether `sira-client` runs actions in check mode.
+    check: bool,
+
+    /// Where to send progress that `sira-client` reports.
+    progress: UnboundedSender<Progress>,
+
+    /// The receiving end of [DockerClient::progress], until taken by
+    /// [ClientInterface::take_progress].
+    progress_receiver: Option<UnboundedReceiver<Progress>>,
+}
+
+impl DockerClient {
+    /// Checks that `container` is running and returns a [DockerClient] that runs `sira-client` in
+    /// it as `settings` say. Settings that only apply to SSH are ignored, and since containers
+    /// usually run as root, `escalation` defaults to [Escalation::None].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `docker` can't be run or the container isn't running.
+    pub async fn connect(container: &str, settings: HostConfig) -> anyhow::Result<Self> {
+        let (progress, progress_receiver) = mpsc::unbounded_channel();
+        let client = DockerClient {
+            container: container.to_string(),
+            user: settings.user,
+            client_path: settings
+                .client_path
+                .unwrap_or_else(|| CLIENT_PATH.to_string()),
+            escalation: settings.escalation.unwrap_or(Escalation::None),
+            check: false,
+            progress,
+            progress_receiver: Some(progress_receiver),
+        };
+        let output = client
+            .docker_exec()
+            .arg("true")
+            .output()
+            .await
+            .context("could not run docker")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "could not run commands in container {container}: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Ok(client)
+    }
+
+    /// Returns the container's platform, e.g. `x86_64`. Please see [platform].
+    pub async fn platform(&self) -> anyhow::Result<String> {
+        let output = self.docker_exec().arg("uname").arg("-sm").output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "uname exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Ok(platform(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Returns `docker exec` for the container, e.g. `docker exec -i --user sira web-test`, to
+    /// which the caller adds the command to run in it.
+    fn docker_exec(&self) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new("docker");
+        command.args(docker_exec_args(&self.container, self.user.as_deref()));
+        command.stdin(StdStdio::null()).kill_on_drop(true);
+        command
+    }
+
+    /// Returns a command that runs `sira-client` with root privileges in the container, e.g.
+    /// `docker exec -i web-test /opt/sira/bin/sira-client`.
+    fn sira_client(&self) -> tokio::process::Command {
+        let mut command = self.docker_exec();
+        if let Some(program) = self.escalation.program() {
+            command.arg(program);
+        }
+        command.arg(&self.client_path);
+        command
+    }
+
+    /// Invoke `docker exec -i <container> /opt/sira/bin/sira-client <yaml> <signature>`, or
+    /// whichever escalation and path it's configured with.
+    async fn client_command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self._client_command(yaml, signature)
+            .await
+            .map_err(openssh::Error::ChildIo)
+    }
+
+    /// Does the work of [DockerClient::client_command].
+    async fn _client_command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> io::Result<Output> {
+        let mut command = self.sira_client();
+        if self.check {
+            command.arg(CHECK_FLAG);
+        }
+        command.arg(yaml);
+        if let Some(sig) = signature {
+            let sig = String::from_utf8(sig)
+                .expect("expected signature to be Base64-encoded, but it was not valid UTF-8");
+            command.arg(&sig);
+        }
+        command.stdout(StdStdio::piped()).stderr(StdStdio::piped());
+        let mut child = command.spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .expect("sira-client's stdout should be piped");
+        let stderr = child
+            .stderr
+            .take()
+            .expect("sira-client's stderr should be piped");
+        let (stdout, stderr) = read_output(stdout, stderr, &self.progress).await?;
+        Ok(Output {
+            status: child.wait().await?,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// Returns the arguments to `docker` that run a command in `container`, as `user` if provided,
+/// with stdin attached.
+pub(crate) fn docker_exec_args(container: &str, user: Option<&str>) -> Vec<String> {
+    let mut args = vec!["exec".to_string(), "-i".to_string()];
+    if let Some(user) = user {
+        args.push("--user".to_string());
+        args.push(user.to_string());
+    }
+    args.push(container.to_string());
+    args
+}
+
+/// The script with which [DockerClient] receives an upload on stdin. As with [Client::upload], it
+/// clears the way first and, given permissions in `$2`, creates the file with them before writing
+/// to it. `$1` is [FILE_TRANSFER_PATH].
+const DOCKER_UPLOAD_SCRIPT: &str =
+    r#"rm -rf "$1" && { [ -z "$2" ] || install -m "$2" /dev/null "$1"; } && cat > "$1""#;
+
+#[async_trait]
+impl ClientInterface for DockerClient {
+    async fn command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn controller_key(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn custom(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn kernel_module(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn line_in_file(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn script(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn swap(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.client_command(yaml, signature).await
+    }
+
+    async fn upload(
+        &mut self,
+        from: &str,
+        transfer_permissions: Option<&str>,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> anyhow::Result<Output> {
+        let mut child = self
+            .docker_exec()
+            .args(["sh", "-c", DOCKER_UPLOAD_SCRIPT, "sh", FILE_TRANSFER_PATH])
+            .arg(transfer_permissions.unwrap_or_default())
+            .stdin(StdStdio::piped())
+            .stdout(StdStdio::null())
+            .stderr(StdStdio::piped())
+            .spawn()
+            .context("could not run docker")?;
+        let stdin = child
+            .stdin
+            .take()
+            .expect("the upload's stdin should be piped");
+        let sent = send_file(from, stdin, &self.progress).await;
+        let upload_output = child.wait_with_output().await?;
+        if !upload_output.status.success() {
+            return Ok(upload_output);
+        }
+        sent.with_context(|| format!("could not upload {from}"))?;
+        Ok(self.client_command(yaml, signature).await?)
+    }
+
+    async fn ping(&mut self) -> anyhow::Result<String> {
+        let output = self.sira_client().arg(PING_COMMAND).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sira-client ping exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    async fn facts(&mut self) -> anyhow::Result<Facts> {
+        let output = self.sira_client().arg(FACTS_COMMAND).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sira-client facts exited with error: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+        serde_json::from_slice(&output.stdout).context("could not parse sira-client facts")
+    }
+
+    async fn installed_client(&mut self) -> anyhow::Result<InstalledClient> {
+        let output = self
+            .docker_exec()
+            .args([
+                "sh",
+                "-c",
+                INSTALLED_CLIENT_SCRIPT,
+                "sh",
+                self.client_path.as_str(),
+            ])
+            .output()
+            .await?;
+        InstalledClient::from_output(output, &self.client_path)
+    }
+
+    fn take_progress(&mut self) -> Option<UnboundedReceiver<Progress>> {
+        self.progress_receiver.take()
+    }
+
+    fn fork(&self) -> Option<Self> {
+        let (progress, progress_receiver) = mpsc::unbounded_channel();
+        Some(DockerClient {
+            container: self.container.clone(),
+            user: self.user.clone(),
+            client_path: self.client_path.clone(),
+            escalation: self.escalation,
+            check: self.check,
+            progress,
+            progress_receiver: Some(progress_receiver),
+        })
+    }
+}
+
+/// Files smaller than this upload in an instant, so they report no progress.
+const UPLOAD_PROGRESS_MIN: u64 = 1 << 20;
+
+/// Copies the file at `from` to `to` a chunk at a time, so that even very large files never have
+/// to fit in memory, and then closes `to`.
+///
+/// For files of at least [UPLOAD_PROGRESS_MIN] bytes, sends [Progress] to `progress` each time
+/// another tenth of the file has been sent.
+async fn send_file(
+    from: &str,
+    mut to: impl AsyncWrite + Unpin,
+    progress: &UnboundedSender<Progress>,
+) -> io::Result<()> {
+    let mut file = tokio::fs::File::open(from).await?;
+    let total = file.metadata().await?.len();
+    let mut buffer = vec![0; 64 * 1024];
+    let (mut sent, mut reported) = (0, 0);
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        to.write_all(&buffer[..read]).await?;
+        sent += read as u64;
+
+        let tenths = (sent * 10 / total.max(1)).min(10);
+        if total >= UPLOAD_PROGRESS_MIN && tenths > reported {
+            reported = tenths;
+            let message = format!(
+                "Uploaded {} of {} ({}%)",
+                format_size(sent),
+                format_size(total),
+                tenths * 10,
+            );
+            // If nobody is listening, the progress is simply dropped.
+            drop(progress.send(Progress::new(message)));
+        }
+    }
+    to.shutdown().await
+}
+
+/// Formats a number of bytes for people, e.g. `1.5 GiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Reads `sira-client`'s `stdout` and `stderr` to the end, sending each line of [Progress] to
+/// `progress` as soon as it arrives. Returns what's left of stdout, which doesn't include the
+/// progress lines, and stderr.
+async fn read_output(
+    stdout: impl AsyncRead + Unpin,
+    mut stderr: impl AsyncRead + Unpin,
+    progress: &UnboundedSender<Progress>,
+) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let read_stdout = async move {
+        let mut stdout = BufReader::new(stdout);
+        let (mut output, mut line) = (Vec::new(), Vec::new());
+        loop {
+            line.clear();
+            if stdout.read_until(b'\n', &mut line).await? == 0 {
+                break;
+            }
+            match Progress::from_line(&line) {
+                // If nobody is listening, the progress is simply dropped.
+                Some(event) => drop(progress.send(event)),
+                None => output.extend_from_slice(&line),
+            }
+        }
+        Ok::<_, io::Error>(output)
+    };
+    let read_stderr = async move {
+        let mut output = Vec::new();
+        stderr.read_to_end(&mut output).await?;
+        Ok::<_, io::Error>(output)
+    };
+    tokio::try_join!(read_stdout, read_stderr)
+}
+
+#[cfg(test)]
+mod test;
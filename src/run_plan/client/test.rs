@@ -0,0 +1,270 @@
+use super::*;
+use std::fs;
+use std::io::Write;
+
+mod send_file {
+    use super::*;
+
+    fn source(size: usize) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let contents: Vec<u8> = (0..size).map(|i| i as u8).collect();
+        file.write_all(&contents).unwrap();
+        file
+    }
+
+    async fn send(size: usize) -> (Vec<u8>, Vec<u8>, Vec<String>) {
+        let file = source(size);
+        let (progress, mut receiver) = mpsc::unbounded_channel();
+        let mut sent = Vec::new();
+        send_file(file.path().to_str().unwrap(), &mut sent, &progress)
+            .await
+            .unwrap();
+        drop(progress);
+        let mut messages = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            messages.push(event.message);
+        }
+        (fs::read(file.path()).unwrap(), sent, messages)
+    }
+
+    #[tokio::test]
+    async fn copies_small_file_without_progress() {
+        let (expected, sent, messages) = send(1000).await;
+        assert_eq!(expected, sent);
+        assert!(messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn copies_large_file_with_progress_every_tenth() {
+        let (expected, sent, messages) = send(2 << 20).await;
+        assert_eq!(expected, sent);
+        assert_eq!(10, messages.len());
+        assert_eq!("Uploaded 256.0 KiB of 2.0 MiB (10%)", messages[0]);
+        assert_eq!("Uploaded 2.0 MiB of 2.0 MiB (100%)", messages[9]);
+    }
+
+    #[tokio::test]
+    async fn returns_error_if_source_is_missing() {
+        let (progress, _receiver) = mpsc::unbounded_channel();
+        assert!(send_file("/nonexistent/sira-upload", Vec::new(), &progress)
+            .await
+            .is_err());
+    }
+}
+
+mod format_size {
+    use super::*;
+
+    #[test]
+    fn works() {
+        assert_eq!("0 B", format_size(0));
+        assert_eq!("1023 B", format_size(1023));
+        assert_eq!("1.0 KiB", format_size(1024));
+        assert_eq!("1.5 GiB", format_size(3 << 29));
+        assert_eq!("2048.0 TiB", format_size(1 << 51));
+    }
+}
+
+mod docker {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::process::Command;
+
+    #[test]
+    fn execs_in_container_as_user() {
+        assert_eq!(
+            vec!["exec", "-i", "web-test"],
+            docker_exec_args("web-test", None)
+        );
+        assert_eq!(
+            vec!["exec", "-i", "--user", "sira", "web-test"],
+            docker_exec_args("web-test", Some("sira")),
+        );
+    }
+
+    /// Runs [DOCKER_UPLOAD_SCRIPT] in `dir` as `docker exec` would, with `contents` on stdin.
+    fn upload(dir: &Path, permissions: &str, contents: &str) {
+        let mut child = Command::new("sh")
+            .args([
+                "-c",
+                DOCKER_UPLOAD_SCRIPT,
+                "sh",
+                FILE_TRANSFER_PATH,
+                permissions,
+            ])
+            .current_dir(dir)
+            .stdin(StdStdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn upload_script_replaces_transfer_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_TRANSFER_PATH);
+        fs::create_dir(&path).unwrap();
+        upload(dir.path(), "", "first");
+        assert_eq!("first", fs::read_to_string(&path).unwrap());
+
+        upload(dir.path(), "0600", "second");
+        assert_eq!("second", fs::read_to_string(&path).unwrap());
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+}
+
+mod connect {
+    use super::*;
+
+    #[tokio::test]
+    async fn fails_with_connection_error_if_container_is_unreachable() {
+        let mut connections = ConnectionManager::default().with_connections(
+            HostConfig {
+                transport: Some(Transport::Docker),
+                ..Default::default()
+            },
+            BTreeMap::new(),
+        );
+        let Err(error) = connections.connect("sira-missing-container").await else {
+            panic!("connected to a container that doesn't exist");
+        };
+        assert!(
+            error.downcast_ref::<ConnectionError>().is_some(),
+            "{error:#}"
+        );
+    }
+}
+
+mod connection_error {
+    use super::*;
+
+    #[test]
+    fn wraps_only_ssh_connection_failures() {
+        let error = ConnectionError::from_ssh(openssh::Error::Disconnected.into());
+        assert!(error.downcast_ref::<ConnectionError>().is_some());
+
+        let error = ConnectionError::from_ssh(anyhow::anyhow!("Action exited with exit code 1"));
+        assert!(error.downcast_ref::<ConnectionError>().is_none());
+    }
+
+    #[test]
+    fn displays_like_wrapped_error() {
+        let error = anyhow::anyhow!("container web-test is not running").context("docker");
+        assert_eq!(
+            "docker: container web-test is not running",
+            format!("{:#}", anyhow::Error::new(ConnectionError(error))),
+        );
+    }
+}
+
+mod installed_client {
+    use super::*;
+    use crate::crypto;
+    use std::process::Command;
+
+    const CHECKSUM: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn parses_each_platforms_output() {
+        for (output, platform) in [
+            (
+                format!("Linux x86_64\n{CHECKSUM}  /opt/sira/bin/sira-client\n"),
+                "x86_64",
+            ),
+            (
+                format!("Darwin arm64\n{CHECKSUM}  /opt/sira/bin/sira-client\n"),
+                "macos-aarch64",
+            ),
+            (format!("OpenBSD amd64\n{CHECKSUM}\n"), "openbsd-x86_64"),
+            (
+                format!("Linux aarch64\n\\{CHECKSUM}  /opt/sira/bin/sira\\nclient\n"),
+                "aarch64",
+            ),
+        ] {
+            let expected = InstalledClient {
+                platform: platform.to_string(),
+                sha256: CHECKSUM.to_string(),
+            };
+            assert_eq!(
+                expected,
+                InstalledClient::parse(&output).unwrap(),
+                "{output:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_missing_checksum() {
+        for output in ["", "Linux x86_64\n", "Linux x86_64\nsha256sum: not found\n"] {
+            assert!(InstalledClient::parse(output).is_err(), "{output:?}");
+        }
+    }
+
+    #[test]
+    fn script_checksums_client() {
+        let mut client = tempfile::NamedTempFile::new().unwrap();
+        client.write_all(b"#!/bin/sh\n").unwrap();
+        let path = client.path().to_str().unwrap();
+        let output = Command::new("sh")
+            .args(["-c", INSTALLED_CLIENT_SCRIPT, "sh", path])
+            .output()
+            .unwrap();
+        let installed = InstalledClient::from_output(output, path).unwrap();
+        assert_eq!(crypto::sha256_file(path).unwrap(), installed.sha256);
+    }
+
+    #[test]
+    fn script_fails_for_missing_client() {
+        let output = Command::new("sh")
+            .args([
+                "-c",
+                INSTALLED_CLIENT_SCRIPT,
+                "sh",
+                "/nonexistent/sira-client",
+            ])
+            .output()
+            .unwrap();
+        let error = InstalledClient::from_output(output, "/nonexistent/sira-client").unwrap_err();
+        assert!(
+            error.to_string().contains("/nonexistent/sira-client"),
+            "{error}"
+        );
+    }
+}
+
+mod settings_for {
+    use super::*;
+
+    #[test]
+    fn drops_escalation_on_rootless_hosts() {
+        let connections = ConnectionManager::default().with_connections(
+            HostConfig {
+                escalation: Some(Escalation::Doas),
+                ..Default::default()
+            },
+            BTreeMap::from([(
+                "shared1".to_string(),
+                HostConfig {
+                    rootless: Some(true),
+                    ..Default::default()
+                },
+            )]),
+        );
+        assert_eq!(
+            Some(Escalation::Doas),
+            connections.settings_for("web1").escalation
+        );
+        assert_eq!(
+            Some(Escalation::None),
+            connections.settings_for("shared1").escalation
+        );
+    }
+}
@@ -0,0 +1,580 @@
+//! Shows a full-screen dashboard on the terminal instead of the usual output, from which the user
+//! can follow and steer the run.
+//!
+//! The dashboard lists every host with its status, e.g. how many of its actions are left, and
+//! shows the reports for the selected host beside the list as they arrive:
+//!
+//! ```text
+//! ┌Hosts──────────────┐┌web2: command: apt-get upgrade -y──────────────────────────────────┐
+//! │ done     web1     ││Connecting                                                         │
+//! │>running  web2 (3) ││Connected                                                          │
+//! │ failed   web3     ││Completed command: apt-get update                                  │
+//! │ waiting  web4 (5) ││Starting  command: apt-get upgrade -y                              │
+//! └───────────────────┘└───────────────────────────────────────────────────────────────────┘
+//!  ↑/↓: select  i: ignore host  r: retry host  a: abort run
+//! ```
+//!
+//! Its keys are:
+//!
+//! - `↑`/`↓` (or `k`/`j`) select a host.
+//! - `i` ignores the selected host, if it hasn't finished: a waiting host never connects, and a
+//!   running host stops before its next action. Either way, it fails.
+//! - `r` retries the selected host, if it failed or was ignored, once the hosts that are running
+//!   have finished. Press it again to change your mind.
+//! - `a` (or `Ctrl-C`) aborts the run, as if with [RunOptions::cancel].
+//! - `y` and `n` answer a confirmation prompt, which appears at the bottom of the screen.
+//! - `q` (or `Esc`) quits, once the run has finished.
+//!
+//! The dashboard is a [Report] like any other frontend, with a [StatusHandle] for the list and a
+//! [CancelToken] for aborting the run. Retrying runs the plan again on only the retried hosts, as
+//! another attempt at the same run, so hooks, history, and notifications that the run finished
+//! still happen once.
+//!
+//! [RunOptions::cancel]: super::RunOptions::cancel
+
+use super::cancel::CancelToken;
+use super::report::{_report, title, Report};
+use super::status::{HostState, HostStatus, StatusHandle};
+use super::{run_attempt, Run, RunOptions};
+use crate::core::{Action, Plan};
+use async_trait::async_trait;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::process::Output;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{oneshot, Notify};
+use tokio::task;
+
+/// The most lines that the dashboard keeps for each host. Older lines scroll away for good.
+pub const MAX_LINES: usize = 1000;
+
+/// How often the dashboard redraws while nothing happens on the keyboard.
+const FRAME: Duration = Duration::from_millis(100);
+
+/// Runs `plan` as [run_plan_with](super::run_plan_with) does, showing a [Dashboard] on the
+/// terminal until the user quits.
+///
+/// If `options` has no [RunOptions::status] or [RunOptions::cancel], the dashboard brings its own.
+///
+/// # Returns
+///
+/// Returns the errors of the hosts that failed, each from the host's last attempt, or `Ok(())` if
+/// every host succeeded in the end.
+pub async fn run(plan: Plan, mut options: RunOptions) -> Result<(), Vec<(String, anyhow::Error)>> {
+    let status = options.status.get_or_insert_with(StatusHandle::new).clone();
+    let cancel = options.cancel.get_or_insert_with(CancelToken::new).clone();
+    let dashboard = Dashboard::new(status, cancel);
+    // If the dashboard can't be shown, the run it would have shown needs to stop.
+    dashboard.state().running = true;
+    let shown = dashboard.show();
+
+    let mut run = Run::new(&options);
+    let mut errors: Vec<(String, anyhow::Error)> = vec![];
+    let mut attempt = (plan.clone(), options.clone());
+    loop {
+        let (attempt_plan, attempt_options) = attempt;
+        let ui = Some(dashboard.clone());
+        let result = run_attempt(&mut run, attempt_plan, attempt_options, ui).await;
+        let failed = result.err().unwrap_or_default();
+        dashboard.finish_attempt(&failed);
+        errors.extend(failed);
+
+        let Some(retries) = dashboard.retries().await else {
+            break;
+        };
+        // A retried host's last attempt replaces its earlier ones.
+        errors.retain(|(host, _)| !retries.contains(host));
+        let mut retry_plan = plan.clone();
+        retry_plan.retain_hosts(|host| retries.contains(host));
+        let mut retry_options = options.clone();
+        // Manifests that select hosts by fact choose from these hosts, too.
+        retry_options.hosts.retain(|host, _| retries.contains(host));
+        attempt = (retry_plan, retry_options);
+    }
+    run.finish(&errors);
+
+    match shown.await {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => eprintln!("Warning: could not show dashboard: {e}"),
+        Err(e) => eprintln!("Warning: dashboard failed: {e}"),
+    }
+    match errors.len() {
+        0 => Ok(()),
+        _ => Err(errors),
+    }
+}
+
+/// Shows every host in a run on the terminal and lets the user steer it. Please see the
+/// [module documentation](self).
+///
+/// Clones share the same dashboard.
+#[derive(Clone, Debug)]
+pub struct Dashboard {
+    /// What the dashboard shows and what the user asked for.
+    state: Arc<Mutex<State>>,
+
+    /// The status of the current attempt at the run.
+    status: StatusHandle,
+
+    /// Aborts the run.
+    cancel: CancelToken,
+
+    /// Wakes [Dashboard::retries] when the user retries a host or quits.
+    changed: Arc<Notify>,
+}
+
+/// What a [Dashboard] shows and what the user asked for.
+#[derive(Debug, Default)]
+struct State {
+    /// Each host's reports, oldest first, and the action that it's running, if any.
+    panes: BTreeMap<String, Pane>,
+
+    /// Each host's status at the end of its last attempt, shown unless the current attempt runs
+    /// the host.
+    earlier: BTreeMap<String, HostStatus>,
+
+    /// The hosts that the user ignored, which stop before their next action.
+    ignored: BTreeSet<String>,
+
+    /// The hosts that the user wants to retry once the current attempt has finished.
+    retries: BTreeSet<String>,
+
+    /// The host that the user selected, if any. If [None], the first host is selected.
+    selected: Option<String>,
+
+    /// Whether an attempt at the run is underway.
+    running: bool,
+
+    /// Whether the user quit.
+    quit: bool,
+
+    /// The actions waiting for the user to confirm them, oldest first.
+    prompts: VecDeque<Prompt>,
+}
+
+/// One host's reports on a [Dashboard].
+#[derive(Debug, Default)]
+struct Pane {
+    /// The reports, oldest first, at most [MAX_LINES] of them.
+    lines: VecDeque<String>,
+
+    /// The title of the action that the host is running, if any.
+    action: Option<String>,
+}
+
+/// An action waiting for the user to confirm it.
+#[derive(Debug)]
+struct Prompt {
+    /// The host that would run the action.
+    host: String,
+
+    /// The action's title. Please see [title].
+    title: String,
+
+    /// Hands the user's answer to [Report::confirm].
+    answer: oneshot::Sender<bool>,
+}
+
+impl Dashboard {
+    /// Creates a [Dashboard] that follows runs through `status` and aborts them with `cancel`,
+    /// which should both be in the runs' [RunOptions].
+    pub fn new(status: StatusHandle, cancel: CancelToken) -> Self {
+        Dashboard {
+            state: Default::default(),
+            status,
+            cancel,
+            changed: Default::default(),
+        }
+    }
+
+    /// Takes over the terminal and shows the dashboard until the user quits, on a thread of its
+    /// own. Once the dashboard closes, e.g. because the terminal failed, nobody can follow or
+    /// answer the run, so it's aborted if it's still underway.
+    pub fn show(&self) -> task::JoinHandle<io::Result<()>> {
+        let dashboard = self.clone();
+        task::spawn_blocking(move || {
+            let result = ratatui::try_init().and_then(|mut terminal| {
+                let result = dashboard.interact(&mut terminal);
+                ratatui::try_restore().and(result)
+            });
+            dashboard.close();
+            result
+        })
+    }
+
+    /// Draws the dashboard and handles keys until the user quits.
+    fn interact(&self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.state().quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(FRAME)? {
+                if let Event::Key(key) = event::read()? {
+                    self.handle(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the dashboard on `frame`.
+    pub fn draw(&self, frame: &mut Frame) {
+        let state = self.state();
+        let rows = self.rows(&state);
+        let selected = state.selected_index(&rows);
+
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        // Leave room for the borders, the highlight symbol, and the number of actions left.
+        let width = rows.iter().map(|(host, _)| host.len()).max().unwrap_or(0) + 20;
+        let width = (width as u16).min(main.width / 2);
+        let [hosts, detail] =
+            Layout::horizontal([Constraint::Length(width), Constraint::Min(0)]).areas(main);
+
+        let items = rows.iter().map(|(host, status)| {
+            let (label, color) = state.label(host, status.state);
+            let mut spans = vec![
+                Span::styled(format!("{label:<8} "), Style::new().fg(color)),
+                Span::raw(host.as_str()),
+            ];
+            if matches!(status.state, HostState::Pending | HostState::Active) {
+                spans.push(Span::raw(format!(" ({})", status.actions_remaining)));
+            }
+            ListItem::new(Line::from(spans))
+        });
+        let list = List::new(items)
+            .block(Block::bordered().title("Hosts"))
+            .highlight_symbol(">")
+            .highlight_style(Style::new().reversed());
+        let mut list_state = ListState::default().with_selected(selected);
+        frame.render_stateful_widget(list, hosts, &mut list_state);
+
+        let host = selected.map(|index| rows[index].0.as_str());
+        let pane = host.and_then(|host| state.panes.get(host));
+        let title = match (host, pane.and_then(|pane| pane.action.as_ref())) {
+            (Some(host), Some(action)) => format!("{host}: {action}"),
+            (Some(host), None) => host.to_string(),
+            (None, _) => String::new(),
+        };
+        // Show the latest lines that fit inside the borders.
+        let height = detail.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = pane
+            .map(|pane| {
+                let skip = pane.lines.len().saturating_sub(height);
+                pane.lines.iter().skip(skip).map(Line::raw).collect()
+            })
+            .unwrap_or_default();
+        let paragraph = Paragraph::new(lines).block(Block::bordered().title(title));
+        frame.render_widget(paragraph, detail);
+
+        let hint = match (
+            state.prompts.front(),
+            state.running,
+            self.cancel.is_cancelled(),
+        ) {
+            (Some(prompt), _, _) => Line::from(format!(
+                " [{}] Run {}? y: yes, n: no",
+                prompt.host, prompt.title,
+            ))
+            .yellow()
+            .bold(),
+            (None, true, false) => {
+                Line::raw(" ↑/↓: select  i: ignore host  r: retry host  a: abort run")
+            }
+            (None, true, true) => Line::raw(" Aborting..."),
+            (None, false, false) => {
+                Line::raw(" Run finished.  ↑/↓: select  r: retry host  q: quit")
+            }
+            (None, false, true) => Line::raw(" Run aborted.  ↑/↓: select  q: quit"),
+        };
+        frame.render_widget(hint, footer);
+    }
+
+    /// Does what the user asked for by pressing `key`. Please see the
+    /// [module documentation](self) for the keys.
+    pub fn handle(&self, key: KeyEvent) {
+        // Releasing a key, where terminals report it, isn't pressing it again.
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        let mut state = self.state();
+        let rows = self.rows(&state);
+        let index = state.selected_index(&rows);
+        let selected = index.map(|index| rows[index].clone());
+        let ctrl_c =
+            key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                let index = index.unwrap_or_default().saturating_sub(1);
+                state.selected = rows.get(index).map(|(host, _)| host.clone());
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                let index = index
+                    .map_or(0, |index| index + 1)
+                    .min(rows.len().saturating_sub(1));
+                state.selected = rows.get(index).map(|(host, _)| host.clone());
+            }
+            KeyCode::Char(answer @ ('y' | 'n')) => {
+                if let Some(prompt) = state.prompts.pop_front() {
+                    // The host may have stopped waiting, e.g. because it was cancelled.
+                    let _ = prompt.answer.send(answer == 'y');
+                }
+            }
+            KeyCode::Char('i') => {
+                let Some((host, status)) = selected else {
+                    return;
+                };
+                let running = matches!(status.state, HostState::Pending | HostState::Active);
+                if running && !state.ignored.contains(&host) {
+                    state.decline(|prompt| prompt.host == host);
+                    state.log(&host, "Ignored from the dashboard");
+                    state.ignored.insert(host);
+                }
+            }
+            KeyCode::Char('r') => {
+                let Some((host, status)) = selected else {
+                    return;
+                };
+                let ended = matches!(status.state, HostState::Failed | HostState::Ignored);
+                if ended && !self.cancel.is_cancelled() && !state.retries.remove(&host) {
+                    state.retries.insert(host);
+                    self.changed.notify_one();
+                }
+            }
+            KeyCode::Char('a') => self.abort(&mut state),
+            _ if ctrl_c && state.running => self.abort(&mut state),
+            KeyCode::Char('q') | KeyCode::Esc if !state.running => {
+                state.quit = true;
+                self.changed.notify_one();
+            }
+            _ if ctrl_c => {
+                state.quit = true;
+                self.changed.notify_one();
+            }
+            _ => (),
+        }
+    }
+
+    /// Aborts the run, declining every action that's waiting for confirmation.
+    fn abort(&self, state: &mut State) {
+        self.cancel.cancel();
+        state.decline(|_| true);
+        state.retries.clear();
+    }
+
+    /// Stops showing the dashboard, aborting the run if it's underway.
+    fn close(&self) {
+        let mut state = self.state();
+        if state.running {
+            self.abort(&mut state);
+        }
+        state.quit = true;
+        self.changed.notify_one();
+    }
+
+    /// Records the end of an attempt at the run, in which `errors` are the hosts that failed.
+    fn finish_attempt(&self, errors: &[(String, anyhow::Error)]) {
+        let status = self.status.query();
+        let mut state = self.state();
+        state.earlier.extend(status.hosts);
+        for (host, error) in errors {
+            // Hosts whose facts couldn't be gathered never started, so they have no status.
+            let failed = HostStatus {
+                state: HostState::Failed,
+                actions_remaining: 0,
+            };
+            state.earlier.entry(host.clone()).or_insert(failed);
+            state.log(host, format!("Failed: {error:#}"));
+        }
+        state.running = false;
+    }
+
+    /// Waits until the user either retries some hosts, which it returns, or quits, in which case it
+    /// returns [None].
+    async fn retries(&self) -> Option<BTreeSet<String>> {
+        loop {
+            {
+                let mut state = self.state();
+                if state.quit {
+                    return None;
+                }
+                if !state.retries.is_empty() && !self.cancel.is_cancelled() {
+                    let retries = std::mem::take(&mut state.retries);
+                    for host in &retries {
+                        state.ignored.remove(host);
+                        state.log(host, "Retrying");
+                        if let Some(status) = state.earlier.get_mut(host) {
+                            status.state = HostState::Pending;
+                        }
+                    }
+                    // The next attempt resets the status anyway. Until then, the hosts' earlier
+                    // statuses show the retried hosts waiting.
+                    self.status.start(std::iter::empty());
+                    state.running = true;
+                    return Some(retries);
+                }
+            }
+            self.changed.notified().await;
+        }
+    }
+
+    /// Returns each host and its status, in alphabetical order, from the current attempt if it
+    /// runs the host, or else from the host's last attempt.
+    fn rows(&self, state: &State) -> Vec<(String, HostStatus)> {
+        let mut rows = state.earlier.clone();
+        rows.extend(self.status.query().hosts);
+        rows.into_iter().collect()
+    }
+
+    /// Returns the dashboard's state, for reading or changing.
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Adds `line` to `host`'s reports, if the user hasn't ignored `host`, and otherwise returns an
+    /// error, so that `host` stops.
+    fn log(&self, host: &str, line: impl Into<String>) -> io::Result<()> {
+        let mut state = self.state();
+        if state.ignored.contains(host) {
+            return Err(io::Error::other("Ignored from the dashboard"));
+        }
+        state.log(host, line);
+        Ok(())
+    }
+}
+
+impl State {
+    /// Adds `line` to `host`'s reports.
+    fn log(&mut self, host: &str, line: impl Into<String>) {
+        let lines = &mut self.panes.entry(host.to_string()).or_default().lines;
+        lines.push_back(line.into());
+        if lines.len() > MAX_LINES {
+            lines.pop_front();
+        }
+    }
+
+    /// Declines every action waiting for confirmation that `matches`.
+    fn decline(&mut self, mut matches: impl FnMut(&Prompt) -> bool) {
+        let (declined, kept) = std::mem::take(&mut self.prompts)
+            .into_iter()
+            .partition(|prompt| matches(prompt));
+        self.prompts = kept;
+        for prompt in declined {
+            let _ = prompt.answer.send(false);
+        }
+    }
+
+    /// Returns the index in `rows` of the selected host, if there are any hosts.
+    fn selected_index(&self, rows: &[(String, HostStatus)]) -> Option<usize> {
+        let selected = self.selected.as_ref();
+        let index =
+            selected.and_then(|selected| rows.iter().position(|(host, _)| host == selected));
+        index.or((!rows.is_empty()).then_some(0))
+    }
+
+    /// Returns the label and color with which to list `host`, in `state`.
+    fn label(&self, host: &str, state: HostState) -> (&'static str, Color) {
+        let (label, color) = match state {
+            HostState::Pending => ("waiting", Color::Gray),
+            HostState::Active => ("running", Color::Yellow),
+            HostState::Succeeded => ("done", Color::Green),
+            HostState::Failed => ("failed", Color::Red),
+            HostState::Ignored => ("ignored", Color::DarkGray),
+        };
+        match (
+            self.retries.contains(host),
+            self.ignored.contains(host),
+            state,
+        ) {
+            (true, _, _) => ("retry", Color::Cyan),
+            (false, true, HostState::Active) => ("stopping", Color::Yellow),
+            (false, true, HostState::Pending | HostState::Failed) => ("ignored", Color::DarkGray),
+            _ => (label, color),
+        }
+    }
+}
+
+/// Shows each report in the reporting host's pane. Hosts that the user ignored stop at their next
+/// connection or action, though the actions that they already started are still reported.
+#[async_trait]
+impl Report for Dashboard {
+    async fn action_source(&mut self, host: &str, _manifest: &str, _task: &str) -> io::Result<()> {
+        // Nothing to show yet, but this is the last chance to stop before the next action.
+        let ignored = self.state().ignored.contains(host);
+        match ignored {
+            true => Err(io::Error::other("Ignored from the dashboard")),
+            false => Ok(()),
+        }
+    }
+
+    async fn network(&mut self, host: &str, event: &str) -> io::Result<()> {
+        self.log(host, event)
+    }
+
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        let title = title(action);
+        self.log(host, format!("Starting  {title}"))?;
+        self.state()
+            .panes
+            .entry(host.to_string())
+            .or_default()
+            .action = Some(title);
+        Ok(())
+    }
+
+    async fn progress(&mut self, host: &str, _action: &Action, message: &str) -> io::Result<()> {
+        self.state().log(host, format!("    {message}"));
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        _report(&mut stdout, &mut stderr, host, action, output)?;
+        stdout.extend(stderr);
+
+        // Every line is about the host whose pane it's in, so drop the host from the start.
+        let prefix = format!("[{host}] ");
+        let mut state = self.state();
+        for line in String::from_utf8_lossy(&stdout).lines() {
+            state.log(host, line.strip_prefix(&prefix).unwrap_or(line));
+        }
+        state.panes.entry(host.to_string()).or_default().action = None;
+        Ok(())
+    }
+
+    async fn verifying(&mut self, host: &str) -> io::Result<()> {
+        self.log(host, "Verifying")
+    }
+
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        self.log(host, format!("Touch your security key to {purpose}."))
+    }
+
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        let (answer, answered) = oneshot::channel();
+        {
+            let mut state = self.state();
+            if state.ignored.contains(host) || self.cancel.is_cancelled() {
+                return Ok(false);
+            }
+            let title = title(action);
+            state.log(host, format!("Waiting for confirmation: {title}"));
+            state.prompts.push_back(Prompt {
+                host: host.to_string(),
+                title,
+                answer,
+            });
+        }
+        // If the dashboard drops the prompt, e.g. because it closed, nobody said yes.
+        Ok(answered.await.unwrap_or(false))
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,251 @@
+use super::*;
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+// Returns a dashboard whose run is underway, with `hosts` pending.
+fn dashboard(hosts: &[&str]) -> Dashboard {
+    let status = StatusHandle::new();
+    status.start(hosts.iter().map(|host| (host.to_string(), 2)));
+    let dashboard = Dashboard::new(status, CancelToken::new());
+    dashboard.state().running = true;
+    dashboard
+}
+
+fn action() -> Action {
+    Action::Command(vec!["echo hi".to_string()])
+}
+
+fn output(code: i32, stdout: &str) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: vec![],
+    }
+}
+
+fn press(dashboard: &Dashboard, code: KeyCode) {
+    dashboard.handle(KeyEvent::from(code));
+}
+
+fn lines(dashboard: &Dashboard, host: &str) -> Vec<String> {
+    let state = dashboard.state();
+    state.panes[host].lines.iter().cloned().collect()
+}
+
+// Draws `dashboard` on a terminal of the given size and returns what's on it, one line per row.
+fn screen(dashboard: &Dashboard, width: u16, height: u16) -> Vec<String> {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+    let buffer = terminal.backend().buffer();
+    (0..height)
+        .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect())
+        .collect()
+}
+
+mod report {
+    use super::*;
+
+    #[tokio::test]
+    async fn shows_each_hosts_reports_in_its_pane() {
+        let mut dashboard = dashboard(&["web1", "web2"]);
+        dashboard.network("web1", "Connecting").await.unwrap();
+        dashboard.starting("web1", &action()).await.unwrap();
+        assert_eq!(
+            Some("command: echo hi"),
+            dashboard.state().panes["web1"].action.as_deref(),
+        );
+
+        dashboard
+            .progress("web1", &action(), "halfway")
+            .await
+            .unwrap();
+        let output = output(0, "hi\n");
+        dashboard.report("web1", &action(), &output).await.unwrap();
+        assert_eq!(
+            vec![
+                "Connecting",
+                "Starting  command: echo hi",
+                "    halfway",
+                "Completed command: echo hi",
+                "    Captured stdout:",
+                "        hi",
+            ],
+            lines(&dashboard, "web1"),
+        );
+        assert_eq!(None, dashboard.state().panes["web1"].action);
+        assert!(!dashboard.state().panes.contains_key("web2"));
+    }
+
+    #[tokio::test]
+    async fn keeps_latest_lines() {
+        let mut dashboard = dashboard(&["web1"]);
+        for i in 0..MAX_LINES + 1 {
+            dashboard.network("web1", &i.to_string()).await.unwrap();
+        }
+        let lines = lines(&dashboard, "web1");
+        assert_eq!(MAX_LINES, lines.len());
+        assert_eq!("1", lines[0]);
+    }
+
+    #[tokio::test]
+    async fn stops_ignored_hosts() {
+        let mut dashboard = dashboard(&["web1", "web2"]);
+        press(&dashboard, KeyCode::Char('i'));
+        assert!(dashboard.network("web1", "Connecting").await.is_err());
+        assert!(dashboard.action_source("web1", "m", "t").await.is_err());
+        dashboard.action_source("web2", "m", "t").await.unwrap();
+
+        // An action that already started is still reported.
+        let output = output(0, "");
+        dashboard.report("web1", &action(), &output).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn asks_for_confirmation() {
+        let dashboard = dashboard(&["web1"]);
+        let mut reporter = dashboard.clone();
+        let confirm = tokio::spawn(async move { reporter.confirm("web1", &action()).await });
+        while dashboard.state().prompts.is_empty() {
+            task::yield_now().await;
+        }
+        assert!(screen(&dashboard, 80, 6)[5].contains("[web1] Run command: echo hi? y: yes"));
+
+        press(&dashboard, KeyCode::Char('y'));
+        assert!(confirm.await.unwrap().unwrap());
+        assert!(dashboard.state().prompts.is_empty());
+    }
+}
+
+mod handle {
+    use super::*;
+
+    #[test]
+    fn selects_hosts() {
+        let dashboard = dashboard(&["web1", "web2", "web3"]);
+        press(&dashboard, KeyCode::Down);
+        press(&dashboard, KeyCode::Char('j'));
+        press(&dashboard, KeyCode::Down);
+        assert_eq!(Some("web3"), dashboard.state().selected.as_deref());
+
+        press(&dashboard, KeyCode::Up);
+        assert_eq!(Some("web2"), dashboard.state().selected.as_deref());
+        press(&dashboard, KeyCode::Char('k'));
+        press(&dashboard, KeyCode::Char('k'));
+        assert_eq!(Some("web1"), dashboard.state().selected.as_deref());
+    }
+
+    #[test]
+    fn retries_only_hosts_that_stopped() {
+        let dashboard = dashboard(&["web1", "web2"]);
+        press(&dashboard, KeyCode::Char('r'));
+        assert!(dashboard.state().retries.is_empty());
+
+        dashboard.status.finish("web1", false);
+        press(&dashboard, KeyCode::Char('r'));
+        assert_eq!(
+            BTreeSet::from(["web1".to_string()]),
+            dashboard.state().retries,
+        );
+
+        // Pressing it again changes your mind.
+        press(&dashboard, KeyCode::Char('r'));
+        assert!(dashboard.state().retries.is_empty());
+    }
+
+    #[test]
+    fn aborts_run() {
+        let dashboard = dashboard(&["web1"]);
+        let (answer, mut answered) = oneshot::channel();
+        dashboard.state().prompts.push_back(Prompt {
+            host: "web1".to_string(),
+            title: "command: echo hi".to_string(),
+            answer,
+        });
+
+        press(&dashboard, KeyCode::Char('a'));
+        assert!(dashboard.cancel.is_cancelled());
+        assert_eq!(Ok(false), answered.try_recv());
+
+        // Nothing can be retried once the run is aborted.
+        dashboard.status.finish("web1", false);
+        press(&dashboard, KeyCode::Char('r'));
+        assert!(dashboard.state().retries.is_empty());
+    }
+
+    #[test]
+    fn quits_only_once_run_has_finished() {
+        let dashboard = dashboard(&["web1"]);
+        press(&dashboard, KeyCode::Char('q'));
+        assert!(!dashboard.state().quit);
+
+        dashboard.finish_attempt(&[]);
+        press(&dashboard, KeyCode::Char('q'));
+        assert!(dashboard.state().quit);
+    }
+
+    #[test]
+    fn ignores_key_releases() {
+        let dashboard = dashboard(&["web1"]);
+        let mut key = KeyEvent::from(KeyCode::Char('a'));
+        key.kind = KeyEventKind::Release;
+        dashboard.handle(key);
+        assert!(!dashboard.cancel.is_cancelled());
+    }
+}
+
+mod retries {
+    use super::*;
+
+    #[tokio::test]
+    async fn returns_retried_hosts() {
+        let dashboard = dashboard(&["web1", "web2"]);
+        dashboard.status.finish("web1", false);
+        dashboard.status.finish("web2", true);
+        let error = anyhow::anyhow!("oops");
+        dashboard.finish_attempt(&[("web1".to_string(), error)]);
+        assert!(lines(&dashboard, "web1").contains(&"Failed: oops".to_string()));
+
+        press(&dashboard, KeyCode::Char('r'));
+        let retries = dashboard.retries().await.unwrap();
+        assert_eq!(BTreeSet::from(["web1".to_string()]), retries);
+        let state = dashboard.state();
+        assert!(state.running);
+        assert_eq!(HostState::Pending, state.earlier["web1"].state);
+        assert_eq!(HostState::Succeeded, state.earlier["web2"].state);
+        let rows = dashboard.rows(&state);
+        assert_eq!(HostState::Pending, rows[0].1.state);
+    }
+
+    #[tokio::test]
+    async fn returns_none_once_user_quits() {
+        let dashboard = dashboard(&["web1"]);
+        dashboard.finish_attempt(&[]);
+        let retries = tokio::spawn({
+            let dashboard = dashboard.clone();
+            async move { dashboard.retries().await }
+        });
+        press(&dashboard, KeyCode::Char('q'));
+        assert_eq!(None, retries.await.unwrap());
+    }
+}
+
+mod draw {
+    use super::*;
+
+    #[tokio::test]
+    async fn lists_hosts_beside_selected_hosts_reports() {
+        let mut dashboard = dashboard(&["web1", "web2"]);
+        dashboard.status.activate("web1");
+        dashboard.starting("web1", &action()).await.unwrap();
+
+        let screen = screen(&dashboard, 80, 6);
+        assert!(screen[0].contains("Hosts"));
+        assert!(screen[0].contains("web1: command: echo hi"));
+        assert!(screen[1].contains(">running  web1 (2)"));
+        assert!(screen[1].contains("Starting  command: echo hi"));
+        assert!(screen[2].contains(" waiting  web2 (2)"));
+        assert!(screen[5].contains("i: ignore host"));
+    }
+}
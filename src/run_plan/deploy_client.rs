@@ -0,0 +1,287 @@
+//! Deploys `sira-client` to managed nodes, e.g. to upgrade it after upgrading Sira.
+//!
+//! For each host, Sira first asks the managed node for its platform (please see [platform]), then
+//! picks the matching `sira-client` binary on the control node (please see [client_binary]).
+//! Hosts that share a platform share a built-in [Plan] (please see
+//! [deploy_plan]) that uploads the binary to [CLIENT_PATH], owned by root, and then runs the newly
+//! installed binary to check that it works.
+//!
+//! Because the upload itself runs through the managed node's current `sira-client`, the node
+//! must already be set up for Sira, e.g. by `sira-install`. Like any other upload, the installed
+//! file's checksum is checked against the source file.
+//!
+//! To catch a `sira-client` that was corrupted or never upgraded, Sira can also check each managed
+//! node's installed `sira-client` against the binary that it would deploy there before running
+//! any actions on the node. Please see [verify_client]. The node reports its client's checksum
+//! itself, so this can't catch a node that's been compromised.
+
+use super::client::{ConnectionManager, InstalledClient, ManageClient, CLIENT_PATH};
+use super::rotate_keys::built_in_plan;
+use super::{connection_manager, run_plan_with, RunOptions, TaskError};
+use crate::client::facts::FACTS_COMMAND;
+use crate::config;
+use crate::core::{Action, Plan};
+use crate::crypto;
+use anyhow::{anyhow, bail, Context};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::OnceCell;
+use tokio::task;
+
+/// The name of the directory in Sira's configuration directory that holds a `sira-client` binary
+/// for each platform, e.g. `/etc/sira/clients/aarch64/sira-client`.
+pub const CLIENTS_DIR: &str = "clients";
+
+/// The file name of the client binary.
+pub const CLIENT_BIN: &str = "sira-client";
+
+/// Returns the `sira-client` binary to deploy to managed nodes on `platform` (please see
+/// [platform()]), if there is one.
+///
+/// Sira first looks in [CLIENTS_DIR], e.g. `/etc/sira/clients/aarch64/sira-client`. If there's no
+/// binary there and `platform` is the control node's own platform, Sira uses the `sira-client`
+/// binary installed alongside `sira`.
+pub fn client_binary(platform: &str) -> Option<PathBuf> {
+    let configured = config::config_dir()
+        .join(CLIENTS_DIR)
+        .join(platform)
+        .join(CLIENT_BIN);
+    if configured.is_file() {
+        return Some(configured);
+    }
+
+    if platform != local_platform() {
+        return None;
+    }
+    let sibling = env::current_exe().ok()?.with_file_name(CLIENT_BIN);
+    sibling.is_file().then_some(sibling)
+}
+
+/// Returns the name of the platform that the output of `uname -sm` describes, e.g. `x86_64` for
+/// `Linux x86_64` or `macos-aarch64` for `Darwin arm64`.
+///
+/// Linux platforms are named for their CPU architecture alone. Others start with the name of their
+/// operating system, so that, e.g., an Intel Mac never receives a Linux binary. Both parts follow
+/// Rust's names (please see [env::consts::OS] and [env::consts::ARCH]), so that the control node
+/// can recognize its own platform.
+pub fn platform(uname: &str) -> String {
+    let mut words = uname.split_whitespace();
+    let os = words.next().unwrap_or_default().to_lowercase();
+    let arch = match words.next().unwrap_or_default() {
+        "arm64" => "aarch64",
+        "amd64" => "x86_64",
+        arch => arch,
+    };
+    match os.as_str() {
+        "linux" => arch.to_string(),
+        "darwin" => format!("macos-{arch}"),
+        os => format!("{os}-{arch}"),
+    }
+}
+
+/// Returns the control node's own platform, named as by [platform()].
+fn local_platform() -> String {
+    match env::consts::OS {
+        "linux" => env::consts::ARCH.to_string(),
+        os => format!("{os}-{}", env::consts::ARCH),
+    }
+}
+
+/// Returns a [Plan] that installs `binary` as `sira-client` at `client_path`, usually
+/// [CLIENT_PATH], on each of `hosts` and then checks that it runs.
+pub fn deploy_plan(hosts: &[String], binary: &str, client_path: &str) -> Plan {
+    built_in_plan(
+        "Deploy sira-client",
+        hosts,
+        vec![
+            Action::Upload {
+                from: binary.to_string(),
+                to: client_path.to_string(),
+                user: "root".to_string(),
+                group: "root".to_string(),
+                permissions: Some("0755".to_string()),
+                overwrite: true,
+                encrypt: false,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: None,
+                xattrs: Default::default(),
+                capabilities: None,
+            },
+            Action::Command(vec![format!("{client_path} {FACTS_COMMAND}")]),
+        ],
+    )
+}
+
+/// Deploys `sira-client` to every one of `hosts`. Please see the [module documentation](self).
+/// Hosts' installed clients aren't verified first, whatever their settings say, since they're
+/// about to be replaced.
+///
+/// # Returns
+///
+/// Like [run_plan_with], returns a list of `(host, error)` tuples for the hosts that failed,
+/// including any whose platform couldn't be determined or has no `sira-client` binary.
+pub async fn deploy_client(
+    hosts: &[String],
+    mut options: RunOptions,
+) -> Result<(), Vec<(String, anyhow::Error)>> {
+    // The clients being replaced are expected not to match, so don't verify them.
+    options.connection.verify_client = Some(false);
+    for host in options.hosts.values_mut() {
+        host.verify_client = None;
+    }
+
+    let mut connections = connection_manager(&options);
+    let mut errors = Vec::new();
+
+    // Group hosts by binary and install path, so that each pair gets one manifest.
+    let mut by_binary: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for host in hosts {
+        let client_path = options
+            .connection_for(host)
+            .client_path
+            .unwrap_or_else(|| CLIENT_PATH.to_string());
+        match binary_for(&mut connections, host).await {
+            Ok(binary) => by_binary
+                .entry((binary, client_path))
+                .or_default()
+                .push(host.clone()),
+            Err(e) => errors.push((host.clone(), e)),
+        }
+    }
+
+    let plan = Plan {
+        manifests: by_binary
+            .iter()
+            .flat_map(|((binary, client_path), hosts)| {
+                deploy_plan(hosts, binary, client_path).manifests
+            })
+            .collect(),
+    };
+    if !plan.manifests.is_empty() {
+        if let Err(plan_errors) = run_plan_with(plan, options).await {
+            errors.extend(plan_errors);
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// The `sira-client` binary that [client_binary] picks for a platform, i.e. the one that
+/// [deploy_client] would install there, along with its checksum.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpectedClient {
+    /// The path to the binary on the control node.
+    pub path: PathBuf,
+
+    /// The SHA-256 checksum of the binary, in lowercase hexadecimal.
+    pub sha256: String,
+}
+
+impl ExpectedClient {
+    /// Returns the [ExpectedClient] for `platform`, reading the whole binary to compute its
+    /// checksum.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there's no binary for `platform` or it can't be read.
+    pub fn for_platform(platform: &str) -> anyhow::Result<Self> {
+        let path = client_binary(platform)
+            .ok_or_else(|| missing_binary(platform))
+            .context("could not verify sira-client")?;
+        let sha256 = crypto::sha256_file(&path)?;
+        Ok(Self { path, sha256 })
+    }
+}
+
+/// The [ExpectedClient] for each platform that a run has verified managed nodes on, so that each
+/// binary is read only once per run, however many hosts share its platform. Clones share the same
+/// checksums.
+#[derive(Clone, Debug, Default)]
+pub struct ExpectedClients(Arc<Mutex<BTreeMap<String, Arc<OnceCell<ExpectedClient>>>>>);
+
+impl ExpectedClients {
+    /// Returns the [ExpectedClient] for `platform`, computing it the first time it's asked for.
+    /// Errors aren't remembered, so the next host on `platform` tries again.
+    pub async fn get(&self, platform: &str) -> anyhow::Result<ExpectedClient> {
+        // Hosts on the same platform wait for one checksum rather than each computing their own,
+        // but hosts on other platforms don't wait at all.
+        let cell = self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(platform.to_string())
+            .or_default()
+            .clone();
+        let expected = cell
+            .get_or_try_init(|| {
+                let platform = platform.to_string();
+                async move {
+                    task::spawn_blocking(move || ExpectedClient::for_platform(&platform))
+                        .await
+                        .map_err(TaskError::from)?
+                }
+            })
+            .await?;
+        Ok(expected.clone())
+    }
+}
+
+/// Checks that `installed`, a managed node's `sira-client`, is `expected`, the binary that
+/// [client_binary] picks for the node's platform. Please see [ExpectedClients] to compute
+/// `expected` once per run.
+///
+/// Sira calls this before running any actions on hosts whose `verify_client` setting is on;
+/// please see [HostConfig::verify_client](crate::config::HostConfig::verify_client).
+///
+/// # Errors
+///
+/// Returns an error if the checksums differ, e.g. because the installed `sira-client` was
+/// corrupted or is out of date.
+pub fn verify_client(installed: &InstalledClient, expected: &ExpectedClient) -> anyhow::Result<()> {
+    if expected.sha256 != installed.sha256 {
+        bail!(
+            "installed sira-client does not match {}: expected SHA-256 checksum {} but found {}; \
+            please run `sira deploy-client` if it is out of date",
+            expected.path.display(),
+            expected.sha256,
+            installed.sha256,
+        );
+    }
+    Ok(())
+}
+
+/// Returns an error saying that there's no `sira-client` binary for `platform` and where to
+/// install one.
+fn missing_binary(platform: &str) -> anyhow::Error {
+    anyhow!(
+        "no sira-client binary for {platform}; please install one at {}",
+        config::config_dir()
+            .join(CLIENTS_DIR)
+            .join(platform)
+            .join(CLIENT_BIN)
+            .display(),
+    )
+}
+
+/// Returns the path to the `sira-client` binary that suits `host`.
+async fn binary_for(connections: &mut ConnectionManager, host: &str) -> anyhow::Result<String> {
+    let client = connections.connect(host).await?;
+    let platform = client.platform().await?;
+    let binary = client_binary(&platform).ok_or_else(|| missing_binary(&platform))?;
+    binary
+        .into_os_string()
+        .into_string()
+        .map_err(|path| anyhow!("sira-client path is not UTF-8: {path:?}"))
+        .context("could not choose a sira-client binary")
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,172 @@
+use super::*;
+
+mod client_binary {
+    use super::*;
+
+    #[test]
+    fn finds_configured_binary() {
+        assert_eq!(
+            Some(
+                config::config_dir()
+                    .join(CLIENTS_DIR)
+                    .join("sira-test-arch")
+                    .join(CLIENT_BIN)
+            ),
+            client_binary("sira-test-arch"),
+        );
+    }
+
+    #[test]
+    fn returns_none_for_unknown_arch() {
+        assert_eq!(None, client_binary("sira-missing-arch"));
+    }
+}
+
+mod platform {
+    use super::*;
+
+    #[test]
+    fn names_linux_by_architecture() {
+        assert_eq!("x86_64", platform("Linux x86_64\n"));
+        assert_eq!("aarch64", platform("Linux aarch64\n"));
+    }
+
+    #[test]
+    fn names_other_systems_by_system_and_architecture() {
+        assert_eq!("macos-aarch64", platform("Darwin arm64\n"));
+        assert_eq!("macos-x86_64", platform("Darwin x86_64\n"));
+        assert_eq!("freebsd-x86_64", platform("FreeBSD amd64\n"));
+    }
+
+    #[test]
+    fn recognizes_control_node() {
+        let uname = std::process::Command::new("uname")
+            .arg("-sm")
+            .output()
+            .unwrap();
+        assert_eq!(
+            local_platform(),
+            platform(&String::from_utf8_lossy(&uname.stdout)),
+        );
+    }
+}
+
+mod deploy_plan {
+    use super::*;
+
+    #[test]
+    fn uploads_and_verifies_binary() {
+        let hosts = vec!["alpha".to_string(), "bravo".to_string()];
+        let plan = deploy_plan(&hosts, "/etc/sira/clients/aarch64/sira-client", CLIENT_PATH);
+        assert_eq!(hosts, plan.hosts());
+        assert_eq!(
+            vec![
+                Action::Upload {
+                    from: "/etc/sira/clients/aarch64/sira-client".to_string(),
+                    to: "/opt/sira/bin/sira-client".to_string(),
+                    user: "root".to_string(),
+                    group: "root".to_string(),
+                    permissions: Some("0755".to_string()),
+                    overwrite: true,
+                    encrypt: false,
+                    seuser: None,
+                    serole: None,
+                    setype: None,
+                    acl: vec![],
+                    transfer_permissions: None,
+                    xattrs: Default::default(),
+                    capabilities: None,
+                },
+                Action::Command(vec!["/opt/sira/bin/sira-client facts".to_string()]),
+            ],
+            plan.manifests[0].include[0].actions,
+        );
+    }
+
+    #[test]
+    fn installs_at_configured_path() {
+        let hosts = vec!["alpha".to_string()];
+        let plan = deploy_plan(&hosts, "sira-client", "/usr/local/bin/sira-client");
+        let actions = &plan.manifests[0].include[0].actions;
+        assert!(matches!(
+            &actions[0],
+            Action::Upload { to, .. } if to == "/usr/local/bin/sira-client",
+        ));
+        assert_eq!(
+            Action::Command(vec!["/usr/local/bin/sira-client facts".to_string()]),
+            actions[1],
+        );
+    }
+}
+
+mod verify_client {
+    use super::*;
+    use std::fs;
+
+    fn installed(sha256: String) -> InstalledClient {
+        InstalledClient {
+            platform: "sira-test-arch".to_string(),
+            sha256,
+        }
+    }
+
+    #[test]
+    fn accepts_matching_client() {
+        let expected = ExpectedClient::for_platform("sira-test-arch").unwrap();
+        verify_client(&installed(expected.sha256.clone()), &expected).unwrap();
+    }
+
+    #[test]
+    fn rejects_mismatched_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CLIENT_BIN);
+        fs::write(&path, "new").unwrap();
+        let expected = ExpectedClient {
+            sha256: crypto::sha256_file(&path).unwrap(),
+            path,
+        };
+        let error = verify_client(&installed("0".repeat(64)), &expected).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("does not match"), "{message}");
+        assert!(message.contains(&"0".repeat(64)), "{message}");
+    }
+}
+
+mod expected_clients {
+    use super::*;
+
+    #[tokio::test]
+    async fn computes_each_checksum_once() {
+        let expected = ExpectedClients::default();
+        let first = expected.get("sira-test-arch").await.unwrap();
+        assert_eq!(
+            crypto::sha256_file(client_binary("sira-test-arch").unwrap()).unwrap(),
+            first.sha256,
+        );
+
+        // A clone shares the checksum that was already computed.
+        let tampered = ExpectedClient {
+            sha256: "0".repeat(64),
+            ..first
+        };
+        expected
+            .0
+            .lock()
+            .unwrap()
+            .insert("sira-test-arch".to_string(), Arc::new(tampered.into()));
+        assert_eq!(
+            "0".repeat(64),
+            expected.clone().get("sira-test-arch").await.unwrap().sha256
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_platform_without_binary() {
+        let error = ExpectedClients::default()
+            .get("sira-missing-arch")
+            .await
+            .unwrap_err();
+        let message = format!("{error:#}");
+        assert!(message.contains("no sira-client binary"), "{message}");
+    }
+}
@@ -0,0 +1,163 @@
+//! Turns a run into a compliance audit: each host reports which of the states that its manifests
+//! declare it doesn't currently meet, and nothing changes.
+//!
+//! Pass a [DriftReport] in [RunOptions::check], keep a clone of it, and read it once the run is
+//! over:
+//!
+//! ```no_run
+//! use sira::core::Plan;
+//! use sira::run_plan::drift::DriftReport;
+//! use sira::run_plan::{run_plan_with, RunOptions};
+//!
+//! # async fn example(plan: Plan) {
+//! let drift = DriftReport::new();
+//! let options = RunOptions {
+//!     check: Some(drift.clone()),
+//!     ..Default::default()
+//! };
+//! let _ = run_plan_with(plan, options).await;
+//! for (host, host_drift) in drift.query() {
+//!     println!("{host}: {} states not met", host_drift.unmet.len());
+//! }
+//! # }
+//! ```
+//!
+//! In such a run, `sira-client` runs each action in check mode (please see [CHECK_FLAG]): it
+//! compares the managed node's current state with the state that the action declares and reports
+//! whether the action would change anything, e.g. with the diff that a `line_in_file` action would
+//! make, but changes nothing. Since nothing changes, no action asks for confirmation, and the checks
+//! that tasks list under `verify` don't run.
+//!
+//! Each action then either meets its declared state, doesn't meet it, or can't tell. `sira-client`
+//! can't tell what a command, a script, or a `controller_key` action would change without running
+//! it, so those are listed apart from the states that aren't met rather than counted as drift.
+//! Because each action sees the node as it is, not as earlier actions would have left it, an action
+//! that depends on an earlier one, e.g. an edit to a file that an earlier upload installs, reports
+//! drift whenever the earlier one does. A host that fails only lists the actions that it checked
+//! before failing.
+//!
+//! [CHECK_FLAG]: crate::client::CHECK_FLAG
+//! [RunOptions::check]: super::RunOptions::check
+
+use super::report::{print_host_message, title, Report};
+use crate::core::action::Status;
+use crate::core::Action;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+
+/// Records, for each host in a check-mode run, which declared states it meets. Please see the
+/// [module documentation](self).
+///
+/// Clones share the same record, so reading any clone shows the same run.
+#[derive(Clone, Debug, Default)]
+pub struct DriftReport {
+    hosts: Arc<Mutex<BTreeMap<String, HostDrift>>>,
+}
+
+impl DriftReport {
+    /// Creates an empty [DriftReport].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns what each host reported so far, in alphabetical order by host.
+    pub fn query(&self) -> BTreeMap<String, HostDrift> {
+        self.hosts.lock().unwrap().clone()
+    }
+
+    /// Returns whether every host met every declared state that it could check.
+    pub fn is_compliant(&self) -> bool {
+        self.hosts
+            .lock()
+            .unwrap()
+            .values()
+            .all(|host| host.unmet.is_empty())
+    }
+
+    /// Writes a report to `stdout` with a line for each host, followed by the titles of the
+    /// actions whose states it doesn't meet and of those it couldn't check.
+    pub fn write<O: Write>(&self, stdout: &mut O) -> io::Result<()> {
+        for (host, drift) in self.query() {
+            let checked = drift.met + drift.unmet.len();
+            let summary = match drift.unmet.len() {
+                0 => format!("Compliant: meets all {checked} checked states"),
+                unmet => format!("Drifted: {unmet} of {checked} checked states not met"),
+            };
+            print_host_message(stdout, &host, summary)?;
+            for title in &drift.unmet {
+                writeln!(stdout, "    {title}")?;
+            }
+            if !drift.unknown.is_empty() {
+                print_host_message(
+                    stdout,
+                    &host,
+                    format!("Can't check without running: {}", drift.unknown.len()),
+                )?;
+                for title in &drift.unknown {
+                    writeln!(stdout, "    {title}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reports are equal if they're clones of each other, so that they show the same run.
+impl PartialEq for DriftReport {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.hosts, &other.hosts)
+    }
+}
+
+impl Eq for DriftReport {}
+
+/// Sorts each action that a host finishes by whether it would have changed anything.
+#[async_trait]
+impl Report for DriftReport {
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let drift = hosts.entry(host.to_string()).or_default();
+        match Status::from_output(output) {
+            Status::Ok => drift.met += 1,
+            Status::Changed if can_check(action) => drift.unmet.push(title(action)),
+            Status::Changed => drift.unknown.push(title(action)),
+            // The host's error says what went wrong.
+            Status::Failed => (),
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `sira-client` can tell whether `action` would change anything without running
+/// it.
+fn can_check(action: &Action) -> bool {
+    !matches!(
+        action,
+        Action::Command(_) | Action::ControllerKey { .. } | Action::Script { .. }
+    )
+}
+
+/// What one host reported in a check-mode run. Please see [DriftReport].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostDrift {
+    /// How many actions' declared states the host already meets.
+    pub met: usize,
+
+    /// The titles of the actions whose declared states the host doesn't meet, i.e. that would
+    /// have changed something, in the order in which they ran.
+    pub unmet: Vec<String>,
+
+    /// The titles of the actions that couldn't be checked without running them, e.g. commands, in
+    /// the order in which they ran.
+    pub unknown: Vec<String>,
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,102 @@
+use super::*;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn output(code: i32, status: Status) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: format!("{}\n", status.line()).into_bytes(),
+        stderr: vec![],
+    }
+}
+
+fn line(path: &str) -> Action {
+    Action::LineInFile {
+        path: path.to_string(),
+        line: "a".to_string(),
+        pattern: None,
+        after: None,
+        indent: false,
+    }
+}
+
+fn command() -> Action {
+    Action::Command(vec!["true".to_string()])
+}
+
+/// Returns a report in which `web1` meets one state, misses another, and ran a command, and `web2`
+/// meets one state.
+async fn reported() -> DriftReport {
+    let mut drift = DriftReport::new();
+    let ok = output(0, Status::Ok);
+    let changed = output(0, Status::Changed);
+    drift.report("web1", &line("/a"), &ok).await.unwrap();
+    drift.report("web1", &line("/b"), &changed).await.unwrap();
+    drift.report("web1", &command(), &changed).await.unwrap();
+    drift.report("web2", &line("/a"), &ok).await.unwrap();
+    drift
+}
+
+mod drift_report {
+    use super::*;
+
+    #[tokio::test]
+    async fn sorts_actions_by_status() {
+        let hosts = reported().await.query();
+        assert_eq!(
+            HostDrift {
+                met: 1,
+                unmet: vec!["line_in_file (/b): a".to_string()],
+                unknown: vec!["command: true".to_string()],
+            },
+            hosts["web1"],
+        );
+        assert_eq!(
+            HostDrift {
+                met: 1,
+                ..Default::default()
+            },
+            hosts["web2"],
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_failed_actions() {
+        let mut drift = DriftReport::new();
+        let failed = output(1, Status::Failed);
+        drift.report("web1", &line("/a"), &failed).await.unwrap();
+        assert_eq!(HostDrift::default(), drift.query()["web1"]);
+        assert!(drift.is_compliant());
+    }
+
+    #[tokio::test]
+    async fn is_compliant_unless_a_state_is_unmet() {
+        assert!(DriftReport::new().is_compliant());
+        let mut drift = DriftReport::new();
+        let changed = output(0, Status::Changed);
+        drift.report("web1", &command(), &changed).await.unwrap();
+        assert!(drift.is_compliant());
+        assert!(!reported().await.is_compliant());
+    }
+
+    #[tokio::test]
+    async fn writes_each_host() {
+        let mut written = vec![];
+        reported().await.write(&mut written).unwrap();
+        assert_eq!(
+            "[web1] Drifted: 1 of 2 checked states not met\n    \
+                line_in_file (/b): a\n\
+            [web1] Can't check without running: 1\n    \
+                command: true\n\
+            [web2] Compliant: meets all 1 checked states\n",
+            String::from_utf8(written).unwrap(),
+        );
+    }
+
+    #[test]
+    fn shares_state_with_clones() {
+        let drift = DriftReport::new();
+        assert_eq!(drift, drift.clone());
+        assert_ne!(drift, DriftReport::new());
+    }
+}
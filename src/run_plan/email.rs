@@ -0,0 +1,254 @@
+//! Emails a summary of each run that ends with failures, e.g. for unattended scheduled runs.
+//!
+//! To turn this on, write an email configuration file on the control node (please see
+//! [email_path]), e.g.:
+//!
+//! ```yaml
+//! from: sira@example.com
+//! to:
+//!   - ops@example.com
+//! ```
+//!
+//! Sira hands each email to `sendmail`, which most mail transfer agents provide (e.g. Postfix,
+//! Exim, OpenSMTPD, or msmtp for relaying through another SMTP server). To use a different
+//! program with the same interface, set `sendmail` to its path.
+//!
+//! The email lists the hosts that failed and their errors, followed by each failed action's
+//! captured output.
+
+use super::report::{title, Report};
+use super::webhook::{summary, Event};
+use crate::config;
+use crate::core::Action;
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+
+/// The name of the email configuration file in Sira's configuration directory.
+pub const EMAIL_FILE: &str = "email.yaml";
+
+/// Where and how to send emails about failed runs. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    /// The address from which to send email.
+    pub from: String,
+
+    /// The addresses to which to send email.
+    pub to: Vec<String>,
+
+    /// The subject line. Defaults to one naming how many hosts failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+
+    /// The `sendmail`-compatible program that sends the email.
+    #[serde(default = "default_sendmail")]
+    pub sendmail: String,
+}
+
+fn default_sendmail() -> String {
+    "sendmail".to_string()
+}
+
+impl EmailConfig {
+    /// Parses an email configuration from YAML, checking that every field is usable.
+    pub fn from_yaml(yaml: &str) -> anyhow::Result<Self> {
+        let config: EmailConfig = serde_yaml::from_str(yaml)?;
+        if config.to.is_empty() {
+            bail!("at least one address is required in \"to\"");
+        }
+
+        // These values end up in email headers, where a line break would start a new header.
+        let headers = config
+            .to
+            .iter()
+            .chain([&config.from])
+            .chain(config.subject.as_ref());
+        for value in headers {
+            if value.contains(['\r', '\n']) {
+                bail!("email addresses and subject must not contain line breaks: {value:?}");
+            }
+        }
+        Ok(config)
+    }
+
+    /// Loads the email configuration file, if installed. Please see [email_path].
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        let path = email_path();
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+
+        let yaml = fs::read_to_string(&path)
+            .with_context(|| format!("could not read email configuration: {}", path.display()))?;
+        let config = EmailConfig::from_yaml(&yaml)
+            .with_context(|| format!("could not parse email configuration: {}", path.display()))?;
+        Ok(Some(config))
+    }
+}
+
+/// Returns the path to the email configuration file, e.g. `/etc/sira/email.yaml`.
+pub fn email_path() -> PathBuf {
+    config::config_dir().join(EMAIL_FILE)
+}
+
+/// The action that failed on a host, and its output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Failure {
+    title: String,
+    stdout: String,
+    stderr: String,
+}
+
+/// Emails a summary of each run that ends with failures. Please see the
+/// [module documentation](self).
+///
+/// Because [EmailNotifier] implements [Report], it sees each action's outcome as the run
+/// progresses. It can't tell when the run is over, though, so whoever runs the
+/// [Plan](crate::core::Plan) must call [EmailNotifier::finished].
+///
+/// Clones share the same record of failed actions.
+#[derive(Clone, Debug)]
+pub struct EmailNotifier {
+    /// Where and how to send email.
+    config: EmailConfig,
+
+    /// Maps each host to the action that failed on it, if any.
+    failures: Arc<Mutex<BTreeMap<String, Failure>>>,
+}
+
+impl EmailNotifier {
+    /// Creates an [EmailNotifier] that sends email as configured by `config`.
+    pub fn new(config: EmailConfig) -> Self {
+        EmailNotifier {
+            config,
+            failures: Arc::default(),
+        }
+    }
+
+    /// Emails a summary of the run, if any host failed.
+    ///
+    /// `hosts` lists every host in the plan, and `errors` lists the hosts that failed.
+    pub fn finished(
+        &self,
+        hosts: &[String],
+        errors: &[(String, anyhow::Error)],
+    ) -> anyhow::Result<()> {
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let failures = self.failures.lock().unwrap().clone();
+        let email = message(&self.config, &summary(hosts, errors), &failures);
+        send(&self.config, &email)
+    }
+}
+
+#[async_trait]
+impl Report for EmailNotifier {
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        if !output.status.success() {
+            let failure = Failure {
+                title: title(action),
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            };
+            self.failures
+                .lock()
+                .unwrap()
+                .insert(host.to_string(), failure);
+        }
+        Ok(())
+    }
+}
+
+/// Composes an email, with headers, summarizing a run.
+///
+/// # Panics
+///
+/// Panics if `summary` is not an [Event::RunFinished].
+fn message(config: &EmailConfig, summary: &Event, failures: &BTreeMap<String, Failure>) -> String {
+    let Event::RunFinished { succeeded, failed } = summary else {
+        panic!("called message with an Event that was not a RunFinished: {summary:?}");
+    };
+    let total = succeeded.len() + failed.len();
+    let subject = config
+        .subject
+        .clone()
+        .unwrap_or_else(|| format!("Sira run failed on {} of {total} hosts", failed.len()));
+
+    let mut email = String::new();
+    // Writing to a String can't fail, so the results of writeln! are safe to ignore.
+    let _ = writeln!(email, "From: {}", config.from);
+    let _ = writeln!(email, "To: {}", config.to.join(", "));
+    let _ = writeln!(email, "Subject: {subject}");
+    let _ = writeln!(email, "MIME-Version: 1.0");
+    let _ = writeln!(email, "Content-Type: text/plain; charset=utf-8");
+    let _ = writeln!(email);
+
+    let _ = writeln!(
+        email,
+        "Sira finished a run on {total} hosts: {} succeeded, {} failed.\n",
+        succeeded.len(),
+        failed.len(),
+    );
+    let _ = writeln!(email, "Failed hosts:\n");
+    for (host, error) in failed {
+        let _ = writeln!(email, "[{host}] {error}");
+    }
+
+    for (host, failure) in failures {
+        let _ = writeln!(email, "\n[{host}] Last action: {}", failure.title);
+        for (name, captured) in [("stdout", &failure.stdout), ("stderr", &failure.stderr)] {
+            if !captured.is_empty() {
+                let _ = writeln!(email, "    Captured {name}:");
+                for line in captured.lines() {
+                    let _ = writeln!(email, "        {line}");
+                }
+            }
+        }
+    }
+    email
+}
+
+/// Sends `email` to the configured recipients via `sendmail`.
+fn send(config: &EmailConfig, email: &str) -> anyhow::Result<()> {
+    // sendmail -i -f <from> -- <to>...
+    let mut child = Command::new(&config.sendmail)
+        .args(["-i", "-f", &config.from, "--"])
+        .args(&config.to)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("could not run {}", config.sendmail))?;
+    child
+        .stdin
+        .take()
+        .expect("sendmail's stdin should be piped")
+        .write_all(email.as_bytes())
+        .context("could not send email to sendmail")?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "{} exited with error: {}",
+            config.sendmail,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test;
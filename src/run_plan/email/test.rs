@@ -0,0 +1,138 @@
+use super::*;
+use anyhow::anyhow;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::ExitStatus;
+
+fn config(sendmail: &str) -> EmailConfig {
+    EmailConfig {
+        from: "sira@example.com".to_string(),
+        to: vec!["ops@example.com".to_string(), "dev@example.com".to_string()],
+        subject: None,
+        sendmail: sendmail.to_string(),
+    }
+}
+
+/// Writes a fake sendmail to `dir` that saves its arguments and input, and returns its path.
+fn fake_sendmail(dir: &Path) -> String {
+    let path = dir.join("sendmail");
+    fs::write(
+        &path,
+        format!(
+            "#!/bin/sh\necho \"$@\" > {0}/args\ncat > {0}/email\n",
+            dir.display(),
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+mod from_yaml {
+    use super::*;
+
+    #[test]
+    fn parses_minimal_config() {
+        let yaml = "from: sira@example.com\nto:\n  - ops@example.com\n";
+        let config = EmailConfig::from_yaml(yaml).unwrap();
+        assert_eq!("sira@example.com", config.from);
+        assert_eq!(vec!["ops@example.com".to_string()], config.to);
+        assert_eq!(None, config.subject);
+        assert_eq!("sendmail", config.sendmail);
+    }
+
+    #[test]
+    fn requires_recipients() {
+        assert!(EmailConfig::from_yaml("from: sira@example.com\nto: []\n").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let yaml = "from: a@example.com\nto: [b@example.com]\ncc: [c@example.com]\n";
+        assert!(EmailConfig::from_yaml(yaml).is_err());
+    }
+
+    #[test]
+    fn rejects_header_injection() {
+        let yaml =
+            "from: a@example.com\nto: [b@example.com]\nsubject: \"Hi\\nBcc: c@example.com\"\n";
+        assert!(EmailConfig::from_yaml(yaml).is_err());
+    }
+}
+
+mod load {
+    use super::*;
+
+    #[test]
+    fn returns_none_if_not_installed() {
+        assert_eq!(None, EmailConfig::load().unwrap());
+    }
+}
+
+mod finished {
+    use super::*;
+
+    fn hosts() -> Vec<String> {
+        vec!["web1".to_string(), "web2".to_string()]
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_summary_with_failed_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut notifier = EmailNotifier::new(config(&fake_sendmail(dir.path())));
+        let output = Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: b"out\n".to_vec(),
+            stderr: b"disk full\n".to_vec(),
+        };
+        let action = Action::Command(vec!["false".to_string()]);
+        notifier.report("web2", &action, &output).await.unwrap();
+
+        let errors = vec![(
+            "web2".to_string(),
+            anyhow!("Action exited with exit code 1: command: false"),
+        )];
+        notifier.finished(&hosts(), &errors).unwrap();
+
+        assert_eq!(
+            "-i -f sira@example.com -- ops@example.com dev@example.com\n",
+            fs::read_to_string(dir.path().join("args")).unwrap(),
+        );
+        assert_eq!(
+            "From: sira@example.com\n\
+            To: ops@example.com, dev@example.com\n\
+            Subject: Sira run failed on 1 of 2 hosts\n\
+            MIME-Version: 1.0\n\
+            Content-Type: text/plain; charset=utf-8\n\
+            \n\
+            Sira finished a run on 2 hosts: 1 succeeded, 1 failed.\n\
+            \n\
+            Failed hosts:\n\
+            \n\
+            [web2] Action exited with exit code 1: command: false\n\
+            \n\
+            [web2] Last action: command: false\n\
+            \x20   Captured stdout:\n\
+            \x20       out\n\
+            \x20   Captured stderr:\n\
+            \x20       disk full\n",
+            fs::read_to_string(dir.path().join("email")).unwrap(),
+        );
+    }
+
+    #[test]
+    fn does_nothing_if_run_succeeded() {
+        let dir = tempfile::tempdir().unwrap();
+        let notifier = EmailNotifier::new(config(&fake_sendmail(dir.path())));
+        notifier.finished(&hosts(), &[]).unwrap();
+        assert!(!dir.path().join("email").exists());
+    }
+
+    #[test]
+    fn returns_error_if_sendmail_fails() {
+        let notifier = EmailNotifier::new(config("false"));
+        let errors = vec![("web1".to_string(), anyhow!("oops"))];
+        assert!(notifier.finished(&hosts(), &errors).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! A stable entry point for running Sira from inside a larger Rust program.
+//!
+//! [run_plan](super::run_plan) wires a [Plan] to SSH, the terminal, and the logs that [RunOptions]
+//! asks for. An embedder usually wants its own wiring instead, e.g. to report progress to its own
+//! UI, to keep records in its own database, or to reach managed nodes some other way. [run] takes
+//! each of these as a separate implementation:
+//!
+//! - **Network:** a [ManageClient] that connects to managed nodes, e.g. Sira's own
+//!   [ConnectionManager](super::client::ConnectionManager).
+//! - **UI:** a [Report] that keeps the user informed and, if the [Plan] has actions that require
+//!   confirmation, asks the user to approve them.
+//! - **Logger:** a [Report] that keeps a record of the run, e.g. an
+//!   [AuditLog](super::audit::AuditLog) or [JsonLog](super::json_log::JsonLog). Pass `()` for none.
+//!
+//! # Stability
+//!
+//! Within a major version (or, before 1.0, a minor version), Sira keeps the signature of [run],
+//! the [Report], [ManageClient], and [ClientInterface] traits, and the public API of [RunResult]
+//! and [HostOutcome] compatible: new trait methods come with default implementations, and new
+//! outcomes come with a new major version. Everything else in Sira may change at any time.
+
+use super::client::{ClientInterface, InstalledClient, ManageClient};
+use super::report::Report;
+use super::{_run_plan, panic_message, RunOptions};
+use crate::client::facts::Facts;
+use crate::core::action::Progress;
+use crate::core::{Action, Plan};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::future::{self, Future};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::process::Output;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// How a [Plan] went on one host.
+#[derive(Debug)]
+pub enum HostOutcome {
+    /// Every action on the host succeeded.
+    Succeeded,
+
+    /// The host stopped early, e.g. because Sira could not connect to it or because an action
+    /// failed. Later actions did not run on this host.
+    Failed(anyhow::Error),
+}
+
+impl HostOutcome {
+    /// Returns whether every action on the host succeeded.
+    pub fn is_success(&self) -> bool {
+        matches!(self, HostOutcome::Succeeded)
+    }
+}
+
+/// The outcome of [run] on each host in the [Plan].
+#[derive(Debug, Default)]
+pub struct RunResult {
+    /// Each host's [HostOutcome], by host name.
+    pub hosts: BTreeMap<String, HostOutcome>,
+}
+
+impl RunResult {
+    /// Returns whether the [Plan] succeeded on every host.
+    pub fn is_success(&self) -> bool {
+        self.hosts.values().all(HostOutcome::is_success)
+    }
+
+    /// Returns the hosts on which every action succeeded, in alphabetical order.
+    pub fn succeeded(&self) -> impl Iterator<Item = &str> {
+        self.hosts
+            .iter()
+            .filter(|(_, outcome)| outcome.is_success())
+            .map(|(host, _)| host.as_str())
+    }
+
+    /// Returns the hosts that stopped early, in alphabetical order, with the error that stopped
+    /// each one.
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &anyhow::Error)> {
+        self.hosts
+            .iter()
+            .filter_map(|(host, outcome)| match outcome {
+                HostOutcome::Succeeded => None,
+                HostOutcome::Failed(e) => Some((host.as_str(), e)),
+            })
+    }
+}
+
+/// Runs `plan` on each of its hosts in parallel, connecting through `network` and reporting to
+/// both `ui` and `logger`.
+///
+/// Behaves like [run_plan](super::run_plan) in every other way: a host that fails runs no further
+/// actions, but other hosts run to completion. Of `options`, only those that affect how actions run
+/// apply, e.g. [RunOptions::concurrency], [RunOptions::confirm], [RunOptions::action_key], and
+/// [RunOptions::cancel]. Options that choose reporters or a connection manager, e.g.
+/// [RunOptions::json_log] or [RunOptions::connection], are ignored, since `ui`, `logger`, and
+/// `network` replace them.
+///
+/// Sira clones `network`, `ui`, and `logger` once per host, so implementations that keep state
+/// across hosts should share it, e.g. with an [Arc](std::sync::Arc).
+///
+/// Sira's own reporters, e.g. [AuditLog](super::audit::AuditLog), write files from within the
+/// runtime, so they need a multi-threaded [tokio] runtime.
+///
+/// If `network`, `ui`, or `logger` panics while running a host, that host fails with
+/// [TaskError::EmbedderPanicked](super::TaskError::EmbedderPanicked) rather than crashing the
+/// program.
+pub async fn run<C, N, U, L>(
+    plan: Plan,
+    network: N,
+    ui: U,
+    logger: L,
+    options: RunOptions,
+) -> RunResult
+where
+    C: ClientInterface + Send,
+    N: ManageClient<C> + Clone + Send + 'static,
+    U: Report + Clone + Send + 'static,
+    L: Report + Clone + Send + 'static,
+{
+    let mut hosts: BTreeMap<String, HostOutcome> = plan
+        .hosts()
+        .into_iter()
+        .map(|host| (host, HostOutcome::Succeeded))
+        .collect();
+    let reporter = (Embedded(ui), Embedded(logger));
+    if let Err(errors) = _run_plan(plan, Embedded(network), reporter, options).await {
+        for (host, error) in errors {
+            let _ = hosts.insert(host, HostOutcome::Failed(error));
+        }
+    }
+    RunResult { hosts }
+}
+
+/// Wraps a [Report], [ManageClient], or [ClientInterface] that the program running Sira passed in,
+/// so that if it panics, the host fails with
+/// [TaskError::EmbedderPanicked](super::TaskError::EmbedderPanicked) rather than with
+/// [TaskError::Panicked](super::TaskError::Panicked), which is a bug in Sira.
+#[derive(Clone, Debug)]
+pub(crate) struct Embedded<T>(pub(crate) T);
+
+/// The payload with which a panic leaves an [Embedded] implementation: the original panic's
+/// message, if it had one.
+pub(crate) struct EmbedderPanic(pub(crate) Option<String>);
+
+/// Runs `f`, passing on any panic as an [EmbedderPanic].
+fn blame<T>(f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        panic::resume_unwind(Box::new(EmbedderPanic(panic_message(payload))))
+    })
+}
+
+/// Awaits `future`, passing on any panic as an [EmbedderPanic].
+async fn blame_async<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    future::poll_fn(|cx| blame(|| future.as_mut().poll(cx))).await
+}
+
+#[async_trait]
+impl<R: Report + Send> Report for Embedded<R> {
+    async fn plan_started(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        blame_async(self.0.plan_started(plan_id, hosts)).await
+    }
+
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        blame_async(self.0.action_source(host, manifest, task)).await
+    }
+
+    async fn network(&mut self, host: &str, event: &str) -> io::Result<()> {
+        blame_async(self.0.network(host, event)).await
+    }
+
+    async fn connected(&mut self, host: &str, duration: Duration) -> io::Result<()> {
+        blame_async(self.0.connected(host, duration)).await
+    }
+
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        blame_async(self.0.starting(host, action)).await
+    }
+
+    async fn progress(&mut self, host: &str, action: &Action, message: &str) -> io::Result<()> {
+        blame_async(self.0.progress(host, action, message)).await
+    }
+
+    async fn timing(&mut self, host: &str, action: &Action, duration: Duration) -> io::Result<()> {
+        blame_async(self.0.timing(host, action, duration)).await
+    }
+
+    async fn transferred(&mut self, host: &str, action: &Action, bytes: u64) -> io::Result<()> {
+        blame_async(self.0.transferred(host, action, bytes)).await
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        blame_async(self.0.report(host, action, output)).await
+    }
+
+    async fn verifying(&mut self, host: &str) -> io::Result<()> {
+        blame_async(self.0.verifying(host)).await
+    }
+
+    async fn plan_finished(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        blame_async(self.0.plan_finished(plan_id, hosts)).await
+    }
+
+    async fn finished(&mut self) -> io::Result<()> {
+        blame_async(self.0.finished()).await
+    }
+
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        blame_async(self.0.touch_required(host, purpose)).await
+    }
+
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        blame_async(self.0.confirm(host, action)).await
+    }
+}
+
+#[async_trait]
+impl<C, N> ManageClient<Embedded<C>> for Embedded<N>
+where
+    C: ClientInterface + Send,
+    N: ManageClient<C> + Send,
+{
+    async fn connect(&mut self, host: &str) -> anyhow::Result<Embedded<C>> {
+        blame_async(self.0.connect(host)).await.map(Embedded)
+    }
+}
+
+#[async_trait]
+impl<C: ClientInterface + Send> ClientInterface for Embedded<C> {
+    async fn command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        blame_async(self.0.command(yaml, signature)).await
+    }
+
+    async fn controller_key(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        blame_async(self.0.controller_key(yaml, signature)).await
+    }
+
+    async fn custom(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        blame_async(self.0.custom(yaml, signature)).await
+    }
+
+    async fn kernel_module(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        blame_async(self.0.kernel_module(yaml, signature)).await
+    }
+
+    async fn line_in_file(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        blame_async(self.0.line_in_file(yaml, signature)).await
+    }
+
+    async fn script(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        blame_async(self.0.script(yaml, signature)).await
+    }
+
+    async fn swap(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        blame_async(self.0.swap(yaml, signature)).await
+    }
+
+    async fn upload(
+        &mut self,
+        from: &str,
+        transfer_permissions: Option<&str>,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> anyhow::Result<Output> {
+        blame_async(self.0.upload(from, transfer_permissions, yaml, signature)).await
+    }
+
+    async fn ping(&mut self) -> anyhow::Result<String> {
+        blame_async(self.0.ping()).await
+    }
+
+    async fn facts(&mut self) -> anyhow::Result<Facts> {
+        blame_async(self.0.facts()).await
+    }
+
+    async fn installed_client(&mut self) -> anyhow::Result<InstalledClient> {
+        blame_async(self.0.installed_client()).await
+    }
+
+    fn take_progress(&mut self) -> Option<UnboundedReceiver<Progress>> {
+        blame(|| self.0.take_progress())
+    }
+
+    fn fork(&self) -> Option<Self> {
+        blame(|| self.0.fork()).map(Embedded)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,126 @@
+use super::*;
+use crate::core::fixtures::plan;
+use crate::run_plan::test::fixtures::{TestClientFactory, TestReporter};
+use crate::run_plan::TaskError;
+
+fn plan_for(hosts: &[&str]) -> Plan {
+    let (mut plan, _, _, _) = plan();
+    plan.manifests[0].hosts = hosts.iter().map(|host| host.to_string()).collect();
+    plan
+}
+
+mod run {
+    use super::*;
+
+    // A frontend that panics when an action starts on a host.
+    #[derive(Clone)]
+    struct PanicsOnStart;
+
+    #[async_trait]
+    impl Report for PanicsOnStart {
+        async fn starting(&mut self, host: &str, _action: &Action) -> io::Result<()> {
+            panic!("ui failed on {host}");
+        }
+
+        async fn report(
+            &mut self,
+            _host: &str,
+            _action: &Action,
+            _output: &Output,
+        ) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_success_on_every_host() {
+        let factory = TestClientFactory::new();
+        let result = run(
+            plan_for(&["web2", "web1"]),
+            factory.clone(),
+            TestReporter::new(),
+            (),
+            RunOptions::default(),
+        )
+        .await;
+
+        assert!(result.is_success());
+        assert_eq!(vec!["web1", "web2"], result.succeeded().collect::<Vec<_>>());
+        assert_eq!(0, result.failed().count());
+        assert_eq!(2, factory.lock().unwrap().client_commands().len());
+    }
+
+    #[tokio::test]
+    async fn reports_each_failure_by_host() {
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_unreachable("web1");
+        factory.lock().unwrap().fail_client_command("web3");
+        let result = run(
+            plan_for(&["web1", "web2", "web3"]),
+            factory,
+            TestReporter::new(),
+            (),
+            RunOptions::default(),
+        )
+        .await;
+
+        assert!(!result.is_success());
+        assert_eq!(vec!["web2"], result.succeeded().collect::<Vec<_>>());
+        let failed: Vec<&str> = result.failed().map(|(host, _)| host).collect();
+        assert_eq!(vec!["web1", "web3"], failed);
+    }
+
+    #[tokio::test]
+    async fn reports_to_ui_and_logger() {
+        let ui = TestReporter::new();
+        let logger = TestReporter::new();
+        let result = run(
+            plan_for(&["web1"]),
+            TestClientFactory::new(),
+            ui.clone(),
+            logger.clone(),
+            RunOptions::default(),
+        )
+        .await;
+
+        assert!(result.is_success());
+        assert!(!ui.stdout().is_empty());
+        assert_eq!(*ui.stdout(), *logger.stdout());
+    }
+
+    #[tokio::test]
+    async fn succeeds_with_no_hosts() {
+        let result = run(
+            Plan::new(),
+            TestClientFactory::new(),
+            (),
+            (),
+            RunOptions::default(),
+        )
+        .await;
+        assert!(result.is_success());
+        assert!(result.hosts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn blames_embedder_for_its_panics() {
+        let result = run(
+            plan_for(&["web1"]),
+            TestClientFactory::new(),
+            PanicsOnStart,
+            (),
+            RunOptions::default(),
+        )
+        .await;
+
+        let (host, error) = result.failed().next().unwrap();
+        assert_eq!("web1", host);
+        match error.downcast_ref::<TaskError>() {
+            Some(TaskError::EmbedderPanicked(Some(message))) => {
+                assert_eq!("ui failed on web1", message)
+            }
+            _ => panic!("expected an embedder's panic but got: {error:?}"),
+        }
+        assert!(!error.to_string().contains("bug"));
+    }
+}
@@ -0,0 +1,389 @@
+//! Writes one JSON object to stdout for every report, as it happens, for wrapper tools and CI.
+//!
+//! The stream is in [JSON Lines](https://jsonlines.org) format: each line holds one [Event]. Every
+//! event has a `timestamp`, a `host`, and an `event` field naming its kind, plus fields that
+//! depend on its kind. For example:
+//!
+//! ```json
+//! {"timestamp":"2024-01-01T11:59:59.900000000-08:00","host":"","event":"plan_started","plan_id":1,"hosts":1}
+//! {"timestamp":"2024-01-01T12:00:00.000000000-08:00","host":"web1","event":"task","manifest":"Web servers","task":"Restart app"}
+//! {"timestamp":"2024-01-01T12:00:00.100000000-08:00","host":"web1","event":"starting","action":{"command":["systemctl restart app.service"]}}
+//! {"timestamp":"2024-01-01T12:00:01.200000000-08:00","host":"web1","event":"finished","action":{"command":["systemctl restart app.service"]},"result":"success","status":"changed","exit_code":0,"stdout":"","stderr":""}
+//! {"timestamp":"2024-01-01T12:00:01.300000000-08:00","host":"","event":"plan_finished","plan_id":1,"hosts":1}
+//! ```
+//!
+//! Each run starts with a `plan_started` event and ends with a `plan_finished` event. Both have an
+//! empty `host`, and their `plan_id` tells runs in the same process apart.
+//!
+//! Whereas a JSON log (see [super::json_log]) records each action once it's over, this stream
+//! reports everything that Sira would otherwise print, including tasks starting and network
+//! activity, so that progress can be followed while a run is underway.
+//!
+//! An async Rust program can receive the same events as a [Stream] instead, without parsing JSON.
+//! Please see [run_plan_async](super::run_plan_async). Events can also be stored, e.g. as JSON, and
+//! later [replay]ed to any [Report] implementation.
+
+use super::report::Report;
+use crate::core::action::{ClientError, ErrorCode, Status};
+use crate::core::Action;
+use async_trait::async_trait;
+use futures_core::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::pin::Pin;
+use std::process::{ExitStatus, Output};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task;
+
+/// Something that happened on a host.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    /// When the event happened, in RFC 3339 format.
+    pub timestamp: String,
+
+    /// The host on which the event happened, or an empty string for events about the whole plan,
+    /// i.e. [EventKind::PlanStarted] and [EventKind::PlanFinished].
+    pub host: String,
+
+    /// What happened.
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// The kinds of [Event], one for each kind of report.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A run is about to start. Please see [Report::plan_started].
+    PlanStarted { plan_id: u64, hosts: usize },
+
+    /// The host started running actions from a task. Sent before every action.
+    Task { manifest: String, task: String },
+
+    /// A network-layer event, e.g. connecting or transferring a file.
+    Network { message: String },
+
+    /// Sira connected to the host, which took `duration_ms` milliseconds.
+    Connected { duration_ms: u64 },
+
+    /// An action is about to start.
+    Starting { action: Action },
+
+    /// `sira-client` reported progress on the running action.
+    Progress { message: String },
+
+    /// The running action, which succeeded, transferred `bytes` bytes to the host, e.g. an upload.
+    Transferred { bytes: u64 },
+
+    /// The host ran every action in its plan, and the actions that follow are the checks that its
+    /// tasks list under `verify`.
+    Verifying,
+
+    /// An action finished.
+    Finished {
+        action: Action,
+
+        /// Either `success` or `failure`.
+        result: String,
+
+        /// Whether the action changed anything.
+        status: Status,
+
+        /// The class of error that `sira-client` reported, if the action failed and it reported
+        /// one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<ErrorCode>,
+
+        /// How the action exited and what it printed.
+        #[serde(flatten)]
+        output: RecordedOutput,
+    },
+
+    /// The host is waiting for a touch on a hardware security key.
+    TouchRequired { purpose: String },
+
+    /// An action requires confirmation, which can't be given while stdout carries JSON, so it was
+    /// declined.
+    ConfirmationDeclined { action: Action },
+
+    /// Every host in the run has either finished or stopped. Please see [Report::plan_finished].
+    PlanFinished { plan_id: u64, hosts: usize },
+}
+
+/// A serializable stand-in for an action's [Output], e.g. to send it to another process or to
+/// store it and replay it later.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedOutput {
+    /// The action's exit code, if it exited normally.
+    pub exit_code: Option<i32>,
+
+    /// The signal that terminated the action, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+
+    /// The action's stdout, with invalid UTF-8 replaced.
+    pub stdout: String,
+
+    /// The action's stderr, with invalid UTF-8 replaced.
+    pub stderr: String,
+}
+
+impl From<&Output> for RecordedOutput {
+    fn from(output: &Output) -> Self {
+        RecordedOutput {
+            exit_code: output.status.code(),
+            signal: output.status.signal(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+}
+
+impl From<RecordedOutput> for Output {
+    /// Recreates the recorded [Output]. If neither an exit code nor a signal was recorded, the
+    /// action counts as having failed with exit code 1.
+    fn from(recorded: RecordedOutput) -> Self {
+        let status = match (recorded.exit_code, recorded.signal) {
+            (Some(code), _) => ExitStatus::from_raw((code & 0xff) << 8),
+            (None, Some(signal)) => ExitStatus::from_raw(signal & 0x7f),
+            (None, None) => ExitStatus::from_raw(1 << 8),
+        };
+        Output {
+            status,
+            stdout: recorded.stdout.into_bytes(),
+            stderr: recorded.stderr.into_bytes(),
+        }
+    }
+}
+
+/// Replays recorded `events`, e.g. as read back from `--output json`, to `reporter` as if the run
+/// were happening now, and then tells `reporter` that the run has [finished](Report::finished).
+///
+/// Each event becomes the [Report] call that produced it, except that
+/// [EventKind::ConfirmationDeclined] is skipped, so as not to prompt anyone, and
+/// [EventKind::Progress] and [EventKind::Transferred] are skipped unless an [EventKind::Starting]
+/// event on the same host came before them. Since events don't record how long actions took, [Report::timing] isn't called.
+///
+/// # Errors
+///
+/// Returns the first error that `reporter` returns.
+pub async fn replay<R: Report + ?Sized>(
+    events: impl IntoIterator<Item = Event>,
+    reporter: &mut R,
+) -> io::Result<()> {
+    let mut running: HashMap<String, Action> = HashMap::new();
+    for Event { host, kind, .. } in events {
+        match kind {
+            EventKind::PlanStarted { plan_id, hosts } => {
+                reporter.plan_started(plan_id, hosts).await?
+            }
+            EventKind::Task { manifest, task } => {
+                reporter.action_source(&host, &manifest, &task).await?
+            }
+            EventKind::Network { message } => reporter.network(&host, &message).await?,
+            EventKind::Connected { duration_ms } => {
+                let duration = Duration::from_millis(duration_ms);
+                reporter.connected(&host, duration).await?
+            }
+            EventKind::Starting { action } => {
+                reporter.starting(&host, &action).await?;
+                let _ = running.insert(host, action);
+            }
+            EventKind::Progress { message } => {
+                if let Some(action) = running.get(&host) {
+                    reporter.progress(&host, action, &message).await?;
+                }
+            }
+            EventKind::Transferred { bytes } => {
+                if let Some(action) = running.get(&host) {
+                    reporter.transferred(&host, action, bytes).await?;
+                }
+            }
+            EventKind::Finished { action, output, .. } => {
+                reporter.report(&host, &action, &output.into()).await?;
+                let _ = running.remove(&host);
+            }
+            EventKind::Verifying => reporter.verifying(&host).await?,
+            EventKind::TouchRequired { purpose } => {
+                reporter.touch_required(&host, &purpose).await?
+            }
+            EventKind::ConfirmationDeclined { .. } => (),
+            EventKind::PlanFinished { plan_id, hosts } => {
+                reporter.plan_finished(plan_id, hosts).await?
+            }
+        }
+    }
+    reporter.finished().await
+}
+
+/// Writes an [Event] to stdout for every report, or sends it to an [Events] stream. Please see the
+/// [module documentation](self).
+#[derive(Clone, Debug, Default)]
+pub struct EventStream {
+    /// Where to send events instead of stdout, if anywhere.
+    sender: Option<UnboundedSender<Event>>,
+}
+
+impl EventStream {
+    /// Returns an [EventStream] that sends every event to the returned [Events] instead of stdout.
+    pub fn channel() -> (EventStream, Events) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (
+            EventStream {
+                sender: Some(sender),
+            },
+            Events { receiver },
+        )
+    }
+
+    /// Writes an event of the given `kind` for `host` to stdout, or sends it to [Events].
+    fn emit(&self, host: &str, kind: EventKind) -> io::Result<()> {
+        match &self.sender {
+            // If nobody is listening anymore, there's nobody left to tell.
+            Some(sender) => {
+                let _ = sender.send(event(host, kind));
+                Ok(())
+            }
+            None => task::block_in_place(|| _emit(&mut io::stdout().lock(), host, kind)),
+        }
+    }
+}
+
+/// A [Stream] of every [Event] in a run, in the order in which they happened. Please see
+/// [EventStream::channel].
+///
+/// The stream ends once the run is over and every [EventStream] that sends to it is dropped.
+#[derive(Debug)]
+pub struct Events {
+    receiver: UnboundedReceiver<Event>,
+}
+
+impl Events {
+    /// Waits for the next [Event], or returns [None] if the stream has ended.
+    ///
+    /// This is the same as [Stream]'s `next`, for callers who don't use a `Stream` extension trait.
+    pub async fn next(&mut self) -> Option<Event> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for Events {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[async_trait]
+impl Report for EventStream {
+    async fn plan_started(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        self.emit("", EventKind::PlanStarted { plan_id, hosts })
+    }
+
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        let kind = EventKind::Task {
+            manifest: manifest.to_string(),
+            task: task.to_string(),
+        };
+        self.emit(host, kind)
+    }
+
+    async fn network(&mut self, host: &str, event: &str) -> io::Result<()> {
+        let kind = EventKind::Network {
+            message: event.to_string(),
+        };
+        self.emit(host, kind)
+    }
+
+    async fn connected(&mut self, host: &str, duration: Duration) -> io::Result<()> {
+        let kind = EventKind::Connected {
+            duration_ms: duration.as_millis() as u64,
+        };
+        self.emit(host, kind)
+    }
+
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        let kind = EventKind::Starting {
+            action: action.clone(),
+        };
+        self.emit(host, kind)
+    }
+
+    async fn progress(&mut self, host: &str, _action: &Action, message: &str) -> io::Result<()> {
+        let kind = EventKind::Progress {
+            message: message.to_string(),
+        };
+        self.emit(host, kind)
+    }
+
+    async fn transferred(&mut self, host: &str, _action: &Action, bytes: u64) -> io::Result<()> {
+        self.emit(host, EventKind::Transferred { bytes })
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        self.emit(host, finished(action, output))
+    }
+
+    async fn verifying(&mut self, host: &str) -> io::Result<()> {
+        self.emit(host, EventKind::Verifying)
+    }
+
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        let kind = EventKind::TouchRequired {
+            purpose: purpose.to_string(),
+        };
+        self.emit(host, kind)
+    }
+
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        let kind = EventKind::ConfirmationDeclined {
+            action: action.clone(),
+        };
+        self.emit(host, kind)?;
+        Ok(false)
+    }
+
+    async fn plan_finished(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        self.emit("", EventKind::PlanFinished { plan_id, hosts })
+    }
+}
+
+/// Returns the [EventKind::Finished] event for an action's outcome.
+pub(crate) fn finished(action: &Action, output: &Output) -> EventKind {
+    EventKind::Finished {
+        action: action.clone(),
+        result: match output.status.success() {
+            true => "success",
+            false => "failure",
+        }
+        .to_string(),
+        status: Status::from_output(output),
+        error: ClientError::from_output(output).map(|error| error.code),
+        output: output.into(),
+    }
+}
+
+/// Returns an event of the given `kind` for `host` that happened just now.
+fn event(host: &str, kind: EventKind) -> Event {
+    Event {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        host: host.to_string(),
+        kind,
+    }
+}
+
+/// A testable function that writes an event of the given `kind` for `host` as one line of JSON.
+pub(crate) fn _emit<W: Write>(writer: &mut W, host: &str, kind: EventKind) -> io::Result<()> {
+    let mut line = serde_json::to_string(&event(host, kind))?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,379 @@
+use super::*;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn emit(kind: EventKind) -> (Event, serde_json::Value) {
+    let mut stdout = Vec::new();
+    _emit(&mut stdout, "web1", kind).unwrap();
+    let line = String::from_utf8(stdout).unwrap();
+    assert_eq!(1, line.lines().count());
+    assert!(line.ends_with('\n'));
+    (
+        serde_json::from_str(&line).unwrap(),
+        serde_json::from_str(&line).unwrap(),
+    )
+}
+
+mod _emit {
+    use super::*;
+
+    #[test]
+    fn writes_tagged_event() {
+        let kind = EventKind::Task {
+            manifest: "Web servers".to_string(),
+            task: "Restart app".to_string(),
+        };
+        let (event, json) = emit(kind.clone());
+        assert_eq!("web1", event.host);
+        assert_eq!(kind, event.kind);
+        assert!(chrono::DateTime::parse_from_rfc3339(&event.timestamp).is_ok());
+
+        assert_eq!("task", json["event"]);
+        assert_eq!("Web servers", json["manifest"]);
+        assert_eq!("Restart app", json["task"]);
+    }
+
+    #[test]
+    fn round_trips_every_kind() {
+        let action = Action::Command(vec!["true".to_string()]);
+        for kind in [
+            EventKind::Network {
+                message: "Connecting".to_string(),
+            },
+            EventKind::Starting {
+                action: action.clone(),
+            },
+            EventKind::Progress {
+                message: "Running true".to_string(),
+            },
+            EventKind::Connected { duration_ms: 250 },
+            EventKind::Transferred { bytes: 1 << 20 },
+            EventKind::Verifying,
+            EventKind::TouchRequired {
+                purpose: "log in".to_string(),
+            },
+            EventKind::ConfirmationDeclined {
+                action: action.clone(),
+            },
+            EventKind::PlanStarted {
+                plan_id: 1,
+                hosts: 2,
+            },
+            EventKind::PlanFinished {
+                plan_id: 1,
+                hosts: 2,
+            },
+        ] {
+            assert_eq!(kind, emit(kind.clone()).0.kind);
+        }
+    }
+}
+
+mod finished {
+    use super::*;
+
+    #[test]
+    fn records_outcome() {
+        let action = Action::Command(vec!["false".to_string()]);
+        let output = Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: b"out\n".to_vec(),
+            stderr: b"err\n".to_vec(),
+        };
+        let (_, json) = emit(finished(&action, &output));
+        assert_eq!("finished", json["event"]);
+        assert_eq!("failure", json["result"]);
+        assert_eq!("failed", json["status"]);
+        assert_eq!(1, json["exit_code"]);
+        assert_eq!("out\n", json["stdout"]);
+        assert_eq!("err\n", json["stderr"]);
+        assert_eq!("false", json["action"]["command"][0]);
+        assert!(json.get("error").is_none());
+        assert!(json.get("signal").is_none());
+    }
+}
+
+mod recorded_output {
+    use super::*;
+
+    fn round_trip(status: ExitStatus) -> Output {
+        let output = Output {
+            status,
+            stdout: b"out\n".to_vec(),
+            stderr: b"err\n".to_vec(),
+        };
+        let json = serde_json::to_string(&RecordedOutput::from(&output)).unwrap();
+        let recorded: RecordedOutput = serde_json::from_str(&json).unwrap();
+        let replayed = Output::from(recorded);
+        assert_eq!(output.stdout, replayed.stdout);
+        assert_eq!(output.stderr, replayed.stderr);
+        replayed
+    }
+
+    #[test]
+    fn round_trips_exit_code() {
+        assert_eq!(Some(0), round_trip(ExitStatus::from_raw(0)).status.code());
+        assert_eq!(
+            Some(3),
+            round_trip(ExitStatus::from_raw(3 << 8)).status.code()
+        );
+    }
+
+    #[test]
+    fn round_trips_signal() {
+        let status = round_trip(ExitStatus::from_raw(9)).status;
+        assert_eq!(None, status.code());
+        assert_eq!(Some(9), status.signal());
+    }
+
+    #[test]
+    fn fails_without_exit_code_or_signal() {
+        let recorded = RecordedOutput {
+            exit_code: None,
+            signal: None,
+            stdout: String::new(),
+            stderr: String::new(),
+        };
+        assert!(!Output::from(recorded).status.success());
+    }
+}
+
+mod replay {
+    use super::*;
+
+    // Records each Report call that replay makes, in order.
+    #[derive(Default)]
+    struct Calls(Vec<String>);
+
+    #[async_trait]
+    impl Report for Calls {
+        async fn plan_started(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+            self.0.push(format!("plan {plan_id} started on {hosts}"));
+            Ok(())
+        }
+
+        async fn action_source(&mut self, host: &str, _: &str, task: &str) -> io::Result<()> {
+            self.0.push(format!("{host} task {task}"));
+            Ok(())
+        }
+
+        async fn network(&mut self, host: &str, event: &str) -> io::Result<()> {
+            self.0.push(format!("{host} network {event}"));
+            Ok(())
+        }
+
+        async fn starting(&mut self, host: &str, _: &Action) -> io::Result<()> {
+            self.0.push(format!("{host} starting"));
+            Ok(())
+        }
+
+        async fn progress(&mut self, host: &str, _: &Action, message: &str) -> io::Result<()> {
+            self.0.push(format!("{host} progress {message}"));
+            Ok(())
+        }
+
+        async fn connected(&mut self, host: &str, duration: Duration) -> io::Result<()> {
+            self.0.push(format!("{host} connected {duration:?}"));
+            Ok(())
+        }
+
+        async fn transferred(&mut self, host: &str, _: &Action, bytes: u64) -> io::Result<()> {
+            self.0.push(format!("{host} transferred {bytes}"));
+            Ok(())
+        }
+
+        async fn report(&mut self, host: &str, _: &Action, output: &Output) -> io::Result<()> {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            self.0
+                .push(format!("{host} report {:?} {stdout}", output.status.code()));
+            Ok(())
+        }
+
+        async fn verifying(&mut self, host: &str) -> io::Result<()> {
+            self.0.push(format!("{host} verifying"));
+            Ok(())
+        }
+
+        async fn plan_finished(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+            self.0.push(format!("plan {plan_id} finished on {hosts}"));
+            Ok(())
+        }
+
+        async fn finished(&mut self) -> io::Result<()> {
+            self.0.push("finished".to_string());
+            Ok(())
+        }
+
+        async fn confirm(&mut self, host: &str, _: &Action) -> io::Result<bool> {
+            self.0.push(format!("{host} confirm"));
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_events() {
+        let action = Action::Command(vec!["echo hi".to_string()]);
+        let output = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: b"hi".to_vec(),
+            stderr: vec![],
+        };
+        let events = [
+            event(
+                "",
+                EventKind::PlanStarted {
+                    plan_id: 7,
+                    hosts: 1,
+                },
+            ),
+            event(
+                "web1",
+                EventKind::Progress {
+                    message: "too early".to_string(),
+                },
+            ),
+            event(
+                "web1",
+                EventKind::Task {
+                    manifest: "Web".to_string(),
+                    task: "Greet".to_string(),
+                },
+            ),
+            event(
+                "web1",
+                EventKind::Network {
+                    message: "Connected".to_string(),
+                },
+            ),
+            event("web1", EventKind::Connected { duration_ms: 1500 }),
+            event("web1", EventKind::Transferred { bytes: 7 }),
+            event(
+                "web1",
+                EventKind::ConfirmationDeclined {
+                    action: action.clone(),
+                },
+            ),
+            event(
+                "web1",
+                EventKind::Starting {
+                    action: action.clone(),
+                },
+            ),
+            event(
+                "web1",
+                EventKind::Progress {
+                    message: "Running echo hi".to_string(),
+                },
+            ),
+            event("web1", EventKind::Transferred { bytes: 2 }),
+            event("web1", finished(&action, &output)),
+            event("web1", EventKind::Verifying),
+            event(
+                "",
+                EventKind::PlanFinished {
+                    plan_id: 7,
+                    hosts: 1,
+                },
+            ),
+        ];
+
+        // Replay what a consumer would have read back from JSON.
+        let json: Vec<String> = events
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap())
+            .collect();
+        let events = json.iter().map(|line| serde_json::from_str(line).unwrap());
+
+        let mut calls = Calls::default();
+        replay(events, &mut calls).await.unwrap();
+        assert_eq!(
+            vec![
+                "plan 7 started on 1",
+                "web1 task Greet",
+                "web1 network Connected",
+                "web1 connected 1.5s",
+                "web1 starting",
+                "web1 progress Running echo hi",
+                "web1 transferred 2",
+                "web1 report Some(0) hi",
+                "web1 verifying",
+                "plan 7 finished on 1",
+                "finished",
+            ],
+            calls.0,
+        );
+    }
+}
+
+mod channel {
+    use super::*;
+    use crate::core::fixtures::plan;
+    use crate::run_plan::_run_plan;
+    use crate::run_plan::test::fixtures::TestClientFactory;
+    use crate::run_plan::RunOptions;
+    use std::future;
+
+    #[tokio::test]
+    async fn sends_events_in_order_and_ends_with_run() {
+        let (mut plan, _, _, _) = plan();
+        plan.manifests[0].hosts = vec!["web1".to_string()];
+        let (reporter, mut events) = EventStream::channel();
+        _run_plan(
+            plan,
+            TestClientFactory::new(),
+            reporter,
+            RunOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut hosts = vec![];
+        let mut kinds = vec![];
+        while let Some(event) = events.next().await {
+            hosts.push(event.host);
+            kinds.push(event.kind);
+        }
+        let last = kinds.len() - 1;
+        assert_eq!("", hosts[0]);
+        assert_eq!("", hosts[last]);
+        assert!(hosts[1..last].iter().all(|host| host == "web1"));
+        let EventKind::PlanStarted { plan_id, hosts: 1 } = kinds[0] else {
+            panic!("{:?}", kinds[0]);
+        };
+        assert_eq!(EventKind::PlanFinished { plan_id, hosts: 1 }, kinds[last]);
+        assert!(matches!(kinds[1], EventKind::Network { .. }));
+        assert!(kinds
+            .iter()
+            .any(|kind| matches!(kind, EventKind::Starting { .. })));
+        assert!(matches!(
+            &kinds[last - 1],
+            EventKind::Finished { result, .. } if result == "success",
+        ));
+    }
+
+    #[tokio::test]
+    async fn declines_confirmation() {
+        let (mut reporter, mut events) = EventStream::channel();
+        let action = Action::Command(vec!["true".to_string()]);
+        assert!(!reporter.confirm("web1", &action).await.unwrap());
+        drop(reporter);
+
+        let event = events.next().await.unwrap();
+        assert_eq!(EventKind::ConfirmationDeclined { action }, event.kind);
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn polls_as_stream() {
+        let (mut reporter, mut events) = EventStream::channel();
+        reporter.network("web1", "Connecting").await.unwrap();
+        drop(reporter);
+
+        let mut events = Pin::new(&mut events);
+        let event = future::poll_fn(|cx| events.as_mut().poll_next(cx)).await;
+        assert_eq!("web1", event.unwrap().host);
+        assert!(future::poll_fn(|cx| events.as_mut().poll_next(cx))
+            .await
+            .is_none());
+    }
+}
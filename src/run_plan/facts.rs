@@ -0,0 +1,290 @@
+//! Gathers facts about managed nodes on the control node, e.g. for `sira facts` and for manifests
+//! that select hosts by fact (please see [select_hosts]), and caches them.
+//!
+//! Gathering a managed node's [Facts] means connecting to it and running `sira-client facts`
+//! (please see [crate::client::facts]). To spare repeated runs that cost, Sira caches each node's
+//! facts as JSON in a file of its own, e.g. `~/.cache/sira/facts/web1.json`, and reuses them until
+//! they're older than [RunOptions::facts_ttl] (by default, [DEFAULT_TTL]).
+//!
+//! A cached file's age is its modification time, so deleting the file forgets a node's facts.
+//! [RunOptions::refresh_facts], e.g. `--refresh-facts`, skips the cache, gathers every node's facts
+//! again, and caches them anew. Facts that couldn't be gathered are never cached, and a cache that
+//! can't be written only costs time: Sira warns and carries on.
+
+use super::client::{ClientInterface, ManageClient};
+use super::{connection_manager, RunOptions, TaskError};
+use crate::client::facts::Facts;
+use crate::config;
+use crate::core::expr::FactExpr;
+use crate::core::Plan;
+use anyhow::Context;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// How long cached facts stay fresh, in seconds, unless [RunOptions::facts_ttl] says otherwise.
+pub const DEFAULT_TTL: u64 = 3600;
+
+/// The name of the directory in the user's cache directory that holds cached facts. Please see
+/// [config::user_cache_dir].
+pub const FACTS_DIR: &str = "facts";
+
+/// A directory of cached [Facts], one file per host. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FactsCache {
+    /// The directory that holds the cached facts.
+    dir: PathBuf,
+
+    /// How long cached facts stay fresh.
+    ttl: Duration,
+}
+
+impl FactsCache {
+    /// Creates a [FactsCache] in `dir`, whose facts stay fresh for `ttl`. The directory is
+    /// created when the first facts are cached.
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        FactsCache {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    /// Returns the cache that `options` ask for, or [None] if [RunOptions::facts_ttl] is zero or
+    /// no cache directory is set and the user's cache directory can't be determined.
+    pub fn from_options(options: &RunOptions) -> Option<Self> {
+        let ttl = options.facts_ttl.unwrap_or(DEFAULT_TTL);
+        if ttl == 0 {
+            return None;
+        }
+        let dir = options
+            .facts_cache
+            .clone()
+            .or_else(|| config::user_cache_dir().map(|dir| dir.join(FACTS_DIR)))?;
+        Some(FactsCache::new(dir, Duration::from_secs(ttl)))
+    }
+
+    /// Returns the path to the file that holds `host`'s facts.
+    ///
+    /// The file is named for the host, with `%` and `/` escaped as `%25` and `%2F`, so that every
+    /// host gets a file of its own in the cache directory.
+    pub fn path(&self, host: &str) -> PathBuf {
+        let name = host.replace('%', "%25").replace('/', "%2F");
+        self.dir.join(format!("{name}.json"))
+    }
+
+    /// Returns `host`'s cached facts, or [None] if there are none, they're stale, or they can't be
+    /// read.
+    pub fn get(&self, host: &str) -> Option<Facts> {
+        let path = self.path(host);
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age >= self.ttl {
+            return None;
+        }
+        serde_json::from_slice(&fs::read(&path).ok()?).ok()
+    }
+
+    /// Caches `facts` as `host`'s, replacing any facts cached before.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cache directory or file can't be written.
+    pub fn put(&self, host: &str, facts: &Facts) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| {
+            format!(
+                "could not create facts cache directory: {}",
+                self.dir.display(),
+            )
+        })?;
+
+        // Write a file of our own and then move it into place, so that another run reading the
+        // cache at the same time never sees half of it.
+        let path = self.path(host);
+        let mut temp = path.clone().into_os_string();
+        temp.push(format!(".{}.tmp", process::id()));
+        let result =
+            fs::write(&temp, serde_json::to_vec(facts)?).and_then(|()| fs::rename(&temp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result.with_context(|| format!("could not cache facts in {}", path.display()))
+    }
+}
+
+/// Returns the [Facts] of each of `hosts`, in the order of `hosts`, or the error that kept Sira
+/// from gathering them.
+///
+/// Uses fresh cached facts unless [RunOptions::refresh_facts] is set. Gathers the rest in
+/// parallel, but from no more than [RunOptions::concurrency] hosts at once, and caches them.
+pub async fn gather(
+    hosts: &[String],
+    options: &RunOptions,
+) -> Vec<(String, anyhow::Result<Facts>)> {
+    _gather(
+        hosts,
+        connection_manager(options),
+        FactsCache::from_options(options).as_ref(),
+        options.refresh_facts,
+        options.concurrency,
+    )
+    .await
+}
+
+/// Provides dependency injection for unit-testing [gather] without SSH.
+pub(crate) async fn _gather<
+    C: ClientInterface + Send,
+    CM: ManageClient<C> + Clone + Send + 'static,
+>(
+    hosts: &[String],
+    connection_manager: CM,
+    cache: Option<&FactsCache>,
+    refresh: bool,
+    concurrency: Option<usize>,
+) -> Vec<(String, anyhow::Result<Facts>)> {
+    let mut results: Vec<Option<anyhow::Result<Facts>>> = hosts
+        .iter()
+        .map(|host| match refresh {
+            true => None,
+            false => cache.and_then(|cache| cache.get(host)).map(Ok),
+        })
+        .collect();
+
+    let limit = concurrency.map(|n| Arc::new(Semaphore::new(n)));
+    let mut gathering = JoinSet::new();
+    let mut tasks = HashMap::new();
+    for (index, host) in hosts.iter().enumerate() {
+        if results[index].is_some() {
+            continue;
+        }
+        let host = host.clone();
+        let mut cm = connection_manager.clone();
+        let limit = limit.clone();
+        let handle = gathering.spawn(async move {
+            // The semaphore is never closed, so acquiring a permit can't fail.
+            let _permit = match limit {
+                Some(limit) => limit.acquire_owned().await.ok(),
+                None => None,
+            };
+            let facts = match cm.connect(&host).await {
+                Ok(mut client) => client.facts().await,
+                Err(e) => Err(e),
+            };
+            (index, facts)
+        });
+        let _ = tasks.insert(handle.id(), index);
+    }
+
+    while let Some(join_result) = gathering.join_next_with_id().await {
+        let (index, facts) = match join_result {
+            Ok((_, gathered)) => gathered,
+            Err(err) => {
+                let index = tasks[&err.id()];
+                (index, Err(TaskError::from(err).into()))
+            }
+        };
+        if let (Some(cache), Ok(facts)) = (cache, &facts) {
+            if let Err(e) = cache.put(&hosts[index], facts) {
+                eprintln!("Warning: {e:#}");
+            }
+        }
+        results[index] = Some(facts);
+    }
+
+    hosts
+        .iter()
+        .cloned()
+        .zip(
+            results.into_iter().map(|facts| {
+                facts.expect("facts should have been gathered or cached for every host")
+            }),
+        )
+        .collect()
+}
+
+/// Fills in the hosts of every manifest in `plan` that selects them by a fact expression (please
+/// see [Manifest::fact]), and returns the candidates whose facts couldn't be gathered, with the
+/// errors.
+///
+/// The candidates are the hosts that `plan` names explicitly and the hosts in [RunOptions::hosts],
+/// i.e. the `[hosts]` table in `sira.toml` and any inventory's hosts (please see
+/// [inventory::apply](super::inventory::apply)). Their facts are gathered as in [gather], but only
+/// if some manifest needs them. Each manifest gains the candidates whose facts match its
+/// expression, in alphabetical order, after any hosts it already lists.
+///
+/// # Errors
+///
+/// Returns an error if any manifest's fact expression is invalid. Then no facts are gathered.
+///
+/// [Manifest::fact]: crate::core::Manifest::fact
+pub async fn select_hosts(
+    plan: &mut Plan,
+    options: &RunOptions,
+) -> anyhow::Result<Vec<(String, anyhow::Error)>> {
+    _select_hosts(
+        plan,
+        connection_manager(options),
+        FactsCache::from_options(options).as_ref(),
+        options,
+    )
+    .await
+}
+
+/// Provides dependency injection for unit-testing [select_hosts] without SSH.
+pub(crate) async fn _select_hosts<
+    C: ClientInterface + Send,
+    CM: ManageClient<C> + Clone + Send + 'static,
+>(
+    plan: &mut Plan,
+    connection_manager: CM,
+    cache: Option<&FactsCache>,
+    options: &RunOptions,
+) -> anyhow::Result<Vec<(String, anyhow::Error)>> {
+    let mut selectors = vec![];
+    for (index, manifest) in plan.manifests.iter().enumerate() {
+        if let Some(fact) = &manifest.fact {
+            let expr =
+                FactExpr::parse(fact).with_context(|| format!("manifest {:?}", manifest.name))?;
+            selectors.push((index, expr));
+        }
+    }
+    if selectors.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut candidates: BTreeSet<String> = plan.hosts().into_iter().collect();
+    candidates.extend(options.hosts.keys().cloned());
+    let candidates: Vec<String> = candidates.into_iter().collect();
+    let gathered = _gather(
+        &candidates,
+        connection_manager,
+        cache,
+        options.refresh_facts,
+        options.concurrency,
+    )
+    .await;
+
+    let mut failed = vec![];
+    for (host, facts) in gathered {
+        let facts = match facts {
+            Ok(facts) => facts,
+            Err(e) => {
+                failed.push((host, e.context("could not gather facts to select hosts")));
+                continue;
+            }
+        };
+        for (index, expr) in &selectors {
+            let hosts = &mut plan.manifests[*index].hosts;
+            if expr.matches(&facts) && !hosts.contains(&host) {
+                hosts.push(host.clone());
+            }
+        }
+    }
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,259 @@
+use super::*;
+use crate::client::facts::Os;
+use crate::run_plan::test::fixtures::TestClientFactory;
+use std::fs::{File, FileTimes};
+use std::time::SystemTime;
+
+fn hosts(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn debian(hostname: &str) -> Facts {
+    Facts {
+        hostname: hostname.to_string(),
+        os: Os {
+            id: Some("debian".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+mod facts_cache {
+    use super::*;
+
+    #[test]
+    fn round_trips_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FactsCache::new(dir.path(), Duration::from_secs(60));
+        assert_eq!(None, cache.get("web1"));
+        cache.put("web1", &debian("web1")).unwrap();
+        assert_eq!(Some(debian("web1")), cache.get("web1"));
+        assert_eq!(None, cache.get("web2"));
+    }
+
+    #[test]
+    fn creates_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FactsCache::new(dir.path().join("sira").join("facts"), Duration::MAX);
+        cache.put("web1", &debian("web1")).unwrap();
+        assert!(cache.path("web1").is_file());
+    }
+
+    #[test]
+    fn ignores_stale_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FactsCache::new(dir.path(), Duration::from_secs(60));
+        cache.put("web1", &debian("web1")).unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(cache.path("web1"))
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(an_hour_ago))
+            .unwrap();
+        assert_eq!(None, cache.get("web1"));
+    }
+
+    #[test]
+    fn ignores_corrupt_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FactsCache::new(dir.path(), Duration::MAX);
+        fs::write(cache.path("web1"), "{").unwrap();
+        assert_eq!(None, cache.get("web1"));
+    }
+
+    #[test]
+    fn escapes_host_names() {
+        let cache = FactsCache::new("/cache", Duration::MAX);
+        assert_eq!(PathBuf::from("/cache/web1.json"), cache.path("web1"));
+        assert_eq!(
+            PathBuf::from("/cache/deploy@web1.json"),
+            cache.path("deploy@web1"),
+        );
+        assert_eq!(
+            PathBuf::from("/cache/..%2Fa%252F.json"),
+            cache.path("../a%2F")
+        );
+    }
+
+    #[test]
+    fn is_off_with_zero_ttl() {
+        let options = RunOptions {
+            facts_cache: Some(PathBuf::from("/cache")),
+            facts_ttl: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(None, FactsCache::from_options(&options));
+    }
+
+    #[test]
+    fn defaults_ttl() {
+        let options = RunOptions {
+            facts_cache: Some(PathBuf::from("/cache")),
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(FactsCache::new("/cache", Duration::from_secs(DEFAULT_TTL))),
+            FactsCache::from_options(&options),
+        );
+    }
+}
+
+mod gather {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_each_host_in_order() {
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_unreachable("web2");
+        factory.lock().unwrap().set_facts("web3", debian("web3"));
+
+        let facts = _gather(
+            &hosts(&["web3", "web2", "web1"]),
+            factory,
+            None,
+            false,
+            Some(1),
+        )
+        .await;
+        let hosts: Vec<&str> = facts.iter().map(|(host, _)| host.as_str()).collect();
+        assert_eq!(vec!["web3", "web2", "web1"], hosts);
+        assert_eq!(&debian("web3"), facts[0].1.as_ref().unwrap());
+        assert!(facts[1].1.is_err());
+        assert_eq!("web1", facts[2].1.as_ref().unwrap().hostname);
+    }
+
+    #[tokio::test]
+    async fn uses_fresh_cached_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FactsCache::new(dir.path(), Duration::from_secs(60));
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_facts("web1", debian("web1"));
+        let facts = _gather(
+            &hosts(&["web1"]),
+            factory.clone(),
+            Some(&cache),
+            false,
+            None,
+        )
+        .await;
+        assert!(facts[0].1.is_ok());
+
+        // Now that its facts are cached, web1 doesn't need to answer.
+        factory.lock().unwrap().set_unreachable("web1");
+        let facts = _gather(
+            &hosts(&["web1"]),
+            factory.clone(),
+            Some(&cache),
+            false,
+            None,
+        )
+        .await;
+        assert_eq!(&debian("web1"), facts[0].1.as_ref().unwrap());
+    }
+
+    #[tokio::test]
+    async fn refreshes_cached_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FactsCache::new(dir.path(), Duration::from_secs(60));
+        cache.put("web1", &Facts::default()).unwrap();
+
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_facts("web1", debian("web1"));
+        let facts = _gather(&hosts(&["web1"]), factory, Some(&cache), true, None).await;
+        assert_eq!(&debian("web1"), facts[0].1.as_ref().unwrap());
+        assert_eq!(Some(debian("web1")), cache.get("web1"));
+    }
+
+    #[tokio::test]
+    async fn does_not_cache_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FactsCache::new(dir.path(), Duration::from_secs(60));
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().fail_client_command("web1");
+        let facts = _gather(&hosts(&["web1"]), factory, Some(&cache), false, None).await;
+        assert!(facts[0].1.is_err());
+        assert!(!cache.path("web1").exists());
+    }
+}
+
+mod select_hosts {
+    use super::*;
+    use crate::config::HostConfig;
+    use crate::core::fixtures::plan;
+
+    /// Returns a plan whose only manifest runs on `web1` and the hosts whose OS is Debian, and
+    /// options that also know about `web2` and `web3`.
+    fn debian_plan() -> (Plan, RunOptions) {
+        let (mut plan, mut manifest, _, _) = plan();
+        manifest.hosts = vec![];
+        manifest.fact = Some("os.id == 'debian'".to_string());
+        plan.manifests.push(manifest);
+        plan.manifests[0].hosts = hosts(&["web1"]);
+        let options = RunOptions {
+            hosts: ["web3", "web2"]
+                .into_iter()
+                .map(|host| (host.to_string(), HostConfig::default()))
+                .collect(),
+            ..Default::default()
+        };
+        (plan, options)
+    }
+
+    #[tokio::test]
+    async fn selects_matching_candidates() {
+        let (mut plan, options) = debian_plan();
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_facts("web1", debian("web1"));
+        factory.lock().unwrap().set_facts("web3", debian("web3"));
+
+        let failed = _select_hosts(&mut plan, factory, None, &options)
+            .await
+            .unwrap();
+        assert!(failed.is_empty());
+        assert_eq!(hosts(&["web1"]), plan.manifests[0].hosts);
+        assert_eq!(hosts(&["web1", "web3"]), plan.manifests[1].hosts);
+    }
+
+    #[tokio::test]
+    async fn returns_unreachable_candidates() {
+        let (mut plan, options) = debian_plan();
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_facts("web1", debian("web1"));
+        factory.lock().unwrap().set_unreachable("web2");
+
+        let failed = _select_hosts(&mut plan, factory, None, &options)
+            .await
+            .unwrap();
+        let failed: Vec<&str> = failed.iter().map(|(host, _)| host.as_str()).collect();
+        assert_eq!(vec!["web2"], failed);
+        assert_eq!(hosts(&["web1"]), plan.manifests[1].hosts);
+    }
+
+    #[tokio::test]
+    async fn gathers_nothing_without_fact_expressions() {
+        let (mut plan, _, _, _) = plan();
+        let expected = plan.clone();
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_unreachable("archie-desktop");
+
+        let failed = _select_hosts(&mut plan, factory, None, &RunOptions::default())
+            .await
+            .unwrap();
+        assert!(failed.is_empty());
+        assert_eq!(expected, plan);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_expressions() {
+        let (mut plan, options) = debian_plan();
+        plan.manifests[1].fact = Some("os.id ==".to_string());
+        let factory = TestClientFactory::new();
+
+        let err = _select_hosts(&mut plan, factory, None, &options)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("invalid fact expression"));
+    }
+}
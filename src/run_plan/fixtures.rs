@@ -0,0 +1,648 @@
+//! Fakes and fixtures for testing code that uses Sira, e.g. an embedding application's
+//! integration tests, without SSH or a terminal.
+//!
+//! Sira's own unit tests use these, too. Outside of Sira, they're only available with the
+//! `test-fixtures` feature, e.g. in `[dev-dependencies]`:
+//!
+//! ```toml
+//! sira = { version = "0.1", features = ["test-fixtures"] }
+//! ```
+//!
+//! [TestClientFactory] stands in for the network: it's a [ManageClient] that "connects" to any host
+//! and records what Sira sends it, and it can simulate unreachable hosts and failing actions.
+//! [TestReporter] stands in for the terminal. Both work with [embed::run](super::embed::run), and
+//! [crate::core::fixtures::plan] provides a small [Plan](crate::core::Plan) to run.
+
+use super::client::{ClientInterface, ConnectionError, InstalledClient, ManageClient};
+use super::deploy_client::{CLIENTS_DIR, CLIENT_BIN};
+use super::report::{
+    _confirm, _progress, _report, _starting, _touch_required, _verifying, title, Report,
+};
+use crate::client::facts::Facts;
+use crate::config;
+use crate::core::action::{ClientError, Envelope, Progress, UPLOAD_CHECKSUM_PREFIX};
+use crate::core::Action;
+use crate::crypto;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// A fake network that tests can pass in place of a
+/// [ConnectionManager](super::client::ConnectionManager). Spawns [TestClient] values when asked to
+/// connect to clients, but holds onto references to the [TestClient]s' records so that tests can
+/// examine them later by inspecting the [TestClientFactory].
+///
+/// Every host is reachable and every action succeeds, except as configured with the methods
+/// below, which take the host's name.
+#[derive(Clone, Debug)]
+pub struct TestClientFactory {
+    /// A record of all ClientInterface methods invoked on all clients.
+    client_commands: ClientCommands,
+
+    /// Clients that the connect method should not be able to reach.
+    unreachable_clients: HashSet<String>,
+
+    /// Clients whose ClientInterface methods should return failures.
+    failing_clients: HashSet<String>,
+
+    /// Maps host_name -> exit_code. Allows clients to return custom exit codes via
+    /// ClientInterface to simulate failed commands.
+    custom_exit_codes: HashMap<String, i32>,
+
+    /// Clients that should report the wrong checksum for uploaded files.
+    corrupting_clients: HashSet<String>,
+
+    /// Clients that should report progress on every action.
+    reporting_clients: HashSet<String>,
+
+    /// Maps host_name -> the typed error that the client should report on stdout.
+    reported_errors: HashMap<String, ClientError>,
+
+    /// Maps host_name -> how many actions have been in flight on the host's clients at once.
+    in_flight: HashMap<String, Arc<InFlight>>,
+
+    /// Maps host_name -> the facts that the client should report.
+    facts: HashMap<String, Facts>,
+
+    /// Clients whose installed sira-client should have the wrong checksum.
+    tampered_clients: HashSet<String>,
+}
+
+impl TestClientFactory {
+    /// Returns a [TestClientFactory] that's shared, so that tests can keep a clone to inspect
+    /// after passing one to Sira.
+    pub fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            client_commands: ClientCommands::new(),
+            unreachable_clients: HashSet::new(),
+            failing_clients: HashSet::new(),
+            custom_exit_codes: HashMap::new(),
+            corrupting_clients: HashSet::new(),
+            reporting_clients: HashSet::new(),
+            reported_errors: HashMap::new(),
+            in_flight: HashMap::new(),
+            facts: HashMap::new(),
+            tampered_clients: HashSet::new(),
+        }))
+    }
+
+    /// Makes connecting to `host` fail.
+    pub fn set_unreachable(&mut self, host: impl Into<String>) {
+        self.unreachable_clients.insert(host.into());
+    }
+
+    /// Makes every [ClientInterface] method on `host` return an error, as if the connection
+    /// dropped.
+    pub fn fail_client_command(&mut self, host: impl Into<String>) {
+        self.failing_clients.insert(host.into());
+    }
+
+    /// Makes every action on `host` exit with the raw wait status `code`, e.g. `1 << 8` for exit
+    /// code 1.
+    pub fn exit_code(&mut self, host: impl Into<String>, code: i32) {
+        self.custom_exit_codes.insert(host.into(), code);
+    }
+
+    /// Makes uploads to `host` report the wrong checksum.
+    pub fn corrupt_uploads(&mut self, host: impl Into<String>) {
+        self.corrupting_clients.insert(host.into());
+    }
+
+    /// Makes `host` report [Progress] on every action.
+    pub fn report_progress(&mut self, host: impl Into<String>) {
+        self.reporting_clients.insert(host.into());
+    }
+
+    /// Makes every action on `host` report `error` on stdout, as `sira-client` does.
+    pub fn report_error(&mut self, host: impl Into<String>, error: ClientError) {
+        self.reported_errors.insert(host.into(), error);
+    }
+
+    /// Makes `host` report `facts`. Otherwise, it reports default facts with its name as the
+    /// host name.
+    pub fn set_facts(&mut self, host: impl Into<String>, facts: Facts) {
+        self.facts.insert(host.into(), facts);
+    }
+
+    /// Makes `host`'s installed `sira-client` fail verification. Otherwise, it matches the
+    /// binary in Sira's configuration directory for the [TEST_CLIENT_PLATFORM].
+    pub fn tamper_client(&mut self, host: impl Into<String>) {
+        self.tampered_clients.insert(host.into());
+    }
+
+    /// Returns the most actions that were ever in flight on `host` at once, e.g. 1 if `host` ran
+    /// its actions one at a time, or 0 if it ran none.
+    pub fn most_in_flight(&self, host: &str) -> usize {
+        self.in_flight
+            .get(host)
+            .map_or(0, |in_flight| in_flight.most.load(Ordering::SeqCst))
+    }
+
+    /// Returns the calls made to each host's [TestClient], by host.
+    pub fn client_commands(&self) -> &ClientCommands {
+        &self.client_commands
+    }
+
+    /// Consumes this [TestClientFactory] and returns the calls made to each host's [TestClient].
+    pub fn into_client_commands(self) -> ClientCommands {
+        self.client_commands
+    }
+}
+
+#[async_trait]
+impl ManageClient<TestClient> for Arc<Mutex<TestClientFactory>> {
+    async fn connect(&mut self, host: &str) -> anyhow::Result<TestClient> {
+        let mut factory = self.lock().unwrap();
+        if factory.unreachable_clients.contains(host) {
+            return Err(ConnectionError(anyhow!("unreachable")).into());
+        }
+
+        use std::collections::hash_map::Entry;
+        let commands = match factory.client_commands.entry(host.to_owned()) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry.insert(Arc::default()).clone(),
+        };
+
+        let should_fail = factory.failing_clients.contains(host);
+
+        let custom_exit_code = factory.custom_exit_codes.get(host).copied();
+
+        let corrupt_uploads = factory.corrupting_clients.contains(host);
+
+        let reported_error = factory.reported_errors.get(host).cloned();
+
+        let facts = factory.facts.get(host).cloned().unwrap_or_else(|| Facts {
+            hostname: host.to_owned(),
+            ..Default::default()
+        });
+
+        let tampered = factory.tampered_clients.contains(host);
+
+        let in_flight = factory
+            .in_flight
+            .entry(host.to_owned())
+            .or_default()
+            .clone();
+
+        let (progress, progress_receiver) = match factory.reporting_clients.contains(host) {
+            true => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                (Some(sender), Some(receiver))
+            }
+            false => (None, None),
+        };
+
+        Ok(TestClient {
+            records: commands,
+            should_fail,
+            custom_exit_code,
+            corrupt_uploads,
+            reported_error,
+            in_flight,
+            facts,
+            tampered,
+            progress,
+            progress_receiver,
+        })
+    }
+}
+
+/// Counts the actions in flight on a host's [TestClient]s.
+#[derive(Debug, Default)]
+struct InFlight {
+    /// How many actions are in flight now.
+    now: AtomicUsize,
+
+    /// The most actions that have been in flight at once.
+    most: AtomicUsize,
+}
+
+/// The version that [TestClient] reports when pinged.
+pub const TEST_CLIENT_VERSION: &str = "0.0.0-test";
+
+/// The platform that [TestClient] reports, for which Sira's configuration directory holds a
+/// stand-in `sira-client` binary.
+pub const TEST_CLIENT_PLATFORM: &str = "sira-test-arch";
+
+/// Maps each host's name to the calls made to its [TestClient].
+pub type ClientCommands = HashMap<String, SharedRecords>;
+
+/// The calls made to a single [TestClient].
+pub type SharedRecords = Arc<Mutex<Vec<CommandRecord>>>;
+
+/// A call to a [ClientInterface] method on a [TestClient].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandRecord {
+    /// The method's name, e.g. `command` or `upload`.
+    pub method_name: &'static str,
+
+    /// The [Envelope] that Sira sent, as YAML.
+    pub yaml: String,
+
+    /// The envelope's signature, if Sira signed it.
+    pub signature: Option<String>,
+}
+
+/// A fake connection to a host, which records every call and answers as its [TestClientFactory]
+/// was configured to.
+///
+/// # Panics
+///
+/// Panics if Sira sends anything but a valid [Envelope], or an upload whose envelope doesn't
+/// match the file being uploaded.
+#[derive(Debug)]
+pub struct TestClient {
+    /// ClientInterface methods invoked on this client.
+    records: SharedRecords,
+
+    /// Whether ClientInterface methods should return Err values.
+    should_fail: bool,
+
+    /// Optional custom value that a ClientInterface method should return on success as
+    /// part of its Output value.
+    custom_exit_code: Option<i32>,
+
+    /// Whether ClientInterface::upload should report the wrong checksum.
+    corrupt_uploads: bool,
+
+    /// The typed error, if any, that ClientInterface methods should report on stdout.
+    reported_error: Option<ClientError>,
+
+    /// The actions in flight on this client and the others forked from the same connection.
+    in_flight: Arc<InFlight>,
+
+    /// The facts that ClientInterface::facts should report.
+    facts: Facts,
+
+    /// Whether ClientInterface::installed_client should report the wrong checksum.
+    tampered: bool,
+
+    /// If set, ClientInterface methods report progress here before returning.
+    progress: Option<UnboundedSender<Progress>>,
+
+    /// The other end of `progress`, until taken.
+    progress_receiver: Option<UnboundedReceiver<Progress>>,
+}
+
+#[async_trait]
+impl ClientInterface for TestClient {
+    async fn command(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.run("command", yaml, signature, openssh::Error::Disconnected)
+            .await
+    }
+
+    async fn controller_key(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.run(
+            "controller_key",
+            yaml,
+            signature,
+            openssh::Error::Disconnected,
+        )
+        .await
+    }
+
+    async fn custom(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.run("custom", yaml, signature, openssh::Error::Disconnected)
+            .await
+    }
+
+    async fn kernel_module(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.run(
+            "kernel_module",
+            yaml,
+            signature,
+            openssh::Error::Disconnected,
+        )
+        .await
+    }
+
+    async fn line_in_file(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.run(
+            "line_in_file",
+            yaml,
+            signature,
+            openssh::Error::Disconnected,
+        )
+        .await
+    }
+
+    async fn script(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.run("script", yaml, signature, openssh::Error::Disconnected)
+            .await
+    }
+
+    async fn swap(
+        &mut self,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> Result<Output, openssh::Error> {
+        self.run("swap", yaml, signature, openssh::Error::Disconnected)
+            .await
+    }
+
+    async fn upload(
+        &mut self,
+        from: &str,
+        transfer_permissions: Option<&str>,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+    ) -> anyhow::Result<Output> {
+        // Sanity check.
+        let action = Envelope::from_yaml(yaml).unwrap().action;
+        match action {
+            Action::Upload {
+                from: af,
+                transfer_permissions: atp,
+                ..
+            } => {
+                assert_eq!(from, af);
+                assert_eq!(transfer_permissions, atp.as_deref());
+            }
+            x => panic!("expected Action::Upload but got:\n{x:#?}"),
+        }
+
+        // anyhow::Error doesn't implement std::error::Error. Meanwhile, upload returns
+        // an anyhow::Result, and record requires and returns Error/Result. To solve
+        // this incompatibility, we have to map_err. The error output from rustc isn't
+        // very helpful on this issue.
+        let mut output = self
+            .run("upload", yaml, signature, io::Error::other("expected"))
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        // Like sira-client, report the checksum of the installed file.
+        if output.status.success() {
+            let checksum = match self.corrupt_uploads {
+                true => "0".repeat(64),
+                false => crypto::sha256_file(from)?,
+            };
+            output.stdout = format!("{UPLOAD_CHECKSUM_PREFIX}{checksum}\n").into_bytes();
+        }
+        Ok(output)
+    }
+
+    async fn ping(&mut self) -> anyhow::Result<String> {
+        if self.should_fail {
+            bail!("expected");
+        }
+        Ok(TEST_CLIENT_VERSION.to_string())
+    }
+
+    async fn facts(&mut self) -> anyhow::Result<Facts> {
+        if self.should_fail {
+            bail!("expected");
+        }
+        Ok(self.facts.clone())
+    }
+
+    async fn installed_client(&mut self) -> anyhow::Result<InstalledClient> {
+        if self.should_fail {
+            bail!("expected");
+        }
+        let sha256 = match self.tampered {
+            true => "0".repeat(64),
+            false => crypto::sha256_file(
+                config::config_dir()
+                    .join(CLIENTS_DIR)
+                    .join(TEST_CLIENT_PLATFORM)
+                    .join(CLIENT_BIN),
+            )?,
+        };
+        Ok(InstalledClient {
+            platform: TEST_CLIENT_PLATFORM.to_string(),
+            sha256,
+        })
+    }
+
+    fn take_progress(&mut self) -> Option<UnboundedReceiver<Progress>> {
+        self.progress_receiver.take()
+    }
+
+    fn fork(&self) -> Option<Self> {
+        let (progress, progress_receiver) = match self.progress {
+            Some(_) => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                (Some(sender), Some(receiver))
+            }
+            None => (None, None),
+        };
+        Some(TestClient {
+            records: self.records.clone(),
+            should_fail: self.should_fail,
+            custom_exit_code: self.custom_exit_code,
+            corrupt_uploads: self.corrupt_uploads,
+            reported_error: self.reported_error.clone(),
+            in_flight: self.in_flight.clone(),
+            facts: self.facts.clone(),
+            tampered: self.tampered,
+            progress,
+            progress_receiver,
+        })
+    }
+}
+
+impl TestClient {
+    /// Like [TestClient::record], but yields to other tasks before answering, as a real client
+    /// would while waiting for the host, so that actions in flight at once overlap.
+    async fn run<E: Error + Send + Sync + 'static>(
+        &mut self,
+        caller: &'static str,
+        yaml: &str,
+        signature: Option<Vec<u8>>,
+        error: E,
+    ) -> Result<Output, E> {
+        let now = self.in_flight.now.fetch_add(1, Ordering::SeqCst) + 1;
+        self.in_flight.most.fetch_max(now, Ordering::SeqCst);
+        let output = self.record(caller, yaml, signature, error);
+        tokio::task::yield_now().await;
+        self.in_flight.now.fetch_sub(1, Ordering::SeqCst);
+        output
+    }
+
+    /// Records a call to a [ClientInterface] method and answers it, failing with `error` if
+    /// configured to fail.
+    pub fn record<E: Error + Send + Sync + 'static>(
+        &mut self,
+        caller: &'static str,
+        yaml: impl Into<String>,
+        signature: Option<Vec<u8>>,
+        error: E,
+    ) -> Result<Output, E> {
+        self.records.lock().unwrap().push(CommandRecord {
+            method_name: caller,
+            yaml: yaml.into(),
+            signature: signature.map(|s| String::from_utf8(s).expect("signature was not UTF-8")),
+        });
+
+        if let Some(progress) = &self.progress {
+            let action = Envelope::from_yaml(&self.records.lock().unwrap().last().unwrap().yaml)
+                .unwrap()
+                .action;
+            let _ = progress.send(Progress::new(format!("Running {}", title(&action))));
+        }
+
+        if self.should_fail {
+            Err(error)
+        } else {
+            let exit_code = self.custom_exit_code.unwrap_or(0);
+            let stdout = match &self.reported_error {
+                Some(error) => format!("{}\n", error.line()).into_bytes(),
+                None => vec![],
+            };
+            Ok(Output {
+                status: ExitStatus::from_raw(exit_code),
+                stdout,
+                stderr: vec![],
+            })
+        }
+    }
+}
+
+/// A fake terminal: a [Report] implementation that writes what the terminal would show to
+/// in-memory buffers.
+///
+/// Sira clones its reporter for each host, so [TestReporter] is shared through an [Arc], and tests
+/// can hold onto a clone and examine it after the code under test has run.
+#[derive(Debug, Default)]
+pub struct TestReporter {
+    /// Whether the Report::report method should return an error.
+    should_fail: Mutex<bool>,
+
+    /// Whether the Report::starting method should return an error.
+    should_fail_to_start: Mutex<bool>,
+
+    /// The answer that the Report::confirm method should give.
+    should_confirm: Mutex<bool>,
+
+    /// The shared stderr writer.
+    stderr: Mutex<Vec<u8>>,
+
+    /// The shared stdout writer.
+    stdout: Mutex<Vec<u8>>,
+}
+
+impl TestReporter {
+    /// Returns a [TestReporter] that succeeds and declines confirmation.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            stdout: Mutex::new(vec![]),
+            stderr: Mutex::new(vec![]),
+            should_fail: Mutex::new(false),
+            should_fail_to_start: Mutex::new(false),
+            should_confirm: Mutex::new(false),
+        })
+    }
+
+    /// Instructs this TestReporter to approve actions that require confirmation.
+    pub fn approve(&self) {
+        *self.should_confirm.lock().unwrap() = true;
+    }
+
+    /// Instructs this TestReporter to always fail, i.e. return an error from the
+    /// Report::report method.
+    pub fn fail(&self) {
+        *self.should_fail.lock().unwrap() = true;
+    }
+
+    /// Instructs this TestReporter to always fail to start, i.e. return an error from the
+    /// Report::starting method.
+    pub fn fail_to_start(&self) {
+        *self.should_fail_to_start.lock().unwrap() = true;
+    }
+
+    /// Returns what would have been printed to stdout so far.
+    pub fn stdout(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.stdout.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl Report for Arc<TestReporter> {
+    /// Performs a simulated start notice, and then optionally returns an expected failure.
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        let result = _starting(&mut *self.stdout.lock().unwrap(), host, action);
+
+        if *self.should_fail_to_start.lock().unwrap() {
+            Err(io::Error::other("expected"))
+        } else {
+            result
+        }
+    }
+
+    /// Performs a simulated report, and then optionally returns an expected failure.
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        let result = _report(
+            &mut *self.stdout.lock().unwrap(),
+            &mut *self.stderr.lock().unwrap(),
+            host,
+            action,
+            output,
+        );
+
+        if *self.should_fail.lock().unwrap() {
+            Err(io::Error::other("expected"))
+        } else {
+            result
+        }
+    }
+
+    /// Performs a simulated notice that checks are starting.
+    async fn verifying(&mut self, host: &str) -> io::Result<()> {
+        _verifying(&mut *self.stdout.lock().unwrap(), host)
+    }
+
+    /// Performs a simulated progress report.
+    async fn progress(&mut self, host: &str, _action: &Action, message: &str) -> io::Result<()> {
+        _progress(&mut *self.stdout.lock().unwrap(), host, message)
+    }
+
+    /// Performs a simulated touch prompt.
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        _touch_required(&mut *self.stdout.lock().unwrap(), host, purpose)
+    }
+
+    /// Performs a simulated confirmation prompt, answering as instructed.
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        let answer = match *self.should_confirm.lock().unwrap() {
+            true => "y\n",
+            false => "n\n",
+        };
+        _confirm(
+            &mut *self.stdout.lock().unwrap(),
+            &mut answer.as_bytes(),
+            host,
+            action,
+        )
+    }
+}
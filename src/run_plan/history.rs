@@ -0,0 +1,481 @@
+//! Keeps a history of every run in a local SQLite database, so that questions like "when did web1
+//! last run this task, and did it succeed?" have answers, e.g. with `sira history`.
+//!
+//! Sira hands each statement to the `sqlite3` command-line shell, so SQLite must be installed on
+//! the control node. The database has one row in `runs` for each run, one row in `hosts` for each
+//! host in each run, and one row in `actions` for each action that ran (please see [SCHEMA]).
+//! Actions are recorded as they finish, and hosts' results when the run finishes, so a run that
+//! was interrupted has no `finished` time, and its hosts have no `result`. Rather than make hosts
+//! wait for `sqlite3`, [History] queues each action and writes the queue in the background, in
+//! one transaction per batch.
+//!
+//! Each run also records the SHA-256 checksum of its plan, i.e. its manifests and tasks before
+//! variable substitution, so that runs of the same plan are easy to find. Actions are recorded
+//! redacted, just as in a JSON log, but without their output; to keep output, use
+//! [RunOptions::json_log](super::RunOptions::json_log) or
+//! [RunOptions::artifacts](super::RunOptions::artifacts).
+
+use super::json_log::Record;
+use super::report::title;
+use super::sink::LogSink;
+use crate::core::{Action, Plan};
+use crate::crypto;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use tokio::runtime::Handle;
+
+/// The tables in a history database, which Sira creates if they don't exist.
+pub const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started TEXT NOT NULL,
+    finished TEXT,
+    plan_sha256 TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS hosts (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    host TEXT NOT NULL,
+    result TEXT,
+    error TEXT,
+    PRIMARY KEY (run_id, host)
+);
+CREATE TABLE IF NOT EXISTS actions (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    timestamp TEXT NOT NULL,
+    host TEXT NOT NULL,
+    manifest TEXT NOT NULL,
+    task TEXT NOT NULL,
+    action TEXT NOT NULL,
+    result TEXT NOT NULL,
+    status TEXT NOT NULL,
+    exit_code INTEGER,
+    duration_ms INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS actions_by_host ON actions (host, task);
+";
+
+/// How long `sqlite3` waits for another run to finish writing to the database, in milliseconds.
+const BUSY_TIMEOUT_MS: u32 = 10_000;
+
+/// Records a run in a history database. Please see the [module documentation](self).
+///
+/// Call [History::start] before the run, and [History::finish] after it. In between, [History]
+/// records each action as a [LogSink]. Clones share the same run.
+#[derive(Clone, Debug)]
+pub struct History {
+    /// The path to the database.
+    path: PathBuf,
+
+    /// The ID of the run in `runs`, once started. Also keeps clones from starting or finishing the
+    /// run at once.
+    run_id: Arc<Mutex<Option<i64>>>,
+
+    /// The actions waiting to be written, and a signal for when they have been.
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+}
+
+/// The actions that a [History] has yet to write. Please see [History::log].
+#[derive(Debug, Default)]
+struct Queue {
+    /// An `INSERT` statement for each action, in the order in which they finished.
+    rows: Vec<String>,
+
+    /// Whether a writer is writing the queue, so that no other should start.
+    writing: bool,
+
+    /// Why the last batch couldn't be written, until the error is returned.
+    error: Option<String>,
+}
+
+impl History {
+    /// Creates a [History] that records a run in the database at `path`.
+    ///
+    /// Does not touch the file system; see [History::start].
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        History {
+            path: path.as_ref().to_owned(),
+            run_id: Arc::default(),
+            queue: Arc::default(),
+        }
+    }
+
+    /// Creates the database, if needed, and records the start of a run of `plan` on `hosts`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the plan can't be hashed or the database can't be written.
+    pub fn start(&self, plan: &Plan, hosts: &[String]) -> anyhow::Result<()> {
+        let yaml = serde_yaml::to_string(&plan.manifests)?;
+        let plan_sha256 = crypto::sha256(yaml.as_bytes())?;
+
+        let mut sql = format!(
+            "{SCHEMA}BEGIN IMMEDIATE;\nINSERT INTO runs (started, plan_sha256) VALUES ({}, {});\n",
+            quote(&now()),
+            quote(&plan_sha256),
+        );
+        for host in hosts {
+            let _ = writeln!(
+                sql,
+                "INSERT INTO hosts (run_id, host) VALUES ((SELECT max(id) FROM runs), {});",
+                quote(host),
+            );
+        }
+        // The transaction keeps other runs from writing, so the newest run is this one.
+        sql.push_str("SELECT max(id) FROM runs;\nCOMMIT;\n");
+
+        let mut run_id = self.run_id.lock().unwrap();
+        let output = sqlite3(&self.path, &sql, false)?;
+        *run_id = Some(
+            output
+                .trim()
+                .parse()
+                .with_context(|| format!("unexpected run ID from sqlite3: {output:?}"))?,
+        );
+        Ok(())
+    }
+
+    /// Records the end of the run, in which `errors` lists the hosts that failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the run hasn't started or the database can't be written.
+    pub fn finish(&self, errors: &[(String, anyhow::Error)]) -> anyhow::Result<()> {
+        self.flush()?;
+        let run_id = self.run_id.lock().unwrap();
+        let Some(id) = *run_id else {
+            bail!("history: run not started");
+        };
+        let mut sql = format!(
+            "BEGIN;\n\
+            UPDATE runs SET finished = {} WHERE id = {id};\n\
+            UPDATE hosts SET result = 'success' WHERE run_id = {id};\n",
+            quote(&now()),
+        );
+        for (host, error) in errors {
+            let _ = writeln!(
+                sql,
+                "UPDATE hosts SET result = 'failure', error = {} \
+                WHERE run_id = {id} AND host = {};",
+                quote(&format!("{error:#}")),
+                quote(host),
+            );
+        }
+        sql.push_str("COMMIT;\n");
+        sqlite3(&self.path, &sql, false)?;
+        Ok(())
+    }
+
+    /// Waits until every queued action has been written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a batch of actions couldn't be written and the error hasn't been
+    /// returned yet.
+    fn flush(&self) -> anyhow::Result<()> {
+        let (queue, written) = &*self.queue;
+        let mut queue = written
+            .wait_while(queue.lock().unwrap(), |queue| queue.writing)
+            .unwrap();
+        match queue.error.take() {
+            Some(error) => bail!("{error}"),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes queued actions, a batch at a time, until none are left.
+    fn write_queued(&self) {
+        let (queue, written) = &*self.queue;
+        loop {
+            let rows = {
+                let mut queue = queue.lock().unwrap();
+                if queue.rows.is_empty() {
+                    queue.writing = false;
+                    written.notify_all();
+                    return;
+                }
+                mem::take(&mut queue.rows)
+            };
+            let sql = format!("BEGIN;\n{}COMMIT;\n", rows.concat());
+            if let Err(e) = sqlite3(&self.path, &sql, false) {
+                queue.lock().unwrap().error = Some(format!("{e:#}"));
+            }
+        }
+    }
+}
+
+/// Queues each action to be written in the background, on one of the runtime's blocking threads,
+/// or right away outside a [tokio] runtime. If a batch can't be written, the next action logged,
+/// or else the end of the run, returns the error.
+impl LogSink for History {
+    fn log(&self, record: &Record) -> anyhow::Result<()> {
+        let Some(id) = *self.run_id.lock().unwrap() else {
+            bail!("history: run not started");
+        };
+        let status = serde_json::to_value(record.status)?;
+        let sql = format!(
+            "INSERT INTO actions (run_id, timestamp, host, manifest, task, action, result, \
+            status, exit_code, duration_ms) VALUES ({id}, {}, {}, {}, {}, {}, {}, {}, {}, {});\n",
+            quote(&record.timestamp),
+            quote(&record.host),
+            quote(&record.manifest),
+            quote(&record.task),
+            quote(&serde_json::to_string(&record.action)?),
+            quote(&record.result),
+            quote(status.as_str().unwrap_or_default()),
+            record
+                .exit_code
+                .map_or("NULL".to_string(), |code| code.to_string()),
+            record.duration_ms,
+        );
+
+        let start_writing = {
+            let mut queue = self.queue.0.lock().unwrap();
+            if let Some(error) = queue.error.take() {
+                bail!("{error}");
+            }
+            queue.rows.push(sql);
+            !mem::replace(&mut queue.writing, true)
+        };
+        if start_writing {
+            let history = self.clone();
+            match Handle::try_current() {
+                Ok(runtime) => drop(runtime.spawn_blocking(move || history.write_queued())),
+                Err(_) => history.write_queued(),
+            }
+        }
+        Ok(())
+    }
+
+    fn finished(&self) -> anyhow::Result<()> {
+        self.flush()
+    }
+}
+
+/// A past run, as listed by [runs].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// The run's ID.
+    pub id: i64,
+
+    /// When the run started, in RFC 3339 format.
+    pub started: String,
+
+    /// When the run finished, in RFC 3339 format, or [None] if it was interrupted.
+    pub finished: Option<String>,
+
+    /// The SHA-256 checksum of the run's plan.
+    pub plan_sha256: String,
+
+    /// How many hosts the run included.
+    pub hosts: u64,
+
+    /// How many of those hosts failed.
+    pub failed: u64,
+}
+
+/// An action in a past run, as listed by [actions].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionSummary {
+    /// The ID of the run in which the action ran.
+    pub run_id: i64,
+
+    /// When the action finished, in RFC 3339 format.
+    pub timestamp: String,
+
+    /// The managed node on which the action ran.
+    pub host: String,
+
+    /// The name of the manifest that the action came from.
+    pub manifest: String,
+
+    /// The name of the task that the action came from.
+    pub task: String,
+
+    /// The action as JSON, after variable substitution and redaction.
+    pub action: String,
+
+    /// Either `success` or `failure`.
+    pub result: String,
+
+    /// Whether the action changed anything: `ok`, `changed`, or `failed`.
+    pub status: String,
+
+    /// The action's exit code, if any.
+    pub exit_code: Option<i32>,
+
+    /// How long the action took to run, in milliseconds.
+    pub duration_ms: u64,
+}
+
+/// Which actions [actions] lists. Unset fields match every action.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only actions from the run with this ID.
+    pub run: Option<i64>,
+
+    /// Only actions that ran on this host.
+    pub host: Option<String>,
+
+    /// Only actions from the task with this name.
+    pub task: Option<String>,
+}
+
+/// Returns the `limit` most recent runs in the database at `path`, newest first.
+///
+/// # Errors
+///
+/// Returns an error if the database doesn't exist or can't be read.
+pub fn runs(path: impl AsRef<Path>, limit: usize) -> anyhow::Result<Vec<RunSummary>> {
+    let sql = format!(
+        "SELECT runs.id, started, finished, plan_sha256, count(host) AS hosts, \
+        count(CASE result WHEN 'failure' THEN 1 END) AS failed \
+        FROM runs LEFT JOIN hosts ON hosts.run_id = runs.id \
+        GROUP BY runs.id ORDER BY runs.id DESC LIMIT {limit};\n"
+    );
+    query(path.as_ref(), &sql)
+}
+
+/// Returns the `limit` most recent actions in the database at `path` that match `filter`, newest
+/// first.
+///
+/// # Errors
+///
+/// Returns an error if the database doesn't exist or can't be read.
+pub fn actions(
+    path: impl AsRef<Path>,
+    filter: &Filter,
+    limit: usize,
+) -> anyhow::Result<Vec<ActionSummary>> {
+    let mut conditions = vec![];
+    if let Some(run) = filter.run {
+        conditions.push(format!("run_id = {run}"));
+    }
+    if let Some(host) = &filter.host {
+        conditions.push(format!("host = {}", quote(host)));
+    }
+    if let Some(task) = &filter.task {
+        conditions.push(format!("task = {}", quote(task)));
+    }
+    let mut sql = String::from(
+        "SELECT run_id, timestamp, host, manifest, task, action, result, status, exit_code, \
+        duration_ms FROM actions",
+    );
+    if !conditions.is_empty() {
+        let _ = write!(sql, " WHERE {}", conditions.join(" AND "));
+    }
+    let _ = writeln!(sql, " ORDER BY rowid DESC LIMIT {limit};");
+    query(path.as_ref(), &sql)
+}
+
+/// Formats `runs` for the terminal, one per line, e.g.:
+///
+/// ```text
+/// #12 2024-01-01T12:00:00-08:00: 3 hosts, 1 failed (plan 5891b5b522d5)
+/// ```
+pub fn format_runs(runs: &[RunSummary]) -> String {
+    let mut out = String::new();
+    for run in runs {
+        let outcome = match run.finished {
+            Some(_) => format!("{} hosts, {} failed", run.hosts, run.failed),
+            None => format!("{} hosts, unfinished", run.hosts),
+        };
+        let plan = run.plan_sha256.get(..12).unwrap_or(&run.plan_sha256);
+        let _ = writeln!(out, "#{} {}: {outcome} (plan {plan})", run.id, run.started);
+    }
+    out
+}
+
+/// Formats `actions` for the terminal, one per line, e.g.:
+///
+/// ```text
+/// #12 2024-01-01T12:00:01-08:00 [web1] Install nginx: command: apt-get install -y nginx (changed, 1042 ms)
+/// ```
+///
+/// Failed actions show their exit code, if any.
+pub fn format_actions(actions: &[ActionSummary]) -> String {
+    let mut out = String::new();
+    for action in actions {
+        let summary = match serde_json::from_str::<Action>(&action.action) {
+            Ok(parsed) => title(&parsed),
+            Err(_) => action.action.clone(),
+        };
+        let outcome = match (action.result.as_str(), action.exit_code) {
+            ("failure", Some(code)) => format!("failed with exit code {code}"),
+            ("failure", None) => "failed".to_string(),
+            _ => action.status.clone(),
+        };
+        let _ = writeln!(
+            out,
+            "#{} {} [{}] {}: {summary} ({outcome}, {} ms)",
+            action.run_id, action.timestamp, action.host, action.task, action.duration_ms,
+        );
+    }
+    out
+}
+
+/// Returns the current time in RFC 3339 format, as in a [Record].
+fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+/// Quotes `s` as an SQL string literal. SQLite strings can't hold NUL, so NULs become U+FFFD.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\0', "\u{FFFD}").replace('\'', "''"))
+}
+
+/// Runs `sql` on the database at `path` and returns the rows it selects as JSON.
+fn query<T: for<'de> Deserialize<'de>>(path: &Path, sql: &str) -> anyhow::Result<Vec<T>> {
+    if !path.exists() {
+        bail!("no history database at {}", path.display());
+    }
+    let output = sqlite3(path, sql, true)?;
+
+    // sqlite3 prints nothing at all if no rows match.
+    match output.trim().is_empty() {
+        true => Ok(vec![]),
+        false => serde_json::from_str(&output)
+            .with_context(|| format!("could not parse output of sqlite3: {output}")),
+    }
+}
+
+/// Runs `sql` on the database at `path` with `sqlite3` and returns what it prints, optionally as
+/// JSON. Stops at the first error.
+fn sqlite3(path: &Path, sql: &str, json: bool) -> anyhow::Result<String> {
+    let mut command = Command::new("sqlite3");
+    command.arg("-bail");
+    if json {
+        command.arg("-json");
+    }
+    let mut child = command
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("could not run sqlite3; is SQLite installed?")?;
+    child
+        .stdin
+        .take()
+        .expect("sqlite3's stdin should be piped")
+        .write_all(format!(".timeout {BUSY_TIMEOUT_MS}\n{sql}").as_bytes())
+        .context("could not send statements to sqlite3")?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "sqlite3 failed on history database {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,247 @@
+use super::*;
+use crate::core::fixtures::plan;
+use anyhow::anyhow;
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::time::Duration;
+
+fn record(host: &str, task: &str, command: &str, code: i32) -> Record {
+    let output = Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: b"secret output\n".to_vec(),
+        stderr: vec![],
+    };
+    let action = Action::Command(vec![command.to_string()]);
+    Record::new(
+        host,
+        "Web servers",
+        task,
+        &action,
+        &output,
+        Duration::from_millis(42),
+    )
+}
+
+// Records one run in which web1 succeeds and web2 fails, and returns the database's path.
+fn recorded_run(dir: &Path) -> PathBuf {
+    let path = dir.join("history.db");
+    let history = History::new(&path);
+    let hosts = ["web1".to_string(), "web2".to_string()];
+    history.start(&plan().0, &hosts).unwrap();
+    history
+        .log(&record("web1", "Install nginx", "apt-get install nginx", 0))
+        .unwrap();
+    history
+        .log(&record("web2", "Install nginx", "apt-get install nginx", 1))
+        .unwrap();
+    history
+        .finish(&[("web2".to_string(), anyhow!("it's broken"))])
+        .unwrap();
+    path
+}
+
+mod history {
+    use super::*;
+
+    #[test]
+    fn records_runs_and_host_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = recorded_run(dir.path());
+        recorded_run(dir.path());
+
+        let runs = runs(&path, 10).unwrap();
+        assert_eq!(vec![2, 1], runs.iter().map(|r| r.id).collect::<Vec<_>>());
+        assert_eq!(2, runs[0].hosts);
+        assert_eq!(1, runs[0].failed);
+        assert!(runs[0].finished.is_some());
+        assert_eq!(runs[0].plan_sha256, runs[1].plan_sha256);
+        assert_eq!(64, runs[0].plan_sha256.len());
+    }
+
+    #[test]
+    fn leaves_interrupted_run_unfinished() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        History::new(&path)
+            .start(&plan().0, &["web1".to_string()])
+            .unwrap();
+        let runs = runs(&path, 10).unwrap();
+        assert_eq!(None, runs[0].finished);
+        assert_eq!(0, runs[0].failed);
+    }
+
+    #[test]
+    fn records_actions_without_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = recorded_run(dir.path());
+        let actions = actions(&path, &Filter::default(), 10).unwrap();
+        assert_eq!(2, actions.len());
+        assert_eq!("web2", actions[0].host);
+        assert_eq!("failure", actions[0].result);
+        assert_eq!(Some(1), actions[0].exit_code);
+        assert_eq!("web1", actions[1].host);
+        assert_eq!("changed", actions[1].status);
+        assert_eq!(42, actions[1].duration_ms);
+        assert!(!actions[1].action.contains("secret"));
+    }
+
+    #[test]
+    fn quotes_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let history = History::new(&path);
+        history.start(&plan().0, &["web1".to_string()]).unwrap();
+        let task = "It's a task'); DROP TABLE actions; --";
+        history
+            .log(&record("web1", task, "printf '\\0'\n.tables", 0))
+            .unwrap();
+        let filter = Filter {
+            task: Some(task.to_string()),
+            ..Default::default()
+        };
+        let actions = actions(&path, &filter, 10).unwrap();
+        assert_eq!(1, actions.len());
+        assert_eq!(task, actions[0].task);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn writes_actions_in_background_before_finishing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let history = History::new(&path);
+        history.start(&plan().0, &["web1".to_string()]).unwrap();
+        for i in 0..10 {
+            let command = format!("echo {i}");
+            history.log(&record("web1", "Task", &command, 0)).unwrap();
+        }
+        history.finished().unwrap();
+
+        let actions = actions(&path, &Filter::default(), 20).unwrap();
+        let commands: Vec<_> = actions.iter().rev().map(|a| a.action.clone()).collect();
+        let expected: Vec<_> = (0..10)
+            .map(|i| format!(r#"{{"command":["echo {i}"]}}"#))
+            .collect();
+        assert_eq!(expected, commands);
+    }
+
+    #[test]
+    fn returns_write_errors_later() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let history = History::new(&path);
+        history.start(&plan().0, &["web1".to_string()]).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+
+        // Outside a runtime, the action is written right away, so the next one hears about it.
+        history.log(&record("web1", "Task", "true", 0)).unwrap();
+        assert!(history.log(&record("web1", "Task", "true", 0)).is_err());
+        assert!(history.finish(&[]).is_err());
+    }
+
+    #[test]
+    fn refuses_to_log_before_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path().join("history.db"));
+        assert!(history.log(&record("web1", "Task", "true", 0)).is_err());
+    }
+}
+
+mod actions {
+    use super::*;
+
+    #[test]
+    fn filters_by_host_task_and_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = recorded_run(dir.path());
+        recorded_run(dir.path());
+
+        let filter = Filter {
+            host: Some("web1".to_string()),
+            task: Some("Install nginx".to_string()),
+            ..Default::default()
+        };
+        let found = actions(&path, &filter, 10).unwrap();
+        assert_eq!(
+            vec![2, 1],
+            found.iter().map(|a| a.run_id).collect::<Vec<_>>()
+        );
+
+        let filter = Filter {
+            run: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(2, actions(&path, &filter, 10).unwrap().len());
+        assert_eq!(1, actions(&path, &filter, 1).unwrap().len());
+
+        let filter = Filter {
+            task: Some("Nothing".to_string()),
+            ..Default::default()
+        };
+        assert!(actions(&path, &filter, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn returns_error_for_missing_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.db");
+        assert!(actions(&path, &Filter::default(), 10).is_err());
+        assert!(!path.exists());
+    }
+}
+
+mod format {
+    use super::*;
+
+    #[test]
+    fn formats_runs() {
+        let run = RunSummary {
+            id: 12,
+            started: "2024-01-01T12:00:00-08:00".to_string(),
+            finished: Some("2024-01-01T12:01:00-08:00".to_string()),
+            plan_sha256: "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+                .to_string(),
+            hosts: 3,
+            failed: 1,
+        };
+        let unfinished = RunSummary {
+            finished: None,
+            ..run.clone()
+        };
+        assert_eq!(
+            "#12 2024-01-01T12:00:00-08:00: 3 hosts, 1 failed (plan 5891b5b522d5)\n\
+            #12 2024-01-01T12:00:00-08:00: 3 hosts, unfinished (plan 5891b5b522d5)\n",
+            format_runs(&[run, unfinished]),
+        );
+    }
+
+    #[test]
+    fn formats_actions() {
+        let action = ActionSummary {
+            run_id: 12,
+            timestamp: "2024-01-01T12:00:01-08:00".to_string(),
+            host: "web1".to_string(),
+            manifest: "Web servers".to_string(),
+            task: "Install nginx".to_string(),
+            action: r#"{"command":["apt-get install -y nginx"]}"#.to_string(),
+            result: "success".to_string(),
+            status: "changed".to_string(),
+            exit_code: Some(0),
+            duration_ms: 1042,
+        };
+        let failed = ActionSummary {
+            result: "failure".to_string(),
+            status: "failed".to_string(),
+            exit_code: Some(100),
+            ..action.clone()
+        };
+        assert_eq!(
+            "#12 2024-01-01T12:00:01-08:00 [web1] Install nginx: command: apt-get install -y \
+            nginx (changed, 1042 ms)\n\
+            #12 2024-01-01T12:00:01-08:00 [web1] Install nginx: command: apt-get install -y \
+            nginx (failed with exit code 100, 1042 ms)\n",
+            format_actions(&[action, failed]),
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Runs commands on the control node before a run starts and after it finishes, e.g. to build
+//! files that a plan uploads, or to fetch files that it produced.
+//!
+//! Hooks are shell commands, set in `sira.toml`:
+//!
+//! ```toml
+//! pre_run = ["make -C files"]
+//! post_run = ["./scripts/collect-results.sh"]
+//! ```
+//!
+//! Sira runs each hook with `sh -c` in its working directory, one at a time, in order. If a
+//! pre-run hook fails, nothing runs, and every host fails with the hook's error. Post-run hooks
+//! run after every run, whether or not any host failed, and receive the run's summary on stdin as
+//! an [Event::RunFinished], e.g.:
+//!
+//! ```json
+//! {"event":"run_finished","succeeded":["web1","web2"],"failed":{"web3":"Action exited with exit code 1: command: false"}}
+//! ```
+//!
+//! A failed post-run hook prints a warning, but later hooks still run. Hooks' stdout goes to
+//! Sira's stderr, so that it never mixes with JSON on stdout.
+
+use super::webhook::summary;
+#[cfg(doc)]
+use super::webhook::Event;
+use anyhow::{bail, Context};
+use std::io::{self, Write};
+use std::os::fd::AsFd;
+use std::process::{Command, Stdio};
+
+/// Runs each of `commands` in turn, stopping at the first that fails.
+///
+/// # Errors
+///
+/// Returns an error if a command can't be run or exits with an error.
+pub fn pre_run(commands: &[String]) -> anyhow::Result<()> {
+    for command in commands {
+        run(command, None).with_context(|| format!("pre-run hook failed: {command}"))?;
+    }
+    Ok(())
+}
+
+/// Runs each of `commands` in turn, passing each a summary of the run on stdin, and printing a
+/// warning for any that fail.
+///
+/// `hosts` lists every host in the plan, and `errors` lists the hosts that failed.
+pub fn post_run(commands: &[String], hosts: &[String], errors: &[(String, anyhow::Error)]) {
+    if commands.is_empty() {
+        return;
+    }
+    let summary = match serde_json::to_string(&summary(hosts, errors)) {
+        Ok(json) => json + "\n",
+        Err(e) => {
+            eprintln!("Warning: could not summarize run for post-run hooks: {e}");
+            return;
+        }
+    };
+    for command in commands {
+        if let Err(e) = run(command, Some(&summary)) {
+            eprintln!("Warning: post-run hook failed: {command}: {e:#}");
+        }
+    }
+}
+
+/// Runs `command` with `sh -c`, writing `stdin` to it, if given, and sending its stdout to
+/// Sira's stderr.
+fn run(command: &str, stdin: Option<&str>) -> anyhow::Result<()> {
+    let stderr = io::stderr().as_fd().try_clone_to_owned()?;
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(match stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(stderr)
+        .spawn()
+        .context("could not run sh")?;
+    if let Some(input) = stdin {
+        let mut pipe = child.stdin.take().expect("hook's stdin should be piped");
+
+        // A hook that doesn't read its summary closes the pipe early, which is fine.
+        match pipe.write_all(input.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                return Err(e).context("could not send summary to hook")
+            }
+            _ => (),
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("hook exited with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,64 @@
+use super::*;
+use anyhow::anyhow;
+use std::fs;
+
+mod pre_run {
+    use super::*;
+
+    #[test]
+    fn runs_commands_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        let commands = vec![
+            format!("echo first >> {}", log.display()),
+            format!("echo second >> {}", log.display()),
+        ];
+        pre_run(&commands).unwrap();
+        assert_eq!("first\nsecond\n", fs::read_to_string(log).unwrap());
+    }
+
+    #[test]
+    fn stops_at_first_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let commands = vec!["exit 3".to_string(), format!("touch {}", marker.display())];
+        let error = format!("{:#}", pre_run(&commands).unwrap_err());
+        assert!(error.contains("pre-run hook failed: exit 3"), "{error}");
+        assert!(!marker.exists());
+    }
+}
+
+mod post_run {
+    use super::*;
+
+    #[test]
+    fn passes_summary_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("summary.json");
+        let hosts = ["web2".to_string(), "web1".to_string(), "web3".to_string()];
+        let errors = [("web3".to_string(), anyhow!("oops"))];
+        post_run(&[format!("cat > {}", out.display())], &hosts, &errors);
+        assert_eq!(
+            "{\"event\":\"run_finished\",\"succeeded\":[\"web1\",\"web2\"],\
+            \"failed\":{\"web3\":\"oops\"}}\n",
+            fs::read_to_string(out).unwrap(),
+        );
+    }
+
+    #[test]
+    fn runs_later_commands_after_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let commands = vec!["false".to_string(), format!("touch {}", marker.display())];
+        post_run(&commands, &[], &[]);
+        assert!(marker.exists());
+    }
+
+    #[test]
+    fn tolerates_hooks_that_ignore_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        post_run(&[format!("touch {}", marker.display())], &[], &[]);
+        assert!(marker.exists());
+    }
+}
@@ -0,0 +1,297 @@
+//! Writes a standalone HTML page summarizing a run, e.g. to archive as a CI artifact or to email.
+//!
+//! The page has one section per host. Each section shows a timeline of the host's actions, drawn
+//! to the same scale for every host, followed by each action's captured output. Failed actions and
+//! the hosts on which they ran are highlighted in red.
+//!
+//! The page has no external dependencies (no scripts, stylesheets, or images), so it renders the
+//! same wherever it's opened. Sira rewrites the whole page after every action, so it's always
+//! complete, even if the run is interrupted.
+
+use super::report::{title, Report, RunningActions};
+use crate::core::Action;
+use anyhow::Context;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task;
+
+/// The stylesheet embedded in every page.
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; }
+h2.failed, tr.failed td { color: #a00; }
+.timeline { position: relative; height: 1.2em; background: #eee; }
+.bar { position: absolute; top: 0; height: 100%; min-width: 2px; background: #4a8; }
+.bar.failed { background: #c33; }
+table { border-collapse: collapse; width: 100%; }
+td, th { text-align: left; vertical-align: top; padding: 0.2em 0.5em; }
+pre { margin: 0; white-space: pre-wrap; }
+";
+
+/// What the report shows about each action.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The name of the manifest that the action came from.
+    pub manifest: String,
+
+    /// The name of the task that the action came from.
+    pub task: String,
+
+    /// The action's title, as printed to the terminal.
+    pub title: String,
+
+    /// When the action started, in milliseconds since the run began.
+    pub started_ms: u64,
+
+    /// How long the action took, in milliseconds, including signing and file transfers.
+    pub duration_ms: u64,
+
+    /// Whether the action succeeded.
+    pub success: bool,
+
+    /// The action's exit code, if any.
+    pub exit_code: Option<i32>,
+
+    /// The action's stdout, with invalid UTF-8 replaced.
+    pub stdout: String,
+
+    /// The action's stderr, with invalid UTF-8 replaced.
+    pub stderr: String,
+}
+
+/// Writes an HTML report of a run. Please see the [module documentation](self).
+///
+/// Clones share the same report.
+#[derive(Clone, Debug)]
+pub struct HtmlReport {
+    /// The path to the HTML file.
+    path: PathBuf,
+
+    /// The actions run so far, and the actions in flight on each host.
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// When the run began, for the page heading.
+    started_at: String,
+
+    /// When the run began, for the timeline.
+    started: Instant,
+
+    /// Maps each host to the actions that have finished on it, in order.
+    hosts: BTreeMap<String, Vec<Entry>>,
+
+    /// The source and start time of each action in flight on each host.
+    running: RunningActions,
+}
+
+impl HtmlReport {
+    /// Creates an [HtmlReport] that writes to `path`, overwriting any existing file.
+    ///
+    /// Does not touch the file system until the first action finishes. The run's timeline starts
+    /// now.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        HtmlReport {
+            path: path.as_ref().to_owned(),
+            state: Arc::new(Mutex::new(State {
+                started_at: chrono::Local::now().to_rfc3339(),
+                started: Instant::now(),
+                hosts: BTreeMap::new(),
+                running: RunningActions::new(),
+            })),
+        }
+    }
+
+    /// Notes the manifest and task that the next action on `host` comes from.
+    pub(crate) fn set_source(&self, host: &str, manifest: &str, task: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.running.source(host, manifest, task);
+    }
+
+    /// Starts timing the newest action on `host`.
+    pub(crate) fn start(&self, host: &str) {
+        self.state.lock().unwrap().running.start(host);
+    }
+
+    /// Adds the outcome of the oldest action in flight on `host` to the report and rewrites the
+    /// HTML file.
+    pub(crate) fn append(
+        &self,
+        host: &str,
+        action: &Action,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let running = state.running.finish(host);
+        let started = running.as_ref().map_or(state.started, |r| r.started);
+
+        let entry = Entry {
+            manifest: running
+                .as_ref()
+                .map(|r| r.manifest.clone())
+                .unwrap_or_default(),
+            task: running.map(|r| r.task).unwrap_or_default(),
+            title: title(action),
+            started_ms: started.duration_since(state.started).as_millis() as u64,
+            duration_ms: started.elapsed().as_millis() as u64,
+            success: output.status.success(),
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        };
+        state.hosts.entry(host.to_string()).or_default().push(entry);
+
+        let html = render(&state.started_at, &state.hosts);
+        fs::write(&self.path, html)
+            .with_context(|| format!("could not write HTML report: {}", self.path.display()))
+    }
+}
+
+#[async_trait]
+impl Report for HtmlReport {
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        self.set_source(host, manifest, task);
+        Ok(())
+    }
+
+    async fn starting(&mut self, host: &str, _action: &Action) -> io::Result<()> {
+        self.start(host);
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        task::block_in_place(|| self.append(host, action, output))
+            .map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+}
+
+/// Renders the HTML page for a run that began at `started_at`.
+pub(crate) fn render(started_at: &str, hosts: &BTreeMap<String, Vec<Entry>>) -> String {
+    // Draw every host's timeline to the same scale, so that hosts can be compared at a glance.
+    let end_ms = hosts
+        .values()
+        .flatten()
+        .map(|e| e.started_ms + e.duration_ms)
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    let percent = |ms: u64| ms as f64 * 100.0 / end_ms as f64;
+
+    let mut html = String::new();
+    // Writing to a String can't fail, so the results of write! are safe to ignore.
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n\
+        <html>\n\
+        <head>\n\
+        <meta charset=\"utf-8\">\n\
+        <title>Sira run report: {started_at}</title>\n\
+        <style>\n{STYLE}</style>\n\
+        </head>\n\
+        <body>\n\
+        <h1>Sira run report</h1>\n\
+        <p>Started {started_at}</p>\n",
+        started_at = escape(started_at),
+    );
+
+    for (host, entries) in hosts {
+        let failed = entries.iter().any(|e| !e.success);
+        let _ = writeln!(
+            html,
+            "<h2 class=\"{}\">{}: {}</h2>",
+            class(!failed),
+            escape(host),
+            match failed {
+                true => "failed",
+                false => "succeeded",
+            },
+        );
+
+        let _ = writeln!(html, "<div class=\"timeline\">");
+        for entry in entries {
+            let _ = writeln!(
+                html,
+                "<div class=\"bar {}\" style=\"left: {:.2}%; width: {:.2}%\" title=\"{}\"></div>",
+                class(entry.success),
+                percent(entry.started_ms),
+                percent(entry.duration_ms),
+                escape(&entry.title),
+            );
+        }
+        let _ = writeln!(html, "</div>");
+
+        let _ = writeln!(
+            html,
+            "<table>\n\
+            <tr><th>Start</th><th>Duration</th><th>Manifest</th><th>Task</th><th>Action</th>\
+            <th>Result</th></tr>",
+        );
+        for entry in entries {
+            let result = match (entry.success, entry.exit_code) {
+                (true, _) => "success".to_string(),
+                (false, Some(code)) => format!("exit code {code}"),
+                (false, None) => "error".to_string(),
+            };
+            let _ = writeln!(
+                html,
+                "<tr class=\"{}\"><td>{:.3}s</td><td>{:.3}s</td><td>{}</td><td>{}</td>\
+                <td>{}</td><td>{}</td></tr>",
+                class(entry.success),
+                entry.started_ms as f64 / 1000.0,
+                entry.duration_ms as f64 / 1000.0,
+                escape(&entry.manifest),
+                escape(&entry.task),
+                escape(&entry.title),
+                result,
+            );
+            for (name, captured) in [("stdout", &entry.stdout), ("stderr", &entry.stderr)] {
+                if !captured.is_empty() {
+                    let _ = writeln!(
+                        html,
+                        "<tr><td></td><td>{name}</td><td colspan=\"4\"><pre>{}</pre></td></tr>",
+                        escape(captured),
+                    );
+                }
+            }
+        }
+        let _ = writeln!(html, "</table>");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Returns the CSS class for an action or host that succeeded or failed.
+fn class(success: bool) -> &'static str {
+    match success {
+        true => "ok",
+        false => "failed",
+    }
+}
+
+/// Escapes `text` for use in HTML content or a quoted attribute.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,136 @@
+use super::*;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn output(code: i32, stdout: &str) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: vec![],
+    }
+}
+
+fn action(command: &str) -> Action {
+    Action::Command(vec![command.to_string()])
+}
+
+mod append {
+    use super::*;
+
+    #[test]
+    fn writes_page_after_each_action() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.html");
+        let report = HtmlReport::new(&path);
+
+        report.set_source("alpha", "Web servers", "Restart app");
+        report.start("alpha");
+        report
+            .append("alpha", &action("systemctl restart app"), &output(0, ""))
+            .unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        assert!(html.contains("alpha: succeeded"), "{html}");
+        assert!(html.contains("command: systemctl restart app"), "{html}");
+
+        report.set_source("bravo", "Web servers", "Check app");
+        report.start("bravo");
+        report
+            .append("bravo", &action("false"), &output(1, ""))
+            .unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        assert!(html.contains("alpha: succeeded"), "{html}");
+        assert!(html.contains("bravo: failed"), "{html}");
+        assert!(html.contains("exit code 1"), "{html}");
+    }
+
+    #[test]
+    fn returns_error_if_file_cannot_be_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = HtmlReport::new(dir.path().join("missing").join("report.html"));
+        assert!(report
+            .append("alpha", &action("true"), &output(0, ""))
+            .is_err());
+    }
+}
+
+mod render {
+    use super::*;
+
+    fn entry(title: &str, started_ms: u64, duration_ms: u64, success: bool) -> Entry {
+        Entry {
+            manifest: "Web servers".to_string(),
+            task: "Restart app".to_string(),
+            title: title.to_string(),
+            started_ms,
+            duration_ms,
+            success,
+            exit_code: Some(if success { 0 } else { 2 }),
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+
+    #[test]
+    fn draws_timelines_to_the_same_scale() {
+        let mut hosts = BTreeMap::new();
+        hosts.insert("alpha".to_string(), vec![entry("a", 0, 1000, true)]);
+        hosts.insert("bravo".to_string(), vec![entry("b", 1000, 3000, true)]);
+        let html = render("now", &hosts);
+        assert!(html.contains("left: 0.00%; width: 25.00%"), "{html}");
+        assert!(html.contains("left: 25.00%; width: 75.00%"), "{html}");
+    }
+
+    #[test]
+    fn highlights_failures() {
+        let mut hosts = BTreeMap::new();
+        hosts.insert(
+            "alpha".to_string(),
+            vec![entry("ok", 0, 1, true), entry("broken", 1, 1, false)],
+        );
+        let html = render("now", &hosts);
+        assert!(
+            html.contains("<h2 class=\"failed\">alpha: failed</h2>"),
+            "{html}"
+        );
+        assert!(html.contains("class=\"bar failed\""), "{html}");
+        assert!(html.contains("<tr class=\"failed\">"), "{html}");
+        assert!(html.contains("exit code 2"), "{html}");
+    }
+
+    #[test]
+    fn includes_captured_output() {
+        let mut hosts = BTreeMap::new();
+        let mut with_output = entry("a", 0, 1, true);
+        with_output.stdout = "hello\n".to_string();
+        hosts.insert("alpha".to_string(), vec![with_output]);
+        let html = render("now", &hosts);
+        assert!(html.contains("<td>stdout</td>"), "{html}");
+        assert!(html.contains("<pre>hello\n</pre>"), "{html}");
+        assert!(!html.contains("<td>stderr</td>"), "{html}");
+    }
+
+    #[test]
+    fn escapes_content() {
+        let mut hosts = BTreeMap::new();
+        hosts.insert(
+            "<alpha>".to_string(),
+            vec![entry("command: echo \"<b>\"", 0, 1, true)],
+        );
+        let html = render("now", &hosts);
+        assert!(!html.contains("<alpha>"), "{html}");
+        assert!(!html.contains("<b>"), "{html}");
+        assert!(html.contains("&lt;alpha&gt;"), "{html}");
+    }
+}
+
+mod escape {
+    use super::*;
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;",
+            escape("<a href=\"x\">Tom & Jerry's</a>"),
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Loads inventories, i.e. lists of hosts, groups of hosts, and their variables, from commands on
+//! the control node, so that e.g. a CMDB can decide where manifests run.
+//!
+//! Inventory commands are shell commands, set in `sira.toml`:
+//!
+//! ```toml
+//! inventory = ["./inventory/cmdb.sh"]
+//! ```
+//!
+//! Sira runs each command with `sh -c` in its working directory, one at a time, in order, and
+//! reads an [Inventory] from its stdout as JSON, e.g.:
+//!
+//! ```json
+//! {
+//!   "hosts": {"web1": {"role": "frontend"}, "db1": {}},
+//!   "groups": {"web": {"hosts": ["web1", "web2"], "vars": {"port": "8080"}}}
+//! }
+//! ```
+//!
+//! Both keys are optional, and variables' values must be strings. A host may appear in any number
+//! of groups without being listed under `hosts`. The commands' stderr goes to Sira's stderr.
+//!
+//! Once loaded, each name in a manifest's hosts that names a group stands for the group's hosts,
+//! so a manifest can say `hosts: [web]`. A group's name takes precedence over a host's. Each host
+//! gets its groups' variables, in alphabetical order of the groups' names, and then its own, with
+//! later values taking precedence. These become the host's [Manifest::host_vars], which a
+//! manifest's or task's own variables override.
+//!
+//! Groups can also come from DNS. Please see [dns]. With the `ec2` feature, Sira can also list AWS
+//! EC2 instances as an inventory. Please see the `ec2` module.
+//!
+//! [Manifest::host_vars]: crate::core::Manifest::host_vars
+
+use super::{wake, RunOptions};
+use crate::core::Plan;
+use anyhow::{bail, Context};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::{Command, Stdio};
+
+pub mod dns;
+
+#[cfg(feature = "ec2")]
+pub mod ec2;
+
+/// Hosts, groups of hosts, and their variables. Please see the [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Inventory {
+    /// Hosts and their variables, by host name.
+    pub hosts: BTreeMap<String, IndexMap<String, String>>,
+
+    /// Groups of hosts, by group name.
+    pub groups: BTreeMap<String, Group>,
+}
+
+/// A named group of hosts in an [Inventory].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Group {
+    /// The group's hosts, in order.
+    pub hosts: Vec<String>,
+
+    /// Variables for each of the group's hosts.
+    pub vars: IndexMap<String, String>,
+}
+
+impl Inventory {
+    /// Runs each of `commands` with `sh -c`, in order, and merges the inventories they print.
+    /// Please see [Inventory::merge].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a command can't be run, exits with an error, or prints anything but an
+    /// inventory.
+    pub fn load(commands: &[String]) -> anyhow::Result<Self> {
+        let mut inventory = Inventory::default();
+        for command in commands {
+            let loaded = run(command).with_context(|| format!("inventory failed: {command}"))?;
+            inventory.merge(loaded);
+        }
+        Ok(inventory)
+    }
+
+    /// Parses an inventory from JSON.
+    pub fn from_json(json: &[u8]) -> anyhow::Result<Self> {
+        serde_json::from_slice(json).context("could not parse inventory")
+    }
+
+    /// Adds `other`'s hosts, groups, and variables to these. A group in both keeps its hosts and
+    /// gains `other`'s, and where both set a variable, `other`'s value takes precedence.
+    pub fn merge(&mut self, other: Inventory) {
+        for (host, vars) in other.hosts {
+            self.hosts.entry(host).or_default().extend(vars);
+        }
+        for (name, group) in other.groups {
+            let merged = self.groups.entry(name).or_default();
+            for host in group.hosts {
+                if !merged.hosts.contains(&host) {
+                    merged.hosts.push(host);
+                }
+            }
+            merged.vars.extend(group.vars);
+        }
+    }
+
+    /// Returns every host in this inventory, whether listed on its own or in a group, in
+    /// alphabetical order.
+    pub fn hosts(&self) -> BTreeSet<String> {
+        let grouped = self.groups.values().flat_map(|group| &group.hosts);
+        self.hosts.keys().chain(grouped).cloned().collect()
+    }
+
+    /// Returns `host`'s variables: those of each group it belongs to, in alphabetical order of the
+    /// groups' names, and then its own, with later values taking precedence.
+    pub fn vars_for(&self, host: &str) -> IndexMap<String, String> {
+        let mut vars = IndexMap::new();
+        for group in self.groups.values() {
+            if group.hosts.iter().any(|h| h == host) {
+                vars.extend(group.vars.clone());
+            }
+        }
+        if let Some(own) = self.hosts.get(host) {
+            vars.extend(own.clone());
+        }
+        vars
+    }
+
+    /// Replaces each group's name in `hosts` with the group's hosts, keeping the order and
+    /// dropping duplicates.
+    pub fn expand(&self, hosts: &[String]) -> Vec<String> {
+        let mut expanded: Vec<String> = vec![];
+        for name in hosts {
+            let members = match self.groups.get(name) {
+                Some(group) => group.hosts.as_slice(),
+                None => std::slice::from_ref(name),
+            };
+            for host in members {
+                if !expanded.contains(host) {
+                    expanded.push(host.clone());
+                }
+            }
+        }
+        expanded
+    }
+
+    /// Expands the groups in each of `plan`'s manifests' hosts (please see [Inventory::expand])
+    /// and gives each manifest every host's variables (please see [Inventory::vars_for]).
+    ///
+    /// Every host gets its variables, not just the hosts a manifest lists, since a manifest that
+    /// selects hosts by fact only learns them when it runs.
+    pub fn apply(&self, plan: &mut Plan) {
+        let host_vars: Vec<_> = self
+            .hosts()
+            .into_iter()
+            .map(|host| {
+                let vars = self.vars_for(&host);
+                (host, vars)
+            })
+            .filter(|(_, vars)| !vars.is_empty())
+            .collect();
+        for manifest in &mut plan.manifests {
+            manifest.hosts = self.expand(&manifest.hosts);
+            for (host, vars) in &host_vars {
+                manifest
+                    .host_vars
+                    .entry(host.clone())
+                    .or_default()
+                    .extend(vars.clone());
+            }
+        }
+    }
+}
+
+/// Loads the inventory that [RunOptions::inventory] asks for, if any, and applies it to `plan`
+/// (please see [Inventory::apply]). The instances that [RunOptions::ec2] asks for, with the `ec2`
+/// feature, come first, then the groups that [RunOptions::dns] asks for, and then the commands'
+/// inventories, so that the commands can add to the others and override their variables.
+///
+/// Also adds each of the inventory's hosts to [RunOptions::hosts], if it isn't there already, so
+/// that manifests that select hosts by fact consider them, too, and gives each host the MAC address
+/// in its `mac_address` variable, unless it already has one. Please see [wake].
+///
+/// # Errors
+///
+/// Returns an error if the inventory can't be loaded. Please see [Inventory::load].
+pub fn apply(plan: &mut Plan, options: &mut RunOptions) -> anyhow::Result<()> {
+    let mut inventory = Inventory::default();
+    #[cfg(feature = "ec2")]
+    if let Some(config) = &options.ec2 {
+        inventory.merge(ec2::load(config)?);
+    }
+    inventory.merge(dns::load(&options.dns)?);
+    inventory.merge(Inventory::load(&options.inventory)?);
+    if inventory == Inventory::default() {
+        return Ok(());
+    }
+    inventory.apply(plan);
+    for host in inventory.hosts() {
+        let mac_address = inventory
+            .vars_for(&host)
+            .shift_remove(wake::MAC_ADDRESS_VAR);
+        let settings = options.hosts.entry(host.clone()).or_default();
+        if let (None, Some(mac_address)) = (&settings.mac_address, mac_address) {
+            wake::parse_mac_address(&mac_address)
+                .with_context(|| format!("invalid inventory for host {host}"))?;
+            settings.mac_address = Some(mac_address);
+        }
+    }
+    Ok(())
+}
+
+/// Runs `command` with `sh -c` and parses the inventory it prints.
+fn run(command: &str) -> anyhow::Result<Inventory> {
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("could not run sh")?;
+    if !output.status.success() {
+        bail!("inventory exited with {}", output.status);
+    }
+    Inventory::from_json(&output.stdout)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,201 @@
+//! Expands groups of hosts from DNS, for networks where DNS already knows every machine's name.
+//!
+//! Each group comes from either the SRV records of one name or a zone transfer (AXFR) of one
+//! zone, set in a `dns` table in `sira.toml`, keyed by the group's name:
+//!
+//! ```toml
+//! [dns.web]
+//! srv = "_ssh._tcp.web.example.com"
+//!
+//! [dns.lab]
+//! zone = "lab.example.com"
+//! server = "ns1.example.com"
+//! ```
+//!
+//! Sira looks the records up with `dig`, so it must be installed on the control node. A group
+//! from SRV records holds each record's target, in order of priority and then weight, and each
+//! host gets its record's port in the `dns_srv_port` variable. A group from a zone transfer holds
+//! each name in the zone with an `A`, `AAAA`, or `CNAME` record, in the order that the server lists
+//! them, except the zone itself and wildcards. Either way, names lose their trailing dots.
+//!
+//! `server` asks that name server rather than the system's resolver. Most name servers only allow
+//! zone transfers to certain addresses, so a zone usually needs one.
+
+use super::Inventory;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+
+/// Where to find one group's hosts in DNS. Please see the [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DnsSource {
+    /// A name whose SRV records' targets are the group's hosts, e.g. `_ssh._tcp.example.com`.
+    pub srv: Option<String>,
+
+    /// A zone whose names are the group's hosts, e.g. `lab.example.com`.
+    pub zone: Option<String>,
+
+    /// The name server to ask, if not the system's resolver.
+    pub server: Option<String>,
+}
+
+impl DnsSource {
+    /// Returns an error unless exactly one of [DnsSource::srv] and [DnsSource::zone] is set.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match (&self.srv, &self.zone) {
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => bail!("set exactly one of srv and zone"),
+        }
+    }
+
+    /// Returns the arguments to `dig` that look up this source's records.
+    pub(crate) fn dig_args(&self) -> Vec<String> {
+        let mut args = match (&self.srv, &self.zone) {
+            (Some(name), _) => vec!["+short".to_string(), "SRV".to_string(), name.clone()],
+            (None, Some(zone)) => vec![
+                "+noall".to_string(),
+                "+answer".to_string(),
+                "AXFR".to_string(),
+                zone.clone(),
+            ],
+            (None, None) => vec![],
+        };
+        if let Some(server) = &self.server {
+            args.push(format!("@{server}"));
+        }
+        args
+    }
+
+    /// Returns the [Inventory] that `dig`'s output describes, with the group `group`. Please see
+    /// the [module documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output is malformed, reports a failed zone transfer, or names no
+    /// hosts.
+    pub fn parse(&self, group: &str, output: &str) -> anyhow::Result<Inventory> {
+        let mut inventory = Inventory::default();
+        let members = inventory.groups.entry(group.to_string()).or_default();
+        match (&self.srv, &self.zone) {
+            (Some(name), _) => {
+                for (host, port) in parse_srv(output)? {
+                    if members.hosts.contains(&host) {
+                        continue;
+                    }
+                    members.hosts.push(host.clone());
+                    let vars = inventory.hosts.entry(host).or_default();
+                    let _ = vars.insert("dns_srv_port".to_string(), port.to_string());
+                }
+                if members.hosts.is_empty() {
+                    bail!("no SRV records for {name}");
+                }
+            }
+            (None, Some(zone)) => {
+                members.hosts = parse_axfr(zone, output)?;
+                if members.hosts.is_empty() {
+                    bail!("no hosts in zone {zone}");
+                }
+            }
+            (None, None) => self.validate()?,
+        }
+        Ok(inventory)
+    }
+}
+
+/// Looks up each of `sources` in turn and merges the groups they describe into one [Inventory].
+///
+/// # Errors
+///
+/// Returns an error if `dig` can't be run, exits with an error, or finds no hosts for a group.
+pub fn load<'a>(
+    sources: impl IntoIterator<Item = (&'a String, &'a DnsSource)>,
+) -> anyhow::Result<Inventory> {
+    let mut inventory = Inventory::default();
+    for (group, source) in sources {
+        let loaded = dig(source)
+            .and_then(|output| source.parse(group, &output))
+            .with_context(|| format!("could not look up group {group} in DNS"))?;
+        inventory.merge(loaded);
+    }
+    Ok(inventory)
+}
+
+/// Runs `dig` to look up `source`'s records and returns its output.
+fn dig(source: &DnsSource) -> anyhow::Result<String> {
+    source.validate()?;
+    let output = Command::new("dig")
+        .args(source.dig_args())
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("could not run dig")?;
+    if !output.status.success() {
+        bail!("dig exited with {}", output.status);
+    }
+    String::from_utf8(output.stdout).context("dig printed invalid UTF-8")
+}
+
+/// Parses the output of `dig +short SRV`, i.e. lines of the form `<priority> <weight> <port>
+/// <target>`, and returns each target and its port, sorted by priority and then by descending
+/// weight.
+fn parse_srv(output: &str) -> anyhow::Result<Vec<(String, u16)>> {
+    let mut records = vec![];
+    for line in output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [priority, weight, port, target] = fields[..] else {
+            bail!("malformed SRV record: {line}");
+        };
+        let number = |field: &str| -> anyhow::Result<u16> {
+            field
+                .parse()
+                .with_context(|| format!("malformed SRV record: {line}"))
+        };
+        let (priority, weight, port) = (number(priority)?, number(weight)?, number(port)?);
+        // A target of "." means that the service isn't available at this name.
+        let target = target.trim_end_matches('.');
+        if !target.is_empty() {
+            records.push((priority, weight, target.to_string(), port));
+        }
+    }
+    records.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    Ok(records
+        .into_iter()
+        .map(|(_, _, target, port)| (target, port))
+        .collect())
+}
+
+/// Parses the output of `dig +noall +answer AXFR <zone>` and returns the name of each `A`, `AAAA`,
+/// or `CNAME` record, other than `zone` itself and wildcards, in order and without duplicates.
+fn parse_axfr(zone: &str, output: &str) -> anyhow::Result<Vec<String>> {
+    let zone = zone.trim_end_matches('.');
+    let mut hosts: Vec<String> = vec![];
+    for line in output.lines().map(str::trim) {
+        if line.starts_with("; Transfer failed") {
+            bail!("zone transfer of {zone} failed");
+        }
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [name, _ttl, _class, kind, ..] = fields[..] else {
+            bail!("malformed record: {line}");
+        };
+        let name = name.trim_end_matches('.');
+        let is_host = matches!(kind, "A" | "AAAA" | "CNAME");
+        if !is_host || name.eq_ignore_ascii_case(zone) || name.starts_with("*.") {
+            continue;
+        }
+        if !hosts.iter().any(|host| host == name) {
+            hosts.push(name.to_string());
+        }
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,166 @@
+use super::*;
+use indexmap::IndexMap;
+
+fn srv() -> DnsSource {
+    DnsSource {
+        srv: Some("_ssh._tcp.example.com".to_string()),
+        ..Default::default()
+    }
+}
+
+fn zone() -> DnsSource {
+    DnsSource {
+        zone: Some("lab.example.com".to_string()),
+        server: Some("ns1.example.com".to_string()),
+        ..Default::default()
+    }
+}
+
+/// Output of `dig +noall +answer AXFR lab.example.com`.
+const AXFR: &str = "\
+lab.example.com.\t3600\tIN\tSOA\tns1.example.com. admin.example.com. 7 3600 600 86400 60
+lab.example.com.\t3600\tIN\tNS\tns1.example.com.
+lab.example.com.\t3600\tIN\tA\t10.1.0.1
+build1.lab.example.com.\t3600\tIN\tA\t10.1.0.10
+build1.lab.example.com.\t3600\tIN\tAAAA\tfd00::10
+ci.lab.example.com.\t3600\tIN\tCNAME\tbuild1.lab.example.com.
+*.lab.example.com.\t3600\tIN\tA\t10.1.0.1
+lab.example.com.\t3600\tIN\tMX\t10 mail.example.com.
+_ssh._tcp.lab.example.com.\t3600\tIN\tSRV\t0 0 22 build1.lab.example.com.
+lab.example.com.\t3600\tIN\tSOA\tns1.example.com. admin.example.com. 7 3600 600 86400 60
+";
+
+mod validate {
+    use super::*;
+
+    #[test]
+    fn requires_exactly_one_source() {
+        assert!(srv().validate().is_ok());
+        assert!(zone().validate().is_ok());
+        assert!(DnsSource::default().validate().is_err());
+        let both = DnsSource {
+            srv: srv().srv,
+            ..zone()
+        };
+        assert!(both.validate().is_err());
+    }
+}
+
+mod dig_args {
+    use super::*;
+
+    #[test]
+    fn looks_up_srv_records() {
+        assert_eq!(
+            vec!["+short", "SRV", "_ssh._tcp.example.com"],
+            srv().dig_args()
+        );
+    }
+
+    #[test]
+    fn transfers_zone_from_server() {
+        assert_eq!(
+            vec![
+                "+noall",
+                "+answer",
+                "AXFR",
+                "lab.example.com",
+                "@ns1.example.com"
+            ],
+            zone().dig_args(),
+        );
+    }
+}
+
+mod parse {
+    use super::*;
+
+    #[test]
+    fn orders_srv_targets_by_priority_then_weight() {
+        let output = "20 0 22 web3.example.com.\n\
+                      10 5 22 web1.example.com.\n\
+                      10 50 2222 web2.example.com.\n\
+                      30 0 22 web1.example.com.\n";
+        let inventory = srv().parse("web", output).unwrap();
+        assert_eq!(
+            vec!["web2.example.com", "web1.example.com", "web3.example.com"],
+            inventory.groups["web"].hosts,
+        );
+        assert_eq!(
+            IndexMap::from([("dns_srv_port".to_string(), "2222".to_string())]),
+            inventory.hosts["web2.example.com"],
+        );
+        assert_eq!("22", inventory.hosts["web1.example.com"]["dns_srv_port"]);
+    }
+
+    #[test]
+    fn rejects_missing_or_malformed_srv_records() {
+        for output in [
+            "",
+            "0 0 0 .\n",
+            "10 5 web1.example.com.\n",
+            "10 5 ssh web1.\n",
+        ] {
+            assert!(srv().parse("web", output).is_err(), "{output:?}");
+        }
+    }
+
+    #[test]
+    fn lists_names_in_zone() {
+        let inventory = zone().parse("lab", AXFR).unwrap();
+        assert_eq!(
+            vec!["build1.lab.example.com", "ci.lab.example.com"],
+            inventory.groups["lab"].hosts,
+        );
+        assert!(inventory.hosts.is_empty());
+    }
+
+    #[test]
+    fn rejects_failed_or_empty_zone_transfer() {
+        let failed = "; Transfer failed.\n";
+        let empty = "lab.example.com.\t3600\tIN\tNS\tns1.example.com.\n";
+        for output in [failed, empty, "lab.example.com. 3600\n"] {
+            assert!(zone().parse("lab", output).is_err(), "{output:?}");
+        }
+    }
+}
+
+mod dns_config {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn parses_from_sira_toml() {
+        let config = Config::from_toml(
+            r#"
+            [dns.web]
+            srv = "_ssh._tcp.example.com"
+
+            [dns.lab]
+            zone = "lab.example.com"
+            server = "ns1.example.com"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(srv(), config.dns["web"]);
+        assert_eq!(zone(), config.dns["lab"]);
+    }
+
+    #[test]
+    fn rejects_invalid_sources() {
+        for toml in [
+            "[dns.web]\nsrv = \"_ssh._tcp.example.com\"\nzone = \"example.com\"",
+            "[dns.web]\nserver = \"ns1.example.com\"",
+            "[dns.web]\nsrv = \"_ssh._tcp.example.com\"\nport = 22",
+        ] {
+            assert!(Config::from_toml(toml).is_err(), "{toml}");
+        }
+    }
+
+    #[test]
+    fn later_files_replace_groups() {
+        let first = Config::from_toml("[dns.web]\nzone = \"example.com\"").unwrap();
+        let second = Config::from_toml("[dns.web]\nsrv = \"_ssh._tcp.example.com\"").unwrap();
+        assert_eq!(srv(), first.merge(second).dns["web"]);
+    }
+}
@@ -0,0 +1,283 @@
+//! Lists AWS EC2 instances as an [Inventory], so that a cloud fleet needs no hand-maintained list
+//! of hosts. Requires the `ec2` feature.
+//!
+//! Configure it in an `ec2` table in `sira.toml`:
+//!
+//! ```toml
+//! [ec2]
+//! regions = ["us-east-1", "eu-west-1"]
+//! tags = { env = "prod" }
+//! group_by = ["role"]
+//! address = "private-ip"
+//! ```
+//!
+//! Sira lists the running instances in each region with `aws ec2 describe-instances`, so the AWS
+//! CLI must be installed and able to find credentials on the control node, e.g. in
+//! `~/.aws/credentials` or the instance's role. Only instances with every tag in `tags`, if any,
+//! are listed.
+//!
+//! Each instance becomes a host named by its address, e.g. its private IP address. Instances
+//! without one, e.g. without a public IP address when `address = "public-ip"`, are skipped with a
+//! warning. Every instance is in the `ec2` group, and for each tag in `group_by`, an instance with
+//! that tag is in a group named after the tag and its value, e.g. `role_web`. Each host gets these
+//! variables:
+//!
+//! - `ec2_instance_id`, `ec2_instance_type`, `ec2_region`, and `ec2_availability_zone`;
+//! - `ec2_private_ip` and `ec2_public_ip`, if the instance has them; and
+//! - `ec2_tag_<key>` for each of the instance's tags, e.g. `ec2_tag_role`.
+//!
+//! In groups' and variables' names, every character other than an ASCII letter, digit, or
+//! underscore becomes an underscore, so that manifests can name them.
+
+use super::Inventory;
+use anyhow::{bail, Context};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+
+/// The group that every instance belongs to.
+pub const ALL_GROUP: &str = "ec2";
+
+/// Which EC2 instances to list and how to name them. Please see the
+/// [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ec2Config {
+    /// The regions in which to list instances, e.g. `us-east-1`.
+    pub regions: Vec<String>,
+
+    /// Only list instances with each of these tags, set to the given value.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+
+    /// Tags whose values put instances into groups, e.g. `role` puts an instance tagged
+    /// `role=web` into `role_web`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub group_by: Vec<String>,
+
+    /// Which of an instance's addresses names it. Defaults to [Address::PrivateIp].
+    #[serde(default)]
+    pub address: Address,
+
+    /// The AWS CLI profile to use, if not the default.
+    pub profile: Option<String>,
+}
+
+/// Which of an EC2 instance's addresses Sira uses as its host name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Address {
+    /// The instance's private IPv4 address.
+    #[default]
+    PrivateIp,
+
+    /// The instance's public IPv4 address.
+    PublicIp,
+
+    /// The instance's private DNS name.
+    PrivateDns,
+
+    /// The instance's public DNS name.
+    PublicDns,
+}
+
+impl Address {
+    /// Describes this kind of address in words, e.g. "public IP address".
+    pub fn description(self) -> &'static str {
+        match self {
+            Address::PrivateIp => "private IP address",
+            Address::PublicIp => "public IP address",
+            Address::PrivateDns => "private DNS name",
+            Address::PublicDns => "public DNS name",
+        }
+    }
+}
+
+/// The parts of `aws ec2 describe-instances`'s output that Sira reads.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DescribeInstances {
+    #[serde(default)]
+    reservations: Vec<Reservation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Reservation {
+    #[serde(default)]
+    instances: Vec<Instance>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Instance {
+    instance_id: String,
+    instance_type: String,
+    private_ip_address: Option<String>,
+    public_ip_address: Option<String>,
+    private_dns_name: Option<String>,
+    public_dns_name: Option<String>,
+    placement: Option<Placement>,
+    #[serde(default)]
+    tags: Vec<Tag>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Placement {
+    availability_zone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Tag {
+    key: String,
+    value: String,
+}
+
+impl Instance {
+    /// Returns the address that `address` asks for, if the instance has it.
+    fn address(&self, address: Address) -> Option<&str> {
+        let address = match address {
+            Address::PrivateIp => &self.private_ip_address,
+            Address::PublicIp => &self.public_ip_address,
+            Address::PrivateDns => &self.private_dns_name,
+            Address::PublicDns => &self.public_dns_name,
+        };
+        address.as_deref().filter(|address| !address.is_empty())
+    }
+
+    /// Returns the instance's variables. Please see the [module documentation](self).
+    fn vars(&self, region: &str) -> IndexMap<String, String> {
+        let mut vars = IndexMap::from([
+            ("ec2_instance_id".to_string(), self.instance_id.clone()),
+            ("ec2_instance_type".to_string(), self.instance_type.clone()),
+            ("ec2_region".to_string(), region.to_string()),
+        ]);
+        let zone = self
+            .placement
+            .as_ref()
+            .and_then(|placement| placement.availability_zone.as_ref());
+        let optional = [
+            ("ec2_availability_zone", zone),
+            ("ec2_private_ip", self.private_ip_address.as_ref()),
+            ("ec2_public_ip", self.public_ip_address.as_ref()),
+        ];
+        for (var, value) in optional {
+            if let Some(value) = value {
+                let _ = vars.insert(var.to_string(), value.clone());
+            }
+        }
+        for tag in &self.tags {
+            let _ = vars.insert(format!("ec2_tag_{}", sanitize(&tag.key)), tag.value.clone());
+        }
+        vars
+    }
+}
+
+/// Lists the instances in each of `config`'s regions, in order, and merges them into one
+/// [Inventory]. Please see the [module documentation](self).
+///
+/// # Errors
+///
+/// Returns an error if the AWS CLI can't be run, exits with an error, or prints anything
+/// unexpected.
+pub fn load(config: &Ec2Config) -> anyhow::Result<Inventory> {
+    let mut inventory = Inventory::default();
+    for region in &config.regions {
+        let json = describe_instances(config, region)
+            .with_context(|| format!("could not list EC2 instances in {region}"))?;
+        inventory.merge(from_describe_instances(config, region, &json)?);
+    }
+    Ok(inventory)
+}
+
+/// Parses the output of `aws ec2 describe-instances` in `region` into an [Inventory], as `config`
+/// asks. Please see the [module documentation](self).
+pub fn from_describe_instances(
+    config: &Ec2Config,
+    region: &str,
+    json: &[u8],
+) -> anyhow::Result<Inventory> {
+    let described: DescribeInstances =
+        serde_json::from_slice(json).context("could not parse EC2 instances")?;
+    let mut inventory = Inventory::default();
+    let instances = described.reservations.into_iter().flat_map(|r| r.instances);
+    for instance in instances {
+        let Some(host) = instance.address(config.address) else {
+            eprintln!(
+                "Warning: skipping EC2 instance {} in {region}: it has no {}",
+                instance.instance_id,
+                config.address.description(),
+            );
+            continue;
+        };
+        let host = host.to_string();
+        let mut groups = vec![ALL_GROUP.to_string()];
+        for key in &config.group_by {
+            if let Some(tag) = instance.tags.iter().find(|tag| tag.key == *key) {
+                groups.push(format!("{}_{}", sanitize(key), sanitize(&tag.value)));
+            }
+        }
+        for group in groups {
+            let group = inventory.groups.entry(group).or_default();
+            if !group.hosts.contains(&host) {
+                group.hosts.push(host.clone());
+            }
+        }
+        let _ = inventory.hosts.insert(host, instance.vars(region));
+    }
+    Ok(inventory)
+}
+
+/// Returns the arguments to `aws` that list the running instances in `region` that `config` asks
+/// for.
+pub(crate) fn describe_instances_args(config: &Ec2Config, region: &str) -> Vec<String> {
+    let mut filters = vec![json!({"Name": "instance-state-name", "Values": ["running"]})];
+    for (key, value) in &config.tags {
+        filters.push(json!({"Name": format!("tag:{key}"), "Values": [value]}));
+    }
+    let mut args: Vec<String> = [
+        "ec2",
+        "describe-instances",
+        "--output",
+        "json",
+        "--region",
+        region,
+        "--filters",
+    ]
+    .map(String::from)
+    .into();
+    args.push(serde_json::Value::from(filters).to_string());
+    if let Some(profile) = &config.profile {
+        args.extend(["--profile".to_string(), profile.clone()]);
+    }
+    args
+}
+
+/// Runs `aws ec2 describe-instances` in `region` and returns its output.
+fn describe_instances(config: &Ec2Config, region: &str) -> anyhow::Result<Vec<u8>> {
+    let output = Command::new("aws")
+        .args(describe_instances_args(config, region))
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("could not run aws")?;
+    if !output.status.success() {
+        bail!("aws exited with {}", output.status);
+    }
+    Ok(output.stdout)
+}
+
+/// Replaces each character of `name` other than an ASCII letter, digit, or underscore with an
+/// underscore.
+pub(crate) fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod test;
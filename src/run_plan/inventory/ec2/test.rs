@@ -0,0 +1,182 @@
+use super::*;
+
+fn config() -> Ec2Config {
+    Ec2Config {
+        regions: vec!["us-east-1".to_string()],
+        group_by: vec!["role".to_string(), "aws:team".to_string()],
+        ..Default::default()
+    }
+}
+
+/// Output of `aws ec2 describe-instances` with two reservations: one instance with tags and a
+/// public IP address, and one without either.
+const DESCRIBED: &[u8] = br#"{
+    "Reservations": [
+        {
+            "Instances": [
+                {
+                    "InstanceId": "i-0123456789abcdef0",
+                    "InstanceType": "t3.micro",
+                    "PrivateIpAddress": "10.0.0.5",
+                    "PublicIpAddress": "203.0.113.7",
+                    "PrivateDnsName": "ip-10-0-0-5.ec2.internal",
+                    "PublicDnsName": "",
+                    "Placement": {"AvailabilityZone": "us-east-1a", "Tenancy": "default"},
+                    "State": {"Code": 16, "Name": "running"},
+                    "Tags": [
+                        {"Key": "role", "Value": "web"},
+                        {"Key": "aws:team", "Value": "front-end"}
+                    ]
+                }
+            ]
+        },
+        {
+            "Instances": [
+                {
+                    "InstanceId": "i-0fedcba9876543210",
+                    "InstanceType": "m5.large",
+                    "PrivateIpAddress": "10.0.0.6",
+                    "PrivateDnsName": "ip-10-0-0-6.ec2.internal",
+                    "Placement": {"AvailabilityZone": "us-east-1b"}
+                }
+            ]
+        }
+    ]
+}"#;
+
+mod from_describe_instances {
+    use super::*;
+
+    #[test]
+    fn maps_instances_to_hosts_and_groups() {
+        let inventory = from_describe_instances(&config(), "us-east-1", DESCRIBED).unwrap();
+        assert_eq!(
+            vec!["10.0.0.5", "10.0.0.6"],
+            inventory.hosts.keys().collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            vec!["aws_team_front_end", "ec2", "role_web"],
+            inventory.groups.keys().collect::<Vec<_>>(),
+        );
+        assert_eq!(vec!["10.0.0.5", "10.0.0.6"], inventory.groups["ec2"].hosts);
+        assert_eq!(vec!["10.0.0.5"], inventory.groups["role_web"].hosts);
+    }
+
+    #[test]
+    fn sets_host_vars() {
+        let inventory = from_describe_instances(&config(), "us-east-1", DESCRIBED).unwrap();
+        let expected: IndexMap<String, String> = [
+            ("ec2_instance_id", "i-0123456789abcdef0"),
+            ("ec2_instance_type", "t3.micro"),
+            ("ec2_region", "us-east-1"),
+            ("ec2_availability_zone", "us-east-1a"),
+            ("ec2_private_ip", "10.0.0.5"),
+            ("ec2_public_ip", "203.0.113.7"),
+            ("ec2_tag_role", "web"),
+            ("ec2_tag_aws_team", "front-end"),
+        ]
+        .into_iter()
+        .map(|(var, value)| (var.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(expected, inventory.hosts["10.0.0.5"]);
+        assert!(!inventory.hosts["10.0.0.6"].contains_key("ec2_public_ip"));
+    }
+
+    #[test]
+    fn skips_instances_without_address() {
+        for (address, expected) in [
+            (Address::PublicIp, vec!["203.0.113.7"]),
+            (
+                Address::PrivateDns,
+                vec!["ip-10-0-0-5.ec2.internal", "ip-10-0-0-6.ec2.internal"],
+            ),
+            (Address::PublicDns, vec![]),
+        ] {
+            let config = Ec2Config {
+                address,
+                ..config()
+            };
+            let inventory = from_describe_instances(&config, "us-east-1", DESCRIBED).unwrap();
+            assert_eq!(expected, inventory.hosts.keys().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_output() {
+        for json in [
+            "",
+            r#"{"Reservations": {}}"#,
+            r#"{"Reservations": [{"Instances": [{}]}]}"#,
+        ] {
+            assert!(
+                from_describe_instances(&config(), "us-east-1", json.as_bytes()).is_err(),
+                "{json}",
+            );
+        }
+    }
+}
+
+mod describe_instances_args {
+    use super::*;
+
+    #[test]
+    fn filters_running_instances_by_tag() {
+        let config = Ec2Config {
+            tags: BTreeMap::from([("env".to_string(), "prod".to_string())]),
+            profile: Some("ops".to_string()),
+            ..config()
+        };
+        let args = describe_instances_args(&config, "eu-west-1");
+        assert_eq!(
+            vec![
+                "ec2",
+                "describe-instances",
+                "--output",
+                "json",
+                "--region",
+                "eu-west-1",
+                "--filters",
+                r#"[{"Name":"instance-state-name","Values":["running"]},{"Name":"tag:env","Values":["prod"]}]"#,
+                "--profile",
+                "ops",
+            ],
+            args,
+        );
+    }
+}
+
+mod ec2_config {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn parses_from_sira_toml() {
+        let config = Config::from_toml(
+            r#"
+            [ec2]
+            regions = ["us-east-1"]
+            tags = { env = "prod" }
+            group_by = ["role"]
+            address = "public-dns"
+            "#,
+        )
+        .unwrap();
+        let ec2 = config.ec2.unwrap();
+        assert_eq!(vec!["us-east-1".to_string()], ec2.regions);
+        assert_eq!(Some("prod"), ec2.tags.get("env").map(String::as_str));
+        assert_eq!(vec!["role".to_string()], ec2.group_by);
+        assert_eq!(Address::PublicDns, ec2.address);
+        assert_eq!(None, ec2.profile);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(Config::from_toml("[ec2]\nregions = []\nregion = \"us-east-1\"").is_err());
+        assert!(Config::from_toml("[ec2]\nregions = []\naddress = \"ipv6\"").is_err());
+    }
+}
+
+#[test]
+fn sanitize_replaces_other_characters() {
+    assert_eq!("aws_team_front_end_2", sanitize("aws:team/front-end.2"));
+}
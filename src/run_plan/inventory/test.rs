@@ -0,0 +1,223 @@
+use super::*;
+use crate::config::HostConfig;
+use crate::core::fixtures::plan;
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn vars(pairs: &[(&str, &str)]) -> IndexMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Returns an inventory in which `web1` and `web2` are in the `web` group, `web1` is also in the
+/// `canary` group, and `db1` is in no group.
+fn inventory() -> Inventory {
+    Inventory::from_json(
+        br#"{
+            "hosts": {"web1": {"role": "frontend"}, "db1": {"role": "database"}},
+            "groups": {
+                "web": {"hosts": ["web1", "web2"], "vars": {"port": "8080", "role": "web"}},
+                "canary": {"hosts": ["web1"], "vars": {"port": "8081"}}
+            }
+        }"#,
+    )
+    .unwrap()
+}
+
+mod from_json {
+    use super::*;
+
+    #[test]
+    fn parses_empty_inventory() {
+        assert_eq!(Inventory::default(), Inventory::from_json(b"{}").unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_inventories() {
+        for json in [
+            "",
+            r#""web1""#,
+            r#"{"hosts": ["web1"]}"#,
+            r#"{"hosts": {"web1": {"port": 22}}}"#,
+            r#"{"groups": {"web": {"members": ["web1"]}}}"#,
+            r#"{"children": {}}"#,
+        ] {
+            assert!(Inventory::from_json(json.as_bytes()).is_err(), "{json}");
+        }
+    }
+}
+
+mod hosts {
+    use super::*;
+
+    #[test]
+    fn lists_hosts_and_group_members() {
+        let hosts: Vec<String> = inventory().hosts().into_iter().collect();
+        assert_eq!(strings(&["db1", "web1", "web2"]), hosts);
+    }
+}
+
+mod vars_for {
+    use super::*;
+
+    #[test]
+    fn applies_groups_in_order_then_host() {
+        // canary sorts before web, so web's port wins, and web1's own role beats web's.
+        assert_eq!(
+            vars(&[("port", "8080"), ("role", "frontend")]),
+            inventory().vars_for("web1"),
+        );
+        assert_eq!(
+            vars(&[("port", "8080"), ("role", "web")]),
+            inventory().vars_for("web2"),
+        );
+        assert!(inventory().vars_for("mail1").is_empty());
+    }
+}
+
+mod expand {
+    use super::*;
+
+    #[test]
+    fn replaces_groups_with_their_hosts() {
+        assert_eq!(
+            strings(&["db1", "web1", "web2", "mail1"]),
+            inventory().expand(&strings(&["db1", "web", "canary", "mail1", "web2"])),
+        );
+    }
+}
+
+mod merge {
+    use super::*;
+
+    #[test]
+    fn later_inventory_takes_precedence() {
+        let mut merged = inventory();
+        merged.merge(
+            Inventory::from_json(
+                br#"{
+                    "hosts": {"web1": {"role": "api"}},
+                    "groups": {"web": {"hosts": ["web3", "web1"], "vars": {"tls": "yes"}}}
+                }"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(vars(&[("role", "api")]), merged.hosts["web1"]);
+        assert_eq!(
+            strings(&["web1", "web2", "web3"]),
+            merged.groups["web"].hosts
+        );
+        assert_eq!(
+            vars(&[("port", "8080"), ("role", "web"), ("tls", "yes")]),
+            merged.groups["web"].vars,
+        );
+    }
+}
+
+mod apply {
+    use super::*;
+
+    #[test]
+    fn expands_hosts_and_sets_host_vars() {
+        let (mut plan, _, _, _) = plan();
+        plan.manifests[0].hosts = strings(&["web"]);
+        inventory().apply(&mut plan);
+
+        let manifest = &plan.manifests[0];
+        assert_eq!(strings(&["web1", "web2"]), manifest.hosts);
+        assert_eq!(
+            strings(&["db1", "web1", "web2"]),
+            manifest.host_vars.keys().cloned().collect::<Vec<_>>(),
+        );
+        assert_eq!(inventory().vars_for("web2"), manifest.host_vars["web2"]);
+    }
+
+    #[test]
+    fn adds_hosts_to_options() {
+        let (mut plan, _, _, _) = plan();
+        let mut options = RunOptions {
+            inventory: strings(&[r#"echo '{"groups": {"web": {"hosts": ["web1"]}}}'"#]),
+            hosts: BTreeMap::from([(
+                "web1".to_string(),
+                HostConfig {
+                    port: Some(2222),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        apply(&mut plan, &mut options).unwrap();
+        assert_eq!(Some(2222), options.hosts["web1"].port);
+    }
+
+    #[test]
+    fn takes_mac_addresses_from_variables() {
+        let (mut plan, _, _, _) = plan();
+        let json = r#"{"hosts": {
+            "web1": {"mac_address": "00:11:22:33:44:55"},
+            "web2": {"mac_address": "00:11:22:33:44:66"}
+        }}"#;
+        let mut options = RunOptions {
+            inventory: vec![format!("echo '{json}'")],
+            hosts: BTreeMap::from([(
+                "web2".to_string(),
+                HostConfig {
+                    mac_address: Some("aa:bb:cc:dd:ee:ff".to_string()),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        apply(&mut plan, &mut options).unwrap();
+        let mac_address = |host: &str| options.hosts[host].mac_address.clone();
+        assert_eq!(Some("00:11:22:33:44:55".to_string()), mac_address("web1"));
+        assert_eq!(Some("aa:bb:cc:dd:ee:ff".to_string()), mac_address("web2"));
+
+        let mut options = RunOptions {
+            inventory: strings(&[r#"echo '{"hosts": {"web1": {"mac_address": "web1"}}}'"#]),
+            ..Default::default()
+        };
+        assert!(apply(&mut plan, &mut options).is_err());
+    }
+
+    #[test]
+    fn does_nothing_without_inventory() {
+        let (mut plan, _, _, _) = plan();
+        let expected = plan.clone();
+        apply(&mut plan, &mut RunOptions::default()).unwrap();
+        assert_eq!(expected, plan);
+    }
+}
+
+mod load {
+    use super::*;
+
+    #[test]
+    fn merges_each_command_in_order() {
+        let inventory = Inventory::load(&strings(&[
+            r#"echo '{"hosts": {"web1": {"role": "web"}}}'"#,
+            r#"printf '{"hosts": {"web1": {"role": "api"}, "db1": {}}}'"#,
+        ]))
+        .unwrap();
+        assert_eq!(vars(&[("role", "api")]), inventory.hosts["web1"]);
+        assert!(inventory.hosts.contains_key("db1"));
+    }
+
+    #[test]
+    fn names_failed_command() {
+        let err = Inventory::load(&strings(&["exit 3"])).unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("inventory failed: exit 3"), "{message}");
+        assert!(message.contains("exit status: 3"), "{message}");
+    }
+
+    #[test]
+    fn rejects_invalid_output() {
+        let err = Inventory::load(&strings(&["echo web1"])).unwrap_err();
+        assert!(format!("{err:#}").contains("could not parse inventory"));
+    }
+}
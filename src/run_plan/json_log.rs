@@ -0,0 +1,233 @@
+//! A structured log with one JSON object per action, for log aggregators like Loki or
+//! Elasticsearch.
+//!
+//! The log is in [JSON Lines](https://jsonlines.org) format: each line holds one [Record]. For
+//! example (wrapped here for readability):
+//!
+//! ```json
+//! {"timestamp":"2024-01-01T12:00:00.000000000-08:00","host":"web1","manifest":"Web servers",
+//! "task":"Restart app","action":{"command":["systemctl restart app.service"]},
+//! "result":"success","status":"changed","exit_code":0,"duration_ms":1042,"stdout":"",
+//! "stderr":""}
+//! ```
+//!
+//! Unlike the audit log (see [super::audit]), this log is not signed. It records the same
+//! redacted actions and output that Sira prints to the terminal.
+
+use crate::core::action::{ClientError, ErrorCode, Status};
+use crate::core::Action;
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task;
+
+use super::report::{Report, RunningActions};
+
+/// The path that tells [JsonLog] to write to stdout instead of a file.
+pub const STDOUT: &str = "-";
+
+/// The information logged about each action.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// When the action finished, in RFC 3339 format.
+    pub timestamp: String,
+
+    /// The managed node on which the action ran.
+    pub host: String,
+
+    /// The name of the manifest that the action came from.
+    pub manifest: String,
+
+    /// The name of the task that the action came from.
+    pub task: String,
+
+    /// The action, after variable substitution and redaction.
+    pub action: Action,
+
+    /// Either `success` or `failure`.
+    pub result: String,
+
+    /// Whether the action changed anything. Please see [Status].
+    pub status: Status,
+
+    /// The action's exit code, if any.
+    pub exit_code: Option<i32>,
+
+    /// The class of error that `sira-client` reported, if the action failed and it reported one.
+    /// Please see [crate::core::action::error].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorCode>,
+
+    /// How long the action took to run, in milliseconds, including signing and file transfers.
+    pub duration_ms: u64,
+
+    /// The action's stdout, with invalid UTF-8 replaced.
+    pub stdout: String,
+
+    /// The action's stderr, with invalid UTF-8 replaced.
+    pub stderr: String,
+
+    /// A unified diff of the changes that the action made to a file, if any. Please see
+    /// [super::report::diff].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+impl Record {
+    /// Creates a [Record] of an action that just finished on `host` after running for
+    /// `duration`.
+    pub fn new(
+        host: impl Into<String>,
+        manifest: impl Into<String>,
+        task: impl Into<String>,
+        action: &Action,
+        output: &Output,
+        duration: Duration,
+    ) -> Self {
+        Record {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            host: host.into(),
+            manifest: manifest.into(),
+            task: task.into(),
+            action: action.clone(),
+            result: match output.status.success() {
+                true => "success",
+                false => "failure",
+            }
+            .to_string(),
+            status: Status::from_output(output),
+            exit_code: output.status.code(),
+            error: ClientError::from_output(output).map(|error| error.code),
+            duration_ms: duration.as_millis() as u64,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            diff: super::report::diff(action, &output.stdout),
+        }
+    }
+}
+
+/// Writes a [Record] to a JSON log for every action that runs.
+///
+/// Because [JsonLog] implements [Report], it runs alongside (or instead of) the usual reporting.
+/// The log file is opened in append mode when the first action is about to start. Clones share the
+/// same file.
+#[derive(Clone, Debug)]
+pub struct JsonLog {
+    /// The path to the log file, or [STDOUT].
+    path: PathBuf,
+
+    /// The open log file and the actions in flight on each host.
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The open log file, if any. Always [None] when writing to stdout.
+    file: Option<File>,
+
+    /// The source and start time of each action in flight on each host.
+    running: RunningActions,
+}
+
+impl JsonLog {
+    /// Creates a [JsonLog] that appends to `path`, or writes to stdout if `path` is [STDOUT].
+    ///
+    /// Does not touch the file system; see [JsonLog].
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        JsonLog {
+            path: path.as_ref().to_owned(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Returns whether this log writes to stdout.
+    pub fn is_stdout(&self) -> bool {
+        self.path.as_os_str() == STDOUT
+    }
+
+    /// Notes the manifest and task that the next action on `host` comes from.
+    pub(crate) fn set_source(&self, host: &str, manifest: &str, task: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.running.source(host, manifest, task);
+    }
+
+    /// Opens the log file, if needed, and starts timing the newest action on `host`.
+    pub(crate) fn start(&self, host: &str) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        self.open(&mut state)?;
+        state.running.start(host);
+        Ok(())
+    }
+
+    /// Writes a record of the outcome of the oldest action in flight on `host`.
+    pub(crate) fn append(
+        &self,
+        host: &str,
+        action: &Action,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        self.open(&mut state)?;
+        let running = state.running.finish(host);
+
+        let (manifest, task, duration) = match running {
+            Some(r) => (r.manifest, r.task, r.started.elapsed()),
+            None => Default::default(),
+        };
+        let record = Record::new(host, manifest, task, action, output, duration);
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+
+        match &mut state.file {
+            Some(file) => {
+                file.write_all(line.as_bytes())?;
+                file.flush()?;
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(line.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the log file, unless it's open already or this log writes to stdout.
+    fn open(&self, state: &mut State) -> anyhow::Result<()> {
+        if state.file.is_none() && !self.is_stdout() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("could not open JSON log: {}", self.path.display()))?;
+            state.file = Some(file);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Report for JsonLog {
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        self.set_source(host, manifest, task);
+        Ok(())
+    }
+
+    async fn starting(&mut self, host: &str, _action: &Action) -> io::Result<()> {
+        task::block_in_place(|| self.start(host)).map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        task::block_in_place(|| self.append(host, action, output))
+            .map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+}
+
+#[cfg(test)]
+mod test;
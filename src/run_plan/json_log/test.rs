@@ -0,0 +1,170 @@
+use super::*;
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn output(code: i32) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: b"out\n".to_vec(),
+        stderr: vec![],
+    }
+}
+
+fn action(command: &str) -> Action {
+    Action::Command(vec![command.to_string()])
+}
+
+fn read_log(path: &Path) -> Vec<Record> {
+    fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+mod append {
+    use super::*;
+
+    #[test]
+    fn writes_one_record_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sira.jsonl");
+        let log = JsonLog::new(&path);
+
+        log.set_source("alpha", "Web servers", "Restart app");
+        log.start("alpha").unwrap();
+        log.append("alpha", &action("true"), &output(0)).unwrap();
+        log.set_source("bravo", "Web servers", "Check app");
+        log.start("bravo").unwrap();
+        log.append("bravo", &action("false"), &output(1)).unwrap();
+
+        let records = read_log(&path);
+        assert_eq!(2, records.len());
+
+        assert_eq!("alpha", records[0].host);
+        assert_eq!("Web servers", records[0].manifest);
+        assert_eq!("Restart app", records[0].task);
+        assert_eq!(action("true"), records[0].action);
+        assert_eq!("success", records[0].result);
+        assert_eq!(Status::Changed, records[0].status);
+        assert_eq!(Some(0), records[0].exit_code);
+        assert_eq!("out\n", records[0].stdout);
+
+        assert_eq!("bravo", records[1].host);
+        assert_eq!("Check app", records[1].task);
+        assert_eq!("failure", records[1].result);
+        assert_eq!(Some(1), records[1].exit_code);
+        assert_eq!(Status::Failed, records[1].status);
+        assert_eq!(None, records[1].error);
+    }
+
+    #[test]
+    fn records_reported_error_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sira.jsonl");
+        let mut output = output(1);
+        output.stdout = ClientError::new(ErrorCode::Unauthorized, "bad signature")
+            .line()
+            .into_bytes();
+        JsonLog::new(&path)
+            .append("alpha", &action("true"), &output)
+            .unwrap();
+
+        let records = read_log(&path);
+        assert_eq!(Some(ErrorCode::Unauthorized), records[0].error);
+    }
+
+    #[test]
+    fn appends_to_existing_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sira.jsonl");
+        JsonLog::new(&path)
+            .append("alpha", &action("true"), &output(0))
+            .unwrap();
+        JsonLog::new(&path)
+            .append("bravo", &action("true"), &output(0))
+            .unwrap();
+
+        let records = read_log(&path);
+        assert_eq!(2, records.len());
+        assert_eq!("bravo", records[1].host);
+    }
+
+    #[test]
+    fn serializes_action_as_singleton_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sira.jsonl");
+        JsonLog::new(&path)
+            .append("alpha", &action("true"), &output(0))
+            .unwrap();
+
+        let line = fs::read_to_string(&path).unwrap();
+        assert!(line.contains(r#""action":{"command":["true"]}"#));
+    }
+
+    #[test]
+    fn records_diff_of_changed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sira.jsonl");
+        let line_in_file = Action::LineInFile {
+            path: "/etc/hosts".to_string(),
+            line: "b".to_string(),
+            pattern: None,
+            after: None,
+            indent: false,
+        };
+        let diff = "--- /etc/hosts\n+++ /etc/hosts\n@@ -1 +1,2 @@\n a\n+b\n";
+        let changed = Output {
+            stdout: diff.as_bytes().to_vec(),
+            ..output(0)
+        };
+        let log = JsonLog::new(&path);
+        log.append("alpha", &line_in_file, &changed).unwrap();
+        log.append("alpha", &action("true"), &output(0)).unwrap();
+
+        let records = read_log(&path);
+        assert_eq!(Some(diff.to_string()), records[0].diff);
+        assert_eq!(None, records[1].diff);
+        assert!(!fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .nth(1)
+            .unwrap()
+            .contains("diff"));
+    }
+
+    #[test]
+    fn returns_error_if_log_is_unwritable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("sira.jsonl");
+        assert!(JsonLog::new(path)
+            .append("alpha", &action("true"), &output(0))
+            .is_err());
+    }
+}
+
+#[test]
+fn is_stdout_works() {
+    assert!(JsonLog::new(STDOUT).is_stdout());
+    assert!(!JsonLog::new("sira.jsonl").is_stdout());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_source_of_each_action() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sira.jsonl");
+    let mut log = JsonLog::new(&path);
+
+    log.action_source("alpha", "Web servers", "Restart app")
+        .await
+        .unwrap();
+    log.starting("alpha", &action("true")).await.unwrap();
+    log.report("alpha", &action("true"), &output(0))
+        .await
+        .unwrap();
+
+    let records = read_log(&path);
+    assert_eq!("Web servers", records[0].manifest);
+    assert_eq!("Restart app", records[0].task);
+}
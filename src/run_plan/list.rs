@@ -0,0 +1,147 @@
+//! Describes what a [Plan] would do without running it, e.g. for `sira --list-hosts` and
+//! `sira graph`.
+
+use crate::core::action::HostAction;
+use crate::core::{Plan, Task};
+use crate::run_plan::report;
+use anyhow::bail;
+use std::fmt::Write;
+
+/// Returns the hosts that `plan` would run on, one per line.
+///
+/// If `by_manifest`, lists each manifest's name followed by its hosts, indented, in the order
+/// the manifest lists them. Otherwise, lists each host once, in alphabetical order, as
+/// [Plan::hosts] does.
+pub fn hosts(plan: &Plan, by_manifest: bool) -> String {
+    let mut list = String::new();
+    if !by_manifest {
+        for host in plan.hosts() {
+            list.push_str(&host);
+            list.push('\n');
+        }
+        return list;
+    }
+
+    for manifest in &plan.manifests {
+        let _ = writeln!(list, "{}:", manifest.name);
+        for host in &manifest.hosts {
+            let _ = writeln!(list, "    {host}");
+        }
+    }
+    list
+}
+
+/// Returns the actions that `plan` would run on `host`, in order, under the names of the
+/// manifests and tasks that list them. Each task's checks follow its actions, marked `verify:`,
+/// although they run only after every action in `plan`.
+///
+/// Each action appears as its kind and a short summary, as in the run report. If `compiled`, shows
+/// each action with its variables substituted, as it would be sent to `host`; values that the
+/// manifest or task marks as sensitive stay masked.
+///
+/// # Errors
+///
+/// Returns an error if no manifest in `plan` names `host`.
+pub fn actions(plan: &Plan, host: &str, compiled: bool) -> anyhow::Result<String> {
+    let mut list = String::new();
+    let manifests = plan
+        .manifests
+        .iter()
+        .filter(|manifest| manifest.hosts.iter().any(|h| h == host));
+    let mut found = false;
+    for manifest in manifests {
+        found = true;
+        let _ = writeln!(list, "{}:", manifest.name);
+        for task in &manifest.include {
+            let _ = writeln!(list, "    {}:", task.name);
+            let title = |action| match compiled {
+                true => {
+                    let host_action = HostAction::new(host, manifest, task, action);
+                    report::title(&host_action.redactor().redact_action(&host_action.compile()))
+                }
+                false => report::title(action),
+            };
+            for action in &task.actions {
+                let _ = writeln!(list, "        {}", title(action));
+            }
+            for action in &task.verify {
+                let _ = writeln!(list, "        verify: {}", title(action));
+            }
+        }
+    }
+    if !found {
+        bail!("no manifest names host {host:?}");
+    }
+    Ok(list)
+}
+
+/// Returns a [Graphviz](https://graphviz.org/) graph, in the DOT language, of the manifests in
+/// `plan`, the tasks that each manifest includes, and the hosts that each manifest runs on.
+///
+/// Edges from a manifest to its tasks are numbered in the order in which the tasks run. A task
+/// that several manifests include appears once, with an edge from each of them.
+pub fn graph(plan: &Plan) -> String {
+    let mut graph = String::from("digraph sira {\n    rankdir=LR;\n");
+    let mut tasks: Vec<&Task> = vec![];
+    let mut hosts: Vec<&str> = vec![];
+    for (index, manifest) in plan.manifests.iter().enumerate() {
+        let id = format!("manifest{index}");
+        let _ = writeln!(
+            graph,
+            "    {id} [label={}, shape=folder];",
+            quote(&manifest.name),
+        );
+
+        for (order, task) in manifest.include.iter().enumerate() {
+            let task_index = match tasks.iter().position(|t| *t == task) {
+                Some(task_index) => task_index,
+                None => {
+                    tasks.push(task);
+                    let _ = writeln!(
+                        graph,
+                        "    task{} [label={}, shape=box];",
+                        tasks.len() - 1,
+                        quote(&task.name),
+                    );
+                    tasks.len() - 1
+                }
+            };
+            let _ = writeln!(
+                graph,
+                "    {id} -> task{task_index} [label=\"{}\"];",
+                order + 1
+            );
+        }
+
+        for host in &manifest.hosts {
+            let host_index = match hosts.iter().position(|h| h == host) {
+                Some(host_index) => host_index,
+                None => {
+                    hosts.push(host);
+                    let _ = writeln!(
+                        graph,
+                        "    host{} [label={}, shape=ellipse];",
+                        hosts.len() - 1,
+                        quote(host),
+                    );
+                    hosts.len() - 1
+                }
+            };
+            let _ = writeln!(graph, "    {id} -> host{host_index} [style=dashed];");
+        }
+    }
+    graph.push_str("}\n");
+    graph
+}
+
+/// Quotes `label` as a DOT string.
+fn quote(label: &str) -> String {
+    let escaped = label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod test;
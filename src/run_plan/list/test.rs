@@ -0,0 +1,151 @@
+use super::*;
+use crate::core::fixtures::plan;
+use crate::core::Task;
+
+mod hosts {
+    use super::*;
+
+    fn two_manifests() -> Plan {
+        let (mut plan, mut manifest, _, _) = plan();
+        plan.manifests[0].name = "Web servers".to_string();
+        plan.manifests[0].hosts = vec!["web2".to_string(), "web1".to_string()];
+        manifest.name = "Everything".to_string();
+        manifest.hosts = vec!["db1".to_string(), "web1".to_string()];
+        plan.manifests.push(manifest);
+        plan
+    }
+
+    #[test]
+    fn lists_each_host_once_in_order() {
+        assert_eq!("db1\nweb1\nweb2\n", hosts(&two_manifests(), false));
+    }
+
+    #[test]
+    fn lists_hosts_by_manifest() {
+        assert_eq!(
+            "Web servers:\n    web2\n    web1\nEverything:\n    db1\n    web1\n",
+            hosts(&two_manifests(), true),
+        );
+    }
+
+    #[test]
+    fn lists_nothing_for_empty_plan() {
+        assert_eq!("", hosts(&Plan::new(), false));
+        assert_eq!("", hosts(&Plan::new(), true));
+    }
+}
+
+mod actions {
+    use super::*;
+    use crate::core::action::redact::MASK;
+    use crate::core::Action;
+
+    fn plan_with_vars() -> Plan {
+        let (mut plan, _, _, _) = plan();
+        let manifest = &mut plan.manifests[0];
+        let _ = manifest.vars.insert("user".into(), "archie".into());
+        let _ = manifest.vars.insert("password".into(), "hunter2".into());
+        manifest.sensitive = vec!["password".into()];
+        manifest.include[0].actions = vec![
+            Action::Command(vec!["passwd -u $user".into()]),
+            Action::Command(vec!["login -p $password".into()]),
+        ];
+        plan
+    }
+
+    #[test]
+    fn lists_actions_under_manifest_and_task() {
+        let (mut plan, mut manifest, task, _) = plan();
+        manifest.name = "Other".to_string();
+        manifest.hosts = vec!["web1".to_string()];
+        plan.manifests.push(manifest);
+        plan.manifests[0].include.push(Task {
+            name: "Second".to_string(),
+            ..task
+        });
+        assert_eq!(
+            "API test:\n    \
+            API test:\n        \
+            command: echo hi; pwd\n    \
+            Second:\n        \
+            command: echo hi; pwd\n",
+            actions(&plan, "archie-desktop", false).unwrap(),
+        );
+    }
+
+    #[test]
+    fn lists_actions_as_written() {
+        let list = actions(&plan_with_vars(), "archie-desktop", false).unwrap();
+        assert!(list.contains("command: passwd -u $user\n"));
+        assert!(list.contains("command: login -p $password\n"));
+    }
+
+    #[test]
+    fn lists_compiled_actions_with_sensitive_values_masked() {
+        let list = actions(&plan_with_vars(), "archie-desktop", true).unwrap();
+        assert!(list.contains("command: passwd -u archie\n"));
+        assert!(list.contains(&format!("command: login -p {MASK}\n")));
+        assert!(!list.contains("hunter2"));
+    }
+
+    #[test]
+    fn lists_checks_after_actions() {
+        let mut plan = plan_with_vars();
+        plan.manifests[0].include[0].verify = vec![Action::Command(vec!["id $user".into()])];
+        let list = actions(&plan, "archie-desktop", true).unwrap();
+        assert!(list.ends_with(&format!(
+            "command: login -p {MASK}\n        verify: command: id archie\n"
+        )));
+    }
+
+    #[test]
+    fn returns_error_for_unknown_host() {
+        assert!(actions(&plan().0, "web1", false).is_err());
+    }
+}
+
+mod graph {
+    use super::*;
+
+    #[test]
+    fn links_manifests_to_tasks_and_hosts() {
+        let (mut plan, mut manifest, task, _) = plan();
+        plan.manifests[0].include.push(Task {
+            name: "Second".to_string(),
+            ..task
+        });
+        manifest.name = "Other".to_string();
+        manifest.hosts = vec!["archie-desktop".to_string(), "web1".to_string()];
+        plan.manifests.push(manifest);
+        assert_eq!(
+            "digraph sira {\n    \
+            rankdir=LR;\n    \
+            manifest0 [label=\"API test\", shape=folder];\n    \
+            task0 [label=\"API test\", shape=box];\n    \
+            manifest0 -> task0 [label=\"1\"];\n    \
+            task1 [label=\"Second\", shape=box];\n    \
+            manifest0 -> task1 [label=\"2\"];\n    \
+            host0 [label=\"archie-desktop\", shape=ellipse];\n    \
+            manifest0 -> host0 [style=dashed];\n    \
+            manifest1 [label=\"Other\", shape=folder];\n    \
+            manifest1 -> task0 [label=\"1\"];\n    \
+            manifest1 -> host0 [style=dashed];\n    \
+            host1 [label=\"web1\", shape=ellipse];\n    \
+            manifest1 -> host1 [style=dashed];\n\
+            }\n",
+            graph(&plan),
+        );
+    }
+
+    #[test]
+    fn escapes_labels() {
+        let (mut plan, _, _, _) = plan();
+        plan.manifests[0].name = "Say \"hi\"\\\n".to_string();
+        assert!(graph(&plan).contains(r#"[label="Say \"hi\"\\\n", shape=folder]"#));
+    }
+
+    #[test]
+    fn graphs_empty_plan() {
+        assert_eq!("digraph sira {\n    rankdir=LR;\n}\n", graph(&Plan::new()));
+    }
+}
@@ -0,0 +1,320 @@
+//! Exports Prometheus metrics about each run, for graphing and alerting on fleet health.
+//!
+//! Sira writes the metrics to a file in the Prometheus text format, for the node exporter's
+//! textfile collector to pick up, e.g.:
+//!
+//! ```text
+//! sira --metrics /var/lib/node_exporter/textfile_collector/sira.prom <manifest-file>...
+//! ```
+//!
+//! The file holds running totals of runs and failed runs, which carry over from the previous
+//! contents of the file, plus per-host details of the most recent run: whether the host succeeded,
+//! how long it took to connect, how many actions succeeded and failed, how long they took, and how
+//! many bytes were uploaded.
+//!
+//! The file is replaced atomically at the end of each run, so the collector never sees a partial
+//! file.
+
+use super::report::Report;
+use crate::core::Action;
+use anyhow::Context;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The name of the counter of all runs.
+const RUNS_TOTAL: &str = "sira_runs_total";
+
+/// The name of the counter of runs in which any host failed.
+const FAILED_RUNS_TOTAL: &str = "sira_failed_runs_total";
+
+/// What the metrics record about each host during a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HostStats {
+    /// The number of actions that succeeded.
+    pub succeeded: u64,
+
+    /// The number of actions that failed.
+    pub failed: u64,
+
+    /// How long the host's actions took in total, including signing and file transfers.
+    pub duration_seconds: f64,
+
+    /// The total size of the files that the host's actions uploaded.
+    pub upload_bytes: u64,
+
+    /// How long it took to connect to the host.
+    pub connect_seconds: f64,
+}
+
+/// Records metrics about a run. Please see the [module documentation](self).
+///
+/// Because [Metrics] implements [Report], it sees each action's outcome as the run progresses. It
+/// can't tell when the run is over, though, so whoever runs the [Plan](crate::core::Plan) must
+/// call [Metrics::finished] to write the metrics file.
+///
+/// Clones share the same metrics.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    /// The path to the metrics file.
+    path: PathBuf,
+
+    /// The statistics for each host so far.
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    hosts: HashMap<String, HostStats>,
+}
+
+impl Metrics {
+    /// Creates a [Metrics] that writes to `path`.
+    ///
+    /// Does not touch the file system until [Metrics::finished] is called.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Metrics {
+            path: path.as_ref().to_owned(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Adds how long an action took to the time spent running actions on `host`.
+    ///
+    /// Actions in flight at the same time (please see
+    /// [RunOptions::pipeline](super::RunOptions::pipeline)) each count in full.
+    pub(crate) fn record_timing(&self, host: &str, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.hosts.entry(host.to_string()).or_default();
+        stats.duration_seconds += duration.as_secs_f64();
+    }
+
+    /// Records how long it took to connect to `host`.
+    pub(crate) fn record_connection(&self, host: &str, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.hosts.entry(host.to_string()).or_default();
+        stats.connect_seconds = duration.as_secs_f64();
+    }
+
+    /// Adds an action's outcome to the statistics for `host`.
+    pub(crate) fn record(&self, host: &str, action: &Action, output: &Output) {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.hosts.entry(host.to_string()).or_default();
+
+        match output.status.success() {
+            true => stats.succeeded += 1,
+            false => stats.failed += 1,
+        }
+        if let (true, Action::Upload { from, .. }) = (output.status.success(), action) {
+            stats.upload_bytes += fs::metadata(from).map(|m| m.len()).unwrap_or_default();
+        }
+    }
+
+    /// Writes the metrics file for a run on `hosts`, in which `errors` occurred.
+    ///
+    /// Reads the totals from the existing metrics file, if any, and adds this run to them.
+    pub fn finished(
+        &self,
+        hosts: &[String],
+        errors: &[(String, anyhow::Error)],
+    ) -> anyhow::Result<()> {
+        let previous = match fs::read_to_string(&self.path) {
+            Ok(previous) => previous,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("could not read metrics: {}", self.path.display()))
+            }
+        };
+
+        let failed_hosts: Vec<&str> = errors.iter().map(|(host, _)| host.as_str()).collect();
+        let mut stats: BTreeMap<String, (bool, HostStats)> = BTreeMap::new();
+        {
+            let state = self.state.lock().unwrap();
+            for host in hosts {
+                let host_stats = state.hosts.get(host).cloned().unwrap_or_default();
+                let success = !failed_hosts.contains(&host.as_str());
+                stats.insert(host.clone(), (success, host_stats));
+            }
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let metrics = render(&previous, timestamp, &stats);
+
+        // Write to a temporary file in the same directory, then rename it into place, so that the
+        // textfile collector never reads a partial file.
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, metrics)
+            .and_then(|()| fs::rename(&temporary, &self.path))
+            .with_context(|| format!("could not write metrics: {}", self.path.display()))
+    }
+}
+
+#[async_trait]
+impl Report for Metrics {
+    async fn connected(&mut self, host: &str, duration: Duration) -> io::Result<()> {
+        self.record_connection(host, duration);
+        Ok(())
+    }
+
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn timing(&mut self, host: &str, _action: &Action, duration: Duration) -> io::Result<()> {
+        self.record_timing(host, duration);
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        self.record(host, action, output);
+        Ok(())
+    }
+}
+
+/// Renders the metrics file for a run that finished at `timestamp` (in seconds since the Unix
+/// epoch), given the `previous` contents of the file and each host's success and statistics.
+pub(crate) fn render(
+    previous: &str,
+    timestamp: u64,
+    hosts: &BTreeMap<String, (bool, HostStats)>,
+) -> String {
+    let run_failed = hosts.values().any(|(success, _)| !success);
+    let runs = previous_counter(previous, RUNS_TOTAL) + 1;
+    let failed_runs = previous_counter(previous, FAILED_RUNS_TOTAL) + run_failed as u64;
+
+    // Writing to a String can't fail, so the results of write! are safe to ignore.
+    fn header(metrics: &mut String, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(metrics, "# HELP {name} {help}");
+        let _ = writeln!(metrics, "# TYPE {name} {kind}");
+    }
+
+    let mut metrics = String::new();
+
+    header(&mut metrics, RUNS_TOTAL, "counter", "Number of Sira runs.");
+    let _ = writeln!(metrics, "{RUNS_TOTAL} {runs}");
+
+    header(
+        &mut metrics,
+        FAILED_RUNS_TOTAL,
+        "counter",
+        "Number of Sira runs in which any host failed.",
+    );
+    let _ = writeln!(metrics, "{FAILED_RUNS_TOTAL} {failed_runs}");
+
+    header(
+        &mut metrics,
+        "sira_last_run_timestamp_seconds",
+        "gauge",
+        "When the last Sira run finished.",
+    );
+    let _ = writeln!(metrics, "sira_last_run_timestamp_seconds {timestamp}");
+
+    header(
+        &mut metrics,
+        "sira_last_run_host_success",
+        "gauge",
+        "Whether each host completed the last Sira run.",
+    );
+    for (host, (success, _)) in hosts {
+        let _ = writeln!(
+            metrics,
+            "sira_last_run_host_success{{host=\"{}\"}} {}",
+            escape_label(host),
+            *success as u8,
+        );
+    }
+
+    header(
+        &mut metrics,
+        "sira_last_run_connect_duration_seconds",
+        "gauge",
+        "Time spent connecting to each host in the last Sira run.",
+    );
+    for (host, (_, stats)) in hosts {
+        let _ = writeln!(
+            metrics,
+            "sira_last_run_connect_duration_seconds{{host=\"{}\"}} {:.3}",
+            escape_label(host),
+            stats.connect_seconds,
+        );
+    }
+
+    header(
+        &mut metrics,
+        "sira_last_run_actions",
+        "gauge",
+        "Number of actions that succeeded or failed on each host in the last Sira run.",
+    );
+    for (host, (_, stats)) in hosts {
+        for (result, count) in [("success", stats.succeeded), ("failure", stats.failed)] {
+            let _ = writeln!(
+                metrics,
+                "sira_last_run_actions{{host=\"{}\",result=\"{result}\"}} {count}",
+                escape_label(host),
+            );
+        }
+    }
+
+    header(
+        &mut metrics,
+        "sira_last_run_action_duration_seconds",
+        "gauge",
+        "Total time spent running actions on each host in the last Sira run.",
+    );
+    for (host, (_, stats)) in hosts {
+        let _ = writeln!(
+            metrics,
+            "sira_last_run_action_duration_seconds{{host=\"{}\"}} {:.3}",
+            escape_label(host),
+            stats.duration_seconds,
+        );
+    }
+
+    header(
+        &mut metrics,
+        "sira_last_run_upload_bytes",
+        "gauge",
+        "Total size of files uploaded to each host in the last Sira run.",
+    );
+    for (host, (_, stats)) in hosts {
+        let _ = writeln!(
+            metrics,
+            "sira_last_run_upload_bytes{{host=\"{}\"}} {}",
+            escape_label(host),
+            stats.upload_bytes,
+        );
+    }
+
+    metrics
+}
+
+/// Returns the value of the unlabeled counter `name` in `previous`, or 0 if there is none.
+fn previous_counter(previous: &str, name: &str) -> u64 {
+    previous
+        .lines()
+        .filter_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .find_map(|value| value.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+/// Escapes `value` for use as a Prometheus label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,141 @@
+use super::*;
+use anyhow::anyhow;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn output(code: i32) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: vec![],
+        stderr: vec![],
+    }
+}
+
+fn hosts() -> Vec<String> {
+    vec!["alpha".to_string(), "bravo".to_string()]
+}
+
+mod record {
+    use super::*;
+
+    #[test]
+    fn counts_actions_and_upload_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("file");
+        fs::write(&from, "12345").unwrap();
+        let upload = Action::Upload {
+            from: from.to_string_lossy().to_string(),
+            to: "/tmp".to_string(),
+            user: "root".to_string(),
+            group: "root".to_string(),
+            permissions: None,
+            overwrite: true,
+            encrypt: false,
+            seuser: None,
+            serole: None,
+            setype: None,
+            acl: vec![],
+            transfer_permissions: None,
+            xattrs: Default::default(),
+            capabilities: None,
+        };
+
+        let metrics = Metrics::new(dir.path().join("sira.prom"));
+        metrics.record_connection("alpha", Duration::from_millis(1500));
+        metrics.record_timing("alpha", Duration::from_millis(250));
+        metrics.record("alpha", &upload, &output(0));
+        metrics.record_timing("alpha", Duration::from_millis(500));
+        metrics.record("alpha", &Action::Command(vec![]), &output(1));
+
+        let state = metrics.state.lock().unwrap();
+        let stats = &state.hosts["alpha"];
+        assert_eq!(1, stats.succeeded);
+        assert_eq!(1, stats.failed);
+        assert_eq!(5, stats.upload_bytes);
+        assert_eq!(1.5, stats.connect_seconds);
+        assert_eq!(0.75, stats.duration_seconds);
+    }
+}
+
+mod finished {
+    use super::*;
+
+    #[test]
+    fn writes_metrics_and_accumulates_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sira.prom");
+        let metrics = Metrics::new(&path);
+        metrics.record("alpha", &Action::Command(vec![]), &output(0));
+
+        metrics.finished(&hosts(), &[]).unwrap();
+        let file = fs::read_to_string(&path).unwrap();
+        assert!(file.contains("\nsira_runs_total 1\n"), "{file}");
+        assert!(file.contains("\nsira_failed_runs_total 0\n"), "{file}");
+
+        let errors = vec![("bravo".to_string(), anyhow!("connection refused"))];
+        metrics.finished(&hosts(), &errors).unwrap();
+        let file = fs::read_to_string(&path).unwrap();
+        assert!(file.contains("\nsira_runs_total 2\n"), "{file}");
+        assert!(file.contains("\nsira_failed_runs_total 1\n"), "{file}");
+        assert!(
+            file.contains("\nsira_last_run_host_success{host=\"bravo\"} 0\n"),
+            "{file}",
+        );
+        assert!(!dir.path().join("sira.prom.tmp").exists());
+    }
+}
+
+mod render {
+    use super::*;
+
+    #[test]
+    fn renders_host_stats() {
+        let stats = HostStats {
+            succeeded: 3,
+            failed: 1,
+            duration_seconds: 1.5,
+            upload_bytes: 42,
+            connect_seconds: 0.25,
+        };
+        let hosts = BTreeMap::from([("alpha".to_string(), (false, stats))]);
+        let metrics = render("", 1700000000, &hosts);
+
+        for line in [
+            "sira_runs_total 1",
+            "sira_failed_runs_total 1",
+            "sira_last_run_timestamp_seconds 1700000000",
+            "sira_last_run_host_success{host=\"alpha\"} 0",
+            "sira_last_run_connect_duration_seconds{host=\"alpha\"} 0.250",
+            "sira_last_run_actions{host=\"alpha\",result=\"success\"} 3",
+            "sira_last_run_actions{host=\"alpha\",result=\"failure\"} 1",
+            "sira_last_run_action_duration_seconds{host=\"alpha\"} 1.500",
+            "sira_last_run_upload_bytes{host=\"alpha\"} 42",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{line}\n{metrics}");
+        }
+    }
+
+    #[test]
+    fn continues_previous_totals() {
+        let previous =
+            "# TYPE sira_runs_total counter\nsira_runs_total 41\nsira_failed_runs_total 2\n";
+        let metrics = render(previous, 0, &BTreeMap::new());
+        assert!(
+            metrics.lines().any(|l| l == "sira_runs_total 42"),
+            "{metrics}"
+        );
+        assert!(
+            metrics.lines().any(|l| l == "sira_failed_runs_total 2"),
+            "{metrics}"
+        );
+    }
+}
+
+mod escape_label {
+    use super::*;
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(r#"a\\b\"c\nd"#, escape_label("a\\b\"c\nd"));
+    }
+}
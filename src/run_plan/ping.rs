@@ -0,0 +1,175 @@
+//! Checks that Sira can reach managed nodes and run `sira-client` on them, e.g. with `sira ping`
+//! as a quick check before a big run.
+
+use super::client::{ClientInterface, ManageClient};
+use super::{connection_manager, RunOptions, TaskError};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// A managed node's answer to [ping].
+#[derive(Debug)]
+pub enum Reply {
+    /// `sira-client` answered.
+    Pong {
+        /// How long `sira-client` took to answer, once connected.
+        latency: Duration,
+
+        /// The version of `sira-client` that answered.
+        version: String,
+    },
+
+    /// Sira connected, but `sira-client` didn't answer, e.g. because it isn't installed or is too
+    /// old to know `ping`, or because `sudo` asked for a password. Also used if Sira's own task
+    /// for the host fails, with a [TaskError].
+    ClientFailed(anyhow::Error),
+
+    /// Sira could not connect to the managed node.
+    Unreachable(anyhow::Error),
+}
+
+impl Reply {
+    /// Returns whether `sira-client` answered.
+    pub fn is_pong(&self) -> bool {
+        matches!(self, Reply::Pong { .. })
+    }
+}
+
+/// Connects to each of `hosts` and runs `sira-client ping` there, which changes nothing.
+///
+/// Pings hosts in parallel, but no more than [RunOptions::concurrency] at once. Returns each host's
+/// [Reply], in the order of `hosts`.
+pub async fn ping(hosts: &[String], options: &RunOptions) -> Vec<(String, Reply)> {
+    _ping(hosts, connection_manager(options), options.concurrency).await
+}
+
+/// Provides dependency injection for unit-testing [ping] without SSH.
+pub(crate) async fn _ping<
+    C: ClientInterface + Send,
+    CM: ManageClient<C> + Clone + Send + 'static,
+>(
+    hosts: &[String],
+    connection_manager: CM,
+    concurrency: Option<usize>,
+) -> Vec<(String, Reply)> {
+    let limit = concurrency.map(|n| Arc::new(Semaphore::new(n)));
+    let mut pings = JoinSet::new();
+    let mut tasks = HashMap::new();
+    for (index, host) in hosts.iter().enumerate() {
+        let host = host.clone();
+        let mut cm = connection_manager.clone();
+        let limit = limit.clone();
+        let task_host = host.clone();
+        let handle = pings.spawn(async move {
+            let host = task_host;
+            // The semaphore is never closed, so acquiring a permit can't fail.
+            let _permit = match limit {
+                Some(limit) => limit.acquire_owned().await.ok(),
+                None => None,
+            };
+            let reply = match cm.connect(&host).await {
+                Ok(mut client) => {
+                    let start = Instant::now();
+                    match client.ping().await {
+                        Ok(version) => Reply::Pong {
+                            latency: start.elapsed(),
+                            version,
+                        },
+                        Err(e) => Reply::ClientFailed(e),
+                    }
+                }
+                Err(e) => Reply::Unreachable(e),
+            };
+            (index, host, reply)
+        });
+        let _ = tasks.insert(handle.id(), (index, host));
+    }
+
+    let mut replies = Vec::with_capacity(hosts.len());
+    while let Some(join_result) = pings.join_next_with_id().await {
+        match join_result {
+            Ok((_, reply)) => replies.push(reply),
+            Err(err) => {
+                let (index, host) = tasks.remove(&err.id()).unwrap_or_default();
+                replies.push((
+                    index,
+                    host,
+                    Reply::ClientFailed(TaskError::from(err).into()),
+                ));
+            }
+        }
+    }
+    replies.sort_by_key(|(index, _, _)| *index);
+    replies
+        .into_iter()
+        .map(|(_, host, reply)| (host, reply))
+        .collect()
+}
+
+/// Formats the results of [ping] as a table with one row per host, e.g.:
+///
+/// ```text
+/// HOST  STATUS       LATENCY  DETAILS
+/// web1  ok           12 ms    sira-client 0.1.0
+/// web2  unreachable  -        connection timed out
+/// ```
+pub fn table(replies: &[(String, Reply)]) -> String {
+    let rows: Vec<[String; 4]> = replies
+        .iter()
+        .map(|(host, reply)| match reply {
+            Reply::Pong { latency, version } => [
+                host.clone(),
+                "ok".to_string(),
+                format!("{} ms", latency.as_millis()),
+                format!("sira-client {version}"),
+            ],
+            Reply::ClientFailed(e) => [
+                host.clone(),
+                "client failed".to_string(),
+                "-".to_string(),
+                one_line(e),
+            ],
+            Reply::Unreachable(e) => [
+                host.clone(),
+                "unreachable".to_string(),
+                "-".to_string(),
+                one_line(e),
+            ],
+        })
+        .collect();
+
+    let header = ["HOST", "STATUS", "LATENCY", "DETAILS"].map(String::from);
+    let mut widths = [0; 3];
+    for row in [&header].into_iter().chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        let _ = writeln!(
+            table,
+            "{:w0$}  {:w1$}  {:w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        );
+    }
+    table
+}
+
+/// Returns `error` and its causes on a single line.
+fn one_line(error: &anyhow::Error) -> String {
+    format!("{error:#}").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod test;
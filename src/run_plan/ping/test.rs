@@ -0,0 +1,65 @@
+use super::*;
+use crate::run_plan::test::fixtures::{TestClientFactory, TEST_CLIENT_VERSION};
+
+fn hosts(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+mod ping {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_each_host_in_order() {
+        let factory = TestClientFactory::new();
+        factory.lock().unwrap().set_unreachable("web2");
+        factory.lock().unwrap().fail_client_command("web3");
+
+        let replies = _ping(&hosts(&["web3", "web2", "web1"]), factory, Some(1)).await;
+        let hosts: Vec<&str> = replies.iter().map(|(host, _)| host.as_str()).collect();
+        assert_eq!(vec!["web3", "web2", "web1"], hosts);
+        assert!(matches!(replies[0].1, Reply::ClientFailed(_)));
+        assert!(matches!(replies[1].1, Reply::Unreachable(_)));
+        match &replies[2].1 {
+            Reply::Pong { version, .. } => assert_eq!(TEST_CLIENT_VERSION, version),
+            reply => panic!("expected a pong but got: {reply:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn works_with_no_hosts() {
+        assert!(_ping(&[], TestClientFactory::new(), None).await.is_empty());
+    }
+}
+
+mod table {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn aligns_columns() {
+        let replies = vec![
+            (
+                "web1".to_string(),
+                Reply::Pong {
+                    latency: Duration::from_millis(12),
+                    version: "0.1.0".to_string(),
+                },
+            ),
+            (
+                "database".to_string(),
+                Reply::Unreachable(anyhow!("connection timed out")),
+            ),
+            (
+                "db2".to_string(),
+                Reply::ClientFailed(anyhow!("no such file\nor directory")),
+            ),
+        ];
+        assert_eq!(
+            "HOST      STATUS         LATENCY  DETAILS\n\
+            web1      ok             12 ms    sira-client 0.1.0\n\
+            database  unreachable    -        connection timed out\n\
+            db2       client failed  -        no such file or directory\n",
+            table(&replies),
+        );
+    }
+}
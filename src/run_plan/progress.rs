@@ -0,0 +1,146 @@
+//! Shows a progress bar for each host instead of a report for each action.
+//!
+//! For large runs, the usual reports scroll by faster than anyone can read them. A
+//! [ProgressReporter] instead keeps one line per host on the terminal, showing how many of the
+//! host's actions have completed, the action that's currently running, and the elapsed time:
+//!
+//! ```text
+//!         web1 [##############>---------------]  14/30 00:01:12 command: apt-get update
+//!         web2 [##############################]  30/30 00:02:03 done
+//! ```
+//!
+//! Failures still print in full above the progress bars, since they need attention.
+
+use super::report::{_confirm, _report, _touch_required, title, Report};
+use crate::core::{Action, Plan};
+use async_trait::async_trait;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::process::Output;
+use std::sync::Arc;
+use tokio::task;
+
+/// The template for each host's progress bar. Please see [ProgressStyle::with_template].
+pub const TEMPLATE: &str = "{prefix:>12} [{bar:30}] {pos:>3}/{len:3} {elapsed_precise} {msg}";
+
+/// Shows a progress bar for each host. Please see the [module documentation](self).
+///
+/// Clones share the same progress bars.
+#[derive(Clone, Debug)]
+pub struct ProgressReporter {
+    /// The group of progress bars drawn together on the terminal.
+    multi: MultiProgress,
+
+    /// Maps each host to its progress bar.
+    bars: Arc<HashMap<String, ProgressBar>>,
+}
+
+impl ProgressReporter {
+    /// Creates a [ProgressReporter] with one progress bar for each host in `plan`, drawn on stderr.
+    pub fn new(plan: &Plan) -> Self {
+        Self::with_draw_target(plan, ProgressDrawTarget::stderr())
+    }
+
+    /// Creates a [ProgressReporter] that draws its progress bars on `target`.
+    pub fn with_draw_target(plan: &Plan, target: ProgressDrawTarget) -> Self {
+        let multi = MultiProgress::with_draw_target(target);
+        let style = ProgressStyle::with_template(TEMPLATE)
+            .expect("progress bar template should be valid")
+            .progress_chars("#>-");
+
+        let bars = plan
+            .hosts()
+            .into_iter()
+            .map(|host| {
+                // Each task's checks count, too, since they're reported like actions.
+                let checks: usize = plan
+                    .manifests
+                    .iter()
+                    .filter(|manifest| manifest.hosts.contains(&host))
+                    .flat_map(|manifest| &manifest.include)
+                    .map(|task| task.verify.len())
+                    .sum();
+                let total = plan.plan_for(&host).unwrap().iter().count() + checks;
+                let bar = multi.add(ProgressBar::new(total as u64));
+                bar.set_style(style.clone());
+                bar.set_prefix(host.clone());
+                bar.set_message("waiting");
+                (host, bar)
+            })
+            .collect();
+
+        ProgressReporter {
+            multi,
+            bars: Arc::new(bars),
+        }
+    }
+
+    /// Returns the progress bar for `host`, if `host` is part of the plan.
+    pub fn bar(&self, host: &str) -> Option<&ProgressBar> {
+        self.bars.get(host)
+    }
+}
+
+#[async_trait]
+impl Report for ProgressReporter {
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        if let Some(bar) = self.bar(host) {
+            bar.set_message(title(action));
+        }
+        Ok(())
+    }
+
+    async fn progress(&mut self, host: &str, action: &Action, message: &str) -> io::Result<()> {
+        if let Some(bar) = self.bar(host) {
+            bar.set_message(format!("{}: {message}", title(action)));
+        }
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        let Some(bar) = self.bar(host) else {
+            return Ok(());
+        };
+
+        if !output.status.success() {
+            // Print the full report above the progress bars, then leave this host's bar where it
+            // stopped.
+            let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+            _report(&mut stdout, &mut stderr, host, action, output)?;
+            task::block_in_place(|| {
+                self.multi.suspend(|| {
+                    io::stdout().lock().write_all(&stdout)?;
+                    io::stderr().lock().write_all(&stderr)
+                })
+            })?;
+            bar.abandon_with_message(format!("failed: {}", title(action)));
+            return Ok(());
+        }
+
+        bar.inc(1);
+        if bar.position() >= bar.length().unwrap_or_default() {
+            bar.finish_with_message("done");
+        }
+        Ok(())
+    }
+
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        let multi = self.multi.clone();
+        task::block_in_place(move || {
+            multi.suspend(|| _touch_required(&mut io::stdout().lock(), host, purpose))
+        })
+    }
+
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        // As with Reporter, hold stdin for the whole prompt so that hosts ask one at a time.
+        let multi = self.multi.clone();
+        task::block_in_place(move || {
+            let mut stdin = io::stdin().lock();
+            multi.suspend(|| _confirm(&mut io::stdout().lock(), &mut stdin, host, action))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test;
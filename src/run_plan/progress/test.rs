@@ -0,0 +1,65 @@
+use super::*;
+use crate::core::fixtures::plan;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+const HOST: &str = "archie-desktop";
+
+// Returns a ProgressReporter for a plan with two actions on HOST.
+fn reporter() -> ProgressReporter {
+    let (mut plan, ..) = plan();
+    plan.manifests[0].include[0].actions.push(action());
+    ProgressReporter::with_draw_target(&plan, ProgressDrawTarget::hidden())
+}
+
+fn output(code: i32) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: vec![],
+        stderr: vec![],
+    }
+}
+
+fn action() -> Action {
+    Action::Command(vec!["echo hi".to_string()])
+}
+
+#[test]
+fn counts_actions_for_each_host() {
+    let reporter = reporter();
+    let bar = reporter.bar(HOST).unwrap();
+    assert_eq!(Some(2), bar.length());
+    assert_eq!(0, bar.position());
+    assert!(reporter.bar("nobody").is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shows_current_action() {
+    let mut reporter = reporter();
+    reporter.starting(HOST, &action()).await.unwrap();
+    assert_eq!("command: echo hi", reporter.bar(HOST).unwrap().message());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn advances_on_success() {
+    let mut reporter = reporter();
+    reporter.report(HOST, &action(), &output(0)).await.unwrap();
+    let bar = reporter.bar(HOST).unwrap();
+    assert_eq!(1, bar.position());
+    assert!(!bar.is_finished());
+
+    reporter.report(HOST, &action(), &output(0)).await.unwrap();
+    let bar = reporter.bar(HOST).unwrap();
+    assert!(bar.is_finished());
+    assert_eq!("done", bar.message());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stops_on_failure() {
+    let mut reporter = reporter();
+    reporter.report(HOST, &action(), &output(1)).await.unwrap();
+    let bar = reporter.bar(HOST).unwrap();
+    assert_eq!(0, bar.position());
+    assert!(bar.is_finished());
+    assert_eq!("failed: command: echo hi", bar.message());
+}
@@ -0,0 +1,187 @@
+//! Queues signed actions on managed nodes that are rarely online, to be applied later.
+//!
+//! For each host, Sira compiles and signs the actions that it would otherwise run over SSH into a
+//! [Bundle] (please see [bundle_for]) and then drops the bundle into [QUEUE_DIR] on the managed
+//! node, using a built-in [Plan] that runs through the node's `sira-client` like any other (please
+//! see [queue_plan]). `sira-client apply-queued` applies the bundle later, e.g. at the node's next
+//! boot. Please see [crate::core::action::bundle] for details.
+
+use super::rotate_keys::built_in_plan;
+use super::{run_plan_with, sign_as_controller, RunOptions};
+use crate::client;
+use crate::core::action::bundle::{encode_hex, QueuedAction, QUEUE_DIR};
+use crate::core::action::{Bundle, Envelope};
+use crate::core::{Action, Plan};
+use crate::crypto;
+use anyhow::{anyhow, bail, Context};
+use chrono::{TimeDelta, Utc};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// How long, in days, queued actions remain valid by default.
+pub const DEFAULT_LIFETIME_DAYS: i64 = 7;
+
+/// Compiles and signs the actions that `plan` would run on `host` into a [Bundle] whose envelopes
+/// expire `lifetime` from now.
+///
+/// Actions are signed with `action_key`, if provided, or else as [run_plan_with] would sign them.
+///
+/// # Errors
+///
+/// Returns an error if `host` isn't in the plan, if any of its tasks requires confirmation, which
+/// nobody would be around to give, or if an action can't be signed or a file can't be read.
+pub fn bundle_for(
+    plan: &Plan,
+    host: &str,
+    lifetime: TimeDelta,
+    action_key: Option<&Path>,
+) -> anyhow::Result<Bundle> {
+    let host_plan = plan
+        .plan_for(host)
+        .ok_or_else(|| anyhow!("{host} is not in the plan"))?;
+
+    let mut actions = Vec::new();
+    for action in host_plan {
+        let task = action.task();
+        if task.confirm {
+            bail!(
+                "task {:?} requires confirmation, so its actions can't be queued",
+                task.name,
+            );
+        }
+        let (limits, sandbox, user) =
+            (task.limits.clone(), task.sandbox.clone(), task.user.clone());
+        let action = action.compile();
+
+        let envelope = Envelope::new(action.clone())
+            .with_lifetime(lifetime)
+            .with_limits(limits)
+            .with_sandbox(sandbox)
+            .with_user(user)
+            .with_escalate(task.escalate)
+            .with_dpkg_lock_timeout(task.dpkg_lock_timeout)
+            .to_yaml();
+        let signature = sign_as_controller(envelope.as_bytes(), action_key)?
+            .map(String::from_utf8)
+            .transpose()
+            .context("action signature was not valid UTF-8")?;
+        let file = match &action {
+            Action::Upload { from, encrypt, .. } => {
+                Some(encode_hex(&upload_contents(host, from, *encrypt)?))
+            }
+            _ => None,
+        };
+        actions.push(QueuedAction {
+            envelope,
+            signature,
+            file,
+        });
+    }
+    Ok(Bundle { actions })
+}
+
+/// Returns a [Plan] that drops the bundle at `bundle` into [QUEUE_DIR] on `host` as `name`.
+///
+/// The bundle holds signed actions and possibly secrets, so only root may read it, including while
+/// it's in transit.
+pub fn queue_plan(host: &str, bundle: &str, name: &str) -> Plan {
+    built_in_plan(
+        "Queue actions",
+        &[host.to_string()],
+        vec![
+            Action::Command(vec![format!("mkdir -p -m 700 {QUEUE_DIR}")]),
+            Action::Upload {
+                from: bundle.to_string(),
+                to: format!("{QUEUE_DIR}/{name}"),
+                user: "root".to_string(),
+                group: "root".to_string(),
+                permissions: Some("600".to_string()),
+                overwrite: true,
+                encrypt: false,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: Some("600".to_string()),
+                xattrs: Default::default(),
+                capabilities: None,
+            },
+        ],
+    )
+}
+
+/// Queues the actions that `plan` would run on each of its hosts, so that each managed node
+/// applies them on its own schedule. Please see the [module documentation](self).
+///
+/// # Returns
+///
+/// Like [run_plan_with], returns a list of `(host, error)` tuples for the hosts that failed,
+/// including any whose bundle couldn't be built.
+pub async fn queue(
+    plan: &Plan,
+    lifetime: TimeDelta,
+    options: RunOptions,
+) -> Result<(), Vec<(String, anyhow::Error)>> {
+    // Name bundles by creation time, so that a managed node applies them in order.
+    let name = format!("{}.yaml", Utc::now().format("%Y%m%dT%H%M%S%.3fZ"));
+    let mut errors = Vec::new();
+    let mut bundles = Vec::new();
+
+    for host in plan.hosts() {
+        let written = bundle_for(plan, &host, lifetime, options.action_key.as_deref())
+            .and_then(|bundle| write_bundle(&bundle));
+        match written {
+            Ok(path) => bundles.push((host, path)),
+            Err(e) => errors.push((host, e)),
+        }
+    }
+
+    let queue_plan = Plan {
+        manifests: bundles
+            .iter()
+            .flat_map(|(host, path)| queue_plan(host, path, &name).manifests)
+            .collect(),
+    };
+    if !queue_plan.manifests.is_empty() {
+        if let Err(plan_errors) = run_plan_with(queue_plan, options).await {
+            errors.extend(plan_errors);
+        }
+    }
+    for (_, path) in bundles {
+        let _ = fs::remove_file(path);
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// Returns the contents of the file to upload, encrypted for `host` if `encrypt` is set.
+fn upload_contents(host: &str, from: &str, encrypt: bool) -> anyhow::Result<Vec<u8>> {
+    if !encrypt {
+        return fs::read(from).with_context(|| format!("could not read {from}"));
+    }
+    let (file, encrypted) = client::mktemp()?;
+    drop(file);
+    let contents = crypto::recipients_path(host)
+        .and_then(|recipients| crypto::encrypt_file(from, recipients, &encrypted))
+        .and_then(|()| Ok(fs::read(&encrypted)?));
+    let _ = fs::remove_file(&encrypted);
+    contents
+}
+
+/// Writes `bundle` to a temporary file that only the current user can read and returns its path.
+fn write_bundle(bundle: &Bundle) -> anyhow::Result<String> {
+    let (mut file, path) = client::mktemp()?;
+    let written = file.write_all(bundle.to_yaml().as_bytes());
+    if let Err(e) = written {
+        let _ = fs::remove_file(&path);
+        return Err(e).context("could not write bundle");
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,94 @@
+use super::*;
+use crate::core::fixtures::plan;
+use std::io::Write;
+
+mod bundle_for {
+    use super::*;
+
+    #[test]
+    fn signs_each_action_with_long_lifetime() {
+        let (plan, _, _, _) = plan();
+        let bundle = bundle_for(&plan, "archie-desktop", TimeDelta::days(7), None).unwrap();
+        assert_eq!(1, bundle.actions.len());
+
+        let queued = &bundle.actions[0];
+        let envelope = Envelope::from_yaml(&queued.envelope).unwrap();
+        assert_eq!(plan.manifests[0].include[0].actions[0], envelope.action);
+        let expires = chrono::DateTime::parse_from_rfc3339(&envelope.expires).unwrap();
+        assert!(expires > Utc::now() + TimeDelta::days(6));
+        assert_eq!(None, queued.file);
+    }
+
+    #[test]
+    fn includes_upload_contents() {
+        let mut from = tempfile::NamedTempFile::new().unwrap();
+        from.write_all(b"contents").unwrap();
+        let (mut plan, _, _, _) = plan();
+        plan.manifests[0].include[0].actions = vec![Action::Upload {
+            from: from.path().to_str().unwrap().to_string(),
+            to: "b".to_string(),
+            user: "c".to_string(),
+            group: "d".to_string(),
+            permissions: None,
+            overwrite: true,
+            encrypt: false,
+            seuser: None,
+            serole: None,
+            setype: None,
+            acl: vec![],
+            transfer_permissions: None,
+            xattrs: Default::default(),
+            capabilities: None,
+        }];
+
+        let bundle = bundle_for(&plan, "archie-desktop", TimeDelta::days(7), None).unwrap();
+        assert_eq!(
+            Some(b"contents".to_vec()),
+            bundle.actions[0].file_contents().unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_tasks_that_require_confirmation() {
+        let (mut plan, _, _, _) = plan();
+        plan.manifests[0].include[0].confirm = true;
+        assert!(bundle_for(&plan, "archie-desktop", TimeDelta::days(7), None).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_host() {
+        let (plan, _, _, _) = plan();
+        assert!(bundle_for(&plan, "nowhere", TimeDelta::days(7), None).is_err());
+    }
+}
+
+mod queue_plan {
+    use super::*;
+
+    #[test]
+    fn uploads_privately_into_queue_dir() {
+        let plan = queue_plan("alpha", "/tmp/tmp.abc", "20261016T120000.000Z.yaml");
+        assert_eq!(vec!["alpha".to_string()], plan.hosts());
+
+        let actions = &plan.manifests[0].include[0].actions;
+        assert_eq!(
+            Action::Command(vec!["mkdir -p -m 700 /var/lib/sira/queue".to_string()]),
+            actions[0],
+        );
+        match &actions[1] {
+            Action::Upload {
+                from,
+                to,
+                permissions,
+                transfer_permissions,
+                ..
+            } => {
+                assert_eq!("/tmp/tmp.abc", from);
+                assert_eq!("/var/lib/sira/queue/20261016T120000.000Z.yaml", to);
+                assert_eq!(Some("600"), permissions.as_deref());
+                assert_eq!(Some("600"), transfer_permissions.as_deref());
+            }
+            x => panic!("expected Action::Upload but got:\n{x:#?}"),
+        }
+    }
+}
@@ -12,34 +12,582 @@
 //!
 //! [Action]: crate::core::Action
 
+use crate::core::action::{Status, ERROR_PREFIX, STATUS_PREFIX, UPLOAD_CHECKSUM_PREFIX};
 use crate::core::Action;
 use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::env;
 use std::fmt::Display;
-use std::io::{self, Write};
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::process::Output;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::task;
 
-/// Prints feedback about each [Action] run on a client to stdout/stderr to keep the user informed.
+/// The interface between a run and its user interface, which keeps the user informed about each
+/// [Action] run on a client and answers Sira's questions.
+///
+/// Sira's own frontends, such as the terminal output ([Reporter]), progress bars
+/// ([ProgressReporter](super::progress::ProgressReporter)), and JSON events
+/// ([EventStream](super::event_stream::EventStream)), are all implementations of this trait, so
+/// other frontends, e.g. graphical or web frontends, can be written the same way outside of Sira
+/// and passed to [run_plan_with_ui](super::run_plan_with_ui) or [embed::run](super::embed::run).
+/// Please see [embed](super::embed#stability) for which parts of this interface stay stable.
+///
+/// Sira calls most methods to tell the frontend what's happening. Each run starts with
+/// [Report::plan_started]. Each host gets [Report::connected] once it has connected, and then
+/// each action goes in this order: [Report::action_source], [Report::starting], any number of
+/// [Report::progress], then [Report::transferred], if it transferred files, [Report::timing], and
+/// [Report::report]. [Report::network] and [Report::touch_required] can come at any time, and the
+/// run ends with [Report::plan_finished] and then [Report::finished]. Sira asks the frontend for an
+/// answer only with [Report::confirm]. Hosts run in parallel, each with its own clone of
+/// the frontend, so calls for different hosts can interleave.
+///
+/// When a host keeps several actions in flight at once (please see
+/// [RunOptions::pipeline](super::RunOptions::pipeline)), the next action's [Report::action_source]
+/// and [Report::starting] can come before the last action's [Report::report]. Actions are still
+/// reported in the order that they started, so a frontend that needs to know which action a report
+/// is about can keep a queue of each host's actions. Please see [RunningActions].
+///
+/// If a method returns an error, the host that it reported on stops.
 ///
 /// [Action]: crate::core::Action
 #[async_trait]
-pub trait Report {
+pub trait Report: Send {
+    /// Reports that Sira is about to run a plan on `hosts` hosts. `plan_id` identifies the run in
+    /// every later call to [Report::plan_finished]: IDs count up from 1 in the order that runs
+    /// start in this process, so a frontend that reports on several runs, e.g. of queued plans, can
+    /// tell them apart.
+    ///
+    /// Sira calls this once per run, before reporting anything about its hosts, on the original
+    /// [Report] rather than any of its clones. Does nothing by default.
+    async fn plan_started(&mut self, _plan_id: u64, _hosts: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports the names of the manifest and task that the next action on `host` comes from.
+    ///
+    /// Sira calls this before reporting anything else about the action. Does nothing by default.
+    async fn action_source(&mut self, _host: &str, _manifest: &str, _task: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports a network-layer event on `host`, e.g. connecting or transferring a file.
+    ///
+    /// Does nothing by default.
+    async fn network(&mut self, _host: &str, _event: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports how long it took to connect to `host`, including waiting for the user to touch a
+    /// security key, if logging in needed one.
+    ///
+    /// Sira calls this once per host, before reporting any of its actions. Does nothing by default.
+    async fn connected(&mut self, _host: &str, _duration: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
     /// Reports that an action is about to commence.
     async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()>;
 
+    /// Reports progress that `sira-client` made on an action that's still running, e.g. which of
+    /// several commands it's running. Please see [Progress](crate::core::action::Progress).
+    ///
+    /// Does nothing by default.
+    async fn progress(&mut self, _host: &str, _action: &Action, _message: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports how long an action took to run on `host`, not counting time spent waiting for the
+    /// user, e.g. to touch a security key.
+    ///
+    /// Sira calls this just before [Report::report]. Does nothing by default.
+    async fn timing(
+        &mut self,
+        _host: &str,
+        _action: &Action,
+        _duration: Duration,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports how many bytes an action transferred to `host`, e.g. the size of the file that an
+    /// upload sent.
+    ///
+    /// Sira calls this just before [Report::timing], and only for actions that transferred files
+    /// and succeeded. Does nothing by default.
+    async fn transferred(&mut self, _host: &str, _action: &Action, _bytes: u64) -> io::Result<()> {
+        Ok(())
+    }
+
     /// Reports the outcome of an action.
     async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()>;
+
+    /// Reports that `host` has run every action in its plan and is about to run the checks that
+    /// its tasks list under `verify`. Everything reported about `host` from then on is about those
+    /// checks. Please see [Task::verify](crate::core::Task::verify).
+    ///
+    /// Does nothing by default.
+    async fn verifying(&mut self, _host: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports that every one of the `hosts` hosts in the plan `plan_id` has either finished or
+    /// stopped. Please see [Report::plan_started].
+    ///
+    /// Sira calls this once per run, just before [Report::finished], on the original [Report]
+    /// rather than any of its clones. Does nothing by default.
+    async fn plan_finished(&mut self, _plan_id: u64, _hosts: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports that every host has either finished or stopped.
+    ///
+    /// Sira calls this once per run, on the original [Report] rather than any of its clones. Does
+    /// nothing by default.
+    async fn finished(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Asks the user to touch their hardware security key so that Sira can `purpose`, e.g.
+    /// "log in" or "sign an action".
+    ///
+    /// Does nothing by default, since only interactive reporters can usefully prompt the user.
+    async fn touch_required(&mut self, _host: &str, _purpose: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Asks the user whether to run an action that requires confirmation. Returns whether the user
+    /// approved it.
+    ///
+    /// Declines by default, since only interactive reporters can ask the user.
+    async fn confirm(&mut self, _host: &str, _action: &Action) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Reports to two [Report] implementations in turn, e.g. the terminal and an audit log.
+#[async_trait]
+impl<A: Report + Send, B: Report + Send> Report for (A, B) {
+    async fn plan_started(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        // Both hear that the plan started, so that each can pair it with plan_finished.
+        let first = self.0.plan_started(plan_id, hosts).await;
+        let second = self.1.plan_started(plan_id, hosts).await;
+        first.and(second)
+    }
+
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        // Both hear about every action that runs, even if the first one fails, e.g. because stdout
+        // is a closed pipe, so that logs and audits still record it.
+        let first = self.0.action_source(host, manifest, task).await;
+        let second = self.1.action_source(host, manifest, task).await;
+        first.and(second)
+    }
+
+    async fn network(&mut self, host: &str, event: &str) -> io::Result<()> {
+        self.0.network(host, event).await?;
+        self.1.network(host, event).await
+    }
+
+    async fn connected(&mut self, host: &str, duration: Duration) -> io::Result<()> {
+        self.0.connected(host, duration).await?;
+        self.1.connected(host, duration).await
+    }
+
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        let first = self.0.starting(host, action).await;
+        let second = self.1.starting(host, action).await;
+        first.and(second)
+    }
+
+    async fn progress(&mut self, host: &str, action: &Action, message: &str) -> io::Result<()> {
+        self.0.progress(host, action, message).await?;
+        self.1.progress(host, action, message).await
+    }
+
+    async fn timing(&mut self, host: &str, action: &Action, duration: Duration) -> io::Result<()> {
+        let first = self.0.timing(host, action, duration).await;
+        let second = self.1.timing(host, action, duration).await;
+        first.and(second)
+    }
+
+    async fn transferred(&mut self, host: &str, action: &Action, bytes: u64) -> io::Result<()> {
+        let first = self.0.transferred(host, action, bytes).await;
+        let second = self.1.transferred(host, action, bytes).await;
+        first.and(second)
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        let first = self.0.report(host, action, output).await;
+        let second = self.1.report(host, action, output).await;
+        first.and(second)
+    }
+
+    async fn verifying(&mut self, host: &str) -> io::Result<()> {
+        self.0.verifying(host).await?;
+        self.1.verifying(host).await
+    }
+
+    async fn plan_finished(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        // Like finished, both hear that the plan is over even if the first one fails.
+        let first = self.0.plan_finished(plan_id, hosts).await;
+        let second = self.1.plan_finished(plan_id, hosts).await;
+        first.and(second)
+    }
+
+    async fn finished(&mut self) -> io::Result<()> {
+        // Both get to finish, e.g. to flush their logs, even if the first one fails.
+        let first = self.0.finished().await;
+        let second = self.1.finished().await;
+        first.and(second)
+    }
+
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        self.0.touch_required(host, purpose).await?;
+        self.1.touch_required(host, purpose).await
+    }
+
+    /// Approves the action if either implementation does, asking the second only if the first
+    /// declines.
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        Ok(self.0.confirm(host, action).await? || self.1.confirm(host, action).await?)
+    }
+}
+
+/// Reports to an optional [Report] implementation, doing nothing (and declining confirmation) if
+/// it's [None].
+#[async_trait]
+impl<R: Report + Send> Report for Option<R> {
+    async fn plan_started(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        match self {
+            Some(r) => r.plan_started(plan_id, hosts).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        match self {
+            Some(r) => r.action_source(host, manifest, task).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn network(&mut self, host: &str, event: &str) -> io::Result<()> {
+        match self {
+            Some(r) => r.network(host, event).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn connected(&mut self, host: &str, duration: Duration) -> io::Result<()> {
+        match self {
+            Some(r) => r.connected(host, duration).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        match self {
+            Some(r) => r.starting(host, action).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn progress(&mut self, host: &str, action: &Action, message: &str) -> io::Result<()> {
+        match self {
+            Some(r) => r.progress(host, action, message).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn timing(&mut self, host: &str, action: &Action, duration: Duration) -> io::Result<()> {
+        match self {
+            Some(r) => r.timing(host, action, duration).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn transferred(&mut self, host: &str, action: &Action, bytes: u64) -> io::Result<()> {
+        match self {
+            Some(r) => r.transferred(host, action, bytes).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        match self {
+            Some(r) => r.report(host, action, output).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn verifying(&mut self, host: &str) -> io::Result<()> {
+        match self {
+            Some(r) => r.verifying(host).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn plan_finished(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+        match self {
+            Some(r) => r.plan_finished(plan_id, hosts).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn finished(&mut self) -> io::Result<()> {
+        match self {
+            Some(r) => r.finished().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        match self {
+            Some(r) => r.touch_required(host, purpose).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        match self {
+            Some(r) => r.confirm(host, action).await,
+            None => Ok(false),
+        }
+    }
+}
+
+/// Reports nothing and declines confirmation, e.g. for an embedder that needs no logger. Please see
+/// [embed](crate::run_plan::embed).
+#[async_trait]
+impl Report for () {
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn report(&mut self, _host: &str, _action: &Action, _output: &Output) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How much detail [Reporter] prints. Each level includes everything from the levels before it.
+///
+/// Failed actions are always reported in full, and prompts (e.g. for confirmation) always appear.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Prints nothing but failures (`--quiet`).
+    Quiet,
+
+    /// Also prints each task as it starts on each host.
+    #[default]
+    Tasks,
+
+    /// Also prints each action as it starts and completes (`-v`).
+    Actions,
+
+    /// Also prints the captured stdout and stderr of successful actions (`-vv`).
+    Output,
+
+    /// Also prints network-layer events, e.g. connecting and transferring files (`-vvv`).
+    Network,
+}
+
+impl Verbosity {
+    /// Returns the [Verbosity] for a number of `-v` flags, e.g. 2 for `-vv`.
+    pub fn from_count(count: usize) -> Self {
+        match count {
+            0 => Verbosity::Tasks,
+            1 => Verbosity::Actions,
+            2 => Verbosity::Output,
+            _ => Verbosity::Network,
+        }
+    }
+}
+
+/// The format in which Sira reports a run's progress on stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text, as configured by [Verbosity].
+    #[default]
+    Text,
+
+    /// One JSON object per report. Please see [EventStream](super::event_stream::EventStream).
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => anyhow::bail!("unknown output format {s:?}; expected \"text\" or \"json\""),
+        }
+    }
+}
+
+/// Whether [Reporter] uses color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Uses color when writing to a terminal, unless the `NO_COLOR` environment variable is set.
+    #[default]
+    Auto,
+
+    /// Always uses color.
+    Always,
+
+    /// Never uses color.
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns whether to use color when writing to a stream, given whether it's a terminal.
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => anyhow::bail!(
+                "unknown color choice {s:?}; expected \"auto\", \"always\", or \"never\""
+            ),
+        }
+    }
 }
 
 /// The real, production-ready [Report] implementation. Uses the real stdout/stderr.
-#[derive(Clone, Debug)]
-pub struct Reporter;
+///
+/// Every line that [Reporter] prints starts with the name of the host it's about, so that output
+/// from many hosts is easy to tell apart. With color, each host's name has its own color, which
+/// stays the same from run to run, and outcomes are colored, too. Please see [HostLines].
+///
+/// At the end of the run, unless quiet, [Reporter] counts how many actions changed something
+/// (please see [Status]) and, separately, how many checks passed (please see [Report::verifying]),
+/// and it lists the slowest actions (please see [SLOWEST_ACTIONS]).
+///
+/// Clones share their record of which task each host is running and how each action went.
+#[derive(Clone, Debug, Default)]
+pub struct Reporter {
+    /// How much detail to print.
+    verbosity: Verbosity,
+
+    /// Whether to use color.
+    color: ColorChoice,
+
+    /// Maps each host to the manifest and task it's running, to tell when a new task starts.
+    tasks: Arc<Mutex<HashMap<String, (String, String)>>>,
+
+    /// How long each action took, as `(duration, host, title)`.
+    timings: Arc<Mutex<Vec<(Duration, String, String)>>>,
+
+    /// How many actions ended with each [Status].
+    statuses: Arc<Mutex<BTreeMap<Status, usize>>>,
+
+    /// The hosts that have moved on to their checks.
+    verifying: Arc<Mutex<HashSet<String>>>,
+
+    /// How many checks passed and how many failed.
+    checks: Arc<Mutex<(usize, usize)>>,
+}
+
+impl Reporter {
+    /// Creates a [Reporter] that prints at the given [Verbosity], in color if `color` says so.
+    pub fn new(verbosity: Verbosity, color: ColorChoice) -> Self {
+        Reporter {
+            verbosity,
+            color,
+            ..Default::default()
+        }
+    }
+
+    /// Locks stdout for printing lines about `host`.
+    fn stdout<'a>(&self, host: &'a str) -> HostLines<'a, io::StdoutLock<'static>> {
+        let color = self.color.enabled(io::stdout().is_terminal());
+        HostLines::new(io::stdout().lock(), host, color)
+    }
+
+    /// Locks stderr for printing lines about `host`.
+    fn stderr<'a>(&self, host: &'a str) -> HostLines<'a, io::StderrLock<'static>> {
+        let color = self.color.enabled(io::stderr().is_terminal());
+        HostLines::new(io::stderr().lock(), host, color)
+    }
+}
 
 #[async_trait]
 impl Report for Reporter {
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        if self.verbosity < Verbosity::Tasks {
+            return Ok(());
+        }
+        let source = (manifest.to_string(), task.to_string());
+        let previous = self
+            .tasks
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), source.clone());
+        if previous.as_ref() == Some(&source) {
+            return Ok(());
+        }
+
+        task::block_in_place(|| {
+            let mut stdout = self.stdout(host);
+            _task_starting(&mut stdout, host, manifest, task)?;
+            stdout.flush()
+        })
+    }
+
+    async fn network(&mut self, host: &str, event: &str) -> io::Result<()> {
+        if self.verbosity < Verbosity::Network {
+            return Ok(());
+        }
+        task::block_in_place(|| {
+            let mut stdout = self.stdout(host);
+            print_host_message(&mut stdout, host, event)?;
+            stdout.flush()
+        })
+    }
+
     async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
-        let mut stdout = io::stdout().lock();
-        task::block_in_place(move || _starting(&mut stdout, host, action))
+        if self.verbosity < Verbosity::Actions {
+            return Ok(());
+        }
+        task::block_in_place(|| {
+            let mut stdout = self.stdout(host);
+            _starting(&mut stdout, host, action)?;
+            stdout.flush()
+        })
+    }
+
+    async fn progress(&mut self, host: &str, _action: &Action, message: &str) -> io::Result<()> {
+        if self.verbosity < Verbosity::Actions {
+            return Ok(());
+        }
+        task::block_in_place(|| {
+            let mut stdout = self.stdout(host);
+            _progress(&mut stdout, host, message)?;
+            stdout.flush()
+        })
+    }
+
+    async fn timing(&mut self, host: &str, action: &Action, duration: Duration) -> io::Result<()> {
+        self.timings
+            .lock()
+            .unwrap()
+            .push((duration, host.to_string(), title(action)));
+        Ok(())
     }
 
     async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
@@ -48,9 +596,263 @@ impl Report for Reporter {
         //
         // We need to release the locks as soon as we're done reporting rather than holding them
         // across invocations, so we construct them here instead of storing them in the struct.
-        let mut stdout = io::stdout().lock();
-        let mut stderr = io::stderr().lock();
-        task::block_in_place(move || _report(&mut stdout, &mut stderr, host, action, output))
+        // Taking them can block while another host holds them, e.g. for a prompt, so we take them
+        // only inside block_in_place.
+        //
+        // Checks change nothing, so they're counted apart from the actions that make changes.
+        if self.verifying.lock().unwrap().contains(host) {
+            let mut checks = self.checks.lock().unwrap();
+            match output.status.success() {
+                true => checks.0 += 1,
+                false => checks.1 += 1,
+            }
+        } else {
+            *self
+                .statuses
+                .lock()
+                .unwrap()
+                .entry(Status::from_output(output))
+                .or_default() += 1;
+        }
+        task::block_in_place(|| {
+            let mut stdout = self.stdout(host);
+            let mut stderr = self.stderr(host);
+            _report_at(
+                &mut stdout,
+                &mut stderr,
+                host,
+                action,
+                output,
+                self.verbosity,
+            )?;
+            stdout.flush()?;
+            stderr.flush()
+        })
+    }
+
+    async fn verifying(&mut self, host: &str) -> io::Result<()> {
+        self.verifying.lock().unwrap().insert(host.to_string());
+
+        // Name each task again as its checks start, even the task that ran last.
+        self.tasks.lock().unwrap().remove(host);
+        if self.verbosity < Verbosity::Tasks {
+            return Ok(());
+        }
+        task::block_in_place(|| {
+            let mut stdout = self.stdout(host);
+            _verifying(&mut stdout, host)?;
+            stdout.flush()
+        })
+    }
+
+    async fn finished(&mut self) -> io::Result<()> {
+        if self.verbosity < Verbosity::Tasks {
+            return Ok(());
+        }
+        let statuses = self.statuses.lock().unwrap().clone();
+        let (passed, failed) = *self.checks.lock().unwrap();
+        let timings = self.timings.lock().unwrap().clone();
+        task::block_in_place(move || {
+            let mut stdout = io::stdout().lock();
+            _summary(&mut stdout, &statuses)?;
+            _checks_summary(&mut stdout, passed, failed)?;
+            _slowest_actions(&mut stdout, timings, SLOWEST_ACTIONS)
+        })
+    }
+
+    async fn touch_required(&mut self, host: &str, purpose: &str) -> io::Result<()> {
+        task::block_in_place(|| _touch_required(&mut self.stdout(host), host, purpose))
+    }
+
+    async fn confirm(&mut self, host: &str, action: &Action) -> io::Result<bool> {
+        // Hold stdin for the whole prompt so that hosts ask one at a time. Lock stdin before
+        // stdout, since other hosts may lock stdout while they wait for their turn. The prompt may
+        // wait for the user indefinitely, so take both locks only once blocking is allowed.
+        task::block_in_place(|| {
+            let mut stdin = io::stdin().lock();
+            _confirm(&mut self.stdout(host), &mut stdin, host, action)
+        })
+    }
+}
+
+/// The actions in flight on each host, oldest first, for frontends that need to know where each
+/// action came from and when it started once it finishes. Please see [Report] for the order in
+/// which Sira reports actions.
+#[derive(Debug, Default)]
+pub struct RunningActions {
+    /// Maps each host to how many actions it has started and the ones that haven't finished yet.
+    hosts: HashMap<String, (usize, VecDeque<RunningAction>)>,
+}
+
+/// An action in flight on a host. Please see [RunningActions].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunningAction {
+    /// The name of the manifest that the action came from.
+    pub manifest: String,
+
+    /// The name of the task that the action came from.
+    pub task: String,
+
+    /// The action's position among the host's actions, counting from 1.
+    pub number: usize,
+
+    /// When the action started.
+    pub started: Instant,
+}
+
+impl RunningActions {
+    /// Creates an empty [RunningActions].
+    pub fn new() -> Self {
+        RunningActions::default()
+    }
+
+    /// Adds the next action on `host`, which comes from `manifest` and `task`, as reported by
+    /// [Report::action_source].
+    pub fn source(&mut self, host: &str, manifest: &str, task: &str) {
+        let (count, running) = self.hosts.entry(host.to_string()).or_default();
+        *count += 1;
+        running.push_back(RunningAction {
+            manifest: manifest.to_string(),
+            task: task.to_string(),
+            number: *count,
+            started: Instant::now(),
+        });
+    }
+
+    /// Restarts the clock on the newest action on `host`, as reported by [Report::starting].
+    pub fn start(&mut self, host: &str) {
+        if let Some(newest) = self.hosts.get_mut(host).and_then(|(_, r)| r.back_mut()) {
+            newest.started = Instant::now();
+        }
+    }
+
+    /// Removes and returns the oldest action on `host`, which is the one that [Report::report]
+    /// is about, if any.
+    pub fn finish(&mut self, host: &str) -> Option<RunningAction> {
+        self.hosts.get_mut(host)?.1.pop_front()
+    }
+}
+
+/// The colors in which [HostLines] prints host names, as ANSI SGR parameters.
+///
+/// Red and green are left out, since they mark failure and success.
+const HOST_COLORS: [&str; 8] = ["33", "34", "35", "36", "93", "94", "95", "96"];
+
+/// The ANSI SGR parameters for lines reporting that an action succeeded (green).
+const SUCCESS_COLOR: &str = "32";
+
+/// The ANSI SGR parameters for lines reporting that an action failed (bold red).
+const FAILURE_COLOR: &str = "1;31";
+
+/// Returns the color in which to print `host`, which is the same every time.
+pub(crate) fn host_color(host: &str) -> &'static str {
+    // FNV-1a, which unlike std's default hasher is guaranteed stable across Rust releases.
+    let hash = host.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    HOST_COLORS[hash as usize % HOST_COLORS.len()]
+}
+
+/// Wraps `text` in the ANSI escape codes to print it in `color`.
+fn paint(color: &str, text: &str) -> String {
+    format!("\x1b[{color}m{text}\x1b[0m")
+}
+
+/// A writer that starts every line with `[host]`, optionally in color.
+///
+/// Lines that already start with `[host] `, e.g. from [print_host_message], keep a single prefix.
+/// With color, lines that report success or failure are colored to match.
+///
+/// Complete lines pass through as soon as they're written. A partial line, e.g. a prompt, passes
+/// through when [HostLines] is flushed or dropped.
+pub struct HostLines<'a, W: Write> {
+    /// Where to write the prefixed lines.
+    inner: W,
+
+    /// The host to name at the start of each line.
+    host: &'a str,
+
+    /// Whether to use color.
+    color: bool,
+
+    /// Text written since the last newline.
+    pending: Vec<u8>,
+
+    /// Whether the next text written starts a new line.
+    at_line_start: bool,
+}
+
+impl<'a, W: Write> HostLines<'a, W> {
+    /// Creates a [HostLines] that prefixes lines with `host` before writing them to `inner`.
+    pub fn new(inner: W, host: &'a str, color: bool) -> Self {
+        HostLines {
+            inner,
+            host,
+            color,
+            pending: Vec::new(),
+            at_line_start: true,
+        }
+    }
+
+    /// Writes `text`, which is either one line ending in a newline or a partial line.
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        let text = String::from_utf8_lossy(text);
+        let (body, newline) = match text.strip_suffix('\n') {
+            Some(body) => (body, "\n"),
+            None => (&text[..], ""),
+        };
+
+        if self.at_line_start {
+            let prefix = format!("[{}]", self.host);
+            let body = body
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_prefix(' '))
+                .unwrap_or(body);
+            match self.color {
+                true => {
+                    let body = match body {
+                        b if b.starts_with("Completed ") => paint(SUCCESS_COLOR, b),
+                        b if b.starts_with("Action failed") || b.starts_with("Action exited") => {
+                            paint(FAILURE_COLOR, b)
+                        }
+                        b => b.to_string(),
+                    };
+                    let prefix = paint(host_color(self.host), &prefix);
+                    write!(self.inner, "{prefix} {body}{newline}")?;
+                }
+                false => write!(self.inner, "{prefix} {body}{newline}")?,
+            }
+        } else {
+            write!(self.inner, "{body}{newline}")?;
+        }
+
+        self.at_line_start = !newline.is_empty();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for HostLines<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.emit(&line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let partial = std::mem::take(&mut self.pending);
+            self.emit(&partial)?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for HostLines<'_, W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
     }
 }
 
@@ -74,46 +876,220 @@ pub fn title(action: &Action) -> String {
             // It's unlikely that vec has more than one element, but that's not our concern.
             format!("command: {}", vec.join("; "))
         }
+        ControllerKey {
+            login_key,
+            action_key,
+            remove,
+        } => {
+            let verb = match remove {
+                true => "remove",
+                false => "add",
+            };
+            let keys: Vec<&str> = [("login", login_key), ("action", action_key)]
+                .into_iter()
+                .filter(|(_, key)| key.is_some())
+                .map(|(kind, _)| kind)
+                .collect();
+            format!("controller_key ({verb}): {}", keys.join(", "))
+        }
+        Custom { plugin, .. } => format!("custom: {plugin}"),
+        KernelModule { name, remove, .. } => {
+            let verb = match remove {
+                true => "remove",
+                false => "load",
+            };
+            format!("kernel_module ({verb}): {name}")
+        }
         LineInFile { line, path, .. } => format!("line_in_file ({path}): {line}"),
-        Script { name, user, .. } => format!("script ({user}): {name}"),
+        Script { name, user, .. } => {
+            let user = user.as_deref().unwrap_or(Action::DEFAULT_USER_AND_GROUP);
+            format!("script ({user}): {name}")
+        }
+        Swap { path, remove, .. } => {
+            let verb = match remove {
+                true => "remove",
+                false => "enable",
+            };
+            format!("swap ({verb}): {path}")
+        }
         Upload { from, to, .. } => format!("upload: {from} -> {to}"),
     }
 }
 
-/// A testable function containing the logic for reporting the outcome of an [Action].
-pub(crate) fn _report<O: Write, E: Write>(
+/// A testable function that reports the outcome of an [Action] in as much detail as `verbosity`
+/// calls for.
+///
+/// Reports failures in full, like [_report], regardless of `verbosity`.
+pub(crate) fn _report_at<O: Write, E: Write>(
     stdout: &mut O,
     stderr: &mut E,
     host: &str,
     action: &Action,
     output: &Output,
+    verbosity: Verbosity,
 ) -> io::Result<()> {
-    fn write_indented<W: Write>(
-        writer: &mut W,
-        header: impl Display,
-        content: impl AsRef<str>,
-    ) -> io::Result<()> {
-        //                1234
-        writeln!(writer, "    {header}")?;
-        for line in content.as_ref().lines() {
-            //                12345678
-            writeln!(writer, "        {line}")?;
+    match (output.status.success(), verbosity) {
+        (false, _) | (true, Verbosity::Output | Verbosity::Network) => {
+            _report(stdout, stderr, host, action, output)
         }
-        Ok(())
+        (true, Verbosity::Actions) => {
+            print_host_message(stdout, host, completed(action, output))?;
+            if let Some(diff) = diff(action, &output.stdout) {
+                write_indented(stdout, "Changes:", diff)?;
+            }
+            Ok(())
+        }
+        (true, Verbosity::Quiet | Verbosity::Tasks) => Ok(()),
+    }
+}
+
+/// Returns the unified diff that `sira-client` reported for an [Action] that changed a file, if
+/// any.
+///
+/// For [Action::LineInFile] and [Action::Upload], `sira-client` prints nothing to stdout but the
+/// diff, the [Status], and, for uploads, the installed file's checksum.
+pub fn diff(action: &Action, stdout: &[u8]) -> Option<String> {
+    if !matches!(action, Action::LineInFile { .. } | Action::Upload { .. }) {
+        return None;
+    }
+    let diff: String = String::from_utf8_lossy(stdout)
+        .split_inclusive('\n')
+        .filter(|line| {
+            !line.starts_with(UPLOAD_CHECKSUM_PREFIX)
+                && !line.starts_with(STATUS_PREFIX)
+                && !line.starts_with(ERROR_PREFIX)
+        })
+        .collect();
+    (!diff.is_empty()).then_some(diff)
+}
+
+/// Returns the message reporting that an [Action] succeeded, noting if it changed nothing.
+fn completed(action: &Action, output: &Output) -> String {
+    match Status::from_output(output) {
+        Status::Ok => format!("Completed {} (no changes)", title(action)),
+        _ => format!("Completed {}", title(action)),
+    }
+}
+
+/// Writes `header` and then `content`, indented beneath it.
+fn write_indented<W: Write>(
+    writer: &mut W,
+    header: impl Display,
+    content: impl AsRef<str>,
+) -> io::Result<()> {
+    //                1234
+    writeln!(writer, "    {header}")?;
+    for line in content.as_ref().lines() {
+        //                12345678
+        writeln!(writer, "        {line}")?;
+    }
+    Ok(())
+}
+
+/// A testable function that counts how many actions ended with each [Status].
+///
+/// Prints nothing if no actions ran.
+pub(crate) fn _summary<O: Write>(
+    stdout: &mut O,
+    statuses: &BTreeMap<Status, usize>,
+) -> io::Result<()> {
+    let count = |status| statuses.get(&status).copied().unwrap_or_default();
+    let (ok, changed, failed) = (
+        count(Status::Ok),
+        count(Status::Changed),
+        count(Status::Failed),
+    );
+    match (ok, changed, failed) {
+        (0, 0, 0) => Ok(()),
+        (_, 0, 0) => writeln!(stdout, "\nNo changes ({ok} unchanged)"),
+        _ => writeln!(
+            stdout,
+            "\nActions: {changed} changed, {ok} unchanged, {failed} failed",
+        ),
+    }
+}
+
+/// A testable function that counts how many checks passed and failed. Please see
+/// [Report::verifying].
+///
+/// Prints nothing if no checks ran.
+pub(crate) fn _checks_summary<O: Write>(
+    stdout: &mut O,
+    passed: usize,
+    failed: usize,
+) -> io::Result<()> {
+    match (passed, failed) {
+        (0, 0) => Ok(()),
+        _ => writeln!(stdout, "Checks: {passed} passed, {failed} failed"),
+    }
+}
+
+/// The number of actions that [Reporter] lists at the end of a run, slowest first.
+pub const SLOWEST_ACTIONS: usize = 5;
+
+/// A testable function that lists the `count` slowest of `timings`, given as
+/// `(duration, host, title)`.
+///
+/// Prints nothing if there are no timings.
+pub(crate) fn _slowest_actions<O: Write>(
+    stdout: &mut O,
+    mut timings: Vec<(Duration, String, String)>,
+    count: usize,
+) -> io::Result<()> {
+    if timings.is_empty() {
+        return Ok(());
     }
+    timings.sort_by_key(|(duration, ..)| std::cmp::Reverse(*duration));
 
+    writeln!(stdout, "\nSlowest actions:")?;
+    for (duration, host, title) in timings.iter().take(count) {
+        writeln!(
+            stdout,
+            "    {:>9.3}s  [{host}] {title}",
+            duration.as_secs_f64(),
+        )?;
+    }
+    Ok(())
+}
+
+/// A testable function containing the logic for reporting that a host is starting a new task.
+pub(crate) fn _task_starting<O: Write>(
+    stdout: &mut O,
+    host: &str,
+    manifest: &str,
+    task: &str,
+) -> io::Result<()> {
+    print_host_message(stdout, host, format!("Task: {task} ({manifest})"))
+}
+
+/// A testable function that reports that `host` is about to run its checks. Please see
+/// [Report::verifying].
+pub(crate) fn _verifying<O: Write>(stdout: &mut O, host: &str) -> io::Result<()> {
+    print_host_message(stdout, host, "Verifying")
+}
+
+/// A testable function containing the logic for reporting the outcome of an [Action].
+pub(crate) fn _report<O: Write, E: Write>(
+    stdout: &mut O,
+    stderr: &mut E,
+    host: &str,
+    action: &Action,
+    output: &Output,
+) -> io::Result<()> {
     if output.status.success() {
-        print_host_message(stdout, host, format!("Completed {}", title(action)))?;
+        print_host_message(stdout, host, completed(action, output))?;
     } else {
         print_host_message(stderr, host, "Action failed. See below for details.")?;
     }
 
-    if !output.stdout.is_empty() {
-        write_indented(
-            stdout,
-            "Captured stdout:",
-            String::from_utf8_lossy(&output.stdout),
-        )?;
+    // The status and error lines are for Sira, not the user, who sees the status above and the
+    // error on stderr.
+    let captured: String = String::from_utf8_lossy(&output.stdout)
+        .split_inclusive('\n')
+        .filter(|line| !line.starts_with(STATUS_PREFIX) && !line.starts_with(ERROR_PREFIX))
+        .collect();
+    if !captured.is_empty() {
+        write_indented(stdout, "Captured stdout:", captured)?;
     }
 
     if !output.stderr.is_empty() {
@@ -135,6 +1111,12 @@ pub(crate) fn _report<O: Write, E: Write>(
     Ok(())
 }
 
+/// A testable function containing the logic for reporting progress on a running [Action].
+pub(crate) fn _progress<O: Write>(stdout: &mut O, host: &str, message: &str) -> io::Result<()> {
+    // Indent progress beneath the "Starting" line for its action.
+    print_host_message(stdout, host, format!("    {message}"))
+}
+
 /// A testable function containing the logic for reporting that an [Action] is starting.
 pub(crate) fn _starting<O: Write>(stdout: &mut O, host: &str, action: &Action) -> io::Result<()> {
     let action = title(action);
@@ -145,5 +1127,42 @@ pub(crate) fn _starting<O: Write>(stdout: &mut O, host: &str, action: &Action) -
     print_host_message(stdout, host, message)
 }
 
+/// A testable function containing the logic for prompting the user to touch their security key.
+pub(crate) fn _touch_required<O: Write>(
+    stdout: &mut O,
+    host: &str,
+    purpose: &str,
+) -> io::Result<()> {
+    print_host_message(
+        stdout,
+        host,
+        format!("Touch your security key to {purpose}."),
+    )?;
+    stdout.flush()
+}
+
+/// A testable function containing the logic for asking the user to confirm an [Action].
+///
+/// Only an answer that starts with `y` or `Y` approves the action. End of input declines it.
+pub(crate) fn _confirm<O: Write, I: BufRead>(
+    stdout: &mut O,
+    stdin: &mut I,
+    host: &str,
+    action: &Action,
+) -> io::Result<bool> {
+    print_host_message(stdout, host, "This action requires confirmation:")?;
+    let yaml = serde_yaml::to_string(action).unwrap();
+    for line in yaml.lines() {
+        //                12345678
+        writeln!(stdout, "        {line}")?;
+    }
+    write!(stdout, "Run this action on {host}? [y/N]: ")?;
+    stdout.flush()?;
+
+    let mut answer = String::new();
+    stdin.read_line(&mut answer)?;
+    Ok(answer.trim_start().to_ascii_lowercase().starts_with('y'))
+}
+
 #[cfg(test)]
 mod test;
@@ -37,6 +37,18 @@ mod title {
         );
     }
 
+    #[test]
+    fn controller_key() {
+        assert_eq!(
+            "controller_key (remove): login, action",
+            title(&ControllerKey {
+                login_key: Some("ssh-ed25519 AAAA sira".to_string()),
+                action_key: Some("ssh-ed25519 BBBB sira".to_string()),
+                remove: true,
+            }),
+        );
+    }
+
     #[test]
     fn line_in_file() {
         assert_eq!(
@@ -57,7 +69,7 @@ mod title {
             "script (alice): Set up Alice's user account",
             title(&Script {
                 name: "Set up Alice's user account".to_string(),
-                user: "alice".to_string(),
+                user: Some("alice".to_string()),
                 contents: "#!/bin/bash\n\
                     \n\
                     echo Eh, maybe later.\n"
@@ -77,6 +89,14 @@ mod title {
                 group: "alice".to_string(),
                 permissions: Some("644".to_string()),
                 overwrite: true,
+                encrypt: false,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: None,
+                xattrs: Default::default(),
+                capabilities: None,
             }),
         );
     }
@@ -231,11 +251,8 @@ mod _report {
             impl Drop for FailingWriter {
                 fn drop(&mut self) {
                     // If we never saw the expected line, the test presumably has a bug.
-                    if !std::thread::panicking() && self.failing_line.is_some() {
-                        panic!(
-                            "never received failing line: {}",
-                            self.failing_line.as_ref().unwrap()
-                        );
+                    if let (false, Some(line)) = (std::thread::panicking(), &self.failing_line) {
+                        panic!("never received failing line: {line}");
                     }
                 }
             }
@@ -367,6 +384,381 @@ mod _report {
     }
 }
 
+mod _report_at {
+    use super::_report::fixtures::*;
+    use super::*;
+    use crate::core::action::{ClientError, ErrorCode};
+
+    fn report_at(output: Output, verbosity: Verbosity) -> (String, String) {
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let action = Action::Command(vec!["echo hi".to_string()]);
+        _report_at(&mut stdout, &mut stderr, "bob", &action, &output, verbosity).unwrap();
+        (
+            String::from_utf8(stdout).unwrap(),
+            String::from_utf8(stderr).unwrap(),
+        )
+    }
+
+    fn success_with_stdout() -> Output {
+        Output {
+            stdout: b"hi\n".to_vec(),
+            ..success()
+        }
+    }
+
+    #[test]
+    fn quiet_level_skips_successes() {
+        assert_eq!(
+            (String::new(), String::new()),
+            report_at(success_with_stdout(), Verbosity::Quiet),
+        );
+    }
+
+    #[test]
+    fn tasks_level_skips_successes() {
+        assert_eq!(
+            (String::new(), String::new()),
+            report_at(success_with_stdout(), Verbosity::Tasks),
+        );
+    }
+
+    #[test]
+    fn actions_level_skips_output() {
+        assert_eq!(
+            (
+                "[bob] Completed command: echo hi\n".to_string(),
+                String::new()
+            ),
+            report_at(success_with_stdout(), Verbosity::Actions),
+        );
+    }
+
+    #[test]
+    fn actions_level_shows_diffs() {
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let action = Action::LineInFile {
+            path: "/etc/hosts".to_string(),
+            line: "b".to_string(),
+            pattern: None,
+            after: None,
+            indent: false,
+        };
+        let output = Output {
+            stdout: b"--- /etc/hosts\n+++ /etc/hosts\n@@ -1 +1,2 @@\n a\n+b\n".to_vec(),
+            ..success()
+        };
+        _report_at(
+            &mut stdout,
+            &mut stderr,
+            "bob",
+            &action,
+            &output,
+            Verbosity::Actions,
+        )
+        .unwrap();
+        assert_eq!(
+            "[bob] Completed line_in_file (/etc/hosts): b\n    \
+            Changes:\n        \
+            --- /etc/hosts\n        \
+            +++ /etc/hosts\n        \
+            @@ -1 +1,2 @@\n         \
+            a\n        \
+            +b\n",
+            String::from_utf8(stdout).unwrap(),
+        );
+    }
+
+    #[test]
+    fn notes_unchanged_actions() {
+        let output = Output {
+            stdout: format!("{}\n", Status::Ok.line()).into_bytes(),
+            ..success()
+        };
+        assert_eq!(
+            (
+                "[bob] Completed command: echo hi (no changes)\n".to_string(),
+                String::new()
+            ),
+            report_at(output, Verbosity::Actions),
+        );
+    }
+
+    #[test]
+    fn output_level_hides_status_line() {
+        let output = Output {
+            stdout: format!("hi\n{}\n", Status::Changed.line()).into_bytes(),
+            ..success()
+        };
+        assert_eq!(
+            (
+                "[bob] Completed command: echo hi\n    Captured stdout:\n        hi\n".to_string(),
+                String::new(),
+            ),
+            report_at(output, Verbosity::Output),
+        );
+    }
+
+    #[test]
+    fn output_level_includes_output() {
+        assert_eq!(
+            (
+                "[bob] Completed command: echo hi\n    Captured stdout:\n        hi\n".to_string(),
+                String::new(),
+            ),
+            report_at(success_with_stdout(), Verbosity::Output),
+        );
+    }
+
+    #[test]
+    fn reports_failures_in_full_at_every_level() {
+        for verbosity in [
+            Verbosity::Quiet,
+            Verbosity::Tasks,
+            Verbosity::Actions,
+            Verbosity::Output,
+            Verbosity::Network,
+        ] {
+            let (_, stderr) = report_at(error_code(3), verbosity);
+            assert!(stderr.contains("Action failed"), "{verbosity:?}: {stderr}");
+            assert!(stderr.contains("exit code 3"), "{verbosity:?}: {stderr}");
+        }
+    }
+
+    #[test]
+    fn hides_error_line() {
+        let error = ClientError::new(ErrorCode::NotFound, "no such file");
+        let output = Output {
+            stdout: format!("hi\n{}\n", error.line()).into_bytes(),
+            ..error_code(1)
+        };
+        let (stdout, _) = report_at(output, Verbosity::Output);
+        assert_eq!("    Captured stdout:\n        hi\n", stdout);
+    }
+}
+
+mod diff {
+    use super::*;
+
+    fn upload() -> Action {
+        Action::Upload {
+            from: "hosts".to_string(),
+            to: "/etc/hosts".to_string(),
+            user: "root".to_string(),
+            group: "root".to_string(),
+            permissions: None,
+            overwrite: true,
+            encrypt: false,
+            seuser: None,
+            serole: None,
+            setype: None,
+            acl: vec![],
+            transfer_permissions: None,
+            xattrs: Default::default(),
+            capabilities: None,
+        }
+    }
+
+    #[test]
+    fn skips_checksum() {
+        assert_eq!(
+            Some("--- /etc/hosts\n+++ /etc/hosts\n".to_string()),
+            diff(&upload(), b"--- /etc/hosts\n+++ /etc/hosts\nsha256: 0123\n",),
+        );
+        assert_eq!(None, diff(&upload(), b"sha256: 0123\n"));
+    }
+
+    #[test]
+    fn skips_status() {
+        let stdout = format!(
+            "--- /etc/hosts\n+++ /etc/hosts\n{}\n",
+            Status::Changed.line()
+        );
+        assert_eq!(
+            Some("--- /etc/hosts\n+++ /etc/hosts\n".to_string()),
+            diff(&upload(), stdout.as_bytes()),
+        );
+        let stdout = format!("{}\n", Status::Ok.line());
+        assert_eq!(None, diff(&upload(), stdout.as_bytes()));
+    }
+
+    #[test]
+    fn ignores_other_actions() {
+        let action = Action::Command(vec!["cat diff".to_string()]);
+        assert_eq!(None, diff(&action, b"--- a\n+++ b\n"));
+    }
+}
+
+mod _summary {
+    use super::*;
+
+    fn summary(statuses: &[(Status, usize)]) -> String {
+        let mut stdout: Vec<u8> = Vec::new();
+        _summary(&mut stdout, &statuses.iter().copied().collect()).unwrap();
+        String::from_utf8(stdout).unwrap()
+    }
+
+    #[test]
+    fn counts_each_status() {
+        assert_eq!(
+            "\nActions: 2 changed, 5 unchanged, 1 failed\n",
+            summary(&[(Status::Ok, 5), (Status::Changed, 2), (Status::Failed, 1)]),
+        );
+        assert_eq!(
+            "\nActions: 0 changed, 5 unchanged, 1 failed\n",
+            summary(&[(Status::Ok, 5), (Status::Failed, 1)]),
+        );
+    }
+
+    #[test]
+    fn says_when_nothing_changed() {
+        assert_eq!("\nNo changes (5 unchanged)\n", summary(&[(Status::Ok, 5)]));
+    }
+
+    #[test]
+    fn prints_nothing_without_actions() {
+        assert_eq!("", summary(&[]));
+    }
+}
+
+mod _slowest_actions {
+    use super::*;
+    use std::time::Duration;
+
+    fn timing(millis: u64, host: &str, title: &str) -> (Duration, String, String) {
+        (
+            Duration::from_millis(millis),
+            host.to_string(),
+            title.to_string(),
+        )
+    }
+
+    #[test]
+    fn lists_slowest_first() {
+        let timings = vec![
+            timing(1_500, "web1", "Upload a.txt"),
+            timing(12_250, "web2", "Run command"),
+            timing(20, "web1", "Line in file"),
+        ];
+        let mut stdout: Vec<u8> = Vec::new();
+        _slowest_actions(&mut stdout, timings, SLOWEST_ACTIONS).unwrap();
+        assert_eq!(
+            concat!(
+                "\nSlowest actions:\n",
+                "       12.250s  [web2] Run command\n",
+                "        1.500s  [web1] Upload a.txt\n",
+                "        0.020s  [web1] Line in file\n",
+            ),
+            String::from_utf8_lossy(&stdout),
+        );
+    }
+
+    #[test]
+    fn lists_at_most_count() {
+        let timings = vec![
+            timing(1, "a", "One"),
+            timing(3, "b", "Three"),
+            timing(2, "c", "Two"),
+        ];
+        let mut stdout: Vec<u8> = Vec::new();
+        _slowest_actions(&mut stdout, timings, 2).unwrap();
+        let stdout = String::from_utf8_lossy(&stdout);
+        assert!(stdout.contains("Three"));
+        assert!(stdout.contains("Two"));
+        assert!(!stdout.contains("One"));
+    }
+
+    #[test]
+    fn prints_nothing_without_timings() {
+        let mut stdout: Vec<u8> = Vec::new();
+        _slowest_actions(&mut stdout, vec![], SLOWEST_ACTIONS).unwrap();
+        assert!(stdout.is_empty());
+    }
+}
+
+mod _checks_summary {
+    use super::*;
+
+    #[test]
+    fn counts_passed_and_failed() {
+        let mut stdout: Vec<u8> = Vec::new();
+        _checks_summary(&mut stdout, 3, 1).unwrap();
+        assert_eq!(
+            "Checks: 3 passed, 1 failed\n",
+            String::from_utf8_lossy(&stdout)
+        );
+    }
+
+    #[test]
+    fn prints_nothing_without_checks() {
+        let mut stdout: Vec<u8> = Vec::new();
+        _checks_summary(&mut stdout, 0, 0).unwrap();
+        assert!(stdout.is_empty());
+    }
+}
+
+mod _verifying {
+    use super::*;
+
+    #[test]
+    fn works() {
+        let mut stdout: Vec<u8> = Vec::new();
+        _verifying(&mut stdout, "bob").unwrap();
+        assert_eq!("[bob] Verifying\n", String::from_utf8_lossy(&stdout));
+    }
+}
+
+mod _task_starting {
+    use super::*;
+
+    #[test]
+    fn works() {
+        let mut stdout: Vec<u8> = Vec::new();
+        _task_starting(&mut stdout, "bob", "Web servers", "Restart app").unwrap();
+        assert_eq!(
+            "[bob] Task: Restart app (Web servers)\n",
+            String::from_utf8_lossy(&stdout),
+        );
+    }
+}
+
+mod _progress {
+    use super::*;
+
+    #[test]
+    fn works() {
+        let mut stdout: Vec<u8> = Vec::new();
+        _progress(&mut stdout, "bob", "Running apt-get update").unwrap();
+        assert_eq!(
+            "[bob]     Running apt-get update\n",
+            String::from_utf8_lossy(&stdout),
+        );
+    }
+}
+
+mod verbosity {
+    use super::*;
+
+    #[test]
+    fn from_count_saturates() {
+        assert_eq!(Verbosity::Tasks, Verbosity::from_count(0));
+        assert_eq!(Verbosity::Actions, Verbosity::from_count(1));
+        assert_eq!(Verbosity::Output, Verbosity::from_count(2));
+        assert_eq!(Verbosity::Network, Verbosity::from_count(3));
+        assert_eq!(Verbosity::Network, Verbosity::from_count(4));
+    }
+}
+
+mod output_format {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        assert_eq!(OutputFormat::Text, "text".parse().unwrap());
+        assert_eq!(OutputFormat::Json, "json".parse().unwrap());
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+}
+
 mod _starting {
     use super::*;
 
@@ -398,3 +790,302 @@ mod _starting {
         assert!(_starting(&mut FailingWriter(), "bob", &action).is_err());
     }
 }
+
+mod _touch_required {
+    use super::*;
+
+    #[test]
+    fn works() {
+        let mut stdout: Vec<u8> = Vec::new();
+        _touch_required(&mut stdout, "bob", "log in").unwrap();
+        assert_eq!(
+            "[bob] Touch your security key to log in.\n",
+            String::from_utf8_lossy(&stdout),
+        );
+    }
+}
+
+mod _confirm {
+    use super::*;
+
+    fn confirm(answer: &str) -> (bool, String) {
+        let mut stdout: Vec<u8> = Vec::new();
+        let action = Action::Command(vec!["wipefs -a /dev/sdb".to_string()]);
+        let approved = _confirm(&mut stdout, &mut answer.as_bytes(), "bob", &action).unwrap();
+        (approved, String::from_utf8(stdout).unwrap())
+    }
+
+    #[test]
+    fn shows_host_and_action() {
+        let (_, stdout) = confirm("y\n");
+        assert_eq!(
+            "[bob] This action requires confirmation:\n\
+            \x20       command:\n\
+            \x20       - wipefs -a /dev/sdb\n\
+            Run this action on bob? [y/N]: ",
+            stdout,
+        );
+    }
+
+    #[test]
+    fn approves_yes() {
+        assert!(confirm("y\n").0);
+        assert!(confirm("Yes\n").0);
+    }
+
+    #[test]
+    fn declines_anything_else() {
+        assert!(!confirm("n\n").0);
+        assert!(!confirm("\n").0);
+        assert!(!confirm("").0);
+    }
+}
+
+mod host_lines {
+    use super::*;
+
+    fn write(color: bool, f: impl FnOnce(&mut HostLines<&mut Vec<u8>>)) -> String {
+        let mut out = Vec::new();
+        let mut lines = HostLines::new(&mut out, "bob", color);
+        f(&mut lines);
+        drop(lines);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn prefixes_every_line() {
+        let out = write(false, |w| {
+            write!(w, "    Captured stdout:\n        one\n        two\n").unwrap();
+        });
+        assert_eq!(
+            "[bob]     Captured stdout:\n[bob]         one\n[bob]         two\n",
+            out,
+        );
+    }
+
+    #[test]
+    fn does_not_repeat_prefix() {
+        let out = write(false, |w| {
+            print_host_message(w, "bob", "Task: Restart app (Web servers)").unwrap();
+        });
+        assert_eq!("[bob] Task: Restart app (Web servers)\n", out);
+    }
+
+    #[test]
+    fn joins_lines_written_in_pieces() {
+        let out = write(false, |w| {
+            w.write_all(b"Compl").unwrap();
+            w.write_all(b"eted command: true\nStarting").unwrap();
+            w.write_all(b"  command: false\n").unwrap();
+        });
+        assert_eq!(
+            "[bob] Completed command: true\n[bob] Starting  command: false\n",
+            out,
+        );
+    }
+
+    #[test]
+    fn flushes_partial_lines_once() {
+        let mut out = Vec::new();
+        let mut lines = HostLines::new(&mut out, "bob", false);
+        write!(lines, "Run this action on bob? [y/N]: ").unwrap();
+        lines.flush().unwrap();
+        write!(lines, "(declined)\nnext\n").unwrap();
+        drop(lines);
+        assert_eq!(
+            "[bob] Run this action on bob? [y/N]: (declined)\n[bob] next\n",
+            String::from_utf8(out).unwrap(),
+        );
+    }
+
+    #[test]
+    fn colors_host_and_outcomes() {
+        let color = host_color("bob");
+        let out = write(true, |w| {
+            print_host_message(w, "bob", "Completed command: true").unwrap();
+            print_host_message(w, "bob", "Action failed. See below for details.").unwrap();
+            writeln!(w, "        output").unwrap();
+        });
+        assert_eq!(
+            format!(
+                "\x1b[{color}m[bob]\x1b[0m \x1b[32mCompleted command: true\x1b[0m\n\
+                \x1b[{color}m[bob]\x1b[0m \x1b[1;31mAction failed. See below for details.\x1b[0m\n\
+                \x1b[{color}m[bob]\x1b[0m         output\n",
+            ),
+            out,
+        );
+    }
+}
+
+mod host_color {
+    use super::*;
+
+    #[test]
+    fn is_stable() {
+        // These values must not change between releases, so that hosts keep their colors.
+        assert_eq!(host_color("web1"), host_color("web1"));
+        assert_eq!("33", host_color("web1"));
+        assert_eq!("34", host_color("web2"));
+    }
+
+    #[test]
+    fn avoids_outcome_colors() {
+        for host in ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"] {
+            assert_ne!("31", host_color(host));
+            assert_ne!("32", host_color(host));
+        }
+    }
+}
+
+mod color_choice {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        assert_eq!(ColorChoice::Auto, "auto".parse().unwrap());
+        assert_eq!(ColorChoice::Always, "always".parse().unwrap());
+        assert_eq!(ColorChoice::Never, "never".parse().unwrap());
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+
+    #[test]
+    fn enabled() {
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+    }
+}
+
+mod option {
+    use super::*;
+
+    #[tokio::test]
+    async fn none_declines_confirmation() {
+        let action = Action::Command(vec!["true".to_string()]);
+        assert!(!None::<Reporter>.confirm("bob", &action).await.unwrap());
+    }
+}
+
+mod tuple {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    // Fails to report anything, like a terminal whose stdout is a closed pipe.
+    struct Broken;
+
+    #[async_trait]
+    impl Report for Broken {
+        async fn action_source(&mut self, _: &str, _: &str, _: &str) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        async fn starting(&mut self, _: &str, _: &Action) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        async fn timing(&mut self, _: &str, _: &Action, _: Duration) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        async fn transferred(&mut self, _: &str, _: &Action, _: u64) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        async fn report(&mut self, _: &str, _: &Action, _: &Output) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    // Records the name of each method called.
+    #[derive(Default)]
+    struct Recorder(Vec<&'static str>);
+
+    #[async_trait]
+    impl Report for Recorder {
+        async fn action_source(&mut self, _: &str, _: &str, _: &str) -> io::Result<()> {
+            self.0.push("action_source");
+            Ok(())
+        }
+
+        async fn starting(&mut self, _: &str, _: &Action) -> io::Result<()> {
+            self.0.push("starting");
+            Ok(())
+        }
+
+        async fn timing(&mut self, _: &str, _: &Action, _: Duration) -> io::Result<()> {
+            self.0.push("timing");
+            Ok(())
+        }
+
+        async fn transferred(&mut self, _: &str, _: &Action, _: u64) -> io::Result<()> {
+            self.0.push("transferred");
+            Ok(())
+        }
+
+        async fn report(&mut self, _: &str, _: &Action, _: &Output) -> io::Result<()> {
+            self.0.push("report");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_each_action_to_second_if_first_fails() {
+        let action = Action::Command(vec!["true".to_string()]);
+        let output = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: vec![],
+            stderr: vec![],
+        };
+        let mut reporter = (Broken, Recorder::default());
+
+        let results = [
+            reporter.action_source("bob", "Manifest", "Task").await,
+            reporter.starting("bob", &action).await,
+            reporter
+                .timing("bob", &action, Duration::from_secs(1))
+                .await,
+            reporter.transferred("bob", &action, 1024).await,
+            reporter.report("bob", &action, &output).await,
+        ];
+
+        for result in results {
+            assert_eq!(io::ErrorKind::BrokenPipe, result.unwrap_err().kind());
+        }
+        assert_eq!(
+            [
+                "action_source",
+                "starting",
+                "timing",
+                "transferred",
+                "report"
+            ],
+            &reporter.1 .0[..],
+        );
+    }
+}
+
+mod running_actions {
+    use super::*;
+
+    #[test]
+    fn finishes_each_hosts_actions_in_order() {
+        let mut running = RunningActions::new();
+        running.source("web1", "Web servers", "Install packages");
+        running.source("web2", "Web servers", "Install packages");
+        running.source("web1", "Web servers", "Restart app");
+        running.start("web1");
+
+        let first = running.finish("web1").unwrap();
+        assert_eq!(("Install packages", 1), (first.task.as_str(), first.number));
+        let second = running.finish("web1").unwrap();
+        assert_eq!(("Restart app", 2), (second.task.as_str(), second.number));
+        assert!(second.started >= first.started);
+        assert_eq!(None, running.finish("web1"));
+        assert_eq!(1, running.finish("web2").unwrap().number);
+
+        // Numbers keep counting after earlier actions finish.
+        running.source("web1", "Web servers", "Check app");
+        assert_eq!(3, running.finish("web1").unwrap().number);
+    }
+}
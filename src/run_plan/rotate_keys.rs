@@ -0,0 +1,490 @@
+//! Rotates Sira's login and action keys across a network of managed nodes.
+//!
+//! Rotation proceeds in phases. Each phase that touches managed nodes runs a built-in [Plan] on
+//! every host:
+//!
+//! 1. **Stage.** Generate new key pairs on the control node, in `~/.ssh` with a `.new` suffix.
+//!    Using the current keys, add the new public keys to each managed node alongside the current
+//!    ones (see [stage_plan]).
+//! 1. **Verify.** Using only the new keys, run a no-op action on every managed node (see
+//!    [verify_plan]).
+//! 1. **Install.** Install the new keys on the control node. The current login key pair is kept
+//!    in `~/.ssh` with a `.old` suffix.
+//! 1. **Retire.** Using the new keys, replace each managed node's `authorized_keys` file and action
+//!    allowed signers file with files that contain only the new keys (see [retire_plan]).
+//!
+//! If staging or verification fails on any host, rotation stops before anything is installed or
+//! retired, and the current keys continue to work everywhere. Running rotation again reuses the
+//! staged key pairs, so it's safe to fix the problem and try again.
+//!
+//! If retirement fails on some hosts, those hosts simply accept both the old and new keys.
+//! Running rotation again will generate and roll out another set of keys, retiring all others.
+//!
+//! The manifest key never leaves the administrator's control, so rotating it doesn't involve
+//! managed nodes. Simply generate a new key pair, install its allowed signers file on the control
+//! node, and re-sign your manifest and task files.
+
+use super::{run_plan_with, RunOptions, ACTION_SIGNING_KEY};
+use crate::client;
+use crate::config;
+use crate::core::{Action, Manifest, Plan, Task};
+use crate::crypto::{self, KEY_DIR};
+use anyhow::{anyhow, bail, Context};
+use indexmap::IndexMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The name of the SSH private key used to log into managed nodes, found in `~/.ssh`.
+pub const LOGIN_KEY: &str = "sira";
+
+/// The suffix appended to a private key's file name while its replacement is staged.
+pub const STAGED_SUFFIX: &str = ".new";
+
+/// The suffix appended to a private key's file name once it has been replaced.
+pub const RETIRED_SUFFIX: &str = ".old";
+
+/// The public keys that a rotation will roll out to managed nodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewKeys {
+    /// The new login public key, as read from its `.pub` file.
+    pub login: String,
+
+    /// The new action public key, as read from its `.pub` file, if rotating the action key.
+    pub action: Option<String>,
+}
+
+/// Rotates the login key and, if installed, the action key on the control node and all `hosts`.
+///
+/// `sira_user` is the user that Sira logs in as on managed nodes. Please see the
+/// [module documentation](self) for details on the rotation process.
+///
+/// `ssh-keygen` will prompt for a passphrase for each new key pair. If you keep the login key in
+/// `ssh-agent`, remember to load the new key once rotation completes.
+///
+/// # Errors
+///
+/// Returns an error if any phase fails. The error message describes which phase failed, on which
+/// hosts, and what state the network is in.
+pub async fn rotate_keys(sira_user: &str, hosts: &[String]) -> anyhow::Result<()> {
+    let ssh_dir = home::home_dir()
+        .ok_or_else(|| anyhow!("could not retrieve user's home directory"))?
+        .join(".ssh");
+    let login_key = ssh_dir.join(LOGIN_KEY);
+    if !login_key.try_exists()? {
+        bail!(
+            "could not find the current login key: {}\n\
+            Sira can only rotate the login key from its default location.",
+            login_key.display(),
+        );
+    }
+
+    let installed_action_key = config::config_dir().join(KEY_DIR).join(ACTION_SIGNING_KEY);
+    let rotate_action_key = installed_action_key.try_exists()?;
+
+    // Phase 1: stage.
+    println!("Staging new keys.");
+    let staged_login_key = staged(&login_key);
+    stage_key_pair(
+        &staged_login_key,
+        crypto::key_file_is_security_key(&login_key),
+    )?;
+    let staged_action_key = match rotate_action_key {
+        true => {
+            let key = staged(ssh_dir.join(ACTION_SIGNING_KEY));
+            stage_key_pair(
+                &key,
+                crypto::key_file_is_security_key(&installed_action_key),
+            )?;
+            Some(key)
+        }
+        false => None,
+    };
+
+    let new_keys = NewKeys {
+        login: read_public_key(&staged_login_key)?,
+        action: match &staged_action_key {
+            Some(key) => Some(read_public_key(key)?),
+            None => None,
+        },
+    };
+
+    run_phase(
+        stage_plan(sira_user, hosts, &new_keys)?,
+        RunOptions::default(),
+        "staging",
+        "No keys have been retired, so the current keys still work everywhere.",
+    )
+    .await?;
+
+    // Phase 2: verify.
+    println!("\nVerifying new keys.");
+    let new_options = RunOptions {
+        login_key: Some(staged_login_key.clone()),
+        action_key: staged_action_key.clone(),
+        ..RunOptions::default()
+    };
+    run_phase(
+        verify_plan(hosts),
+        new_options,
+        "verification",
+        "No keys have been retired, so the current keys still work everywhere.",
+    )
+    .await?;
+
+    // Phase 3: install.
+    println!("\nInstalling new keys on the control node.");
+    swap_key_pair(&login_key)?;
+    if let Some(staged_action_key) = &staged_action_key {
+        install_action_key_pair(staged_action_key, &installed_action_key)?;
+        swap_key_pair(ssh_dir.join(ACTION_SIGNING_KEY))?;
+    }
+
+    // Phase 4: retire.
+    println!("\nRetiring old keys.");
+    let authorized_keys_path = write_temp_file(&new_keys.login)?;
+    let allowed_signers_path = match &new_keys.action {
+        Some(key) => Some(write_temp_file(&crypto::allowed_signers_line(key)?)?),
+        None => None,
+    };
+
+    let new_options = RunOptions {
+        login_key: Some(login_key),
+        action_key: rotate_action_key.then_some(installed_action_key),
+        ..RunOptions::default()
+    };
+    let result = run_phase(
+        retire_plan(
+            sira_user,
+            hosts,
+            &authorized_keys_path,
+            allowed_signers_path.as_deref(),
+        )?,
+        new_options,
+        "retirement",
+        "The new keys are installed and work everywhere, but the hosts above still accept the \
+        old keys as well. Run key rotation again to retire them.",
+    )
+    .await;
+
+    // Clean up temp files regardless of the outcome.
+    fs::remove_file(&authorized_keys_path)?;
+    if let Some(path) = &allowed_signers_path {
+        fs::remove_file(path)?;
+    }
+    result?;
+
+    println!("\nKey rotation complete.");
+    Ok(())
+}
+
+/// Returns a [Plan] that adds the new public keys to each host, alongside the current keys.
+///
+/// The login key is added to the Sira user's `~/.ssh/authorized_keys`. The action key, if any, is
+/// added to the action allowed signers file. Existing entries are left untouched, so this plan is
+/// idempotent.
+pub fn stage_plan(sira_user: &str, hosts: &[String], keys: &NewKeys) -> anyhow::Result<Plan> {
+    let mut actions = vec![Action::LineInFile {
+        path: authorized_keys_path(sira_user),
+        line: keys.login.trim().to_string(),
+        pattern: None,
+        after: None,
+        indent: false,
+    }];
+
+    if let Some(action_key) = &keys.action {
+        actions.push(Action::LineInFile {
+            path: remote_allowed_signers_path()?,
+            line: crypto::allowed_signers_line(action_key)?.trim().to_string(),
+            pattern: None,
+            after: None,
+            indent: false,
+        });
+    }
+
+    Ok(built_in_plan("Stage new keys", hosts, actions))
+}
+
+/// Returns a [Plan] that runs a no-op command on each host.
+///
+/// Run this plan with only the new keys: if it succeeds on a host, that host accepts them.
+pub fn verify_plan(hosts: &[String]) -> Plan {
+    built_in_plan(
+        "Verify new keys",
+        hosts,
+        vec![Action::Command(vec!["true".to_string()])],
+    )
+}
+
+/// Returns a [Plan] that replaces each host's authorized keys and allowed signers with new files.
+///
+/// `authorized_keys` and `allowed_signers` are paths to local files that contain only the new
+/// keys. If `allowed_signers` is [None], the action allowed signers file is left untouched.
+pub fn retire_plan(
+    sira_user: &str,
+    hosts: &[String],
+    authorized_keys: &str,
+    allowed_signers: Option<&str>,
+) -> anyhow::Result<Plan> {
+    let mut actions = vec![Action::Upload {
+        from: authorized_keys.to_string(),
+        to: authorized_keys_path(sira_user),
+        user: sira_user.to_string(),
+        group: sira_user.to_string(),
+        permissions: Some("0644".to_string()),
+        overwrite: true,
+        encrypt: false,
+        seuser: None,
+        serole: None,
+        setype: None,
+        acl: vec![],
+        transfer_permissions: None,
+        xattrs: Default::default(),
+        capabilities: None,
+    }];
+
+    if let Some(allowed_signers) = allowed_signers {
+        actions.push(Action::Upload {
+            from: allowed_signers.to_string(),
+            to: remote_allowed_signers_path()?,
+            user: "root".to_string(),
+            group: "root".to_string(),
+            permissions: Some("0644".to_string()),
+            overwrite: true,
+            encrypt: false,
+            seuser: None,
+            serole: None,
+            setype: None,
+            acl: vec![],
+            transfer_permissions: None,
+            xattrs: Default::default(),
+            capabilities: None,
+        });
+    }
+
+    Ok(built_in_plan("Retire old keys", hosts, actions))
+}
+
+/// Returns the path to the Sira user's `authorized_keys` file on managed nodes.
+///
+/// Like the installer, this assumes that the Sira user's home directory is `/home/<sira-user>`.
+fn authorized_keys_path(sira_user: &str) -> String {
+    format!("/home/{sira_user}/.ssh/authorized_keys")
+}
+
+/// Returns the path to the action allowed signers file on managed nodes.
+///
+/// Managed nodes share the control node's configuration layout.
+fn remote_allowed_signers_path() -> anyhow::Result<String> {
+    let path = crypto::allowed_signers_path(ACTION_SIGNING_KEY)?;
+    path.to_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("allowed signers path is not UTF-8: {}", path.display()))
+}
+
+/// Wraps `actions` in a [Plan] with one [Manifest] that targets `hosts`.
+pub(crate) fn built_in_plan(name: &str, hosts: &[String], actions: Vec<Action>) -> Plan {
+    let task = Task {
+        source: None,
+        name: name.to_string(),
+        actions,
+        vars: IndexMap::new(),
+        sensitive: Vec::new(),
+        confirm: false,
+        user: None,
+        escalate: false,
+        dpkg_lock_timeout: None,
+        limits: Default::default(),
+        sandbox: None,
+        independent: false,
+        verify: Vec::new(),
+    };
+
+    Plan {
+        manifests: vec![Manifest {
+            source: None,
+            name: name.to_string(),
+            hosts: hosts.to_vec(),
+            fact: None,
+            host_vars: Default::default(),
+            include: vec![task],
+            vars: IndexMap::new(),
+            sensitive: Vec::new(),
+        }],
+    }
+}
+
+/// Runs one phase of key rotation and converts any per-host errors into a single error.
+async fn run_phase(
+    plan: Plan,
+    options: RunOptions,
+    phase: &str,
+    advice: &str,
+) -> anyhow::Result<()> {
+    let Err(errors) = run_plan_with(plan, options).await else {
+        return Ok(());
+    };
+
+    let mut message = format!("key rotation failed during {phase} on these hosts:\n");
+    for (host, error) in errors {
+        message.push_str(&format!("[{host}] {error:?}\n"));
+    }
+    message.push('\n');
+    message.push_str(advice);
+    bail!(message)
+}
+
+/// Appends `suffix` to a path without performing a lossy UTF-8 conversion.
+fn with_suffix(path: impl AsRef<Path>, suffix: impl AsRef<OsStr>) -> PathBuf {
+    let mut path: OsString = path.as_ref().to_owned().into();
+    path.push(suffix);
+    path.into()
+}
+
+/// Returns the path where a private key's replacement is staged.
+pub fn staged(private_key: impl AsRef<Path>) -> PathBuf {
+    with_suffix(private_key, STAGED_SUFFIX)
+}
+
+/// Returns the path where a private key is kept after it is replaced.
+pub fn retired(private_key: impl AsRef<Path>) -> PathBuf {
+    with_suffix(private_key, RETIRED_SUFFIX)
+}
+
+/// Returns the path to the public key that corresponds to a private key.
+pub fn public_key(private_key: impl AsRef<Path>) -> PathBuf {
+    with_suffix(private_key, ".pub")
+}
+
+/// Generates a key pair at `private_key` unless a previous rotation already staged one there.
+///
+/// If `security_key`, generates the key pair on a FIDO2 hardware security key, like the key it
+/// replaces.
+fn stage_key_pair(private_key: &Path, security_key: bool) -> anyhow::Result<()> {
+    let private_exists = private_key.try_exists()?;
+    let public_exists = public_key(private_key).try_exists()?;
+    match (private_exists, public_exists) {
+        (true, true) => {
+            println!("Reusing staged key pair: {}", private_key.display());
+            Ok(())
+        }
+        (false, false) => {
+            println!("Generating key pair: {}", private_key.display());
+            if security_key {
+                println!("Touch your security key when it blinks to generate the key pair.");
+            }
+            client::run(
+                "ssh-keygen",
+                &[
+                    OsStr::new("-t"),
+                    OsStr::new(match security_key {
+                        true => "ed25519-sk",
+                        false => "ed25519",
+                    }),
+                    OsStr::new("-C"),
+                    OsStr::new("sira"),
+                    OsStr::new("-f"),
+                    private_key.as_os_str(),
+                ],
+            )
+        }
+        _ => bail!(
+            "found one half of a staged key pair; please remove it and try again: {}",
+            private_key.display(),
+        ),
+    }
+}
+
+/// Reads the public key that corresponds to `private_key`.
+fn read_public_key(private_key: &Path) -> anyhow::Result<String> {
+    let path = public_key(private_key);
+    fs::read_to_string(&path)
+        .with_context(|| format!("could not read public key: {}", path.display()))
+}
+
+/// Replaces a key pair with its staged replacement, keeping the current pair, if any, as retired.
+fn swap_key_pair(private_key: impl AsRef<Path>) -> anyhow::Result<()> {
+    let current = private_key.as_ref();
+    let staged = staged(current);
+
+    for (from, to) in [
+        (current.to_owned(), retired(current)),
+        (public_key(current), public_key(retired(current))),
+        (staged.clone(), current.to_owned()),
+        (public_key(&staged), public_key(current)),
+    ] {
+        if from.try_exists()? {
+            fs::rename(&from, &to).with_context(|| {
+                format!("could not move {} to {}", from.display(), to.display())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Installs a staged action key pair into Sira's key directory using `sudo`.
+///
+/// Ownership and permissions match those set by the installer.
+fn install_action_key_pair(staged: &Path, installed: &Path) -> anyhow::Result<()> {
+    println!(
+        "Installing new action key to {}\n\
+        You might be prompted for your password one or more times.",
+        installed.display(),
+    );
+    let installed_public = public_key(installed);
+    let private_owner = format!("root:{}", client::whoami());
+
+    client::run(
+        "sudo",
+        &[OsStr::new("cp"), staged.as_ref(), installed.as_ref()],
+    )?;
+    client::run(
+        "sudo",
+        &[
+            OsStr::new("cp"),
+            public_key(staged).as_ref(),
+            installed_public.as_ref(),
+        ],
+    )?;
+    client::run(
+        "sudo",
+        &[
+            OsStr::new("chown"),
+            OsStr::new(&private_owner),
+            installed.as_ref(),
+        ],
+    )?;
+    client::run(
+        "sudo",
+        &[
+            OsStr::new("chown"),
+            OsStr::new("root:root"),
+            installed_public.as_ref(),
+        ],
+    )?;
+    client::run(
+        "sudo",
+        &[OsStr::new("chmod"), OsStr::new("0640"), installed.as_ref()],
+    )?;
+    client::run(
+        "sudo",
+        &[
+            OsStr::new("chmod"),
+            OsStr::new("0644"),
+            installed_public.as_ref(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Writes `contents` to a new temporary file and returns its path.
+fn write_temp_file(contents: &str) -> anyhow::Result<String> {
+    let (mut file, path) = client::mktemp()?;
+    file.write_all(contents.as_bytes())?;
+    file.flush()?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,201 @@
+use super::*;
+
+fn hosts() -> Vec<String> {
+    vec!["alpha".to_string(), "bravo".to_string()]
+}
+
+fn new_keys(action: bool) -> NewKeys {
+    NewKeys {
+        login: "ssh-ed25519 LOGIN sira\n".to_string(),
+        action: action.then(|| "ssh-ed25519 ACTION sira\n".to_string()),
+    }
+}
+
+// Returns the actions in a built-in plan, checking that the plan targets `hosts()`.
+fn actions(plan: Plan) -> Vec<Action> {
+    assert_eq!(hosts(), plan.hosts());
+    assert_eq!(1, plan.manifests.len());
+    assert_eq!(1, plan.manifests[0].include.len());
+    plan.manifests[0].include[0].actions.clone()
+}
+
+mod stage_plan {
+    use super::*;
+
+    #[test]
+    fn adds_login_key_to_authorized_keys() {
+        let plan = stage_plan("sira", &hosts(), &new_keys(false)).unwrap();
+        assert_eq!(
+            vec![Action::LineInFile {
+                path: "/home/sira/.ssh/authorized_keys".to_string(),
+                line: "ssh-ed25519 LOGIN sira".to_string(),
+                pattern: None,
+                after: None,
+                indent: false,
+            }],
+            actions(plan),
+        );
+    }
+
+    #[test]
+    fn adds_action_key_to_allowed_signers() {
+        let plan = stage_plan("sira", &hosts(), &new_keys(true)).unwrap();
+        let actions = actions(plan);
+        assert_eq!(2, actions.len());
+        assert_eq!(
+            Action::LineInFile {
+                path: remote_allowed_signers_path().unwrap(),
+                line: "sira ssh-ed25519 ACTION".to_string(),
+                pattern: None,
+                after: None,
+                indent: false,
+            },
+            actions[1],
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_action_key() {
+        let mut keys = new_keys(true);
+        keys.action = Some("not a\nkey".to_string());
+        assert!(stage_plan("sira", &hosts(), &keys).is_err());
+    }
+}
+
+mod verify_plan {
+    use super::*;
+
+    #[test]
+    fn runs_no_op() {
+        assert_eq!(
+            vec![Action::Command(vec!["true".to_string()])],
+            actions(verify_plan(&hosts())),
+        );
+    }
+}
+
+mod retire_plan {
+    use super::*;
+
+    #[test]
+    fn replaces_authorized_keys() {
+        let plan = retire_plan("sira", &hosts(), "/tmp/authorized_keys", None).unwrap();
+        assert_eq!(
+            vec![Action::Upload {
+                from: "/tmp/authorized_keys".to_string(),
+                to: "/home/sira/.ssh/authorized_keys".to_string(),
+                user: "sira".to_string(),
+                group: "sira".to_string(),
+                permissions: Some("0644".to_string()),
+                overwrite: true,
+                encrypt: false,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: None,
+                xattrs: Default::default(),
+                capabilities: None,
+            }],
+            actions(plan),
+        );
+    }
+
+    #[test]
+    fn replaces_allowed_signers() {
+        let plan = retire_plan(
+            "sira",
+            &hosts(),
+            "/tmp/authorized_keys",
+            Some("/tmp/allowed_signers"),
+        )
+        .unwrap();
+        let actions = actions(plan);
+        assert_eq!(2, actions.len());
+        assert_eq!(
+            Action::Upload {
+                from: "/tmp/allowed_signers".to_string(),
+                to: remote_allowed_signers_path().unwrap(),
+                user: "root".to_string(),
+                group: "root".to_string(),
+                permissions: Some("0644".to_string()),
+                overwrite: true,
+                encrypt: false,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: None,
+                xattrs: Default::default(),
+                capabilities: None,
+            },
+            actions[1],
+        );
+    }
+}
+
+mod swap_key_pair {
+    use super::*;
+
+    #[test]
+    fn retires_current_pair_and_installs_staged_pair() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("sira");
+        for (path, contents) in [
+            (key.clone(), "old"),
+            (public_key(&key), "old.pub"),
+            (staged(&key), "new"),
+            (public_key(staged(&key)), "new.pub"),
+        ] {
+            fs::write(path, contents).unwrap();
+        }
+
+        swap_key_pair(&key).unwrap();
+
+        assert_eq!("new", fs::read_to_string(&key).unwrap());
+        assert_eq!("new.pub", fs::read_to_string(public_key(&key)).unwrap());
+        assert_eq!("old", fs::read_to_string(retired(&key)).unwrap());
+        assert_eq!(
+            "old.pub",
+            fs::read_to_string(public_key(retired(&key))).unwrap(),
+        );
+        assert!(!staged(&key).exists());
+        assert!(!public_key(staged(&key)).exists());
+    }
+
+    #[test]
+    fn works_without_current_pair() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("action");
+        fs::write(staged(&key), "new").unwrap();
+        fs::write(public_key(staged(&key)), "new.pub").unwrap();
+
+        swap_key_pair(&key).unwrap();
+
+        assert_eq!("new", fs::read_to_string(&key).unwrap());
+        assert!(!retired(&key).exists());
+    }
+}
+
+mod stage_key_pair {
+    use super::*;
+
+    #[test]
+    fn reuses_existing_pair() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("sira.new");
+        fs::write(&key, "private").unwrap();
+        fs::write(public_key(&key), "public").unwrap();
+
+        stage_key_pair(&key, false).unwrap();
+        assert_eq!("private", fs::read_to_string(&key).unwrap());
+    }
+
+    #[test]
+    fn rejects_half_a_pair() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("sira.new");
+        fs::write(public_key(&key), "public").unwrap();
+        assert!(stage_key_pair(&key, false).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Runs plans again and again on a schedule, e.g. to enforce manifests every half hour, with
+//! `sira daemon` instead of a cron job.
+//!
+//! Each entry in the `schedule` table of `sira.toml` is a [ScheduledRun], named by its key. It
+//! says when to run as a cron expression, which manifest files to run, and optionally which
+//! profile's settings to use and whether to check for drift instead of changing anything, e.g.:
+//!
+//! ```toml
+//! [schedule.enforce]
+//! cron = "*/30 * * * *"
+//! manifests = ["/etc/sira/manifests/site.yaml"]
+//!
+//! [schedule.audit]
+//! cron = "0 6 * * mon-fri"
+//! manifests = ["/etc/sira/manifests/site.yaml"]
+//! profile = "prod"
+//! check = true
+//! ```
+//!
+//! A cron expression has five fields, separated by white space: the minute (0-59), the hour
+//! (0-23), the day of the month (1-31), the month (1-12 or `jan`-`dec`), and the day of the week
+//! (0-7 or `sun`-`sat`, where both 0 and 7 are Sunday). Each field is `*`, a value, a range such
+//! as `1-5`, or a list of these such as `1,15`, and `/` followed by a step takes every so many
+//! values, e.g. `*/15` or `9-17/2`. As in cron, if both day fields are restricted, i.e. neither
+//! starts with `*`, a day matches if either field does. Instead of five fields, a schedule may be
+//! one of `@yearly` (or `@annually`), `@monthly`, `@weekly`, `@daily` (or `@midnight`), and
+//! `@hourly`. Please see [Schedule].
+//!
+//! Times are in the control node's local time zone. Times that a change to daylight saving time
+//! skips never come due, and times that it repeats come due only once.
+
+use crate::config::Config;
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How far ahead [Schedule::next_after] looks before deciding that a schedule never comes due,
+/// e.g. `0 0 30 2 *`. Every valid date recurs within this many years.
+const LOOKAHEAD_YEARS: i64 = 8;
+
+/// A run that `sira daemon` starts on a schedule. Please see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledRun {
+    /// When to start the run.
+    pub cron: Schedule,
+
+    /// The manifest files to run, which Sira loads afresh for each run.
+    pub manifests: Vec<String>,
+
+    /// The profile in `sira.toml` whose settings the run uses, if any. Please see
+    /// [Config::select].
+    pub profile: Option<String>,
+
+    /// Whether to check each host for drift instead of changing anything. Defaults to `false`.
+    /// Please see [drift](super::drift).
+    pub check: Option<bool>,
+}
+
+impl ScheduledRun {
+    /// Returns an error if the run can't ever start, e.g. because it has no manifest files.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.manifests.is_empty() {
+            bail!("manifests must list at least one manifest file");
+        }
+        Ok(())
+    }
+
+    /// Returns the settings with which to run, i.e. those of [ScheduledRun::profile] in `config`,
+    /// if any, or else the settings outside of profiles.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such profile.
+    pub fn config(&self, config: &Config) -> anyhow::Result<Config> {
+        config.clone().select(self.profile.as_deref())
+    }
+}
+
+/// When a [ScheduledRun] is due, as parsed from a cron expression. Please see the
+/// [module documentation](self) for the syntax.
+///
+/// Each field is a set of the values that it matches, with bit `n` set if it matches `n`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    /// The expression as written, e.g. for error messages.
+    expression: String,
+
+    /// The minutes of the hour.
+    minutes: u64,
+
+    /// The hours of the day.
+    hours: u64,
+
+    /// The days of the month.
+    days: u64,
+
+    /// The months of the year, from 1 to 12.
+    months: u64,
+
+    /// The days of the week, from 0 (Sunday) to 6.
+    weekdays: u64,
+
+    /// Whether the day-of-the-month field restricts the days, i.e. doesn't start with `*`.
+    days_restricted: bool,
+
+    /// Whether the day-of-the-week field restricts the days, i.e. doesn't start with `*`.
+    weekdays_restricted: bool,
+}
+
+impl Schedule {
+    /// Returns the first time after `after`, to the minute, at which the schedule is due, or
+    /// [None] if it never is, e.g. `0 0 30 2 *`.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)?;
+        let mut time = start + TimeDelta::minutes(1);
+        let limit = start + TimeDelta::days(LOOKAHEAD_YEARS * 366);
+        while time < limit {
+            if !self.matches_date(time.date()) {
+                time = midnight(time.date().succ_opt()?);
+            } else if !contains(self.hours, time.hour()) {
+                time = time.with_minute(0)? + TimeDelta::hours(1);
+            } else if !contains(self.minutes, time.minute()) {
+                time += TimeDelta::minutes(1);
+            } else {
+                // Daylight saving time may skip this time or repeat it, even before `after`.
+                match timezone.from_local_datetime(&time).earliest() {
+                    Some(due) if due > *after => return Some(due),
+                    _ => time += TimeDelta::minutes(1),
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns whether the schedule is due on `date` at all.
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let day = contains(self.days, date.day());
+        let weekday = contains(self.weekdays, date.weekday().num_days_from_sunday());
+        contains(self.months, date.month())
+            && match (self.days_restricted, self.weekdays_restricted) {
+                (true, true) => day || weekday,
+                _ => day && weekday,
+            }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let expanded = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other if other.starts_with('@') => bail!("unknown schedule {other:?}"),
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            bail!(
+                "invalid schedule {s:?}: expected five fields (minute, hour, day of month, month, \
+                day of week), e.g. \"*/30 * * * *\""
+            );
+        };
+        let parse = |field: &str, name: &str, min: u32, max: u32, names: &[&str]| {
+            parse_field(field, min, max, names)
+                .with_context(|| format!("invalid {name} {field:?} in schedule {s:?}"))
+        };
+        let weekdays_mask = parse(weekdays, "day of week", 0, 7, &WEEKDAYS)?;
+        Ok(Schedule {
+            expression: s.to_string(),
+            minutes: parse(minutes, "minute", 0, 59, &[])?,
+            hours: parse(hours, "hour", 0, 23, &[])?,
+            days: parse(days, "day of month", 1, 31, &[])?,
+            months: parse(months, "month", 1, 12, &MONTHS)?,
+            // Both 0 and 7 are Sunday.
+            weekdays: (weekdays_mask | weekdays_mask >> 7) & 0x7f,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(schedule: Schedule) -> Self {
+        schedule.expression
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// The names of the months, from 1.
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// The names of the days of the week, from 0.
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parses one field of a cron expression, whose values range from `min` to `max`, into a set of
+/// values. `names`, if any, name the values from `min` on.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<u64> {
+    let value = |s: &str| -> anyhow::Result<u32> {
+        let value = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(index) => min + index as u32,
+            None => s
+                .parse()
+                .map_err(|_| anyhow!("expected a number from {min} to {max}, not {s:?}"))?,
+        };
+        match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => bail!("{value} is out of range; expected {min} to {max}"),
+        }
+    };
+
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse() {
+                Ok(step) if step > 0 => (range, step),
+                _ => bail!("expected a positive step, not {step:?}"),
+            },
+            None => (item, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // A single value with a step runs to the end, as in `5/15`.
+            None if item.contains('/') => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+        if first > last {
+            bail!("range {range:?} runs backwards");
+        }
+        for value in (first..=last).step_by(step) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Returns whether `set` contains `value`.
+fn contains(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Returns the start of `date`.
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0)
+        .expect("midnight should be a valid time")
+}
+
+#[cfg(test)]
+mod test;
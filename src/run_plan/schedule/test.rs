@@ -0,0 +1,217 @@
+use super::*;
+use chrono::Utc;
+
+/// Parses a time in UTC, e.g. `2024-01-01 12:00`.
+fn at(time: &str) -> DateTime<Utc> {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")
+        .unwrap()
+        .and_utc()
+}
+
+/// Returns when `schedule` is next due after `time`, both in the format of [at].
+fn next(schedule: &str, time: &str) -> Option<String> {
+    let schedule: Schedule = schedule.parse().unwrap();
+    let next = schedule.next_after(&at(time))?;
+    Some(next.format("%Y-%m-%d %H:%M").to_string())
+}
+
+mod schedule {
+    use super::*;
+
+    #[test]
+    fn finds_next_minute() {
+        assert_eq!(
+            Some("2024-01-01 12:01".to_string()),
+            next("* * * * *", "2024-01-01 12:00")
+        );
+    }
+
+    #[test]
+    fn is_never_due_at_the_same_time() {
+        let schedule: Schedule = "0 12 * * *".parse().unwrap();
+        let noon = at("2024-01-01 12:00") + TimeDelta::seconds(30);
+        assert_eq!(Some(at("2024-01-02 12:00")), schedule.next_after(&noon),);
+    }
+
+    #[test]
+    fn steps_through_ranges() {
+        assert_eq!(
+            Some("2024-01-01 12:30".to_string()),
+            next("*/15 * * * *", "2024-01-01 12:16")
+        );
+        assert_eq!(
+            Some("2024-01-01 11:05".to_string()),
+            next("5 9-17/2 * * *", "2024-01-01 09:05")
+        );
+        assert_eq!(
+            Some("2024-01-01 12:50".to_string()),
+            next("5/15 * * * *", "2024-01-01 12:36")
+        );
+    }
+
+    #[test]
+    fn follows_lists() {
+        assert_eq!(
+            Some("2024-01-15 00:00".to_string()),
+            next("0 0 1,15 * *", "2024-01-01 00:00")
+        );
+    }
+
+    #[test]
+    fn accepts_names() {
+        // 2024-01-01 is a Monday.
+        assert_eq!(
+            Some("2024-01-03 06:00".to_string()),
+            next("0 6 * * WED-fri", "2024-01-01 12:00")
+        );
+        assert_eq!(
+            Some("2024-03-01 00:00".to_string()),
+            next("0 0 1 mar *", "2024-01-01 12:00")
+        );
+    }
+
+    #[test]
+    fn treats_seven_as_sunday() {
+        assert_eq!(
+            Some("2024-01-07 00:00".to_string()),
+            next("0 0 * * 7", "2024-01-01 12:00")
+        );
+    }
+
+    #[test]
+    fn matches_either_day_field_if_both_are_restricted() {
+        // The 10th, or any Monday.
+        assert_eq!(
+            Some("2024-01-08 00:00".to_string()),
+            next("0 0 10 * mon", "2024-01-02 00:00")
+        );
+        // With any day of the month, only the day of the week counts.
+        assert_eq!(
+            Some("2024-02-05 00:00".to_string()),
+            next("0 0 * * mon", "2024-02-01 00:00")
+        );
+    }
+
+    #[test]
+    fn expands_shortcuts() {
+        assert_eq!(
+            Some("2025-01-01 00:00".to_string()),
+            next("@yearly", "2024-01-01 12:00")
+        );
+        assert_eq!(
+            Some("2024-01-07 00:00".to_string()),
+            next("@weekly", "2024-01-01 12:00")
+        );
+        assert_eq!(
+            Some("2024-01-01 13:00".to_string()),
+            next("@hourly", "2024-01-01 12:00")
+        );
+    }
+
+    #[test]
+    fn finds_leap_days() {
+        assert_eq!(
+            Some("2028-02-29 00:00".to_string()),
+            next("0 0 29 2 *", "2024-03-01 00:00")
+        );
+    }
+
+    #[test]
+    fn is_never_due_on_impossible_dates() {
+        assert_eq!(None, next("0 0 30 2 *", "2024-01-01 00:00"));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in [
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "x * * * *",
+            "@sometimes",
+        ] {
+            assert!(
+                expression.parse::<Schedule>().is_err(),
+                "{expression:?} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn names_field_in_errors() {
+        let error = "* 24 * * *".parse::<Schedule>().unwrap_err();
+        let message = format!("{error:#}");
+        assert!(message.contains("invalid hour \"24\""), "{message}");
+        assert!(message.contains("out of range"), "{message}");
+    }
+
+    #[test]
+    fn round_trips_as_written() {
+        let schedule: Schedule = "*/30  * * * *".parse().unwrap();
+        assert_eq!("*/30  * * * *", schedule.to_string());
+        assert_eq!("*/30  * * * *", String::from(schedule));
+    }
+}
+
+mod scheduled_run {
+    use super::*;
+
+    #[test]
+    fn parses_from_config() {
+        let config = Config::from_toml(
+            r#"
+            [schedule.audit]
+            cron = "0 6 * * mon-fri"
+            manifests = ["site.yaml"]
+            profile = "prod"
+            check = true
+            "#,
+        )
+        .unwrap();
+        let audit = &config.schedule["audit"];
+        assert_eq!("0 6 * * mon-fri", audit.cron.to_string());
+        assert_eq!(vec!["site.yaml".to_string()], audit.manifests);
+        assert_eq!(Some("prod"), audit.profile.as_deref());
+        assert_eq!(Some(true), audit.check);
+    }
+
+    #[test]
+    fn rejects_invalid_runs() {
+        let no_manifests = "[schedule.a]\ncron = \"@daily\"\nmanifests = []\n";
+        assert!(Config::from_toml(no_manifests).is_err());
+        let bad_cron = "[schedule.a]\ncron = \"daily\"\nmanifests = [\"site.yaml\"]\n";
+        assert!(Config::from_toml(bad_cron).is_err());
+        let in_profile = "[profile.prod.schedule.a]\ncron = \"@daily\"\nmanifests = [\"a\"]\n";
+        assert!(Config::from_toml(in_profile).is_err());
+    }
+
+    #[test]
+    fn uses_profile_settings() {
+        let config = Config::from_toml(
+            r#"
+            concurrency = 10
+
+            [profile.prod]
+            concurrency = 2
+
+            [schedule.enforce]
+            cron = "@hourly"
+            manifests = ["site.yaml"]
+            profile = "prod"
+            "#,
+        )
+        .unwrap();
+        let run = &config.schedule["enforce"];
+        assert_eq!(Some(2), run.config(&config).unwrap().concurrency);
+
+        let mut unknown = run.clone();
+        unknown.profile = Some("staging".to_string());
+        assert!(unknown.config(&config).is_err());
+    }
+}
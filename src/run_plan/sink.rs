@@ -0,0 +1,147 @@
+//! Sends a [Record] of every action to destinations that Sira doesn't know about, e.g. a database
+//! or an OpenTelemetry collector, for programs that use Sira as a library.
+//!
+//! To add a destination, implement [LogSink] and add it to
+//! [RunOptions::log_sinks](super::RunOptions::log_sinks). Each sink receives the same redacted
+//! records that [JsonLog](super::json_log::JsonLog) writes, as each action finishes, and is told
+//! when the run is over so that it can flush anything it has buffered.
+//!
+//! For finer-grained events, e.g. to know when each action starts, implement [Report] instead.
+
+use super::json_log::Record;
+use super::report::{Report, RunningActions};
+use crate::core::Action;
+use async_trait::async_trait;
+use std::fmt;
+use std::io;
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use tokio::task;
+
+/// A destination for a [Record] of every action that runs. Please see the
+/// [module documentation](self).
+///
+/// Sira calls these methods from the threads that run each host's plan, so a sink may be called
+/// for several hosts at once. Both methods may block.
+pub trait LogSink: Send + Sync {
+    /// Logs the outcome of an action.
+    ///
+    /// An error stops the plan on the record's host, just as if the action had failed.
+    fn log(&self, record: &Record) -> anyhow::Result<()>;
+
+    /// Finishes logging, e.g. by flushing buffered records, once every host has either finished or
+    /// stopped.
+    ///
+    /// Does nothing by default.
+    fn finished(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// A list of [LogSink]s, as given in [RunOptions](super::RunOptions).
+///
+/// Clones share the same sinks. Two lists are equal if they hold the same sinks in the same order.
+#[derive(Clone, Default)]
+pub struct LogSinks(Vec<Arc<dyn LogSink>>);
+
+impl LogSinks {
+    /// Creates an empty list of sinks.
+    pub fn new() -> Self {
+        LogSinks::default()
+    }
+
+    /// Adds a sink to the end of the list.
+    pub fn push(&mut self, sink: impl LogSink + 'static) {
+        self.0.push(Arc::new(sink));
+    }
+
+    /// Returns the number of sinks in the list.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for LogSinks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LogSinks({} sinks)", self.0.len())
+    }
+}
+
+impl PartialEq for LogSinks {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for LogSinks {}
+
+/// Builds a [Record] for every action that runs and passes it to each of a list of [LogSinks].
+///
+/// Clones share the same sinks and the same record of what's in flight on each host.
+#[derive(Clone, Debug)]
+pub struct SinkLogger {
+    /// Where to send each record.
+    sinks: LogSinks,
+
+    /// The source and start time of each action in flight on each host.
+    running: Arc<Mutex<RunningActions>>,
+}
+
+impl SinkLogger {
+    /// Creates a [SinkLogger] that sends records to `sinks`.
+    pub fn new(sinks: LogSinks) -> Self {
+        SinkLogger {
+            sinks,
+            running: Arc::default(),
+        }
+    }
+
+    /// Passes a record of the outcome of the oldest action in flight on `host` to every sink,
+    /// stopping at the first error.
+    pub(crate) fn log(&self, host: &str, action: &Action, output: &Output) -> anyhow::Result<()> {
+        let running = self.running.lock().unwrap().finish(host);
+        let (manifest, task, duration) = match running {
+            Some(r) => (r.manifest, r.task, r.started.elapsed()),
+            None => Default::default(),
+        };
+        let record = Record::new(host, manifest, task, action, output, duration);
+        self.sinks.0.iter().try_for_each(|sink| sink.log(&record))
+    }
+
+    /// Tells every sink that the run is over. Every sink is told, even if some return errors;
+    /// returns the first error.
+    pub(crate) fn finish(&self) -> anyhow::Result<()> {
+        let results: Vec<_> = self.sinks.0.iter().map(|sink| sink.finished()).collect();
+        results.into_iter().collect()
+    }
+}
+
+#[async_trait]
+impl Report for SinkLogger {
+    async fn action_source(&mut self, host: &str, manifest: &str, task: &str) -> io::Result<()> {
+        self.running.lock().unwrap().source(host, manifest, task);
+        Ok(())
+    }
+
+    async fn starting(&mut self, host: &str, _action: &Action) -> io::Result<()> {
+        self.running.lock().unwrap().start(host);
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        task::block_in_place(|| self.log(host, action, output))
+            .map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+
+    async fn finished(&mut self) -> io::Result<()> {
+        task::block_in_place(|| self.finish()).map_err(|e| io::Error::other(format!("{e:#}")))
+    }
+}
+
+#[cfg(test)]
+mod test;
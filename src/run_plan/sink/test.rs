@@ -0,0 +1,137 @@
+use super::*;
+use anyhow::bail;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+// A LogSink that keeps every record it receives, and can be told to fail.
+#[derive(Debug, Default)]
+struct TestSink {
+    records: Mutex<Vec<Record>>,
+    finished: Mutex<bool>,
+    fail: bool,
+}
+
+impl LogSink for TestSink {
+    fn log(&self, record: &Record) -> anyhow::Result<()> {
+        if self.fail {
+            bail!("sink failed");
+        }
+        self.records.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+
+    fn finished(&self) -> anyhow::Result<()> {
+        *self.finished.lock().unwrap() = true;
+        match self.fail {
+            true => bail!("sink failed to finish"),
+            false => Ok(()),
+        }
+    }
+}
+
+fn output(code: i32) -> Output {
+    Output {
+        status: ExitStatus::from_raw(code << 8),
+        stdout: b"out\n".to_vec(),
+        stderr: vec![],
+    }
+}
+
+fn action(command: &str) -> Action {
+    Action::Command(vec![command.to_string()])
+}
+
+// Returns a SinkLogger for `sinks`, plus handles that tests can examine afterward.
+fn logger(sinks: Vec<TestSink>) -> (SinkLogger, Vec<Arc<TestSink>>) {
+    let sinks: Vec<Arc<TestSink>> = sinks.into_iter().map(Arc::new).collect();
+    let list = LogSinks(
+        sinks
+            .iter()
+            .map(|sink| Arc::clone(sink) as Arc<dyn LogSink>)
+            .collect(),
+    );
+    (SinkLogger::new(list), sinks)
+}
+
+mod log_sinks {
+    use super::*;
+
+    #[test]
+    fn equal_only_with_same_sinks() {
+        let mut sinks = LogSinks::new();
+        sinks.push(TestSink::default());
+        assert_eq!(sinks, sinks.clone());
+        assert_eq!(LogSinks::new(), LogSinks::new());
+
+        let mut other = LogSinks::new();
+        other.push(TestSink::default());
+        assert_ne!(sinks, other);
+        assert_ne!(sinks, LogSinks::new());
+    }
+}
+
+mod sink_logger {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_records_to_every_sink() {
+        let (mut logger, sinks) = logger(vec![TestSink::default(), TestSink::default()]);
+
+        logger
+            .action_source("alpha", "Web servers", "Restart app")
+            .await
+            .unwrap();
+        logger.starting("alpha", &action("true")).await.unwrap();
+        logger
+            .report("alpha", &action("true"), &output(0))
+            .await
+            .unwrap();
+        logger
+            .report("bravo", &action("false"), &output(1))
+            .await
+            .unwrap();
+
+        for sink in sinks {
+            let records = sink.records.lock().unwrap();
+            assert_eq!(2, records.len());
+
+            assert_eq!("alpha", records[0].host);
+            assert_eq!("Web servers", records[0].manifest);
+            assert_eq!("Restart app", records[0].task);
+            assert_eq!(action("true"), records[0].action);
+            assert_eq!("success", records[0].result);
+            assert_eq!("out\n", records[0].stdout);
+
+            assert_eq!("bravo", records[1].host);
+            assert_eq!("", records[1].task);
+            assert_eq!("failure", records[1].result);
+            assert_eq!(Some(1), records[1].exit_code);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn returns_sink_errors() {
+        let failing = TestSink {
+            fail: true,
+            ..Default::default()
+        };
+        let (mut logger, _) = logger(vec![failing]);
+        let error = logger
+            .report("alpha", &action("true"), &output(0))
+            .await
+            .unwrap_err();
+        assert_eq!("sink failed", error.to_string());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn finishes_every_sink_despite_errors() {
+        let failing = TestSink {
+            fail: true,
+            ..Default::default()
+        };
+        let (mut logger, sinks) = logger(vec![failing, TestSink::default()]);
+        let error = logger.finished().await.unwrap_err();
+        assert_eq!("sink failed to finish", error.to_string());
+        assert!(sinks.iter().all(|sink| *sink.finished.lock().unwrap()));
+    }
+}
@@ -0,0 +1,113 @@
+//! Passes extra OpenSSH options, e.g. `ProxyJump`, to the connections that Sira makes.
+//!
+//! Please see [HostConfig::ssh_options](crate::config::HostConfig::ssh_options). OpenSSH reads
+//! most options only from its command line or a configuration file, and [openssh] only passes a
+//! fixed set of them on the command line. So, when a host needs extra options, Sira writes a
+//! temporary configuration file that sets them and then includes the user's and the system's usual
+//! configuration files, and connects with that file instead. Since OpenSSH uses the first value it
+//! finds for each option, the extra options take precedence over the usual files, but not over the
+//! settings that Sira passes on the command line, e.g. the port.
+//!
+//! The one exception is `StrictHostKeyChecking`, which [openssh] always passes on the command
+//! line. Please see [known_hosts_check].
+
+use crate::client;
+use anyhow::{bail, Context};
+use openssh::KnownHosts;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The option that [known_hosts_check] handles instead of the configuration file.
+pub const STRICT_HOST_KEY_CHECKING: &str = "StrictHostKeyChecking";
+
+/// The system-wide OpenSSH client configuration file.
+pub const SYSTEM_CONFIG: &str = "/etc/ssh/ssh_config";
+
+/// A temporary OpenSSH configuration file, which is removed when dropped.
+#[derive(Debug)]
+pub struct SshConfig {
+    path: PathBuf,
+}
+
+impl SshConfig {
+    /// Writes a temporary configuration file that sets `options`, or returns [None] if there are
+    /// no options to set.
+    pub fn write(options: &BTreeMap<String, String>) -> anyhow::Result<Option<SshConfig>> {
+        if rendered_options(options).next().is_none() {
+            return Ok(None);
+        }
+
+        let user_config = home::home_dir().map(|home| home.join(".ssh").join("config"));
+        let includes: Vec<PathBuf> = user_config
+            .into_iter()
+            .chain([PathBuf::from(SYSTEM_CONFIG)])
+            .filter(|path| path.is_file())
+            .collect();
+
+        let (mut file, path) = client::mktemp()?;
+        let config = SshConfig {
+            path: PathBuf::from(path),
+        };
+        file.write_all(render(options, &includes).as_bytes())
+            .context("could not write temporary SSH configuration file")?;
+        Ok(Some(config))
+    }
+
+    /// Returns the path to the configuration file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SshConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Returns the contents of a configuration file that sets `options` and then includes each of
+/// `includes`.
+pub(crate) fn render(options: &BTreeMap<String, String>, includes: &[PathBuf]) -> String {
+    let mut config = String::from("# Written by Sira for a single connection.\n");
+    for (name, value) in rendered_options(options) {
+        config.push_str(&format!("{name} {value}\n"));
+    }
+    for include in includes {
+        config.push_str(&format!("Include \"{}\"\n", include.display()));
+    }
+    config
+}
+
+/// Returns the [KnownHosts] check that `options` ask for with `StrictHostKeyChecking`, if any.
+///
+/// # Errors
+///
+/// Returns an error if the value is `ask`, since Sira can't answer OpenSSH's prompts, or is not a
+/// value that OpenSSH accepts.
+pub fn known_hosts_check(options: &BTreeMap<String, String>) -> anyhow::Result<Option<KnownHosts>> {
+    let Some((_, value)) = options
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(STRICT_HOST_KEY_CHECKING))
+    else {
+        return Ok(None);
+    };
+    Ok(Some(match value.trim().to_ascii_lowercase().as_str() {
+        "yes" => KnownHosts::Strict,
+        "accept-new" => KnownHosts::Add,
+        "no" | "off" => KnownHosts::Accept,
+        "ask" => bail!("{STRICT_HOST_KEY_CHECKING} ask is not supported, since Sira can't answer"),
+        _ => bail!("invalid value for {STRICT_HOST_KEY_CHECKING}: {value:?}"),
+    }))
+}
+
+/// Returns the options that belong in the configuration file.
+fn rendered_options(options: &BTreeMap<String, String>) -> impl Iterator<Item = (&str, &str)> {
+    options
+        .iter()
+        .filter(|(name, _)| !name.eq_ignore_ascii_case(STRICT_HOST_KEY_CHECKING))
+        .map(|(name, value)| (name.as_str(), value.trim()))
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,93 @@
+use super::*;
+
+fn options(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+mod render {
+    use super::*;
+
+    #[test]
+    fn sets_options_before_includes() {
+        let options = options(&[("ProxyJump", "bastion"), ("ConnectionAttempts", "3")]);
+        let includes = [
+            PathBuf::from("/home/alice/.ssh/config"),
+            PathBuf::from(SYSTEM_CONFIG),
+        ];
+        assert_eq!(
+            "# Written by Sira for a single connection.\n\
+            ConnectionAttempts 3\n\
+            ProxyJump bastion\n\
+            Include \"/home/alice/.ssh/config\"\n\
+            Include \"/etc/ssh/ssh_config\"\n",
+            render(&options, &includes),
+        );
+    }
+
+    #[test]
+    fn leaves_out_strict_host_key_checking() {
+        let options = options(&[("stricthostkeychecking", "yes"), ("ProxyJump", "bastion")]);
+        assert!(!render(&options, &[])
+            .to_lowercase()
+            .contains("stricthostkeychecking"));
+    }
+}
+
+mod write {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn writes_nothing_without_options() {
+        assert!(SshConfig::write(&BTreeMap::new()).unwrap().is_none());
+        let options = options(&[(STRICT_HOST_KEY_CHECKING, "yes")]);
+        assert!(SshConfig::write(&options).unwrap().is_none());
+    }
+
+    #[test]
+    fn removes_file_when_dropped() {
+        let config = SshConfig::write(&options(&[("ProxyJump", "bastion")]))
+            .unwrap()
+            .unwrap();
+        let path = config.path().to_path_buf();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("ProxyJump bastion\n"));
+
+        drop(config);
+        assert!(!path.exists());
+    }
+}
+
+mod known_hosts_check {
+    use super::*;
+
+    fn check(value: &str) -> Option<KnownHosts> {
+        known_hosts_check(&options(&[(STRICT_HOST_KEY_CHECKING, value)])).unwrap()
+    }
+
+    #[test]
+    fn maps_values() {
+        assert!(matches!(check("yes"), Some(KnownHosts::Strict)));
+        assert!(matches!(check("accept-new"), Some(KnownHosts::Add)));
+        assert!(matches!(check("no"), Some(KnownHosts::Accept)));
+        assert!(matches!(check("Off"), Some(KnownHosts::Accept)));
+    }
+
+    #[test]
+    fn returns_none_if_unset() {
+        let options = options(&[("ProxyJump", "bastion")]);
+        assert!(known_hosts_check(&options).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_ask_and_invalid_values() {
+        for value in ["ask", "maybe"] {
+            let options = options(&[(STRICT_HOST_KEY_CHECKING, value)]);
+            assert!(known_hosts_check(&options).is_err());
+        }
+    }
+}
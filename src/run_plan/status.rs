@@ -0,0 +1,223 @@
+//! Lets an interactive frontend ask how a run is going, e.g. to draw a status panel, without
+//! reconstructing it from every [Report].
+//!
+//! Pass a [StatusHandle] in [RunOptions::status], keep a clone of it, start the run, and call
+//! [StatusHandle::query] whenever the frontend redraws:
+//!
+//! ```no_run
+//! use sira::core::Plan;
+//! use sira::run_plan::status::StatusHandle;
+//! use sira::run_plan::{run_plan_with, RunOptions};
+//!
+//! # async fn example(plan: Plan) {
+//! let status = StatusHandle::new();
+//! let options = RunOptions {
+//!     status: Some(status.clone()),
+//!     ..Default::default()
+//! };
+//! let run = tokio::spawn(run_plan_with(plan, options));
+//!
+//! // Later, e.g. on every frame:
+//! let now = status.query();
+//! println!(
+//!     "{} waiting, {} running, {} actions left",
+//!     now.pending().len(),
+//!     now.active().len(),
+//!     now.actions_remaining(),
+//! );
+//! # }
+//! ```
+//!
+//! Each host in the run starts out [HostState::Pending], becomes [HostState::Active] once it gets
+//! its turn (please see [RunOptions::concurrency]) and starts connecting, and ends up
+//! [HostState::Succeeded] or [HostState::Failed]. If the run is cancelled (please see
+//! [RunOptions::cancel]) or aborted (please see [RunOptions::on_failure]) while a host is still
+//! pending, Sira never connects to it, so it ends up
+//! [HostState::Ignored] instead. Each host also counts the actions, including checks under
+//! `verify`, that it hasn't finished yet. A host that fails keeps the count of actions that it
+//! never ran.
+//!
+//! A query is a snapshot: it doesn't change as the run goes on. A new run resets the status, so use
+//! a new handle, e.g. from [StatusHandle::new], for each run that should be followed separately.
+//!
+//! [Report]: super::report::Report
+//! [RunOptions::cancel]: super::RunOptions::cancel
+//! [RunOptions::concurrency]: super::RunOptions::concurrency
+//! [RunOptions::on_failure]: super::RunOptions::on_failure
+//! [RunOptions::status]: super::RunOptions::status
+
+use super::report::Report;
+use crate::core::Action;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+
+/// A handle with which to query a run's status. Please see the [module documentation](self).
+///
+/// Clones share the same state, so querying any clone shows the same run.
+#[derive(Clone, Debug, Default)]
+pub struct StatusHandle {
+    status: Arc<Mutex<RunStatus>>,
+}
+
+impl StatusHandle {
+    /// Creates a [StatusHandle] for a run that hasn't started, with no hosts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the status of the run as of now.
+    pub fn query(&self) -> RunStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Starts following a new run on `hosts`, each with the number of actions that it will run,
+    /// forgetting any earlier run.
+    pub(crate) fn start(&self, hosts: impl IntoIterator<Item = (String, usize)>) {
+        let hosts = hosts
+            .into_iter()
+            .map(|(host, actions_remaining)| {
+                let status = HostStatus {
+                    state: HostState::Pending,
+                    actions_remaining,
+                };
+                (host, status)
+            })
+            .collect();
+        *self.status.lock().unwrap() = RunStatus { hosts };
+    }
+
+    /// Records that `host` got its turn and is connecting.
+    pub(crate) fn activate(&self, host: &str) {
+        self.update(host, |status| status.state = HostState::Active);
+    }
+
+    /// Records that `host` stopped, either successfully or not. A host that fails before it got
+    /// its turn was never run, so it's [HostState::Ignored].
+    pub(crate) fn finish(&self, host: &str, succeeded: bool) {
+        self.update(host, |status| {
+            status.state = match (succeeded, status.state) {
+                (true, _) => HostState::Succeeded,
+                (false, HostState::Pending) => HostState::Ignored,
+                (false, _) => HostState::Failed,
+            }
+        });
+    }
+
+    /// Applies `update` to `host`'s status, if the run includes `host`.
+    fn update(&self, host: &str, update: impl FnOnce(&mut HostStatus)) {
+        if let Some(status) = self.status.lock().unwrap().hosts.get_mut(host) {
+            update(status);
+        }
+    }
+}
+
+/// Handles are equal if they're clones of each other, so that they show the same run.
+impl PartialEq for StatusHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.status, &other.status)
+    }
+}
+
+impl Eq for StatusHandle {}
+
+/// Counts each action that a host finishes, since Sira reports every one of them, whatever the
+/// outcome, exactly once.
+#[async_trait]
+impl Report for StatusHandle {
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, _action: &Action, _output: &Output) -> io::Result<()> {
+        self.update(host, |status| {
+            status.actions_remaining = status.actions_remaining.saturating_sub(1)
+        });
+        Ok(())
+    }
+}
+
+/// The status of every host in a run, as returned by [StatusHandle::query].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunStatus {
+    /// Each host in the run and its status, in alphabetical order.
+    pub hosts: BTreeMap<String, HostStatus>,
+}
+
+impl RunStatus {
+    /// Returns the hosts that are waiting for their turn, in alphabetical order.
+    pub fn pending(&self) -> Vec<&str> {
+        self.in_state(HostState::Pending)
+    }
+
+    /// Returns the hosts that are connecting or running actions, in alphabetical order.
+    pub fn active(&self) -> Vec<&str> {
+        self.in_state(HostState::Active)
+    }
+
+    /// Returns the hosts that Sira never ran because the run was cancelled or aborted first, in
+    /// alphabetical order.
+    pub fn ignored(&self) -> Vec<&str> {
+        self.in_state(HostState::Ignored)
+    }
+
+    /// Returns how many actions, including checks, the hosts that haven't stopped have yet to
+    /// finish.
+    pub fn actions_remaining(&self) -> usize {
+        self.hosts
+            .values()
+            .filter(|status| matches!(status.state, HostState::Pending | HostState::Active))
+            .map(|status| status.actions_remaining)
+            .sum()
+    }
+
+    /// Returns whether every host has stopped, or the run hasn't started.
+    pub fn is_done(&self) -> bool {
+        self.pending().is_empty() && self.active().is_empty()
+    }
+
+    /// Returns the hosts in `state`, in alphabetical order.
+    fn in_state(&self, state: HostState) -> Vec<&str> {
+        self.hosts
+            .iter()
+            .filter(|(_, status)| status.state == state)
+            .map(|(host, _)| host.as_str())
+            .collect()
+    }
+}
+
+/// The status of one host in a run. Please see [RunStatus].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostStatus {
+    /// Where the host is in the run.
+    pub state: HostState,
+
+    /// How many of the host's actions, including checks under `verify`, haven't finished.
+    pub actions_remaining: usize,
+}
+
+/// Where a host is in a run. Please see the [module documentation](self).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostState {
+    /// Waiting for its turn.
+    Pending,
+
+    /// Connecting or running actions.
+    Active,
+
+    /// Ran every action successfully.
+    Succeeded,
+
+    /// Stopped early, e.g. because an action failed or the run was cancelled.
+    Failed,
+
+    /// Never ran, because the run was cancelled or aborted before the host's turn.
+    Ignored,
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,92 @@
+use super::*;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn started() -> StatusHandle {
+    let handle = StatusHandle::new();
+    handle.start([("web1".to_string(), 2), ("web2".to_string(), 3)]);
+    handle
+}
+
+mod status_handle {
+    use super::*;
+
+    #[test]
+    fn starts_with_every_host_pending() {
+        let now = started().query();
+        assert_eq!(vec!["web1", "web2"], now.pending());
+        assert!(now.active().is_empty());
+        assert_eq!(5, now.actions_remaining());
+        assert!(!now.is_done());
+    }
+
+    #[test]
+    fn starts_empty_before_run() {
+        let now = StatusHandle::new().query();
+        assert!(now.hosts.is_empty());
+        assert!(now.is_done());
+    }
+
+    #[tokio::test]
+    async fn counts_finished_actions() {
+        let mut handle = started();
+        handle.activate("web1");
+        let action = Action::Command(vec!["true".to_string()]);
+        let output = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: vec![],
+            stderr: vec![],
+        };
+        handle.report("web1", &action, &output).await.unwrap();
+
+        let now = handle.query();
+        assert_eq!(vec!["web1"], now.active());
+        assert_eq!(
+            HostStatus {
+                state: HostState::Active,
+                actions_remaining: 1,
+            },
+            now.hosts["web1"],
+        );
+        assert_eq!(4, now.actions_remaining());
+    }
+
+    #[test]
+    fn ignores_hosts_that_stop_before_their_turn() {
+        let handle = started();
+        handle.activate("web1");
+        handle.finish("web1", false);
+        handle.finish("web2", false);
+
+        let now = handle.query();
+        assert_eq!(HostState::Failed, now.hosts["web1"].state);
+        assert_eq!(vec!["web2"], now.ignored());
+        assert_eq!(0, now.actions_remaining());
+        assert!(now.is_done());
+    }
+
+    #[test]
+    fn ignores_unknown_hosts() {
+        let handle = started();
+        handle.activate("db1");
+        assert_eq!(started().query(), handle.query());
+    }
+
+    #[test]
+    fn shares_state_with_clones() {
+        let handle = started();
+        let clone = handle.clone();
+        clone.finish("web1", true);
+        assert_eq!(HostState::Succeeded, handle.query().hosts["web1"].state);
+        assert_eq!(handle, clone);
+        assert_ne!(handle, StatusHandle::new());
+    }
+
+    #[test]
+    fn queries_are_snapshots() {
+        let handle = started();
+        let before = handle.query();
+        handle.activate("web1");
+        assert_eq!(vec!["web1", "web2"], before.pending());
+    }
+}
@@ -0,0 +1,119 @@
+//! Sends reports to the control node's system log, e.g. syslog or the systemd journal.
+//!
+//! Sira hands each message to the `logger` utility, which writes to the local syslog socket. On
+//! systems that run systemd, the journal reads from that same socket, so messages appear in
+//! `journalctl` as well as in any syslog files. Messages are tagged `sira` and use the `user`
+//! facility, e.g.:
+//!
+//! ```text
+//! journalctl -t sira
+//! ```
+
+use super::report::title;
+use crate::core::Action;
+use async_trait::async_trait;
+use std::io;
+use std::process::{Command, Output};
+use tokio::task;
+
+use super::report::Report;
+
+/// The tag (i.e. program name) attached to every message.
+pub const TAG: &str = "sira";
+
+/// The syslog facility used for every message.
+pub const FACILITY: &str = "user";
+
+/// The priority of a message, i.e. its syslog severity level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// An action, or a run, failed.
+    Err,
+
+    /// An action, or a run, succeeded.
+    Info,
+
+    /// An action is about to start.
+    Debug,
+}
+
+impl Priority {
+    /// Returns the name that `logger` uses for this priority.
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Err => "err",
+            Priority::Info => "info",
+            Priority::Debug => "debug",
+        }
+    }
+}
+
+/// Sends a message to the system log for each action that starts and finishes.
+///
+/// Failed actions are logged at priority `err`, successful ones at `info`, and starting actions at
+/// `debug`, so the usual log filters keep the noise down.
+#[derive(Clone, Debug, Default)]
+pub struct Syslog;
+
+#[async_trait]
+impl Report for Syslog {
+    async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
+        let (priority, message) = starting_message(host, action);
+        task::block_in_place(|| log(priority, &message))
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        let (priority, message) = report_message(host, action, output);
+        task::block_in_place(|| log(priority, &message))
+    }
+}
+
+/// Returns the priority and message to log when an action is about to start.
+pub(crate) fn starting_message(host: &str, action: &Action) -> (Priority, String) {
+    (
+        Priority::Debug,
+        format!("[{host}] Starting {}", title(action)),
+    )
+}
+
+/// Returns the priority and message to log when an action finishes.
+pub(crate) fn report_message(host: &str, action: &Action, output: &Output) -> (Priority, String) {
+    match (output.status.success(), output.status.code()) {
+        (true, _) => (
+            Priority::Info,
+            format!("[{host}] Completed {}", title(action)),
+        ),
+        (false, Some(code)) => (
+            Priority::Err,
+            format!("[{host}] Failed with exit code {code}: {}", title(action)),
+        ),
+        (false, None) => (
+            Priority::Err,
+            format!("[{host}] Failed with error: {}", title(action)),
+        ),
+    }
+}
+
+/// Sends a message to the system log via `logger`, e.g. about a run as a whole rather than an
+/// action.
+pub fn log(priority: Priority, message: &str) -> io::Result<()> {
+    // logger -t <tag> -p <facility>.<priority> -- <message>
+    let output = Command::new("logger")
+        .arg("-t")
+        .arg(TAG)
+        .arg("-p")
+        .arg(format!("{FACILITY}.{}", priority.name()))
+        .arg("--")
+        .arg(message)
+        .output()?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "logger exited with error: {}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,65 @@
+use super::*;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+fn action() -> Action {
+    Action::Command(vec!["systemctl restart app.service".to_string()])
+}
+
+fn output(status: ExitStatus) -> Output {
+    Output {
+        status,
+        stdout: vec![],
+        stderr: vec![],
+    }
+}
+
+#[test]
+fn starting_message_is_debug() {
+    assert_eq!(
+        (
+            Priority::Debug,
+            "[web1] Starting command: systemctl restart app.service".to_string(),
+        ),
+        starting_message("web1", &action()),
+    );
+}
+
+mod report_message {
+    use super::*;
+
+    #[test]
+    fn success_is_info() {
+        assert_eq!(
+            (
+                Priority::Info,
+                "[web1] Completed command: systemctl restart app.service".to_string(),
+            ),
+            report_message("web1", &action(), &output(ExitStatus::from_raw(0))),
+        );
+    }
+
+    #[test]
+    fn failure_is_err() {
+        assert_eq!(
+            (
+                Priority::Err,
+                "[web1] Failed with exit code 3: command: systemctl restart app.service"
+                    .to_string(),
+            ),
+            report_message("web1", &action(), &output(ExitStatus::from_raw(3 << 8))),
+        );
+    }
+
+    #[test]
+    fn signal_is_err() {
+        // Killed by SIGKILL, so there is no exit code.
+        assert_eq!(
+            (
+                Priority::Err,
+                "[web1] Failed with error: command: systemctl restart app.service".to_string(),
+            ),
+            report_message("web1", &action(), &output(ExitStatus::from_raw(9))),
+        );
+    }
+}
@@ -1,13 +1,11 @@
 use super::*;
+use crate::config::Escalation;
+use crate::core::action::ErrorCode;
 use crate::core::fixtures::plan;
 use crate::core::Action;
-use anyhow::bail;
 use async_trait::async_trait;
-use std::collections::{HashMap, HashSet};
-use std::error::Error;
-use std::io;
-use std::os::unix::process::ExitStatusExt;
-use std::process::{ExitStatus, Output};
+use std::io::{self, Write};
+use std::process::Output;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 pub mod fixtures {
@@ -28,6 +26,16 @@ pub mod fixtures {
         }
     }
 
+    // Asserts that `record` is a call to `method_name` that sends `action` in an unexpired
+    // envelope, signed if `signed` and the signing key is installed.
+    pub fn assert_record(record: &CommandRecord, method_name: &str, action: &Action, signed: bool) {
+        assert_eq!(method_name, record.method_name);
+        let envelope = Envelope::from_yaml(&record.yaml).unwrap();
+        envelope.check_expiry().unwrap();
+        assert_eq!(action, &envelope.action);
+        assert_eq!(maybe_sign(&record.yaml, signed), record.signature);
+    }
+
     pub mod fixture {
         use super::*;
 
@@ -36,6 +44,7 @@ pub mod fixtures {
             pub plan: Plan,
             pub client_factory: Arc<Mutex<TestClientFactory>>,
             pub reporter: Arc<TestReporter>,
+            pub options: RunOptions,
         }
 
         impl Fixture {
@@ -50,6 +59,7 @@ pub mod fixtures {
                     plan,
                     client_factory,
                     reporter,
+                    options: RunOptions::default(),
                 }
             }
 
@@ -65,6 +75,8 @@ pub mod fixtures {
                     self.plan.plan_for(&self.host).unwrap().into_iter(),
                     self.client_factory.clone(),
                     self.reporter.clone(),
+                    self.options.clone(),
+                    ExpectedClients::default(),
                 )
                 .await
             }
@@ -96,19 +108,12 @@ pub mod fixtures {
                 signed: bool,
             ) {
                 let mut fixture = Fixture::new();
-                let yaml = serde_yaml::to_string(&action).unwrap();
-                fixture.plan.manifests[0].include[0].actions = vec![action];
-                let signature = maybe_sign(&yaml, signed);
+                fixture.plan.manifests[0].include[0].actions = vec![action.clone()];
 
                 fixture.run_host_plan().await.unwrap();
 
                 let recorded_commands = fixture.recorded_commands();
-                let expected = CommandRecord {
-                    method_name,
-                    yaml,
-                    signature,
-                };
-                assert_eq!(expected, recorded_commands[0]);
+                assert_record(&recorded_commands[0], method_name, &action, signed);
             }
 
             // DRY helper for run_host_plan tests that verify the error handling on
@@ -119,346 +124,462 @@ pub mod fixtures {
                 signed: bool,
             ) {
                 let mut fixture = Fixture::new();
-                let yaml = serde_yaml::to_string(&action).unwrap();
-                fixture.plan.manifests[0].include[0].actions = vec![action];
+                fixture.plan.manifests[0].include[0].actions = vec![action.clone()];
                 fixture.client_factory().fail_client_command(&fixture.host);
-                let signature = maybe_sign(&yaml, signed);
 
                 assert!(fixture.run_host_plan().await.is_err());
 
                 let recorded_commands = fixture.recorded_commands();
-                let expected = CommandRecord {
-                    method_name,
-                    yaml,
-                    signature,
-                };
-                assert_eq!(expected, recorded_commands[0]);
+                assert_record(&recorded_commands[0], method_name, &action, signed);
             }
         }
     }
     pub use fixture::*;
 
-    pub mod client {
-        use super::*;
+    pub use crate::run_plan::fixtures::*;
+}
+use fixtures::*;
 
-        // A factory that tests can pass into _run_plan. Spawns TestClient values when asked to
-        // connect to clients, but holds onto references to the TestClient's fields so that
-        // tests can examine them later by inspecting the TestClientFactory.
-        #[derive(Clone, Debug)]
-        pub struct TestClientFactory {
-            // A record of all ClientInterface methods invoked on all clients.
-            client_commands: ClientCommands,
-
-            // Clients that the connect method should not be able to reach.
-            unreachable_clients: HashSet<String>,
-
-            // Clients whose ClientInterface methods should return failures.
-            failing_clients: HashSet<String>,
-
-            // Maps host_name -> exit_code. Allows clients to return custom exit codes via
-            // ClientInterface to simulate failed commands.
-            custom_exit_codes: HashMap<String, i32>,
-        }
-
-        impl TestClientFactory {
-            pub fn new() -> Arc<Mutex<Self>> {
-                Arc::new(Mutex::new(Self {
-                    client_commands: ClientCommands::new(),
-                    unreachable_clients: HashSet::new(),
-                    failing_clients: HashSet::new(),
-                    custom_exit_codes: HashMap::new(),
-                }))
-            }
+mod run_options {
+    use super::*;
 
-            pub fn set_unreachable(&mut self, host: impl Into<String>) {
-                self.unreachable_clients.insert(host.into());
-            }
+    #[test]
+    fn from_config_applies_every_setting() {
+        let config = Config {
+            user: Some("deploy".to_string()),
+            client_path: Some("/usr/local/bin/sira-client".to_string()),
+            hosts: BTreeMap::from([(
+                "legacy1".to_string(),
+                HostConfig {
+                    port: Some(2222),
+                    user: Some("admin".to_string()),
+                    ..Default::default()
+                },
+            )]),
+            concurrency: Some(4),
+            pipeline: Some(8),
+            host_key_checking: Some(HostKeyChecking::Strict),
+            json_log: Some(PathBuf::from("sira.jsonl")),
+            syslog: Some(true),
+            pre_run: Some(vec!["make".to_string()]),
+            history: Some(PathBuf::from("history.db")),
+            facts_ttl: Some(60),
+            inventory: Some(vec!["./cmdb.sh".to_string()]),
+            ..Default::default()
+        };
+        let options = RunOptions::from_config(config);
+        assert_eq!(Some("deploy"), options.connection.user.as_deref());
+        assert_eq!(
+            Some("/usr/local/bin/sira-client"),
+            options.connection.client_path.as_deref(),
+        );
+        assert_eq!(Some(4), options.concurrency);
+        assert_eq!(Some(8), options.pipeline);
+        assert_eq!(Some(HostKeyChecking::Strict), options.host_key_checking);
+        assert_eq!(Some(PathBuf::from("sira.jsonl")), options.json_log);
+        assert!(options.syslog);
+        assert_eq!(vec!["make".to_string()], options.pre_run);
+        assert!(options.post_run.is_empty());
+        assert_eq!(Some(PathBuf::from("history.db")), options.history);
+        assert_eq!(Some(60), options.facts_ttl);
+        assert_eq!(vec!["./cmdb.sh".to_string()], options.inventory);
+    }
 
-            pub fn fail_client_command(&mut self, host: impl Into<String>) {
-                self.failing_clients.insert(host.into());
-            }
+    #[test]
+    fn connection_for_merges_host_overrides() {
+        let options = RunOptions {
+            connection: HostConfig {
+                user: Some("sira".to_string()),
+                escalation: Some(Escalation::Sudo),
+                ..Default::default()
+            },
+            hosts: BTreeMap::from([(
+                "legacy1".to_string(),
+                HostConfig {
+                    port: Some(2222),
+                    escalation: Some(Escalation::Doas),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
 
-            pub fn exit_code(&mut self, host: impl Into<String>, code: i32) {
-                self.custom_exit_codes.insert(host.into(), code);
-            }
+        let legacy1 = options.connection_for("legacy1");
+        assert_eq!(Some(2222), legacy1.port);
+        assert_eq!(Some("sira"), legacy1.user.as_deref());
+        assert_eq!(Some(Escalation::Doas), legacy1.escalation);
 
-            pub fn client_commands(&self) -> &ClientCommands {
-                &self.client_commands
-            }
+        assert_eq!(options.connection, options.connection_for("web1"));
+    }
 
-            pub fn into_client_commands(self) -> ClientCommands {
-                self.client_commands
-            }
-        }
+    #[test]
+    fn empty_config_is_default() {
+        assert_eq!(
+            RunOptions::default(),
+            RunOptions::from_config(Config::default())
+        );
+    }
+}
 
-        #[async_trait]
-        impl ManageClient<TestClient> for Arc<Mutex<TestClientFactory>> {
-            async fn connect(&mut self, host: &str) -> anyhow::Result<TestClient> {
-                let mut factory = self.lock().unwrap();
-                if factory.unreachable_clients.contains(host) {
-                    bail!("unreachable");
-                }
+mod check_upload_checksum {
+    use super::*;
 
-                use std::collections::hash_map::Entry;
-                let commands = match factory.client_commands.entry(host.to_owned()) {
-                    Entry::Occupied(entry) => entry.get().clone(),
-                    Entry::Vacant(entry) => entry.insert(Arc::default()).clone(),
-                };
+    // SHA-256 of "hello\n".
+    const HELLO: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
 
-                let should_fail = factory.failing_clients.contains(host);
+    fn source() -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello\n").unwrap();
+        file
+    }
 
-                let custom_exit_code = factory.custom_exit_codes.get(host).copied();
+    #[test]
+    fn accepts_matching_checksum() {
+        let from = source();
+        let stdout = format!("moved\n{UPLOAD_CHECKSUM_PREFIX}{HELLO}\n");
+        check_upload_checksum(from.path().to_str().unwrap(), true, stdout.as_bytes()).unwrap();
+    }
 
-                Ok(TestClient {
-                    records: commands,
-                    should_fail,
-                    custom_exit_code,
-                })
-            }
-        }
+    #[test]
+    fn rejects_mismatched_checksum() {
+        let from = source();
+        let stdout = format!("{UPLOAD_CHECKSUM_PREFIX}{}\n", "0".repeat(64));
+        let error = check_upload_checksum(from.path().to_str().unwrap(), true, stdout.as_bytes())
+            .unwrap_err();
+        assert!(error.to_string().contains("checksum mismatch"));
+    }
 
-        // Maps host_name -> SharedRecords.
-        type ClientCommands = HashMap<String, SharedRecords>;
+    #[test]
+    fn requires_checksum_when_overwriting() {
+        assert!(check_upload_checksum("a", true, b"").is_err());
+    }
 
-        // The record of ClientInterface method calls for a single TestClient.
-        type SharedRecords = Arc<Mutex<Vec<CommandRecord>>>;
+    #[test]
+    fn allows_missing_checksum_when_not_overwriting() {
+        check_upload_checksum("a", false, b"").unwrap();
+    }
+}
 
-        #[derive(Clone, Debug, PartialEq, Eq)]
-        pub struct CommandRecord {
-            pub method_name: &'static str,
-            pub yaml: String,
-            pub signature: Option<String>,
-        }
+mod _run_plan {
+    use super::*;
 
-        #[derive(Clone, Debug)]
-        pub struct TestClient {
-            // ClientInterface methods invoked on this client.
-            records: SharedRecords,
+    #[tokio::test]
+    async fn runs_plan_for_all_hosts() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
 
-            // Whether ClientInterface methods should return Err values.
-            should_fail: bool,
+        _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            fixture.reporter.clone(),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap();
 
-            // Optional custom value that a ClientInterface method should return on success as
-            // part of its Output value.
-            custom_exit_code: Option<i32>,
-        }
+        let locked = fixture.client_factory();
+        assert_eq!(3, locked.client_commands().len());
+        assert!(locked.client_commands().contains_key("a"));
+        assert!(locked.client_commands().contains_key("b"));
+        assert!(locked.client_commands().contains_key("c"));
+    }
 
-        #[async_trait]
-        impl ClientInterface for TestClient {
-            async fn command(
-                &mut self,
-                yaml: &str,
-                signature: Option<Vec<u8>>,
-            ) -> Result<Output, openssh::Error> {
-                self.record("command", yaml, signature, openssh::Error::Disconnected)
-            }
+    #[tokio::test]
+    async fn runs_plan_for_all_hosts_with_concurrency_limit() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        fixture.options.concurrency = Some(1);
 
-            async fn line_in_file(
-                &mut self,
-                yaml: &str,
-                signature: Option<Vec<u8>>,
-            ) -> Result<Output, openssh::Error> {
-                self.record(
-                    "line_in_file",
-                    yaml,
-                    signature,
-                    openssh::Error::Disconnected,
-                )
-            }
+        _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            fixture.reporter.clone(),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap();
 
-            async fn script(
-                &mut self,
-                yaml: &str,
-                signature: Option<Vec<u8>>,
-            ) -> Result<Output, openssh::Error> {
-                self.record("script", yaml, signature, openssh::Error::Disconnected)
-            }
+        assert_eq!(3, fixture.client_factory().client_commands().len());
+    }
 
-            async fn upload(
-                &mut self,
-                from: &str,
-                yaml: &str,
-                signature: Option<Vec<u8>>,
-            ) -> anyhow::Result<Output> {
-                // Sanity check.
-                let action: Action = serde_yaml::from_str(yaml).unwrap();
-                match action {
-                    Action::Upload { from: af, .. } => assert_eq!(from, af),
-                    x => panic!("expected Action::Upload but got:\n{x:#?}"),
-                }
+    #[tokio::test]
+    async fn returns_all_errors() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
 
-                // anyhow::Error doesn't implement std::error::Error. Meanwhile, upload returns
-                // an anyhow::Result, and record requires and returns Error/Result. To solve
-                // this incompatibility, we have to map_err. The error output from rustc isn't
-                // very helpful on this issue.
-                self.record("upload", yaml, signature, io::Error::other("expected"))
-                    .map_err(Into::into)
-            }
+        {
+            let mut locked = fixture.client_factory();
+            locked.fail_client_command("b");
+            locked.fail_client_command("c");
         }
 
-        impl TestClient {
-            // Records a call to a ClientInterface method.
-            pub fn record<E: Error + Send + Sync + 'static>(
-                &mut self,
-                caller: &'static str,
-                yaml: impl Into<String>,
-                signature: Option<Vec<u8>>,
-                error: E,
-            ) -> Result<Output, E> {
-                self.records.lock().unwrap().push(CommandRecord {
-                    method_name: caller,
-                    yaml: yaml.into(),
-                    signature: signature
-                        .map(|s| String::from_utf8(s).expect("signature was not UTF-8")),
-                });
-
-                if self.should_fail {
-                    Err(error)
-                } else {
-                    let exit_code = self.custom_exit_code.unwrap_or(0);
-                    Ok(Output {
-                        status: ExitStatus::from_raw(exit_code),
-                        stdout: vec![],
-                        stderr: vec![],
-                    })
-                }
+        let errors = _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            fixture.reporter.clone(),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(2, errors.len());
+    }
+
+    // A reporter that panics when an action starts on the given host.
+    #[derive(Clone)]
+    struct PanicsOn(&'static str);
+
+    #[async_trait]
+    impl Report for PanicsOn {
+        async fn starting(&mut self, host: &str, _action: &Action) -> io::Result<()> {
+            if host == self.0 {
+                panic!("reporter failed on {host}");
             }
+            Ok(())
+        }
+
+        async fn report(
+            &mut self,
+            _host: &str,
+            _action: &Action,
+            _output: &Output,
+        ) -> io::Result<()> {
+            Ok(())
         }
     }
-    pub use client::*;
 
-    pub mod report {
-        use super::*;
+    #[tokio::test]
+    async fn returns_task_error_if_host_panics() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
 
-        // A Report implementation that uses Vecs as fake stdout/stderr writers. _run_plan
-        // needs to clone its reporter for each client, so TestReporter uses shared references
-        // to the same stdout and stderr Vecs, and tests can hold onto a clone and examine it
-        // after the code under test.
-        #[derive(Debug, Default)]
-        pub struct TestReporter {
-            // Whether the Report::report method should return an error.
-            should_fail: Mutex<bool>,
-
-            // Whether the Report::starting method should return an error.
-            should_fail_to_start: Mutex<bool>,
-
-            // The shared stderr writer.
-            stderr: Mutex<Vec<u8>>,
-
-            // The shared stdout writer.
-            stdout: Mutex<Vec<u8>>,
-        }
-
-        impl TestReporter {
-            pub fn new() -> Arc<Self> {
-                Arc::new(Self {
-                    stdout: Mutex::new(vec![]),
-                    stderr: Mutex::new(vec![]),
-                    should_fail: Mutex::new(false),
-                    should_fail_to_start: Mutex::new(false),
-                })
-            }
+        let errors = _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            (fixture.reporter.clone(), PanicsOn("b")),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap_err();
 
-            // Instructs this TestReporter to always fail, i.e. return an error from the
-            // Report::report method.
-            pub fn fail(&self) {
-                *self.should_fail.lock().unwrap() = true;
+        assert_eq!(1, errors.len());
+        let (host, error) = &errors[0];
+        assert_eq!("b", host);
+        match error.downcast_ref::<TaskError>() {
+            Some(TaskError::Panicked(Some(message))) => {
+                assert_eq!("reporter failed on b", message)
             }
+            _ => panic!("expected a TaskError but got: {error:?}"),
+        }
+        let locked = fixture.client_factory();
+        assert!(locked.client_commands().contains_key("a"));
+        assert!(locked.client_commands().contains_key("c"));
+    }
 
-            // Instructs this TestReporter to always fail to start, i.e. return an error from the
-            // Report::starting method.
-            pub fn fail_to_start(&self) {
-                *self.should_fail_to_start.lock().unwrap() = true;
-            }
+    #[tokio::test]
+    async fn runs_nothing_if_cancelled_before_starting() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string()];
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        fixture.options.cancel = Some(cancel);
 
-            pub fn stdout(&self) -> MutexGuard<Vec<u8>> {
-                self.stdout.lock().unwrap()
-            }
+        let errors = _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            fixture.reporter.clone(),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(2, errors.len());
+        assert!(errors.iter().all(|(_, error)| error.is::<Cancelled>()));
+        assert!(fixture.client_factory().client_commands().is_empty());
+    }
+
+    // A reporter that cancels the run when an action starts, and then never returns, as though the
+    // action were still running.
+    #[derive(Clone)]
+    struct CancelsOnStart(CancelToken);
+
+    #[async_trait]
+    impl Report for CancelsOnStart {
+        async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+            self.0.cancel();
+            std::future::pending().await
         }
 
-        #[async_trait]
-        impl Report for Arc<TestReporter> {
-            // Performs a simulated start notice, and then optionally returns an expected failure.
-            async fn starting(&mut self, host: &str, action: &Action) -> io::Result<()> {
-                let result = _starting(&mut *self.stdout.lock().unwrap(), host, action);
+        async fn report(
+            &mut self,
+            _host: &str,
+            _action: &Action,
+            _output: &Output,
+        ) -> io::Result<()> {
+            Ok(())
+        }
+    }
 
-                if *self.should_fail_to_start.lock().unwrap() {
-                    Err(io::Error::other("expected"))
-                } else {
-                    result
-                }
-            }
+    #[tokio::test]
+    async fn interrupts_hosts_when_cancelled() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string()];
+        let cancel = CancelToken::new();
+        fixture.options.cancel = Some(cancel.clone());
 
-            // Performs a simulated report, and then optionally returns an expected failure.
-            async fn report(
-                &mut self,
-                host: &str,
-                action: &Action,
-                output: &Output,
-            ) -> io::Result<()> {
-                let result = _report(
-                    &mut *self.stdout.lock().unwrap(),
-                    &mut *self.stderr.lock().unwrap(),
-                    host,
-                    action,
-                    output,
-                );
+        let errors = _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            (fixture.reporter.clone(), CancelsOnStart(cancel)),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap_err();
 
-                if *self.should_fail.lock().unwrap() {
-                    Err(io::Error::other("expected"))
-                } else {
-                    result
-                }
+        assert_eq!(2, errors.len());
+        assert!(errors.iter().all(|(_, error)| error.is::<Cancelled>()));
+    }
+
+    // A reporter that never returns when an action starts on the given host, as though the action
+    // were still running.
+    #[derive(Clone)]
+    struct HangsOn(&'static str);
+
+    #[async_trait]
+    impl Report for HangsOn {
+        async fn starting(&mut self, host: &str, _action: &Action) -> io::Result<()> {
+            if host == self.0 {
+                std::future::pending::<()>().await;
             }
+            Ok(())
+        }
+
+        async fn report(
+            &mut self,
+            _host: &str,
+            _action: &Action,
+            _output: &Output,
+        ) -> io::Result<()> {
+            Ok(())
         }
     }
-    pub use report::*;
-}
-use fixtures::*;
 
-mod _run_plan {
-    use super::*;
+    #[tokio::test]
+    async fn aborts_running_hosts_when_one_fails() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string()];
+        fixture.options.on_failure = OnFailure::Abort;
+        fixture.client_factory().fail_client_command("a");
+
+        let errors: HashMap<_, _> = _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            (fixture.reporter.clone(), HangsOn("b")),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap_err()
+        .into_iter()
+        .collect();
+
+        assert_eq!(2, errors.len());
+        assert!(!errors["a"].is::<Aborted>());
+        assert!(errors["b"].is::<Aborted>());
+    }
 
     #[tokio::test]
-    async fn runs_plan_for_all_hosts() {
+    async fn aborts_pending_hosts_when_one_fails() {
         let mut fixture = Fixture::new();
         fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        fixture.options.concurrency = Some(1);
+        fixture.options.on_failure = OnFailure::Abort;
+        fixture.client_factory().fail_client_command("a");
+
+        let errors = _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            fixture.reporter.clone(),
+            fixture.options.clone(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(3, errors.len());
+        let aborted: Vec<_> = errors
+            .iter()
+            .filter(|(_, error)| error.is::<Aborted>())
+            .map(|(host, _)| host.as_str())
+            .collect();
+        assert_eq!(2, aborted.len());
+        assert!(!aborted.contains(&"a"));
+        assert_eq!(1, fixture.client_factory().client_commands().len());
+    }
+
+    #[tokio::test]
+    async fn finishes_every_host_when_none_fails_despite_abort() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string()];
+        fixture.options.on_failure = OnFailure::Abort;
 
         _run_plan(
             fixture.plan.clone(),
             fixture.client_factory.clone(),
             fixture.reporter.clone(),
+            fixture.options.clone(),
         )
         .await
         .unwrap();
 
-        let locked = fixture.client_factory();
-        assert_eq!(3, locked.client_commands().len());
-        assert!(locked.client_commands().contains_key("a"));
-        assert!(locked.client_commands().contains_key("b"));
-        assert!(locked.client_commands().contains_key("c"));
+        assert_eq!(2, fixture.client_factory().client_commands().len());
     }
 
     #[tokio::test]
-    async fn returns_all_errors() {
+    async fn ignores_pending_hosts_when_cancelled() {
         let mut fixture = Fixture::new();
-        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string()];
+        fixture.options.concurrency = Some(1);
+        let cancel = CancelToken::new();
+        fixture.options.cancel = Some(cancel.clone());
+        let run_status = StatusHandle::new();
+        fixture.options.status = Some(run_status.clone());
+
+        let _ = _run_plan(
+            fixture.plan.clone(),
+            fixture.client_factory.clone(),
+            (fixture.reporter.clone(), CancelsOnStart(cancel)),
+            fixture.options.clone(),
+        )
+        .await;
 
-        {
-            let mut locked = fixture.client_factory();
-            locked.fail_client_command("b");
-            locked.fail_client_command("c");
-        }
+        let now = run_status.query();
+        assert_eq!(status::HostState::Failed, now.hosts["a"].state);
+        assert_eq!(vec!["b"], now.ignored());
+        assert!(now.is_done());
+    }
 
-        let errors = _run_plan(
+    #[tokio::test]
+    async fn tracks_status() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].hosts = vec!["a".to_string(), "b".to_string()];
+        fixture.client_factory().fail_client_command("b");
+        let run_status = StatusHandle::new();
+        fixture.options.status = Some(run_status.clone());
+        let actions = fixture.plan.plan_for("b").unwrap().iter().count();
+
+        let _ = _run_plan(
             fixture.plan.clone(),
             fixture.client_factory.clone(),
             fixture.reporter.clone(),
+            fixture.options.clone(),
         )
-        .await
-        .unwrap_err();
-
-        assert_eq!(2, errors.len());
+        .await;
+
+        let now = run_status.query();
+        assert_eq!(status::HostState::Succeeded, now.hosts["a"].state);
+        assert_eq!(0, now.hosts["a"].actions_remaining);
+        assert_eq!(status::HostState::Failed, now.hosts["b"].state);
+        assert_eq!(actions, now.hosts["b"].actions_remaining);
+        assert_eq!(0, now.actions_remaining());
     }
 
     #[tokio::test]
@@ -469,12 +590,244 @@ mod _run_plan {
             fixture.plan.clone(),
             fixture.client_factory.clone(),
             fixture.reporter.clone(),
+            fixture.options.clone(),
         )
         .await
         .is_ok());
     }
 }
 
+mod task_error {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_panic_message() {
+        let error = tokio::spawn(async { panic!("oh no") }).await.unwrap_err();
+        let error = TaskError::from(error);
+        assert!(matches!(&error, TaskError::Panicked(Some(message)) if message == "oh no"));
+        assert_eq!(
+            "Sira's task for this host panicked: oh no. Please report this bug!",
+            error.to_string(),
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_formatted_panic_message() {
+        let host = "web1";
+        let error = tokio::spawn(async move { panic!("oh no: {host}") })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(TaskError::from(error), TaskError::Panicked(Some(message)) if message == "oh no: web1")
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_cancellation() {
+        let handle = tokio::spawn(std::future::pending::<()>());
+        handle.abort();
+        let error = TaskError::from(handle.await.unwrap_err());
+        assert!(matches!(error, TaskError::Cancelled));
+        assert_eq!("Sira's task for this host was cancelled", error.to_string());
+    }
+
+    #[tokio::test]
+    async fn tells_embedders_panics_apart() {
+        let error = tokio::spawn(async {
+            let payload = EmbedderPanic(Some("oh no".to_string()));
+            std::panic::resume_unwind(Box::new(payload))
+        })
+        .await
+        .unwrap_err();
+        let error = TaskError::from(error);
+        assert!(matches!(&error, TaskError::EmbedderPanicked(Some(message)) if message == "oh no"));
+        assert_eq!(
+            "The program running Sira panicked on this host: oh no",
+            error.to_string(),
+        );
+    }
+}
+
+mod run_plan_with_ui {
+    use super::*;
+
+    // A frontend that only records whether the run finished.
+    #[derive(Clone, Default)]
+    struct FinishedUi(Arc<Mutex<bool>>);
+
+    #[async_trait]
+    impl Report for FinishedUi {
+        async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+            Ok(())
+        }
+
+        async fn report(
+            &mut self,
+            _host: &str,
+            _action: &Action,
+            _output: &Output,
+        ) -> io::Result<()> {
+            Ok(())
+        }
+
+        async fn finished(&mut self) -> io::Result<()> {
+            *self.0.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    // A call to Report::plan_started or Report::plan_finished: its name, plan ID, and host count.
+    type PlanCall = (&'static str, u64, usize);
+
+    // A frontend that only records the plans that start and finish.
+    #[derive(Clone, Default)]
+    struct PlanUi(Arc<Mutex<Vec<PlanCall>>>);
+
+    #[async_trait]
+    impl Report for PlanUi {
+        async fn plan_started(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+            self.0.lock().unwrap().push(("started", plan_id, hosts));
+            Ok(())
+        }
+
+        async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+            Ok(())
+        }
+
+        async fn report(
+            &mut self,
+            _host: &str,
+            _action: &Action,
+            _output: &Output,
+        ) -> io::Result<()> {
+            Ok(())
+        }
+
+        async fn plan_finished(&mut self, plan_id: u64, hosts: usize) -> io::Result<()> {
+            self.0.lock().unwrap().push(("finished", plan_id, hosts));
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn delimits_each_plan_with_its_own_id() {
+        let ui = PlanUi::default();
+        for _ in 0..2 {
+            run_plan_with_ui(Plan::new(), RunOptions::default(), ui.clone())
+                .await
+                .unwrap();
+        }
+        let calls = ui.0.lock().unwrap();
+        let [("started", first, 0), ("finished", first_again, 0), ("started", second, 0), ("finished", second_again, 0)] =
+            calls[..]
+        else {
+            panic!("{calls:?}");
+        };
+        assert_eq!(first, first_again);
+        assert_eq!(second, second_again);
+        assert!(second > first);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reports_to_ui() {
+        let ui = FinishedUi::default();
+        run_plan_with_ui(Plan::new(), RunOptions::default(), ui.clone())
+            .await
+            .unwrap();
+        assert!(*ui.0.lock().unwrap());
+    }
+
+    // Without hooks or history, there's nothing to block on, so single-threaded runtimes work.
+    #[tokio::test]
+    async fn runs_on_current_thread_runtime() {
+        let ui = FinishedUi::default();
+        run_plan_with_ui(Plan::new(), RunOptions::default(), ui.clone())
+            .await
+            .unwrap();
+        assert!(*ui.0.lock().unwrap());
+    }
+}
+
+mod run_plan_async {
+    use super::*;
+    use event_stream::EventKind;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ends_stream_when_run_finishes() {
+        let (run, mut events) = run_plan_async(Plan::new(), RunOptions::default());
+        let run = tokio::spawn(run);
+        let started = events.next().await.unwrap();
+        assert!(matches!(
+            started.kind,
+            EventKind::PlanStarted { hosts: 0, .. }
+        ));
+        let finished = events.next().await.unwrap();
+        assert!(matches!(
+            finished.kind,
+            EventKind::PlanFinished { hosts: 0, .. }
+        ));
+        assert!(events.next().await.is_none());
+        assert!(run.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn runs_on_current_thread_runtime() {
+        let (run, events) = run_plan_async(Plan::new(), RunOptions::default());
+        drop(events);
+        assert!(run.await.is_ok());
+    }
+}
+
+mod hooks {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_pre_run_hook_fails_every_host_without_connecting() {
+        let (plan, _, _, _) = plan();
+        let options = RunOptions {
+            pre_run: vec!["exit 1".to_string()],
+            ..Default::default()
+        };
+        let errors = run_plan_with(plan, options).await.unwrap_err();
+        assert_eq!(1, errors.len());
+        assert_eq!("archie-desktop", errors[0].0);
+        assert!(format!("{:#}", errors[0].1).contains("pre-run hook failed"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_post_run_hooks_after_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("summary.json");
+        let options = RunOptions {
+            post_run: vec![format!("cat > {}", out.display())],
+            ..Default::default()
+        };
+        run_plan_with(Plan::new(), options).await.unwrap();
+        assert!(fs::read_to_string(out).unwrap().contains("run_finished"));
+    }
+
+    // E.g. when the user retries hosts from the dashboard.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_hooks_once_however_many_attempts() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("hooks");
+        let options = RunOptions {
+            pre_run: vec![format!("echo pre >> {}", out.display())],
+            post_run: vec![format!("cat > /dev/null; echo post >> {}", out.display())],
+            ..Default::default()
+        };
+        let mut run = Run::new(&options);
+        for _ in 0..2 {
+            run_attempt(&mut run, Plan::new(), options.clone(), None::<()>)
+                .await
+                .unwrap();
+        }
+        assert_eq!("pre\n", fs::read_to_string(&out).unwrap());
+        run.finish(&[]);
+        assert_eq!("pre\npost\n", fs::read_to_string(&out).unwrap());
+    }
+}
+
 mod run_host_plan {
     use super::*;
 
@@ -486,10 +839,25 @@ mod run_host_plan {
     }
 
     #[tokio::test]
-    async fn returns_error_if_fails_to_connect() {
+    async fn returns_error_if_fails_to_connect() {
+        let fixture = Fixture::new();
+        fixture.client_factory().set_unreachable(&fixture.host);
+        let error = fixture.run_host_plan().await.unwrap_err();
+        assert!(
+            error.downcast_ref::<ConnectionError>().is_some(),
+            "{error:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_connection_error_if_connection_drops() {
         let fixture = Fixture::new();
-        fixture.client_factory().set_unreachable(&fixture.host);
-        assert!(fixture.run_host_plan().await.is_err());
+        fixture.client_factory().fail_client_command(&fixture.host);
+        let error = fixture.run_host_plan().await.unwrap_err();
+        assert!(
+            error.downcast_ref::<ConnectionError>().is_some(),
+            "{error:#}"
+        );
     }
 
     #[tokio::test]
@@ -512,29 +880,197 @@ mod run_host_plan {
             .unwrap();
         let mut commands = locked_client_commands.iter();
 
-        let yaml = serde_yaml::to_string(&fixture.plan.manifests[0].include[0].actions[0]).unwrap();
-        let signature = maybe_sign(&yaml, true);
+        let actions = &fixture.plan.manifests[0].include[0].actions;
+        assert_record(commands.next().unwrap(), "command", &actions[0], true);
+        assert_record(commands.next().unwrap(), "command", &actions[1], true);
+    }
+
+    #[tokio::test]
+    async fn signs_with_action_key_option() {
+        let mut fixture = Fixture::new();
+        let key_file = crate::config::config_dir()
+            .join(crypto::KEY_DIR)
+            .join("manifest");
+        fixture.options.action_key = Some(key_file.clone());
+        fixture.run_host_plan().await.unwrap();
+
+        let recorded_commands = fixture.recorded_commands();
+        let signature =
+            crypto::sign_with_key_file(recorded_commands[0].yaml.as_bytes(), key_file).unwrap();
         assert_eq!(
-            &CommandRecord {
-                method_name: "command",
-                yaml,
-                signature,
-            },
-            commands.next().unwrap(),
+            Some(String::from_utf8(signature).unwrap()),
+            recorded_commands[0].signature,
         );
+    }
+
+    #[tokio::test]
+    async fn asks_client_to_escalate_for_tasks_that_need_root() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].include[0].escalate = true;
+        fixture.run_host_plan().await.unwrap();
 
-        let yaml = serde_yaml::to_string(&fixture.plan.manifests[0].include[0].actions[1]).unwrap();
-        let signature = maybe_sign(&yaml, true);
+        let recorded_commands = fixture.recorded_commands();
+        assert!(!recorded_commands.is_empty());
+        for record in recorded_commands {
+            assert!(Envelope::from_yaml(&record.yaml).unwrap().escalate);
+        }
+    }
+
+    #[tokio::test]
+    async fn redacts_sensitive_variables_in_reports() {
+        let mut fixture = Fixture::new();
+        let manifest = &mut fixture.plan.manifests[0];
+        let _ = manifest.vars.insert("password".into(), "hunter2".into());
+        manifest.sensitive = vec!["password".into()];
+        let action = Action::Command(vec!["login -p $password".into()]);
+        manifest.include[0].actions = vec![action];
+        fixture.run_host_plan().await.unwrap();
+
+        let stdout = String::from_utf8(fixture.reporter.stdout().to_vec()).unwrap();
+        assert!(stdout.contains("login -p ********"));
+        assert!(!stdout.contains("hunter2"));
+
+        // The client still receives the real value.
+        let recorded_commands = fixture.recorded_commands();
+        let envelope = Envelope::from_yaml(&recorded_commands[0].yaml).unwrap();
         assert_eq!(
-            &CommandRecord {
-                method_name: "command",
-                yaml,
-                signature,
-            },
-            commands.next().unwrap(),
+            Action::Command(vec!["login -p hunter2".into()]),
+            envelope.action,
         );
     }
 
+    #[tokio::test]
+    async fn prompts_for_touch_when_logging_in_with_security_key() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("sira.pub"),
+            "sk-ssh-ed25519@openssh.com AAAA sira\n",
+        )
+        .unwrap();
+
+        let mut fixture = Fixture::new();
+        fixture.options.login_key = Some(dir.path().join("sira"));
+        fixture.run_host_plan().await.unwrap();
+
+        let stdout = String::from_utf8(fixture.reporter.stdout().to_vec()).unwrap();
+        assert!(stdout.starts_with(&format!(
+            "[{}] Touch your security key to log in.\n",
+            fixture.host,
+        )));
+    }
+
+    #[tokio::test]
+    async fn sends_task_limits_with_action() {
+        let mut fixture = Fixture::new();
+        let limits = crate::core::action::Limits {
+            memory: Some("512M".to_string()),
+            ..Default::default()
+        };
+        fixture.plan.manifests[0].include[0].limits = limits.clone();
+        fixture.run_host_plan().await.unwrap();
+
+        let recorded_commands = fixture.recorded_commands();
+        let envelope = Envelope::from_yaml(&recorded_commands[0].yaml).unwrap();
+        assert_eq!(limits, envelope.limits);
+    }
+
+    #[tokio::test]
+    async fn sends_task_sandbox_with_action() {
+        let mut fixture = Fixture::new();
+        let sandbox = Some(crate::core::action::Sandbox::default());
+        fixture.plan.manifests[0].include[0].sandbox = sandbox.clone();
+        fixture.run_host_plan().await.unwrap();
+
+        let recorded_commands = fixture.recorded_commands();
+        let envelope = Envelope::from_yaml(&recorded_commands[0].yaml).unwrap();
+        assert_eq!(sandbox, envelope.sandbox);
+    }
+
+    #[tokio::test]
+    async fn sends_task_user_with_action() {
+        let mut fixture = Fixture::new();
+        fixture.plan.manifests[0].include[0].user = Some("app".to_string());
+        fixture.run_host_plan().await.unwrap();
+
+        let recorded_commands = fixture.recorded_commands();
+        let envelope = Envelope::from_yaml(&recorded_commands[0].yaml).unwrap();
+        assert_eq!(Some("app".to_string()), envelope.user);
+    }
+
+    #[tokio::test]
+    async fn reports_progress() {
+        let mut fixture = Fixture::new();
+        let action = Action::Command(vec!["apt-get update".to_string()]);
+        fixture.plan.manifests[0].include[0].actions = vec![action.clone()];
+        fixture.client_factory().report_progress(&fixture.host);
+        fixture.run_host_plan().await.unwrap();
+
+        let stdout = String::from_utf8(fixture.reporter.stdout().to_vec()).unwrap();
+        let starting = format!("[{}] Starting  {}\n", fixture.host, title(&action));
+        let progress = format!("[{}]     Running {}\n", fixture.host, title(&action));
+        assert!(stdout.contains(&format!("{starting}{progress}")));
+    }
+
+    #[tokio::test]
+    async fn redacts_sensitive_variables_in_progress() {
+        let mut fixture = Fixture::new();
+        let manifest = &mut fixture.plan.manifests[0];
+        let _ = manifest.vars.insert("password".into(), "hunter2".into());
+        manifest.sensitive = vec!["password".into()];
+        manifest.include[0].actions = vec![Action::Command(vec!["login -p $password".into()])];
+        fixture.client_factory().report_progress(&fixture.host);
+        fixture.run_host_plan().await.unwrap();
+
+        let stdout = String::from_utf8(fixture.reporter.stdout().to_vec()).unwrap();
+        assert!(stdout.contains("    Running"));
+        assert!(!stdout.contains("hunter2"));
+    }
+
+    mod confirm {
+        use super::*;
+
+        #[tokio::test]
+        async fn runs_action_if_approved() {
+            let mut fixture = Fixture::new();
+            fixture.plan.manifests[0].include[0].confirm = true;
+            fixture.reporter.approve();
+            fixture.run_host_plan().await.unwrap();
+
+            let stdout = String::from_utf8(fixture.reporter.stdout().to_vec()).unwrap();
+            assert!(stdout.contains("This action requires confirmation"));
+            assert!(!fixture.recorded_commands().is_empty());
+        }
+
+        #[tokio::test]
+        async fn stops_if_declined() {
+            let mut fixture = Fixture::new();
+            fixture.plan.manifests[0].include[0].confirm = true;
+            let error = fixture.run_host_plan().await.unwrap_err();
+
+            assert!(error.to_string().starts_with("Action declined: "));
+            assert!(fixture.recorded_commands().is_empty());
+        }
+
+        #[tokio::test]
+        async fn does_not_ask_by_default() {
+            let fixture = Fixture::new();
+            fixture.run_host_plan().await.unwrap();
+
+            let stdout = String::from_utf8(fixture.reporter.stdout().to_vec()).unwrap();
+            assert!(!stdout.contains("This action requires confirmation"));
+        }
+
+        #[tokio::test]
+        async fn asks_for_every_action_if_configured() {
+            let mut fixture = Fixture::new();
+            fixture.options.confirm = Confirmation::All;
+            let error = fixture.run_host_plan().await.unwrap_err();
+
+            assert!(error.to_string().starts_with("Action declined: "));
+            assert!(fixture.recorded_commands().is_empty());
+        }
+    }
+
     mod starting {
         use super::*;
 
@@ -547,6 +1083,63 @@ mod run_host_plan {
                 .contains("Starting "));
         }
 
+        #[tokio::test]
+        async fn reports_connection_time_and_bytes_transferred() {
+            let mut from = tempfile::NamedTempFile::new().unwrap();
+            from.write_all(b"12345").unwrap();
+            let mut fixture = Fixture::new();
+            fixture.plan.manifests[0].include[0].actions = vec![Action::Upload {
+                from: from.path().to_str().unwrap().to_string(),
+                to: "b".to_string(),
+                user: "c".to_string(),
+                group: "d".to_string(),
+                permissions: None,
+                overwrite: true,
+                encrypt: false,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: None,
+                xattrs: Default::default(),
+                capabilities: None,
+            }];
+            let (reporter, mut events) = EventStream::channel();
+
+            run_host_plan(
+                fixture.host.clone(),
+                fixture.plan.plan_for(&fixture.host).unwrap().into_iter(),
+                fixture.client_factory.clone(),
+                reporter,
+                fixture.options.clone(),
+                ExpectedClients::default(),
+            )
+            .await
+            .unwrap();
+
+            let mut kinds = vec![];
+            while let Some(event) = events.next().await {
+                kinds.push(event.kind);
+            }
+            let kind_names: Vec<_> = kinds
+                .iter()
+                .map(|kind| serde_json::to_value(kind).unwrap()["event"].clone())
+                .collect();
+            assert_eq!(
+                [
+                    "network",
+                    "network",
+                    "connected",
+                    "task",
+                    "starting",
+                    "network",
+                    "transferred"
+                ],
+                kind_names[..7],
+            );
+            assert_eq!(event_stream::EventKind::Transferred { bytes: 5 }, kinds[6]);
+        }
+
         #[tokio::test]
         async fn returns_error_if_reporting_fails() {
             let fixture = Fixture::new();
@@ -579,6 +1172,28 @@ mod run_host_plan {
         }
     }
 
+    mod controller_key {
+        use super::*;
+
+        fn action() -> Action {
+            Action::ControllerKey {
+                login_key: Some("ssh-ed25519 AAAA sira".to_string()),
+                action_key: None,
+                remove: false,
+            }
+        }
+
+        #[tokio::test]
+        async fn calls_client_controller_key() {
+            Fixture::test_calls_client("controller_key", action(), true).await
+        }
+
+        #[tokio::test]
+        async fn returns_error_on_failure() {
+            Fixture::test_client_returns_error("controller_key", action(), true).await
+        }
+    }
+
     mod script {
         use super::*;
 
@@ -588,7 +1203,7 @@ mod run_host_plan {
                 "script",
                 Action::Script {
                     name: "Run a script".to_string(),
-                    user: "alice".to_string(),
+                    user: Some("alice".to_string()),
                     contents: "touch /tmp/alice_was_here".to_string(),
                 },
                 true,
@@ -602,7 +1217,7 @@ mod run_host_plan {
                 "script",
                 Action::Script {
                     name: "Run a script".to_string(),
-                    user: "alice".to_string(),
+                    user: Some("alice".to_string()),
                     contents: "touch /tmp/alice_was_here".to_string(),
                 },
                 true,
@@ -611,6 +1226,73 @@ mod run_host_plan {
         }
     }
 
+    mod custom {
+        use super::*;
+
+        fn action() -> Action {
+            Action::Custom {
+                plugin: "firewall".to_string(),
+                args: serde_json::json!({"port": 443}),
+            }
+        }
+
+        #[tokio::test]
+        async fn calls_client_custom() {
+            Fixture::test_calls_client("custom", action(), true).await
+        }
+
+        #[tokio::test]
+        async fn returns_error_on_failure() {
+            Fixture::test_client_returns_error("custom", action(), true).await
+        }
+    }
+
+    mod kernel_module {
+        use super::*;
+
+        fn action() -> Action {
+            Action::KernelModule {
+                name: "br_netfilter".to_string(),
+                options: vec![],
+                persist: true,
+                remove: false,
+            }
+        }
+
+        #[tokio::test]
+        async fn calls_client_kernel_module() {
+            Fixture::test_calls_client("kernel_module", action(), true).await
+        }
+
+        #[tokio::test]
+        async fn returns_error_on_failure() {
+            Fixture::test_client_returns_error("kernel_module", action(), true).await
+        }
+    }
+
+    mod swap {
+        use super::*;
+
+        fn action() -> Action {
+            Action::Swap {
+                path: "/swapfile".to_string(),
+                size: Some("2G".to_string()),
+                persist: true,
+                remove: false,
+            }
+        }
+
+        #[tokio::test]
+        async fn calls_client_swap() {
+            Fixture::test_calls_client("swap", action(), true).await
+        }
+
+        #[tokio::test]
+        async fn returns_error_on_failure() {
+            Fixture::test_client_returns_error("swap", action(), true).await
+        }
+    }
+
     mod line_in_file {
         use super::*;
 
@@ -652,15 +1334,24 @@ mod run_host_plan {
 
         #[tokio::test]
         async fn calls_client_upload() {
+            let from = tempfile::NamedTempFile::new().unwrap();
             Fixture::test_calls_client(
                 "upload",
                 Action::Upload {
-                    from: "a".to_string(),
+                    from: from.path().to_str().unwrap().to_string(),
                     to: "b".to_string(),
                     user: "c".to_string(),
                     group: "d".to_string(),
                     permissions: Some("e".to_string()),
                     overwrite: true,
+                    encrypt: false,
+                    seuser: None,
+                    serole: None,
+                    setype: None,
+                    acl: vec!["u:deploy:r".to_string()],
+                    transfer_permissions: Some("600".to_string()),
+                    xattrs: Default::default(),
+                    capabilities: None,
                 },
                 true,
             )
@@ -678,11 +1369,70 @@ mod run_host_plan {
                     group: "d".to_string(),
                     permissions: Some("e".to_string()),
                     overwrite: true,
+                    encrypt: false,
+                    seuser: None,
+                    serole: None,
+                    setype: None,
+                    acl: vec![],
+                    transfer_permissions: None,
+                    xattrs: Default::default(),
+                    capabilities: None,
                 },
                 true,
             )
             .await
         }
+
+        #[tokio::test]
+        async fn encrypted_upload_requires_recipients() {
+            let mut fixture = Fixture::new();
+            fixture.plan.manifests[0].include[0].actions = vec![Action::Upload {
+                from: "a".to_string(),
+                to: "b".to_string(),
+                user: "c".to_string(),
+                group: "d".to_string(),
+                permissions: None,
+                overwrite: true,
+                encrypt: true,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: None,
+                xattrs: Default::default(),
+                capabilities: None,
+            }];
+
+            let error = fixture.run_host_plan().await.unwrap_err();
+            assert!(error.to_string().contains("recipients file"));
+            assert!(fixture.recorded_commands().is_empty());
+        }
+
+        #[tokio::test]
+        async fn returns_error_on_checksum_mismatch() {
+            let from = tempfile::NamedTempFile::new().unwrap();
+            let mut fixture = Fixture::new();
+            fixture.plan.manifests[0].include[0].actions = vec![Action::Upload {
+                from: from.path().to_str().unwrap().to_string(),
+                to: "b".to_string(),
+                user: "c".to_string(),
+                group: "d".to_string(),
+                permissions: None,
+                overwrite: true,
+                encrypt: false,
+                seuser: None,
+                serole: None,
+                setype: None,
+                acl: vec![],
+                transfer_permissions: None,
+                xattrs: Default::default(),
+                capabilities: None,
+            }];
+            fixture.client_factory().corrupt_uploads(&fixture.host);
+
+            let error = fixture.run_host_plan().await.unwrap_err();
+            assert!(format!("{error:#}").contains("checksum mismatch"));
+        }
     }
 
     mod report {
@@ -705,6 +1455,22 @@ mod run_host_plan {
         }
     }
 
+    #[tokio::test]
+    async fn attaches_reported_error_if_action_fails() {
+        let fixture = Fixture::new();
+        let error = ClientError::new(ErrorCode::Busy, "dpkg is locked");
+        fixture.client_factory().exit_code(&fixture.host, 1 << 8);
+        fixture
+            .client_factory()
+            .report_error(&fixture.host, error.clone());
+
+        let outcome = fixture.run_host_plan().await.unwrap_err();
+        assert!(outcome
+            .to_string()
+            .starts_with("Action exited with exit code 1"));
+        assert_eq!(Some(&error), outcome.downcast_ref::<ClientError>());
+    }
+
     #[tokio::test]
     #[should_panic(expected = "Action exited with error")]
     async fn returns_if_action_fails() {
@@ -716,6 +1482,14 @@ mod run_host_plan {
             group: "d".to_string(),
             permissions: Some("e".to_string()),
             overwrite: true,
+            encrypt: false,
+            seuser: None,
+            serole: None,
+            setype: None,
+            acl: vec![],
+            transfer_permissions: None,
+            xattrs: Default::default(),
+            capabilities: None,
         }];
         fixture.client_factory().exit_code(&fixture.host, -1);
 
@@ -727,6 +1501,272 @@ mod run_host_plan {
         outcome.unwrap();
     }
 
+    mod pipeline {
+        use super::*;
+
+        // Returns a Fixture whose one task has `actions` commands, marked independent if
+        // `independent`, and which allows up to `pipeline` actions in flight.
+        fn fixture(actions: usize, independent: bool, pipeline: Option<usize>) -> Fixture {
+            let mut fixture = Fixture::new();
+            let task = &mut fixture.plan.manifests[0].include[0];
+            task.actions = (0..actions)
+                .map(|i| Action::Command(vec![format!("command {i}")]))
+                .collect();
+            task.independent = independent;
+            fixture.options.pipeline = pipeline;
+            fixture
+        }
+
+        #[tokio::test]
+        async fn keeps_independent_actions_in_flight() {
+            let fixture = fixture(5, true, Some(3));
+            fixture.run_host_plan().await.unwrap();
+
+            assert_eq!(3, fixture.client_factory().most_in_flight(&fixture.host));
+            let actions = fixture.plan.manifests[0].include[0].actions.clone();
+            let recorded_commands = fixture.recorded_commands();
+            assert_eq!(actions.len(), recorded_commands.len());
+            for (record, action) in recorded_commands.iter().zip(&actions) {
+                assert_record(record, "command", action, true);
+            }
+        }
+
+        #[tokio::test]
+        async fn runs_one_action_at_a_time_by_default() {
+            let fixture = fixture(5, true, None);
+            fixture.run_host_plan().await.unwrap();
+            assert_eq!(1, fixture.client_factory().most_in_flight(&fixture.host));
+        }
+
+        #[tokio::test]
+        async fn runs_dependent_actions_one_at_a_time() {
+            let fixture = fixture(5, false, Some(3));
+            fixture.run_host_plan().await.unwrap();
+            assert_eq!(1, fixture.client_factory().most_in_flight(&fixture.host));
+        }
+
+        #[tokio::test]
+        async fn waits_for_task_before_starting_the_next() {
+            let mut fixture = fixture(2, true, Some(4));
+            let task = fixture.plan.manifests[0].include[0].clone();
+            fixture.plan.manifests[0].include.push(task);
+            fixture.run_host_plan().await.unwrap();
+
+            assert_eq!(2, fixture.client_factory().most_in_flight(&fixture.host));
+            assert_eq!(4, fixture.recorded_commands().len());
+        }
+
+        #[tokio::test]
+        async fn finishes_actions_in_flight_if_one_fails() {
+            let fixture = fixture(5, true, Some(3));
+            fixture.client_factory().exit_code(&fixture.host, 1 << 8);
+
+            let error = fixture.run_host_plan().await.unwrap_err();
+
+            // The first action fails, but the two started after it still finish.
+            assert!(error.to_string().contains("command 0"), "{error}");
+            assert_eq!(3, fixture.recorded_commands().len());
+        }
+
+        // Reporters that look up each action's source when it finishes see every action in
+        // flight, not just the newest.
+        #[tokio::test(flavor = "multi_thread")]
+        async fn reports_each_action_in_flight_with_its_source() {
+            let fixture = fixture(3, true, Some(3));
+            let dir = tempfile::tempdir().unwrap();
+            let artifacts = Artifacts::new(dir.path().join("runs"));
+            let json_log = JsonLog::new(dir.path().join("log.jsonl"));
+            run_host_plan(
+                fixture.host.clone(),
+                fixture.plan.plan_for(&fixture.host).unwrap().into_iter(),
+                fixture.client_factory.clone(),
+                (artifacts.clone(), json_log),
+                fixture.options.clone(),
+                ExpectedClients::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(3, fixture.client_factory().most_in_flight(&fixture.host));
+
+            let manifest = &fixture.plan.manifests[0];
+            let task = &manifest.include[0];
+            let log = fs::read_to_string(dir.path().join("log.jsonl")).unwrap();
+            let records: Vec<json_log::Record> = log
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(3, records.len());
+            for (i, record) in records.iter().enumerate() {
+                assert_eq!(task.actions[i], record.action);
+                assert_eq!(manifest.name, record.manifest);
+                assert_eq!(task.name, record.task);
+            }
+
+            let host_dir = artifacts.run_dir().join(&fixture.host);
+            for (i, action) in task.actions.iter().enumerate() {
+                let name = format!("{:03}-{}.log", i + 1, task.name.replace(' ', "-"));
+                let contents = fs::read_to_string(host_dir.join(name)).unwrap();
+                assert!(
+                    contents.contains(&format!("Task: {}\n", task.name)),
+                    "{contents}"
+                );
+                assert!(contents.contains(&title(action)), "{contents}");
+            }
+        }
+    }
+
+    mod verify_client {
+        use super::*;
+
+        fn verifying() -> Fixture {
+            let mut fixture = Fixture::new();
+            fixture.options.connection.verify_client = Some(true);
+            fixture
+        }
+
+        #[tokio::test]
+        async fn runs_actions_on_matching_client() {
+            let fixture = verifying();
+            fixture.run_host_plan().await.unwrap();
+            assert!(!fixture.recorded_commands().is_empty());
+        }
+
+        #[tokio::test]
+        async fn refuses_tampered_client() {
+            let fixture = verifying();
+            fixture.client_factory().tamper_client(&fixture.host);
+            let error = fixture.run_host_plan().await.unwrap_err();
+            assert!(error.to_string().contains("does not match"), "{error}");
+            assert!(fixture.recorded_commands().is_empty());
+        }
+
+        #[tokio::test]
+        async fn skips_check_unless_enabled() {
+            let mut fixture = verifying();
+            fixture.client_factory().tamper_client(&fixture.host);
+            fixture.options.hosts.insert(
+                fixture.host.clone(),
+                HostConfig {
+                    verify_client: Some(false),
+                    ..Default::default()
+                },
+            );
+            fixture.run_host_plan().await.unwrap();
+        }
+    }
+
+    mod verify {
+        use super::*;
+
+        fn check(name: &str) -> Action {
+            Action::Command(vec![format!("check {name}")])
+        }
+
+        #[tokio::test]
+        async fn runs_checks_after_every_action() {
+            let mut fixture = Fixture::new();
+            let mut second = fixture.plan.manifests[0].include[0].clone();
+            second.actions = vec![Action::Command(vec!["second".to_string()])];
+            second.verify = vec![check("second")];
+            let first = &mut fixture.plan.manifests[0].include[0];
+            first.verify = vec![check("first")];
+            let first_action = first.actions[0].clone();
+            fixture.plan.manifests[0].include.push(second);
+
+            fixture.run_host_plan().await.unwrap();
+            let stdout = String::from_utf8(fixture.reporter.stdout().clone()).unwrap();
+            assert!(
+                stdout.contains(&format!("[{}] Verifying\n", fixture.host)),
+                "{stdout}",
+            );
+
+            let recorded_commands = fixture.recorded_commands();
+            let expected = [
+                first_action,
+                Action::Command(vec!["second".to_string()]),
+                check("first"),
+                check("second"),
+            ];
+            assert_eq!(expected.len(), recorded_commands.len());
+            for (record, action) in recorded_commands.iter().zip(&expected) {
+                assert_record(record, "command", action, true);
+            }
+        }
+
+        #[tokio::test]
+        async fn skips_checks_if_an_action_fails() {
+            let mut fixture = Fixture::new();
+            fixture.plan.manifests[0].include[0].verify = vec![check("first")];
+            fixture.client_factory().exit_code(&fixture.host, 1 << 8);
+
+            let error = fixture.run_host_plan().await.unwrap_err();
+            assert!(!error.to_string().contains("Verification"), "{error}");
+            assert_eq!(1, fixture.recorded_commands().len());
+        }
+
+        #[tokio::test]
+        async fn fails_host_if_a_check_fails() {
+            let mut fixture = Fixture::new();
+            let task = &mut fixture.plan.manifests[0].include[0];
+            task.actions.clear();
+            task.verify = vec![check("first")];
+            fixture.client_factory().exit_code(&fixture.host, 1 << 8);
+
+            let error = fixture.run_host_plan().await.unwrap_err();
+            assert!(
+                error.to_string().starts_with("Verification failed: "),
+                "{error}"
+            );
+            assert!(error.to_string().contains("check first"), "{error}");
+        }
+    }
+
+    mod check_mode {
+        use super::*;
+
+        fn checking() -> Fixture {
+            let mut fixture = Fixture::new();
+            fixture.options.check = Some(DriftReport::new());
+            fixture
+        }
+
+        #[tokio::test]
+        async fn asks_client_to_check_every_action() {
+            let fixture = checking();
+            fixture.run_host_plan().await.unwrap();
+
+            let recorded_commands = fixture.recorded_commands();
+            assert!(!recorded_commands.is_empty());
+            for record in recorded_commands {
+                assert!(Envelope::from_yaml(&record.yaml).unwrap().check);
+            }
+        }
+
+        #[tokio::test]
+        async fn skips_confirmation_and_verification() {
+            let mut fixture = checking();
+            let task = &mut fixture.plan.manifests[0].include[0];
+            task.confirm = true;
+            task.verify = vec![Action::Command(vec!["check".to_string()])];
+            let actions = task.actions.len();
+            fixture.run_host_plan().await.unwrap();
+
+            let stdout = String::from_utf8(fixture.reporter.stdout().to_vec()).unwrap();
+            assert!(!stdout.contains("This action requires confirmation"));
+            assert!(!stdout.contains("Verifying"));
+            assert_eq!(actions, fixture.recorded_commands().len());
+        }
+
+        #[tokio::test]
+        async fn runs_normally_by_default() {
+            let fixture = Fixture::new();
+            fixture.run_host_plan().await.unwrap();
+            for record in fixture.recorded_commands() {
+                assert!(!Envelope::from_yaml(&record.yaml).unwrap().check);
+            }
+        }
+    }
+
     #[tokio::test]
     async fn returns_ok() {
         assert!(Fixture::new().run_host_plan().await.is_ok());
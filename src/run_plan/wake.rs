@@ -0,0 +1,88 @@
+//! Wakes hosts that are switched off or asleep, e.g. laptops, with Wake-on-LAN, so that a run can
+//! reach them rather than give up on them.
+//!
+//! To wake a host that Sira can't connect to, turn on `wake_on_lan` in `sira.toml`, for every host
+//! or in the host's own table, and give the host its MAC address:
+//!
+//! ```toml
+//! wake_on_lan = true
+//! wake_delay = 90
+//!
+//! [hosts.archie-laptop]
+//! mac_address = "00:11:22:33:44:55"
+//! ```
+//!
+//! An inventory can give hosts their MAC addresses instead, with the host variable `mac_address`.
+//! A MAC address in `sira.toml` takes precedence. Please see [inventory](super::inventory).
+//!
+//! If Sira can't connect to such a host, it broadcasts a Wake-on-LAN "magic packet" for the host's
+//! MAC address to [BROADCAST_ADDRESS] from the control node, waits `wake_delay` seconds (by
+//! default, [DEFAULT_WAKE_DELAY]) for the host to boot, and then tries to connect once more. The
+//! packet only reaches hosts on the control node's local network, and the host's firmware and
+//! network interface must have Wake-on-LAN turned on.
+
+use anyhow::{bail, Context};
+use tokio::net::UdpSocket;
+
+/// How long to wait for a host to boot after waking it, in seconds, unless
+/// [HostConfig::wake_delay](crate::config::HostConfig::wake_delay) says otherwise.
+pub const DEFAULT_WAKE_DELAY: u64 = 60;
+
+/// Where to send magic packets: the discard port of every host on the local network.
+pub const BROADCAST_ADDRESS: &str = "255.255.255.255:9";
+
+/// The name of the inventory variable that holds a host's MAC address.
+pub const MAC_ADDRESS_VAR: &str = "mac_address";
+
+/// Parses a MAC address, e.g. `00:11:22:33:44:55` or `00-11-22-33-44-55`.
+///
+/// # Errors
+///
+/// Returns an error if `mac_address` isn't six pairs of hexadecimal digits, separated by colons
+/// or hyphens.
+pub fn parse_mac_address(mac_address: &str) -> anyhow::Result<[u8; 6]> {
+    let octets: Vec<&str> = mac_address.split([':', '-']).collect();
+    let mut parsed = [0; 6];
+    if octets.len() != parsed.len() {
+        bail!("invalid MAC address {mac_address:?}; expected e.g. \"00:11:22:33:44:55\"");
+    }
+    for (octet, parsed) in octets.iter().zip(&mut parsed) {
+        if octet.len() != 2 || !octet.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("invalid MAC address {mac_address:?}; {octet:?} isn't two hex digits");
+        }
+        *parsed = u8::from_str_radix(octet, 16)?;
+    }
+    Ok(parsed)
+}
+
+/// Returns the magic packet that wakes the host with MAC address `mac_address`: six bytes of
+/// `0xff` followed by the address 16 times.
+pub fn magic_packet(mac_address: [u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac_address);
+    }
+    packet
+}
+
+/// Broadcasts a magic packet that wakes the host with MAC address `mac_address`.
+///
+/// # Errors
+///
+/// Returns an error if `mac_address` is invalid or the packet can't be sent, e.g. because the
+/// control node has no network.
+pub async fn wake(mac_address: &str) -> anyhow::Result<()> {
+    let packet = magic_packet(parse_mac_address(mac_address)?);
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .context("could not open a socket for Wake-on-LAN")?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&packet, BROADCAST_ADDRESS)
+        .await
+        .with_context(|| format!("could not send Wake-on-LAN packet to {BROADCAST_ADDRESS}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,45 @@
+use super::*;
+
+mod parse_mac_address {
+    use super::*;
+
+    #[test]
+    fn accepts_colons_and_hyphens() {
+        let expected = [0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc];
+        assert_eq!(expected, parse_mac_address("00:11:22:aa:bb:cc").unwrap());
+        assert_eq!(expected, parse_mac_address("00-11-22-AA-BB-CC").unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_addresses() {
+        for mac_address in [
+            "",
+            "00:11:22:33:44",
+            "00:11:22:33:44:55:66",
+            "0:11:22:33:44:55",
+            "00:11:22:33:44:gg",
+            "00:11:22:33:44:+f",
+            "001122334455",
+        ] {
+            assert!(
+                parse_mac_address(mac_address).is_err(),
+                "{mac_address:?} should be invalid"
+            );
+        }
+    }
+}
+
+mod magic_packet {
+    use super::*;
+
+    #[test]
+    fn repeats_address_after_header() {
+        let mac_address = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+        let packet = magic_packet(mac_address);
+        assert_eq!(102, packet.len());
+        assert_eq!([0xff; 6], packet[..6]);
+        for repeat in packet[6..].chunks(6) {
+            assert_eq!(mac_address, repeat);
+        }
+    }
+}
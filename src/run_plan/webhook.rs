@@ -0,0 +1,269 @@
+//! Posts notifications to webhooks, e.g. Slack or Matrix, when a host fails and when a run
+//! finishes.
+//!
+//! Each webhook receives one notification as soon as any host fails, and one summary at the end of
+//! the run. Under `sira daemon`, each webhook also hears about scheduled runs that couldn't start.
+//! Generic webhooks receive an [Event] as JSON; Slack and Matrix webhooks receive a message that
+//! those services can display, e.g.:
+//!
+//! ```json
+//! {"text":"Sira run finished: 2 hosts succeeded, 1 failed\n[web3] Action exited with exit code 1: command: false"}
+//! ```
+//!
+//! Sira hands each notification to `curl`. A failed notification prints a warning but never stops
+//! a run.
+
+use super::report::{title, Report};
+use crate::core::Action;
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+use tokio::task;
+
+/// The kind of service that a webhook belongs to, which determines what Sira posts to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Receives each [Event] as JSON.
+    #[default]
+    Generic,
+
+    /// A Slack incoming webhook. Receives a `text` message.
+    Slack,
+
+    /// A Matrix webhook bridge, e.g. matrix-hookshot. Receives a `text` message.
+    Matrix,
+}
+
+/// A webhook to notify.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Webhook {
+    /// What to post to the webhook.
+    pub format: Format,
+
+    /// The URL to which to post.
+    pub url: String,
+}
+
+impl FromStr for Webhook {
+    type Err = anyhow::Error;
+
+    /// Parses a webhook of the form `[slack:|matrix:|generic:]<url>`, e.g.
+    /// `slack:https://hooks.slack.com/services/...`.
+    ///
+    /// Without a prefix, the webhook is [Format::Generic].
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (format, url) = match s.split_once(':') {
+            Some(("slack", url)) => (Format::Slack, url),
+            Some(("matrix", url)) => (Format::Matrix, url),
+            Some(("generic", url)) => (Format::Generic, url),
+            _ => (Format::Generic, s),
+        };
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            bail!("webhook URL must start with https:// or http://: {url}");
+        }
+        Ok(Webhook {
+            format,
+            url: url.to_string(),
+        })
+    }
+}
+
+/// Something that happened during a run that's worth notifying someone about.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// An action failed on a host, so that host has stopped.
+    HostFailed {
+        /// The host on which the action failed.
+        host: String,
+
+        /// The title of the action that failed.
+        action: String,
+
+        /// The action's exit code, if any.
+        exit_code: Option<i32>,
+
+        /// The action's stderr, with invalid UTF-8 replaced.
+        stderr: String,
+    },
+
+    /// Every host has either finished or stopped.
+    RunFinished {
+        /// The hosts that ran every action successfully, in alphabetical order.
+        succeeded: Vec<String>,
+
+        /// Maps each host that failed, including hosts that couldn't connect, to its error.
+        failed: BTreeMap<String, String>,
+    },
+
+    /// A scheduled run couldn't start, e.g. because a manifest file was invalid, so no host ran.
+    /// Please see [schedule](super::schedule).
+    RunNotStarted {
+        /// The name of the scheduled run.
+        schedule: String,
+
+        /// Why the run couldn't start.
+        error: String,
+    },
+}
+
+impl Event {
+    /// Returns a human-readable message describing this event.
+    pub fn message(&self) -> String {
+        match self {
+            Event::HostFailed {
+                host,
+                action,
+                exit_code,
+                ..
+            } => match exit_code {
+                Some(code) => {
+                    format!("[{host}] Sira action failed with exit code {code}: {action}")
+                }
+                None => format!("[{host}] Sira action failed with error: {action}"),
+            },
+            Event::RunFinished { succeeded, failed } => {
+                let mut message = format!(
+                    "Sira run finished: {} hosts succeeded, {} failed",
+                    succeeded.len(),
+                    failed.len(),
+                );
+                for (host, error) in failed {
+                    let _ = write!(message, "\n[{host}] {error}");
+                }
+                message
+            }
+            Event::RunNotStarted { schedule, error } => {
+                format!("Sira scheduled run {schedule} could not start: {error}")
+            }
+        }
+    }
+
+    /// Returns the JSON to post to a webhook of the given [Format].
+    pub fn payload(&self, format: Format) -> serde_json::Result<String> {
+        match format {
+            Format::Generic => serde_json::to_string(self),
+            Format::Slack | Format::Matrix => {
+                serde_json::to_string(&serde_json::json!({ "text": self.message() }))
+            }
+        }
+    }
+}
+
+/// Notifies webhooks when a host fails. Please see the [module documentation](self).
+///
+/// Because [Notifier] implements [Report], it sees each action's outcome as the run progresses.
+/// It can't tell when the run is over, though, so whoever runs the [Plan](crate::core::Plan) must
+/// call [Notifier::finished].
+#[derive(Clone, Debug, Default)]
+pub struct Notifier {
+    /// The webhooks to notify.
+    webhooks: Vec<Webhook>,
+}
+
+impl Notifier {
+    /// Creates a [Notifier] that posts to `webhooks`.
+    pub fn new(webhooks: Vec<Webhook>) -> Self {
+        Notifier { webhooks }
+    }
+
+    /// Sends a summary of the run to every webhook.
+    ///
+    /// `hosts` lists every host in the plan, and `errors` lists the hosts that failed.
+    pub fn finished(&self, hosts: &[String], errors: &[(String, anyhow::Error)]) {
+        self.notify(&summary(hosts, errors));
+    }
+
+    /// Tells every webhook that the scheduled run named `schedule` couldn't start because of
+    /// `error`.
+    pub fn not_started(&self, schedule: &str, error: &anyhow::Error) {
+        self.notify(&Event::RunNotStarted {
+            schedule: schedule.to_string(),
+            error: format!("{error:#}"),
+        });
+    }
+
+    /// Posts `event` to every webhook, printing a warning for any that fail.
+    fn notify(&self, event: &Event) {
+        for webhook in &self.webhooks {
+            if let Err(e) = post(webhook, event) {
+                eprintln!("Warning: could not notify webhook {}: {e:#}", webhook.url);
+            }
+        }
+    }
+}
+
+/// Returns the [Event::RunFinished] for a run on `hosts` in which `errors` occurred.
+pub(crate) fn summary(hosts: &[String], errors: &[(String, anyhow::Error)]) -> Event {
+    let failed: BTreeMap<String, String> = errors
+        .iter()
+        .map(|(host, error)| (host.clone(), format!("{error:#}")))
+        .collect();
+    let mut succeeded: Vec<String> = hosts
+        .iter()
+        .filter(|host| !failed.contains_key(*host))
+        .cloned()
+        .collect();
+    succeeded.sort();
+    Event::RunFinished { succeeded, failed }
+}
+
+#[async_trait]
+impl Report for Notifier {
+    async fn starting(&mut self, _host: &str, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn report(&mut self, host: &str, action: &Action, output: &Output) -> io::Result<()> {
+        if !output.status.success() {
+            let event = Event::HostFailed {
+                host: host.to_string(),
+                action: title(action),
+                exit_code: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            };
+            task::block_in_place(|| self.notify(&event));
+        }
+        Ok(())
+    }
+}
+
+/// Posts `event` to `webhook` via `curl`.
+fn post(webhook: &Webhook, event: &Event) -> anyhow::Result<()> {
+    let payload = event.payload(webhook.format)?;
+
+    // curl -sS --fail -X POST -H 'Content-Type: application/json' --data-binary @- <url>
+    let mut child = Command::new("curl")
+        .args(["-sS", "--fail", "-X", "POST"])
+        .args(["-H", "Content-Type: application/json"])
+        .args(["--data-binary", "@-"])
+        .arg(&webhook.url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("could not run curl")?;
+    child
+        .stdin
+        .take()
+        .expect("curl's stdin should be piped")
+        .write_all(payload.as_bytes())
+        .context("could not send notification to curl")?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "curl exited with error: {}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,110 @@
+use super::*;
+use anyhow::anyhow;
+
+fn host_failed() -> Event {
+    Event::HostFailed {
+        host: "web3".to_string(),
+        action: "command: false".to_string(),
+        exit_code: Some(1),
+        stderr: "oops\n".to_string(),
+    }
+}
+
+mod from_str {
+    use super::*;
+
+    #[test]
+    fn defaults_to_generic() {
+        assert_eq!(
+            Webhook {
+                format: Format::Generic,
+                url: "https://example.com/hook".to_string(),
+            },
+            "https://example.com/hook".parse().unwrap(),
+        );
+    }
+
+    #[test]
+    fn parses_prefixes() {
+        for (prefix, format) in [
+            ("slack", Format::Slack),
+            ("matrix", Format::Matrix),
+            ("generic", Format::Generic),
+        ] {
+            let webhook: Webhook = format!("{prefix}:https://example.com/hook")
+                .parse()
+                .unwrap();
+            assert_eq!(format, webhook.format);
+            assert_eq!("https://example.com/hook", webhook.url);
+        }
+    }
+
+    #[test]
+    fn rejects_other_schemes() {
+        assert!("ftp://example.com/hook".parse::<Webhook>().is_err());
+        assert!("slack:hooks.slack.com".parse::<Webhook>().is_err());
+    }
+}
+
+mod payload {
+    use super::*;
+
+    #[test]
+    fn generic_is_tagged_event() {
+        let payload: serde_json::Value =
+            serde_json::from_str(&host_failed().payload(Format::Generic).unwrap()).unwrap();
+        assert_eq!("host_failed", payload["event"]);
+        assert_eq!("web3", payload["host"]);
+        assert_eq!(1, payload["exit_code"]);
+        assert_eq!("oops\n", payload["stderr"]);
+    }
+
+    #[test]
+    fn slack_and_matrix_are_text() {
+        for format in [Format::Slack, Format::Matrix] {
+            let payload: serde_json::Value =
+                serde_json::from_str(&host_failed().payload(format).unwrap()).unwrap();
+            assert_eq!(
+                "[web3] Sira action failed with exit code 1: command: false",
+                payload["text"],
+            );
+        }
+    }
+    #[test]
+    fn names_schedule_that_did_not_start() {
+        let event = Event::RunNotStarted {
+            schedule: "enforce".to_string(),
+            error: "could not read site.yaml".to_string(),
+        };
+        let payload: serde_json::Value =
+            serde_json::from_str(&event.payload(Format::Generic).unwrap()).unwrap();
+        assert_eq!("run_not_started", payload["event"]);
+        assert_eq!("enforce", payload["schedule"]);
+        assert_eq!(
+            "Sira scheduled run enforce could not start: could not read site.yaml",
+            event.message(),
+        );
+    }
+}
+
+mod summary {
+    use super::*;
+
+    #[test]
+    fn splits_hosts_by_outcome() {
+        let hosts = ["web2", "web3", "web1"].map(String::from);
+        let errors = vec![("web3".to_string(), anyhow!("connection refused"))];
+        let event = summary(&hosts, &errors);
+        assert_eq!(
+            Event::RunFinished {
+                succeeded: vec!["web1".to_string(), "web2".to_string()],
+                failed: BTreeMap::from([("web3".to_string(), "connection refused".to_string())]),
+            },
+            event,
+        );
+        assert_eq!(
+            "Sira run finished: 2 hosts succeeded, 1 failed\n[web3] connection refused",
+            event.message(),
+        );
+    }
+}
@@ -0,0 +1,26 @@
+//! Verifies that the `test-fixtures` feature gives downstream crates what they need to test code
+//! that embeds Sira, using only the public API.
+
+#![cfg(feature = "test-fixtures")]
+
+use sira::core::fixtures::plan;
+use sira::run_plan::embed;
+use sira::run_plan::fixtures::{TestClientFactory, TestReporter};
+use sira::run_plan::RunOptions;
+
+#[tokio::test]
+async fn runs_plan_against_fakes() {
+    let (mut plan, _, _, _) = plan();
+    plan.manifests[0].hosts = vec!["web1".to_string(), "web2".to_string()];
+    let network = TestClientFactory::new();
+    network.lock().unwrap().set_unreachable("web2");
+    let ui = TestReporter::new();
+
+    let result = embed::run(plan, network.clone(), ui.clone(), (), RunOptions::default()).await;
+
+    assert_eq!(vec!["web1"], result.succeeded().collect::<Vec<_>>());
+    assert_eq!(1, result.failed().count());
+    let commands = network.lock().unwrap().client_commands()["web1"].clone();
+    assert_eq!("command", commands.lock().unwrap()[0].method_name);
+    assert!(!ui.stdout().is_empty());
+}
@@ -6,7 +6,7 @@
 //! Tasks.
 
 use indexmap::IndexMap;
-use sira::core::manifest::ManifestFile;
+use sira::core::manifest::{Hosts, ManifestFile};
 use sira::core::*;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -172,6 +172,15 @@ include:
                                 "uname -r".to_owned(),
                             ])],
                             vars: task1_vars,
+                            sensitive: Vec::new(),
+                            confirm: false,
+                            user: None,
+                            escalate: false,
+                            dpkg_lock_timeout: None,
+                            limits: Default::default(),
+                            sandbox: None,
+                            independent: false,
+                            verify: Vec::new(),
                         },
                         Task {
                             source: None,
@@ -183,8 +192,25 @@ include:
                                 group: "buster".to_owned(),
                                 permissions: Some("ugo=rwx".to_owned()),
                                 overwrite: true,
+                                encrypt: false,
+                                seuser: None,
+                                serole: None,
+                                setype: None,
+                                acl: vec![],
+                                transfer_permissions: None,
+                                xattrs: Default::default(),
+                                capabilities: None,
                             }],
                             vars: IndexMap::new(),
+                            sensitive: Vec::new(),
+                            confirm: false,
+                            user: None,
+                            escalate: false,
+                            dpkg_lock_timeout: None,
+                            limits: Default::default(),
+                            sandbox: None,
+                            independent: false,
+                            verify: Vec::new(),
                         },
                     ]
                 }
@@ -211,8 +237,11 @@ include:
                 source: None,
                 name,
                 hosts,
+                fact: None,
+                host_vars: Default::default(),
                 include,
                 vars,
+                sensitive: Vec::new(),
             };
             (yaml, manifest)
         }
@@ -508,9 +537,10 @@ include:
         let manifest = ManifestFile {
             source,
             name,
-            hosts,
+            hosts: Hosts::List(hosts),
             include,
             vars,
+            sensitive: Vec::new(),
         };
         (yaml, manifest)
     }
@@ -577,9 +607,10 @@ include:
     mod hosts {
         use super::*;
 
-        /// Documents the fact that ManifestFile::hosts deserializes only from a sequence, not from
-        /// a single value. It is possible to overcome this, if desired, but doing so requires a
-        /// more complicated setup and has thus far been deemed not worth the effort.
+        /// Documents the fact that ManifestFile::hosts deserializes only from a sequence (or a map
+        /// with a fact expression), not from a single value. It is possible to overcome this, if
+        /// desired, but doing so requires a more complicated setup and has thus far been deemed not
+        /// worth the effort.
         #[test]
         #[should_panic(expected = "expected a sequence")]
         fn deserialization_from_short_form_fails() {
@@ -784,6 +815,15 @@ mod task {
                 name,
                 actions,
                 vars,
+                sensitive: Vec::new(),
+                confirm: false,
+                user: None,
+                escalate: false,
+                dpkg_lock_timeout: None,
+                limits: Default::default(),
+                sandbox: None,
+                independent: false,
+                verify: Vec::new(),
             };
             (yaml, task)
         }